@@ -0,0 +1,139 @@
+//! Compares the join orders used to resolve the function each instruction belongs to
+//! on a wide module, both for the initial load and for rewriting every instruction
+//!
+//! Run with `cargo bench --bench join_order`
+
+use differential_dataflow::operators::Consolidate;
+use sruth::{
+    builder::{BuildResult, Builder, Context},
+    dataflow::{call_graph, with_functions, Cardinalities, Diff, InputManager, JoinOrder, Time},
+    repr::{instruction::Assign, Constant, FuncId, InstId, Instruction, InstructionExt, Type},
+};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use timely::dataflow::ProbeHandle;
+
+/// The number of functions within the module
+const FUNCTIONS: usize = 256;
+
+/// The number of instructions within each function
+const WIDTH: usize = 256;
+
+/// Every nth instruction is a call
+const CALL_EVERY: usize = 16;
+
+fn main() {
+    for &order in &[JoinOrder::InstructionsFirst, JoinOrder::LocationsFirst] {
+        let (load, rewrite) = run(order, false);
+        println!(
+            "instruction functions, {:?}: load {:?}, rewrite {:?}",
+            order, load, rewrite,
+        );
+    }
+
+    for &order in &[JoinOrder::InstructionsFirst, JoinOrder::LocationsFirst] {
+        let (load, rewrite) = run(order, true);
+        println!(
+            "call graph, {:?}: load {:?}, rewrite {:?}",
+            order, load, rewrite,
+        );
+    }
+}
+
+/// Returns the time taken to load the module and then to rewrite every one of its
+/// non-call instructions
+fn run(order: JoinOrder, call_graph_only: bool) -> (Duration, Duration) {
+    timely::execute_directly(move |worker| {
+        let context = Arc::new(Context::new(0));
+        let mut probe = ProbeHandle::new();
+        let instructions = Rc::new(RefCell::new(Vec::<(InstId, Instruction)>::new()));
+
+        let mut input = worker.dataflow(|scope| InputManager::<Time, Diff>::new(scope));
+        worker.dataflow(|scope| {
+            let program = input.import_program(scope);
+
+            let seen = instructions.clone();
+            program.instructions.inspect(move |((id, inst), _, diff)| {
+                if *diff > 0 {
+                    seen.borrow_mut().push((*id, inst.clone()));
+                }
+            });
+
+            if call_graph_only {
+                call_graph::call_graph_with(&program, order).probe_with(&mut probe);
+            } else {
+                with_functions(&program, &program.instructions, order)
+                    .map(|(func, _)| func)
+                    .consolidate()
+                    .probe_with(&mut probe);
+            }
+        });
+
+        let mut builder = context.builder();
+        build_module(&mut builder).unwrap();
+        let functions: Vec<_> = builder.materialize().collect();
+        println!("{:?}", Cardinalities::of(&functions));
+
+        let start = Instant::now();
+        builder.finish(&mut input, 0).unwrap();
+        input.advance_to(1);
+        while probe.less_than(input.time()) {
+            worker.step();
+        }
+        let load = start.elapsed();
+
+        let start = Instant::now();
+        for (id, inst) in instructions.borrow_mut().drain(..) {
+            if let Instruction::Call(_) = inst {
+                continue;
+            }
+
+            let rewritten =
+                Instruction::Assign(Assign::new(inst.dest(), Constant::Int(1).into(), None));
+            input.instructions.update_at((id, inst), 1, -1);
+            input.instructions.update_at((id, rewritten), 1, 1);
+        }
+        input.advance_to(2);
+        while probe.less_than(input.time()) {
+            worker.step();
+        }
+
+        (load, start.elapsed())
+    })
+}
+
+/// Builds a chain of wide functions that each call the one built before them
+fn build_module(builder: &mut Builder) -> BuildResult<()> {
+    let mut previous: Option<FuncId> = None;
+
+    for _ in 0..FUNCTIONS {
+        let id = builder.function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let mut value = x;
+                for i in 0..WIDTH {
+                    match previous {
+                        Some(callee) if i % CALL_EVERY == 0 => {
+                            block.call(callee, vec![value.clone().into()])?;
+                        }
+                        _ => value = block.add(value, Constant::Int(i as i64))?,
+                    }
+                }
+                block.ret(value)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })?;
+
+        previous = Some(id);
+    }
+
+    Ok(())
+}
@@ -4,8 +4,10 @@ mod programs;
 use parse::BrainfuckAst;
 use programs::HELLO_WORLD;
 use sruth::{
+    artifacts::DirectorySink,
     builder::{BuildResult, Context, FunctionBuilder},
     dataflow::{Diff, Time},
+    repr::ModuleMeta,
     vsdg::{
         dot,
         node::{CmpKind, Constant, NodeId},
@@ -77,7 +79,10 @@ fn main() {
     })
     .unwrap();
 
-    dot::render_graphs(receiver);
+    let meta = ModuleMeta::new().with_source(HELLO_WORLD);
+    if let Err(err) = dot::render_graphs(receiver, DirectorySink::from_env(), Some(&meta)) {
+        eprintln!("failed to render graphs: {}", err);
+    }
 }
 
 fn compile_node(
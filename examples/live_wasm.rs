@@ -0,0 +1,340 @@
+//! Differential optimization of a live-edited wasm module
+//!
+//! Loads and optimizes a wasm module, writes the optimized module next to it and then
+//! watches for edits. Each edit is a line of the form `<path> <function>...` read from
+//! stdin, or from connections made to the address given with `--listen`, where `path`
+//! is a wasm module holding edited versions of the named functions. Only the named
+//! functions are replaced, the rest of the edited module only has to be there so the
+//! edited functions can call into it
+//!
+//! The pipeline only redoes the work affected by each edit, and every function whose
+//! optimized form changed is written out as a wasm patch that imports the functions
+//! it calls from the original module
+//!
+//! Run with `cargo run --example live_wasm --features wasm -- <module.wasm> [--listen <addr>]`
+
+use crossbeam_channel::{Receiver, Sender};
+use differential_dataflow::consolidation::consolidate;
+use sruth::{
+    builder::Context,
+    dataflow::{operators::CrossbeamPusher, Diff, InputManager, Time},
+    driver::{LoadedFunction, Pass, Pipeline},
+    repr::{utils::IRDisplay, FuncId, Function, Instruction},
+    wasm,
+};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, BufReader},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    thread,
+};
+use timely::dataflow::operators::{capture::Event, Capture};
+
+type FunctionUpdates = Event<Time, Vec<(Function, Time, Diff)>>;
+
+const PASSES: &[Pass] = &[
+    Pass::ConstantFolding,
+    Pass::Peephole,
+    Pass::CullUnreachableBlocks,
+    Pass::CompactBasicBlocks,
+    Pass::Cleanup,
+];
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let (module_path, listen) = match (args.next(), args.next(), args.next()) {
+        (Some(path), None, None) => (PathBuf::from(path), None),
+        (Some(path), Some(flag), Some(addr)) if flag == "--listen" => {
+            (PathBuf::from(path), Some(addr))
+        }
+        _ => {
+            eprintln!("usage: live_wasm <module.wasm> [--listen <addr>]");
+            process::exit(1);
+        }
+    };
+
+    let context = Arc::new(Context::new(0));
+    let functions = match load_module(&context, &module_path) {
+        Ok(functions) => functions,
+        Err(err) => {
+            eprintln!("failed to load {}: {}", module_path.display(), err);
+            process::exit(1);
+        }
+    };
+
+    let (edit_sender, edits) = crossbeam_channel::unbounded();
+    thread::spawn(move || match listen {
+        Some(addr) => listen_for_edits(&addr, edit_sender),
+        None => read_edits(io::stdin().lock(), &edit_sender),
+    });
+
+    timely::execute_directly(move |worker| {
+        let pipeline = PASSES
+            .iter()
+            .fold(Pipeline::new(context.clone()), |pipeline, &pass| {
+                pipeline.add_pass(pass)
+            });
+        let mut handles = pipeline.build(worker);
+
+        let (sender, updates) = crossbeam_channel::unbounded();
+        let (functions_trace, probe) = (&mut handles.functions, &mut handles.probe);
+        worker.dataflow_named("watched functions", |scope| {
+            functions_trace
+                .import(scope)
+                .as_collection(|_id, function| function.clone())
+                .probe_with(probe)
+                .inner
+                .capture_into(CrossbeamPusher::new(sender));
+        });
+
+        let mut watcher = Watcher {
+            context: context.clone(),
+            module_path,
+            ids: HashMap::new(),
+            loaded: HashMap::new(),
+            optimized: HashMap::new(),
+            updates,
+        };
+
+        for function in functions {
+            let name = watcher.function_name(&function);
+            watcher.ids.insert(name, function.id);
+
+            let loaded = LoadedFunction::new(&context, function);
+            loaded.insert(&mut handles.input);
+            watcher.loaded.insert(loaded.id(), loaded);
+        }
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+        watcher.apply_updates();
+
+        let optimized = watcher.module();
+        match wasm::emit(&optimized, context.interner()) {
+            Ok(bytes) => {
+                watcher.write(&bytes, "opt.wasm");
+            }
+            Err(err) => eprintln!("failed to emit the optimized module: {}", err),
+        }
+
+        for edit in edits {
+            let epoch = handles.time();
+            if let Err(err) = watcher.edit(&edit, &mut handles.input) {
+                eprintln!("skipped edit `{}`: {}", edit.trim(), err);
+                continue;
+            }
+
+            handles.advance_to(epoch + 1);
+            handles.step_until_complete(worker);
+            let changed = watcher.apply_updates();
+            handles.compact(worker, epoch + 1);
+
+            if changed.is_empty() {
+                println!("edit {}: nothing changed", epoch);
+                continue;
+            }
+
+            let names: Vec<String> = changed
+                .iter()
+                .map(|function| watcher.function_name(function))
+                .collect();
+            match wasm::emit_patch(&changed, &watcher.module(), context.interner()) {
+                Ok(bytes) => {
+                    let path = watcher.write(&bytes, &format!("patch{}.wasm", epoch));
+                    println!(
+                        "edit {}: re-optimized {} into {}",
+                        epoch,
+                        names.join(", "),
+                        path
+                    );
+                }
+                Err(err) => eprintln!("edit {}: failed to emit a patch: {}", epoch, err),
+            }
+        }
+    });
+}
+
+struct Watcher {
+    context: Arc<Context>,
+    module_path: PathBuf,
+    /// The id of every function within the original module by its name
+    ids: HashMap<String, FuncId>,
+    /// The unoptimized functions as they were given to the pipeline
+    loaded: HashMap<FuncId, LoadedFunction>,
+    /// The latest optimized version of every function
+    optimized: HashMap<FuncId, Function>,
+    updates: Receiver<FunctionUpdates>,
+}
+
+impl Watcher {
+    /// Replaces the functions named by the edit with their edited versions
+    fn edit(&mut self, edit: &str, input: &mut InputManager<Time, Diff>) -> Result<(), String> {
+        let mut parts = edit.split_whitespace();
+        let path = parts.next().ok_or("expected `<path> <function>...`")?;
+        let targets: Vec<&str> = parts.collect();
+        if targets.is_empty() {
+            return Err("no functions were named".to_owned());
+        }
+
+        let edited = load_module(&self.context, Path::new(path))?;
+
+        // Functions are matched up with the originals by name, including the callees of
+        // every call within the edited functions
+        let edited_ids: HashMap<FuncId, FuncId> = edited
+            .iter()
+            .filter_map(|function| {
+                self.ids
+                    .get(&self.function_name(function))
+                    .map(|&original| (function.id, original))
+            })
+            .collect();
+
+        let mut replacements = Vec::with_capacity(targets.len());
+        for &target in targets.iter() {
+            let mut function = edited
+                .iter()
+                .find(|function| self.function_name(function) == target)
+                .cloned()
+                .ok_or_else(|| format!("{} has no function named `{}`", path, target))?;
+            function.id = *self
+                .ids
+                .get(target)
+                .ok_or_else(|| format!("the module has no function named `{}`", target))?;
+
+            for inst in function
+                .basic_blocks
+                .iter_mut()
+                .flat_map(|block| block.instructions.iter_mut())
+            {
+                if let Instruction::Call(call) = inst {
+                    call.func = *edited_ids.get(&call.func).ok_or_else(|| {
+                        format!("`{}` calls a function the module doesn't have", target)
+                    })?;
+                }
+            }
+
+            replacements.push(function);
+        }
+
+        for function in replacements {
+            if let Some(previous) = self.loaded.remove(&function.id) {
+                previous.retract(input);
+            }
+
+            let loaded = LoadedFunction::new(&self.context, function);
+            loaded.insert(input);
+            self.loaded.insert(loaded.id(), loaded);
+        }
+
+        Ok(())
+    }
+
+    /// Applies every update the pipeline has produced so far, returning the functions
+    /// whose optimized form changed
+    fn apply_updates(&mut self) -> Vec<Function> {
+        let mut updates = Vec::new();
+        for event in self.updates.try_iter() {
+            if let Event::Messages(_time, data) = event {
+                updates.extend(
+                    data.into_iter()
+                        .map(|(function, _time, diff)| (function, diff)),
+                );
+            }
+        }
+        consolidate(&mut updates);
+
+        let mut changed = Vec::new();
+        for (function, diff) in updates.iter() {
+            if *diff > 0 {
+                self.optimized.insert(function.id, function.clone());
+                changed.push(function.clone());
+            }
+        }
+
+        // Functions that were retracted without being replaced no longer exist
+        for (function, diff) in updates {
+            if diff < 0 && changed.iter().all(|changed| changed.id != function.id) {
+                self.optimized.remove(&function.id);
+            }
+        }
+        changed.sort_by_key(|function| function.id);
+
+        changed
+    }
+
+    fn module(&self) -> Vec<Function> {
+        let mut functions: Vec<Function> = self.optimized.values().cloned().collect();
+        functions.sort_by_key(|function| function.id);
+        functions
+    }
+
+    fn function_name(&self, function: &Function) -> String {
+        function.name.map_or_else(
+            || format!("func{}", function.id.as_u64()),
+            |name| name.to_pretty_string(self.context.interner()),
+        )
+    }
+
+    /// Writes the bytes next to the watched module with the given extension, returning
+    /// the path they were written to
+    fn write(&self, bytes: &[u8], extension: &str) -> String {
+        let path = self.module_path.with_extension(extension);
+        if let Err(err) = fs::write(&path, bytes) {
+            eprintln!("failed to write {}: {}", path.display(), err);
+        }
+
+        path.display().to_string()
+    }
+}
+
+fn load_module(context: &Arc<Context>, path: &Path) -> Result<Vec<Function>, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+
+    let mut builder = context.builder();
+    let parsed = wasm::parse(&bytes, &mut builder);
+    let functions = builder.materialize().collect();
+    builder.discard();
+
+    parsed.map(|_| functions).map_err(|err| err.to_string())
+}
+
+fn read_edits<R>(reader: R, edits: &Sender<String>)
+where
+    R: BufRead,
+{
+    for line in reader.lines() {
+        match line {
+            Ok(line) if line.trim().is_empty() => {}
+            Ok(line) => {
+                if edits.send(line).is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                eprintln!("failed to read an edit: {}", err);
+                return;
+            }
+        }
+    }
+}
+
+fn listen_for_edits(addr: &str, edits: Sender<String>) {
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("failed to listen on {}: {}", addr, err);
+            return;
+        }
+    };
+    println!("listening for edits on {}", addr);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => read_edits(BufReader::new(stream), &edits),
+            Err(err) => eprintln!("failed to accept a connection: {}", err),
+        }
+    }
+}
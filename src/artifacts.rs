@@ -0,0 +1,197 @@
+//! Emission of debug artifacts like graphs, reports and captures
+//!
+//! All debug tooling writes through an [`ArtifactSink`] so that output
+//! locations are configurable and missing external tools (like graphviz)
+//! degrade into warnings instead of panics
+
+use std::{
+    env, fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The environment variable used to override the default artifact directory
+pub const ARTIFACT_DIR_VAR: &str = "SRUTH_ARTIFACT_DIR";
+
+/// The directory artifacts are written to when [`ARTIFACT_DIR_VAR`] isn't set
+pub const DEFAULT_ARTIFACT_DIR: &str = "target/debug";
+
+pub trait ArtifactSink {
+    /// Writes an artifact to `path` (relative to the sink's root), returning
+    /// the full path of the artifact if it was persisted anywhere
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<Option<PathBuf>>;
+
+    /// Renders a graphviz file into the given format (`png`, `svg`, etc.),
+    /// returning the path of the rendered file if rendering was possible
+    fn render_graphviz(&self, dot: &Path, format: &str) -> io::Result<Option<PathBuf>>;
+
+    /// Writes a graph's dot source along with png and svg renderings of it
+    fn write_graph(&self, name: &str, dot: &str) -> io::Result<()> {
+        let name = sanitize_name(name);
+        let dot_path = match self.write(&Path::new(&name).join("graphviz.dot"), dot.as_bytes())? {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        for format in &["png", "svg"] {
+            self.render_graphviz(&dot_path, format)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<S> ArtifactSink for &S
+where
+    S: ArtifactSink + ?Sized,
+{
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<Option<PathBuf>> {
+        (**self).write(path, contents)
+    }
+
+    fn render_graphviz(&self, dot: &Path, format: &str) -> io::Result<Option<PathBuf>> {
+        (**self).render_graphviz(dot, format)
+    }
+}
+
+/// Writes artifacts into a directory on disk
+#[derive(Debug, Clone)]
+pub struct DirectorySink {
+    root: PathBuf,
+    graphviz: Option<PathBuf>,
+}
+
+impl DirectorySink {
+    /// Creates a sink rooted at `root`, using graphviz from the `PATH` if it's installed
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        let graphviz = PathBuf::from("dot");
+        let graphviz = if graphviz_available(&graphviz) {
+            Some(graphviz)
+        } else {
+            tracing::warn!("graphviz could not be found, graphs will not be rendered");
+            None
+        };
+
+        Self {
+            root: root.into(),
+            graphviz,
+        }
+    }
+
+    /// Creates a sink rooted at the directory given by [`ARTIFACT_DIR_VAR`],
+    /// falling back to [`DEFAULT_ARTIFACT_DIR`]
+    pub fn from_env() -> Self {
+        let root = env::var_os(ARTIFACT_DIR_VAR)
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| PathBuf::from(DEFAULT_ARTIFACT_DIR), PathBuf::from);
+
+        Self::new(root)
+    }
+
+    /// Sets the graphviz executable to use, `None` disables graph rendering
+    pub fn with_graphviz<P>(mut self, graphviz: Option<P>) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.graphviz = graphviz.map(Into::into);
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Default for DirectorySink {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+impl ArtifactSink for DirectorySink {
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<Option<PathBuf>> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&path, contents)?;
+        tracing::trace!("wrote debug artifact to {}", path.display());
+
+        Ok(Some(path))
+    }
+
+    fn render_graphviz(&self, dot: &Path, format: &str) -> io::Result<Option<PathBuf>> {
+        let graphviz = match self.graphviz.as_ref() {
+            Some(graphviz) => graphviz,
+            None => return Ok(None),
+        };
+
+        let output = dot.with_extension(format);
+        let status = Command::new(graphviz)
+            .arg(dot)
+            .arg(format!("-T{}", format))
+            .arg("-o")
+            .arg(&output)
+            .status();
+
+        match status {
+            Ok(status) if status.success() => Ok(Some(output)),
+            Ok(status) => {
+                tracing::warn!(
+                    "graphviz failed to render {} into {} with {}",
+                    dot.display(),
+                    format,
+                    status,
+                );
+
+                Ok(None)
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                tracing::warn!(
+                    "graphviz could not be found, skipped rendering {}",
+                    dot.display()
+                );
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Discards all artifacts
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSink;
+
+impl ArtifactSink for NullSink {
+    fn write(&self, _path: &Path, _contents: &[u8]) -> io::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
+    fn render_graphviz(&self, _dot: &Path, _format: &str) -> io::Result<Option<PathBuf>> {
+        Ok(None)
+    }
+}
+
+/// Replaces characters that aren't allowed within file names on some platforms
+pub fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|char| match char {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            char if char.is_control() => '_',
+            char => char,
+        })
+        .collect()
+}
+
+fn graphviz_available(graphviz: &Path) -> bool {
+    Command::new(graphviz)
+        .arg("-V")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
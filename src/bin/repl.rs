@@ -0,0 +1,229 @@
+//! An interactive shell for loading ir, running passes over it and inspecting the results
+
+use sruth::{
+    builder::Context,
+    driver::{Driver, Pass},
+    repr::{json, utils::IRDisplay, Function},
+    symbols::FunctionNames,
+    verify::ValidityError,
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    sync::Arc,
+};
+
+const HELP: &str = "\
+commands:
+    load <path>         load a json module, replacing the current one
+    functions           list the loaded functions
+    print <name|id>     pretty print a function
+    passes              list the available passes
+    run <pass>...       run passes over the module in the order given
+    fuel <n|off>        limit the number of rewrites each run can make
+    errors              show the diagnostics from the last run
+    dump <path>         write the current module to a json file
+    help                show this message
+    quit                exit the repl";
+
+struct Repl {
+    driver: Driver,
+    functions: Vec<Function>,
+    errors: Vec<ValidityError>,
+}
+
+impl Repl {
+    fn new() -> Self {
+        Self {
+            driver: Driver::new(Arc::new(Context::new(0))),
+            functions: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Runs a single command, returning `false` if the repl should exit
+    fn execute(&mut self, line: &str) -> bool {
+        let mut args = line.split_whitespace();
+        let command = match args.next() {
+            Some(command) => command,
+            None => return true,
+        };
+        let args: Vec<&str> = args.collect();
+
+        match (command, args.as_slice()) {
+            ("load", [path]) => self.load(path),
+            ("functions", []) => self.list_functions(),
+            ("print", [function]) => self.print(function),
+            ("passes", []) => {
+                for pass in Pass::ALL {
+                    println!("{:<24} {}", pass.name(), pass.description());
+                }
+            }
+            ("run", passes) if !passes.is_empty() => self.run(passes),
+            ("fuel", [fuel]) => self.set_fuel(fuel),
+            ("errors", []) => self.list_errors(),
+            ("dump", [path]) => self.dump(path),
+            ("help", []) => println!("{}", HELP),
+            ("quit", []) | ("exit", []) => return false,
+
+            _ => eprintln!("invalid command `{}`, try `help`", line.trim()),
+        }
+
+        true
+    }
+
+    fn load(&mut self, path: &str) {
+        let module: json::Module = match File::open(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| json::from_reader(BufReader::new(file)).map_err(|err| err.to_string()))
+        {
+            Ok(module) => module,
+            Err(err) => return eprintln!("failed to read {}: {}", path, err),
+        };
+
+        // Idents are only valid for the interner they were created with, so each
+        // module gets a fresh context
+        let driver = Driver::new(Arc::new(Context::new(0))).with_fuel(self.driver.fuel());
+        match module.load(driver.context().interner()) {
+            Ok(functions) => {
+                println!("loaded {} functions from {}", functions.len(), path);
+
+                self.driver = driver;
+                self.functions = functions;
+                self.errors.clear();
+            }
+
+            Err(err) => eprintln!("failed to load {}: {}", path, err),
+        }
+    }
+
+    fn list_functions(&self) {
+        if self.functions.is_empty() {
+            println!("no functions are loaded");
+        }
+
+        for function in self.functions.iter() {
+            println!(
+                "{:<16} {:<24} {} blocks",
+                function
+                    .id
+                    .to_pretty_string(self.driver.context().interner()),
+                self.function_name(function),
+                function.basic_blocks.len(),
+            );
+        }
+    }
+
+    fn print(&self, target: &str) {
+        let (interner, constants) = (
+            self.driver.context().interner(),
+            self.driver.context().constants(),
+        );
+        let function = self.functions.iter().find(|function| {
+            self.function_name(function) == target
+                || function.id.to_pretty_string(interner) == target
+                || function.id.as_u64().to_string() == target
+        });
+
+        match function {
+            Some(function) => println!("{}", function.to_pretty_string_with(interner, constants)),
+            None => eprintln!("no function named `{}`", target),
+        }
+    }
+
+    fn run(&mut self, names: &[&str]) {
+        let mut passes = Vec::with_capacity(names.len());
+        for &name in names {
+            match Pass::from_name(name) {
+                Some(pass) => passes.push(pass),
+                None => return eprintln!("unknown pass `{}`, try `passes`", name),
+            }
+        }
+
+        let output = self.driver.run(self.functions.clone(), &passes);
+        println!(
+            "ran {} passes, {} functions and {} errors",
+            passes.len(),
+            output.functions.len(),
+            output.errors.len(),
+        );
+
+        self.functions = output.functions;
+        self.errors = output.errors;
+    }
+
+    fn set_fuel(&mut self, fuel: &str) {
+        let fuel = match fuel {
+            "off" => None,
+            fuel => match fuel.parse() {
+                Ok(fuel) => Some(fuel),
+                Err(_) => return eprintln!("invalid fuel `{}`, expected a number or `off`", fuel),
+            },
+        };
+
+        self.driver = self.driver.clone().with_fuel(fuel);
+        match fuel {
+            Some(fuel) => println!("each run can make at most {} rewrites", fuel),
+            None => println!("runs are no longer limited"),
+        }
+    }
+
+    fn list_errors(&self) {
+        if self.errors.is_empty() {
+            println!("no errors");
+        }
+
+        let names = FunctionNames::new(self.driver.context().interner(), &self.functions);
+        for error in self.errors.iter() {
+            println!("{}", error.display_with(&names));
+        }
+    }
+
+    fn dump(&self, path: &str) {
+        let module = json::Module::new(
+            self.functions.iter().cloned(),
+            self.driver.context().interner(),
+        );
+
+        let result = File::create(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                json::to_writer(BufWriter::new(file), &module).map_err(|err| err.to_string())
+            });
+
+        match result {
+            Ok(()) => println!("wrote {} functions to {}", module.functions.len(), path),
+            Err(err) => eprintln!("failed to write {}: {}", path, err),
+        }
+    }
+
+    fn function_name(&self, function: &Function) -> String {
+        function
+            .name
+            .map(|name| name.to_pretty_string(self.driver.context().interner()))
+            .unwrap_or_default()
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    println!("sruth repl, type `help` for a list of commands");
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+
+        match lines.next() {
+            Some(line) => {
+                if !repl.execute(&line?) {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
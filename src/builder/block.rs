@@ -1,10 +1,14 @@
 use crate::{
-    builder::{BuildResult, BuilderError, FunctionBuilder},
+    builder::{BinaryOpKind, BuildResult, BuilderError, FunctionBuilder, Signature, TypeMismatch},
     repr::{
         basic_block::BasicBlockDesc,
-        instruction::{Add, Assign, Call, Cmp, Div, Mul, Sub},
-        terminator::{Branch, Label, Return},
-        BasicBlockId, FuncId, Ident, InstId, Terminator, Type, TypedVar, Value, VarId,
+        instruction::{
+            Add, Assign, BinopExt, Call, Cmp, Div, ExtractValue, InsertValue, Intrinsic,
+            IntrinsicOp, Mul, Neg, Opaque, Rem, Select, Sub,
+        },
+        terminator::{Branch, Label, Return, Switch},
+        BasicBlockId, Constant, FuncId, Ident, InstId, Instruction, Span, Terminator, TrapCode,
+        Type, TypedVar, Value, VarId,
     },
 };
 use std::{convert::TryInto, mem, ops::Deref, thread};
@@ -13,6 +17,7 @@ use std::{convert::TryInto, mem, ops::Deref, thread};
 pub struct BasicBlockBuilder<'a, 'b: 'a> {
     meta: IncompleteBasicBlock,
     function: &'a mut FunctionBuilder<'b>,
+    span: Option<Span>,
     pub(super) finished: bool,
 }
 
@@ -22,10 +27,45 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
         self.meta.id
     }
 
+    /// Gets the function builder the current block belongs to, allowing other blocks
+    /// (like the merge block of [`BasicBlockBuilder::if_else()`]) to be built
+    pub fn function(&mut self) -> &mut FunctionBuilder<'b> {
+        self.function
+    }
+
+    /// Sets the source span that's attached to all instructions built after this,
+    /// as well as to the block's terminator, e.g. `block.at(span).add(lhs, rhs)?`
+    pub fn at(&mut self, span: Span) -> &mut Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Clears the current source span, returning it
+    pub fn clear_span(&mut self) -> Option<Span> {
+        self.span.take()
+    }
+
+    pub const fn span(&self) -> Option<Span> {
+        self.span
+    }
+
     pub const fn is_terminated(&self) -> bool {
         self.meta.terminator.is_some()
     }
 
+    pub const fn name(&self) -> Option<Ident> {
+        self.meta.name
+    }
+
+    /// Names the current block, returning its previous name
+    pub fn named<N>(&mut self, name: N) -> Option<Ident>
+    where
+        N: AsRef<str>,
+    {
+        let name = Ident::new(self.function.context.interner.get_or_intern(name));
+        self.meta.name.replace(name)
+    }
+
     pub fn assign<V>(&mut self, value: V) -> TypedVar
     where
         V: Into<Value>,
@@ -34,9 +74,7 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
         let value = value.into();
         let var = TypedVar::new(dest, value.ty().clone());
 
-        self.function
-            .instructions
-            .push((id, Assign::new(dest, value, None).into()));
+        self.push_instruction(id, Assign::new(dest, value, None).into());
 
         var
     }
@@ -51,147 +89,233 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
         let value = value.into();
         let var = TypedVar::new(dest, value.ty().clone());
 
-        self.function
-            .instructions
-            .push((id, Assign::new(dest, value, Some(name)).into()));
+        self.push_instruction(id, Assign::new(dest, value, Some(name)).into());
 
         var
     }
 
-    // TODO: Verify param types & get return type
+    /// Calls a function, checking the arguments against its [signature](Signature)
+    ///
+    /// Calls to functions that haven't been built or declared yet can't be checked
+    /// until verification and leave their return type to be inferred
     pub fn call(&mut self, function: FuncId, args: Vec<Value>) -> BuildResult<TypedVar> {
-        let (id, dest) = self.inst_and_dest();
-        let var = TypedVar::new(dest, Type::Infer);
+        let (args, ret_ty) = match self.callee_signature(function) {
+            Some(signature) => {
+                let args = self.check_call_args(function, &signature, args)?;
+                (args, signature.ret_ty)
+            }
+            None => (args, Type::Infer),
+        };
 
-        self.function
-            .instructions
-            .push((id, Call::new(function, args, dest, Type::Infer).into()));
-        self.meta.instructions.push(id);
+        let (id, dest) = self.inst_and_dest();
+        let var = TypedVar::new(dest, ret_ty.clone());
+        self.push_instruction(id, Call::new(function, args, dest, ret_ty).into());
 
         Ok(var)
     }
 
-    pub fn add<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    /// Calls a function with the leading arguments given in `args`, passing the
+    /// default of every parameter after them
+    pub fn call_with_defaults(
+        &mut self,
+        function: FuncId,
+        args: Vec<Value>,
+    ) -> BuildResult<TypedVar> {
+        let signature = self.known_signature(function)?;
+        let mut args: Vec<_> = args.into_iter().map(Some).collect();
+        args.resize(args.len().max(signature.params.len()), None);
+
+        let args = Self::fill_defaults(function, &signature, args)?;
+        self.call(function, args)
+    }
+
+    /// Calls a function with arguments passed by the name of their parameter,
+    /// passing the default of every parameter that isn't named
+    pub fn call_named<I, N, V>(&mut self, function: FuncId, named: I) -> BuildResult<TypedVar>
     where
-        L: Into<Value>,
-        R: Into<Value>,
+        I: IntoIterator<Item = (N, V)>,
+        N: AsRef<str>,
+        V: Into<Value>,
     {
-        let (lhs, rhs) = (lhs.into(), rhs.into());
-        let (lhs, rhs) = match (lhs.ty().is_infer(), rhs.ty().is_infer()) {
-            (true, false) => (
-                Value {
-                    ty: rhs.ty().clone(),
-                    ..lhs
-                },
-                rhs,
-            ),
-            (false, true) => {
-                let ty = lhs.ty().clone();
-                (lhs, Value { ty, ..rhs })
+        let signature = self.known_signature(function)?;
+
+        let mut args = vec![None; signature.params.len()];
+        for (name, value) in named {
+            let unknown = || BuilderError::UnknownNamedArgument {
+                callee: function,
+                name: name.as_ref().to_owned(),
+            };
+            let ident = self
+                .function
+                .context
+                .interner
+                .get(name.as_ref())
+                .ok_or_else(unknown)?;
+            let idx = signature
+                .param_index(Ident::new(ident))
+                .ok_or_else(unknown)?;
+
+            if args[idx].replace(value.into()).is_some() {
+                return Err(BuilderError::DuplicateNamedArgument {
+                    callee: function,
+                    name: name.as_ref().to_owned(),
+                });
             }
-            (true, true) => (lhs, rhs),
-            (false, false) if lhs.ty() == rhs.ty() => (lhs, rhs),
-            (false, false) => {
-                tracing::error!(
-                    "created an add with a left hand side type of {:?} and a right hand side type of {:?} in {:?}",
-                    lhs.ty(), rhs.ty(), self.block_id(),
-                );
+        }
 
-                return Err(BuilderError::MismatchedOperandTypes);
-            }
-        };
+        let args = Self::fill_defaults(function, &signature, args)?;
+        self.call(function, args)
+    }
 
+    /// Emits a target-specific payload that's passed through to the backend for
+    /// `target` untouched, see [`Opaque`]
+    pub fn opaque<T, P>(
+        &mut self,
+        target: T,
+        payload: P,
+        args: Vec<Value>,
+        ret_ty: Type,
+    ) -> TypedVar
+    where
+        T: Into<String>,
+        P: Into<Vec<u8>>,
+    {
         let (id, dest) = self.inst_and_dest();
-        let var = TypedVar::new(dest, lhs.ty().clone());
+        let var = TypedVar::new(dest, ret_ty.clone());
 
-        self.function
-            .instructions
-            .push((id, Add::new(lhs, rhs, dest).into()));
-        self.meta.instructions.push(id);
+        let opaque = Opaque::new(target.into(), payload.into(), args, dest, ret_ty);
+        self.push_instruction(id, opaque.into());
+
+        var
+    }
+
+    /// Applies an intrinsic to the given arguments, returning an error if they
+    /// don't fit its signature, see [`Intrinsic`]
+    pub fn intrinsic(&mut self, op: IntrinsicOp, args: Vec<Value>) -> BuildResult<TypedVar> {
+        let arg_types: Vec<Type> = args.iter().map(|arg| arg.ty().clone()).collect();
+        let ret_ty = op
+            .result_type(&arg_types)
+            .ok_or(BuilderError::InvalidIntrinsicArguments {
+                intrinsic: op,
+                args: arg_types,
+            })?;
+
+        let (id, dest) = self.inst_and_dest();
+        let var = TypedVar::new(dest, ret_ty.clone());
+
+        self.push_instruction(id, Intrinsic::new(op, args, dest, ret_ty).into());
 
         Ok(var)
     }
 
-    pub fn sub<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    /// Reads the element or field at `index` out of an array or struct
+    pub fn extract_value<V>(&mut self, aggregate: V, index: u64) -> BuildResult<TypedVar>
     where
-        L: Into<Value>,
-        R: Into<Value>,
+        V: Into<Value>,
     {
-        let (lhs, rhs) = (lhs.into(), rhs.into());
-        let (lhs, rhs) = match (lhs.ty().is_infer(), rhs.ty().is_infer()) {
-            (true, false) => (
-                Value {
-                    ty: rhs.ty().clone(),
-                    ..lhs
-                },
-                rhs,
-            ),
-            (false, true) => {
-                let ty = lhs.ty().clone();
-                (lhs, Value { ty, ..rhs })
-            }
-            (true, true) => (lhs, rhs),
-            (false, false) if lhs.ty() == rhs.ty() => (lhs, rhs),
-            (false, false) => {
-                tracing::error!(
-                    "created an sub with a left hand side type of {:?} and a right hand side type of {:?} in {:?}",
-                    lhs.ty(), rhs.ty(), self.block_id(),
-                );
+        let aggregate = aggregate.into();
+        let ty = Self::field_type(aggregate.ty(), index)?;
+        let (id, dest) = self.inst_and_dest();
+        let var = TypedVar::new(dest, ty.clone());
 
-                return Err(BuilderError::MismatchedOperandTypes);
-            }
-        };
+        self.push_instruction(id, ExtractValue::new(dest, aggregate, index, ty).into());
+
+        Ok(var)
+    }
+
+    /// Copies an array or struct with the element or field at `index` replaced by
+    /// `value`
+    pub fn insert_value<A, V>(
+        &mut self,
+        aggregate: A,
+        index: u64,
+        value: V,
+    ) -> BuildResult<TypedVar>
+    where
+        A: Into<Value>,
+        V: Into<Value>,
+    {
+        let (aggregate, value) = (aggregate.into(), value.into());
+        let field = Self::field_type(aggregate.ty(), index)?;
+        if !field.is_infer() && !value.ty().is_infer() && field != *value.ty() {
+            return Err(BuilderError::IncorrectFieldType {
+                expected: field,
+                got: value.ty().clone(),
+            });
+        }
 
         let (id, dest) = self.inst_and_dest();
-        let var = TypedVar::new(dest, lhs.ty().clone());
+        let var = TypedVar::new(dest, aggregate.ty().clone());
 
-        self.function
-            .instructions
-            .push((id, Sub::new(lhs, rhs, dest).into()));
-        self.meta.instructions.push(id);
+        self.push_instruction(id, InsertValue::new(dest, aggregate, index, value).into());
 
         Ok(var)
     }
 
-    pub fn mul<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    pub fn add<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
     where
         L: Into<Value>,
         R: Into<Value>,
     {
-        let (lhs, rhs) = (lhs.into(), rhs.into());
-        let (lhs, rhs) = match (lhs.ty().is_infer(), rhs.ty().is_infer()) {
-            (true, false) => (
-                Value {
-                    ty: rhs.ty().clone(),
-                    ..lhs
-                },
-                rhs,
-            ),
-            (false, true) => {
-                let ty = lhs.ty().clone();
-                (lhs, Value { ty, ..rhs })
-            }
-            (true, true) => (lhs, rhs),
-            (false, false) if lhs.ty() == rhs.ty() => (lhs, rhs),
-            (false, false) => {
-                tracing::error!(
-                    "created an mul with a left hand side type of {:?} and a right hand side type of {:?} in {:?}",
-                    lhs.ty(), rhs.ty(), self.block_id(),
-                );
+        self.binop::<Add>(BinaryOpKind::Add, lhs.into(), rhs.into(), None)
+    }
 
-                return Err(BuilderError::MismatchedOperandTypes);
-            }
-        };
+    pub fn add_named<N, L, R>(&mut self, name: N, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    where
+        N: AsRef<str>,
+        L: Into<Value>,
+        R: Into<Value>,
+    {
+        self.binop::<Add>(
+            BinaryOpKind::Add,
+            lhs.into(),
+            rhs.into(),
+            Some(name.as_ref()),
+        )
+    }
 
-        let (id, dest) = self.inst_and_dest();
-        let var = TypedVar::new(dest, lhs.ty().clone());
+    pub fn sub<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    where
+        L: Into<Value>,
+        R: Into<Value>,
+    {
+        self.binop::<Sub>(BinaryOpKind::Sub, lhs.into(), rhs.into(), None)
+    }
 
-        self.function
-            .instructions
-            .push((id, Mul::new(lhs, rhs, dest).into()));
-        self.meta.instructions.push(id);
+    pub fn sub_named<N, L, R>(&mut self, name: N, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    where
+        N: AsRef<str>,
+        L: Into<Value>,
+        R: Into<Value>,
+    {
+        self.binop::<Sub>(
+            BinaryOpKind::Sub,
+            lhs.into(),
+            rhs.into(),
+            Some(name.as_ref()),
+        )
+    }
 
-        Ok(var)
+    pub fn mul<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    where
+        L: Into<Value>,
+        R: Into<Value>,
+    {
+        self.binop::<Mul>(BinaryOpKind::Mul, lhs.into(), rhs.into(), None)
+    }
+
+    pub fn mul_named<N, L, R>(&mut self, name: N, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    where
+        N: AsRef<str>,
+        L: Into<Value>,
+        R: Into<Value>,
+    {
+        self.binop::<Mul>(
+            BinaryOpKind::Mul,
+            lhs.into(),
+            rhs.into(),
+            Some(name.as_ref()),
+        )
     }
 
     pub fn div<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
@@ -199,38 +323,62 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
         L: Into<Value>,
         R: Into<Value>,
     {
-        let (lhs, rhs) = (lhs.into(), rhs.into());
-        let (lhs, rhs) = match (lhs.ty().is_infer(), rhs.ty().is_infer()) {
-            (true, false) => (
-                Value {
-                    ty: rhs.ty().clone(),
-                    ..lhs
-                },
-                rhs,
-            ),
-            (false, true) => {
-                let ty = lhs.ty().clone();
-                (lhs, Value { ty, ..rhs })
-            }
-            (true, true) => (lhs, rhs),
-            (false, false) if lhs.ty() == rhs.ty() => (lhs, rhs),
-            (false, false) => {
-                tracing::error!(
-                    "created an div with a left hand side type of {:?} and a right hand side type of {:?} in {:?}",
-                    lhs.ty(), rhs.ty(), self.block_id(),
-                );
+        self.binop::<Div>(BinaryOpKind::Div, lhs.into(), rhs.into(), None)
+    }
 
-                return Err(BuilderError::MismatchedOperandTypes);
-            }
-        };
+    pub fn div_named<N, L, R>(&mut self, name: N, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    where
+        N: AsRef<str>,
+        L: Into<Value>,
+        R: Into<Value>,
+    {
+        self.binop::<Div>(
+            BinaryOpKind::Div,
+            lhs.into(),
+            rhs.into(),
+            Some(name.as_ref()),
+        )
+    }
+
+    pub fn rem<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    where
+        L: Into<Value>,
+        R: Into<Value>,
+    {
+        self.binop::<Rem>(BinaryOpKind::Rem, lhs.into(), rhs.into(), None)
+    }
+
+    pub fn rem_named<N, L, R>(&mut self, name: N, lhs: L, rhs: R) -> BuildResult<TypedVar>
+    where
+        N: AsRef<str>,
+        L: Into<Value>,
+        R: Into<Value>,
+    {
+        self.binop::<Rem>(
+            BinaryOpKind::Rem,
+            lhs.into(),
+            rhs.into(),
+            Some(name.as_ref()),
+        )
+    }
+
+    /// Negates a number, unlike subtracting it from zero this flips the sign of
+    /// float zeroes
+    pub fn neg<V>(&mut self, value: V) -> BuildResult<TypedVar>
+    where
+        V: Into<Value>,
+    {
+        let value = value.into();
+        if !value.ty().is_numeric() && !value.ty().is_infer() {
+            return Err(BuilderError::InvalidNegation {
+                ty: value.ty().clone(),
+            });
+        }
 
         let (id, dest) = self.inst_and_dest();
-        let var = TypedVar::new(dest, lhs.ty().clone());
+        let var = TypedVar::new(dest, value.ty().clone());
 
-        self.function
-            .instructions
-            .push((id, Div::new(lhs, rhs, dest).into()));
-        self.meta.instructions.push(id);
+        self.push_instruction(id, Neg::new(dest, value).into());
 
         Ok(var)
     }
@@ -266,51 +414,209 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
         Ok(old_terminator)
     }
 
+    /// Terminates the current block with a switch on `scrutinee`, jumping to the
+    /// block of the first case equal to it or to `default` if there isn't one
+    pub fn switch<V, C>(
+        &mut self,
+        scrutinee: V,
+        cases: C,
+        default: BasicBlockId,
+    ) -> BuildResult<Option<Terminator>>
+    where
+        V: Into<Value>,
+        C: IntoIterator<Item = (Constant, BasicBlockId)>,
+    {
+        let mut scrutinee = scrutinee.into();
+        let cases: Vec<_> = cases
+            .into_iter()
+            .map(|(case, target)| (case, Label::new(target)))
+            .collect();
+
+        if scrutinee.ty().is_infer() {
+            if let Some((case, _)) = cases.first() {
+                scrutinee.ty = case.ty();
+            }
+        }
+
+        if let Some((case, _)) = cases
+            .iter()
+            .find(|(case, _)| !scrutinee.ty().is_infer() && &case.ty() != scrutinee.ty())
+        {
+            tracing::error!(
+                "created a switch over a {:?} with a case of type {:?} for {:?} in {:?}",
+                scrutinee.ty(),
+                case.ty(),
+                self.block_id(),
+                self.function.func_id(),
+            );
+
+            return Err(BuilderError::IncorrectSwitchCaseType);
+        }
+
+        let old_terminator = self
+            .meta
+            .terminator
+            .replace(Switch::new(scrutinee, cases, Label::new(default)).into());
+
+        Ok(old_terminator)
+    }
+
+    /// Weighs the edges of the current block's branch or switch, one weight for each
+    /// of its targets in the order they were given in
+    pub fn branch_weights<W>(&mut self, weights: W) -> BuildResult<()>
+    where
+        W: IntoIterator<Item = u32>,
+    {
+        let weights: Vec<u32> = weights.into_iter().collect();
+        let mut labels = self
+            .meta
+            .terminator
+            .as_mut()
+            .map(Terminator::labels_mut)
+            .unwrap_or_default();
+
+        let label_count = labels.len();
+        if label_count != weights.len() {
+            tracing::error!(
+                "gave {} branch weights to the terminator of {:?} in {:?} with {} labels",
+                weights.len(),
+                self.block_id(),
+                self.function.func_id(),
+                label_count,
+            );
+
+            return Err(BuilderError::MismatchedBranchWeights {
+                labels: label_count,
+                weights: weights.len(),
+            });
+        }
+
+        for (label, weight) in labels.iter_mut().zip(weights) {
+            label.weight = Some(weight);
+        }
+
+        Ok(())
+    }
+
     pub fn cmp<L, R>(&mut self, lhs: L, rhs: R) -> BuildResult<TypedVar>
     where
         L: Into<Value>,
         R: Into<Value>,
     {
-        let (lhs, rhs) = (lhs.into(), rhs.into());
-        let (lhs, rhs) = match (lhs.ty().is_infer(), rhs.ty().is_infer()) {
-            (true, false) => (
-                Value {
-                    ty: rhs.ty().clone(),
-                    ..lhs
-                },
-                rhs,
-            ),
-            (false, true) => {
-                let ty = lhs.ty().clone();
-                (lhs, Value { ty, ..rhs })
-            }
-            (true, true) => (lhs, rhs),
-            (false, false) if lhs.ty() == rhs.ty() => (lhs, rhs),
-            (false, false) => {
-                tracing::error!(
-                    "created a cmp with a left hand side type of {:?} and a right hand side type of {:?} in {:?}",
-                    lhs.ty(), rhs.ty(), self.block_id(),
-                );
+        let (lhs, rhs) = self.unify_operands(BinaryOpKind::Cmp, lhs.into(), rhs.into())?;
+        let (id, dest) = self.inst_and_dest();
+        let var = TypedVar::new(dest, Type::Bool);
 
-                return Err(BuilderError::MismatchedOperandTypes);
-            }
-        };
+        self.push_instruction(id, Cmp::new(lhs, rhs, dest).into());
+
+        Ok(var)
+    }
+
+    /// Picks `if_true` when `cond` holds and `if_false` otherwise without branching
+    pub fn select<C, T, F>(&mut self, cond: C, if_true: T, if_false: F) -> BuildResult<TypedVar>
+    where
+        C: Into<Value>,
+        T: Into<Value>,
+        F: Into<Value>,
+    {
+        let mut cond = cond.into();
+        if cond.is_var() && cond.ty().is_infer() {
+            cond.ty = Type::Bool;
+        } else if cond.ty() != &Type::Bool {
+            tracing::error!(
+                "created a select with a condition type of {:?} for {:?} in {:?}",
+                cond.ty(),
+                self.block_id(),
+                self.function.func_id(),
+            );
+
+            return Err(BuilderError::IncorrectConditionType);
+        }
 
+        let (if_true, if_false) =
+            self.unify_operands(BinaryOpKind::Select, if_true.into(), if_false.into())?;
         let (id, dest) = self.inst_and_dest();
-        let var = TypedVar::new(dest, Type::Bool);
+        let var = TypedVar::new(dest, if_true.ty().clone());
 
-        self.function
-            .instructions
-            .push((id, Cmp::new(lhs, rhs, dest).into()));
-        self.meta.instructions.push(id);
+        self.push_instruction(id, Select::new(dest, cond, if_true, if_false).into());
 
         Ok(var)
     }
 
+    /// Terminates the current block with a branch on `cond`, building the `then` and
+    /// `else` blocks which both jump to a shared merge block if they aren't otherwise
+    /// terminated
+    ///
+    /// Building should continue within the returned [`IfElse::merge`] block via
+    /// [`FunctionBuilder::resume_building()`]
+    // TODO: Return the merged value once phi nodes exist
+    pub fn if_else<C, T, E>(&mut self, cond: C, then: T, else_: E) -> BuildResult<IfElse>
+    where
+        C: Into<Value>,
+        T: FnOnce(&mut BasicBlockBuilder<'_, '_>) -> BuildResult<()>,
+        E: FnOnce(&mut BasicBlockBuilder<'_, '_>) -> BuildResult<()>,
+    {
+        let (mut then_block, mut else_block, mut merge) = (
+            self.function.allocate_named_basic_block("if.then"),
+            self.function.allocate_named_basic_block("if.else"),
+            self.function.allocate_named_basic_block("if.merge"),
+        );
+        let (then_id, else_id, merge_id) = (*then_block, *else_block, *merge);
+
+        if let Err(err) = self.branch(cond, then_id, else_id) {
+            // Prevent drop panics from the unused blocks
+            then_block.finished = true;
+            else_block.finished = true;
+            merge.finished = true;
+
+            return Err(err);
+        }
+
+        let then_built = self.function.resume_building(then_block, |block| {
+            then(block)?;
+
+            if !block.is_terminated() {
+                block.jump(merge_id);
+            }
+
+            Ok(())
+        });
+        if let Err(err) = then_built {
+            else_block.finished = true;
+            merge.finished = true;
+
+            return Err(err);
+        }
+
+        let else_built = self.function.resume_building(else_block, |block| {
+            else_(block)?;
+
+            if !block.is_terminated() {
+                block.jump(merge_id);
+            }
+
+            Ok(())
+        });
+        if let Err(err) = else_built {
+            merge.finished = true;
+            return Err(err);
+        }
+
+        Ok(IfElse {
+            then: then_id,
+            else_: else_id,
+            merge,
+        })
+    }
+
     pub fn jump(&mut self, block: BasicBlockId) -> Option<Terminator> {
         self.meta.terminator.replace(Terminator::Jump(block))
     }
 
+    pub fn trap(&mut self, code: TrapCode) -> Option<Terminator> {
+        self.meta.terminator.replace(Terminator::Trap(code))
+    }
+
     pub fn ret<V>(&mut self, value: V) -> BuildResult<Option<Terminator>>
     where
         V: Into<Value>,
@@ -341,6 +647,54 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
     pub fn ret_unit(&mut self) -> Option<Terminator> {
         self.meta.terminator.replace(Return::new(None).into())
     }
+
+    /// Returns multiple values from a function returning a [tuple](Type::Tuple),
+    /// one value for each of its elements
+    pub fn ret_multiple<I, V>(&mut self, values: I) -> BuildResult<Option<Terminator>>
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<Value>,
+    {
+        let results = self.function.meta.ret_ty.results().to_vec();
+        let mut values: Vec<Value> = values.into_iter().map(Into::into).collect();
+        if values.len() != results.len() {
+            tracing::error!(
+                "created a return of {} values for {:?} when {:?} returns {} values",
+                values.len(),
+                self.block_id(),
+                self.function.func_id(),
+                results.len(),
+            );
+
+            return Err(BuilderError::MismatchedReturnArity {
+                expected: results.len(),
+                got: values.len(),
+            });
+        }
+
+        for (value, expected) in values.iter_mut().zip(results) {
+            if value.is_var() && value.ty().is_infer() {
+                value.ty = expected;
+            } else if value.ty() != &expected {
+                tracing::error!(
+                    "created a return with a value of type {:?} for {:?} when {:?} returns {:?}",
+                    value.ty(),
+                    self.block_id(),
+                    self.function.func_id(),
+                    self.function.meta.ret_ty,
+                );
+
+                return Err(BuilderError::MismatchedReturnTypes);
+            }
+        }
+
+        let old_terminator = self
+            .meta
+            .terminator
+            .replace(Return::multiple(values).into());
+
+        Ok(old_terminator)
+    }
 }
 
 // Private API
@@ -349,10 +703,209 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
         Self {
             meta,
             function,
+            span: None,
             finished: false,
         }
     }
 
+    /// The signature of a callee, functions calling themselves use the signature
+    /// they've been given so far
+    fn callee_signature(&self, function: FuncId) -> Option<Signature> {
+        self.function
+            .context
+            .signature(function)
+            .or_else(|| (function == self.function.func_id()).then(|| self.function.signature()))
+    }
+
+    fn known_signature(&self, function: FuncId) -> BuildResult<Signature> {
+        self.callee_signature(function).ok_or_else(|| {
+            tracing::error!(
+                "called {:?} by parameter name or default before its signature was known",
+                function,
+            );
+
+            BuilderError::UnknownSignature { callee: function }
+        })
+    }
+
+    /// Replaces every missing argument with its parameter's default
+    fn fill_defaults(
+        function: FuncId,
+        signature: &Signature,
+        args: Vec<Option<Value>>,
+    ) -> BuildResult<Vec<Value>> {
+        args.into_iter()
+            .enumerate()
+            .map(|(arg, value)| {
+                value
+                    .or_else(|| {
+                        signature
+                            .params
+                            .get(arg)
+                            .and_then(|param| param.default.clone())
+                            .map(Value::from)
+                    })
+                    .ok_or(BuilderError::MissingCallArgument {
+                        callee: function,
+                        arg,
+                    })
+            })
+            .collect()
+    }
+
+    /// Checks the arguments of a call against the callee's signature, untyped
+    /// arguments take on the type of their parameter
+    fn check_call_args(
+        &self,
+        function: FuncId,
+        signature: &Signature,
+        args: Vec<Value>,
+    ) -> BuildResult<Vec<Value>> {
+        if args.len() != signature.params.len() {
+            tracing::error!(
+                "called {:?} with {} arguments when it takes {} in {:?} of {:?}",
+                function,
+                args.len(),
+                signature.params.len(),
+                self.block_id(),
+                self.function.func_id(),
+            );
+
+            return Err(BuilderError::CallArityMismatch {
+                callee: function,
+                expected: signature.params.len(),
+                got: args.len(),
+            });
+        }
+
+        args.into_iter()
+            .zip(signature.params.iter())
+            .enumerate()
+            .map(|(arg, (value, param))| {
+                if value.ty().is_infer() {
+                    Ok(Value {
+                        ty: param.ty.clone(),
+                        ..value
+                    })
+                } else if param.ty.is_infer() || *value.ty() == param.ty {
+                    Ok(value)
+                } else {
+                    tracing::error!(
+                        "passed a {} as argument {} of {:?} when it takes a {} in {:?} of {:?}",
+                        value.ty(),
+                        arg,
+                        function,
+                        param.ty,
+                        self.block_id(),
+                        self.function.func_id(),
+                    );
+
+                    Err(BuilderError::CallArgumentTypeMismatch {
+                        callee: function,
+                        arg,
+                        expected: param.ty.clone(),
+                        got: value.ty().clone(),
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// Adds an instruction to the current block, attaching the current span to it
+    fn push_instruction(&mut self, id: InstId, inst: Instruction) {
+        if let Some(span) = self.span {
+            self.function.instruction_spans.push((id, span));
+        }
+
+        self.function.instructions.push((id, inst));
+        self.meta.instructions.push(id);
+    }
+
+    /// Gets the type of the element or field at `index` within an aggregate type
+    fn field_type(ty: &Type, index: u64) -> BuildResult<Type> {
+        if ty.is_infer() {
+            return Ok(Type::Infer);
+        }
+
+        ty.field(index)
+            .cloned()
+            .ok_or_else(|| BuilderError::InvalidAggregateIndex {
+                ty: ty.clone(),
+                index,
+            })
+    }
+
+    /// Infers the types of untyped operands from their counterparts and checks that
+    /// the operand types are compatible with each other and with the operation
+    fn unify_operands(
+        &self,
+        operation: BinaryOpKind,
+        lhs: Value,
+        rhs: Value,
+    ) -> BuildResult<(Value, Value)> {
+        let (lhs, rhs) = match (lhs.ty().is_infer(), rhs.ty().is_infer()) {
+            (true, false) => (
+                Value {
+                    ty: rhs.ty().clone(),
+                    ..lhs
+                },
+                rhs,
+            ),
+            (false, true) => {
+                let ty = lhs.ty().clone();
+                (lhs, Value { ty, ..rhs })
+            }
+            (true, true) | (false, false) => (lhs, rhs),
+        };
+
+        let is_valid = lhs.ty() == rhs.ty()
+            && (!operation.is_arithmetic() || lhs.ty().is_numeric() || lhs.ty().is_infer());
+        if !is_valid {
+            let function = self.function.func_id();
+            let function_name = self
+                .function
+                .name()
+                .map(|name| self.function.context.interner.resolve(&name.0).to_owned());
+
+            tracing::error!(
+                "created a {} with a left hand side type of {:?} and a right hand side type of {:?} in {:?} of {:?}",
+                operation, lhs.ty(), rhs.ty(), self.block_id(), function,
+            );
+
+            return Err(BuilderError::TypeMismatch(TypeMismatch {
+                operation,
+                lhs: lhs.ty().clone(),
+                rhs: rhs.ty().clone(),
+                function,
+                function_name,
+            }));
+        }
+
+        Ok((lhs, rhs))
+    }
+
+    /// Builds a binary operation from its unified operands, naming its destination
+    /// if it's given a name
+    fn binop<B>(
+        &mut self,
+        operation: BinaryOpKind,
+        lhs: Value,
+        rhs: Value,
+        name: Option<&str>,
+    ) -> BuildResult<TypedVar>
+    where
+        B: BinopExt + Into<Instruction>,
+    {
+        let (lhs, rhs) = self.unify_operands(operation, lhs, rhs)?;
+        let (id, dest) = self.inst_and_dest();
+        let name = name.map(|name| Ident::new(self.function.context.interner.get_or_intern(name)));
+        let var = TypedVar::new(dest, lhs.ty().clone());
+
+        self.push_instruction(id, B::from_parts(lhs, rhs, dest, name).into());
+
+        Ok(var)
+    }
+
     fn inst_and_dest(&self) -> (InstId, VarId) {
         (
             self.function.context.inst_id(),
@@ -360,6 +913,42 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
         )
     }
 
+    /// Terminates the block with a return of the function's default value,
+    /// used by permissive builders for blocks that fall off their end
+    ///
+    /// Functions with results that have no default can't be returned from implicitly
+    /// and leave the block without a terminator
+    fn implicit_return(&mut self) -> BuildResult<()> {
+        let values: Vec<Value> = self
+            .function
+            .meta
+            .ret_ty
+            .results()
+            .iter()
+            .map(|ty| ty.default_constant().map(Value::from))
+            .collect::<Option<_>>()
+            .ok_or_else(|| {
+                tracing::error!(
+                    "couldn't implicitly terminate {:?} in {:?}, {} has no default value",
+                    self.block_id(),
+                    self.function.func_id(),
+                    self.function.meta.ret_ty,
+                );
+
+                BuilderError::MissingTerminator
+            })?;
+
+        tracing::warn!(
+            "implicitly terminated {:?} in {:?} with a return of {:?}",
+            self.block_id(),
+            self.function.func_id(),
+            values,
+        );
+
+        self.meta.terminator = Some(Return::multiple(values).into());
+        Ok(())
+    }
+
     #[track_caller]
     pub(super) fn finish(mut self) -> BuildResult<BasicBlockId> {
         self.finish_inner()
@@ -376,7 +965,15 @@ impl<'a, 'b> BasicBlockBuilder<'a, 'b> {
         }
 
         self.finished = true;
+        if self.function.permissive && !self.is_terminated() {
+            self.implicit_return()?;
+        }
+
         let block = self.meta.take().try_into()?;
+        if let Some(span) = self.span {
+            self.function.terminator_spans.push((id, span));
+        }
+
         self.function.blocks.push(block);
         self.function.meta.basic_blocks.push(id);
 
@@ -395,6 +992,15 @@ impl Drop for BasicBlockBuilder<'_, '_> {
     }
 }
 
+/// The blocks created by [`BasicBlockBuilder::if_else()`]
+#[derive(Debug)]
+#[must_use = "The merge block of an if/else must be built"]
+pub struct IfElse {
+    pub then: BasicBlockId,
+    pub else_: BasicBlockId,
+    pub merge: DeferredBasicBlock,
+}
+
 #[derive(Debug)]
 #[must_use = "Dropping a deferred basic block without completing it will panic"]
 pub struct DeferredBasicBlock {
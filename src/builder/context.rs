@@ -1,12 +1,18 @@
 use crate::{
-    builder::Builder,
+    builder::{
+        ids::{IdKind, WorkerIds},
+        signature::SignatureRegistry,
+        Builder, Signature,
+    },
     dataflow::operators::Uuid,
-    repr::{BasicBlockId, FuncId, InstId, VarId},
+    repr::{BasicBlockId, ConstId, Constant, ConstantPool, FuncId, InstId, VarId},
+    symbols::InternerSnapshot,
     vsdg::node::NodeId,
 };
 use lasso::ThreadedRodeo;
 use std::{
     num::NonZeroU64,
+    ops::Range,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
@@ -21,6 +27,8 @@ pub struct Context {
     inst_counter: AtomicU64,
     var_counter: AtomicU64,
     node_counter: AtomicU64,
+    constants: ConstantPool,
+    signatures: SignatureRegistry,
     pub(super) ident_generation: u8,
 }
 
@@ -34,6 +42,8 @@ impl Context {
             inst_counter: AtomicU64::new(0),
             var_counter: AtomicU64::new(0),
             node_counter: AtomicU64::new(0),
+            constants: ConstantPool::new(),
+            signatures: SignatureRegistry::new(),
             ident_generation,
         }
     }
@@ -45,27 +55,94 @@ impl Context {
     pub fn interner(&self) -> &ThreadedRodeo {
         &self.interner
     }
+
+    /// Takes a snapshot of the interner that can be sent to other threads and
+    /// processes, see [`InternerSnapshot`]
+    pub fn interner_snapshot(&self) -> InternerSnapshot {
+        InternerSnapshot::new(&self.interner)
+    }
+
+    /// The pool holding the constants that instructions refer to by id
+    pub fn constants(&self) -> &ConstantPool {
+        &self.constants
+    }
+
+    /// Adds a constant to the context's pool, see [`ConstantPool::intern()`]
+    pub fn intern_constant(&self, constant: Constant) -> ConstId {
+        self.constants.intern(constant)
+    }
+
+    /// The signature of a function that's been built or declared within the context
+    pub fn signature(&self, func: FuncId) -> Option<Signature> {
+        self.signatures.get(func)
+    }
+
+    /// Creates the id allocator for a dataflow worker, see [`WorkerIds`]
+    pub fn worker_ids(self: &Arc<Self>, worker: usize, chunk_size: u64) -> WorkerIds {
+        WorkerIds::new(self.clone(), worker, chunk_size)
+    }
+
+    /// Claims a contiguous range of `len` raw ids of the given kind, no id within
+    /// it will ever be allocated by the context again
+    pub fn claim_ids(&self, kind: IdKind, len: u64) -> Range<u64> {
+        let counter = match kind {
+            IdKind::Function => &self.func_counter,
+            IdKind::Block => &self.block_counter,
+            IdKind::Inst => &self.inst_counter,
+            IdKind::Var => &self.var_counter,
+        };
+
+        let start = counter.fetch_add(len, Ordering::Relaxed);
+        if start
+            .checked_add(len)
+            .map_or(true, |end| end == u64::max_value())
+        {
+            panic!("created the maximum number of ids (how did you even manage that?)");
+        }
+
+        start + 1..start + len + 1
+    }
 }
 
 // Private API
 impl Context {
-    crate fn function_id(&self) -> FuncId {
+    pub(crate) fn function_id(&self) -> FuncId {
         FuncId::new(fetch_id(&self.func_counter))
     }
 
-    crate fn block_id(&self) -> BasicBlockId {
+    pub(crate) fn block_id(&self) -> BasicBlockId {
         BasicBlockId::new(fetch_id(&self.block_counter))
     }
 
-    crate fn inst_id(&self) -> InstId {
+    pub(crate) fn inst_id(&self) -> InstId {
         InstId::new(fetch_id(&self.inst_counter))
     }
 
-    crate fn var_id(&self) -> VarId {
+    pub(crate) fn var_id(&self) -> VarId {
         VarId::new(fetch_id(&self.var_counter))
     }
 
-    crate fn node_id(&self) -> NodeId {
+    /// Makes sure that the context never allocates the given function id
+    pub(crate) fn reserve_function_id(&self, id: FuncId) {
+        self.func_counter
+            .fetch_max(id.as_u64() + 1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn reserve_block_id(&self, id: BasicBlockId) {
+        self.block_counter
+            .fetch_max(id.as_u64() + 1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn reserve_var_id(&self, id: VarId) {
+        self.var_counter
+            .fetch_max(id.as_u64() + 1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn declare_signature(&self, func: FuncId, signature: Signature) {
+        self.signatures.insert(func, signature);
+    }
+
+    pub(crate) fn node_id(&self) -> NodeId {
         NodeId::new(Uuid::new(
             self.ident_generation,
             fetch_id(&self.node_counter).get(),
@@ -1,14 +1,130 @@
+use crate::repr::{instruction::IntrinsicOp, FuncId, Type};
 use abomonation_derive::Abomonation;
+use std::fmt::{self, Display};
+use thiserror::Error;
 
 pub type BuildResult<T> = Result<T, BuilderError>;
 
-// TODO: Impl Display and Error, add docs
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+/// An error that occurred while building a function
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation, Error)]
 pub enum BuilderError {
+    #[error("a basic block has no terminator")]
     MissingTerminator,
+    #[error("a function has no basic blocks")]
     EmptyFunctionBody,
+    #[error("a function has no entry block")]
     MissingEntryBlock,
+    #[error("a function returns values of different types")]
     MismatchedReturnTypes,
-    MismatchedOperandTypes,
+    #[error("a return of {got} values was created for a function returning {expected} values")]
+    MismatchedReturnArity { expected: usize, got: usize },
+    #[error(transparent)]
+    TypeMismatch(TypeMismatch),
+    #[error("a value of type {ty} can't be negated")]
+    InvalidNegation { ty: Type },
+    #[error("the condition of a branch isn't a boolean")]
     IncorrectConditionType,
+    #[error("a switch case doesn't have the type of the switch's scrutinee")]
+    IncorrectSwitchCaseType,
+    #[error("{weights} branch weights were given for a terminator with {labels} labels")]
+    MismatchedBranchWeights { labels: usize, weights: usize },
+    #[error("index {index} is out of bounds for a value of type {ty}")]
+    InvalidAggregateIndex { ty: Type, index: u64 },
+    #[error("a value of type {got} was inserted into a field of type {expected}")]
+    IncorrectFieldType { expected: Type, got: Type },
+    #[error("a parameter of type {expected} was given a default of type {got}")]
+    IncorrectDefaultType { expected: Type, got: Type },
+    #[error("a call to {callee:?} passed {got} arguments to a function taking {expected}")]
+    CallArityMismatch {
+        callee: FuncId,
+        expected: usize,
+        got: usize,
+    },
+    #[error("argument {arg} of a call to {callee:?} has type {got} instead of {expected}")]
+    CallArgumentTypeMismatch {
+        callee: FuncId,
+        arg: usize,
+        expected: Type,
+        got: Type,
+    },
+    #[error("the signature of {callee:?} isn't known yet, it has to be built or declared first")]
+    UnknownSignature { callee: FuncId },
+    #[error("a call to {callee:?} didn't pass argument {arg}, which has no default")]
+    MissingCallArgument { callee: FuncId, arg: usize },
+    #[error("a call to {callee:?} passed the argument `{name}` which it has no parameter for")]
+    UnknownNamedArgument { callee: FuncId, name: String },
+    #[error("a call to {callee:?} passed the argument `{name}` more than once")]
+    DuplicateNamedArgument { callee: FuncId, name: String },
+    #[error("the intrinsic {intrinsic} can't be applied to arguments of the types {args:?}")]
+    InvalidIntrinsicArguments {
+        intrinsic: IntrinsicOp,
+        args: Vec<Type>,
+    },
+}
+
+/// The operations that have their operand types checked while building
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum BinaryOpKind {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Cmp,
+    /// The values a select chooses between
+    Select,
 }
+
+impl BinaryOpKind {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Rem => "rem",
+            Self::Cmp => "cmp",
+            Self::Select => "select",
+        }
+    }
+
+    /// Returns `true` if the operation requires integer operands
+    pub const fn is_arithmetic(&self) -> bool {
+        !matches!(self, Self::Cmp | Self::Select)
+    }
+}
+
+impl Display for BinaryOpKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An operation was given operands with incompatible types
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct TypeMismatch {
+    pub operation: BinaryOpKind,
+    pub lhs: Type,
+    pub rhs: Type,
+    pub function: FuncId,
+    /// The resolved name of the function the operation was built within
+    pub function_name: Option<String>,
+}
+
+impl Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mismatched operand types for {}: {} and {} in function ",
+            self.operation, self.lhs, self.rhs,
+        )?;
+
+        if let Some(name) = self.function_name.as_ref() {
+            write!(f, "`{}`", name)
+        } else {
+            write!(f, "{:?}", self.function)
+        }
+    }
+}
+
+impl std::error::Error for TypeMismatch {}
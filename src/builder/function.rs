@@ -1,18 +1,21 @@
 use crate::{
     builder::{
         block::{DeferredBasicBlock, IncompleteBasicBlock},
-        BasicBlockBuilder, BuildResult, BuilderError, Context,
+        BasicBlockBuilder, BuildResult, BuilderError, Context, Signature, SignatureParam,
     },
     dataflow::operators::Uuid,
     repr::{
-        basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, FuncId, Ident, InstId,
-        Instruction, Type, TypedVar,
+        basic_block::BasicBlockDesc,
+        function::{CallingConvention, FunctionAttributes, FunctionDesc, ParamAttributes},
+        BasicBlockId, Constant as ConstValue, FuncId, Ident, InstId, Instruction, ModuleId, Span,
+        Type, TypedVar, Value,
     },
     vsdg::{
         node::{
-            Add as NodeAdd, Cmp, CmpKind, Constant, End, FuncId as VFuncId, Load, LoopHead,
-            LoopTail, Mul as NodeMul, Node, NodeId, Operation, Parameter, Pointer, Return, Start,
-            Store, Sub as NodeSub, Type as NodeType, Value,
+            Add as NodeAdd, And as NodeAnd, Cmp, CmpKind, Constant, Div as NodeDiv, End,
+            FuncId as VFuncId, Load, LoopHead, LoopTail, Mul as NodeMul, Node, NodeId,
+            Not as NodeNot, Operation, Or as NodeOr, Parameter, Pointer, Return, Start, Store,
+            Sub as NodeSub, Type as NodeType, Value as NodeValue, Xor as NodeXor,
         },
         Edge,
     },
@@ -25,13 +28,17 @@ pub struct FunctionBuilder<'a> {
     pub(super) blocks: &'a mut Vec<BasicBlockDesc>,
     pub(super) functions: &'a mut Vec<FunctionDesc>,
     pub(super) instructions: &'a mut Vec<(InstId, Instruction)>,
+    pub(super) instruction_spans: &'a mut Vec<(InstId, Span)>,
+    pub(super) terminator_spans: &'a mut Vec<(BasicBlockId, Span)>,
     pub(super) nodes: &'a mut Vec<(NodeId, Node)>,
     pub(super) function_nodes: &'a mut Vec<(NodeId, VFuncId)>,
     pub(super) effect_edges: &'a mut Vec<Edge>,
     pub(super) control_edges: &'a mut Vec<Edge>,
     pub(super) value_edges: &'a mut Vec<Edge>,
+    pub(super) node_names: &'a mut Vec<(NodeId, Ident)>,
     pub(super) context: &'a Context,
     pub(super) finished: bool,
+    pub(super) permissive: bool,
     last_control: NodeId,
     last_effect: Option<NodeId>,
     end_node: NodeId,
@@ -43,6 +50,26 @@ impl<'a> FunctionBuilder<'a> {
         self.meta.entry.is_some()
     }
 
+    pub const fn attributes(&self) -> FunctionAttributes {
+        self.meta.attributes
+    }
+
+    /// Adds the given attributes to the function
+    pub fn with_attrs(&mut self, attributes: FunctionAttributes) -> &mut Self {
+        self.meta.attributes |= attributes;
+        self
+    }
+
+    pub const fn calling_convention(&self) -> CallingConvention {
+        self.meta.calling_convention
+    }
+
+    /// Sets the calling convention the function is called with
+    pub fn with_calling_convention(&mut self, calling_convention: CallingConvention) -> &mut Self {
+        self.meta.calling_convention = calling_convention;
+        self
+    }
+
     pub fn basic_block<F>(&mut self, build: F) -> BuildResult<BasicBlockId>
     where
         F: FnOnce(&mut BasicBlockBuilder<'_, '_>) -> BuildResult<()>,
@@ -60,6 +87,28 @@ impl<'a> FunctionBuilder<'a> {
         self.build_basic_block(None, Some(name), build)
     }
 
+    /// Builds a basic block and makes it the function's entry point,
+    /// replacing any previously set entry
+    pub fn entry_block<F>(&mut self, build: F) -> BuildResult<BasicBlockId>
+    where
+        F: FnOnce(&mut BasicBlockBuilder<'_, '_>) -> BuildResult<()>,
+    {
+        let entry = self.build_basic_block(None, None, build)?;
+
+        if let Some(old_entry) = self.set_entry(entry) {
+            if old_entry != entry {
+                tracing::trace!(
+                    "replaced the entry point {:?} of {:?} with {:?}",
+                    old_entry,
+                    self.func_id(),
+                    entry,
+                );
+            }
+        }
+
+        Ok(entry)
+    }
+
     pub fn allocate_basic_block(&mut self) -> DeferredBasicBlock {
         DeferredBasicBlock::new(self.context.block_id(), None)
     }
@@ -89,6 +138,62 @@ impl<'a> FunctionBuilder<'a> {
         self.build_basic_block(Some(block.id), block.name, build)
     }
 
+    /// Builds a `while` loop made up of a header block that evaluates the loop's
+    /// condition and a body block that jumps back to the header if it isn't
+    /// otherwise terminated
+    ///
+    /// Control flow must be directed to the returned [`WhileLoop::header`] by the caller,
+    /// and the [`WhileLoop::exit`] block (which is jumped to once the condition is false)
+    /// must be built via [`FunctionBuilder::resume_building()`]
+    // TODO: Return the loop's carried values once phi nodes exist
+    pub fn while_loop<C, B, V>(&mut self, cond: C, body: B) -> BuildResult<WhileLoop>
+    where
+        C: FnOnce(&mut BasicBlockBuilder<'_, '_>) -> BuildResult<V>,
+        B: FnOnce(&mut BasicBlockBuilder<'_, '_>) -> BuildResult<()>,
+        V: Into<Value>,
+    {
+        let (header, mut body_block, mut exit) = (
+            self.allocate_named_basic_block("loop.header"),
+            self.allocate_named_basic_block("loop.body"),
+            self.allocate_named_basic_block("loop.exit"),
+        );
+        let (header_id, body_id, exit_id) = (*header, *body_block, *exit);
+
+        let header_built = self.resume_building(header, |block| {
+            let cond = cond(block)?;
+            block.branch(cond, body_id, exit_id)?;
+
+            Ok(())
+        });
+        if let Err(err) = header_built {
+            // Prevent drop panics from the unused blocks
+            body_block.finished = true;
+            exit.finished = true;
+
+            return Err(err);
+        }
+
+        let body_built = self.resume_building(body_block, |block| {
+            body(block)?;
+
+            if !block.is_terminated() {
+                block.jump(header_id);
+            }
+
+            Ok(())
+        });
+        if let Err(err) = body_built {
+            exit.finished = true;
+            return Err(err);
+        }
+
+        Ok(WhileLoop {
+            header: header_id,
+            body: body_id,
+            exit,
+        })
+    }
+
     pub const fn name(&self) -> Option<Ident> {
         self.meta.name
     }
@@ -114,10 +219,57 @@ impl<'a> FunctionBuilder<'a> {
     where
         T: Into<Type>,
     {
-        let (id, ty) = (self.context.var_id(), ty.into());
-        self.meta.params.push(TypedVar::new(id, ty.clone()));
+        self.param_with_attrs(ty, ParamAttributes::DEFAULT)
+    }
 
-        TypedVar::new(id, ty)
+    /// Adds a parameter that's passed according to `attributes`
+    pub fn param_with_attrs<T>(&mut self, ty: T, attributes: ParamAttributes) -> TypedVar
+    where
+        T: Into<Type>,
+    {
+        self.push_param(ty.into(), attributes, None, None)
+    }
+
+    /// Adds a parameter that calls built with [`BasicBlockBuilder::call_named()`]
+    /// can pass by name
+    pub fn named_param<T, N>(&mut self, ty: T, name: N) -> TypedVar
+    where
+        T: Into<Type>,
+        N: AsRef<str>,
+    {
+        let name = Ident::new(self.context.interner.get_or_intern(name));
+        self.push_param(ty.into(), ParamAttributes::DEFAULT, Some(name), None)
+    }
+
+    /// Adds a named parameter that calls can leave out, passing `default` in its place
+    pub fn param_with_default<T, N, C>(
+        &mut self,
+        ty: T,
+        name: N,
+        default: C,
+    ) -> BuildResult<TypedVar>
+    where
+        T: Into<Type>,
+        N: AsRef<str>,
+        C: Into<ConstValue>,
+    {
+        let (ty, default) = (ty.into(), default.into());
+        if !ty.is_infer() && default.ty() != ty {
+            tracing::error!(
+                "created a parameter of type {} with a default of type {} in {:?}",
+                ty,
+                default.ty(),
+                self.func_id(),
+            );
+
+            return Err(BuilderError::IncorrectDefaultType {
+                expected: ty,
+                got: default.ty(),
+            });
+        }
+
+        let name = Ident::new(self.context.interner.get_or_intern(name));
+        Ok(self.push_param(ty, ParamAttributes::DEFAULT, Some(name), Some(default)))
     }
 
     pub fn vsdg_param<T>(&mut self, ty: T) -> NodeId
@@ -142,14 +294,10 @@ impl<'a> FunctionBuilder<'a> {
     where
         I: IntoIterator<Item = Type>,
     {
-        let mut ids = Vec::with_capacity(10);
-        for ty in types {
-            let id = self.context.var_id();
-            self.meta.params.push(TypedVar::new(id, ty.clone()));
-            ids.push(TypedVar::new(id, ty));
-        }
-
-        ids
+        types
+            .into_iter()
+            .map(|ty| self.push_param(ty, ParamAttributes::DEFAULT, None, None))
+            .collect()
     }
 
     pub fn vsdg_params<I>(&mut self, types: I) -> Vec<NodeId>
@@ -206,6 +354,55 @@ impl<'a> FunctionBuilder<'a> {
         Ok(node_id)
     }
 
+    pub fn vsdg_div(&mut self, lhs: NodeId, rhs: NodeId) -> BuildResult<NodeId> {
+        let node_id = self.context.node_id();
+        self.nodes.push((node_id, NodeDiv { lhs, rhs }.into()));
+
+        self.value_edges.push((node_id, lhs));
+        self.value_edges.push((node_id, rhs));
+
+        Ok(node_id)
+    }
+
+    pub fn vsdg_and(&mut self, lhs: NodeId, rhs: NodeId) -> BuildResult<NodeId> {
+        let node_id = self.context.node_id();
+        self.nodes.push((node_id, NodeAnd { lhs, rhs }.into()));
+
+        self.value_edges.push((node_id, lhs));
+        self.value_edges.push((node_id, rhs));
+
+        Ok(node_id)
+    }
+
+    pub fn vsdg_or(&mut self, lhs: NodeId, rhs: NodeId) -> BuildResult<NodeId> {
+        let node_id = self.context.node_id();
+        self.nodes.push((node_id, NodeOr { lhs, rhs }.into()));
+
+        self.value_edges.push((node_id, lhs));
+        self.value_edges.push((node_id, rhs));
+
+        Ok(node_id)
+    }
+
+    pub fn vsdg_xor(&mut self, lhs: NodeId, rhs: NodeId) -> BuildResult<NodeId> {
+        let node_id = self.context.node_id();
+        self.nodes.push((node_id, NodeXor { lhs, rhs }.into()));
+
+        self.value_edges.push((node_id, lhs));
+        self.value_edges.push((node_id, rhs));
+
+        Ok(node_id)
+    }
+
+    pub fn vsdg_not(&mut self, value: NodeId) -> BuildResult<NodeId> {
+        let node_id = self.context.node_id();
+        self.nodes.push((node_id, NodeNot { value }.into()));
+
+        self.value_edges.push((node_id, value));
+
+        Ok(node_id)
+    }
+
     pub fn vsdg_return(&mut self, value: NodeId) -> BuildResult<()> {
         let node_id = self.context.node_id();
         self.nodes.push((node_id, Return {}.into()));
@@ -217,6 +414,16 @@ impl<'a> FunctionBuilder<'a> {
         Ok(())
     }
 
+    /// Names a node, the name is shown alongside the node within
+    /// [dot output](crate::vsdg::dot::to_dot_named)
+    pub fn vsdg_name<N>(&mut self, node: NodeId, name: N)
+    where
+        N: AsRef<str>,
+    {
+        let name = Ident::new(self.context.interner.get_or_intern(name));
+        self.node_names.push((node, name));
+    }
+
     pub fn vsdg_const(&mut self, value: Constant) -> NodeId {
         let node_id = self.context.node_id();
         self.nodes.push((node_id, value.into()));
@@ -227,7 +434,7 @@ impl<'a> FunctionBuilder<'a> {
     pub fn vsdg_ptr_to(&mut self, value: NodeId) -> NodeId {
         let node_id = self.context.node_id();
         self.nodes
-            .push((node_id, Value::Pointer(Pointer {}).into()));
+            .push((node_id, NodeValue::Pointer(Pointer {}).into()));
 
         self.value_edges.push((node_id, value));
 
@@ -317,12 +524,16 @@ impl<'a> FunctionBuilder<'a> {
         blocks: &'a mut Vec<BasicBlockDesc>,
         functions: &'a mut Vec<FunctionDesc>,
         instructions: &'a mut Vec<(InstId, Instruction)>,
+        instruction_spans: &'a mut Vec<(InstId, Span)>,
+        terminator_spans: &'a mut Vec<(BasicBlockId, Span)>,
         nodes: &'a mut Vec<(NodeId, Node)>,
         function_nodes: &'a mut Vec<(NodeId, VFuncId)>,
         value_edges: &'a mut Vec<Edge>,
         control_edges: &'a mut Vec<Edge>,
         effect_edges: &'a mut Vec<Edge>,
+        node_names: &'a mut Vec<(NodeId, Ident)>,
         context: &'a Context,
+        permissive: bool,
     ) -> Self {
         let last_control = context.node_id();
         nodes.push((last_control, Start.into()));
@@ -339,13 +550,17 @@ impl<'a> FunctionBuilder<'a> {
             blocks,
             functions,
             instructions,
+            instruction_spans,
+            terminator_spans,
             nodes,
             function_nodes,
             value_edges,
             control_edges,
             effect_edges,
+            node_names,
             context,
             finished: false,
+            permissive,
             last_control,
             last_effect: None,
             end_node,
@@ -405,6 +620,28 @@ impl<'a> FunctionBuilder<'a> {
         })
     }
 
+    fn push_param(
+        &mut self,
+        ty: Type,
+        attributes: ParamAttributes,
+        name: Option<Ident>,
+        default: Option<ConstValue>,
+    ) -> TypedVar {
+        let id = self.context.var_id();
+        self.meta.params.push(TypedVar::new(id, ty.clone()));
+        self.meta.param_attributes.push(attributes);
+        self.meta
+            .signature_params
+            .push(SignatureParam::new(ty.clone(), name, default));
+
+        TypedVar::new(id, ty)
+    }
+
+    /// The signature of the function as it's been built so far
+    pub(super) fn signature(&self) -> Signature {
+        Signature::new(self.meta.signature_params.clone(), self.meta.ret_ty.clone())
+    }
+
     #[track_caller]
     pub(super) fn finish(mut self) -> BuildResult<FuncId> {
         self.finish_inner()
@@ -421,8 +658,10 @@ impl<'a> FunctionBuilder<'a> {
         }
 
         self.finished = true;
+        let signature = self.signature();
         let function = self.meta.take().try_into()?;
         self.functions.push(function);
+        self.context.declare_signature(id, signature);
 
         Ok(id)
     }
@@ -439,6 +678,15 @@ impl Drop for FunctionBuilder<'_> {
     }
 }
 
+/// The blocks created by [`FunctionBuilder::while_loop()`]
+#[derive(Debug)]
+#[must_use = "The exit block of a loop must be built"]
+pub struct WhileLoop {
+    pub header: BasicBlockId,
+    pub body: BasicBlockId,
+    pub exit: DeferredBasicBlock,
+}
+
 #[derive(Debug)]
 #[must_use = "Dropping a deferred function without completing it will panic"]
 pub struct DeferredFunction {
@@ -483,6 +731,13 @@ pub(super) struct IncompleteFunction {
     pub(super) ret_ty: Type,
     entry: Option<BasicBlockId>,
     pub(super) basic_blocks: Vec<BasicBlockId>,
+    pub(super) attributes: FunctionAttributes,
+    param_attributes: Vec<ParamAttributes>,
+    /// The names and defaults of the parameters, kept next to their types for the
+    /// function's [`Signature`]
+    signature_params: Vec<SignatureParam>,
+    calling_convention: CallingConvention,
+    pub(super) module: ModuleId,
 }
 
 impl IncompleteFunction {
@@ -501,6 +756,11 @@ impl IncompleteFunction {
             ret_ty,
             entry,
             basic_blocks,
+            attributes: FunctionAttributes::NONE,
+            param_attributes: Vec::new(),
+            signature_params: Vec::new(),
+            calling_convention: CallingConvention::Sruth,
+            module: ModuleId::DEFAULT,
         }
     }
 
@@ -512,6 +772,11 @@ impl IncompleteFunction {
             ret_ty: mem::replace(&mut self.ret_ty, Type::Unit),
             entry: self.entry,
             basic_blocks: mem::take(&mut self.basic_blocks),
+            attributes: self.attributes,
+            param_attributes: mem::take(&mut self.param_attributes),
+            signature_params: mem::take(&mut self.signature_params),
+            calling_convention: self.calling_convention,
+            module: self.module,
         }
     }
 }
@@ -520,7 +785,14 @@ impl TryInto<FunctionDesc> for IncompleteFunction {
     type Error = BuilderError;
 
     fn try_into(self) -> Result<FunctionDesc, Self::Error> {
-        let entry = self.entry.ok_or(BuilderError::MissingEntryBlock)?;
+        let entry = self.entry.ok_or_else(|| {
+            tracing::error!(
+                "{:?} has no entry block, create a basic block or use `.entry_block()` to add one",
+                self.id,
+            );
+
+            BuilderError::MissingEntryBlock
+        })?;
 
         if self.basic_blocks.is_empty() {
             return Err(BuilderError::EmptyFunctionBody);
@@ -533,6 +805,10 @@ impl TryInto<FunctionDesc> for IncompleteFunction {
             ret_ty: self.ret_ty,
             entry,
             basic_blocks: self.basic_blocks,
+            attributes: self.attributes,
+            param_attributes: self.param_attributes,
+            calling_convention: self.calling_convention,
+            module: self.module,
         })
     }
 }
@@ -0,0 +1,219 @@
+use crate::{
+    builder::Context,
+    repr::{BasicBlockId, FuncId, InstId, VarId},
+};
+use std::{
+    cell::Cell,
+    fmt::{self, Debug},
+    num::NonZeroU64,
+    ops::Range,
+    rc::Rc,
+    sync::Arc,
+};
+
+/// The number of ids a [`WorkerIds`] claims from its context at once by default
+pub const DEFAULT_ID_CHUNK: u64 = 1024;
+
+/// The kinds of ids that are allocated from a [`Context`]'s counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IdKind {
+    Function,
+    Block,
+    Inst,
+    Var,
+}
+
+/// Something that mints fresh ids which are never handed out twice
+pub trait IdAllocator {
+    fn function_id(&self) -> FuncId;
+
+    fn block_id(&self) -> BasicBlockId;
+
+    fn inst_id(&self) -> InstId;
+
+    fn var_id(&self) -> VarId;
+}
+
+impl IdAllocator for Context {
+    fn function_id(&self) -> FuncId {
+        Context::function_id(self)
+    }
+
+    fn block_id(&self) -> BasicBlockId {
+        Context::block_id(self)
+    }
+
+    fn inst_id(&self) -> InstId {
+        Context::inst_id(self)
+    }
+
+    fn var_id(&self) -> VarId {
+        Context::var_id(self)
+    }
+}
+
+impl<A> IdAllocator for Rc<A>
+where
+    A: IdAllocator + ?Sized,
+{
+    fn function_id(&self) -> FuncId {
+        (**self).function_id()
+    }
+
+    fn block_id(&self) -> BasicBlockId {
+        (**self).block_id()
+    }
+
+    fn inst_id(&self) -> InstId {
+        (**self).inst_id()
+    }
+
+    fn var_id(&self) -> VarId {
+        (**self).var_id()
+    }
+}
+
+impl<A> IdAllocator for Arc<A>
+where
+    A: IdAllocator + ?Sized,
+{
+    fn function_id(&self) -> FuncId {
+        (**self).function_id()
+    }
+
+    fn block_id(&self) -> BasicBlockId {
+        (**self).block_id()
+    }
+
+    fn inst_id(&self) -> InstId {
+        (**self).inst_id()
+    }
+
+    fn var_id(&self) -> VarId {
+        (**self).var_id()
+    }
+}
+
+/// An id allocator for a single dataflow worker
+///
+/// Each worker claims disjoint ranges of ids from the [`Context`] all workers
+/// share and mints ids out of its current range without touching the context's
+/// counters, so passes running inside of dataflow operators can create new
+/// blocks, instructions and variables without colliding with the ids minted by
+/// other workers or by the context itself. Allocators aren't `Clone` since two
+/// copies would hand out the same ids, operators within a worker should share
+/// one through an `Rc` instead
+pub struct WorkerIds {
+    context: Arc<Context>,
+    worker: usize,
+    chunk_size: u64,
+    functions: Cell<Range<u64>>,
+    blocks: Cell<Range<u64>>,
+    insts: Cell<Range<u64>>,
+    vars: Cell<Range<u64>>,
+}
+
+impl WorkerIds {
+    /// Creates the id allocator of the given worker, claiming `chunk_size` ids
+    /// from the context whenever a range runs out
+    pub fn new(context: Arc<Context>, worker: usize, chunk_size: u64) -> Self {
+        assert_ne!(
+            chunk_size, 0,
+            "workers must claim at least one id at a time"
+        );
+
+        Self {
+            context,
+            worker,
+            chunk_size,
+            functions: Cell::new(0..0),
+            blocks: Cell::new(0..0),
+            insts: Cell::new(0..0),
+            vars: Cell::new(0..0),
+        }
+    }
+
+    /// The index of the worker the allocator belongs to
+    pub const fn worker(&self) -> usize {
+        self.worker
+    }
+
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    fn fetch(&self, kind: IdKind) -> NonZeroU64 {
+        let range = match kind {
+            IdKind::Function => &self.functions,
+            IdKind::Block => &self.blocks,
+            IdKind::Inst => &self.insts,
+            IdKind::Var => &self.vars,
+        };
+
+        let mut ids = range.replace(0..0);
+        let id = match ids.next() {
+            Some(id) => id,
+            None => {
+                ids = self.context.claim_ids(kind, self.chunk_size);
+                tracing::trace!(
+                    worker = self.worker,
+                    kind = ?kind,
+                    start = ids.start,
+                    end = ids.end,
+                    "claimed a range of ids",
+                );
+
+                ids.next().expect("claimed an empty range of ids")
+            }
+        };
+        range.set(ids);
+
+        NonZeroU64::new(id).expect("claimed an invalid id")
+    }
+}
+
+impl IdAllocator for WorkerIds {
+    fn function_id(&self) -> FuncId {
+        FuncId::new(self.fetch(IdKind::Function))
+    }
+
+    fn block_id(&self) -> BasicBlockId {
+        BasicBlockId::new(self.fetch(IdKind::Block))
+    }
+
+    fn inst_id(&self) -> InstId {
+        InstId::new(self.fetch(IdKind::Inst))
+    }
+
+    fn var_id(&self) -> VarId {
+        VarId::new(self.fetch(IdKind::Var))
+    }
+}
+
+impl Debug for WorkerIds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (functions, blocks, insts, vars) = (
+            self.functions.replace(0..0),
+            self.blocks.replace(0..0),
+            self.insts.replace(0..0),
+            self.vars.replace(0..0),
+        );
+
+        let result = f
+            .debug_struct("WorkerIds")
+            .field("worker", &self.worker)
+            .field("chunk_size", &self.chunk_size)
+            .field("functions", &functions)
+            .field("blocks", &blocks)
+            .field("insts", &insts)
+            .field("vars", &vars)
+            .finish();
+
+        self.functions.set(functions);
+        self.blocks.set(blocks);
+        self.insts.set(insts);
+        self.vars.set(vars);
+
+        result
+    }
+}
@@ -2,28 +2,36 @@ mod block;
 mod context;
 mod error;
 mod function;
+mod ids;
+mod signature;
+mod warnings;
 
-pub use block::BasicBlockBuilder;
+pub use block::{BasicBlockBuilder, IfElse};
 pub use context::Context;
-pub use error::{BuildResult, BuilderError};
-pub use function::FunctionBuilder;
+pub use error::{BinaryOpKind, BuildResult, BuilderError, TypeMismatch};
+pub use function::{FunctionBuilder, WhileLoop};
+pub use ids::{IdAllocator, IdKind, WorkerIds, DEFAULT_ID_CHUNK};
+pub use signature::{Signature, SignatureParam};
+pub use warnings::{BuilderWarning, WarningKind};
 
 use crate::{
     builder::function::{DeferredFunction, IncompleteFunction},
-    dataflow::InputManager,
+    dataflow::{operators::Uuid, InputManager},
     repr::{
         basic_block::BasicBlockDesc,
-        function::{FunctionDesc, Metadata},
+        function::{FunctionAttributes, FunctionDesc},
         instruction::Call,
-        BasicBlock, FuncId, Function, Ident, InstId, Instruction, InstructionExt, Type,
+        BasicBlock, BasicBlockId, FuncId, Function, Ident, InstId, Instruction, InstructionExt,
+        ModuleId, ModuleMeta, Span, Type, TypedVar,
     },
     vsdg::{
+        dot::GraphNames,
         node::{FuncId as VFuncId, Node, NodeId},
         Edge, ProgramInputs,
     },
 };
-use abomonation_derive::Abomonation;
 use differential_dataflow::{difference::Semigroup, lattice::Lattice};
+use fxhash::FxHashMap;
 use std::{mem, sync::Arc, thread};
 use timely::progress::Timestamp;
 
@@ -31,14 +39,22 @@ pub struct Builder {
     blocks: Vec<BasicBlockDesc>,
     functions: Vec<FunctionDesc>,
     instructions: Vec<(InstId, Instruction)>,
+    instruction_spans: Vec<(InstId, Span)>,
+    terminator_spans: Vec<(BasicBlockId, Span)>,
     context: Arc<Context>,
     finished: bool,
+    permissive: bool,
+    warn_unused: bool,
+    warnings: Vec<BuilderWarning>,
+    module_meta: Option<ModuleMeta>,
+    module: ModuleId,
 
     nodes: Vec<(NodeId, Node)>,
     function_nodes: Vec<(NodeId, VFuncId)>,
     value_edges: Vec<Edge>,
     control_edges: Vec<Edge>,
     effect_edges: Vec<Edge>,
+    node_names: Vec<(NodeId, Ident)>,
 }
 
 // Public API
@@ -66,6 +82,50 @@ impl Builder {
         self.build_function(None, Some(name), return_ty.into(), build)
     }
 
+    /// Declares a function that's defined outside of the module, giving it a
+    /// signature but no body
+    ///
+    /// External functions are marked as [external](FunctionAttributes::EXTERNAL)
+    /// and their entry is a block id that no block is ever built for
+    pub fn declare_external<N, P, T>(&mut self, name: N, params: P, return_ty: T) -> FuncId
+    where
+        N: AsRef<str>,
+        P: IntoIterator<Item = Type>,
+        T: Into<Type>,
+    {
+        let name = Ident::new(self.context.interner.get_or_intern(name));
+        let params: Vec<_> = params
+            .into_iter()
+            .map(|ty| TypedVar::new(self.context.var_id(), ty))
+            .collect();
+        let return_ty = return_ty.into();
+
+        let id = self.context.function_id();
+        let signature = Signature::new(
+            params
+                .iter()
+                .map(|param| SignatureParam::new(param.ty.clone(), None, None))
+                .collect(),
+            return_ty.clone(),
+        );
+        self.context.declare_signature(id, signature);
+
+        let desc = FunctionDesc::new(
+            Some(name),
+            id,
+            params,
+            return_ty,
+            self.context.block_id(),
+            Vec::new(),
+        )
+        .with_attributes(FunctionAttributes::EXTERNAL)
+        .with_module(self.module);
+        self.functions.push(desc);
+
+        tracing::trace!("declared the external function {:?}", id);
+        id
+    }
+
     pub fn allocate_function<T>(&mut self, return_ty: T) -> DeferredFunction
     where
         T: Into<Type>,
@@ -104,7 +164,74 @@ impl Builder {
         )
     }
 
+    pub const fn is_permissive(&self) -> bool {
+        self.permissive
+    }
+
+    /// Sets whether the builder is permissive, returning the previous setting
+    ///
+    /// Permissive builders implicitly terminate any basic block that falls off
+    /// its end with a return of the function's default value (or `unit`) instead
+    /// of failing with [`BuilderError::MissingTerminator`]
+    pub fn set_permissive(&mut self, permissive: bool) -> bool {
+        mem::replace(&mut self.permissive, permissive)
+    }
+
+    pub const fn is_warning_unused(&self) -> bool {
+        self.warn_unused
+    }
+
+    /// Sets whether the builder checks each function it finishes for unused values
+    /// and parameters, returning the previous setting
+    ///
+    /// Every problem found is emitted as a warning and collected into
+    /// [`Builder::warnings()`], which is much cheaper feedback than waiting on the
+    /// full pipeline to remove the dead code
+    pub fn set_warn_unused(&mut self, warn_unused: bool) -> bool {
+        mem::replace(&mut self.warn_unused, warn_unused)
+    }
+
+    /// The warnings found within every function built so far
+    pub fn warnings(&self) -> &[BuilderWarning] {
+        &self.warnings
+    }
+
+    pub const fn module_meta(&self) -> Option<&ModuleMeta> {
+        self.module_meta.as_ref()
+    }
+
+    /// Sets the metadata of the module being built, returning the previous metadata
+    ///
+    /// The metadata is given to the dataflow along with the rest of the module
+    /// when the builder is finished
+    pub fn set_module_meta(&mut self, meta: ModuleMeta) -> Option<ModuleMeta> {
+        self.module_meta.replace(meta)
+    }
+
+    pub const fn module(&self) -> ModuleId {
+        self.module
+    }
+
+    /// Sets the module that every function built or declared from now on belongs
+    /// to, returning the previous module
+    pub fn set_module(&mut self, module: ModuleId) -> ModuleId {
+        mem::replace(&mut self.module, module)
+    }
+
     pub fn materialize(&self) -> impl Iterator<Item = Function> + '_ {
+        // Index everything up front so that lookups don't scan the whole module
+        let blocks: FxHashMap<BasicBlockId, &BasicBlockDesc> =
+            self.blocks.iter().map(|block| (block.id, block)).collect();
+        let instructions: FxHashMap<InstId, &Instruction> = self
+            .instructions
+            .iter()
+            .map(|(id, inst)| (*id, inst))
+            .collect();
+        let instruction_spans: FxHashMap<InstId, Span> =
+            self.instruction_spans.iter().copied().collect();
+        let terminator_spans: FxHashMap<BasicBlockId, Span> =
+            self.terminator_spans.iter().copied().collect();
+
         self.functions.iter().map(move |func| Function {
             name: func.name,
             id: func.id,
@@ -114,8 +241,8 @@ impl Builder {
             basic_blocks: func
                 .basic_blocks
                 .iter()
-                .map(|&id| {
-                    let block = self.blocks.iter().find(|block| block.id == id).unwrap();
+                .map(|id| {
+                    let block = blocks[id];
 
                     BasicBlock {
                         name: block.name,
@@ -123,20 +250,19 @@ impl Builder {
                         instructions: block
                             .instructions
                             .iter()
-                            .map(|&id| {
-                                self.instructions
-                                    .iter()
-                                    .find(|&&(inst, _)| inst == id)
-                                    .unwrap()
-                                    .1
-                                    .clone()
-                            })
+                            .map(|id| instructions[id].clone())
                             .collect(),
                         terminator: block.terminator.clone(),
+                        instruction_spans: block
+                            .instructions
+                            .iter()
+                            .map(|id| instruction_spans.get(id).copied())
+                            .collect(),
+                        terminator_span: terminator_spans.get(&block.id).copied(),
                     }
                 })
                 .collect(),
-            metadata: Metadata::default(),
+            metadata: func.metadata(),
         })
     }
 
@@ -198,6 +324,10 @@ impl Builder {
             }
         }
 
+        if let Some(meta) = self.module_meta.take() {
+            input.modules.update_at(meta, time.clone(), R::from(1));
+        }
+
         for function in self.functions.drain(..) {
             input
                 .functions
@@ -216,11 +346,46 @@ impl Builder {
                 .update_at(instruction, time.clone(), R::from(1));
         }
 
-        // TODO: Effect edges
+        for span in self.instruction_spans.drain(..) {
+            input
+                .instruction_spans
+                .update_at(span, time.clone(), R::from(1));
+        }
+
+        for span in self.terminator_spans.drain(..) {
+            input
+                .terminator_spans
+                .update_at(span, time.clone(), R::from(1));
+        }
 
         Ok(())
     }
 
+    /// The names of the functions and [nodes](FunctionBuilder::vsdg_name) built so
+    /// far, for rendering them into [dot output](crate::vsdg::dot::render_graphs_named)
+    pub fn graph_names(&self) -> GraphNames {
+        let resolve = |name: Ident| self.context.interner.resolve(&name.0).to_owned();
+
+        let mut names = GraphNames::new();
+        for function in self.functions.iter() {
+            if let Some(name) = function.name {
+                let func = VFuncId::new(Uuid::new(
+                    self.context.ident_generation,
+                    function.id.0.get(),
+                ));
+                names.functions.insert(func, resolve(name));
+            }
+        }
+
+        names.nodes.extend(
+            self.node_names
+                .iter()
+                .map(|&(node, name)| (node, resolve(name))),
+        );
+
+        names
+    }
+
     pub fn vsdg_finish<T, R>(mut self, input: &mut ProgramInputs<T, R>, time: T) -> BuildResult<()>
     where
         T: Timestamp + Lattice + Clone,
@@ -269,14 +434,22 @@ impl Builder {
             blocks: Vec::with_capacity(1024),
             functions: Vec::with_capacity(512),
             instructions: Vec::with_capacity(2048),
+            instruction_spans: Vec::new(),
+            terminator_spans: Vec::new(),
             context,
             finished: false,
+            permissive: false,
+            warn_unused: false,
+            warnings: Vec::new(),
+            module_meta: None,
+            module: ModuleId::DEFAULT,
 
             nodes: Vec::with_capacity(2048),
             function_nodes: Vec::with_capacity(2048),
             value_edges: Vec::with_capacity(1024),
             control_edges: Vec::with_capacity(1024),
             effect_edges: Vec::with_capacity(1024),
+            node_names: Vec::new(),
         }
     }
 
@@ -304,18 +477,24 @@ impl Builder {
                 tracing::trace!("started building nameless function {:?}", id);
             }
 
-            let meta = IncompleteFunction::new(name, id, Vec::new(), return_ty, None, Vec::new());
+            let mut meta =
+                IncompleteFunction::new(name, id, Vec::new(), return_ty, None, Vec::new());
+            meta.module = self.module;
             let mut builder = FunctionBuilder::new(
                 meta,
                 &mut self.blocks,
                 &mut self.functions,
                 &mut self.instructions,
+                &mut self.instruction_spans,
+                &mut self.terminator_spans,
                 &mut self.nodes,
                 &mut self.function_nodes,
                 &mut self.value_edges,
                 &mut self.control_edges,
                 &mut self.effect_edges,
+                &mut self.node_names,
                 &*self.context,
+                self.permissive,
             );
 
             if let Err(err) = build(&mut builder) {
@@ -323,10 +502,34 @@ impl Builder {
                 builder.finished = true;
                 return Err(err);
             }
+            let id = builder.finish()?;
+
+            if self.warn_unused {
+                self.check_unused(id);
+            }
 
-            builder.finish()
+            Ok(id)
         })
     }
+
+    fn check_unused(&mut self, id: FuncId) {
+        let function = self
+            .functions
+            .iter()
+            .find(|function| function.id == id)
+            .expect("checked a function that wasn't built");
+        let function_name = function
+            .name
+            .map(|name| self.context.interner.resolve(&name.0).to_owned());
+
+        let warnings =
+            warnings::unused_values(function, function_name, &self.blocks, &self.instructions);
+        for warning in warnings.iter() {
+            tracing::warn!("{}", warning);
+        }
+
+        self.warnings.extend(warnings);
+    }
 }
 
 impl Drop for Builder {
@@ -336,9 +539,3 @@ impl Drop for Builder {
         }
     }
 }
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
-pub struct EffectEdge {
-    pub from: InstId,
-    pub to: InstId,
-}
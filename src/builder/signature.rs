@@ -0,0 +1,70 @@
+use crate::repr::{Constant, FuncId, Ident, Type};
+use fxhash::FxHashMap;
+use std::sync::RwLock;
+
+/// The signature of a function as seen by the builder, used to check calls to it
+/// while they're being built
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Signature {
+    pub params: Vec<SignatureParam>,
+    pub ret_ty: Type,
+}
+
+impl Signature {
+    pub fn new(params: Vec<SignatureParam>, ret_ty: Type) -> Self {
+        Self { params, ret_ty }
+    }
+
+    /// The index of the parameter with the given name
+    pub fn param_index(&self, name: Ident) -> Option<usize> {
+        self.params
+            .iter()
+            .position(|param| param.name == Some(name))
+    }
+}
+
+/// A single parameter of a [`Signature`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignatureParam {
+    pub ty: Type,
+    pub name: Option<Ident>,
+    /// The value passed for the parameter when a call leaves it out
+    pub default: Option<Constant>,
+}
+
+impl SignatureParam {
+    pub const fn new(ty: Type, name: Option<Ident>, default: Option<Constant>) -> Self {
+        Self { ty, name, default }
+    }
+}
+
+/// The signatures of every function that's been built or declared within a
+/// [`Context`](crate::builder::Context)
+///
+/// Functions that are only allocated or that are still being built don't have a
+/// signature yet, calls to them are checked during verification instead
+#[derive(Debug, Default)]
+pub(crate) struct SignatureRegistry {
+    signatures: RwLock<FxHashMap<FuncId, Signature>>,
+}
+
+impl SignatureRegistry {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn insert(&self, func: FuncId, signature: Signature) {
+        self.signatures
+            .write()
+            .expect("the signature registry was poisoned")
+            .insert(func, signature);
+    }
+
+    pub(crate) fn get(&self, func: FuncId) -> Option<Signature> {
+        self.signatures
+            .read()
+            .expect("the signature registry was poisoned")
+            .get(&func)
+            .cloned()
+    }
+}
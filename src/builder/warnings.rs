@@ -0,0 +1,109 @@
+use crate::repr::{
+    basic_block::BasicBlockDesc,
+    function::FunctionDesc,
+    utils::{InstructionExt, InstructionPurity},
+    FuncId, InstId, Instruction, VarId,
+};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+};
+
+/// A likely mistake found within a function after it was built, see
+/// [`Builder::set_warn_unused()`](crate::builder::Builder::set_warn_unused)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BuilderWarning {
+    pub kind: WarningKind,
+    pub function: FuncId,
+    /// The resolved name of the function the warning was found within
+    pub function_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WarningKind {
+    /// A side effect free instruction produces a value that's never used
+    UnusedValue { instruction: InstId, value: VarId },
+    /// A function parameter is never read
+    UnusedParam { param: VarId },
+}
+
+impl Display for BuilderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            WarningKind::UnusedValue { instruction, value } => write!(
+                f,
+                "the value {:?} produced by {:?} is never used",
+                value, instruction,
+            )?,
+            WarningKind::UnusedParam { param } => {
+                write!(f, "the parameter {:?} is never read", param)?
+            }
+        }
+
+        if let Some(name) = self.function_name.as_ref() {
+            write!(f, " in function `{}`", name)
+        } else {
+            write!(f, " in function {:?}", self.function)
+        }
+    }
+}
+
+/// Finds every unused value and parameter within the given function
+pub(super) fn unused_values(
+    function: &FunctionDesc,
+    function_name: Option<String>,
+    blocks: &[BasicBlockDesc],
+    instructions: &[(InstId, Instruction)],
+) -> Vec<BuilderWarning> {
+    let blocks: Vec<&BasicBlockDesc> = function
+        .basic_blocks
+        .iter()
+        .filter_map(|&id| blocks.iter().find(|block| block.id == id))
+        .collect();
+    let function_insts: Vec<(InstId, &Instruction)> = blocks
+        .iter()
+        .flat_map(|block| block.instructions.iter())
+        .filter_map(|&id| {
+            instructions
+                .iter()
+                .find(|&&(inst, _)| inst == id)
+                .map(|(id, inst)| (*id, inst))
+        })
+        .collect();
+
+    let mut used = BTreeSet::new();
+    for (_, inst) in function_insts.iter() {
+        used.extend(inst.used_vars().into_iter().map(|var| var.var));
+    }
+    for block in blocks.iter() {
+        used.extend(block.terminator.used_vars());
+    }
+
+    let mut warnings = Vec::new();
+    let mut warn = |kind| {
+        warnings.push(BuilderWarning {
+            kind,
+            function: function.id,
+            function_name: function_name.clone(),
+        })
+    };
+
+    for param in function.params.iter() {
+        if !used.contains(&param.var) {
+            warn(WarningKind::UnusedParam { param: param.var });
+        }
+    }
+
+    // Instructions that may have side effects are kept even when their results
+    // aren't used, so they're not worth warning about
+    for (id, inst) in function_insts {
+        if inst.purity() == InstructionPurity::Pure && !used.contains(&inst.dest()) {
+            warn(WarningKind::UnusedValue {
+                instruction: id,
+                value: inst.dest(),
+            });
+        }
+    }
+
+    warnings
+}
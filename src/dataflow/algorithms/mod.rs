@@ -1 +1,3 @@
+pub mod propagate;
 pub mod reachable;
+pub mod scc;
@@ -0,0 +1,78 @@
+use crate::dataflow::Difference;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{
+        arrange::{ArrangeByKey, Arranged},
+        iterate::SemigroupVariable,
+        reduce::ReduceCore,
+        JoinCore,
+    },
+    trace::{implementations::ord::OrdValSpine, TraceReader},
+    Collection, ExchangeData,
+};
+use std::hash::Hash;
+use timely::{dataflow::Scope, order::Product};
+
+/// Propagates labels forward along edges, giving every reachable node the least
+/// label that can reach it as a `(node, label)` pair
+///
+/// Nodes that no label can reach aren't included within the output
+pub fn least_label_propagation<S, N, L, R>(
+    edges: &Collection<S, (N, N), R>,
+    labels: &Collection<S, (N, L), R>,
+) -> Collection<S, (N, L), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    N: ExchangeData + Hash,
+    L: ExchangeData,
+    R: Difference,
+{
+    least_label_propagation_core("LeastLabelPropagation", &edges.arrange_by_key(), labels)
+}
+
+/// Propagates labels forward along edges, giving every reachable node the least
+/// label that can reach it as a `(node, label)` pair
+// This is `differential_dataflow::algorithms::graphs::propagate::propagate_core()` but only
+// requires the timestamp to be a lattice so that it's usable within nested scopes
+pub fn least_label_propagation_core<S, N, L, Trace, R>(
+    name: &str,
+    edges: &Arranged<S, Trace>,
+    labels: &Collection<S, (N, L), R>,
+) -> Collection<S, (N, L), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    N: ExchangeData + Hash,
+    L: ExchangeData,
+    R: Difference,
+    Trace: TraceReader<Key = N, Val = N, Time = S::Timestamp, R = R> + Clone + 'static,
+{
+    labels
+        .scope()
+        .scoped::<Product<S::Timestamp, usize>, _, _>(name, |scope| {
+            let (edges, labels) = (edges.enter(scope), labels.enter(scope));
+            let proposals = SemigroupVariable::new(scope, Product::new(Default::default(), 1));
+
+            // Each round collapses every node's labels down to a single least label with
+            // a difference of one, so differences can't grow (and eventually overflow) as
+            // they're multiplied along long paths or around cycles
+            let least = proposals
+                .concat(&labels)
+                .arrange_by_key()
+                .reduce_abelian::<_, OrdValSpine<_, _, _, _>>(name, |_node, input, output| {
+                    // Inputs are sorted, so the first label is the least
+                    output.push((input[0].0.clone(), R::from(1)));
+                });
+
+            let propagate: Collection<_, (N, L), R> = least
+                .join_core(&edges, |_node, label, dest| {
+                    Some((dest.clone(), label.clone()))
+                });
+            proposals.set(&propagate);
+
+            least
+                .as_collection(|node, label| (node.clone(), label.clone()))
+                .leave()
+        })
+}
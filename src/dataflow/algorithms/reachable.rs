@@ -1,5 +1,5 @@
+use crate::dataflow::{operators::Present, Difference};
 use differential_dataflow::{
-    difference::{Abelian, Multiply},
     lattice::Lattice,
     operators::{
         arrange::ArrangeBySelf,
@@ -24,7 +24,7 @@ where
     S: Scope,
     S::Timestamp: Lattice,
     N: ExchangeData + Hash,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     reachable_core("Reachable", &edges.arrange_by_key(), roots)
 }
@@ -42,7 +42,7 @@ where
     S: Scope,
     S::Timestamp: Lattice,
     N: ExchangeData + Hash,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
     Trace: TraceReader<Key = N, Val = N, Time = S::Timestamp, R = R> + Clone + 'static,
 {
     roots
@@ -65,3 +65,105 @@ where
             labels.as_collection(|k, &()| k.clone()).leave()
         })
 }
+
+/// Propagates the reachability of nodes forward from the roots like [`reachable()`],
+/// but for collections that are only [present](Present)
+///
+/// Present collections never retract anything, so a node only has to be labelled
+/// the first time it's reached instead of having its multiplicity collapsed each
+/// round, and none of the arrangements involved store any differences
+pub fn reachable_present<S, N>(
+    edges: &Collection<S, (N, N), Present>,
+    roots: &Collection<S, N, Present>,
+) -> Collection<S, N, Present>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    N: ExchangeData + Hash,
+{
+    roots
+        .scope()
+        .scoped::<Product<S::Timestamp, usize>, _, _>("ReachablePresent", |scope| {
+            let (edges, roots) = (edges.enter(scope).arrange_by_key(), roots.enter(scope));
+            let proposals = SemigroupVariable::new(scope, Product::new(Default::default(), 1));
+
+            let labels = proposals
+                .concat(&roots)
+                .arrange_by_self()
+                .reduce_core::<_, OrdKeySpine<_, _, Present>>(
+                    "ReachablePresent",
+                    |_key, _input, output, change| {
+                        if output.is_empty() {
+                            change.push(((), Present));
+                        }
+                    },
+                );
+
+            let propagate: Collection<_, N, Present> =
+                labels.join_core(&edges, |_, &(), node| Some(node.clone()));
+            proposals.set(&propagate);
+
+            labels.as_collection(|k, &()| k.clone()).leave()
+        })
+}
+
+/// Propagates labelled roots forward along edges, producing a `(node, label)` pair
+/// for every label that's able to reach each node
+///
+/// Unlike [`least_label_propagation()`](super::propagate::least_label_propagation)
+/// this keeps every label that reaches a node, not just the least one
+pub fn reachable_from<S, N, L, R>(
+    edges: &Collection<S, (N, N), R>,
+    roots: &Collection<S, (N, L), R>,
+) -> Collection<S, (N, L), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    N: ExchangeData + Hash,
+    L: ExchangeData + Hash,
+    R: Difference,
+{
+    reachable_from_core("ReachableFrom", &edges.arrange_by_key(), roots)
+}
+
+/// Propagates labelled roots forward along edges, producing a `(node, label)` pair
+/// for every label that's able to reach each node
+pub fn reachable_from_core<S, N, L, Trace, R>(
+    name: &str,
+    edges: &Arranged<S, Trace>,
+    roots: &Collection<S, (N, L), R>,
+) -> Collection<S, (N, L), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    N: ExchangeData + Hash,
+    L: ExchangeData + Hash,
+    R: Difference,
+    Trace: TraceReader<Key = N, Val = N, Time = S::Timestamp, R = R> + Clone + 'static,
+{
+    roots
+        .scope()
+        .scoped::<Product<S::Timestamp, usize>, _, _>(name, |scope| {
+            let (edges, roots) = (edges.enter(scope), roots.enter(scope));
+            let proposals = SemigroupVariable::new(scope, Product::new(Default::default(), 1));
+
+            // Every `(node, label)` pair is collapsed to a difference of one each round,
+            // which keeps differences from compounding along paths and cycles
+            let reached = proposals
+                .concat(&roots)
+                .arrange_by_self()
+                .reduce_abelian::<_, OrdKeySpine<_, _, _>>(name, |_pair, _input, output| {
+                    output.push(((), R::from(1)));
+                })
+                .as_collection(|(node, label), &()| (node.clone(), label.clone()));
+
+            let propagate: Collection<_, (N, L), R> = reached
+                .arrange_by_key()
+                .join_core(&edges, |_node, label, dest| {
+                    Some((dest.clone(), label.clone()))
+                });
+            proposals.set(&propagate);
+
+            reached.leave()
+        })
+}
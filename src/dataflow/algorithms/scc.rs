@@ -0,0 +1,60 @@
+use crate::dataflow::Difference;
+use differential_dataflow::{
+    algorithms::graphs::{propagate, scc},
+    lattice::Lattice,
+    operators::Threshold,
+    Collection, ExchangeData,
+};
+use std::hash::Hash;
+use timely::dataflow::Scope;
+
+/// Reduces a graph to only the edges that are part of a strongly connected component,
+/// every edge that remains is within a cycle
+///
+/// This works over arbitrary `(source, dest)` edges, so it can be used for call graphs,
+/// control flow graphs and e-class dependencies alike
+pub fn strongly_connected<S, N, R>(edges: &Collection<S, (N, N), R>) -> Collection<S, (N, N), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    N: ExchangeData + Hash,
+    R: Difference,
+{
+    scc::strongly_connected(edges)
+}
+
+/// Produces all nodes that are part of a cycle, including nodes with edges to themselves
+pub fn cyclic_nodes<S, N, R>(edges: &Collection<S, (N, N), R>) -> Collection<S, N, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    N: ExchangeData + Hash,
+    R: Difference,
+{
+    // Both ends of an edge within a strongly connected component are able to reach
+    // themselves, so only the sources need to be collected
+    strongly_connected(edges)
+        .map(|(src, _dest)| src)
+        .distinct_core()
+}
+
+/// Labels every node within the graph with the component it belongs to, producing a
+/// `(node, component)` pair where `component` is the smallest node in the component
+///
+/// Nodes that aren't part of any cycle are within their own singleton component
+pub fn components<S, N, R>(edges: &Collection<S, (N, N), R>) -> Collection<S, (N, N), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    N: ExchangeData + Hash,
+    R: Difference,
+{
+    let nodes = edges
+        .flat_map(|(src, dest)| vec![src, dest])
+        .distinct_core()
+        .map(|node| (node.clone(), node));
+
+    // Every node within a component can reach every other node within it, so propagating
+    // the smallest label along the component's edges reaches the entire component
+    propagate::propagate(&strongly_connected(edges), &nodes)
+}
@@ -0,0 +1,120 @@
+//! Arranged dominance relations between the blocks of every function
+//!
+//! Each function's dominators are computed in a single reduction over its blocks
+//! with [`Dominators`], the resulting pairs are arranged by the dominated block so
+//! that passes can look up everything dominating a block with a `join_core`
+//!
+//! Dominance frontiers are derived from those pairs and the edges between blocks
+//! with joins alone, so editing a block only revisits the frontiers that pass
+//! through its edges instead of every block of its function
+
+use crate::{
+    dataflow::{Difference, Program},
+    optimize::analysis::Dominators,
+    repr::{basic_block::BasicBlockDesc, BasicBlockId},
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{
+        arrange::{ArrangeByKey, Arranged, TraceAgent},
+        Join, JoinCore, Reduce, Threshold,
+    },
+    trace::implementations::ord::OrdValSpine,
+    Collection,
+};
+use std::collections::BTreeMap;
+use timely::dataflow::Scope;
+
+/// The name of the [`TraceManager`](crate::dataflow::TraceManager) entry holding
+/// the dominators of each block
+pub const DOMINATORS_TRACE: &str = "analysis/dominators";
+
+pub type DominatorTrace<T, R> = TraceAgent<OrdValSpine<BasicBlockId, BasicBlockId, T, R>>;
+
+/// Every block reachable from its function's entry keyed to each block that
+/// dominates it, including itself
+pub fn dominators<S, R>(program: &Program<S, R>) -> Arranged<S, DominatorTrace<S::Timestamp, R>>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let entries = program
+        .function_descriptors
+        .filter(|(_, desc)| !desc.attributes.is_external())
+        .map(|(func, desc)| (func, FunctionPart::Entry(desc.entry)));
+    let blocks = program
+        .block_descriptors
+        .join_map(&program.function_blocks, |_, desc, &func| {
+            (func, FunctionPart::Block(desc.clone()))
+        });
+
+    entries
+        .concat(&blocks)
+        .reduce(|_func, parts, output| {
+            let (mut entry, mut blocks) = (None, BTreeMap::new());
+            for (part, _) in parts {
+                match part {
+                    FunctionPart::Entry(block) => entry = Some(*block),
+                    FunctionPart::Block(desc) => {
+                        blocks.insert(desc.id, desc.clone());
+                    }
+                }
+            }
+
+            if let Some(entry) = entry {
+                let dominators = Dominators::of_blocks(entry, &blocks);
+
+                for &block in blocks.keys() {
+                    for &dominator in dominators.dominators(block).into_iter().flatten() {
+                        output.push(((block, dominator), R::from(1)));
+                    }
+                }
+            }
+        })
+        .map(|(_, dominance)| dominance)
+        .arrange_by_key()
+}
+
+/// Every block keyed to each block within its dominance frontier
+///
+/// A block is within the frontier of another when the other block dominates one of
+/// its predecessors without strictly dominating the block itself, which makes them
+/// the places where definitions coming from different paths meet
+pub fn dominance_frontiers<S, R>(
+    program: &Program<S, R>,
+    dominators: &Arranged<S, DominatorTrace<S::Timestamp, R>>,
+) -> Collection<S, (BasicBlockId, BasicBlockId), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let edges = program.block_terminators.flat_map(|(block, term)| {
+        term.jump_targets()
+            .into_iter()
+            .map(move |target| (block, target))
+    });
+
+    let strictly_dominated = dominators
+        .as_collection(|&block, &dominator| (block, dominator))
+        .filter(|(block, dominator)| block != dominator);
+
+    // Every dominator of a predecessor is a candidate for the frontier of the
+    // predecessor's successor
+    edges
+        .join_core(dominators, |_pred, &succ, &dominator| {
+            Some(((succ, dominator), ()))
+        })
+        .antijoin(&strictly_dominated)
+        .map(|((succ, dominator), ())| (dominator, succ))
+        .distinct_core()
+}
+
+/// The pieces of a function the dominators are computed from
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum FunctionPart {
+    Entry(BasicBlockId),
+    Block(BasicBlockDesc),
+}
@@ -0,0 +1,9 @@
+//! Shared analyses that are arranged once and consumed by many passes
+
+pub mod dominance;
+pub mod phis;
+pub mod use_def;
+
+pub use dominance::{dominance_frontiers, dominators, DominatorTrace, DOMINATORS_TRACE};
+pub use phis::phi_placements;
+pub use use_def::{Def, Use, UseDef, UseDefHandles, UseDefTrace};
@@ -0,0 +1,61 @@
+//! Placement of phi nodes for variables assigned within more than one block
+//!
+//! Phis are placed on the iterated dominance frontier of every block defining a
+//! variable. Both the frontiers and their iteration are maintained incrementally,
+//! so editing a block only recomputes the placements of the variables it defines
+//! and of the frontiers passing through its edges
+
+use crate::{
+    dataflow::{Difference, Program},
+    repr::{BasicBlockId, InstructionExt, VarId},
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Iterate, Join, Reduce, Threshold},
+    Collection,
+};
+use timely::dataflow::Scope;
+
+/// Every variable keyed to the blocks that need a phi merging its definitions
+///
+/// `frontiers` must be the [dominance frontiers](super::dominance_frontiers) of the
+/// program's blocks. Variables defined within a single block never need a phi, so
+/// only variables with definitions in multiple blocks get any placements
+pub fn phi_placements<S, R>(
+    program: &Program<S, R>,
+    frontiers: &Collection<S, (BasicBlockId, BasicBlockId), R>,
+) -> Collection<S, (VarId, BasicBlockId), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let definitions = program
+        .instructions
+        .join_map(&program.block_instructions, |_, inst, &block| {
+            (inst.dest(), block)
+        })
+        .distinct_core()
+        .reduce(|_var, blocks, output| {
+            if blocks.len() > 1 {
+                output.extend(blocks.iter().map(|&(&block, _)| (block, R::from(1))));
+            }
+        });
+
+    // Phis are definitions themselves, so the frontiers of the blocks they're
+    // placed in need phis of their own
+    let reached = definitions.iterate(|reached| {
+        let frontiers = frontiers.enter(&reached.scope());
+
+        reached
+            .map(|(var, block)| (block, var))
+            .join_map(&frontiers, |_, &var, &frontier| (var, frontier))
+            .concat(&definitions.enter(&reached.scope()))
+            .distinct_core()
+    });
+
+    reached
+        .map(|(var, block)| (block, var))
+        .join_map(frontiers, |_, &var, &frontier| (var, frontier))
+        .distinct_core()
+}
@@ -0,0 +1,174 @@
+//! Def-use and use-def indexes over the variables of a program
+//!
+//! Passes that need to know where a variable is defined or everywhere it's used
+//! would otherwise each rebuild the same joins over every instruction and
+//! terminator. [`UseDef`] arranges both directions once per program so that
+//! passes can `join_core` against the shared arrangements, and its traces can be
+//! registered with a [`TraceManager`] to be imported into other dataflows
+
+use crate::{
+    dataflow::{Program, TraceManager, ValTraceHandle},
+    repr::{BasicBlockId, FuncId, InstId, InstructionExt, VarId},
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    difference::Semigroup,
+    lattice::Lattice,
+    operators::arrange::{ArrangeByKey, Arranged, TraceAgent},
+    trace::implementations::ord::OrdValSpine,
+    ExchangeData,
+};
+use lasso::ThreadedRodeo;
+use timely::dataflow::Scope;
+
+/// The name of the [`TraceManager`] entry holding where each variable is defined
+pub const DEFS_TRACE: &str = "analysis/defs";
+
+/// The name of the [`TraceManager`] entry holding the uses of each variable
+pub const DEF_USES_TRACE: &str = "analysis/def_uses";
+
+/// The name of the [`TraceManager`] entry holding the variables used at each use
+pub const USE_DEFS_TRACE: &str = "analysis/use_defs";
+
+/// The place a variable is defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum Def {
+    /// The variable is a parameter of the function
+    Param(FuncId),
+    /// The variable is the destination of the instruction
+    Inst(InstId),
+}
+
+impl Def {
+    pub const fn inst(self) -> Option<InstId> {
+        if let Self::Inst(inst) = self {
+            Some(inst)
+        } else {
+            None
+        }
+    }
+}
+
+/// A place a variable is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum Use {
+    Inst(InstId),
+    /// The variable is used by the terminator of the block
+    Terminator(BasicBlockId),
+}
+
+impl Use {
+    pub const fn inst(self) -> Option<InstId> {
+        if let Self::Inst(inst) = self {
+            Some(inst)
+        } else {
+            None
+        }
+    }
+
+    pub const fn terminator(self) -> Option<BasicBlockId> {
+        if let Self::Terminator(block) = self {
+            Some(block)
+        } else {
+            None
+        }
+    }
+}
+
+pub type DefTrace<T, R> = TraceAgent<OrdValSpine<VarId, Def, T, R>>;
+pub type DefUseTrace<T, R> = TraceAgent<OrdValSpine<VarId, Use, T, R>>;
+pub type UseDefTrace<T, R> = TraceAgent<OrdValSpine<Use, VarId, T, R>>;
+
+/// The arranged definitions and uses of every variable within a program
+#[derive(Clone)]
+pub struct UseDef<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Semigroup,
+{
+    /// Every variable keyed to where it's defined
+    pub defs: Arranged<S, DefTrace<S::Timestamp, R>>,
+    /// Every variable keyed to each place it's used
+    pub def_uses: Arranged<S, DefUseTrace<S::Timestamp, R>>,
+    /// Every place a variable is used keyed to the variables used there, a
+    /// variable used more than once at the same place is only included once
+    pub use_defs: Arranged<S, UseDefTrace<S::Timestamp, R>>,
+}
+
+impl<S, R> UseDef<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Semigroup + ExchangeData,
+{
+    pub fn new(program: &Program<S, R>) -> Self {
+        let params = program.function_descriptors.flat_map(|(func, desc)| {
+            desc.params
+                .into_iter()
+                .map(move |param| (param.var, Def::Param(func)))
+        });
+        let defs = program
+            .instructions
+            .map(|(id, inst)| (inst.dest(), Def::Inst(id)))
+            .concat(&params);
+
+        let instruction_uses = program.instructions.flat_map(|(id, inst)| {
+            let mut used: Vec<VarId> = inst.used_vars().into_iter().map(|used| used.var).collect();
+            used.sort_unstable();
+            used.dedup();
+
+            used.into_iter().map(move |var| (Use::Inst(id), var))
+        });
+        let terminator_uses = program.block_terminators.flat_map(|(block, term)| {
+            let mut used = term.used_vars();
+            used.sort_unstable();
+            used.dedup();
+
+            used.into_iter()
+                .map(move |var| (Use::Terminator(block), var))
+        });
+        let uses = instruction_uses.concat(&terminator_uses);
+
+        Self {
+            defs: defs.arrange_by_key(),
+            def_uses: uses.map(|(used, var)| (var, used)).arrange_by_key(),
+            use_defs: uses.arrange_by_key(),
+        }
+    }
+
+    /// Registers the arrangements' traces with a [`TraceManager`] under
+    /// [`DEFS_TRACE`], [`DEF_USES_TRACE`] and [`USE_DEFS_TRACE`]
+    pub fn register(
+        &self,
+        manager: &mut TraceManager<S::Timestamp>,
+        interner: &ThreadedRodeo,
+    ) -> UseDefHandles<S::Timestamp, R>
+    where
+        S::Timestamp: 'static,
+        R: 'static,
+    {
+        UseDefHandles {
+            defs: manager.register(
+                interner.get_or_intern_static(DEFS_TRACE),
+                self.defs.trace.clone(),
+            ),
+            def_uses: manager.register(
+                interner.get_or_intern_static(DEF_USES_TRACE),
+                self.def_uses.trace.clone(),
+            ),
+            use_defs: manager.register(
+                interner.get_or_intern_static(USE_DEFS_TRACE),
+                self.use_defs.trace.clone(),
+            ),
+        }
+    }
+}
+
+/// Handles to the traces of a [`UseDef`] registered with a [`TraceManager`]
+#[derive(Debug, Clone, Copy)]
+pub struct UseDefHandles<T, R> {
+    pub defs: ValTraceHandle<VarId, Def, T, R>,
+    pub def_uses: ValTraceHandle<VarId, Use, T, R>,
+    pub use_defs: ValTraceHandle<Use, VarId, T, R>,
+}
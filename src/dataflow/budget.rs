@@ -0,0 +1,348 @@
+//! Limits on how large the functions flowing through a pipeline can get
+//!
+//! A pathological input or a runaway rewrite can grow a function without bound, and
+//! with it every arrangement the passes build over it. A [`Budget`] caps the number
+//! of instructions and blocks of each function along with how far a function can
+//! grow past its size within the pipeline's input. Functions of the input that are
+//! already over budget skip the passes entirely and a pass that pushes a function
+//! over budget has its changes to that function thrown away, both of which are
+//! reported as [`BudgetExceeded`] diagnostics instead of failing the pipeline
+
+use crate::{
+    dataflow::{instruction_functions, Difference, Program},
+    repr::FuncId,
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    difference::Abelian,
+    lattice::Lattice,
+    operators::{Join, Reduce, Threshold},
+    Collection, ExchangeData, Hashable,
+};
+use std::fmt::{self, Display};
+use timely::dataflow::Scope;
+
+/// The limits every function within a pipeline is held to
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Budget {
+    /// The most instructions a single function can have
+    pub max_instructions: Option<usize>,
+    /// The most basic blocks a single function can have
+    pub max_blocks: Option<usize>,
+    /// How many times its instruction count within the pipeline's input a function
+    /// can grow to, which bounds inlining and any other rewrite that duplicates code
+    pub max_growth: Option<f64>,
+}
+
+impl Budget {
+    /// A budget without any limits
+    pub const fn unlimited() -> Self {
+        Self {
+            max_instructions: None,
+            max_blocks: None,
+            max_growth: None,
+        }
+    }
+
+    pub fn with_max_instructions(mut self, max_instructions: Option<usize>) -> Self {
+        self.max_instructions = max_instructions;
+        self
+    }
+
+    pub fn with_max_blocks(mut self, max_blocks: Option<usize>) -> Self {
+        self.max_blocks = max_blocks;
+        self
+    }
+
+    pub fn with_max_growth(mut self, max_growth: Option<f64>) -> Self {
+        self.max_growth = max_growth;
+        self
+    }
+
+    /// Returns `true` if the budget doesn't limit anything
+    pub fn is_unlimited(&self) -> bool {
+        self.max_instructions.is_none() && self.max_blocks.is_none() && self.max_growth.is_none()
+    }
+
+    /// Splits the functions of a program that are already over budget off from the
+    /// rest, returning the functions within budget, the ones over it and why
+    #[allow(clippy::type_complexity)]
+    pub fn split_oversized<S, R>(
+        &self,
+        program: &Program<S, R>,
+    ) -> (
+        Program<S, R>,
+        Program<S, R>,
+        Collection<S, BudgetExceeded, R>,
+    )
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+        R: Difference,
+    {
+        let span = tracing::debug_span!("splitting oversized functions");
+        span.in_scope(|| {
+            let exceeded = over_limit(
+                &instruction_counts(program),
+                self.max_instructions,
+                BudgetKind::Instructions,
+                None,
+            )
+            .concat(&over_limit(
+                &block_counts(program),
+                self.max_blocks,
+                BudgetKind::Blocks,
+                None,
+            ));
+            let oversized = exceeded.map(|exceeded| exceeded.func).distinct_core();
+
+            (
+                select_functions(program, &oversized, false),
+                select_functions(program, &oversized, true),
+                exceeded,
+            )
+        })
+    }
+
+    /// Checks the output of a pass against the budget, replacing every function
+    /// that the pass pushed over budget with its version from before the pass
+    ///
+    /// Growth is measured against the instruction counts in `baseline`, functions
+    /// that didn't grow during the pass are never reverted so that passes can still
+    /// shrink a function that started out over budget
+    pub fn enforce<S, R>(
+        &self,
+        pass: &str,
+        baseline: &Collection<S, (FuncId, usize), R>,
+        input: &Program<S, R>,
+        output: &Program<S, R>,
+    ) -> (Program<S, R>, Collection<S, BudgetExceeded, R>)
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+        R: Difference,
+    {
+        let span = tracing::debug_span!("enforcing budget", pass);
+        span.in_scope(|| {
+            let pass = Some(pass.to_owned());
+            let instructions = grown(&instruction_counts(output), &instruction_counts(input));
+            let blocks = grown(&block_counts(output), &block_counts(input));
+
+            let mut exceeded = over_limit(
+                &instructions,
+                self.max_instructions,
+                BudgetKind::Instructions,
+                pass.clone(),
+            )
+            .concat(&over_limit(
+                &blocks,
+                self.max_blocks,
+                BudgetKind::Blocks,
+                pass.clone(),
+            ));
+
+            if let Some(max_growth) = self.max_growth {
+                let overgrown = instructions
+                    .join_map(baseline, move |&func, &actual, &baseline| {
+                        let limit = (baseline as f64 * max_growth).ceil() as usize;
+
+                        (actual > limit).then(|| BudgetExceeded {
+                            func,
+                            pass: pass.clone(),
+                            kind: BudgetKind::Growth,
+                            limit,
+                            actual,
+                        })
+                    })
+                    .flat_map(|exceeded| exceeded);
+
+                exceeded = exceeded.concat(&overgrown);
+            }
+
+            let over_budget = exceeded.map(|exceeded| exceeded.func).distinct_core();
+            let output = select_functions(output, &over_budget, false).concat(&select_functions(
+                input,
+                &over_budget,
+                true,
+            ));
+
+            (output, exceeded)
+        })
+    }
+}
+
+/// The limit of a [`Budget`] that a function went over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BudgetKind {
+    Instructions,
+    Blocks,
+    Growth,
+}
+
+impl BudgetKind {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Instructions => "instructions",
+            Self::Blocks => "blocks",
+            Self::Growth => "growth",
+        }
+    }
+}
+
+impl Display for BudgetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A function went over its [`Budget`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BudgetExceeded {
+    pub func: FuncId,
+    /// The pass whose changes to the function were thrown away, `None` if the
+    /// function was already over budget within the pipeline's input
+    pub pass: Option<String>,
+    pub kind: BudgetKind,
+    pub limit: usize,
+    pub actual: usize,
+}
+
+impl Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            BudgetKind::Instructions => {
+                write!(f, "{:?} has {} instructions", self.func, self.actual)?
+            }
+            BudgetKind::Blocks => write!(f, "{:?} has {} blocks", self.func, self.actual)?,
+            BudgetKind::Growth => {
+                write!(f, "{:?} grew to {} instructions", self.func, self.actual)?
+            }
+        }
+        write!(f, ", over its limit of {}", self.limit)?;
+
+        match self.pass.as_ref() {
+            Some(pass) => write!(f, " after {}", pass),
+            None => f.write_str(" within the input"),
+        }
+    }
+}
+
+/// The number of instructions within each function
+pub fn instruction_counts<S, R>(program: &Program<S, R>) -> Collection<S, (FuncId, usize), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    instruction_functions(program)
+        .map(|(inst, func)| (func, inst))
+        .reduce(|_func, insts, output| output.push((insts.len(), R::from(1))))
+}
+
+/// The number of basic blocks within each function
+pub fn block_counts<S, R>(program: &Program<S, R>) -> Collection<S, (FuncId, usize), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    program
+        .function_blocks
+        .map(|(block, func)| (func, block))
+        .reduce(|_func, blocks, output| output.push((blocks.len(), R::from(1))))
+}
+
+/// The counts within `after` that are larger than their counterparts within `before`
+fn grown<S, R>(
+    after: &Collection<S, (FuncId, usize), R>,
+    before: &Collection<S, (FuncId, usize), R>,
+) -> Collection<S, (FuncId, usize), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let larger = after
+        .join_map(before, |&func, &after, &before| (func, after, before))
+        .filter(|&(_, after, before)| after > before)
+        .map(|(func, after, _)| (func, after));
+    let added = after.antijoin(&before.map(|(func, _)| func));
+
+    larger.concat(&added)
+}
+
+fn over_limit<S, R>(
+    counts: &Collection<S, (FuncId, usize), R>,
+    limit: Option<usize>,
+    kind: BudgetKind,
+    pass: Option<String>,
+) -> Collection<S, BudgetExceeded, R>
+where
+    S: Scope,
+    R: Abelian + ExchangeData,
+{
+    counts.flat_map(move |(func, actual)| {
+        limit
+            .filter(|&limit| actual > limit)
+            .map(|limit| BudgetExceeded {
+                func,
+                pass: pass.clone(),
+                kind,
+                limit,
+                actual,
+            })
+    })
+}
+
+/// Keeps either only the parts of the program belonging to `funcs` or only the
+/// parts belonging to any other function
+fn select_functions<S, R>(
+    program: &Program<S, R>,
+    funcs: &Collection<S, FuncId, R>,
+    keep: bool,
+) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let insts = instruction_functions(program)
+        .map(|(inst, func)| (func, inst))
+        .semijoin(funcs)
+        .map(|(_, inst)| inst);
+    let blocks = program
+        .function_blocks
+        .map(|(block, func)| (func, block))
+        .semijoin(funcs)
+        .map(|(_, block)| block);
+
+    Program {
+        instructions: select(&program.instructions, &insts, keep),
+        block_instructions: select(&program.block_instructions, &insts, keep),
+        block_terminators: select(&program.block_terminators, &blocks, keep),
+        block_descriptors: select(&program.block_descriptors, &blocks, keep),
+        function_blocks: select(&program.function_blocks, &blocks, keep),
+        function_descriptors: select(&program.function_descriptors, funcs, keep),
+    }
+}
+
+fn select<S, K, V, R>(
+    collection: &Collection<S, (K, V), R>,
+    keys: &Collection<S, K, R>,
+    keep: bool,
+) -> Collection<S, (K, V), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    K: ExchangeData + Hashable,
+    V: ExchangeData,
+    R: Difference,
+{
+    if keep {
+        collection.semijoin(keys)
+    } else {
+        collection.antijoin(keys)
+    }
+}
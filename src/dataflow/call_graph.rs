@@ -0,0 +1,64 @@
+use crate::{
+    dataflow::{
+        algorithms::scc,
+        cardinality::{self, JoinOrder},
+        operators::FilterMap,
+        Difference, Program,
+    },
+    repr::{instruction::Call, utils::CastRef, FuncId},
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{arrange::TraceAgent, Threshold},
+    trace::implementations::ord::OrdValSpine,
+    Collection,
+};
+use timely::dataflow::Scope;
+
+/// A caller→callee edge within the call graph
+pub type CallEdge = (FuncId, FuncId);
+
+/// The call graph arranged by caller
+pub type CallGraphTrace<T, R> = TraceAgent<OrdValSpine<FuncId, FuncId, T, R>>;
+
+/// Builds the program's call graph, producing a `(caller, callee)` edge for
+/// every function that calls another function (or itself)
+pub fn call_graph<S, R>(program: &Program<S, R>) -> Collection<S, CallEdge, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    call_graph_with(program, JoinOrder::InstructionsFirst)
+}
+
+/// The same as [`call_graph()`] but joins calls with their callers in the given order,
+/// see [`Cardinalities::call_graph_order()`](crate::dataflow::Cardinalities::call_graph_order)
+pub fn call_graph_with<S, R>(
+    program: &Program<S, R>,
+    order: JoinOrder,
+) -> Collection<S, CallEdge, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let calls = program
+        .instructions
+        .filter_map(|(inst_id, inst)| inst.cast_ref::<Call>().map(|call| (inst_id, call.func)));
+
+    cardinality::with_functions(program, &calls, order).distinct_core()
+}
+
+/// Produces all functions that are part of a cycle within the call graph,
+/// covering direct self-recursion as well as mutual recursion
+pub fn recursive_functions<S, R>(
+    call_graph: &Collection<S, CallEdge, R>,
+) -> Collection<S, FuncId, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
+{
+    scc::cyclic_nodes(call_graph)
+}
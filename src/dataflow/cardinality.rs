@@ -0,0 +1,146 @@
+//! Cardinality statistics for choosing join orders
+//!
+//! Differential joins do work proportional to the changes flowing into them, so the
+//! order of a join chain decides how much work each change to a relation causes.
+//! Analyses that need the function of every instruction can either join the
+//! instructions with their blocks and then with their functions, or first resolve
+//! the function of every instruction id and then join the instructions with that.
+//! The latter only depends on ids, which passes very rarely change, so rewriting an
+//! instruction passes through a single join instead of two. Filtering instructions
+//! first is still cheaper when only a small fraction of them are of interest, which
+//! is what [`Cardinalities::join_order()`] decides
+
+use crate::{
+    dataflow::{Difference, Program},
+    repr::{instruction::Call, utils::CastRef, FuncId, Function, InstId},
+};
+use differential_dataflow::{lattice::Lattice, operators::Join, Collection, ExchangeData};
+use timely::dataflow::Scope;
+
+/// The fraction of instructions an analysis may select before it's cheaper to
+/// resolve the function of every instruction than to join the selected ones twice
+const SELECTIVITY_THRESHOLD: f64 = 0.25;
+
+/// The sizes of a program's core relations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Cardinalities {
+    pub functions: usize,
+    pub basic_blocks: usize,
+    pub instructions: usize,
+    pub calls: usize,
+    pub branches: usize,
+}
+
+impl Cardinalities {
+    /// Collects the cardinalities of the given functions
+    pub fn of(functions: &[Function]) -> Self {
+        let mut cardinalities = Self {
+            functions: functions.len(),
+            ..Self::default()
+        };
+
+        for block in functions.iter().flat_map(|func| func.basic_blocks.iter()) {
+            cardinalities.basic_blocks += 1;
+            cardinalities.instructions += block.instructions.len();
+            cardinalities.calls += block
+                .instructions
+                .iter()
+                .filter(|inst| inst.cast_ref::<Call>().is_some())
+                .count();
+
+            if block.terminator.is_branching() {
+                cardinalities.branches += 1;
+            }
+        }
+
+        cardinalities
+    }
+
+    /// The average number of instructions within each basic block
+    pub fn instructions_per_block(&self) -> f64 {
+        self.instructions as f64 / self.basic_blocks.max(1) as f64
+    }
+
+    /// The fraction of instructions that are calls
+    pub fn call_density(&self) -> f64 {
+        self.calls as f64 / self.instructions.max(1) as f64
+    }
+
+    /// Picks the join order for an analysis that selects `selected` out of every
+    /// instruction and needs the function each of them belongs to
+    pub fn join_order(&self, selected: usize) -> JoinOrder {
+        let selectivity = selected as f64 / self.instructions.max(1) as f64;
+
+        if selectivity < SELECTIVITY_THRESHOLD {
+            JoinOrder::InstructionsFirst
+        } else {
+            JoinOrder::LocationsFirst
+        }
+    }
+
+    /// The join order for building the call graph
+    pub fn call_graph_order(&self) -> JoinOrder {
+        self.join_order(self.calls)
+    }
+}
+
+/// How instructions are joined with the functions they belong to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JoinOrder {
+    /// Select the instructions of interest, then join them with their blocks and
+    /// then with their functions
+    InstructionsFirst,
+    /// Resolve the function of every instruction from ids alone, then join the
+    /// instructions of interest with that
+    LocationsFirst,
+}
+
+impl Default for JoinOrder {
+    fn default() -> Self {
+        Self::InstructionsFirst
+    }
+}
+
+/// Produces the function every instruction belongs to
+pub fn instruction_functions<S, R>(program: &Program<S, R>) -> Collection<S, (InstId, FuncId), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    program
+        .block_instructions
+        .map(|(inst, block)| (block, inst))
+        .join_map(&program.function_blocks, |_block, &inst, &func| {
+            (inst, func)
+        })
+}
+
+/// Joins the selected instructions with the functions they belong to using the
+/// given join order
+pub fn with_functions<S, R, D>(
+    program: &Program<S, R>,
+    selected: &Collection<S, (InstId, D), R>,
+    order: JoinOrder,
+) -> Collection<S, (FuncId, D), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+    D: ExchangeData,
+{
+    match order {
+        JoinOrder::InstructionsFirst => selected
+            .join_map(&program.block_instructions, |_inst, data, &block| {
+                (block, data.clone())
+            })
+            .join_map(&program.function_blocks, |_block, data, &func| {
+                (func, data.clone())
+            }),
+
+        JoinOrder::LocationsFirst => selected
+            .join_map(&instruction_functions(program), |_inst, data, &func| {
+                (func, data.clone())
+            }),
+    }
+}
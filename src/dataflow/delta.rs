@@ -0,0 +1,229 @@
+//! Structured differences between the versions of a program at two epochs
+//!
+//! Every relation of an optimized program lives within a trace, so the changes the
+//! optimizer made between two epochs can be read straight out of them without
+//! reconstructing and comparing whole functions
+
+use crate::{
+    dataflow::ProgramTrace,
+    repr::{
+        basic_block::BasicBlockDesc,
+        function::FunctionDesc,
+        utils::{DisplayCtx, IRDisplay},
+        BasicBlockId, FuncId, InstId, Instruction, Terminator,
+    },
+    symbols::SymbolResolver,
+};
+use differential_dataflow::{
+    difference::Semigroup,
+    lattice::Lattice,
+    trace::{Cursor, TraceReader},
+};
+use pretty::{DocAllocator, DocBuilder};
+use std::fmt::Debug;
+use timely::progress::{frontier::AntichainRef, Timestamp};
+
+/// The items that were added and removed between two epochs, both in key order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changes<T> {
+    pub added: Vec<T>,
+    pub removed: Vec<T>,
+}
+
+impl<T> Changes<T> {
+    pub const fn new() -> Self {
+        Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl<T> Default for Changes<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Everything that changed within a program between two epochs, see
+/// [`ProgramTrace::delta()`]
+///
+/// A modified item shows up as the removal of its old version along with the
+/// addition of its new one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrDelta<T> {
+    pub from: T,
+    pub to: T,
+    pub functions: Changes<(FuncId, FunctionDesc)>,
+    pub basic_blocks: Changes<(BasicBlockId, BasicBlockDesc)>,
+    pub terminators: Changes<(BasicBlockId, Terminator)>,
+    pub instructions: Changes<(InstId, Instruction)>,
+}
+
+impl<T> IrDelta<T> {
+    /// Returns `true` if nothing changed between the two epochs
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+            && self.basic_blocks.is_empty()
+            && self.terminators.is_empty()
+            && self.instructions.is_empty()
+    }
+}
+
+impl<T, R> ProgramTrace<T, R>
+where
+    T: Timestamp + Lattice,
+    R: Semigroup,
+{
+    /// Collects the changes made to the program between the epochs `from` and `to`,
+    /// where the program at an epoch holds every update from before it in the same
+    /// way as [`TraceManager::export()`](crate::dataflow::TraceManager::export)
+    ///
+    /// `from` must be less than or equal to `to`, and updates from before `from`
+    /// must still be distinguishable, so the traces may only have been compacted up
+    /// to a time before it. Returns `None` if the traces haven't been completed up
+    /// to `to` yet
+    pub fn delta(&mut self, from: T, to: T) -> Option<IrDelta<T>> {
+        debug_assert!(from.less_equal(&to));
+
+        Some(IrDelta {
+            functions: trace_changes(&mut self.function_descriptors, &from, &to)?,
+            basic_blocks: trace_changes(&mut self.block_descriptors, &from, &to)?,
+            terminators: trace_changes(&mut self.block_terminators, &from, &to)?,
+            instructions: trace_changes(&mut self.instructions, &from, &to)?,
+            from,
+            to,
+        })
+    }
+}
+
+/// Collects the entries of a trace that are only present at one of the two epochs,
+/// following the same rules as [`ProgramTrace::delta()`]
+pub fn trace_changes<Trace, T>(
+    trace: &mut Trace,
+    from: &T,
+    to: &T,
+) -> Option<Changes<(Trace::Key, Trace::Val)>>
+where
+    T: Timestamp,
+    Trace: TraceReader<Time = T>,
+    Trace::Key: Clone,
+    Trace::Val: Clone,
+    Trace::R: Semigroup,
+{
+    let (from, to) = ([from.clone()], [to.clone()]);
+    let (from, to) = (AntichainRef::new(&from), AntichainRef::new(&to));
+    let (mut cursor, storage) = trace.cursor_through(to)?;
+
+    let accumulate = |accumulated: &mut Option<Trace::R>, diff: &Trace::R| match accumulated {
+        Some(accumulated) => accumulated.plus_equals(diff),
+        None => *accumulated = Some(diff.clone()),
+    };
+    let present = |accumulated: Option<Trace::R>| accumulated.map_or(false, |diff| !diff.is_zero());
+
+    let mut changes = Changes::new();
+    while cursor.key_valid(&storage) {
+        while cursor.val_valid(&storage) {
+            let (mut before, mut after) = (None, None);
+            cursor.map_times(&storage, |time, diff| {
+                if !from.less_equal(time) {
+                    accumulate(&mut before, diff);
+                }
+                if !to.less_equal(time) {
+                    accumulate(&mut after, diff);
+                }
+            });
+
+            match (present(before), present(after)) {
+                (false, true) => changes
+                    .added
+                    .push((cursor.key(&storage).clone(), cursor.val(&storage).clone())),
+                (true, false) => changes
+                    .removed
+                    .push((cursor.key(&storage).clone(), cursor.val(&storage).clone())),
+                _ => {}
+            }
+
+            cursor.step_val(&storage);
+        }
+
+        cursor.step_key(&storage);
+    }
+
+    Some(changes)
+}
+
+impl<T> IRDisplay for IrDelta<T>
+where
+    T: Debug,
+{
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        let header = ctx.text(format!("; delta from {:?} to {:?}", self.from, self.to));
+
+        let functions = self.functions.display_with(ctx, |(func, desc)| {
+            let name = desc
+                .name
+                .map(|name| ctx.space().append(name.display(ctx)))
+                .unwrap_or_else(|| ctx.nil());
+
+            func.display(ctx).append(name)
+        });
+        let basic_blocks = self
+            .basic_blocks
+            .display_with(ctx, |(block, _)| block.display(ctx));
+        let terminators = self.terminators.display_with(ctx, |(block, terminator)| {
+            block
+                .display(ctx)
+                .append(ctx.text(":"))
+                .append(ctx.space())
+                .append(terminator.display(ctx))
+        });
+        let instructions = self
+            .instructions
+            .display_with(ctx, |(_, inst)| inst.display(ctx));
+
+        ctx.intersperse(
+            vec![header]
+                .into_iter()
+                .chain(functions)
+                .chain(basic_blocks)
+                .chain(terminators)
+                .chain(instructions),
+            ctx.hardline(),
+        )
+    }
+}
+
+impl<T> Changes<T> {
+    /// Renders every removed item prefixed with `-` and every added one with `+`
+    fn display_with<'a, D, A, R, F>(
+        &self,
+        ctx: DisplayCtx<'a, D, A, R>,
+        mut display: F,
+    ) -> Vec<DocBuilder<'a, D, A>>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+        F: FnMut(&T) -> DocBuilder<'a, D, A>,
+    {
+        let removed = self.removed.iter().map(|item| (ctx.text("-"), item));
+        let added = self.added.iter().map(|item| (ctx.text("+"), item));
+
+        removed
+            .chain(added)
+            .map(|(sign, item)| sign.append(ctx.space()).append(display(item)).group())
+            .collect()
+    }
+}
@@ -0,0 +1,98 @@
+//! Ordering dependencies between side-effecting instructions
+//!
+//! Effectful instructions must keep their relative order and can't be removed just
+//! because nothing uses their results. Effect edges are derived from the order of
+//! instructions within each basic block instead of being given as an input so that
+//! passes which rewrite instructions never have to keep them up to date: every
+//! effectful instruction points to the next effectful instruction within its block
+//! and the last one points to the block's terminator. Calls to external functions
+//! are always effectful since nothing is known about what their callee does
+
+use crate::{
+    dataflow::{Difference, Program},
+    repr::{
+        instruction::Call,
+        utils::{CastRef, InstructionPurity},
+        BasicBlockId, InstId, InstructionExt,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+    Collection,
+};
+use timely::dataflow::Scope;
+
+/// `to` depends on the effects of the instruction `from`, so `from` must be kept
+/// and must come before `to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct EffectEdge {
+    pub from: InstId,
+    pub to: EffectTarget,
+}
+
+impl EffectEdge {
+    pub const fn new(from: InstId, to: EffectTarget) -> Self {
+        Self { from, to }
+    }
+}
+
+/// The dependent side of an [`EffectEdge`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum EffectTarget {
+    Instruction(InstId),
+    Terminator(BasicBlockId),
+}
+
+/// Produces the effect edges of every basic block within the program
+pub fn effect_edges<S, R>(program: &Program<S, R>) -> Collection<S, EffectEdge, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    // Calls to external functions can do anything, so they're treated as impure
+    // even though their callee's body can't be looked at
+    let externals = program
+        .function_descriptors
+        .filter(|(_, desc)| desc.attributes.is_external())
+        .map(|(func, _)| func);
+    let external_calls = program
+        .instructions
+        .filter_map(|(id, inst)| inst.cast_ref::<Call>().map(|call| (call.func, id)))
+        .semijoin(&externals)
+        .map(|(_, id)| id);
+
+    let effectful = program
+        .instructions
+        .filter(|(_, inst)| inst.purity() == InstructionPurity::Impure)
+        .map(|(id, _)| id)
+        .concat(&external_calls);
+
+    program
+        .block_descriptors
+        .flat_map(|(block, desc)| {
+            desc.instructions
+                .into_iter()
+                .enumerate()
+                .map(move |(index, inst)| (inst, (block, index)))
+        })
+        .semijoin(&effectful)
+        .map(|(inst, (block, index))| (block, (index, inst)))
+        .reduce(|&block, effects, output| {
+            // Values are sorted, so the effects are in the order they appear within the block
+            let mut effects = effects.iter().map(|&(&(_, inst), _)| inst).peekable();
+
+            while let Some(from) = effects.next() {
+                let to = effects
+                    .peek()
+                    .map_or(EffectTarget::Terminator(block), |&to| {
+                        EffectTarget::Instruction(to)
+                    });
+
+                output.push((EffectEdge::new(from, to), R::from(1)));
+            }
+        })
+        .map(|(_, edge)| edge)
+}
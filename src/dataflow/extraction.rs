@@ -0,0 +1,177 @@
+//! Rendering of the functions extracted from a dataflow's output
+//!
+//! By default every extracted function is printed in full for each timestamp, which
+//! quickly becomes unreadable for incremental runs where only a handful of functions
+//! change between timestamps. [`ExtractionDisplay::Changes`] instead pairs up the
+//! retractions and additions of each function and only prints the lines that changed,
+//! prefixed with `+` and `-` markers
+
+use crate::{
+    dataflow::{Diff, Time},
+    parallel::{self, MaybeSync},
+    repr::{utils::IRDisplay, FuncId, Function},
+    symbols::SymbolResolver,
+    verify::ValidityError,
+};
+use std::{collections::BTreeMap, env, fmt::Write};
+
+/// The environment variable that selects the [`ExtractionDisplay`] used by
+/// [`ExtractionDisplay::from_env()`], either `full` or `changes`
+pub const EXTRACTION_DISPLAY_VAR: &str = "SRUTH_EXTRACTION_DISPLAY";
+
+/// An extracted function or error along with the time and difference it was
+/// produced with
+pub type ExtractedItem = (Result<(FuncId, Function), ValidityError>, Time, Diff);
+
+/// How extracted functions should be displayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExtractionDisplay {
+    /// Print every extracted function in full
+    Full,
+    /// Only print the lines of functions that changed at each timestamp
+    Changes,
+}
+
+impl ExtractionDisplay {
+    /// Reads the display mode from [`EXTRACTION_DISPLAY_VAR`], falling back to
+    /// [`ExtractionDisplay::Full`] if it isn't set or isn't recognized
+    pub fn from_env() -> Self {
+        match env::var(EXTRACTION_DISPLAY_VAR).as_deref() {
+            Ok("changes") => Self::Changes,
+            Ok("full") | Err(_) => Self::Full,
+
+            Ok(other) => {
+                tracing::warn!(
+                    "unrecognized value for {}: {:?}, expected `full` or `changes`",
+                    EXTRACTION_DISPLAY_VAR,
+                    other,
+                );
+
+                Self::Full
+            }
+        }
+    }
+
+    /// Renders the extracted data of every timestamp
+    pub fn render<R>(self, extracted: &[(Time, Vec<ExtractedItem>)], interner: &R) -> String
+    where
+        R: SymbolResolver + MaybeSync,
+    {
+        // Timestamps are rendered independently and then stitched back together in order
+        parallel::map(extracted.iter().collect(), |(time, data)| {
+            let mut output = String::new();
+            match self {
+                Self::Full => render_full(&mut output, *time, data, interner),
+                Self::Changes => render_changes(&mut output, *time, data, interner),
+            }
+
+            output
+        })
+        .concat()
+    }
+}
+
+impl Default for ExtractionDisplay {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+fn render_full<R>(output: &mut String, time: Time, data: &[ExtractedItem], interner: &R)
+where
+    R: SymbolResolver,
+{
+    let _ = writeln!(output, "Data from timestamp {}:", time);
+
+    for (data, _time, _diff) in data {
+        match data {
+            Ok((_id, function)) => {
+                let _ = writeln!(output, "{}", function.to_pretty_string(interner));
+            }
+
+            Err(err) => {
+                let _ = writeln!(output, "Error: {:?}", err);
+            }
+        }
+    }
+}
+
+fn render_changes<R>(output: &mut String, time: Time, data: &[ExtractedItem], interner: &R)
+where
+    R: SymbolResolver,
+{
+    // The retracted and added versions of each function
+    let mut functions: BTreeMap<FuncId, (Option<String>, Option<String>)> = BTreeMap::new();
+    let mut errors = Vec::new();
+
+    for (data, _time, diff) in data {
+        match data {
+            Ok((id, function)) => {
+                let (removed, added) = functions.entry(*id).or_default();
+                let slot = if *diff < 0 { removed } else { added };
+                *slot = Some(function.to_pretty_string(interner));
+            }
+
+            Err(err) => errors.push((err, *diff)),
+        }
+    }
+
+    if functions.is_empty() && errors.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(output, "Changes at timestamp {}:", time);
+    for (removed, added) in functions.values() {
+        let (removed, added) = (
+            removed.as_deref().unwrap_or_default(),
+            added.as_deref().unwrap_or_default(),
+        );
+
+        for (marker, line) in diff_lines(removed, added) {
+            let _ = writeln!(output, "{} {}", marker, line);
+        }
+    }
+
+    for (err, diff) in errors {
+        let marker = if diff < 0 { '-' } else { '+' };
+        let _ = writeln!(output, "{} Error: {:?}", marker, err);
+    }
+}
+
+/// Computes a line diff between two texts from their longest common subsequence,
+/// lines only present in `old` are marked with `-` and ones only present in `new`
+/// are marked with `+`
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<(char, &'a str)> {
+    let (old, new): (Vec<_>, Vec<_>) = (old.lines().collect(), new.lines().collect());
+
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j, mut lines) = (0, 0, Vec::with_capacity(old.len().max(new.len())));
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|&line| ('-', line)));
+    lines.extend(new[j..].iter().map(|&line| ('+', line)));
+
+    lines
+}
@@ -1,19 +1,29 @@
-use crate::repr::{
-    basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, FuncId, InstId, Instruction,
+use crate::{
+    dataflow::Program,
+    repr::{
+        basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, ConstId, Constant,
+        FuncId, InstId, Instruction, ModuleMeta, Span,
+    },
 };
 use differential_dataflow::{
     difference::{Abelian, Semigroup},
     input::{Input, InputSession},
     lattice::Lattice,
     operators::{
-        arrange::{ArrangeByKey, TraceAgent},
+        arrange::{ArrangeByKey, ArrangeBySelf, Arranged, TraceAgent},
         Threshold,
     },
-    trace::implementations::ord::OrdValSpine,
-    ExchangeData,
+    trace::{
+        implementations::ord::{OrdKeySpine, OrdValSpine},
+        TraceReader,
+    },
+    Collection, ExchangeData,
 };
 use std::fmt::Debug;
-use timely::{dataflow::Scope, progress::Timestamp};
+use timely::{
+    dataflow::Scope,
+    progress::{frontier::AntichainRef, Timestamp},
+};
 
 pub struct InputManager<T, R>
 where
@@ -28,6 +38,21 @@ where
 
     pub functions: InputSession<T, (FuncId, FunctionDesc), R>,
     pub function_trace: TraceAgent<OrdValSpine<FuncId, FunctionDesc, T, R>>,
+
+    /// The contents of every pooled constant referenced by the program
+    pub constants: InputSession<T, (ConstId, Constant), R>,
+    pub constant_trace: TraceAgent<OrdValSpine<ConstId, Constant, T, R>>,
+
+    pub modules: InputSession<T, ModuleMeta, R>,
+    pub module_trace: TraceAgent<OrdKeySpine<ModuleMeta, T, R>>,
+
+    /// Source spans are kept as side collections keyed by the ids of instructions and
+    /// blocks, so they're preserved by any pass that keeps those ids intact
+    pub instruction_spans: InputSession<T, (InstId, Span), R>,
+    pub instruction_span_trace: TraceAgent<OrdValSpine<InstId, Span, T, R>>,
+
+    pub terminator_spans: InputSession<T, (BasicBlockId, Span), R>,
+    pub terminator_span_trace: TraceAgent<OrdValSpine<BasicBlockId, Span, T, R>>,
 }
 
 impl<T, R> InputManager<T, R>
@@ -46,12 +71,25 @@ where
         let (basic_blocks, basic_block_trace) =
             scope.new_collection::<(BasicBlockId, BasicBlockDesc), R>();
         let (functions, function_trace) = scope.new_collection::<(FuncId, FunctionDesc), R>();
+        let (constants, constant_trace) = scope.new_collection::<(ConstId, Constant), R>();
+        let (modules, module_trace) = scope.new_collection::<ModuleMeta, R>();
+        let (instruction_spans, instruction_span_trace) =
+            scope.new_collection::<(InstId, Span), R>();
+        let (terminator_spans, terminator_span_trace) =
+            scope.new_collection::<(BasicBlockId, Span), R>();
 
         // TODO: Exchange more intelligently to put all blocks & instructions for
         //       a given function onto the same worker
         let instruction_trace = instruction_trace.distinct_core().arrange_by_key().trace;
         let basic_block_trace = basic_block_trace.distinct_core().arrange_by_key().trace;
         let function_trace = function_trace.distinct_core().arrange_by_key().trace;
+        let constant_trace = constant_trace.distinct_core().arrange_by_key().trace;
+        let module_trace = module_trace.distinct_core().arrange_by_self().trace;
+        let instruction_span_trace = instruction_span_trace
+            .distinct_core()
+            .arrange_by_key()
+            .trace;
+        let terminator_span_trace = terminator_span_trace.distinct_core().arrange_by_key().trace;
 
         Self {
             instructions,
@@ -60,9 +98,61 @@ where
             basic_block_trace,
             functions,
             function_trace,
+            constants,
+            constant_trace,
+            modules,
+            module_trace,
+            instruction_spans,
+            instruction_span_trace,
+            terminator_spans,
+            terminator_span_trace,
         }
     }
 
+    /// Imports every program input trace into the given scope, the imported
+    /// arrangements share the input's indices instead of building their own
+    pub fn import<S>(&mut self, scope: &mut S) -> ImportedProgram<S, R>
+    where
+        S: Scope<Timestamp = T>,
+    {
+        ImportedProgram {
+            instructions: self.instruction_trace.import(scope),
+            basic_blocks: self.basic_block_trace.import(scope),
+            functions: self.function_trace.import(scope),
+            constants: self.constant_trace.import(scope),
+        }
+    }
+
+    /// Imports the program held by the input traces into the given scope
+    pub fn import_program<S>(&mut self, scope: &mut S) -> Program<S, R>
+    where
+        S: Scope<Timestamp = T>,
+    {
+        self.import(scope).program()
+    }
+
+    /// Imports the instruction and terminator spans into the given scope
+    #[allow(clippy::type_complexity)]
+    pub fn import_spans<S>(
+        &mut self,
+        scope: &mut S,
+    ) -> (
+        Collection<S, (InstId, Span), R>,
+        Collection<S, (BasicBlockId, Span), R>,
+    )
+    where
+        S: Scope<Timestamp = T>,
+    {
+        (
+            self.instruction_span_trace
+                .import(scope)
+                .as_collection(|&inst, &span| (inst, span)),
+            self.terminator_span_trace
+                .import(scope)
+                .as_collection(|&block, &span| (block, span)),
+        )
+    }
+
     pub fn advance_to(&mut self, time: T)
     where
         T: Debug + Clone,
@@ -75,14 +165,121 @@ where
         self.basic_blocks.advance_to(time.clone());
         self.basic_blocks.flush();
 
-        self.functions.advance_to(time);
+        self.functions.advance_to(time.clone());
         self.functions.flush();
+
+        self.constants.advance_to(time.clone());
+        self.constants.flush();
+
+        self.modules.advance_to(time.clone());
+        self.modules.flush();
+
+        self.instruction_spans.advance_to(time.clone());
+        self.instruction_spans.flush();
+
+        self.terminator_spans.advance_to(time);
+        self.terminator_spans.flush();
+    }
+
+    /// Allows every input trace to compact updates from before the given frontier
+    pub fn compact(&mut self, frontier: AntichainRef<'_, T>) {
+        tracing::info!("compacting input traces");
+
+        self.instruction_trace.set_logical_compaction(frontier);
+        self.instruction_trace.set_physical_compaction(frontier);
+
+        self.basic_block_trace.set_logical_compaction(frontier);
+        self.basic_block_trace.set_physical_compaction(frontier);
+
+        self.function_trace.set_logical_compaction(frontier);
+        self.function_trace.set_physical_compaction(frontier);
+
+        self.constant_trace.set_logical_compaction(frontier);
+        self.constant_trace.set_physical_compaction(frontier);
+
+        self.module_trace.set_logical_compaction(frontier);
+        self.module_trace.set_physical_compaction(frontier);
+
+        self.instruction_span_trace.set_logical_compaction(frontier);
+        self.instruction_span_trace
+            .set_physical_compaction(frontier);
+
+        self.terminator_span_trace.set_logical_compaction(frontier);
+        self.terminator_span_trace.set_physical_compaction(frontier);
     }
 
     pub fn time(&self) -> &T {
         debug_assert_eq!(self.instructions.time(), self.basic_blocks.time());
         debug_assert_eq!(self.instructions.time(), self.functions.time());
+        debug_assert_eq!(self.instructions.time(), self.constants.time());
+        debug_assert_eq!(self.instructions.time(), self.modules.time());
 
         self.instructions.time()
     }
 }
+
+/// The input traces of an [`InputManager`] imported into a dataflow
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct ImportedProgram<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Semigroup,
+{
+    pub instructions: Arranged<S, TraceAgent<OrdValSpine<InstId, Instruction, S::Timestamp, R>>>,
+    pub basic_blocks:
+        Arranged<S, TraceAgent<OrdValSpine<BasicBlockId, BasicBlockDesc, S::Timestamp, R>>>,
+    pub functions: Arranged<S, TraceAgent<OrdValSpine<FuncId, FunctionDesc, S::Timestamp, R>>>,
+    pub constants: Arranged<S, TraceAgent<OrdValSpine<ConstId, Constant, S::Timestamp, R>>>,
+}
+
+impl<S, R> ImportedProgram<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Semigroup,
+{
+    pub fn instructions(&self) -> Collection<S, (InstId, Instruction), R> {
+        self.instructions
+            .as_collection(|&id, inst| (id, inst.clone()))
+    }
+
+    pub fn basic_blocks(&self) -> Collection<S, (BasicBlockId, BasicBlockDesc), R> {
+        self.basic_blocks
+            .as_collection(|&block, desc| (block, desc.clone()))
+    }
+
+    pub fn functions(&self) -> Collection<S, (FuncId, FunctionDesc), R> {
+        self.functions
+            .as_collection(|&func, desc| (func, desc.clone()))
+    }
+
+    pub fn constants(&self) -> Collection<S, (ConstId, Constant), R> {
+        self.constants
+            .as_collection(|&id, constant| (id, constant.clone()))
+    }
+
+    /// Splits the imported blocks and functions into the collections of a [`Program`]
+    pub fn program(&self) -> Program<S, R> {
+        Program::new(
+            self.instructions(),
+            self.basic_blocks.flat_map_ref(|&block, desc| {
+                desc.instructions
+                    .clone()
+                    .into_iter()
+                    .map(move |inst| (inst, block))
+            }),
+            self.basic_blocks
+                .as_collection(|&block, desc| (block, desc.terminator.clone())),
+            self.basic_blocks(),
+            self.functions.flat_map_ref(|&func, desc| {
+                desc.basic_blocks
+                    .clone()
+                    .into_iter()
+                    .map(move |block| (block, func))
+            }),
+            self.functions(),
+        )
+    }
+}
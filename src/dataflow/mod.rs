@@ -1,16 +1,57 @@
+mod budget;
+mod cardinality;
+mod delta;
+mod effects;
+mod extraction;
 mod input_manager;
+mod partitioning;
 mod program;
+mod sanitize;
+mod shared;
+mod stats;
 mod trace_manager;
 mod translate;
 
 pub mod algorithms;
+pub mod analysis;
+pub mod call_graph;
 pub mod operators;
+pub mod panics;
+pub mod recording;
 
-pub use input_manager::InputManager;
-pub use program::{Program, ProgramVariable};
-pub use trace_manager::TraceManager;
+pub use budget::{block_counts, instruction_counts, Budget, BudgetExceeded, BudgetKind};
+pub use cardinality::{instruction_functions, with_functions, Cardinalities, JoinOrder};
+pub use delta::{trace_changes, Changes, IrDelta};
+pub use effects::{effect_edges, EffectEdge, EffectTarget};
+pub use extraction::{ExtractedItem, ExtractionDisplay, EXTRACTION_DISPLAY_VAR};
+pub use input_manager::{ImportedProgram, InputManager};
+pub use partitioning::{function_worker, partition_by_function, Partitioning};
+pub use program::{ArrangedProgram, Program, ProgramTrace, ProgramVariable};
+pub use sanitize::{function_costs, sanitize, verify_program, SanitizerDiagnostic};
+pub use shared::Shared;
+pub use stats::{
+    opt_summaries, pass_stats, DisplayOptSummary, EpochTimestamp, InstructionChange, OptSummary,
+    PassStats,
+};
+pub use trace_manager::{KeyTraceHandle, TraceHandle, TraceManager, ValTraceHandle};
 pub use translate::translate;
 
+use differential_dataflow::{
+    difference::{Abelian, Multiply},
+    ExchangeData,
+};
+
+/// The difference type the pipeline is instantiated with
 pub type Diff = isize;
 
+/// The bounds every operator places on its difference type, satisfied by any ring
+/// of integers like [`Diff`] or `i32`
+///
+/// Collections that only ever need to know whether a value is present can use the
+/// [`Present`](operators::Present) semiring instead, which isn't a [`Difference`]
+/// since it can't be negated
+pub trait Difference: Abelian + ExchangeData + Multiply<Output = Self> + From<i8> {}
+
+impl<R> Difference for R where R: Abelian + ExchangeData + Multiply<Output = R> + From<i8> {}
+
 pub type Time = usize;
@@ -1,17 +1,21 @@
 use crate::{
     dataflow::{
-        operators::{CollectCastable, CollectDeclarations, CountExt, FilterMap},
-        Program,
+        algorithms::propagate::least_label_propagation,
+        operators::{CollectCastable, CollectDeclarations, CollectUsages, CountExt, FilterMap},
+        Difference, EffectTarget, Program,
     },
-    repr::{function::FunctionDesc, instruction::Call, terminator::Return, InstructionExt},
+    optimize::fuel::Fuel,
+    repr::{function::FunctionDesc, instruction::Call, terminator::Return, InstId},
 };
 use differential_dataflow::{
-    algorithms::graphs::propagate,
-    difference::{Abelian, Multiply},
     lattice::Lattice,
-    operators::{Consolidate, Iterate, Join, Reduce, Threshold},
-    ExchangeData,
+    operators::{
+        arrange::{ArrangeByKey, ArrangeBySelf},
+        Consolidate, Iterate, Join, JoinCore, Reduce, Threshold,
+    },
+    Collection,
 };
+use std::iter;
 use timely::dataflow::Scope;
 
 pub trait Cleanup {
@@ -27,177 +31,10 @@ impl<S, R> Cleanup for Program<S, R>
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     fn cleanup(&self) -> Self {
-        // TODO: Rewrite as one single `.scoped()` using `SemigroupVariable`s that's mutually
-        //       recursive between the set of used instructions, blocks and functions. Maybe
-        //       use `Present` as the scope's inner difference type, ask Nami
-        // TODO: Start with a set of desired functions
-        // TODO: Only include reachable return statements
-        // TODO: Update `FunctionMeta`s
-        // TODO: Update `BasicBlockMeta`s
-        let program = self.instructions.scope().region_named("Cleanup", |region| {
-            let program = self.enter_region(region);
-
-            // TODO: Filter for reachable returns
-            let returned_vars = program.block_terminators.collect_castable::<Return>();
-            let declared_vars = program.instructions.collect_declarations();
-
-            // The instructions required for the program to be valid
-            let required_instructions = declared_vars
-                .semijoin(&returned_vars.filter_map(|(_, ret)| ret.returned_var()))
-                .map(|(_, inst)| inst)
-                .iterate(|required| {
-                    let instructions = program.instructions.enter(&required.scope());
-                    let declared_vars = declared_vars.enter(&required.scope());
-
-                    let used_vars = instructions
-                        .semijoin(&required)
-                        .flat_map(|(_, inst)| inst.used_vars().into_iter());
-
-                    declared_vars
-                        .semijoin(&used_vars)
-                        .map(|(_, inst)| inst)
-                        .concat(&required)
-                        .distinct_core()
-                });
-
-            // The blocks required for the program to be valid
-            let required_blocks = returned_vars
-                .map(|(block, _)| block)
-                .concat(
-                    &program
-                        .block_instructions
-                        .semijoin(&required_instructions)
-                        .map(|(_, block)| block),
-                )
-                .iterate(|blocks| {
-                    let terminators = program
-                        .block_terminators
-                        .enter(&blocks.scope())
-                        .semijoin(&blocks);
-
-                    terminators
-                        .flat_map(|(_, term)| term.jump_targets().into_iter())
-                        .concat(&blocks)
-                        .distinct_core()
-                });
-
-            // The functions required for program execution
-            let required_functions = program
-                .function_blocks
-                .semijoin(&required_blocks)
-                .map(|(_, func)| func)
-                .iterate(|funcs| {
-                    let function_blocks = program
-                        .function_blocks
-                        .enter(&funcs.scope())
-                        .map(|(block, func)| (func, block))
-                        .semijoin(&funcs)
-                        .map(|(_, block)| block);
-
-                    let inst_ids = program
-                        .block_instructions
-                        .enter(&funcs.scope())
-                        .map(|(inst, block)| (block, inst))
-                        .semijoin(&function_blocks)
-                        .map(|(_, inst)| inst);
-
-                    program
-                        .instructions
-                        .enter(&funcs.scope())
-                        .semijoin(&inst_ids)
-                        .collect_castable::<Call>()
-                        .map(|(_, call)| call.func)
-                        .concat(&funcs)
-                        .distinct_core()
-                });
-
-            let block_instructions = program.block_instructions.semijoin(&required_instructions);
-            let agg_inst = block_instructions
-                .consolidate()
-                .map(|(inst, block)| (block, inst))
-                .reduce(|_, instructions, output| {
-                    let instructions: Vec<_> = instructions.iter().map(|(&id, _)| id).collect();
-                    output.push((instructions, R::from(1)));
-                });
-
-            let block_descriptors = program
-                .block_descriptors
-                .semijoin(&required_blocks)
-                .join_map(&agg_inst, |&id, desc, instructions| {
-                    let mut desc = desc.clone();
-                    desc.instructions = instructions.to_owned();
-
-                    (id, desc)
-                });
-
-            let function_blocks = program.function_blocks.semijoin(&required_blocks);
-            let agg_blocks = function_blocks
-                .consolidate()
-                .map(|(block, func)| (func, block))
-                .reduce(|_, blocks, output| {
-                    let blocks: Vec<_> = blocks.iter().map(|(&id, _)| id).collect();
-                    output.push((blocks, R::from(1)));
-                });
-
-            let function_descriptors = program
-                .function_descriptors
-                .semijoin(&required_functions)
-                .join_map(&agg_blocks, |&id, desc, blocks| {
-                    let mut desc = desc.clone();
-                    desc.basic_blocks = blocks.to_owned();
-
-                    (id, desc)
-                });
-
-            Program {
-                instructions: program.instructions.semijoin(&required_instructions),
-                block_instructions,
-                block_terminators: program.block_terminators.semijoin(&required_blocks),
-                block_descriptors,
-                function_blocks,
-                function_descriptors,
-            }
-            .leave_region()
-        });
-
-        if cfg!(debug_assertions) {
-            self.instructions
-                .join(&self.block_instructions)
-                .antijoin(&program.instructions.map(|(id, _)| id))
-                .consolidate()
-                .inspect(|((inst_id, (inst, block_id)), _, _)| {
-                    tracing::trace!(
-                        inst = ?inst,
-                        "removed instruction {:?} from {:?}",
-                        inst_id,
-                        block_id,
-                    );
-                });
-
-            self.block_terminators
-                .antijoin(&program.block_terminators.map(|(id, _)| id))
-                .consolidate()
-                .inspect(|((block_id, term), _, _)| {
-                    tracing::trace!("removed terminator {:?} from {:?}", term, block_id);
-                });
-
-            self.function_blocks
-                .antijoin(&program.function_blocks.map(|(id, _)| id))
-                .consolidate()
-                .inspect(|((block_id, func_id), _, _)| {
-                    tracing::trace!("removed {:?} from {:?}", block_id, func_id);
-                });
-
-            self.function_descriptors
-                .antijoin(&self.function_descriptors.map(|(id, _)| id))
-                .consolidate()
-                .inspect(|((func_id, _desc), _, _)| tracing::trace!("removed func {:?}", func_id));
-        }
-
-        program
+        cleanup_with(self, None)
     }
 
     fn compact_basic_blocks(&self) -> Self {
@@ -301,26 +138,51 @@ where
                         output.push((blocks, R::from(1)))
                     });
 
-                // Update all function meta with the blocks they now contain
-                let function_descriptors =
-                    function_meta.join_map(&function_block_lists, |&func, meta, blocks| {
+                // Update all function meta with the blocks they now contain, external
+                // functions don't have any blocks to update
+                let function_descriptors = function_meta
+                    .join_map(&function_block_lists, |&func, meta, blocks| {
                         let meta = FunctionDesc {
                             basic_blocks: blocks.clone(),
                             ..meta.clone()
                         };
 
                         (func, meta)
-                    });
+                    })
+                    .concat(&function_meta.filter(|(_, meta)| meta.attributes.is_external()));
 
                 let instructions = program
                     .instructions
                     .semijoin(&block_instructions.map(|(inst, _)| inst));
 
-                let agg_inst = block_instructions
+                // The position of every instruction within its original block
+                let positions = program.block_descriptors.flat_map(|(_, desc)| {
+                    desc.instructions
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, inst)| (inst, index))
+                });
+
+                // Moved instructions come before the ones already within the block they
+                // were moved into and otherwise keep their original order, so effectful
+                // instructions are never reordered
+                let moved_instructions = rewritten_basic_blocks
+                    .join_map(&positions, |&inst, &block, &index| {
+                        (block, (0u8, index, inst))
+                    });
+                let kept_instructions = program
+                    .block_instructions
+                    .antijoin(&rewritten_basic_blocks.map(|(inst, _)| inst))
+                    .join_map(&positions, |&inst, &block, &index| {
+                        (block, (1u8, index, inst))
+                    });
+
+                let agg_inst = moved_instructions
+                    .concat(&kept_instructions)
                     .consolidate()
-                    .map(|(inst, block)| (block, inst))
                     .reduce(|_, instructions, output| {
-                        let instructions: Vec<_> = instructions.iter().map(|(&id, _)| id).collect();
+                        let instructions: Vec<_> =
+                            instructions.iter().map(|&(&(_, _, id), _)| id).collect();
                         output.push((instructions, R::from(1)));
                     });
 
@@ -366,7 +228,7 @@ where
 
                 // Propagate function ids along intra-block paths, all remaining blocks are reachable
                 let mut reachable_blocks =
-                    propagate::propagate(&value_edges, &value_roots).map(|(block, _)| block);
+                    least_label_propagation(&value_edges, &value_roots).map(|(block, _)| block);
 
                 let control_roots = program
                     .block_terminators
@@ -378,7 +240,7 @@ where
 
                 reachable_blocks = reachable_blocks
                     .concat(
-                        &propagate::propagate(&control_edges, &control_roots)
+                        &least_label_propagation(&control_edges, &control_roots)
                             .map(|(block, _)| block),
                     )
                     .distinct_core();
@@ -405,15 +267,19 @@ where
                         output.push((blocks, R::from(1)));
                     });
 
-                let function_descriptors =
-                    program
-                        .function_descriptors
-                        .join_map(&agg_blocks, |&func, meta, blocks| {
-                            let mut meta = meta.clone();
-                            meta.basic_blocks = blocks.clone();
+                let function_descriptors = program
+                    .function_descriptors
+                    .join_map(&agg_blocks, |&func, meta, blocks| {
+                        let mut meta = meta.clone();
+                        meta.basic_blocks = blocks.clone();
 
-                            (func, meta)
-                        });
+                        (func, meta)
+                    })
+                    .concat(
+                        &program
+                            .function_descriptors
+                            .filter(|(_, meta)| meta.attributes.is_external()),
+                    );
 
                 if cfg!(debug_assertions) {
                     program
@@ -439,6 +305,263 @@ where
     }
 }
 
+/// Removes unused instructions like [`Cleanup::cleanup()`], but only removes as many
+/// of them as there's fuel for, see [`Fuel`]
+///
+/// Instructions that there's no fuel to remove are kept along with everything they
+/// use, removing unused blocks and functions doesn't consume any fuel
+pub fn cleanup_with_fuel<S, R>(program: &Program<S, R>, fuel: &mut Fuel<S, R>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    cleanup_with(program, Some(fuel))
+}
+
+fn cleanup_with<S, R>(input: &Program<S, R>, fuel: Option<&mut Fuel<S, R>>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    // TODO: Rewrite as one single `.scoped()` using `SemigroupVariable`s that's mutually
+    //       recursive between the set of used instructions, blocks and functions. Maybe
+    //       use `Present` as the scope's inner difference type, ask Nami
+    // TODO: Start with a set of desired functions
+    // TODO: Only include reachable return statements
+    // TODO: Update `FunctionMeta`s
+    // TODO: Update `BasicBlockMeta`s
+    let program = input
+        .instructions
+        .scope()
+        .region_named("Cleanup", |region| {
+            let program = input.enter_region(region);
+
+            // TODO: Filter for reachable returns
+            let returned_vars = program.block_terminators.collect_castable::<Return>();
+            let declared_vars = program.instructions.collect_declarations();
+
+            // Effectful instructions are kept even if nothing uses their results, every
+            // instruction a terminator transitively depends on the effects of is required
+            let effect_edges = program.effect_edges().map(|edge| (edge.to, edge.from));
+            let effectful_instructions = effect_edges
+                .filter(|(to, _)| matches!(to, EffectTarget::Terminator(_)))
+                .map(|(_, from)| from)
+                .iterate(|effectful| {
+                    let edges = effect_edges.enter(&effectful.scope());
+
+                    edges
+                        .semijoin(&effectful.map(EffectTarget::Instruction))
+                        .map(|(_, from)| from)
+                        .concat(effectful)
+                        .distinct_core()
+                });
+
+            // The def-use relations are arranged once outside of the loop so that each
+            // iteration only joins ids instead of rearranging every instruction
+            let declarations = declared_vars.arrange_by_key();
+            let uses = program
+                .instructions
+                .collect_usages()
+                .map(|(var, inst)| (inst, var))
+                .arrange_by_key();
+
+            // Every instruction that the given ones transitively use
+            let with_dependencies = |roots: &Collection<_, InstId, R>| {
+                roots.iterate(|required| {
+                    let (declarations, uses) = (
+                        declarations.enter(&required.scope()),
+                        uses.enter(&required.scope()),
+                    );
+
+                    required
+                        .arrange_by_self()
+                        .join_core(&uses, |_inst, &(), var| iter::once(var.clone()))
+                        .arrange_by_self()
+                        .join_core(&declarations, |_var, &(), &inst| iter::once(inst))
+                        .concat(&required)
+                        .distinct_core()
+                })
+            };
+
+            // The instructions required for the program to be valid
+            let mut required_instructions = with_dependencies(
+                &declared_vars
+                    .semijoin(&returned_vars.flat_map(|(_, ret)| ret.returned_vars()))
+                    .map(|(_, inst)| inst)
+                    .concat(&effectful_instructions),
+            );
+
+            if let Some(fuel) = fuel {
+                let mut region_fuel = fuel.enter_region(region);
+
+                let unused = program
+                    .instructions
+                    .antijoin(&required_instructions)
+                    .map(|(inst, _)| inst);
+                let removed = region_fuel.consume(&unused);
+
+                required_instructions = with_dependencies(
+                    &required_instructions.concat(&unused.concat(&removed.negate())),
+                );
+                *fuel = region_fuel.leave_region();
+            }
+
+            // Exported functions can be called from outside of the program, so
+            // they're always kept along with everything they call
+            let exported_functions = program
+                .function_descriptors
+                .filter(|(_, desc)| desc.attributes.is_exported());
+
+            // The blocks required for the program to be valid
+            let required_blocks = returned_vars
+                .map(|(block, _)| block)
+                .concat(&exported_functions.map(|(_, desc)| desc.entry))
+                .concat(
+                    &program
+                        .block_instructions
+                        .semijoin(&required_instructions)
+                        .map(|(_, block)| block),
+                )
+                .iterate(|blocks| {
+                    let terminators = program
+                        .block_terminators
+                        .enter(&blocks.scope())
+                        .semijoin(&blocks);
+
+                    terminators
+                        .flat_map(|(_, term)| term.jump_targets().into_iter())
+                        .concat(&blocks)
+                        .distinct_core()
+                });
+
+            // The functions required for program execution
+            let required_functions = program
+                .function_blocks
+                .semijoin(&required_blocks)
+                .map(|(_, func)| func)
+                .concat(&exported_functions.map(|(func, _)| func))
+                .iterate(|funcs| {
+                    let function_blocks = program
+                        .function_blocks
+                        .enter(&funcs.scope())
+                        .map(|(block, func)| (func, block))
+                        .semijoin(&funcs)
+                        .map(|(_, block)| block);
+
+                    let inst_ids = program
+                        .block_instructions
+                        .enter(&funcs.scope())
+                        .map(|(inst, block)| (block, inst))
+                        .semijoin(&function_blocks)
+                        .map(|(_, inst)| inst);
+
+                    program
+                        .instructions
+                        .enter(&funcs.scope())
+                        .semijoin(&inst_ids)
+                        .collect_castable::<Call>()
+                        .map(|(_, call)| call.func)
+                        .concat(&funcs)
+                        .distinct_core()
+                });
+
+            let block_instructions = program.block_instructions.semijoin(&required_instructions);
+            let agg_inst = block_instructions
+                .consolidate()
+                .map(|(inst, block)| (block, inst))
+                .reduce(|_, instructions, output| {
+                    let instructions: Vec<_> = instructions.iter().map(|(&id, _)| id).collect();
+                    output.push((instructions, R::from(1)));
+                });
+
+            // Removing instructions mustn't reorder the remaining ones, especially
+            // effectful ones, so the block's original order is kept
+            let block_descriptors = program
+                .block_descriptors
+                .semijoin(&required_blocks)
+                .join_map(&agg_inst, |&id, desc, instructions| {
+                    let mut desc = desc.clone();
+                    desc.instructions
+                        .retain(|inst| instructions.binary_search(inst).is_ok());
+
+                    (id, desc)
+                });
+
+            let function_blocks = program.function_blocks.semijoin(&required_blocks);
+            let agg_blocks = function_blocks
+                .consolidate()
+                .map(|(block, func)| (func, block))
+                .reduce(|_, blocks, output| {
+                    let blocks: Vec<_> = blocks.iter().map(|(&id, _)| id).collect();
+                    output.push((blocks, R::from(1)));
+                });
+
+            // External functions are kept as long as they're called, but they have no
+            // blocks to be rebuilt from
+            let required_descriptors = program.function_descriptors.semijoin(&required_functions);
+            let function_descriptors = required_descriptors
+                .join_map(&agg_blocks, |&id, desc, blocks| {
+                    let mut desc = desc.clone();
+                    desc.basic_blocks = blocks.to_owned();
+
+                    (id, desc)
+                })
+                .concat(&required_descriptors.filter(|(_, desc)| desc.attributes.is_external()));
+
+            Program {
+                instructions: program.instructions.semijoin(&required_instructions),
+                block_instructions,
+                block_terminators: program.block_terminators.semijoin(&required_blocks),
+                block_descriptors,
+                function_blocks,
+                function_descriptors,
+            }
+            .leave_region()
+        });
+
+    if cfg!(debug_assertions) {
+        input
+            .instructions
+            .join(&input.block_instructions)
+            .antijoin(&program.instructions.map(|(id, _)| id))
+            .consolidate()
+            .inspect(|((inst_id, (inst, block_id)), _, _)| {
+                tracing::trace!(
+                    inst = ?inst,
+                    "removed instruction {:?} from {:?}",
+                    inst_id,
+                    block_id,
+                );
+            });
+
+        input
+            .block_terminators
+            .antijoin(&program.block_terminators.map(|(id, _)| id))
+            .consolidate()
+            .inspect(|((block_id, term), _, _)| {
+                tracing::trace!("removed terminator {:?} from {:?}", term, block_id);
+            });
+
+        input
+            .function_blocks
+            .antijoin(&program.function_blocks.map(|(id, _)| id))
+            .consolidate()
+            .inspect(|((block_id, func_id), _, _)| {
+                tracing::trace!("removed {:?} from {:?}", block_id, func_id);
+            });
+
+        input
+            .function_descriptors
+            .antijoin(&input.function_descriptors.map(|(id, _)| id))
+            .consolidate()
+            .inspect(|((func_id, _desc), _, _)| tracing::trace!("removed func {:?}", func_id));
+    }
+
+    program
+}
+
 // TODO
 // fn eliminate_unreachable_blocks<S, R, A1, A2>(
 //     scope: &mut S,
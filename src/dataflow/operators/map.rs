@@ -4,27 +4,24 @@ use timely::{
     Data,
 };
 
-pub trait MapExt<D1> {
-    type Output<D2>;
+pub trait MapExt<D1, D2> {
+    type Output;
 
-    fn map_named<D2, L>(&self, name: &str, logic: L) -> Self::Output<D2>
+    fn map_named<L>(&self, name: &str, logic: L) -> Self::Output
     where
-        D1: Data,
-        D2: Data,
         L: FnMut(D1) -> D2 + 'static;
 }
 
-impl<S, D1> MapExt<D1> for Stream<S, D1>
+impl<S, D1, D2> MapExt<D1, D2> for Stream<S, D1>
 where
     S: Scope,
     D1: Data,
+    D2: Data,
 {
-    type Output<D2> = Stream<S, D2>;
+    type Output = Stream<S, D2>;
 
-    fn map_named<D2, L>(&self, name: &str, mut logic: L) -> Self::Output<D2>
+    fn map_named<L>(&self, name: &str, mut logic: L) -> Self::Output
     where
-        D1: Data,
-        D2: Data,
         L: FnMut(D1) -> D2 + 'static,
     {
         let mut buffer = Vec::new();
@@ -43,18 +40,17 @@ where
     }
 }
 
-impl<S, D1, R> MapExt<D1> for Collection<S, D1, R>
+impl<S, D1, D2, R> MapExt<D1, D2> for Collection<S, D1, R>
 where
     S: Scope,
     D1: Data,
+    D2: Data,
     R: Semigroup,
 {
-    type Output<D2> = Collection<S, D2, R>;
+    type Output = Collection<S, D2, R>;
 
-    fn map_named<D2, L>(&self, name: &str, mut logic: L) -> Self::Output<D2>
+    fn map_named<L>(&self, name: &str, mut logic: L) -> Self::Output
     where
-        D1: Data,
-        D2: Data,
         L: FnMut(D1) -> D2 + 'static,
     {
         self.inner
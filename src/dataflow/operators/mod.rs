@@ -18,8 +18,11 @@ mod map;
 mod max;
 mod min;
 mod partition;
+mod present;
 mod reverse;
 mod split;
+#[cfg(feature = "nightly")]
+mod split_by;
 mod threshold;
 mod union_find;
 
@@ -27,7 +30,7 @@ pub use apply_seq::ApplySequenced;
 pub use arrange::{ArrangeByKeyExt, ArrangeBySelfExt};
 pub use bounded_loop::BoundedLoop;
 pub use buffered_flat_map::BufferedFlatMap;
-pub use cleanup::Cleanup;
+pub use cleanup::{cleanup_with_fuel, Cleanup};
 pub use collect::{
     CollectCastable, CollectDeclarations, CollectUsages, CollectValues, CollectVariableTypes,
 };
@@ -45,7 +48,10 @@ pub use map::MapExt;
 pub use max::Max;
 pub use min::Min;
 pub use partition::PartitionExt;
+pub use present::{Present, PresentExt};
 pub use reverse::Reverse;
-pub use split::{FilterSplit, FlatSplit, Split, SplitBy};
+pub use split::{FilterSplit, FlatSplit, Split};
+#[cfg(feature = "nightly")]
+pub use split_by::{SplitBy, Splittable};
 pub use threshold::ThresholdExt;
 pub use union_find::UnionFind;
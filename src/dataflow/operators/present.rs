@@ -0,0 +1,68 @@
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    difference::{Multiply, Semigroup},
+    AsCollection, Collection,
+};
+use timely::{
+    dataflow::{operators::Map, Scope},
+    Data,
+};
+
+/// A difference that only records a value as being present within a collection
+///
+/// Arrangements of present collections don't have to store any differences at all,
+/// which makes it the cheapest option for analyses that only need set semantics.
+/// It has no zero and can't be negated, so anything that has to retract values
+/// (like [`Difference`](crate::dataflow::Difference) bounded operators) won't
+/// accept it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Present;
+
+impl Semigroup for Present {
+    fn is_zero(&self) -> bool {
+        false
+    }
+
+    fn plus_equals(&mut self, _rhs: &Self) {}
+}
+
+impl<R> Multiply<R> for Present {
+    type Output = Self;
+
+    fn multiply(self, _rhs: &R) -> Self::Output {
+        self
+    }
+}
+
+pub trait PresentExt {
+    type Output;
+
+    /// Forgets the multiplicity of every value, keeping only the fact that it's present
+    ///
+    /// The collection must never retract any of its values since a retraction would
+    /// be turned into another insertion, debug builds panic on any retraction
+    fn present(&self) -> Self::Output;
+}
+
+impl<S, D, R> PresentExt for Collection<S, D, R>
+where
+    S: Scope,
+    D: Data,
+    R: Semigroup + PartialOrd + From<i8>,
+{
+    type Output = Collection<S, D, Present>;
+
+    fn present(&self) -> Self::Output {
+        self.inner
+            .map(|(data, time, diff)| {
+                debug_assert!(
+                    diff > R::from(0),
+                    "retracted a value from a collection with set semantics",
+                );
+
+                (data, time, Present)
+            })
+            .as_collection()
+    }
+}
@@ -0,0 +1,212 @@
+//! Context for panics that happen within the pipeline
+//!
+//! Panics inside of dataflow closures normally only report the closure they came
+//! from, which says very little about what was being optimized. Code that's about
+//! to run something panic-prone records what it's doing with [`with_context()`],
+//! [`install_hook()`] then appends every active context to panic messages and
+//! [`catch()`] converts a panic into a [`PanicDiagnostic`] so that the caller can
+//! recover from it
+
+use crate::repr::{FuncId, InstId};
+use std::{
+    any::Any,
+    cell::RefCell,
+    fmt::{self, Display},
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+};
+
+thread_local! {
+    /// The contexts active on the current thread, innermost last
+    static CONTEXTS: RefCell<Vec<PanicContext>> = RefCell::new(Vec::new());
+
+    /// The innermost context of the most recent panic on the current thread
+    static PANIC_CONTEXT: RefCell<Option<PanicContext>> = RefCell::new(None);
+}
+
+/// What the pipeline was doing when a panic occurred
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct PanicContext {
+    /// The stage of the pipeline, like building or stepping its dataflows
+    pub stage: Option<&'static str>,
+    pub pass: Option<&'static str>,
+    /// The name of the rule within the pass, like a peephole rule
+    pub rule: Option<&'static str>,
+    pub function: Option<FuncId>,
+    pub instruction: Option<InstId>,
+}
+
+impl PanicContext {
+    pub const fn new() -> Self {
+        Self {
+            stage: None,
+            pass: None,
+            rule: None,
+            function: None,
+            instruction: None,
+        }
+    }
+
+    pub const fn stage(stage: &'static str) -> Self {
+        Self {
+            stage: Some(stage),
+            ..Self::new()
+        }
+    }
+
+    pub const fn pass(pass: &'static str) -> Self {
+        Self {
+            pass: Some(pass),
+            ..Self::new()
+        }
+    }
+
+    pub const fn with_pass(mut self, pass: &'static str) -> Self {
+        self.pass = Some(pass);
+        self
+    }
+
+    pub const fn with_rule(mut self, rule: &'static str) -> Self {
+        self.rule = Some(rule);
+        self
+    }
+
+    pub const fn with_function(mut self, function: FuncId) -> Self {
+        self.function = Some(function);
+        self
+    }
+
+    pub const fn with_instruction(mut self, instruction: InstId) -> Self {
+        self.instruction = Some(instruction);
+        self
+    }
+}
+
+impl Display for PanicContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::with_capacity(5);
+        if let Some(stage) = self.stage {
+            parts.push(stage.to_owned());
+        }
+        if let Some(pass) = self.pass {
+            parts.push(format!("pass `{}`", pass));
+        }
+        if let Some(rule) = self.rule {
+            parts.push(format!("rule `{}`", rule));
+        }
+        if let Some(function) = self.function {
+            parts.push(format!("function {:?}", function));
+        }
+        if let Some(instruction) = self.instruction {
+            parts.push(format!("instruction {:?}", instruction));
+        }
+
+        if parts.is_empty() {
+            f.write_str("running the pipeline")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+/// A panic that was caught by [`catch()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicDiagnostic {
+    /// The panic's message, if it had one
+    pub message: Option<String>,
+    /// The innermost context that was active when the panic occurred
+    pub context: PanicContext,
+}
+
+impl Display for PanicDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "panicked while {}: {}",
+            self.context,
+            self.message.as_deref().unwrap_or("<unknown panic payload>"),
+        )
+    }
+}
+
+impl std::error::Error for PanicDiagnostic {}
+
+/// Runs `func` with the given context active on the current thread
+pub fn with_context<F, T>(context: PanicContext, func: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            CONTEXTS.with(|contexts| contexts.borrow_mut().pop());
+        }
+    }
+
+    CONTEXTS.with(|contexts| contexts.borrow_mut().push(context));
+    let _guard = Guard;
+
+    func()
+}
+
+/// Returns the innermost context active on the current thread
+pub fn current_context() -> Option<PanicContext> {
+    CONTEXTS.with(|contexts| contexts.borrow().last().cloned())
+}
+
+/// Runs `func` with the given context active, converting any panic it causes into
+/// a [`PanicDiagnostic`]
+///
+/// Panics are still reported by the panic hook, so [`install_hook()`] should be
+/// called to get the diagnostic's context into the report as well
+pub fn catch<F, T>(context: PanicContext, func: F) -> Result<T, PanicDiagnostic>
+where
+    F: FnOnce() -> T,
+{
+    let fallback = context.clone();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| with_context(context, func)));
+
+    result.map_err(|payload| {
+        let context = PANIC_CONTEXT
+            .with(|panicked| panicked.borrow_mut().take())
+            .unwrap_or(fallback);
+
+        PanicDiagnostic {
+            message: payload_message(&*payload),
+            context,
+        }
+    })
+}
+
+/// Installs a panic hook that appends the contexts active on the panicking thread
+/// to the message of the previously installed hook, only the first call has any effect
+pub fn install_hook() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+
+            let contexts = CONTEXTS
+                .try_with(|contexts| contexts.borrow().clone())
+                .unwrap_or_default();
+            for context in contexts.iter().rev() {
+                eprintln!("note: panicked while {}", context);
+            }
+
+            let _ = PANIC_CONTEXT.try_with(|panicked| {
+                *panicked.borrow_mut() = contexts.last().cloned();
+            });
+        }));
+    });
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> Option<String> {
+    payload
+        .downcast_ref::<&str>()
+        .map(|&message| message.to_owned())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+}
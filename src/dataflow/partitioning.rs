@@ -0,0 +1,122 @@
+//! Placement of a program's data across the workers of a dataflow
+//!
+//! By default every operator exchanges its input by the hash of whatever it's keyed
+//! by, so the instructions, blocks and descriptors of a single function end up
+//! scattered over every worker. Partitioning by function routes every tuple to the
+//! worker owning the function it belongs to, which keeps the stateless stages of
+//! intraprocedural passes like folding and peephole rewrites on a single worker and
+//! leaves their output next to the rest of its function. Operators keyed by anything
+//! else still exchange their input, so interprocedural stages like merging functions
+//! or following the call graph keep joining across workers
+
+use crate::{
+    dataflow::{operators::ExchangeExt, Difference, Program},
+    repr::FuncId,
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+    Collection, ExchangeData, Hashable,
+};
+use timely::dataflow::Scope;
+
+/// How the data of a program is placed onto workers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Partitioning {
+    /// Data is left wherever the default hashing of each operator puts it
+    Hashed,
+    /// All data of a function is routed to the same worker before every
+    /// intraprocedural pass
+    ByFunction,
+}
+
+impl Partitioning {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Hashed => "hashed",
+            Self::ByFunction => "by-function",
+        }
+    }
+}
+
+impl Default for Partitioning {
+    fn default() -> Self {
+        Self::Hashed
+    }
+}
+
+/// The worker owning a function, the same placement that partitioned runtime inputs
+/// are loaded with
+pub fn function_worker(func: FuncId) -> u64 {
+    func.as_u64()
+}
+
+/// Routes every tuple of the program to the [worker](function_worker) owning the
+/// function it belongs to
+///
+/// Tuples that don't belong to any function are left where they are
+pub fn partition_by_function<S, R>(program: &Program<S, R>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let span = tracing::debug_span!("partitioning by function");
+    span.in_scope(|| {
+        // A block belonging to multiple functions is invalid, but it still only gets
+        // one owner so that routing it never duplicates anything
+        let block_owners = program
+            .function_blocks
+            .reduce(|_block, funcs, output| output.push((*funcs[0].0, R::from(1))));
+        let inst_owners = program
+            .block_instructions
+            .map(|(inst, block)| (block, inst))
+            .join_map(&block_owners, |_block, &inst, &func| (inst, func));
+
+        let block_instructions = route(
+            &program
+                .block_instructions
+                .map(|(inst, block)| (block, inst)),
+            &block_owners,
+        )
+        .map(|(block, inst)| (inst, block));
+
+        Program {
+            instructions: route(&program.instructions, &inst_owners),
+            block_instructions,
+            block_terminators: route(&program.block_terminators, &block_owners),
+            block_descriptors: route(&program.block_descriptors, &block_owners),
+            function_blocks: program
+                .function_blocks
+                .exchange_named("ExchangeFunctionBlocks", |&(_, func)| function_worker(func)),
+            function_descriptors: program
+                .function_descriptors
+                .exchange_named("ExchangeFunctionDescriptors", |&(func, _)| {
+                    function_worker(func)
+                }),
+        }
+    })
+}
+
+/// Moves every entry of `collection` to the worker owning its key's function
+fn route<S, K, V, R>(
+    collection: &Collection<S, (K, V), R>,
+    owners: &Collection<S, (K, FuncId), R>,
+) -> Collection<S, (K, V), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    K: ExchangeData + Hashable,
+    V: ExchangeData,
+    R: Difference,
+{
+    let owned = collection
+        .join_map(owners, |key, value, &func| {
+            (func, (key.clone(), value.clone()))
+        })
+        .exchange_named("ExchangeByFunction", |&(func, _)| function_worker(func))
+        .map(|(_, entry)| entry);
+    let unowned = collection.antijoin(&owners.map(|(key, _)| key));
+
+    owned.concat(&unowned)
+}
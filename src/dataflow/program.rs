@@ -1,8 +1,15 @@
 use std::panic::Location;
 
-use crate::repr::{
-    basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, FuncId, InstId, Instruction,
-    Terminator,
+use crate::{
+    dataflow::{
+        analysis::UseDef,
+        effects::{self, EffectEdge},
+        Difference,
+    },
+    repr::{
+        basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, FuncId, InstId,
+        Instruction, ModuleId, Terminator,
+    },
 };
 use differential_dataflow::{
     difference::{Abelian, Semigroup},
@@ -10,14 +17,14 @@ use differential_dataflow::{
     operators::{
         arrange::{ArrangeByKey, Arranged, TraceAgent},
         iterate::Variable,
-        Consolidate,
+        Consolidate, Join, Reduce,
     },
-    trace::implementations::ord::OrdValSpine,
+    trace::{implementations::ord::OrdValSpine, TraceReader},
     Collection, ExchangeData, Hashable,
 };
 use timely::{
     dataflow::{operators::probe::Handle, scopes::Child, Scope},
-    progress::{timestamp::Refines, Timestamp},
+    progress::{frontier::AntichainRef, timestamp::Refines, Timestamp},
 };
 
 #[derive(Clone)]
@@ -100,6 +107,100 @@ where
         }
     }
 
+    /// Merges the contents of two programs
+    pub fn concat(&self, other: &Self) -> Self {
+        Self {
+            instructions: self.instructions.concat(&other.instructions),
+            block_instructions: self.block_instructions.concat(&other.block_instructions),
+            block_terminators: self.block_terminators.concat(&other.block_terminators),
+            block_descriptors: self.block_descriptors.concat(&other.block_descriptors),
+            function_blocks: self.function_blocks.concat(&other.function_blocks),
+            function_descriptors: self
+                .function_descriptors
+                .concat(&other.function_descriptors),
+        }
+    }
+
+    /// The module each function belongs to
+    pub fn function_modules(&self) -> Collection<S, (FuncId, ModuleId), R> {
+        self.function_descriptors
+            .map(|(func, desc)| (func, desc.module))
+    }
+
+    /// The effect dependencies between the instructions of every block, see
+    /// [`effect_edges()`](crate::dataflow::effect_edges)
+    pub fn effect_edges(&self) -> Collection<S, EffectEdge, R>
+    where
+        S::Timestamp: Lattice,
+        R: Difference,
+    {
+        effects::effect_edges(self)
+    }
+
+    /// The arranged definitions and uses of every variable, see [`UseDef`]
+    pub fn use_def(&self) -> UseDef<S, R>
+    where
+        S::Timestamp: Lattice,
+        R: ExchangeData,
+    {
+        UseDef::new(self)
+    }
+
+    /// Collects a value for each instruction of every block, ordered by the
+    /// instruction's position within its block's descriptor
+    ///
+    /// `located` pairs every instruction with its block and the value to collect for
+    /// it. Passes like scheduling reorder a block's descriptor and the descriptor may
+    /// lag behind the instructions placed into the block, so any instruction missing
+    /// from it comes last in the order of its id. Blocks without instructions are
+    /// given an empty list
+    pub fn ordered_block_contents<V>(
+        &self,
+        located: &Collection<S, (InstId, (BasicBlockId, V)), R>,
+    ) -> Collection<S, (BasicBlockId, Vec<V>), R>
+    where
+        S::Timestamp: Lattice,
+        R: Difference,
+        V: ExchangeData,
+    {
+        let positions = self.block_descriptors.flat_map(|(block, desc)| {
+            desc.instructions
+                .into_iter()
+                .enumerate()
+                .map(move |(index, inst)| ((inst, block), index))
+        });
+
+        let located = located.map(|(inst, (block, value))| ((inst, block), value));
+        let contents = located
+            .join_map(&positions, |&(inst, block), value, &index| {
+                (block, (index, inst, value.clone()))
+            })
+            .concat(
+                &located
+                    .antijoin(&positions.map(|(listed, _)| listed))
+                    .map(|((inst, block), value)| (block, (usize::MAX, inst, value))),
+            )
+            .reduce(|_block, input, output| {
+                // Instruction ids are allocated in program order, so sorting by them
+                // recovers the original order of any instructions missing from the
+                // descriptor
+                let values: Vec<V> = input
+                    .iter()
+                    .map(|((_index, _inst, value), _diff)| value.clone())
+                    .collect();
+
+                output.push((values, R::from(1)));
+            });
+
+        contents.concat(
+            &self
+                .block_terminators
+                .map(|(block, _)| block)
+                .antijoin(&contents.map(|(block, _)| block))
+                .map(|block| (block, Vec::new())),
+        )
+    }
+
     pub fn probe(&self) -> Handle<S::Timestamp> {
         let mut handle = Handle::new();
         self.probe_with(&mut handle);
@@ -430,4 +531,22 @@ where
             function_descriptors: self.function_descriptors.import(scope),
         }
     }
+    /// Allows every trace to compact updates from before the given frontier
+    pub fn advance_by(&mut self, frontier: AntichainRef<'_, T>) {
+        self.instructions.set_logical_compaction(frontier);
+        self.block_instructions.set_logical_compaction(frontier);
+        self.block_terminators.set_logical_compaction(frontier);
+        self.block_descriptors.set_logical_compaction(frontier);
+        self.function_blocks.set_logical_compaction(frontier);
+        self.function_descriptors.set_logical_compaction(frontier);
+    }
+
+    pub fn distinguish_since(&mut self, frontier: AntichainRef<'_, T>) {
+        self.instructions.set_physical_compaction(frontier);
+        self.block_instructions.set_physical_compaction(frontier);
+        self.block_terminators.set_physical_compaction(frontier);
+        self.block_descriptors.set_physical_compaction(frontier);
+        self.function_blocks.set_physical_compaction(frontier);
+        self.function_descriptors.set_physical_compaction(frontier);
+    }
 }
@@ -0,0 +1,403 @@
+#![cfg(feature = "json")]
+
+//! Capture and replay of the updates given to an [`InputManager`]
+//!
+//! Bugs that only show up after a particular sequence of incremental updates are
+//! hard to reproduce without the front-end that produced them. An
+//! [`InputRecorder`] writes every update that reaches an input manager's traces
+//! along with its timestamp as one json object per line, and an [`InputReplay`]
+//! reads such a session back and feeds it into a fresh input manager time by time,
+//! so the same dataflow sees the same updates in the same epochs
+//!
+//! Updates are recorded from the input traces, so the recording holds exactly what
+//! the dataflows downstream of the input manager saw regardless of how the updates
+//! were made. Since [`Ident`](crate::repr::Ident)s are interner keys, the strings
+//! they resolve to are written out when the recording is
+//! [finished](InputRecorder::finish)
+
+use crate::{
+    dataflow::InputManager,
+    repr::{
+        basic_block::BasicBlockDesc, function::FunctionDesc, json, BasicBlockId, ConstId, Constant,
+        FuncId, InstId, Instruction, ModuleMeta, Span,
+    },
+};
+use differential_dataflow::{
+    difference::Semigroup, lattice::Lattice, operators::arrange::TraceAgent,
+    trace::implementations::ord::OrdValSpine, Collection, ExchangeData,
+};
+use lasso::{Key, ThreadedRodeo};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    rc::Rc,
+};
+use timely::{dataflow::Scope, order::PartialOrder, progress::Timestamp};
+
+/// A single update to one of an [`InputManager`]'s inputs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputUpdate {
+    Instruction((InstId, Instruction)),
+    BasicBlock((BasicBlockId, BasicBlockDesc)),
+    Function((FuncId, FunctionDesc)),
+    Constant((ConstId, Constant)),
+    Module(ModuleMeta),
+    InstructionSpan((InstId, Span)),
+    TerminatorSpan((BasicBlockId, Span)),
+}
+
+impl InputUpdate {
+    /// Gives the update to the matching input of `input` at the given time
+    pub fn apply<T, R>(self, input: &mut InputManager<T, R>, time: T, diff: R)
+    where
+        T: Timestamp + Lattice,
+        R: Semigroup + ExchangeData,
+    {
+        match self {
+            Self::Instruction(data) => input.instructions.update_at(data, time, diff),
+            Self::BasicBlock(data) => input.basic_blocks.update_at(data, time, diff),
+            Self::Function(data) => input.functions.update_at(data, time, diff),
+            Self::Constant(data) => input.constants.update_at(data, time, diff),
+            Self::Module(data) => input.modules.update_at(data, time, diff),
+            Self::InstructionSpan(data) => input.instruction_spans.update_at(data, time, diff),
+            Self::TerminatorSpan(data) => input.terminator_spans.update_at(data, time, diff),
+        }
+    }
+}
+
+/// A single line of a recording
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum Record<T, R> {
+    Update(InputUpdate, T, R),
+    /// Every interned string, where each string's index is its interner key
+    Strings(Vec<String>),
+}
+
+/// Writes the updates made to an [`InputManager`] to a writer, see the
+/// [module docs](self)
+///
+/// ```rust,ignore
+/// let recorder = InputRecorder::create("session.jsonl")?;
+/// worker.dataflow(|scope| recorder.record(scope, &mut handles.input));
+///
+/// // Feed the pipeline as usual
+///
+/// recorder.finish(context.interner())?;
+/// ```
+pub struct InputRecorder<W> {
+    state: Rc<RefCell<RecorderState<W>>>,
+}
+
+struct RecorderState<W> {
+    /// The recording's writer, taken once the recording is finished
+    writer: Option<W>,
+    /// The first error hit while writing, later updates are dropped
+    error: Option<io::Error>,
+}
+
+impl<W> RecorderState<W>
+where
+    W: Write,
+{
+    fn write<T, R>(&mut self, record: &Record<T, R>)
+    where
+        T: Serialize,
+        R: Serialize,
+    {
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Some(writer) = self.writer.as_mut() {
+            let result = json::to_writer(&mut *writer, record)
+                .map_err(io::Error::from)
+                .and_then(|()| writer.write_all(b"\n"));
+
+            if let Err(error) = result {
+                tracing::error!("failed to record an input update: {}", error);
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+impl InputRecorder<BufWriter<File>> {
+    /// Creates a recorder that writes to a new file at the given path
+    pub fn create<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        File::create(path).map(|file| Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W> InputRecorder<W>
+where
+    W: Write + 'static,
+{
+    pub fn new(writer: W) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(RecorderState {
+                writer: Some(writer),
+                error: None,
+            })),
+        }
+    }
+
+    /// Records every update made to `input` from now on
+    ///
+    /// The input's traces are imported into `scope`, which has to belong to the
+    /// same worker as the input manager. Updates are written as the worker
+    /// processes them
+    pub fn record<S, R>(&self, scope: &mut S, input: &mut InputManager<S::Timestamp, R>)
+    where
+        S: Scope,
+        S::Timestamp: Lattice + Serialize,
+        R: Semigroup + ExchangeData + Serialize,
+    {
+        tracing::info!("recording input updates");
+
+        self.record_trace(
+            scope,
+            &mut input.instruction_trace,
+            InputUpdate::Instruction,
+        );
+        self.record_trace(scope, &mut input.basic_block_trace, InputUpdate::BasicBlock);
+        self.record_trace(scope, &mut input.function_trace, InputUpdate::Function);
+        self.record_trace(scope, &mut input.constant_trace, InputUpdate::Constant);
+        self.record_trace(
+            scope,
+            &mut input.instruction_span_trace,
+            InputUpdate::InstructionSpan,
+        );
+        self.record_trace(
+            scope,
+            &mut input.terminator_span_trace,
+            InputUpdate::TerminatorSpan,
+        );
+
+        let modules = input
+            .module_trace
+            .import(scope)
+            .as_collection(|module, &()| module.clone());
+        self.record_collection(&modules, InputUpdate::Module);
+    }
+
+    fn record_trace<S, K, V, R>(
+        &self,
+        scope: &mut S,
+        trace: &mut TraceAgent<OrdValSpine<K, V, S::Timestamp, R>>,
+        update: fn((K, V)) -> InputUpdate,
+    ) where
+        S: Scope,
+        S::Timestamp: Lattice + Serialize,
+        K: ExchangeData,
+        V: ExchangeData,
+        R: Semigroup + ExchangeData + Serialize,
+    {
+        let collection = trace
+            .import(scope)
+            .as_collection(|key, val| (key.clone(), val.clone()));
+        self.record_collection(&collection, update);
+    }
+
+    fn record_collection<S, D, R>(
+        &self,
+        collection: &Collection<S, D, R>,
+        update: fn(D) -> InputUpdate,
+    ) where
+        S: Scope,
+        S::Timestamp: Serialize,
+        D: ExchangeData,
+        R: Semigroup + ExchangeData + Serialize,
+    {
+        let state = self.state.clone();
+        collection.inspect(move |(data, time, diff)| {
+            let record = Record::Update(update(data.clone()), time.clone(), diff.clone());
+            state.borrow_mut().write(&record);
+        });
+    }
+
+    /// Writes the interner's strings and flushes the recording, returning the
+    /// writer along with the first error hit while recording
+    ///
+    /// Updates made after the recording is finished aren't recorded
+    pub fn finish(self, interner: &ThreadedRodeo) -> io::Result<W> {
+        let mut state = self.state.borrow_mut();
+
+        let mut strings: Vec<_> = interner.iter().collect();
+        strings.sort_by_key(|&(key, _)| key.into_usize());
+        state.write::<(), ()>(&Record::Strings(
+            strings
+                .into_iter()
+                .map(|(_, string)| string.to_owned())
+                .collect(),
+        ));
+
+        if let Some(error) = state.error.take() {
+            return Err(error);
+        }
+
+        let mut writer = state
+            .writer
+            .take()
+            .expect("the recording's writer is only taken once it's finished");
+        writer.flush()?;
+
+        Ok(writer)
+    }
+}
+
+/// A recorded session of updates to an [`InputManager`], see the
+/// [module docs](self)
+///
+/// ```rust,ignore
+/// let replay = InputReplay::<Time, Diff>::open("session.jsonl")?;
+/// replay.replay_with(&mut handles.input, context.interner(), |input, &time| {
+///     input.advance_to(time + 1);
+///     while probe.less_than(input.time()) {
+///         worker.step();
+///     }
+/// })?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputReplay<T, R> {
+    strings: Vec<String>,
+    /// The recorded updates, sorted by time
+    updates: Vec<(InputUpdate, T, R)>,
+}
+
+impl<T, R> InputReplay<T, R>
+where
+    T: Timestamp + Lattice + Ord + DeserializeOwned,
+    R: Semigroup + ExchangeData + DeserializeOwned,
+{
+    pub fn open<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        Self::from_reader(BufReader::new(file)).map_err(io::Error::from)
+    }
+
+    /// Reads a recording written by an [`InputRecorder`]
+    pub fn from_reader<B>(reader: B) -> json::Result<Self>
+    where
+        B: BufRead,
+    {
+        let (mut strings, mut updates) = (Vec::new(), Vec::new());
+        for line in reader.lines() {
+            let line = line.map_err(json::Error::io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match json::from_str(&line)? {
+                Record::Update(update, time, diff) => updates.push((update, time, diff)),
+                Record::Strings(recorded) => strings = recorded,
+            }
+        }
+
+        // Updates within a time can go in any order, but times have to be given
+        // to the inputs in order
+        updates.sort_by(|(_, left, _), (_, right, _)| left.cmp(right));
+
+        Ok(Self { strings, updates })
+    }
+
+    /// The number of recorded updates
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Every time updates were recorded at, in order
+    pub fn times(&self) -> Vec<T> {
+        let mut times: Vec<T> = self
+            .updates
+            .iter()
+            .map(|(_, time, _)| time.clone())
+            .collect();
+        times.dedup();
+        times
+    }
+
+    /// Interns the recording's strings, the interner must either be empty or hold
+    /// the same strings the recording starts with so that the recorded keys resolve
+    /// to the same strings
+    pub fn load_strings(&self, interner: &ThreadedRodeo) -> json::Result<()> {
+        for (idx, string) in self.strings.iter().enumerate() {
+            let key = interner.get_or_intern(string);
+
+            if key.into_usize() != idx {
+                return Err(<json::Error as serde::de::Error>::custom(format!(
+                    "the string {:?} was given the key {} instead of {}, \
+                     recordings can only be replayed into an interner holding the same strings",
+                    string,
+                    key.into_usize(),
+                    idx,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gives every recorded update to `input` at its recorded time without
+    /// advancing the input, the inputs can't be behind the first recorded time
+    pub fn replay(
+        &self,
+        input: &mut InputManager<T, R>,
+        interner: &ThreadedRodeo,
+    ) -> json::Result<()>
+    where
+        T: Debug,
+    {
+        self.replay_with(input, interner, |_, _| {})
+    }
+
+    /// Replays the recording one time at a time, advancing `input` to each recorded
+    /// time before giving it that time's updates and calling `step` afterwards
+    ///
+    /// `step` is expected to advance the input past the given time and step the
+    /// worker until the dataflows have caught up, which reproduces the epochs of
+    /// the original session
+    pub fn replay_with<F>(
+        &self,
+        input: &mut InputManager<T, R>,
+        interner: &ThreadedRodeo,
+        mut step: F,
+    ) -> json::Result<()>
+    where
+        T: Debug,
+        F: FnMut(&mut InputManager<T, R>, &T),
+    {
+        self.load_strings(interner)?;
+
+        let span = tracing::info_span!("replaying input", updates = self.updates.len());
+        let _guard = span.enter();
+
+        let mut updates = self.updates.iter().peekable();
+        while let Some((_, time, _)) = updates.peek() {
+            let time = time.clone();
+            if input.time().less_than(&time) {
+                input.advance_to(time.clone());
+            }
+
+            while let Some((update, _, diff)) = updates.next_if(|(_, next, _)| *next == time) {
+                update.clone().apply(input, time.clone(), diff.clone());
+            }
+
+            step(input, &time);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+//! Per pass checks that catch the pass that broke or pessimized a program
+//!
+//! With many passes composed into a fixpoint, a broken or pessimized output can
+//! have come from any one of them. The sanitizer verifies the program and weighs
+//! every function with a [`CostModel`] both before and after each pass, reporting
+//! a [`SanitizerDiagnostic`] naming the pass whenever one introduces validity
+//! errors or makes a function more expensive. Each pass's output is verified once
+//! and reused as the baseline of the next pass, but that's still a full verification
+//! per pass, so it's only meant to be turned on while debugging passes
+
+use crate::{
+    dataflow::{Difference, Program},
+    optimize::cost::CostModel,
+    repr::{basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, FuncId},
+    verify::{verify, ValidityError},
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+    Collection,
+};
+use num_traits::AsPrimitive;
+use std::fmt::{self, Display};
+use timely::dataflow::Scope;
+
+/// A pass that left the program worse off than it found it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum SanitizerDiagnostic {
+    /// The pass made a function more expensive, costs are rounded to whole units of
+    /// the [`CostModel`] used
+    CostIncreased {
+        pass: String,
+        func: FuncId,
+        before: usize,
+        after: usize,
+    },
+    /// The pass produced a program with an error the program it was given didn't have
+    IntroducedError { pass: String, error: ValidityError },
+}
+
+impl SanitizerDiagnostic {
+    /// The name of the offending pass
+    pub fn pass(&self) -> &str {
+        match self {
+            Self::CostIncreased { pass, .. } | Self::IntroducedError { pass, .. } => pass,
+        }
+    }
+}
+
+impl Display for SanitizerDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CostIncreased {
+                pass,
+                func,
+                before,
+                after,
+            } => write!(
+                f,
+                "{} increased the cost of {:?} from {} to {}",
+                pass, func, before, after,
+            ),
+            Self::IntroducedError { pass, error } => {
+                write!(f, "{} introduced an error: {}", pass, error)
+            }
+        }
+    }
+}
+
+/// Compares the output of a pass against its input, see the [module docs](self)
+///
+/// `input_errors` are the errors [`verify_program()`] finds within the input, which
+/// is usually the output of the previous pass. The errors found within the output
+/// are returned along with the diagnostics so that the next pass can reuse them
+#[allow(clippy::type_complexity)]
+pub fn sanitize<S, R, M>(
+    scope: &mut S,
+    pass: &str,
+    model: M,
+    input: &Program<S, R>,
+    output: &Program<S, R>,
+    input_errors: &Collection<S, ValidityError, R>,
+) -> (
+    Collection<S, SanitizerDiagnostic, R>,
+    Collection<S, ValidityError, R>,
+)
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference + AsPrimitive<usize>,
+    M: CostModel + Clone + 'static,
+{
+    let span = tracing::debug_span!("sanitizing pass", pass);
+    span.in_scope(|| {
+        let (before, after) = (
+            function_costs(input, model.clone()),
+            function_costs(output, model),
+        );
+        let pass_name = pass.to_owned();
+        let increased_costs = after
+            .join_map(&before, move |&func, &after, &before| {
+                (after > before).then(|| SanitizerDiagnostic::CostIncreased {
+                    pass: pass_name.clone(),
+                    func,
+                    before,
+                    after,
+                })
+            })
+            .flat_map(|diagnostic| diagnostic);
+
+        let pass_name = pass.to_owned();
+        let output_errors = verify_program(scope, output);
+        let introduced_errors = output_errors
+            .map(|error| (error, ()))
+            .antijoin(input_errors)
+            .map(move |(error, ())| SanitizerDiagnostic::IntroducedError {
+                pass: pass_name.clone(),
+                error,
+            });
+
+        (increased_costs.concat(&introduced_errors), output_errors)
+    })
+}
+
+/// The cost of every function within the program according to `model`, with the
+/// cost of each instruction and terminator rounded to a whole unit
+pub fn function_costs<S, R, M>(
+    program: &Program<S, R>,
+    model: M,
+) -> Collection<S, (FuncId, usize), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference + AsPrimitive<usize>,
+    M: CostModel + Clone + 'static,
+{
+    let instruction_model = model.clone();
+    let instruction_costs =
+        program
+            .block_instructions
+            .join_map(&program.instructions, move |_inst, &block, inst| {
+                (
+                    block,
+                    instruction_model.instruction_cost(inst).round() as usize,
+                )
+            });
+    let terminator_costs = program.block_terminators.map(move |(block, terminator)| {
+        let mut cost = model.terminator_cost(&terminator);
+        if terminator.is_branching() {
+            cost += model.branch_misprediction();
+        }
+
+        (block, cost.round() as usize)
+    });
+
+    // Every function gets a cost, even ones without any blocks
+    let functions = program.function_descriptors.map(|(func, _)| (func, 0));
+
+    instruction_costs
+        .concat(&terminator_costs)
+        .join_map(&program.function_blocks, |_block, &cost, &func| {
+            (func, cost)
+        })
+        .concat(&functions)
+        .reduce(|_func, costs, output| {
+            let total = costs
+                .iter()
+                .map(|&(&cost, ref diff)| {
+                    let count: usize = diff.as_();
+                    cost * count
+                })
+                .sum::<usize>();
+
+            output.push((total, R::from(1)));
+        })
+}
+
+/// Verifies a program in the middle of a pipeline, where block and function
+/// descriptors may be stale and only the relations between ids are up to date
+pub fn verify_program<S, R>(
+    scope: &mut S,
+    program: &Program<S, R>,
+) -> Collection<S, ValidityError, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
+{
+    let block_contents = program.ordered_block_contents(
+        &program
+            .block_instructions
+            .map(|(inst, block)| (inst, (block, inst))),
+    );
+
+    let basic_blocks = program
+        .block_descriptors
+        .join(&program.block_terminators)
+        .join_map(
+            &block_contents,
+            |&block, (desc, terminator), instructions| {
+                let desc =
+                    BasicBlockDesc::new(desc.name, block, instructions.clone(), terminator.clone());
+                (block, desc)
+            },
+        );
+
+    let function_blocks = program
+        .function_blocks
+        .map(|(block, func)| (func, block))
+        .reduce(|_func, input, output| {
+            let blocks: Vec<BasicBlockId> = input.iter().map(|&(&block, _)| block).collect();
+            output.push((blocks, R::from(1)));
+        });
+    let functions = program
+        .function_descriptors
+        .join_map(&function_blocks, |&func, desc, blocks| {
+            let desc = FunctionDesc {
+                basic_blocks: blocks.clone(),
+                ..desc.clone()
+            };
+            (func, desc)
+        })
+        .concat(
+            &program
+                .function_descriptors
+                .antijoin(&function_blocks.map(|(func, _)| func))
+                .map(|(func, desc)| {
+                    let desc = FunctionDesc {
+                        basic_blocks: Vec::new(),
+                        ..desc
+                    };
+                    (func, desc)
+                }),
+        );
+
+    verify(scope, &program.instructions, &basic_blocks, &functions)
+}
@@ -0,0 +1,167 @@
+//! Reference counted payloads for collections that carry large values
+//!
+//! Joins hand their closures references to the values they match up, so every
+//! output tuple that keeps a value around has to clone it. For instructions that
+//! means deep copying their operands on every join they flow through, wrapping them
+//! within a [`Shared`] makes those clones a reference count increment instead
+//!
+//! Values sent between processes are entombed like a [`Box`] would be. Exhumed
+//! values point into the buffer they were received within and are only ever
+//! borrowed from it, cloning one copies it out of the buffer into a fresh allocation
+
+use abomonation::Abomonation;
+use std::{
+    cmp::Ordering,
+    fmt::{self, Debug},
+    hash::{Hash, Hasher},
+    io::{Result as IoResult, Write},
+    mem,
+    ops::Deref,
+    ptr::{self, NonNull},
+    slice,
+    sync::Arc,
+};
+
+/// An immutable value that's shared between every clone of it
+pub struct Shared<T>(Repr<T>);
+
+enum Repr<T> {
+    Owned(Arc<T>),
+    /// Points into the buffer the value was exhumed from, which outlives every
+    /// borrow of the exhumed value
+    Exhumed(NonNull<T>),
+}
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Self(Repr::Owned(Arc::new(value)))
+    }
+
+    /// Returns the shared value, cloning it only if it's shared with anything else
+    pub fn into_inner(self) -> T
+    where
+        T: Clone,
+    {
+        match self.0 {
+            Repr::Owned(value) => Arc::try_unwrap(value).unwrap_or_else(|value| (*value).clone()),
+            Repr::Exhumed(value) => unsafe { value.as_ref().clone() },
+        }
+    }
+}
+
+impl<T> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match &self.0 {
+            Repr::Owned(value) => value,
+            Repr::Exhumed(value) => unsafe { value.as_ref() },
+        }
+    }
+}
+
+impl<T> Clone for Shared<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        match &self.0 {
+            Repr::Owned(value) => Self(Repr::Owned(value.clone())),
+            // Exhumed values can't outlive their buffer, so they get an allocation
+            // of their own
+            Repr::Exhumed(value) => Self::new(unsafe { value.as_ref().clone() }),
+        }
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> PartialEq for Shared<T>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T> Eq for Shared<T> where T: Eq {}
+
+impl<T> PartialOrd for Shared<T>
+where
+    T: PartialOrd,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T> Ord for Shared<T>
+where
+    T: Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T> Hash for Shared<T>
+where
+    T: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+impl<T> Debug for Shared<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+// Exhumed values are only reachable through borrows of the buffer holding them, so
+// sharing them is as safe as sharing the value itself
+unsafe impl<T> Send for Shared<T> where T: Send + Sync {}
+unsafe impl<T> Sync for Shared<T> where T: Send + Sync {}
+
+impl<T> Abomonation for Shared<T>
+where
+    T: Abomonation,
+{
+    unsafe fn entomb<W: Write>(&self, write: &mut W) -> IoResult<()> {
+        let value: &T = self;
+        write.write_all(slice::from_raw_parts(
+            value as *const T as *const u8,
+            mem::size_of::<T>(),
+        ))?;
+
+        value.entomb(write)
+    }
+
+    unsafe fn exhume<'a, 'b>(&'a mut self, bytes: &'b mut [u8]) -> Option<&'b mut [u8]> {
+        if mem::size_of::<T>() > bytes.len() {
+            return None;
+        }
+
+        let (value, rest) = bytes.split_at_mut(mem::size_of::<T>());
+        let value = value.as_mut_ptr() as *mut T;
+        let rest = (*value).exhume(rest)?;
+
+        // The bytes of `self` hold a dangling `Arc`, so they can't be dropped
+        ptr::write(self, Self(Repr::Exhumed(NonNull::new_unchecked(value))));
+
+        Some(rest)
+    }
+
+    fn extent(&self) -> usize {
+        mem::size_of::<T>() + (**self).extent()
+    }
+}
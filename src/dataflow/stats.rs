@@ -0,0 +1,341 @@
+use crate::{
+    repr::FuncId,
+    symbols::{FunctionName, SymbolResolver},
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    difference::{Monoid, Semigroup},
+    AsCollection, Collection, Data,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+use timely::{
+    dataflow::{channels::pact::Pipeline, operators::Operator, Scope},
+    order::Product,
+};
+
+/// Statistics about a single pass over a single epoch of input
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PassStats {
+    /// The name of the pass or dataflow the statistics are for
+    pub pass: String,
+    pub epoch: usize,
+    /// The number of updates the pass produced
+    pub changes: usize,
+    /// The number of iterations it took for the pass's output to settle, this
+    /// is always one for passes that aren't within an iterative scope
+    pub iterations: usize,
+    /// The wall-clock time between the first and last updates of the epoch, in nanoseconds
+    pub elapsed_nanos: u64,
+}
+
+impl PassStats {
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos)
+    }
+}
+
+impl Display for PassStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (epoch {}): {} changes over {} iterations in {:?}",
+            self.pass,
+            self.epoch,
+            self.changes,
+            self.iterations,
+            self.elapsed(),
+        )
+    }
+}
+
+/// A summary of the instructions a single pass changed within a single function
+/// over a single epoch of input
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptSummary {
+    /// The name of the pass that made the changes
+    pub pass: String,
+    pub epoch: usize,
+    /// The number of instructions the pass removed from the function
+    pub removed: usize,
+    /// The number of instructions the pass added to the function
+    pub added: usize,
+    /// The number of instructions the pass replaced with a different instruction
+    pub rewritten: usize,
+}
+
+impl OptSummary {
+    /// The total number of instructions the pass changed
+    pub fn changes(&self) -> usize {
+        self.removed + self.added + self.rewritten
+    }
+
+    /// Displays the summary along with the function it was made for, which is named
+    /// by `resolver` if it knows the function's name
+    pub fn display_with<'a, R>(&'a self, func: FuncId, resolver: &'a R) -> DisplayOptSummary<'a>
+    where
+        R: SymbolResolver,
+    {
+        DisplayOptSummary {
+            summary: self,
+            func: FunctionName::new(func, Some(resolver)),
+        }
+    }
+}
+
+impl Display for OptSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (epoch {}): removed {}, added {} and rewrote {} instructions",
+            self.pass, self.epoch, self.removed, self.added, self.rewritten,
+        )
+    }
+}
+
+/// Displays an [`OptSummary`] for a specific function, created by
+/// [`OptSummary::display_with()`]
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayOptSummary<'a> {
+    summary: &'a OptSummary,
+    func: FunctionName<'a>,
+}
+
+impl Display for DisplayOptSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = self.summary;
+        write!(
+            f,
+            "{} (epoch {}): removed {}, added {} and rewrote {} instructions of {}",
+            summary.pass,
+            summary.epoch,
+            summary.removed,
+            summary.added,
+            summary.rewritten,
+            self.func,
+        )
+    }
+}
+
+/// How a pass changed a single instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum InstructionChange {
+    Removed,
+    Added,
+    Rewritten,
+}
+
+/// Timestamps that can be broken down into an input epoch and an iteration within it
+pub trait EpochTimestamp {
+    fn epoch(&self) -> usize;
+
+    fn iteration(&self) -> usize;
+}
+
+impl EpochTimestamp for usize {
+    fn epoch(&self) -> usize {
+        *self
+    }
+
+    fn iteration(&self) -> usize {
+        0
+    }
+}
+
+impl<T, I> EpochTimestamp for Product<T, I>
+where
+    T: EpochTimestamp,
+    I: Copy + Into<usize>,
+{
+    fn epoch(&self) -> usize {
+        self.outer.epoch()
+    }
+
+    fn iteration(&self) -> usize {
+        self.inner.into()
+    }
+}
+
+/// Collects [`PassStats`] for every epoch of updates that pass through `collection`,
+/// producing each epoch's statistics once all of its updates have been seen
+///
+/// Statistics are also logged at the `debug` level as they're produced
+pub fn pass_stats<S, D, R>(
+    collection: &Collection<S, D, R>,
+    pass: &str,
+) -> Collection<S, PassStats, R>
+where
+    S: Scope,
+    S::Timestamp: EpochTimestamp,
+    D: Data,
+    R: Semigroup + From<i8>,
+{
+    let pass = pass.to_owned();
+    let mut buffer = Vec::new();
+
+    collection
+        .inner
+        .unary_frontier(
+            Pipeline,
+            &format!("PassStats: {}", pass),
+            move |_capability, _info| {
+                let mut pending = BTreeMap::new();
+
+                move |input, output| {
+                    input.for_each(|capability, data| {
+                        data.swap(&mut buffer);
+                        let now = Instant::now();
+
+                        for (_data, time, _diff) in buffer.drain(..) {
+                            let (_capability, stats) =
+                                pending.entry(time.epoch()).or_insert_with(|| {
+                                    let stats = PendingStats {
+                                        changes: 0,
+                                        iterations: 0,
+                                        first_update: now,
+                                        last_update: now,
+                                    };
+
+                                    (capability.retain(), stats)
+                                });
+
+                            stats.changes += 1;
+                            stats.iterations = stats.iterations.max(time.iteration() + 1);
+                            stats.last_update = now;
+                        }
+                    });
+
+                    // An epoch is complete once nothing from it or any earlier epoch can arrive
+                    let frontier = input.frontier().frontier();
+                    let complete: Vec<usize> = pending
+                        .keys()
+                        .copied()
+                        .take_while(|&epoch| frontier.iter().all(|time| time.epoch() > epoch))
+                        .collect();
+
+                    for epoch in complete {
+                        let (capability, stats) = pending.remove(&epoch).unwrap();
+                        let stats = PassStats {
+                            pass: pass.clone(),
+                            epoch,
+                            changes: stats.changes,
+                            iterations: stats.iterations,
+                            elapsed_nanos: (stats.last_update - stats.first_update).as_nanos()
+                                as u64,
+                        };
+
+                        tracing::debug!(
+                            pass = %stats.pass,
+                            epoch = stats.epoch,
+                            changes = stats.changes,
+                            iterations = stats.iterations,
+                            elapsed = ?stats.elapsed(),
+                            "pass statistics",
+                        );
+
+                        let time = capability.time().clone();
+                        output.session(&capability).give((stats, time, R::from(1)));
+                    }
+                }
+            },
+        )
+        .as_collection()
+}
+
+struct PendingStats {
+    changes: usize,
+    iterations: usize,
+    first_update: Instant,
+    last_update: Instant,
+}
+
+/// Sums the instruction changes made by a pass into an [`OptSummary`] for every
+/// function it changed within each epoch, producing each epoch's summaries once all
+/// of its updates have been seen
+///
+/// Only additions to `changes` are counted, so changes made within an iterative
+/// scope are counted once for every iteration that makes them anew
+pub fn opt_summaries<S, R>(
+    changes: &Collection<S, (FuncId, InstructionChange), R>,
+    pass: &str,
+) -> Collection<S, (FuncId, OptSummary), R>
+where
+    S: Scope,
+    S::Timestamp: EpochTimestamp,
+    R: Monoid + PartialOrd + From<i8>,
+{
+    let pass = pass.to_owned();
+    let mut buffer = Vec::new();
+
+    changes
+        .inner
+        .unary_frontier(
+            Pipeline,
+            &format!("OptSummary: {}", pass),
+            move |_capability, _info| {
+                let mut pending = BTreeMap::new();
+
+                move |input, output| {
+                    input.for_each(|capability, data| {
+                        data.swap(&mut buffer);
+
+                        for ((func, change), time, diff) in buffer.drain(..) {
+                            if !(diff > R::zero()) {
+                                continue;
+                            }
+
+                            let (_capability, summaries) = pending
+                                .entry(time.epoch())
+                                .or_insert_with(|| (capability.retain(), BTreeMap::new()));
+                            let summary = summaries.entry(func).or_insert_with(|| OptSummary {
+                                pass: pass.clone(),
+                                epoch: time.epoch(),
+                                removed: 0,
+                                added: 0,
+                                rewritten: 0,
+                            });
+
+                            match change {
+                                InstructionChange::Removed => summary.removed += 1,
+                                InstructionChange::Added => summary.added += 1,
+                                InstructionChange::Rewritten => summary.rewritten += 1,
+                            }
+                        }
+                    });
+
+                    let frontier = input.frontier().frontier();
+                    let complete: Vec<usize> = pending
+                        .keys()
+                        .copied()
+                        .take_while(|&epoch| frontier.iter().all(|time| time.epoch() > epoch))
+                        .collect();
+
+                    for epoch in complete {
+                        let (capability, summaries) = pending.remove(&epoch).unwrap();
+                        let time = capability.time().clone();
+                        let mut session = output.session(&capability);
+
+                        for (func, summary) in summaries {
+                            tracing::debug!(
+                                pass = %summary.pass,
+                                epoch = summary.epoch,
+                                function = ?func,
+                                removed = summary.removed,
+                                added = summary.added,
+                                rewritten = summary.rewritten,
+                                "optimization summary",
+                            );
+
+                            session.give(((func, summary), time.clone(), R::from(1)));
+                        }
+                    }
+                }
+            },
+        )
+        .as_collection()
+}
@@ -1,18 +1,53 @@
-use std::any::{Any, TypeId};
-
-use differential_dataflow::trace::TraceReader;
+use crate::symbols::SymbolResolver;
+use crossbeam_channel::Sender;
+use differential_dataflow::{
+    difference::Semigroup,
+    operators::arrange::TraceAgent,
+    trace::{
+        implementations::ord::{OrdKeySpine, OrdValSpine},
+        Cursor, TraceReader,
+    },
+};
 use fxhash::FxHashMap;
 use lasso::Spur;
-use timely::progress::frontier::AntichainRef;
+use std::{
+    any::{Any, TypeId},
+    fmt::{self, Debug},
+    marker::PhantomData,
+    sync::Arc,
+};
+use timely::progress::{frontier::AntichainRef, Timestamp};
 
+/// Holds traces shared between dataflows, each trace is identified by its name
+/// along with its type so that a single name can refer to several arrangements
+/// of the same data
 pub struct TraceManager<T> {
-    traces: FxHashMap<Spur, Box<dyn ManagedTrace<T>>>,
+    traces: FxHashMap<(Spur, TypeId), Box<dyn ManagedTrace<T>>>,
+    /// Resolves trace names for logging, names are logged as their raw keys without it
+    resolver: Option<Arc<dyn SymbolResolver + Send + Sync>>,
 }
 
 impl<T> TraceManager<T> {
     pub fn new() -> Self {
         Self {
             traces: FxHashMap::default(),
+            resolver: None,
+        }
+    }
+
+    /// Sets the resolver used to name traces within logs
+    pub fn with_resolver<R>(mut self, resolver: Arc<R>) -> Self
+    where
+        R: SymbolResolver + Send + Sync + 'static,
+    {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    fn display_name(&self, key: Spur) -> String {
+        match &self.resolver {
+            Some(resolver) => resolver.display_symbol(key).to_string(),
+            None => format!("{:?}", key),
         }
     }
 
@@ -24,13 +59,26 @@ impl<T> TraceManager<T> {
     where
         Trace: ManagedTrace<T> + 'static,
     {
-        tracing::debug!("inserting trace {:?}", key);
-        self.traces.insert(key, Box::new(trace))
+        tracing::debug!("inserting trace {}", self.display_name(key));
+        self.traces
+            .insert((key, TypeId::of::<Trace>()), Box::new(trace))
     }
 
-    pub fn remove_trace(&mut self, key: Spur) -> Option<Box<dyn ManagedTrace<T>>> {
-        tracing::debug!("removing trace {:?}", key);
-        self.traces.remove(&key)
+    /// Removes every trace with the given name
+    pub fn remove_trace(&mut self, key: Spur) -> Vec<Box<dyn ManagedTrace<T>>> {
+        tracing::debug!("removing trace {}", self.display_name(key));
+
+        let types: Vec<TypeId> = self
+            .traces
+            .keys()
+            .filter(|&&(name, _)| name == key)
+            .map(|&(_, ty)| ty)
+            .collect();
+
+        types
+            .into_iter()
+            .filter_map(|ty| self.traces.remove(&(key, ty)))
+            .collect()
     }
 
     pub fn get_trace<Trace>(&self, key: Spur) -> Option<Trace>
@@ -38,10 +86,10 @@ impl<T> TraceManager<T> {
         T: 'static,
         Trace: ManagedTrace<T> + Any + Clone,
     {
-        tracing::debug!("getting trace {:?}", key);
+        tracing::debug!("getting trace {}", self.display_name(key));
 
         self.traces
-            .get(&key)
+            .get(&(key, TypeId::of::<Trace>()))
             .and_then(|trace| {
                 let trace: &dyn ManagedTrace<T> = &**trace;
                 if trace.inner_type_id() == TypeId::of::<Trace>() {
@@ -53,6 +101,140 @@ impl<T> TraceManager<T> {
             .cloned()
     }
 
+    /// Inserts a trace and returns a typed handle to it, allowing it to later be
+    /// fetched or exported without restating its type
+    pub fn register<Trace>(&mut self, key: Spur, trace: Trace) -> TraceHandle<Trace>
+    where
+        Trace: ManagedTrace<T> + 'static,
+    {
+        self.insert_trace(key, trace);
+        TraceHandle::new(key)
+    }
+
+    /// Fetches the trace a handle refers to, returning `None` if it was removed
+    /// or replaced by a trace of another type
+    pub fn trace<Trace>(&self, handle: TraceHandle<Trace>) -> Option<Trace>
+    where
+        T: 'static,
+        Trace: ManagedTrace<T> + Any + Clone,
+    {
+        self.get_trace(handle.key)
+    }
+
+    /// Fetches the trace with the given name and type, calling `arrange` to build
+    /// and register it if it doesn't exist yet
+    ///
+    /// Dataflows that need the same arrangement can all go through this instead of
+    /// arranging their own copy, only the first one to ask for it builds it
+    pub fn arrange_once<Trace, F>(&mut self, key: Spur, arrange: F) -> Trace
+    where
+        T: 'static,
+        Trace: ManagedTrace<T> + Any + Clone,
+        F: FnOnce() -> Trace,
+    {
+        if let Some(trace) = self.get_trace(key) {
+            return trace;
+        }
+
+        tracing::debug!("arranging trace {}", self.display_name(key));
+        let trace = arrange();
+        self.insert_trace(key, trace.clone());
+
+        trace
+    }
+
+    /// Returns the names of every managed trace, names shared by traces of
+    /// different types are repeated once per trace
+    pub fn trace_names(&self) -> impl Iterator<Item = Spur> + '_ {
+        self.traces.keys().map(|&(name, _)| name)
+    }
+
+    /// Calls `export` with the consolidated contents of a trace as of the given
+    /// frontier, every update at a time that isn't beyond `frontier` is accumulated
+    /// and only entries with a non-zero difference are exported
+    ///
+    /// Returns `false` without exporting anything if the trace doesn't exist or
+    /// hasn't yet been completed up to `frontier`
+    pub fn export<Trace, F>(
+        &self,
+        handle: TraceHandle<Trace>,
+        frontier: AntichainRef<'_, T>,
+        mut export: F,
+    ) -> bool
+    where
+        T: Timestamp,
+        Trace: TraceReader<Time = T> + ManagedTrace<T> + Any + Clone,
+        Trace::R: Semigroup,
+        F: FnMut(&Trace::Key, &Trace::Val, Trace::R),
+    {
+        let mut trace = match self.trace(handle) {
+            Some(trace) => trace,
+            None => {
+                tracing::warn!(
+                    "attempted to export the missing trace {}",
+                    self.display_name(handle.key),
+                );
+                return false;
+            }
+        };
+
+        let (mut cursor, storage) = match trace.cursor_through(frontier) {
+            Some(cursor) => cursor,
+            None => {
+                tracing::warn!(
+                    "attempted to export trace {} before it reached the requested frontier",
+                    self.display_name(handle.key),
+                );
+                return false;
+            }
+        };
+
+        while cursor.key_valid(&storage) {
+            while cursor.val_valid(&storage) {
+                let mut accumulated: Option<Trace::R> = None;
+                cursor.map_times(&storage, |time, diff| {
+                    if !frontier.less_equal(time) {
+                        match accumulated.as_mut() {
+                            Some(accumulated) => accumulated.plus_equals(diff),
+                            None => accumulated = Some(diff.clone()),
+                        }
+                    }
+                });
+
+                if let Some(diff) = accumulated.filter(|diff| !diff.is_zero()) {
+                    export(cursor.key(&storage), cursor.val(&storage), diff);
+                }
+
+                cursor.step_val(&storage);
+            }
+
+            cursor.step_key(&storage);
+        }
+
+        true
+    }
+
+    /// Sends the consolidated contents of a trace as of the given frontier to
+    /// an external consumer, see [`TraceManager::export()`]
+    pub fn export_to<Trace>(
+        &self,
+        handle: TraceHandle<Trace>,
+        frontier: AntichainRef<'_, T>,
+        sender: &Sender<(Trace::Key, Trace::Val, Trace::R)>,
+    ) -> bool
+    where
+        T: Timestamp,
+        Trace: TraceReader<Time = T> + ManagedTrace<T> + Any + Clone,
+        Trace::Key: Clone,
+        Trace::Val: Clone,
+        Trace::R: Semigroup,
+    {
+        self.export(handle, frontier, |key, val, diff| {
+            // Ignore any errors that occur
+            let _ = sender.send((key.clone(), val.clone(), diff));
+        })
+    }
+
     pub fn advance_by(&mut self, frontier: AntichainRef<'_, T>) {
         tracing::info!("advancing traces");
 
@@ -76,6 +258,47 @@ impl<T> Default for TraceManager<T> {
     }
 }
 
+/// A typed reference to a trace within a [`TraceManager`]
+pub struct TraceHandle<Trace> {
+    key: Spur,
+    trace: PhantomData<fn() -> Trace>,
+}
+
+/// A handle to an arrangement of keys
+pub type KeyTraceHandle<K, T, R> = TraceHandle<TraceAgent<OrdKeySpine<K, T, R>>>;
+
+/// A handle to an arrangement of key-value pairs
+pub type ValTraceHandle<K, V, T, R> = TraceHandle<TraceAgent<OrdValSpine<K, V, T, R>>>;
+
+impl<Trace> TraceHandle<Trace> {
+    /// Creates a handle to the trace with the given name, the trace's type
+    /// is checked when it's fetched
+    pub const fn new(key: Spur) -> Self {
+        Self {
+            key,
+            trace: PhantomData,
+        }
+    }
+
+    pub const fn key(&self) -> Spur {
+        self.key
+    }
+}
+
+impl<Trace> Debug for TraceHandle<Trace> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TraceHandle").field(&self.key).finish()
+    }
+}
+
+impl<Trace> Clone for TraceHandle<Trace> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Trace> Copy for TraceHandle<Trace> {}
+
 pub trait ManagedTrace<T>: Any {
     fn set_logical_compaction(&mut self, frontier: AntichainRef<'_, T>);
 
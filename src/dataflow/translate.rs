@@ -1,16 +1,12 @@
 use crate::{
     dataflow::InputManager,
     repr::{
-        basic_block::BasicBlockDesc,
-        function::FunctionDesc,
-        utils::{DisplayCtx, IRDisplay},
-        Function, InstId,
+        basic_block::BasicBlockDesc, function::FunctionDesc, utils::IRDisplay, Function, InstId,
     },
+    symbols::SymbolResolver,
 };
 use differential_dataflow::{difference::Semigroup, lattice::Lattice};
-use lasso::Resolver;
-use pretty::{BoxAllocator, RefDoc};
-use std::{io, num::NonZeroU64};
+use std::num::NonZeroU64;
 use timely::progress::Timestamp;
 
 pub fn translate<T, R, I, S>(input: &mut InputManager<T, R>, functions: I, interner: &S)
@@ -18,17 +14,10 @@ where
     T: Timestamp + Lattice + Clone,
     R: Semigroup + From<i8>,
     I: IntoIterator<Item = Function>,
-    S: Resolver,
+    S: SymbolResolver,
 {
-    let alloc = BoxAllocator;
-
     for function in functions {
-        function
-            .display::<BoxAllocator, RefDoc, S>(DisplayCtx::new(&alloc, interner))
-            .1
-            .render(70, &mut io::stdout())
-            .unwrap();
-        println!();
+        println!("{}", function.to_pretty_string(interner));
 
         let meta = FunctionDesc::new(
             function.name,
@@ -37,7 +26,8 @@ where
             function.ret_ty,
             function.entry,
             function.basic_blocks.iter().map(|block| block.id).collect(),
-        );
+        )
+        .with_metadata(&function.metadata);
         input.functions.update((function.id, meta), R::from(1));
 
         for basic_block in function.basic_blocks {
@@ -46,12 +36,22 @@ where
             for (idx, instruction) in basic_block.instructions.into_iter().enumerate() {
                 let inst_id = InstId::new(NonZeroU64::new(idx as u64 + 1).unwrap());
 
+                if let Some(span) = basic_block.instruction_spans.get(idx).copied().flatten() {
+                    input.instruction_spans.update((inst_id, span), R::from(1));
+                }
+
                 instructions.push(inst_id);
                 input
                     .instructions
                     .update((inst_id, instruction), R::from(1));
             }
 
+            if let Some(span) = basic_block.terminator_span {
+                input
+                    .terminator_spans
+                    .update((basic_block.id, span), R::from(1));
+            }
+
             let meta = BasicBlockDesc::new(
                 basic_block.name,
                 basic_block.id,
@@ -0,0 +1,239 @@
+use crate::{
+    dataflow::{
+        analysis::{dominators, DominatorTrace, UseDef},
+        call_graph::{self, CallGraphTrace},
+        Difference, Program,
+    },
+    driver::Pass,
+};
+use differential_dataflow::{
+    difference::Semigroup,
+    lattice::Lattice,
+    operators::arrange::{ArrangeByKey, Arranged},
+};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+};
+use timely::dataflow::Scope;
+
+/// The analyses passes can share instead of each building their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Analysis {
+    /// The dominators of every block, see [`dominators()`]
+    Dominators,
+    /// The definitions and uses of every variable, see [`UseDef`]
+    UseDef,
+    /// The caller→callee edges between functions, see [`call_graph::call_graph()`]
+    CallGraph,
+}
+
+impl Analysis {
+    pub const ALL: &'static [Self] = &[Self::Dominators, Self::UseDef, Self::CallGraph];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Dominators => "dominators",
+            Self::UseDef => "use-def",
+            Self::CallGraph => "call-graph",
+        }
+    }
+}
+
+impl Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A single step of a [`PassManager`]'s schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Step {
+    /// Arranges an analysis of the current program
+    Build(Analysis),
+    /// Applies a pass to the current program
+    Run(Pass),
+    /// Drops an analysis that no longer matches the program
+    Invalidate(Analysis),
+}
+
+/// Orders passes along with the analyses they depend on
+///
+/// Every pass declares the analyses it [requires](Pass::required_analyses) and
+/// the ones it [invalidates](Pass::invalidated_analyses). An analysis is only built
+/// right before the first pass that needs it and is then shared by every following
+/// pass until one of them invalidates it, at which point it's rebuilt for the next
+/// pass that needs it
+///
+/// ```rust,ignore
+/// let schedule = PassManager::new()
+///     .add_pass(Pass::CopyPropagation)
+///     .add_pass(Pass::Canonicalize)
+///     .add_pass(Pass::Schedule)
+///     .schedule();
+///
+/// // Copy propagation changes uses, so scheduling gets a fresh use-def analysis
+/// // while canonicalization doesn't need one at all
+/// assert_eq!(
+///     schedule,
+///     [
+///         Step::Build(Analysis::UseDef),
+///         Step::Run(Pass::CopyPropagation),
+///         Step::Invalidate(Analysis::UseDef),
+///         Step::Run(Pass::Canonicalize),
+///         Step::Build(Analysis::UseDef),
+///         Step::Run(Pass::Schedule),
+///     ],
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassManager {
+    passes: Vec<Pass>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self { passes: Vec::new() }
+    }
+
+    pub fn from_passes(passes: &[Pass]) -> Self {
+        Self {
+            passes: passes.to_vec(),
+        }
+    }
+
+    /// Adds a pass to the end of the manager
+    pub fn add_pass(mut self, pass: Pass) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// Interleaves the passes with the construction and invalidation of the
+    /// analyses they use
+    pub fn schedule(&self) -> Vec<Step> {
+        let (mut steps, mut available) = (Vec::new(), BTreeSet::new());
+        for &pass in self.passes.iter() {
+            for &analysis in pass.required_analyses() {
+                if available.insert(analysis) {
+                    steps.push(Step::Build(analysis));
+                }
+            }
+
+            steps.push(Step::Run(pass));
+
+            for &analysis in pass.invalidated_analyses() {
+                if available.remove(&analysis) {
+                    steps.push(Step::Invalidate(analysis));
+                }
+            }
+        }
+
+        steps
+    }
+}
+
+/// The analyses that are currently available to passes, see [`PassManager`]
+pub struct Analyses<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Semigroup,
+{
+    use_def: Option<UseDef<S, R>>,
+    dominators: Option<Arranged<S, DominatorTrace<S::Timestamp, R>>>,
+    call_graph: Option<Arranged<S, CallGraphTrace<S::Timestamp, R>>>,
+}
+
+impl<S, R> Analyses<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    pub fn new() -> Self {
+        Self {
+            use_def: None,
+            dominators: None,
+            call_graph: None,
+        }
+    }
+
+    pub fn is_built(&self, analysis: Analysis) -> bool {
+        match analysis {
+            Analysis::Dominators => self.dominators.is_some(),
+            Analysis::UseDef => self.use_def.is_some(),
+            Analysis::CallGraph => self.call_graph.is_some(),
+        }
+    }
+
+    /// Builds an analysis of the given program, replacing any earlier one
+    pub fn build(&mut self, analysis: Analysis, program: &Program<S, R>) {
+        tracing::debug!(analysis = analysis.name(), "building analysis");
+
+        match analysis {
+            Analysis::Dominators => self.dominators = Some(dominators(program)),
+            Analysis::UseDef => self.use_def = Some(program.use_def()),
+            Analysis::CallGraph => {
+                self.call_graph = Some(call_graph::call_graph(program).arrange_by_key());
+            }
+        }
+    }
+
+    pub fn invalidate(&mut self, analysis: Analysis) {
+        tracing::debug!(analysis = analysis.name(), "invalidating analysis");
+
+        match analysis {
+            Analysis::Dominators => self.dominators = None,
+            Analysis::UseDef => self.use_def = None,
+            Analysis::CallGraph => self.call_graph = None,
+        }
+    }
+
+    /// The use-def analysis of the program, building it if it isn't available
+    pub fn use_def(&mut self, program: &Program<S, R>) -> &UseDef<S, R> {
+        if self.use_def.is_none() {
+            self.build(Analysis::UseDef, program);
+        }
+
+        self.use_def.as_ref().unwrap()
+    }
+
+    /// The dominators of the program's blocks, building them if they aren't available
+    pub fn dominators(
+        &mut self,
+        program: &Program<S, R>,
+    ) -> &Arranged<S, DominatorTrace<S::Timestamp, R>> {
+        if self.dominators.is_none() {
+            self.build(Analysis::Dominators, program);
+        }
+
+        self.dominators.as_ref().unwrap()
+    }
+
+    /// The program's call graph keyed by caller, building it if it isn't available
+    pub fn call_graph(
+        &mut self,
+        program: &Program<S, R>,
+    ) -> &Arranged<S, CallGraphTrace<S::Timestamp, R>> {
+        if self.call_graph.is_none() {
+            self.build(Analysis::CallGraph, program);
+        }
+
+        self.call_graph.as_ref().unwrap()
+    }
+}
+
+impl<S, R> Default for Analyses<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,454 @@
+//! High level entry points for running passes over a module without having to
+//! assemble the dataflows by hand
+
+mod manager;
+mod passes;
+mod pipeline;
+
+pub use manager::{Analyses, Analysis, PassManager, Step};
+pub use passes::Pass;
+pub use pipeline::{
+    BudgetTrace, ConstantTrace, ErrorTrace, FunctionTrace, Pipeline, PipelineHandles,
+    SanitizerTrace, StatsTrace, SummaryTrace, TypeErrorTrace, BUDGET_TRACE, CONSTANTS_TRACE,
+    ERRORS_TRACE, FUNCTIONS_TRACE, SANITIZER_TRACE, STATS_TRACE, SUMMARIES_TRACE,
+    TYPE_ERRORS_TRACE,
+};
+
+use crate::{
+    builder::Context,
+    dataflow::{
+        operators::{CrossbeamExtractor, CrossbeamPusher},
+        panics::{self, PanicContext, PanicDiagnostic},
+        Budget, BudgetExceeded, Difference, InputManager, SanitizerDiagnostic, Time,
+    },
+    optimize::autotune::Autotuner,
+    repr::{
+        basic_block::BasicBlockDesc,
+        function::{FunctionAttributes, FunctionDesc},
+        BasicBlockId, ConstId, Constant, ConstantPool, FuncId, Function, InstId, Instruction,
+        InstructionExt, Span,
+    },
+    verify::ValidityError,
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{lattice::Lattice, operators::Consolidate};
+use std::sync::Arc;
+use timely::{
+    communication::Allocate,
+    dataflow::operators::{capture::Extract, Capture},
+    progress::Timestamp,
+    worker::Worker,
+};
+
+/// Runs modules through the optimization dataflow
+#[derive(Debug, Clone)]
+pub struct Driver {
+    context: Arc<Context>,
+    fuel: Option<usize>,
+    budget: Budget,
+    sanitize: bool,
+}
+
+impl Driver {
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            context,
+            fuel: None,
+            budget: Budget::unlimited(),
+            sanitize: false,
+        }
+    }
+
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    /// Limits the number of rewrites each run can make, see
+    /// [`Pipeline::fuel()`]
+    pub fn with_fuel(mut self, fuel: Option<usize>) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    pub const fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    /// Limits the size of every function, see [`Pipeline::budget()`]
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub const fn budget(&self) -> Budget {
+        self.budget
+    }
+
+    /// Checks every pass for breaking or pessimizing functions, see
+    /// [`Pipeline::sanitize()`]
+    pub fn with_sanitizer(mut self, sanitize: bool) -> Self {
+        self.sanitize = sanitize;
+        self
+    }
+
+    pub const fn sanitize(&self) -> bool {
+        self.sanitize
+    }
+
+    /// Verifies the given functions and then runs each pass over them once and in order,
+    /// returning the transformed functions along with any validity errors
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pipeline panics, see [`Driver::try_run()`] for a version that
+    /// returns the panic instead
+    pub fn run(&self, functions: Vec<Function>, passes: &[Pass]) -> DriverOutput {
+        self.try_run(functions, passes)
+            .unwrap_or_else(|diagnostic| panic!("the driver {}", diagnostic))
+    }
+
+    /// Runs the passes like [`Driver::run()`], catching any panic that occurs within
+    /// the pipeline and returning it along with what the pipeline was doing at the time
+    pub fn try_run(
+        &self,
+        functions: Vec<Function>,
+        passes: &[Pass],
+    ) -> Result<DriverOutput, PanicDiagnostic> {
+        panics::install_hook();
+
+        let (context, pipeline) = (self.context.clone(), self.pipeline(passes));
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        panics::catch(PanicContext::stage("running the driver"), || {
+            timely::execute_directly(move |worker| {
+                let mut handles = pipeline.build(worker);
+
+                let (functions_trace, errors_trace, probe) = (
+                    &mut handles.functions,
+                    &mut handles.errors,
+                    &mut handles.probe,
+                );
+                let budget_trace = handles.budget.as_mut();
+                let sanitizer_trace = handles.sanitizer.as_mut();
+                worker.dataflow_named("driver outputs", |scope| {
+                    let functions = functions_trace
+                        .import(scope)
+                        .as_collection(|_id, function| DriverItem::Function(function.clone()));
+                    let errors = errors_trace
+                        .import(scope)
+                        .as_collection(|error, &()| DriverItem::Error(error.clone()));
+
+                    let mut items = functions.concat(&errors);
+                    if let Some(budget_trace) = budget_trace {
+                        let exceeded = budget_trace.import(scope).as_collection(|exceeded, &()| {
+                            DriverItem::OverBudget(exceeded.clone())
+                        });
+                        items = items.concat(&exceeded);
+                    }
+                    if let Some(sanitizer_trace) = sanitizer_trace {
+                        let diagnostics =
+                            sanitizer_trace
+                                .import(scope)
+                                .as_collection(|diagnostic, &()| {
+                                    DriverItem::Sanitizer(diagnostic.clone())
+                                });
+                        items = items.concat(&diagnostics);
+                    }
+
+                    items
+                        .consolidate()
+                        .probe_with(probe)
+                        .inner
+                        .capture_into(CrossbeamPusher::new(sender));
+                });
+
+                load_functions(&context, &mut handles.input, functions);
+                handles.advance_to(1);
+                handles.step_until_complete(worker);
+            })
+        })?;
+
+        let mut output = DriverOutput::default();
+        for (_time, data) in CrossbeamExtractor::new(receiver).extract() {
+            for (data, _time, diff) in data {
+                for _ in 0..diff {
+                    match data.clone() {
+                        DriverItem::Function(function) => output.functions.push(function),
+                        DriverItem::Error(error) => output.errors.push(error),
+                        DriverItem::OverBudget(exceeded) => output.budget_exceeded.push(exceeded),
+                        DriverItem::Sanitizer(diagnostic) => output.sanitizer.push(diagnostic),
+                    }
+                }
+            }
+        }
+        layout_functions(&mut output.functions);
+
+        Ok(output)
+    }
+
+    /// The pipeline the driver runs the given passes with, carrying over the driver's
+    /// fuel, budget and sanitizer
+    ///
+    /// Embedders that keep the pipeline around to feed it edits over many epochs
+    /// should [compact](Driver::compact()) it between them
+    pub fn pipeline(&self, passes: &[Pass]) -> Pipeline {
+        passes.iter().fold(
+            Pipeline::new(self.context.clone())
+                .fixpoint(false)
+                .fuel(self.fuel)
+                .budget(self.budget)
+                .sanitize(self.sanitize),
+            |pipeline, &pass| pipeline.add_pass(pass),
+        )
+    }
+
+    /// Advances the inputs of a pipeline to `up_to`, compacts every one of its
+    /// traces and drops the batches that became unreachable, see
+    /// [`PipelineHandles::compact()`]
+    ///
+    /// Advancing a pipeline doesn't compact it, so this has to be called between
+    /// bursts of edits to keep the memory of a long running pipeline bounded
+    pub fn compact<A>(&self, handles: &mut PipelineHandles, worker: &mut Worker<A>, up_to: Time)
+    where
+        A: Allocate,
+    {
+        handles.compact(worker, up_to);
+    }
+
+    /// Runs every candidate ordering of the tuner over a sample of the functions and
+    /// then runs the smallest-code ordering over all of them, the tuner is left with
+    /// the measurements and choice that [`Autotuner::report()`] renders
+    ///
+    /// Falls back to [`Pass::ALL`] if the tuner has no candidates
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pipeline panics, like [`Driver::run()`]
+    pub fn run_autotuned(
+        &self,
+        functions: Vec<Function>,
+        tuner: &mut Autotuner<Pass>,
+    ) -> DriverOutput {
+        let ids: Vec<FuncId> = functions.iter().map(|function| function.id).collect();
+        let sampled = tuner.sample(&ids);
+        let sample: Vec<Function> = functions
+            .iter()
+            .filter(|function| sampled.contains(&function.id))
+            .cloned()
+            .collect();
+
+        for idx in 0..tuner.candidates().len() {
+            let output = self.run(sample.clone(), &tuner.candidates()[idx]);
+            tuner.record(idx, &output.functions);
+        }
+        tracing::info!("{}", tuner.report());
+
+        let ordering = tuner.best_ordering().unwrap_or(Pass::ALL).to_vec();
+        self.run(functions, &ordering)
+    }
+}
+
+/// The results of running a module through the [`Driver`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DriverOutput {
+    pub functions: Vec<Function>,
+    pub errors: Vec<ValidityError>,
+    /// The functions that went over the driver's [`Budget`]
+    pub budget_exceeded: Vec<BudgetExceeded>,
+    /// The passes that broke or pessimized a function, if the driver
+    /// [sanitizes them](Driver::with_sanitizer)
+    pub sanitizer: Vec<SanitizerDiagnostic>,
+}
+
+/// Everything the driver reads back out of its pipeline
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum DriverItem {
+    Function(Function),
+    Error(ValidityError),
+    OverBudget(BudgetExceeded),
+    Sanitizer(SanitizerDiagnostic),
+}
+
+/// Orders functions by their id with all [cold](FunctionAttributes::COLD) functions
+/// placed after every other function
+pub fn layout_functions(functions: &mut [Function]) {
+    functions.sort_by_key(|function| (function.metadata.attributes.is_cold(), function.id));
+}
+
+/// Gives functions to the dataflow, allocating new ids for their instructions
+pub(crate) fn load_functions<T, R>(
+    context: &Context,
+    input: &mut InputManager<T, R>,
+    functions: Vec<Function>,
+) where
+    T: Timestamp + Lattice,
+    R: Difference,
+{
+    load_functions_with(context.constants(), input, functions, || context.inst_id())
+}
+
+/// Gives functions to the dataflow, taking the ids of their instructions from `inst_id`
+/// and the contents of their pooled constants from `constants`
+pub(crate) fn load_functions_with<T, R, F>(
+    constants: &ConstantPool,
+    input: &mut InputManager<T, R>,
+    functions: Vec<Function>,
+    mut inst_id: F,
+) where
+    T: Timestamp + Lattice,
+    R: Difference,
+    F: FnMut() -> InstId,
+{
+    for function in functions {
+        LoadedFunction::with_inst_ids(function, constants, &mut inst_id).insert(input);
+    }
+}
+
+/// A function as it was given to the dataflow, kept around so that it can later be
+/// retracted or replaced by an edited version of itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedFunction {
+    desc: FunctionDesc,
+    basic_blocks: Vec<BasicBlockDesc>,
+    instructions: Vec<(InstId, Instruction)>,
+    instruction_spans: Vec<(InstId, Span)>,
+    terminator_spans: Vec<(BasicBlockId, Span)>,
+    constants: Vec<(ConstId, Constant)>,
+}
+
+impl LoadedFunction {
+    /// Splits a function into the updates it's given to the dataflow as, allocating
+    /// new ids for its instructions
+    pub fn new(context: &Context, function: Function) -> Self {
+        Self::with_inst_ids(function, context.constants(), || context.inst_id())
+    }
+
+    fn with_inst_ids<F>(function: Function, constants: &ConstantPool, mut inst_id: F) -> Self
+    where
+        F: FnMut() -> InstId,
+    {
+        let desc = FunctionDesc::new(
+            function.name,
+            function.id,
+            function.params,
+            function.ret_ty,
+            function.entry,
+            function.basic_blocks.iter().map(|block| block.id).collect(),
+        )
+        .with_metadata(&function.metadata);
+
+        let mut loaded = Self {
+            desc,
+            basic_blocks: Vec::with_capacity(function.basic_blocks.len()),
+            instructions: Vec::new(),
+            instruction_spans: Vec::new(),
+            terminator_spans: Vec::new(),
+            constants: Vec::new(),
+        };
+
+        let mut pooled: Vec<ConstId> = function
+            .basic_blocks
+            .iter()
+            .flat_map(|block| {
+                block
+                    .instructions
+                    .iter()
+                    .flat_map(|inst| inst.used_values())
+                    .chain(block.terminator.used_values())
+                    .filter_map(|value| value.as_pooled())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        pooled.sort_unstable();
+        pooled.dedup();
+
+        for id in pooled {
+            match constants.get(id) {
+                Some(constant) => loaded.constants.push((id, constant)),
+                None => tracing::warn!(
+                    "{:?} uses the pooled constant {:?}, which isn't within the pool",
+                    function.id,
+                    id,
+                ),
+            }
+        }
+
+        for block in function.basic_blocks {
+            let mut instructions = Vec::with_capacity(block.instructions.len());
+            for (idx, inst) in block.instructions.into_iter().enumerate() {
+                let id = inst_id();
+
+                if let Some(span) = block.instruction_spans.get(idx).copied().flatten() {
+                    loaded.instruction_spans.push((id, span));
+                }
+
+                instructions.push(id);
+                loaded.instructions.push((id, inst));
+            }
+
+            if let Some(span) = block.terminator_span {
+                loaded.terminator_spans.push((block.id, span));
+            }
+
+            loaded.basic_blocks.push(BasicBlockDesc::new(
+                block.name,
+                block.id,
+                instructions,
+                block.terminator,
+            ));
+        }
+
+        loaded
+    }
+
+    pub const fn id(&self) -> FuncId {
+        self.desc.id
+    }
+
+    /// Gives the function to the dataflow
+    pub fn insert<T, R>(&self, input: &mut InputManager<T, R>)
+    where
+        T: Timestamp + Lattice,
+        R: Difference,
+    {
+        self.update(input, R::from(1));
+    }
+
+    /// Removes the function from the dataflow, it must have been inserted before
+    pub fn retract<T, R>(&self, input: &mut InputManager<T, R>)
+    where
+        T: Timestamp + Lattice,
+        R: Difference,
+    {
+        self.update(input, R::from(-1));
+    }
+
+    fn update<T, R>(&self, input: &mut InputManager<T, R>, diff: R)
+    where
+        T: Timestamp + Lattice,
+        R: Difference,
+    {
+        input
+            .functions
+            .update((self.desc.id, self.desc.clone()), diff.clone());
+
+        for block in self.basic_blocks.iter() {
+            input
+                .basic_blocks
+                .update((block.id, block.clone()), diff.clone());
+        }
+        for inst in self.instructions.iter() {
+            input.instructions.update(inst.clone(), diff.clone());
+        }
+        for &span in self.instruction_spans.iter() {
+            input.instruction_spans.update(span, diff.clone());
+        }
+        for &span in self.terminator_spans.iter() {
+            input.terminator_spans.update(span, diff.clone());
+        }
+        // Constants are shared between functions, the input only keeps one copy of them
+        for constant in self.constants.iter() {
+            input.constants.update(constant.clone(), diff.clone());
+        }
+    }
+}
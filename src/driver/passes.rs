@@ -0,0 +1,265 @@
+use crate::{
+    dataflow::{
+        operators::{cleanup_with_fuel, Cleanup},
+        Difference, Program,
+    },
+    driver::{Analyses, Analysis},
+    optimize::{
+        canonicalize, constant_folding, copy_propagation, equality_saturation, fuel::Fuel,
+        merge_functions, peephole, schedule,
+    },
+};
+use differential_dataflow::lattice::Lattice;
+use std::fmt::{self, Display};
+use timely::dataflow::Scope;
+
+/// The registry of optimization passes that can be run over a [`Program`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Pass {
+    Canonicalize,
+    ConstantFolding,
+    ConditionalConstantPropagation,
+    EqualitySaturation,
+    CopyPropagation,
+    Peephole,
+    CullUnreachableBlocks,
+    CompactBasicBlocks,
+    MergeFunctions,
+    Cleanup,
+    Schedule,
+}
+
+impl Pass {
+    /// Every registered pass in the order they're usually run in
+    ///
+    /// Every pass keeps each variable defined exactly once. Lowerings like
+    /// [tail call elimination](crate::optimize::tail_call) and
+    /// [if-conversion](crate::optimize::if_conversion) assign to variables more than
+    /// once, which the verifier reports as redeclarations and which breaks passes like
+    /// sccp that give each variable a single value. They aren't registered here and
+    /// are instead applied once all of these have run, while emitting a program
+    pub const ALL: &'static [Self] = &[
+        Self::Canonicalize,
+        Self::ConstantFolding,
+        Self::ConditionalConstantPropagation,
+        Self::EqualitySaturation,
+        Self::CopyPropagation,
+        Self::Peephole,
+        Self::CullUnreachableBlocks,
+        Self::CompactBasicBlocks,
+        Self::MergeFunctions,
+        Self::Cleanup,
+        Self::Schedule,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Canonicalize => "canonicalize",
+            Self::ConstantFolding => "constant-folding",
+            Self::ConditionalConstantPropagation => "sccp",
+            Self::EqualitySaturation => "equality-saturation",
+            Self::CopyPropagation => "copy-propagation",
+            Self::Peephole => "peephole",
+            Self::CullUnreachableBlocks => "cull-unreachable-blocks",
+            Self::CompactBasicBlocks => "compact-basic-blocks",
+            Self::MergeFunctions => "merge-functions",
+            Self::Cleanup => "cleanup",
+            Self::Schedule => "schedule",
+        }
+    }
+
+    pub const fn description(&self) -> &'static str {
+        match self {
+            Self::Canonicalize => "orders the operands of commutative instructions canonically",
+            Self::ConstantFolding => "evaluates constant expressions and branches",
+            Self::ConditionalConstantPropagation => {
+                "propagates constants through the blocks that can actually be executed"
+            }
+            Self::EqualitySaturation => {
+                "replaces integer arithmetic with the cheapest equivalent an e-graph finds"
+            }
+            Self::CopyPropagation => {
+                "replaces uses of copied variables with their sources and removes the copies"
+            }
+            Self::Peephole => "applies local algebraic simplifications to instructions",
+            Self::CullUnreachableBlocks => "removes blocks that can't be reached from an entry",
+            Self::CompactBasicBlocks => "merges blocks that unconditionally jump to each other",
+            Self::MergeFunctions => "deduplicates functions that are identical up to their ids",
+            Self::Cleanup => "removes unused instructions, blocks and functions",
+            Self::Schedule => "orders instructions so values are computed right before their use",
+        }
+    }
+
+    /// Returns `true` if the pass looks across function boundaries, interprocedural
+    /// passes are given the program as is instead of having it
+    /// [partitioned by function](crate::dataflow::Partitioning::ByFunction)
+    pub const fn is_interprocedural(&self) -> bool {
+        matches!(self, Self::MergeFunctions)
+    }
+
+    /// The analyses the pass reads from the [`Analyses`] it's given
+    pub const fn required_analyses(&self) -> &'static [Analysis] {
+        match self {
+            Self::ConditionalConstantPropagation | Self::CopyPropagation | Self::Schedule => {
+                &[Analysis::UseDef]
+            }
+
+            Self::Canonicalize
+            | Self::ConstantFolding
+            | Self::EqualitySaturation
+            | Self::Peephole
+            | Self::CullUnreachableBlocks
+            | Self::CompactBasicBlocks
+            | Self::MergeFunctions
+            | Self::Cleanup => &[],
+        }
+    }
+
+    /// The analyses that may no longer describe the program once the pass has been
+    /// applied to it
+    pub const fn invalidated_analyses(&self) -> &'static [Analysis] {
+        match self {
+            // Reordering operands or instructions doesn't change what's used where
+            Self::Canonicalize | Self::Schedule => &[],
+
+            Self::EqualitySaturation | Self::CopyPropagation | Self::Peephole => {
+                &[Analysis::UseDef]
+            }
+
+            // Folded branches change the control flow graph
+            Self::ConstantFolding
+            | Self::ConditionalConstantPropagation
+            | Self::CompactBasicBlocks => &[Analysis::Dominators, Analysis::UseDef],
+
+            // Removing blocks, functions or calls changes everything
+            Self::CullUnreachableBlocks | Self::MergeFunctions | Self::Cleanup => Analysis::ALL,
+        }
+    }
+
+    /// Looks up a pass by its [name](Pass::name)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|pass| pass.name() == name)
+    }
+
+    /// Applies the pass to a program, returning the transformed program
+    pub fn apply<S, R>(&self, scope: &mut S, program: &Program<S, R>) -> Program<S, R>
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+        R: Difference,
+    {
+        self.apply_with(scope, program, &mut Analyses::new())
+    }
+
+    /// Applies the pass like [`Pass::apply()`], reading its
+    /// [required analyses](Pass::required_analyses) from `analyses` and building any
+    /// of them that aren't available yet
+    ///
+    /// Invalidating the analyses afterwards is up to the caller, see
+    /// [`PassManager`](crate::driver::PassManager)
+    pub fn apply_with<S, R>(
+        &self,
+        scope: &mut S,
+        program: &Program<S, R>,
+        analyses: &mut Analyses<S, R>,
+    ) -> Program<S, R>
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+        R: Difference,
+    {
+        let span = tracing::debug_span!("applying pass", pass = self.name());
+        span.in_scope(|| match self {
+            Self::Canonicalize => Program {
+                instructions: canonicalize::canonicalize_operands(&program.instructions),
+                ..program.clone()
+            },
+
+            Self::ConstantFolding => {
+                let (instructions, block_terminators) = constant_folding::constant_folding(
+                    scope,
+                    &program.instructions,
+                    &program.block_terminators,
+                );
+
+                Program {
+                    instructions,
+                    block_terminators,
+                    ..program.clone()
+                }
+            }
+
+            Self::ConditionalConstantPropagation => {
+                constant_folding::sccp_with(scope, program, analyses.use_def(program))
+            }
+            Self::EqualitySaturation => equality_saturation::equality_saturation(scope, program),
+            Self::CopyPropagation => {
+                copy_propagation::copy_propagation_with(scope, program, analyses.use_def(program))
+            }
+
+            Self::Peephole => Program {
+                instructions: peephole::peephole(scope, &program.instructions),
+                ..program.clone()
+            },
+
+            Self::CullUnreachableBlocks => program.cull_unreachable_blocks(),
+            Self::CompactBasicBlocks => program.compact_basic_blocks(),
+            Self::MergeFunctions => merge_functions::merge_functions(program),
+            Self::Cleanup => program.cleanup(),
+            Self::Schedule => schedule::schedule_with(program, analyses.use_def(program)),
+        })
+    }
+
+    /// Applies the pass like [`Pass::apply_with()`], limiting the rewrites it makes to the
+    /// ones there's fuel for
+    ///
+    /// Canonicalization, constant folding, sccp, equality saturation, copy propagation, peephole and the dead
+    /// code elimination done by cleanup consume fuel, every other pass always runs to
+    /// completion
+    pub fn apply_fueled<S, R>(
+        &self,
+        scope: &mut S,
+        program: &Program<S, R>,
+        analyses: &mut Analyses<S, R>,
+        fuel: &mut Fuel<S, R>,
+    ) -> Program<S, R>
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+        R: Difference,
+    {
+        match self {
+            Self::Canonicalize
+            | Self::ConstantFolding
+            | Self::ConditionalConstantPropagation
+            | Self::EqualitySaturation
+            | Self::CopyPropagation
+            | Self::Peephole => {
+                let output = self.apply_with(scope, program, analyses);
+
+                Program {
+                    instructions: fuel.limit(&program.instructions, &output.instructions),
+                    block_terminators: fuel
+                        .limit(&program.block_terminators, &output.block_terminators),
+                    ..program.clone()
+                }
+            }
+
+            Self::Cleanup => {
+                let span = tracing::debug_span!("applying pass", pass = self.name());
+                span.in_scope(|| cleanup_with_fuel(program, fuel))
+            }
+
+            Self::CullUnreachableBlocks
+            | Self::CompactBasicBlocks
+            | Self::MergeFunctions
+            | Self::Schedule => self.apply_with(scope, program, analyses),
+        }
+    }
+}
+
+impl Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
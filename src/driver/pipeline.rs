@@ -0,0 +1,835 @@
+use crate::{
+    builder::Context,
+    dataflow::{
+        instruction_counts, instruction_functions, opt_summaries,
+        panics::{self, PanicContext},
+        partition_by_function, pass_stats, sanitize, verify_program, Budget, BudgetExceeded, Diff,
+        EpochTimestamp, InputManager, InstructionChange, IrDelta, OptSummary, Partitioning,
+        PassStats, Program, ProgramTrace, ProgramVariable, SanitizerDiagnostic, Shared, Time,
+        TraceManager,
+    },
+    driver::{Analyses, Pass, PassManager, Step},
+    optimize::{cost::DefaultCostModel, fuel::Fuel, rewrites},
+    repr::{
+        function::FunctionDesc, BasicBlock, BasicBlockId, ConstId, Constant, FuncId, Function,
+        InstId, Span,
+    },
+    verify::{typecheck, verify, TypeError, ValidityError},
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{
+        arrange::{ArrangeByKey, ArrangeBySelf, TraceAgent},
+        iterate::Variable,
+        Consolidate, Join, Reduce,
+    },
+    trace::{
+        implementations::ord::{OrdKeySpine, OrdValSpine},
+        TraceReader,
+    },
+    Collection,
+};
+use std::sync::Arc;
+use timely::{
+    communication::Allocate,
+    dataflow::{scopes::Child, ProbeHandle, Scope},
+    order::Product,
+    progress::frontier::AntichainRef,
+    worker::Worker,
+};
+
+/// The name of the [`TraceManager`] entry holding the reconstructed functions
+pub const FUNCTIONS_TRACE: &str = "pipeline/functions";
+
+/// The name of the [`TraceManager`] entry holding the validity errors of the input
+pub const ERRORS_TRACE: &str = "pipeline/errors";
+
+/// The name of the [`TraceManager`] entry holding the type errors of the input
+pub const TYPE_ERRORS_TRACE: &str = "pipeline/type_errors";
+
+/// The name of the [`TraceManager`] entry holding the contents of pooled constants
+pub const CONSTANTS_TRACE: &str = "pipeline/constants";
+
+/// The name of the [`TraceManager`] entry holding the statistics of each pass
+pub const STATS_TRACE: &str = "pipeline/stats";
+
+/// The name of the [`TraceManager`] entry holding the per function summary of each pass
+pub const SUMMARIES_TRACE: &str = "pipeline/summaries";
+
+/// The name of the [`TraceManager`] entry holding the functions that went over budget
+pub const BUDGET_TRACE: &str = "pipeline/budget";
+
+/// The name of the [`TraceManager`] entry holding the diagnostics of the sanitizer
+pub const SANITIZER_TRACE: &str = "pipeline/sanitizer";
+
+pub type FunctionTrace = TraceAgent<OrdValSpine<FuncId, Function, Time, Diff>>;
+pub type ErrorTrace = TraceAgent<OrdKeySpine<ValidityError, Time, Diff>>;
+pub type TypeErrorTrace = TraceAgent<OrdKeySpine<TypeError, Time, Diff>>;
+pub type ConstantTrace = TraceAgent<OrdValSpine<ConstId, Constant, Time, Diff>>;
+pub type StatsTrace = TraceAgent<OrdKeySpine<PassStats, Time, Diff>>;
+pub type SummaryTrace = TraceAgent<OrdValSpine<FuncId, OptSummary, Time, Diff>>;
+pub type BudgetTrace = TraceAgent<OrdKeySpine<BudgetExceeded, Time, Diff>>;
+pub type SanitizerTrace = TraceAgent<OrdKeySpine<SanitizerDiagnostic, Time, Diff>>;
+
+/// Assembles the dataflows needed to optimize a program from a list of passes
+///
+/// ```rust,ignore
+/// let mut handles = Pipeline::new(context)
+///     .add_pass(Pass::ConstantFolding)
+///     .add_pass(Pass::CopyPropagation)
+///     .add_pass(Pass::Peephole)
+///     .add_pass(Pass::Cleanup)
+///     .build(worker);
+///
+/// builder.finish(&mut handles.input, 0)?;
+/// handles.advance_to(1);
+/// handles.step_until_complete(worker);
+///
+/// // Once the results for the first epoch have been read
+/// handles.compact(worker, 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    context: Arc<Context>,
+    passes: Vec<Pass>,
+    fixpoint: bool,
+    stats: bool,
+    fuel: Option<usize>,
+    partitioning: Partitioning,
+    budget: Budget,
+    sanitize: bool,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline which runs its passes until the program stops changing
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            context,
+            passes: Vec::new(),
+            fixpoint: true,
+            stats: false,
+            fuel: None,
+            partitioning: Partitioning::default(),
+            budget: Budget::unlimited(),
+            sanitize: false,
+        }
+    }
+
+    /// Adds a pass to the end of the pipeline
+    pub fn add_pass(mut self, pass: Pass) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    /// Sets whether the passes are iterated until the program reaches a fixpoint
+    /// or are only run once
+    pub fn fixpoint(mut self, fixpoint: bool) -> Self {
+        self.fixpoint = fixpoint;
+        self
+    }
+
+    /// Sets whether [`PassStats`] and per function [`OptSummary`]s are collected for
+    /// each pass, which requires consolidating the input and output of every pass
+    pub fn stats(mut self, stats: bool) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Limits the number of rewrites the passes can make to the given budget, see
+    /// [`Fuel`]
+    ///
+    /// Fueled pipelines only run their passes once, since every iteration towards a
+    /// fixpoint would otherwise get a fresh budget
+    pub fn fuel(mut self, fuel: Option<usize>) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    /// Sets how the program's data is placed onto workers, see [`Partitioning`]
+    pub fn partitioning(mut self, partitioning: Partitioning) -> Self {
+        self.partitioning = partitioning;
+        self
+    }
+
+    /// Limits the size of every function within the pipeline, see [`Budget`]
+    ///
+    /// Functions that go over budget are reported within
+    /// [`PipelineHandles::budget`] instead of being optimized further
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Sets whether every pass is checked for introducing validity errors or making
+    /// functions more expensive, see [`sanitize()`]
+    ///
+    /// Diagnostics are logged as warnings as soon as they're found and collected
+    /// within [`PipelineHandles::sanitizer`]. Iterating towards a fixpoint retracts
+    /// the diagnostics of earlier iterations, so only pipelines that run their passes
+    /// once keep every diagnostic within the trace
+    ///
+    /// This isn't tied to `debug_assertions` since passes tend to misbehave on large
+    /// real-world programs that are only practical to optimize with release builds,
+    /// sanitizing stays opt-in instead
+    pub fn sanitize(mut self, sanitize: bool) -> Self {
+        self.sanitize = sanitize;
+        self
+    }
+
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// Builds the pipeline's dataflows on the given worker
+    ///
+    /// Every update given to the returned inputs, including retractions, flows
+    /// through the passes and into the returned traces
+    pub fn build<A>(&self, worker: &mut Worker<A>) -> PipelineHandles
+    where
+        A: Allocate,
+    {
+        let span = tracing::info_span!("building pipeline", passes = self.passes.len());
+        let _guard = span.enter();
+
+        let mut probe = ProbeHandle::new();
+        let mut trace_manager = TraceManager::new().with_resolver(self.context.clone());
+
+        let (mut input, errors, type_errors) = worker.dataflow_named("pipeline inputs", |scope| {
+            let mut input = InputManager::<Time, Diff>::new(scope);
+
+            let imported = input.import(scope);
+            let (instructions, basic_blocks, functions) = (
+                imported.instructions(),
+                imported.basic_blocks(),
+                imported.functions(),
+            );
+
+            let errors = verify(scope, &instructions, &basic_blocks, &functions)
+                .probe_with(&mut probe)
+                .arrange_by_self();
+            let type_errors = typecheck(scope, &instructions, &basic_blocks, &functions)
+                .probe_with(&mut probe)
+                .arrange_by_self();
+
+            (input, errors.trace, type_errors.trace)
+        });
+
+        let (passes, fixpoint, collect_stats, fuel, partitioning, budget, sanitize_passes) = (
+            &self.passes,
+            self.fixpoint,
+            self.stats,
+            self.fuel,
+            self.partitioning,
+            (!self.budget.is_unlimited()).then(|| self.budget),
+            self.sanitize,
+        );
+        let (mut program, stats, summaries, budget, sanitizer) =
+            worker.dataflow_named("pipeline passes", |scope| {
+                let program = input.import_program(scope);
+
+                // Functions that are over budget from the start are left untouched
+                let (program, oversized, mut exceeded) = match budget {
+                    Some(budget) => {
+                        let (program, oversized, exceeded) = budget.split_oversized(&program);
+                        (program, Some(oversized), Some(exceeded))
+                    }
+                    None => (program, None, None),
+                };
+
+                let (program, reports, pass_exceeded, diagnostics) = if fixpoint && fuel.is_none() {
+                    scope.scoped::<Product<Time, Time>, _, _>("optimization", |scope| {
+                        let baseline = program.enter(scope);
+                        let variables = program_variable(scope, &program);
+
+                        let (result, reports, exceeded, diagnostics) = apply_passes(
+                            scope,
+                            passes,
+                            &variables.program(),
+                            collect_stats,
+                            None,
+                            partitioning,
+                            budget.as_ref().map(|budget| (budget, &baseline)),
+                            sanitize_passes,
+                        );
+                        variables.set(&result);
+
+                        let reports =
+                            reports.map(|(stats, summaries)| (stats.leave(), summaries.leave()));
+                        (
+                            result.leave(),
+                            reports,
+                            exceeded.map(|exceeded| exceeded.leave()),
+                            diagnostics.map(|diagnostics| diagnostics.leave()),
+                        )
+                    })
+                } else {
+                    apply_passes(
+                        scope,
+                        passes,
+                        &program,
+                        collect_stats,
+                        fuel,
+                        partitioning,
+                        budget.as_ref().map(|budget| (budget, &program)),
+                        sanitize_passes,
+                    )
+                };
+
+                let program = match oversized {
+                    Some(oversized) => program.concat(&oversized),
+                    None => program,
+                };
+                if let (Some(exceeded), Some(pass_exceeded)) = (exceeded.as_mut(), pass_exceeded) {
+                    *exceeded = exceeded.concat(&pass_exceeded);
+                }
+                let budget = exceeded.map(|exceeded| {
+                    exceeded
+                        .consolidate()
+                        .probe_with(&mut probe)
+                        .arrange_by_self()
+                        .trace
+                });
+
+                let sanitizer = diagnostics.map(|diagnostics| {
+                    diagnostics
+                        .consolidate()
+                        .probe_with(&mut probe)
+                        .arrange_by_self()
+                        .trace
+                });
+
+                let (stats, summaries) = match reports {
+                    Some((stats, summaries)) => (
+                        Some(stats.probe_with(&mut probe).arrange_by_self().trace),
+                        Some(summaries.probe_with(&mut probe).arrange_by_key().trace),
+                    ),
+                    None => (None, None),
+                };
+                (
+                    program.probe_with(&mut probe).arrange_by_key().trace(),
+                    stats,
+                    summaries,
+                    budget,
+                    sanitizer,
+                )
+            });
+
+        let functions = worker.dataflow_named("pipeline outputs", |scope| {
+            let program = program.import(scope).as_collection();
+            let spans = input.import_spans(scope);
+
+            reconstruct_functions(&program, &spans)
+                .probe_with(&mut probe)
+                .map(|function| (function.id, function))
+                .arrange_by_key()
+                .trace
+        });
+
+        let interner = self.context.interner();
+        trace_manager.insert_trace(interner.get_or_intern_static(ERRORS_TRACE), errors.clone());
+        trace_manager.insert_trace(
+            interner.get_or_intern_static(TYPE_ERRORS_TRACE),
+            type_errors.clone(),
+        );
+        trace_manager.insert_trace(
+            interner.get_or_intern_static(FUNCTIONS_TRACE),
+            functions.clone(),
+        );
+        trace_manager.insert_trace(
+            interner.get_or_intern_static(CONSTANTS_TRACE),
+            input.constant_trace.clone(),
+        );
+        if let Some(stats) = stats.clone() {
+            trace_manager.insert_trace(interner.get_or_intern_static(STATS_TRACE), stats);
+        }
+        if let Some(summaries) = summaries.clone() {
+            trace_manager.insert_trace(interner.get_or_intern_static(SUMMARIES_TRACE), summaries);
+        }
+        if let Some(budget) = budget.clone() {
+            trace_manager.insert_trace(interner.get_or_intern_static(BUDGET_TRACE), budget);
+        }
+        if let Some(sanitizer) = sanitizer.clone() {
+            trace_manager.insert_trace(interner.get_or_intern_static(SANITIZER_TRACE), sanitizer);
+        }
+
+        PipelineHandles {
+            input,
+            probe,
+            trace_manager,
+            program,
+            functions,
+            errors,
+            type_errors,
+            stats,
+            summaries,
+            budget,
+            sanitizer,
+        }
+    }
+}
+
+/// The inputs and outputs of a built [`Pipeline`]
+pub struct PipelineHandles {
+    pub input: InputManager<Time, Diff>,
+    pub probe: ProbeHandle<Time>,
+    pub trace_manager: TraceManager<Time>,
+    pub program: ProgramTrace<Time, Diff>,
+    /// The optimized functions keyed by their ids
+    pub functions: FunctionTrace,
+    /// The validity errors found within the input program
+    pub errors: ErrorTrace,
+    /// The type errors found within the input program
+    pub type_errors: TypeErrorTrace,
+    /// The statistics of each pass, if the pipeline [collects them](Pipeline::stats)
+    pub stats: Option<StatsTrace>,
+    /// The changes each pass made to each function, if the pipeline
+    /// [collects statistics](Pipeline::stats)
+    pub summaries: Option<SummaryTrace>,
+    /// The functions that went over budget, if the pipeline has a [`Budget`]
+    pub budget: Option<BudgetTrace>,
+    /// The passes that broke or pessimized a function, if the pipeline
+    /// [sanitizes them](Pipeline::sanitize)
+    pub sanitizer: Option<SanitizerTrace>,
+}
+
+impl PipelineHandles {
+    pub fn time(&self) -> Time {
+        *self.input.time()
+    }
+
+    /// Advances the inputs to the given time, making every earlier update visible
+    /// to the pipeline
+    ///
+    /// This doesn't compact any traces, which would keep [deltas](Self::delta)
+    /// between earlier epochs from being taken. Pipelines that are advanced over
+    /// many epochs have to call [`PipelineHandles::compact()`] (or
+    /// [`Driver::compact()`](crate::driver::Driver::compact)) once they're done
+    /// reading an epoch, otherwise their traces keep every update they were ever given
+    pub fn advance_to(&mut self, time: Time) {
+        self.input.advance_to(time);
+    }
+
+    /// Reclaims the memory held by every update from before `up_to`, advancing
+    /// the inputs to it if they're behind
+    ///
+    /// Every input, program, output and managed trace is compacted, and the worker
+    /// is stepped until the pipeline catches up so that the batches made
+    /// unreachable by the compaction are merged away and dropped. Times before
+    /// `up_to` can no longer be distinguished afterwards
+    pub fn compact<A>(&mut self, worker: &mut Worker<A>, up_to: Time)
+    where
+        A: Allocate,
+    {
+        let span = tracing::info_span!("compacting pipeline", up_to);
+        let _guard = span.enter();
+
+        if self.time() < up_to {
+            self.advance_to(up_to);
+        }
+
+        let frontier = [up_to];
+        let frontier = AntichainRef::new(&frontier);
+        self.input.compact(frontier);
+        self.trace_manager.advance_by(frontier);
+        self.trace_manager.distinguish_since(frontier);
+        self.program.advance_by(frontier);
+        self.program.distinguish_since(frontier);
+        self.functions.set_logical_compaction(frontier);
+        self.functions.set_physical_compaction(frontier);
+        self.errors.set_logical_compaction(frontier);
+        self.errors.set_physical_compaction(frontier);
+        self.type_errors.set_logical_compaction(frontier);
+        self.type_errors.set_physical_compaction(frontier);
+        if let Some(stats) = self.stats.as_mut() {
+            stats.set_logical_compaction(frontier);
+            stats.set_physical_compaction(frontier);
+        }
+        if let Some(summaries) = self.summaries.as_mut() {
+            summaries.set_logical_compaction(frontier);
+            summaries.set_physical_compaction(frontier);
+        }
+        if let Some(budget) = self.budget.as_mut() {
+            budget.set_logical_compaction(frontier);
+            budget.set_physical_compaction(frontier);
+        }
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.set_logical_compaction(frontier);
+            sanitizer.set_physical_compaction(frontier);
+        }
+
+        self.step_until_complete(worker);
+    }
+
+    /// Collects the changes made to the optimized program between two epochs, see
+    /// [`ProgramTrace::delta()`]
+    pub fn delta(&mut self, from: Time, to: Time) -> Option<IrDelta<Time>> {
+        self.program.delta(from, to)
+    }
+
+    /// Steps the worker until every output has caught up with the inputs
+    pub fn step_until_complete<A>(&self, worker: &mut Worker<A>)
+    where
+        A: Allocate,
+    {
+        panics::with_context(PanicContext::stage("stepping the pipeline"), || {
+            while self.probe.less_than(self.input.time()) {
+                worker.step_or_park(None);
+            }
+        });
+    }
+}
+
+/// Applies each pass to the program in order, building the analyses they share
+/// as laid out by a [`PassManager`], optionally collecting statistics and
+/// per function summaries of the changes each one makes, limiting the rewrites
+/// they make to a fuel budget, holding their output to a [`Budget`] measured
+/// against a baseline program and [sanitizing](sanitize()) each of them
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn apply_passes<S>(
+    scope: &mut S,
+    passes: &[Pass],
+    program: &Program<S, Diff>,
+    collect_stats: bool,
+    fuel: Option<usize>,
+    partitioning: Partitioning,
+    budget: Option<(&Budget, &Program<S, Diff>)>,
+    sanitize_passes: bool,
+) -> (
+    Program<S, Diff>,
+    Option<(
+        Collection<S, PassStats, Diff>,
+        Collection<S, (FuncId, OptSummary), Diff>,
+    )>,
+    Option<Collection<S, BudgetExceeded, Diff>>,
+    Option<Collection<S, SanitizerDiagnostic, Diff>>,
+)
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord + EpochTimestamp,
+{
+    let mut reports: Option<(
+        Collection<S, PassStats, Diff>,
+        Collection<S, (FuncId, OptSummary), Diff>,
+    )> = None;
+    let mut fuel = fuel.map(|budget| Fuel::new(scope, budget));
+    let budget = budget.map(|(budget, baseline)| (budget, instruction_counts(baseline)));
+    let mut exceeded: Option<Collection<S, BudgetExceeded, Diff>> = None;
+    let mut diagnostics: Option<Collection<S, SanitizerDiagnostic, Diff>> = None;
+    let mut verified: Option<Collection<S, ValidityError, Diff>> = None;
+
+    let mut analyses = Analyses::new();
+    let mut output = program.clone();
+    for step in PassManager::from_passes(passes).schedule() {
+        let pass = match step {
+            Step::Build(analysis) => {
+                analyses.build(analysis, &output);
+                continue;
+            }
+            Step::Invalidate(analysis) => {
+                analyses.invalidate(analysis);
+                continue;
+            }
+            Step::Run(pass) => pass,
+        };
+
+        let input = match partitioning {
+            Partitioning::ByFunction if !pass.is_interprocedural() => {
+                partition_by_function(&output)
+            }
+            Partitioning::ByFunction | Partitioning::Hashed => output,
+        };
+        output = panics::with_context(
+            PanicContext::stage("building the pipeline").with_pass(pass.name()),
+            || match fuel.as_mut() {
+                Some(fuel) => pass.apply_fueled(scope, &input, &mut analyses, fuel),
+                None => pass.apply_with(scope, &input, &mut analyses),
+            },
+        );
+
+        rewrites::log_rewrites(pass.name(), &input, &output);
+
+        if let Some((budget, baseline)) = budget.as_ref() {
+            let (enforced, pass_exceeded) = budget.enforce(pass.name(), baseline, &input, &output);
+            output = enforced;
+            exceeded = Some(match exceeded {
+                Some(exceeded) => exceeded.concat(&pass_exceeded),
+                None => pass_exceeded,
+            });
+        }
+
+        if sanitize_passes {
+            // The input of every pass but the first is the output of the one before
+            // it, which has already been verified
+            let input_errors = verified
+                .take()
+                .unwrap_or_else(|| verify_program(scope, &input));
+            let (pass_diagnostics, output_errors) = sanitize(
+                scope,
+                pass.name(),
+                DefaultCostModel,
+                &input,
+                &output,
+                &input_errors,
+            );
+            verified = Some(output_errors);
+
+            let pass_diagnostics = pass_diagnostics.inspect(|(diagnostic, _, diff)| {
+                if *diff > 0 {
+                    tracing::warn!(pass = diagnostic.pass(), "{}", diagnostic);
+                }
+            });
+
+            diagnostics = Some(match diagnostics {
+                Some(diagnostics) => diagnostics.concat(&pass_diagnostics),
+                None => pass_diagnostics,
+            });
+        }
+
+        if collect_stats {
+            let pass_stats = pass_stats(&program_changes(&input, &output), pass.name());
+            let summaries = opt_summaries(&instruction_changes(&input, &output), pass.name());
+
+            reports = Some(match reports {
+                Some((stats, all_summaries)) => {
+                    (stats.concat(&pass_stats), all_summaries.concat(&summaries))
+                }
+                None => (pass_stats, summaries),
+            });
+        }
+    }
+
+    (output.consolidate(), reports, exceeded, diagnostics)
+}
+
+/// The updates that turned `input` into `output`, with one unit for each changed tuple
+fn program_changes<S>(
+    input: &Program<S, Diff>,
+    output: &Program<S, Diff>,
+) -> Collection<S, (), Diff>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+{
+    let instructions = output
+        .instructions
+        .concat(&input.instructions.negate())
+        .consolidate()
+        .map(|_| ());
+    let block_instructions = output
+        .block_instructions
+        .concat(&input.block_instructions.negate())
+        .consolidate()
+        .map(|_| ());
+    let block_terminators = output
+        .block_terminators
+        .concat(&input.block_terminators.negate())
+        .consolidate()
+        .map(|_| ());
+    let block_descriptors = output
+        .block_descriptors
+        .concat(&input.block_descriptors.negate())
+        .consolidate()
+        .map(|_| ());
+    let function_blocks = output
+        .function_blocks
+        .concat(&input.function_blocks.negate())
+        .consolidate()
+        .map(|_| ());
+    let function_descriptors = output
+        .function_descriptors
+        .concat(&input.function_descriptors.negate())
+        .consolidate()
+        .map(|_| ());
+
+    instructions
+        .concat(&block_instructions)
+        .concat(&block_terminators)
+        .concat(&block_descriptors)
+        .concat(&function_blocks)
+        .concat(&function_descriptors)
+}
+
+/// How each instruction changed between `input` and `output`, attributed to the
+/// function it belonged to before removals and after additions and rewrites
+fn instruction_changes<S>(
+    input: &Program<S, Diff>,
+    output: &Program<S, Diff>,
+) -> Collection<S, (FuncId, InstructionChange), Diff>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+{
+    let changes = input
+        .instructions
+        .map(|(id, inst)| (id, (false, inst)))
+        .concat(&output.instructions.map(|(id, inst)| (id, (true, inst))))
+        .reduce(|_id, versions, changes| {
+            let change = match versions {
+                [((false, _), _)] => InstructionChange::Removed,
+                [((true, _), _)] => InstructionChange::Added,
+                [((_, before), _), ((_, after), _)] if before != after => {
+                    InstructionChange::Rewritten
+                }
+                _ => return,
+            };
+
+            changes.push((change, 1));
+        });
+
+    let removed = changes
+        .filter(|&(_, change)| change == InstructionChange::Removed)
+        .join_map(&instruction_functions(input), |_id, &change, &func| {
+            (func, change)
+        });
+    let changed = changes
+        .filter(|&(_, change)| change != InstructionChange::Removed)
+        .join_map(&instruction_functions(output), |_id, &change, &func| {
+            (func, change)
+        });
+
+    removed.concat(&changed)
+}
+
+/// Brings a program into an iterative scope as a set of variables seeded with it
+fn program_variable<'a, S>(
+    scope: &mut Child<'a, S, Product<Time, Time>>,
+    program: &Program<S, Diff>,
+) -> ProgramVariable<Child<'a, S, Product<Time, Time>>, Diff>
+where
+    S: Scope<Timestamp = Time>,
+{
+    let program = program.enter(scope);
+    let summary = Product::new(Default::default(), 1);
+
+    ProgramVariable::new(
+        Variable::new_from(program.instructions, summary),
+        Variable::new_from(program.block_instructions, summary),
+        Variable::new_from(program.block_terminators, summary),
+        Variable::new_from(program.block_descriptors, summary),
+        Variable::new_from(program.function_blocks, summary),
+        Variable::new_from(program.function_descriptors, summary),
+    )
+}
+
+/// Reassembles the functions held within a program, along with the spans of their
+/// instructions and terminators
+#[allow(clippy::type_complexity)]
+fn reconstruct_functions<S>(
+    program: &Program<S, Diff>,
+    (instruction_spans, terminator_spans): &(
+        Collection<S, (InstId, Span), Diff>,
+        Collection<S, (BasicBlockId, Span), Diff>,
+    ),
+) -> Collection<S, Function, Diff>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+{
+    // Instructions are shared so that the joins and reductions below only copy them
+    // once, when they're placed into their finished blocks
+    let located = program
+        .block_instructions
+        .join_map(&program.instructions, |&inst_id, &block, inst| {
+            (inst_id, (block, Shared::new(inst.clone())))
+        });
+    let located = located
+        .join_map(instruction_spans, |&inst_id, (block, inst), &span| {
+            (inst_id, (*block, (inst.clone(), Some(span))))
+        })
+        .concat(
+            &located
+                .antijoin(&instruction_spans.map(|(inst_id, _)| inst_id))
+                .map(|(inst_id, (block, inst))| (inst_id, (block, (inst, None)))),
+        );
+    let block_contents = program
+        .ordered_block_contents(&located)
+        .map(|(block, contents)| {
+            let instructions: Vec<_> = contents.iter().map(|(inst, _)| inst.clone()).collect();
+
+            // Blocks without any spans keep an empty span list, like blocks that
+            // were built by hand
+            let spans: Vec<_> = if contents.iter().any(|(_, span)| span.is_some()) {
+                contents.iter().map(|&(_, span)| span).collect()
+            } else {
+                Vec::new()
+            };
+
+            (block, (instructions, spans))
+        });
+
+    let terminators = program
+        .block_terminators
+        .join_map(terminator_spans, |&block, terminator, &span| {
+            (block, (terminator.clone(), Some(span)))
+        })
+        .concat(
+            &program
+                .block_terminators
+                .antijoin(&terminator_spans.map(|(block, _)| block))
+                .map(|(block, terminator)| (block, (terminator, None))),
+        );
+
+    let blocks = block_contents
+        .join(&terminators)
+        .join_map(
+            &program.block_descriptors,
+            |&id, ((instructions, spans), (terminator, terminator_span)), desc| {
+                let block = BasicBlock {
+                    name: desc.name,
+                    id,
+                    instructions: instructions.iter().map(|inst| (**inst).clone()).collect(),
+                    terminator: terminator.clone(),
+                    instruction_spans: spans.clone(),
+                    terminator_span: *terminator_span,
+                };
+
+                (id, block)
+            },
+        )
+        .join_map(&program.function_blocks, |_id, block, &func| {
+            (func, block.clone())
+        })
+        .reduce(|_func, input, output| {
+            let blocks: Vec<_> = input
+                .iter()
+                .map(|(block, _diff)| (*block).clone())
+                .collect();
+            output.push((blocks, 1));
+        });
+
+    let function = |id: FuncId, desc: &FunctionDesc, basic_blocks: Vec<BasicBlock>| Function {
+        name: desc.name,
+        id,
+        params: desc.params.clone(),
+        ret_ty: desc.ret_ty.clone(),
+        entry: desc.entry,
+        basic_blocks,
+        metadata: desc.metadata(),
+    };
+
+    // External functions are only declarations, so they have no blocks to join with
+    let externals = program
+        .function_descriptors
+        .filter(|(_, desc)| desc.attributes.is_external())
+        .map(move |(id, desc)| function(id, &desc, Vec::new()));
+
+    program
+        .function_descriptors
+        .join_map(&blocks, move |&id, desc, blocks| {
+            // Keep blocks in their original order
+            let mut basic_blocks = blocks.clone();
+            basic_blocks.sort_by_key(|block| {
+                desc.basic_blocks
+                    .iter()
+                    .position(|&desc_block| desc_block == block.id)
+                    .unwrap_or_else(|| desc.basic_blocks.len())
+            });
+
+            function(id, desc, basic_blocks)
+        })
+        .concat(&externals)
+}
@@ -0,0 +1,609 @@
+#![cfg(feature = "cranelift")]
+
+//! Translation of sruth ir into Cranelift ir
+//!
+//! Functions can be translated into any cranelift [`Module`] with
+//! [`translate_into()`], which makes them callable through a jit, or compiled into
+//! an object file for the host with [`emit_object()`]. Every function is exported
+//! under its name and [external](crate::repr::FunctionAttributes::EXTERNAL)
+//! functions are imported, calls can only target functions that are being
+//! translated
+//!
+//! Every sruth variable becomes a cranelift [`Variable`], so the frontend builds
+//! the ssa form for variables assigned in multiple blocks. Booleans are held as
+//! zero or one within `i8`s and Cranelift's integer division traps just like
+//! `div` and `rem` do, aggregates and float remainders aren't supported yet
+
+use crate::{
+    repr::{
+        function::Extension,
+        instruction::{Add, Assign, Bitcast, Call, Cmp, Div, Mul, Neg, Rem, Select, Sub},
+        terminator::{Branch, Return, Switch},
+        BasicBlockId, Constant, FuncId, Function, Instruction, InstructionExt, Terminator,
+        TrapCode, Type, Value, ValueKind, VarId,
+    },
+    symbols::SymbolResolver,
+};
+use cranelift_codegen::{
+    binemit::NullTrapSink,
+    ir::{
+        self,
+        condcodes::{FloatCC, IntCC},
+        immediates::{Ieee32, Ieee64},
+        types, AbiParam, InstBuilder,
+    },
+    settings, Context,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_module::{Linkage, Module, ModuleError};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// Compiles the given functions into an object file for the host
+pub fn emit_object<R>(functions: &[Function], interner: &R) -> Result<Vec<u8>, EmitError>
+where
+    R: SymbolResolver,
+{
+    let isa = cranelift_native::builder()
+        .map_err(EmitError::Isa)?
+        .finish(settings::Flags::new(settings::builder()));
+    let builder = ObjectBuilder::new(isa, "sruth", cranelift_module::default_libcall_names())?;
+
+    let mut module = ObjectModule::new(builder);
+    translate_into(&mut module, functions, interner)?;
+
+    module
+        .finish()
+        .emit()
+        .map_err(|error| EmitError::Object(error.to_string()))
+}
+
+/// Declares every function within `module` and defines the ones with a body,
+/// returning the ids each function was declared under
+pub fn translate_into<M, R>(
+    module: &mut M,
+    functions: &[Function],
+    interner: &R,
+) -> Result<HashMap<FuncId, cranelift_module::FuncId>, EmitError>
+where
+    M: Module,
+    R: SymbolResolver,
+{
+    let mut callees = HashMap::with_capacity(functions.len());
+    for function in functions {
+        let signature = signature(module, function)?;
+        let linkage = if function.metadata.attributes.is_external() {
+            Linkage::Import
+        } else {
+            Linkage::Export
+        };
+
+        let id =
+            module.declare_function(&function_name(function, interner), linkage, &signature)?;
+        let callee = Callee {
+            id,
+            results: signature.returns.iter().map(|ret| ret.value_type).collect(),
+        };
+
+        callees.insert(function.id, callee);
+    }
+
+    let (mut context, mut builder_context) = (module.make_context(), FunctionBuilderContext::new());
+    for function in functions
+        .iter()
+        .filter(|function| !function.metadata.attributes.is_external())
+    {
+        context.func.signature = signature(module, function)?;
+        Translator::translate(
+            module,
+            &callees,
+            function,
+            &mut context,
+            &mut builder_context,
+        )?;
+
+        module.define_function(callees[&function.id].id, &mut context, &mut NullTrapSink {})?;
+        module.clear_context(&mut context);
+    }
+
+    Ok(callees
+        .into_iter()
+        .map(|(func, callee)| (func, callee.id))
+        .collect())
+}
+
+#[derive(Debug)]
+pub enum EmitError {
+    /// A function calls a function that isn't being translated
+    UnknownFunction(FuncId),
+    /// A block jumps to a block that isn't within its function
+    UnknownBlock(BasicBlockId),
+    /// A variable is used without being a parameter or the result of an instruction
+    UndefinedVar(VarId),
+    /// A value of the given type can't be held in a single cranelift value
+    UnsupportedType(Type),
+    /// The function uses a feature of the ir that can't be translated yet
+    Unsupported(&'static str),
+    /// The host isn't supported by cranelift
+    Isa(&'static str),
+    /// The cranelift module rejected a function
+    Module(ModuleError),
+    /// The object file couldn't be written
+    Object(String),
+}
+
+impl Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFunction(func) => write!(f, "call to unknown function {:?}", func),
+            Self::UnknownBlock(block) => write!(f, "jump to unknown block {:?}", block),
+            Self::UndefinedVar(var) => write!(f, "use of undefined variable {:?}", var),
+            Self::UnsupportedType(ty) => write!(f, "unsupported type {:?}", ty),
+            Self::Unsupported(feature) => write!(f, "unsupported ir feature: {}", feature),
+            Self::Isa(error) => write!(f, "unsupported host: {}", error),
+            Self::Module(error) => Display::fmt(error, f),
+            Self::Object(error) => write!(f, "failed to write object file: {}", error),
+        }
+    }
+}
+
+impl Error for EmitError {}
+
+impl From<ModuleError> for EmitError {
+    fn from(error: ModuleError) -> Self {
+        Self::Module(error)
+    }
+}
+
+/// Functions are exported under their names, unnamed ones get a name derived from their id
+fn function_name<R>(function: &Function, interner: &R) -> String
+where
+    R: SymbolResolver,
+{
+    function.name.map_or_else(
+        || format!("func{}", function.id.as_u64()),
+        |name| interner.display_symbol(name.0).to_string(),
+    )
+}
+
+/// The cranelift type values of the given type are held in
+fn value_type(ty: &Type) -> Result<ir::Type, EmitError> {
+    match ty {
+        Type::Int | Type::Uint => Ok(types::I64),
+        Type::Int128 | Type::Uint128 => Ok(types::I128),
+        Type::F32 => Ok(types::F32),
+        Type::F64 => Ok(types::F64),
+        Type::Bool => Ok(types::I8),
+        ty => Err(EmitError::UnsupportedType(ty.clone())),
+    }
+}
+
+fn signature<M>(module: &M, function: &Function) -> Result<ir::Signature, EmitError>
+where
+    M: Module,
+{
+    let mut signature = module.make_signature();
+    for (idx, param) in function.params.iter().enumerate() {
+        let attributes = function.metadata.param(idx);
+
+        // There's no memory for references to point into
+        if attributes.is_by_ref() {
+            return Err(EmitError::Unsupported("by-reference parameters"));
+        }
+
+        let param = AbiParam::new(value_type(&param.ty)?);
+        signature.params.push(match attributes.extension {
+            Extension::None => param,
+            Extension::Zero => param.uext(),
+            Extension::Sign => param.sext(),
+        });
+    }
+
+    for result in function.ret_ty.results() {
+        signature.returns.push(AbiParam::new(value_type(result)?));
+    }
+
+    Ok(signature)
+}
+
+#[derive(Debug, Clone)]
+struct Callee {
+    id: cranelift_module::FuncId,
+    results: Vec<ir::Type>,
+}
+
+/// Translates a single function body
+struct Translator<'a, M> {
+    module: &'a mut M,
+    callees: &'a HashMap<FuncId, Callee>,
+    builder: FunctionBuilder<'a>,
+    /// The variable of every parameter and instruction result
+    variables: HashMap<VarId, Variable>,
+    blocks: HashMap<BasicBlockId, ir::Block>,
+}
+
+impl<'a, M> Translator<'a, M>
+where
+    M: Module,
+{
+    fn translate(
+        module: &'a mut M,
+        callees: &'a HashMap<FuncId, Callee>,
+        function: &Function,
+        context: &'a mut Context,
+        builder_context: &'a mut FunctionBuilderContext,
+    ) -> Result<(), EmitError> {
+        let mut translator = Self {
+            module,
+            callees,
+            builder: FunctionBuilder::new(&mut context.func, builder_context),
+            variables: HashMap::new(),
+            blocks: HashMap::new(),
+        };
+
+        for param in function.params.iter() {
+            translator.declare(param.var, value_type(&param.ty)?);
+        }
+        for inst in function
+            .basic_blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter())
+        {
+            // The call's own return type may not have been inferred yet, so the
+            // callee's signature is used instead
+            let ty = match inst {
+                Instruction::Call(call) => {
+                    let callee = callees
+                        .get(&call.func)
+                        .ok_or(EmitError::UnknownFunction(call.func))?;
+
+                    match callee.results.as_slice() {
+                        [] => continue,
+                        &[result] => result,
+                        _ => return Err(EmitError::Unsupported("calls returning multiple values")),
+                    }
+                }
+                inst => value_type(&inst.dest_type())?,
+            };
+
+            translator.declare(inst.dest(), ty);
+        }
+
+        for block in function.basic_blocks.iter() {
+            let created = translator.builder.create_block();
+            translator.blocks.insert(block.id, created);
+        }
+
+        // Cranelift's entry block receives the parameters and can't be jumped to, so
+        // it gets its own block that falls through into the function's entry
+        let start = translator.builder.create_block();
+        translator
+            .builder
+            .append_block_params_for_function_params(start);
+        translator.builder.switch_to_block(start);
+        for (idx, param) in function.params.iter().enumerate() {
+            let value = translator.builder.block_params(start)[idx];
+            let variable = translator.variables[&param.var];
+            translator.builder.def_var(variable, value);
+        }
+        let entry = translator.block(function.entry)?;
+        translator.builder.ins().jump(entry, &[]);
+
+        for block in function.basic_blocks.iter() {
+            let current = translator.blocks[&block.id];
+            translator.builder.switch_to_block(current);
+
+            for inst in block.instructions.iter() {
+                translator.instruction(inst)?;
+            }
+            translator.terminator(&block.terminator)?;
+        }
+
+        translator.builder.seal_all_blocks();
+        translator.builder.finalize();
+
+        Ok(())
+    }
+
+    fn declare(&mut self, var: VarId, ty: ir::Type) {
+        let next = Variable::new(self.variables.len());
+        let builder = &mut self.builder;
+
+        self.variables.entry(var).or_insert_with(|| {
+            builder.declare_var(next, ty);
+            next
+        });
+    }
+
+    fn block(&self, block: BasicBlockId) -> Result<ir::Block, EmitError> {
+        self.blocks
+            .get(&block)
+            .copied()
+            .ok_or(EmitError::UnknownBlock(block))
+    }
+
+    fn instruction(&mut self, inst: &Instruction) -> Result<(), EmitError> {
+        match inst {
+            Instruction::Assign(Assign { value, dest, .. }) => {
+                let value = self.value(value)?;
+                self.set(*dest, value)?;
+            }
+
+            Instruction::Add(Add { lhs, rhs, dest, .. }) => {
+                let (lhs, rhs, ty) = self.operands(lhs, rhs)?;
+                let sum = if ty.is_float() {
+                    self.builder.ins().fadd(lhs, rhs)
+                } else {
+                    self.builder.ins().iadd(lhs, rhs)
+                };
+                self.set(*dest, sum)?;
+            }
+            Instruction::Sub(Sub { lhs, rhs, dest, .. }) => {
+                let (lhs, rhs, ty) = self.operands(lhs, rhs)?;
+                let difference = if ty.is_float() {
+                    self.builder.ins().fsub(lhs, rhs)
+                } else {
+                    self.builder.ins().isub(lhs, rhs)
+                };
+                self.set(*dest, difference)?;
+            }
+            Instruction::Mul(Mul { lhs, rhs, dest, .. }) => {
+                let (lhs, rhs, ty) = self.operands(lhs, rhs)?;
+                let product = if ty.is_float() {
+                    self.builder.ins().fmul(lhs, rhs)
+                } else {
+                    self.builder.ins().imul(lhs, rhs)
+                };
+                self.set(*dest, product)?;
+            }
+            Instruction::Div(Div { lhs, rhs, dest, .. }) => {
+                let unsigned = matches!(lhs.ty, Type::Uint | Type::Uint128);
+                let (lhs, rhs, ty) = self.operands(lhs, rhs)?;
+                let quotient = if ty.is_float() {
+                    self.builder.ins().fdiv(lhs, rhs)
+                } else if unsigned {
+                    self.builder.ins().udiv(lhs, rhs)
+                } else {
+                    self.builder.ins().sdiv(lhs, rhs)
+                };
+                self.set(*dest, quotient)?;
+            }
+            Instruction::Rem(Rem { lhs, rhs, dest, .. }) => {
+                if lhs.ty.is_float() {
+                    return Err(EmitError::Unsupported("float remainders"));
+                }
+
+                let unsigned = matches!(lhs.ty, Type::Uint | Type::Uint128);
+                let (lhs, rhs, _) = self.operands(lhs, rhs)?;
+                let remainder = if unsigned {
+                    self.builder.ins().urem(lhs, rhs)
+                } else {
+                    self.builder.ins().srem(lhs, rhs)
+                };
+                self.set(*dest, remainder)?;
+            }
+
+            Instruction::Neg(Neg { value, dest }) => {
+                let value = self.value(value)?;
+                let negated = if self.builder.func.dfg.value_type(value).is_float() {
+                    self.builder.ins().fneg(value)
+                } else {
+                    self.builder.ins().ineg(value)
+                };
+                self.set(*dest, negated)?;
+            }
+
+            // Floats are compared by value so NaNs never equal anything
+            Instruction::Cmp(Cmp { lhs, rhs, dest }) => {
+                let (lhs, rhs, ty) = self.operands(lhs, rhs)?;
+                let equal = if ty.is_float() {
+                    self.builder.ins().fcmp(FloatCC::Equal, lhs, rhs)
+                } else {
+                    self.builder.ins().icmp(IntCC::Equal, lhs, rhs)
+                };
+                let equal = self.builder.ins().bint(types::I8, equal);
+                self.set(*dest, equal)?;
+            }
+
+            Instruction::Select(Select {
+                cond,
+                if_true,
+                if_false,
+                dest,
+            }) => {
+                let cond = self.value(cond)?;
+                let (if_true, if_false, _) = self.operands(if_true, if_false)?;
+                let selected = self.builder.ins().select(cond, if_true, if_false);
+                self.set(*dest, selected)?;
+            }
+
+            Instruction::Call(Call {
+                func, args, dest, ..
+            }) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.value(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let callees = self.callees;
+                let callee = callees.get(func).ok_or(EmitError::UnknownFunction(*func))?;
+
+                let callee = self
+                    .module
+                    .declare_func_in_func(callee.id, self.builder.func);
+                let call = self.builder.ins().call(callee, &args);
+
+                if let Some(&result) = self.builder.inst_results(call).first() {
+                    self.set(*dest, result)?;
+                }
+            }
+
+            // Booleans are widened and narrowed between `i8` and `i64`, every other
+            // valid bitcast is between types of the same width
+            Instruction::Bitcast(Bitcast { dest, source }) => {
+                let source = self.value(source)?;
+                let (from, to) = (
+                    self.builder.func.dfg.value_type(source),
+                    value_type(&dest.ty)?,
+                );
+
+                let cast = if from == to {
+                    source
+                } else if from == types::I8 {
+                    self.builder.ins().uextend(to, source)
+                } else if to == types::I8 {
+                    self.builder.ins().ireduce(to, source)
+                } else {
+                    self.builder.ins().bitcast(to, source)
+                };
+                self.set(dest.var, cast)?;
+            }
+
+            Instruction::Opaque(_) => return Err(EmitError::Unsupported("opaque instructions")),
+            Instruction::Intrinsic(_) => return Err(EmitError::Unsupported("intrinsics")),
+            Instruction::ExtractValue(_) | Instruction::InsertValue(_) => {
+                return Err(EmitError::Unsupported("aggregates"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn terminator(&mut self, terminator: &Terminator) -> Result<(), EmitError> {
+        match terminator {
+            &Terminator::Jump(target) => {
+                let target = self.block(target)?;
+                self.builder.ins().jump(target, &[]);
+            }
+
+            Terminator::Branch(Branch {
+                cond,
+                if_true,
+                if_false,
+            }) => {
+                let cond = self.value(cond)?;
+                let (if_true, if_false) = (self.block(if_true.block)?, self.block(if_false.block)?);
+
+                self.builder.ins().brnz(cond, if_true, &[]);
+                self.builder.ins().jump(if_false, &[]);
+            }
+
+            Terminator::Switch(Switch {
+                scrutinee,
+                cases,
+                default,
+            }) => {
+                let scrutinee = self.value(scrutinee)?;
+
+                let mut switch = cranelift_frontend::Switch::new();
+                for (case, label) in cases {
+                    let case = match *case {
+                        Constant::Int(int) => int as u64,
+                        Constant::Uint(uint) => uint,
+                        Constant::Bool(bool) => bool as u64,
+                        ref case => return Err(EmitError::UnsupportedType(case.ty())),
+                    };
+
+                    switch.set_entry(case as _, self.block(label.block)?);
+                }
+
+                let default = self.block(default.block)?;
+                switch.emit(&mut self.builder, scrutinee, default);
+            }
+
+            Terminator::Return(Return { values }) => {
+                let values = values
+                    .iter()
+                    .map(|value| self.value(value))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.builder.ins().return_(&values);
+            }
+
+            Terminator::Unreachable => {
+                self.builder
+                    .ins()
+                    .trap(ir::TrapCode::UnreachableCodeReached);
+            }
+            Terminator::Trap(code) => {
+                let code = match code {
+                    TrapCode::DivisionByZero => ir::TrapCode::IntegerDivisionByZero,
+                    TrapCode::IntegerOverflow => ir::TrapCode::IntegerOverflow,
+                };
+
+                self.builder.ins().trap(code);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Translates both operands of a binary operation along with their type
+    fn operands(
+        &mut self,
+        lhs: &Value,
+        rhs: &Value,
+    ) -> Result<(ir::Value, ir::Value, ir::Type), EmitError> {
+        let (lhs, rhs) = (self.value(lhs)?, self.value(rhs)?);
+        let ty = self.builder.func.dfg.value_type(lhs);
+
+        Ok((lhs, rhs, ty))
+    }
+
+    fn value(&mut self, value: &Value) -> Result<ir::Value, EmitError> {
+        match value.value {
+            ValueKind::Var(var) => {
+                let variable = *self
+                    .variables
+                    .get(&var)
+                    .ok_or(EmitError::UndefinedVar(var))?;
+
+                Ok(self.builder.use_var(variable))
+            }
+
+            ValueKind::Const(ref constant) => self.constant(constant),
+            ValueKind::Pooled(_) => Err(EmitError::Unsupported("pooled constants")),
+        }
+    }
+
+    fn constant(&mut self, constant: &Constant) -> Result<ir::Value, EmitError> {
+        let value = match *constant {
+            Constant::Bool(bool) => self.builder.ins().iconst(types::I8, bool as i64),
+            Constant::Int(int) => self.builder.ins().iconst(types::I64, int),
+            Constant::Uint(uint) => self.builder.ins().iconst(types::I64, uint as i64),
+            Constant::Int128(int) => self.wide(int as u128),
+            Constant::Uint128(uint) => self.wide(uint),
+            Constant::F32(bits) => self.builder.ins().f32const(Ieee32::with_bits(bits)),
+            Constant::F64(bits) => self.builder.ins().f64const(Ieee64::with_bits(bits)),
+            Constant::Array(..) | Constant::Struct(_) => {
+                return Err(EmitError::UnsupportedType(constant.ty()));
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// Builds a 128-bit integer from its 64-bit halves
+    fn wide(&mut self, value: u128) -> ir::Value {
+        let low = self.builder.ins().iconst(types::I64, value as u64 as i64);
+        let high = self
+            .builder
+            .ins()
+            .iconst(types::I64, (value >> 64) as u64 as i64);
+
+        self.builder.ins().iconcat(low, high)
+    }
+
+    fn set(&mut self, dest: VarId, value: ir::Value) -> Result<(), EmitError> {
+        let variable = *self
+            .variables
+            .get(&dest)
+            .ok_or(EmitError::UndefinedVar(dest))?;
+        self.builder.def_var(variable, value);
+
+        Ok(())
+    }
+}
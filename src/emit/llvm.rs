@@ -0,0 +1,698 @@
+//! Printing of sruth ir as textual LLVM IR
+//!
+//! The output is plain `.ll` text that `llc` and `opt` accept as-is, so optimized
+//! functions can be compiled into native code or handed to LLVM to cross-check
+//! sruth's own optimizations. Every function is defined under its name while
+//! [external](crate::repr::FunctionAttributes::EXTERNAL) functions are only
+//! declared, calls can only target functions that are being emitted
+//!
+//! Variables defined by a single instruction become LLVM values directly. The few
+//! variables that are assigned more than once get a stack slot that every
+//! definition stores to and every use loads from, which `mem2reg` turns back into
+//! phis. Integer division by zero traps within sruth but is undefined within LLVM,
+//! so divisions that aren't known to succeed may behave differently once LLVM has
+//! optimized them
+
+use crate::{
+    repr::{
+        function::Extension,
+        instruction::{
+            Add, Assign, Bitcast, Call, Cmp, Div, ExtractValue, InsertValue, Mul, Neg, Rem, Select,
+            Sub,
+        },
+        terminator::{Branch, Return, Switch},
+        BasicBlock, BasicBlockId, Constant, FuncId, Function, Instruction, InstructionExt,
+        Terminator, Type, Value, ValueKind, VarId,
+    },
+    symbols::SymbolResolver,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// Prints the given functions as a single LLVM module
+pub fn emit<R>(functions: &[Function], interner: &R) -> Result<String, EmitError>
+where
+    R: SymbolResolver,
+{
+    let callees: HashMap<FuncId, Callee> = functions
+        .iter()
+        .map(|function| {
+            let callee = Callee {
+                name: global_name(&function_name(function, interner)),
+                ret_ty: function.ret_ty.clone(),
+            };
+
+            (function.id, callee)
+        })
+        .collect();
+
+    let (mut module, mut traps) = (String::new(), false);
+    for (idx, function) in functions.iter().enumerate() {
+        if idx != 0 {
+            module.push('\n');
+        }
+
+        let signature = signature(function, &callees[&function.id])?;
+        if function.metadata.attributes.is_external() {
+            module.push_str(&format!("declare {}\n", signature));
+        } else {
+            let body = Body::emit(function, &callees)?;
+            traps |= body.traps;
+
+            module.push_str(&format!("define {} {{\n{}}}\n", signature, body.code));
+        }
+    }
+
+    if traps {
+        module.push_str("\ndeclare void @llvm.trap() cold noreturn nounwind\n");
+    }
+
+    Ok(module)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmitError {
+    /// A function calls a function that isn't being emitted
+    UnknownFunction(FuncId),
+    /// A block jumps to a block that isn't within its function
+    UnknownBlock(BasicBlockId),
+    /// A variable is used without being a parameter or the result of an instruction
+    UndefinedVar(VarId),
+    /// A value of the given type has no LLVM equivalent
+    UnsupportedType(Type),
+    /// The function uses a feature of the ir that can't be emitted yet
+    Unsupported(&'static str),
+}
+
+impl Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFunction(func) => write!(f, "call to unknown function {:?}", func),
+            Self::UnknownBlock(block) => write!(f, "jump to unknown block {:?}", block),
+            Self::UndefinedVar(var) => write!(f, "use of undefined variable {:?}", var),
+            Self::UnsupportedType(ty) => write!(f, "unsupported type {:?}", ty),
+            Self::Unsupported(feature) => write!(f, "unsupported ir feature: {}", feature),
+        }
+    }
+}
+
+impl Error for EmitError {}
+
+/// Unnamed functions get a name derived from their id
+fn function_name<R>(function: &Function, interner: &R) -> String
+where
+    R: SymbolResolver,
+{
+    function.name.map_or_else(
+        || format!("func{}", function.id.as_u64()),
+        |name| interner.display_symbol(name.0).to_string(),
+    )
+}
+
+/// Quotes names that aren't valid bare identifiers
+fn global_name(name: &str) -> String {
+    let is_ident_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '$' | '.' | '_');
+    let is_bare = name.chars().all(is_ident_char)
+        && name.chars().next().map_or(false, |c| !c.is_ascii_digit());
+
+    if is_bare {
+        format!("@{}", name)
+    } else {
+        let mut quoted = String::from("@\"");
+        for byte in name.bytes() {
+            if byte == b'"' || byte == b'\\' || !(0x20..0x7F).contains(&byte) {
+                quoted.push_str(&format!("\\{:02X}", byte));
+            } else {
+                quoted.push(byte as char);
+            }
+        }
+        quoted.push('"');
+
+        quoted
+    }
+}
+
+fn var_name(var: VarId) -> String {
+    format!("%v{}", var.as_u64())
+}
+
+fn block_name(block: BasicBlockId) -> String {
+    format!("bb{}", block.as_u64())
+}
+
+/// The LLVM type values of the given type are held in, functions returning tuples
+/// return a struct of their results
+fn llvm_type(ty: &Type) -> Result<String, EmitError> {
+    let ty = match ty {
+        Type::Int | Type::Uint => "i64".to_owned(),
+        Type::Int128 | Type::Uint128 => "i128".to_owned(),
+        Type::F32 => "float".to_owned(),
+        Type::F64 => "double".to_owned(),
+        Type::Bool => "i1".to_owned(),
+        Type::Unit => "void".to_owned(),
+        Type::Array(element, len) => format!("[{} x {}]", len, llvm_type(element)?),
+        Type::Struct(fields) | Type::Tuple(fields) => {
+            let fields = fields
+                .iter()
+                .map(llvm_type)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            format!("{{ {} }}", fields.join(", "))
+        }
+        Type::Infer => return Err(EmitError::UnsupportedType(Type::Infer)),
+    };
+
+    Ok(ty)
+}
+
+/// LLVM has no unsigned integers, so they're printed as the signed integer with
+/// the same bits. Floats are printed as the hex bits of the equivalent double,
+/// which is exact for every `f32`
+fn constant(constant: &Constant) -> Result<String, EmitError> {
+    let constant = match *constant {
+        Constant::Bool(bool) => bool.to_string(),
+        Constant::Int(int) => int.to_string(),
+        Constant::Uint(uint) => (uint as i64).to_string(),
+        Constant::Int128(int) => int.to_string(),
+        Constant::Uint128(uint) => (uint as i128).to_string(),
+        Constant::F32(bits) => format!("0x{:016X}", f64::from(f32::from_bits(bits)).to_bits()),
+        Constant::F64(bits) => format!("0x{:016X}", bits),
+
+        Constant::Array(_, ref elements) | Constant::Struct(ref elements)
+            if elements.is_empty() =>
+        {
+            "zeroinitializer".to_owned()
+        }
+        Constant::Array(_, ref elements) => format!("[{}]", typed_constants(elements)?),
+        Constant::Struct(ref fields) => format!("{{ {} }}", typed_constants(fields)?),
+    };
+
+    Ok(constant)
+}
+
+fn typed_constants(constants: &[Constant]) -> Result<String, EmitError> {
+    let constants = constants
+        .iter()
+        .map(|element| {
+            Ok(format!(
+                "{} {}",
+                llvm_type(&element.ty())?,
+                constant(element)?
+            ))
+        })
+        .collect::<Result<Vec<_>, EmitError>>()?;
+
+    Ok(constants.join(", "))
+}
+
+fn signature(function: &Function, callee: &Callee) -> Result<String, EmitError> {
+    let mut params = Vec::with_capacity(function.params.len());
+    for (idx, param) in function.params.iter().enumerate() {
+        let attributes = function.metadata.param(idx);
+
+        // There's no memory for references to point into
+        if attributes.is_by_ref() {
+            return Err(EmitError::Unsupported("by-reference parameters"));
+        }
+
+        let extension = match attributes.extension {
+            Extension::None => "",
+            Extension::Zero => " zeroext",
+            Extension::Sign => " signext",
+        };
+
+        params.push(format!(
+            "{}{} {}",
+            llvm_type(&param.ty)?,
+            extension,
+            var_name(param.var),
+        ));
+    }
+
+    Ok(format!(
+        "{} {}({})",
+        llvm_type(&function.ret_ty)?,
+        callee.name,
+        params.join(", "),
+    ))
+}
+
+#[derive(Debug, Clone)]
+struct Callee {
+    name: String,
+    ret_ty: Type,
+}
+
+/// Prints a single function body
+struct Body<'a> {
+    callees: &'a HashMap<FuncId, Callee>,
+    ret_ty: Type,
+    /// The type of every parameter and instruction result
+    types: HashMap<VarId, Type>,
+    /// Variables with more than one definition, which live in stack slots
+    slots: HashSet<VarId>,
+    temporaries: u64,
+    /// Whether any block traps, which requires `llvm.trap` to be declared
+    traps: bool,
+    code: String,
+}
+
+impl<'a> Body<'a> {
+    fn emit(function: &Function, callees: &'a HashMap<FuncId, Callee>) -> Result<Self, EmitError> {
+        let mut types = HashMap::new();
+        let mut definitions: HashMap<VarId, usize> = HashMap::new();
+        for param in function.params.iter() {
+            types.insert(param.var, param.ty.clone());
+            definitions.insert(param.var, 1);
+        }
+
+        for inst in function
+            .basic_blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter())
+        {
+            // The call's own return type may not have been inferred yet, so the
+            // callee's signature is used instead
+            let ty = match inst {
+                Instruction::Call(call) => callees
+                    .get(&call.func)
+                    .ok_or(EmitError::UnknownFunction(call.func))?
+                    .ret_ty
+                    .clone(),
+                inst => inst.dest_type(),
+            };
+
+            types.insert(inst.dest(), ty);
+            *definitions.entry(inst.dest()).or_insert(0) += 1;
+        }
+
+        let mut body = Self {
+            callees,
+            ret_ty: function.ret_ty.clone(),
+            types,
+            slots: definitions
+                .into_iter()
+                .filter(|&(_, definitions)| definitions > 1)
+                .map(|(var, _)| var)
+                .collect(),
+            temporaries: 0,
+            traps: false,
+            code: String::new(),
+        };
+
+        let entry = function
+            .basic_blocks
+            .iter()
+            .find(|block| block.id == function.entry)
+            .ok_or(EmitError::UnknownBlock(function.entry))?;
+
+        // LLVM's entry block can't have any predecessors, so functions that jump back
+        // to their entry get a preamble that falls through into it
+        let reentered = function
+            .basic_blocks
+            .iter()
+            .any(|block| block.terminator.jump_targets().contains(&function.entry));
+        if reentered {
+            body.code.push_str("start:\n");
+        } else {
+            body.label(entry.id);
+        }
+
+        let mut slots: Vec<VarId> = body.slots.iter().copied().collect();
+        slots.sort_unstable();
+        for &var in slots.iter() {
+            let ty = llvm_type(&body.types[&var])?;
+            body.line(format!("{}.slot = alloca {}", var_name(var), ty));
+        }
+        for param in function.params.iter() {
+            if body.slots.contains(&param.var) {
+                let ty = llvm_type(&param.ty)?;
+                body.line(format!(
+                    "store {} {}, ptr {}.slot",
+                    ty,
+                    var_name(param.var),
+                    var_name(param.var),
+                ));
+            }
+        }
+
+        if reentered {
+            body.line(format!("br label %{}", block_name(entry.id)));
+            body.label(entry.id);
+        }
+        body.block(entry)?;
+
+        for block in function
+            .basic_blocks
+            .iter()
+            .filter(|block| block.id != function.entry)
+        {
+            body.code.push('\n');
+            body.label(block.id);
+            body.block(block)?;
+        }
+
+        Ok(body)
+    }
+
+    fn block(&mut self, block: &BasicBlock) -> Result<(), EmitError> {
+        for inst in block.instructions.iter() {
+            self.instruction(inst)?;
+        }
+
+        self.terminator(&block.terminator)
+    }
+
+    fn instruction(&mut self, inst: &Instruction) -> Result<(), EmitError> {
+        match inst {
+            // LLVM has no copies, so scalars are bitcast to their own type and
+            // aggregates are picked by a select that always takes the same value
+            Instruction::Assign(Assign { value, dest, .. }) => {
+                let ty = self.type_of(value)?;
+                let (llvm_ty, operand) = (llvm_type(&ty)?, self.operand(value)?);
+
+                let copy = if ty.is_aggregate() || matches!(ty, Type::Tuple(_)) {
+                    format!("select i1 true, {0} {1}, {0} {1}", llvm_ty, operand)
+                } else {
+                    format!("bitcast {0} {1} to {0}", llvm_ty, operand)
+                };
+                self.define(*dest, copy)?;
+            }
+
+            Instruction::Add(Add { lhs, rhs, dest, .. }) => {
+                self.binop(lhs, rhs, *dest, ["add", "add", "fadd"])?
+            }
+            Instruction::Sub(Sub { lhs, rhs, dest, .. }) => {
+                self.binop(lhs, rhs, *dest, ["sub", "sub", "fsub"])?
+            }
+            Instruction::Mul(Mul { lhs, rhs, dest, .. }) => {
+                self.binop(lhs, rhs, *dest, ["mul", "mul", "fmul"])?
+            }
+            Instruction::Div(Div { lhs, rhs, dest, .. }) => {
+                self.binop(lhs, rhs, *dest, ["sdiv", "udiv", "fdiv"])?
+            }
+            Instruction::Rem(Rem { lhs, rhs, dest, .. }) => {
+                self.binop(lhs, rhs, *dest, ["srem", "urem", "frem"])?
+            }
+
+            Instruction::Neg(Neg { value, dest }) => {
+                let ty = self.type_of(value)?;
+                let (llvm_ty, operand) = (llvm_type(&ty)?, self.operand(value)?);
+
+                let negated = if ty.is_float() {
+                    format!("fneg {} {}", llvm_ty, operand)
+                } else {
+                    format!("sub {} 0, {}", llvm_ty, operand)
+                };
+                self.define(*dest, negated)?;
+            }
+
+            // Floats are compared by value so NaNs never equal anything
+            Instruction::Cmp(Cmp { lhs, rhs, dest }) => {
+                let ty = self.type_of(lhs)?;
+                let comparison = if ty.is_float() { "fcmp oeq" } else { "icmp eq" };
+                let (lhs, rhs) = (self.operand(lhs)?, self.operand(rhs)?);
+
+                self.define(
+                    *dest,
+                    format!("{} {} {}, {}", comparison, llvm_type(&ty)?, lhs, rhs),
+                )?;
+            }
+
+            Instruction::Select(Select {
+                cond,
+                if_true,
+                if_false,
+                dest,
+            }) => {
+                let cond = self.operand(cond)?;
+                let (if_true, if_false) =
+                    (self.typed_operand(if_true)?, self.typed_operand(if_false)?);
+
+                self.define(
+                    *dest,
+                    format!("select i1 {}, {}, {}", cond, if_true, if_false),
+                )?;
+            }
+
+            Instruction::Call(Call {
+                func, args, dest, ..
+            }) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.typed_operand(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let callees = self.callees;
+                let callee = callees.get(func).ok_or(EmitError::UnknownFunction(*func))?;
+
+                let call = format!(
+                    "call {} {}({})",
+                    llvm_type(&callee.ret_ty)?,
+                    callee.name,
+                    args.join(", "),
+                );
+                if callee.ret_ty == Type::Unit {
+                    self.line(call);
+                } else {
+                    self.define(*dest, call)?;
+                }
+            }
+
+            // Booleans are widened and narrowed between `i1` and `i64`, every other
+            // valid bitcast is between types of the same width
+            Instruction::Bitcast(Bitcast { dest, source }) => {
+                let source_ty = self.type_of(source)?;
+                let (from, to) = (llvm_type(&source_ty)?, llvm_type(&dest.ty)?);
+                let operand = self.operand(source)?;
+
+                let cast = if from == to {
+                    "bitcast"
+                } else if source_ty == Type::Bool {
+                    "zext"
+                } else if dest.ty == Type::Bool {
+                    "trunc"
+                } else {
+                    "bitcast"
+                };
+                self.define(dest.var, format!("{} {} {} to {}", cast, from, operand, to))?;
+            }
+
+            Instruction::ExtractValue(ExtractValue {
+                aggregate,
+                index,
+                dest,
+                ..
+            }) => {
+                let aggregate = self.typed_operand(aggregate)?;
+                self.define(*dest, format!("extractvalue {}, {}", aggregate, index))?;
+            }
+            Instruction::InsertValue(InsertValue {
+                aggregate,
+                index,
+                value,
+                dest,
+            }) => {
+                let (aggregate, value) =
+                    (self.typed_operand(aggregate)?, self.typed_operand(value)?);
+                self.define(
+                    *dest,
+                    format!("insertvalue {}, {}, {}", aggregate, value, index),
+                )?;
+            }
+
+            Instruction::Opaque(_) => return Err(EmitError::Unsupported("opaque instructions")),
+            Instruction::Intrinsic(_) => return Err(EmitError::Unsupported("intrinsics")),
+        }
+
+        Ok(())
+    }
+
+    fn terminator(&mut self, terminator: &Terminator) -> Result<(), EmitError> {
+        match terminator {
+            &Terminator::Jump(block) => self.line(format!("br label %{}", block_name(block))),
+
+            Terminator::Branch(Branch {
+                cond,
+                if_true,
+                if_false,
+            }) => {
+                let cond = self.operand(cond)?;
+                self.line(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    cond,
+                    block_name(if_true.block),
+                    block_name(if_false.block),
+                ));
+            }
+
+            Terminator::Switch(Switch {
+                scrutinee,
+                cases,
+                default,
+            }) => {
+                let scrutinee = self.typed_operand(scrutinee)?;
+                let cases = cases
+                    .iter()
+                    .map(|(case, label)| {
+                        Ok(format!(
+                            "{} {}, label %{}",
+                            llvm_type(&case.ty())?,
+                            constant(case)?,
+                            block_name(label.block),
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, EmitError>>()?;
+
+                self.line(format!(
+                    "switch {}, label %{} [{}]",
+                    scrutinee,
+                    block_name(default.block),
+                    cases.join(" "),
+                ));
+            }
+
+            // Tuples are returned as a struct holding each of their elements
+            Terminator::Return(Return { values }) => match values.as_slice() {
+                [] => self.line("ret void".to_owned()),
+                [value] => {
+                    let value = self.typed_operand(value)?;
+                    self.line(format!("ret {}", value));
+                }
+                values => {
+                    let ty = llvm_type(&self.ret_ty)?;
+
+                    let mut tuple = "undef".to_owned();
+                    for (idx, value) in values.iter().enumerate() {
+                        let value = self.typed_operand(value)?;
+                        let element = self.temporary();
+                        self.line(format!(
+                            "{} = insertvalue {} {}, {}, {}",
+                            element, ty, tuple, value, idx,
+                        ));
+
+                        tuple = element;
+                    }
+                    self.line(format!("ret {} {}", ty, tuple));
+                }
+            },
+
+            Terminator::Unreachable => self.line("unreachable".to_owned()),
+            Terminator::Trap(_) => {
+                self.traps = true;
+                self.line("call void @llvm.trap()".to_owned());
+                self.line("unreachable".to_owned());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Emits a binary operation using the opcode for signed integers, unsigned
+    /// integers or floats
+    fn binop(
+        &mut self,
+        lhs: &Value,
+        rhs: &Value,
+        dest: VarId,
+        [signed, unsigned, float]: [&str; 3],
+    ) -> Result<(), EmitError> {
+        let ty = self.type_of(lhs)?;
+        let opcode = match ty {
+            Type::F32 | Type::F64 => float,
+            Type::Uint | Type::Uint128 => unsigned,
+            _ => signed,
+        };
+        let (lhs, rhs) = (self.operand(lhs)?, self.operand(rhs)?);
+
+        self.define(
+            dest,
+            format!("{} {} {}, {}", opcode, llvm_type(&ty)?, lhs, rhs),
+        )
+    }
+
+    /// The type of a value, variables have the type of their definition
+    fn type_of(&self, value: &Value) -> Result<Type, EmitError> {
+        match value.value {
+            ValueKind::Var(var) => self
+                .types
+                .get(&var)
+                .cloned()
+                .ok_or(EmitError::UndefinedVar(var)),
+            ValueKind::Const(ref constant) => Ok(constant.ty()),
+            ValueKind::Pooled(_) => Ok(value.ty.clone()),
+        }
+    }
+
+    /// Prints a value as an operand, loading variables that live in stack slots
+    fn operand(&mut self, value: &Value) -> Result<String, EmitError> {
+        match value.value {
+            ValueKind::Const(ref value) => constant(value),
+
+            ValueKind::Var(var) => {
+                let ty = self.types.get(&var).ok_or(EmitError::UndefinedVar(var))?;
+
+                if self.slots.contains(&var) {
+                    let ty = llvm_type(ty)?;
+                    let loaded = self.temporary();
+                    self.line(format!(
+                        "{} = load {}, ptr {}.slot",
+                        loaded,
+                        ty,
+                        var_name(var),
+                    ));
+
+                    Ok(loaded)
+                } else {
+                    Ok(var_name(var))
+                }
+            }
+
+            ValueKind::Pooled(_) => Err(EmitError::Unsupported("pooled constants")),
+        }
+    }
+
+    fn typed_operand(&mut self, value: &Value) -> Result<String, EmitError> {
+        let ty = llvm_type(&self.type_of(value)?)?;
+        Ok(format!("{} {}", ty, self.operand(value)?))
+    }
+
+    /// Assigns the result of an instruction to `dest`, storing it into the
+    /// variable's slot if it has one
+    fn define(&mut self, dest: VarId, rhs: String) -> Result<(), EmitError> {
+        if self.slots.contains(&dest) {
+            let result = self.temporary();
+            self.line(format!("{} = {}", result, rhs));
+
+            let ty = llvm_type(&self.types[&dest])?;
+            self.line(format!(
+                "store {} {}, ptr {}.slot",
+                ty,
+                result,
+                var_name(dest),
+            ));
+        } else {
+            self.line(format!("{} = {}", var_name(dest), rhs));
+        }
+
+        Ok(())
+    }
+
+    fn temporary(&mut self) -> String {
+        self.temporaries += 1;
+        format!("%t{}", self.temporaries)
+    }
+
+    fn label(&mut self, block: BasicBlockId) {
+        self.code.push_str(&block_name(block));
+        self.code.push_str(":\n");
+    }
+
+    fn line(&mut self, line: String) {
+        self.code.push_str("    ");
+        self.code.push_str(&line);
+        self.code.push('\n');
+    }
+}
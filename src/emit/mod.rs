@@ -0,0 +1,4 @@
+//! Backends that print or compile reconstructed functions for other toolchains
+
+pub mod clif;
+pub mod llvm;
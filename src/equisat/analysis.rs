@@ -0,0 +1,207 @@
+use crate::{
+    dataflow::{operators::FilterMap, Difference},
+    equisat::{
+        EClassENodeLookup, EClassId, EClassMerger, ENode, ENodeCollection, ENodeEClassLookup,
+        Rewrite,
+    },
+};
+use differential_dataflow::{
+    difference::Abelian,
+    lattice::Lattice,
+    operators::{Iterate, Join, JoinCore, Reduce},
+    Collection, ExchangeData,
+};
+use std::iter;
+use timely::dataflow::Scope;
+
+/// Data attached to every e-class, derived from the e-nodes within it
+///
+/// Analysis data forms a lattice: [`Analysis::make()`] produces the data of a single
+/// e-node from the data of its children and [`Analysis::merge()`] joins the data of
+/// every e-node within an e-class. The data of each e-class is maintained as e-nodes
+/// are added and e-classes are merged, so rewrites can match on it
+pub trait Analysis: Clone + 'static {
+    type Data: ExchangeData;
+
+    /// Produces the data of an e-node given the data of each of its children (in the
+    /// order given by [`ENode::children()`]), returning `None` if nothing is known
+    fn make(&self, enode: &ENode, children: &[Self::Data]) -> Option<Self::Data>;
+
+    /// Joins the data of two e-nodes within the same e-class
+    fn merge(&self, left: &Self::Data, right: &Self::Data) -> Self::Data;
+}
+
+/// Produces the analysis data of every canonical e-class that any data could be
+/// derived for
+pub(super) fn render<S, R, A>(
+    analysis: A,
+    enodes: &ENodeCollection<S, R>,
+    eclass_lookup: &ENodeEClassLookup<S, R>,
+) -> Collection<S, (EClassId, A::Data), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+    A: Analysis,
+{
+    // Every enode along with its canonical eclass
+    let enodes = enodes.join_core(eclass_lookup, |&enode_id, enode, &eclass| {
+        iter::once((enode_id, (eclass, enode.clone())))
+    });
+
+    // The canonical eclass of every child of every enode
+    let children = enodes
+        .flat_map(|(enode_id, (_, enode))| {
+            enode
+                .children()
+                .into_iter()
+                .enumerate()
+                .map(move |(index, child)| (child.as_enode(), (enode_id, index)))
+        })
+        .join_core(eclass_lookup, |_child, &(parent, index), &child_eclass| {
+            iter::once((child_eclass, (parent, index)))
+        });
+
+    let leaf_analysis = analysis.clone();
+    let leaves = enodes.filter_map(move |(_, (eclass, enode))| {
+        if enode.children().is_empty() {
+            leaf_analysis.make(&enode, &[]).map(|data| (eclass, data))
+        } else {
+            None
+        }
+    });
+
+    leaves.iterate(|eclass_data| {
+        let (enodes, children, leaves) = (
+            enodes.enter(&eclass_data.scope()),
+            children.enter(&eclass_data.scope()),
+            leaves.enter(&eclass_data.scope()),
+        );
+        let (make_analysis, merge_analysis) = (analysis.clone(), analysis.clone());
+
+        // The data of each enode's children, in order
+        let child_data = children
+            .join_map(eclass_data, |_child, &(parent, index), data| {
+                (parent, (index, data.clone()))
+            })
+            .reduce(|_parent, children, output| {
+                let data: Vec<_> = children
+                    .iter()
+                    .map(|(&(_, ref data), _)| data.clone())
+                    .collect();
+
+                output.push((data, R::from(1)));
+            });
+
+        enodes
+            .join_map(&child_data, |_enode_id, (eclass, enode), data| {
+                (*eclass, (enode.clone(), data.clone()))
+            })
+            .filter_map(move |(eclass, (enode, data))| {
+                // Enodes can only be analyzed once all of their children have data
+                if data.len() == enode.children().len() {
+                    make_analysis.make(&enode, &data).map(|data| (eclass, data))
+                } else {
+                    None
+                }
+            })
+            .concat(&leaves)
+            .reduce(move |_eclass, data, output| {
+                let merged = data
+                    .iter()
+                    .skip(1)
+                    .fold(data[0].0.clone(), |merged, (data, _)| {
+                        merge_analysis.merge(&merged, data)
+                    });
+
+                output.push((merged, R::from(1)));
+            })
+    })
+}
+
+/// Tracks the known integer value of every e-class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ConstantFolding;
+
+impl Analysis for ConstantFolding {
+    type Data = i64;
+
+    fn make(&self, enode: &ENode, children: &[i64]) -> Option<i64> {
+        match *enode {
+            ENode::Literal(value) => Some(value),
+            ENode::Add(_) => Some(children[0].wrapping_add(children[1])),
+            ENode::Sub(_) => Some(children[0].wrapping_sub(children[1])),
+            ENode::Constant => None,
+        }
+    }
+
+    fn merge(&self, left: &i64, right: &i64) -> i64 {
+        // Two different values within one eclass means that the rewrites proved
+        // two different constants equal, which can only come from a faulty rewrite
+        if left != right {
+            tracing::error!(
+                "merged eclasses with different constant values: {} and {}",
+                left,
+                right,
+            );
+        }
+
+        *left.min(right)
+    }
+}
+
+/// `(add ?x 0) => ?x` and `(add 0 ?x) => ?x`, where zeros are found by [`ConstantFolding`]
+pub struct AddZero<S, R>
+where
+    S: Scope,
+    R: Abelian,
+{
+    constants: Collection<S, (EClassId, i64), R>,
+}
+
+impl<S, R> AddZero<S, R>
+where
+    S: Scope,
+    R: Abelian,
+{
+    /// Creates the rewrite from the output of [`ConstantFolding`], see
+    /// [`EGraph::add_analysis()`](crate::equisat::EGraph::add_analysis)
+    pub const fn new(constants: Collection<S, (EClassId, i64), R>) -> Self {
+        Self { constants }
+    }
+}
+
+impl<S, R> Rewrite<S, R> for AddZero<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    fn render(
+        self,
+        _scope: &mut S,
+        enodes: &ENodeCollection<S, R>,
+        eclass_lookup: &ENodeEClassLookup<S, R>,
+        _eclass_lookup_reverse: &EClassENodeLookup<S, R>,
+    ) -> EClassMerger<S, R> {
+        let zeros = self
+            .constants
+            .filter(|&(_, value)| value == 0)
+            .map(|(eclass, _)| eclass);
+
+        enodes
+            .flat_map(|(enode_id, enode)| {
+                enode.as_add().into_iter().flat_map(move |add| {
+                    vec![
+                        (add.rhs().as_enode(), (enode_id, add.lhs())),
+                        (add.lhs().as_enode(), (enode_id, add.rhs())),
+                    ]
+                })
+            })
+            .join_core(eclass_lookup, |_operand, &(add, other), &operand_eclass| {
+                iter::once((operand_eclass, (add, other)))
+            })
+            .semijoin(&zeros)
+            .map(|(_zero, (add, other))| (add.as_eclass(), other))
+    }
+}
@@ -1,12 +1,19 @@
-use crate::dataflow::{
-    operators::{FilterMap, FilterSplit, InspectExt, Reverse, Split},
-    Time,
+mod analysis;
+
+pub use analysis::{AddZero, Analysis, ConstantFolding};
+
+use crate::{
+    dataflow::{
+        operators::{FilterMap, FilterSplit, InspectExt, Reverse, Split},
+        Difference, Time,
+    },
+    repr::{InstId, VarId},
 };
 use abomonation_derive::Abomonation;
 use differential_dataflow::{
     algorithms::graphs::propagate,
     collection::concatenate,
-    difference::{Abelian, Multiply, Semigroup},
+    difference::Semigroup,
     lattice::Lattice,
     operators::{
         arrange::{ArrangeByKey, ArrangeBySelf, Arranged, TraceAgent},
@@ -22,9 +29,9 @@ use dogsdogsdogs::{
 };
 use std::iter;
 use timely::{
-    dataflow::{operators::probe::Handle, Scope, ScopeParent},
+    dataflow::{operators::probe::Handle, scopes::Child, Scope, ScopeParent},
     order::Product,
-    progress::Timestamp,
+    progress::{timestamp::Refines, Timestamp},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
@@ -48,16 +55,45 @@ impl ENodeId {
         Self(id)
     }
 
+    /// The id of an enode lowered from the instruction `inst`
+    ///
+    /// Ids only depend on the id of the instruction and the slot the enode takes up
+    /// within it, so lowering a program always produces the same egraph no matter
+    /// which worker lowers which instruction or how many epochs came before it
+    pub const fn from_inst(inst: InstId, slot: ENodeSlot) -> Self {
+        Self((inst.as_u64() << 2) | slot as u64)
+    }
+
+    /// The id of the opaque enode standing in for a variable that wasn't defined by
+    /// anything the egraph understands, these never collide with the ids of enodes
+    /// lowered from instructions
+    pub const fn from_var(var: VarId) -> Self {
+        Self((var.as_u64() << 2) | 3)
+    }
+
     pub const fn as_eclass(self) -> EClassId {
         EClassId(self.0)
     }
 }
 
+/// The enodes a single instruction can be lowered into, see [`ENodeId::from_inst()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum ENodeSlot {
+    /// The value the instruction computes
+    Result = 0,
+    /// A literal left hand operand
+    Lhs = 1,
+    /// A literal right hand operand
+    Rhs = 2,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
 pub enum ENode {
     Add(Add),
     Sub(Sub),
     Constant,
+    /// A known integer value
+    Literal(i64),
 }
 
 impl ENode {
@@ -86,6 +122,23 @@ impl ENode {
             None
         }
     }
+
+    pub const fn as_literal(&self) -> Option<i64> {
+        if let Self::Literal(value) = *self {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Get the eclasses of the enode's operands
+    pub fn children(&self) -> Vec<EClassId> {
+        match self {
+            Self::Add(add) => vec![add.lhs(), add.rhs()],
+            Self::Sub(sub) => vec![sub.lhs(), sub.rhs()],
+            Self::Constant | Self::Literal(_) => Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
@@ -168,7 +221,7 @@ where
 {
     pub fn new(scope: &mut S, summary: <S::Timestamp as Timestamp>::Summary) -> Self
     where
-        R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+        R: Difference,
     {
         let eclass_mergers_feedback = SemigroupVariable::new(scope, summary.clone());
         let enodes_feedback = SemigroupVariable::new(scope, summary);
@@ -207,6 +260,17 @@ where
         self
     }
 
+    /// Runs the given analysis over the egraph, producing the analysis data of every
+    /// canonical eclass. The returned collection can be handed to rewrites added
+    /// after it, see [`AddZero`]
+    pub fn add_analysis<A>(&self, analysis: A) -> Collection<S, (EClassId, A::Data), R>
+    where
+        A: Analysis,
+        R: Difference,
+    {
+        analysis::render(analysis, &self.enodes_feedback, &self.enode_eclass_lookup)
+    }
+
     pub fn scope(&self) -> S {
         self.scope.clone()
     }
@@ -220,18 +284,45 @@ where
         });
     }
 
-    // pub fn enter<'a, T>(&self, scope: &mut Child<'a, S, T>) -> EGraph<Child<'a, S, T>, R>
-    // where
-    //     T: Refines<S::Timestamp> + Lattice,
-    // {
-    //     EGraph {
-    //         enodes: self.enodes.enter(scope),
-    //         eclass_nodes: self.eclass_nodes.enter(scope),
-    //         eclass_mergers: self.eclass_mergers.enter(scope),
-    //         eclass_canon_lookup: self.eclass_canon_lookup,
-    //         canon_enode_ids: self.canon_enode_ids.enter(scope),
-    //     }
-    // }
+    /// Moves the egraph into a nested scope, like [`Collection::enter()`]
+    ///
+    /// The nested egraph starts out with the enodes of this one along with the
+    /// merges of every rewrite added to it so far, its lookups are rebuilt within
+    /// the nested scope so that the rewrites added to it are saturated there.
+    /// Whatever it finds is handed back with [`EGraph::leave()`]
+    pub fn enter<'a, T>(
+        &self,
+        scope: &mut Child<'a, S, T>,
+        summary: T::Summary,
+    ) -> EGraph<Child<'a, S, T>, R>
+    where
+        T: Refines<S::Timestamp> + Lattice,
+        R: Difference,
+    {
+        let mut egraph = EGraph::new(scope, summary);
+        for enodes in self.enodes.iter() {
+            egraph.add_enodes(enodes.enter(scope));
+        }
+        egraph.eclass_mergers.extend(
+            self.eclass_mergers
+                .iter()
+                .map(|mergers| mergers.enter(scope)),
+        );
+
+        egraph
+    }
+
+    /// Moves the egraph into a region, see [`EGraph::enter()`]
+    pub fn enter_region<'a>(
+        &self,
+        region: &mut Child<'a, S, S::Timestamp>,
+        summary: <S::Timestamp as Timestamp>::Summary,
+    ) -> EGraph<Child<'a, S, S::Timestamp>, R>
+    where
+        R: Difference,
+    {
+        self.enter(region, summary)
+    }
 
     pub fn debug(&self) {
         self.enodes.iter().for_each(|enodes| {
@@ -247,7 +338,10 @@ where
         self.enodes_feedback.debug();
     }
 
-    fn feedback(self) -> (ENodeCollection<S, R>, Collection<S, (ENodeId, EClassId), R>)
+    /// Ties the egraph's feedback loops together, producing the canonical enodes and
+    /// the canonical eclass of every enode. Every enode and rewrite has to be added
+    /// before the egraph is closed off
+    pub fn feedback(self) -> (ENodeCollection<S, R>, Collection<S, (ENodeId, EClassId), R>)
     where
         R: ExchangeData,
     {
@@ -256,8 +350,7 @@ where
         self.enodes_feedback
             .set(&concatenate(&mut scope, self.enodes.into_iter()));
         self.eclass_mergers_feedback
-            .set(&concatenate(&mut scope, self.eclass_mergers.into_iter()))
-            .debug();
+            .set(&concatenate(&mut scope, self.eclass_mergers.into_iter()));
 
         (
             self.canon_enodes
@@ -268,6 +361,30 @@ where
     }
 }
 
+impl<'a, S, T, R> EGraph<Child<'a, S, T>, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    T: Refines<S::Timestamp> + Lattice,
+    R: Semigroup,
+{
+    /// Closes off an egraph within a nested scope and merges the eclasses it found
+    /// within the egraph it was [entered](EGraph::enter()) from, as if they were
+    /// found by one of its rewrites
+    pub fn leave(self, parent: &mut EGraph<S, R>)
+    where
+        R: Difference,
+    {
+        let (_enodes, eclasses) = self.feedback();
+        parent.eclass_mergers.push(
+            eclasses
+                .filter(|&(enode, eclass)| enode.as_eclass() != eclass)
+                .map(|(enode, eclass)| (enode.as_eclass(), eclass))
+                .leave(),
+        );
+    }
+}
+
 fn union<S, R>(
     scope: &mut S,
     enodes: &ENodeCollection<S, R>,
@@ -281,8 +398,12 @@ fn union<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
+    // Structurally identical enodes are merged before entering the fixpoint so they
+    // don't have to wait on the canonicalization joins to find each other
+    let hashconsed = hashcons(enodes);
+
     let (enode_eclass_lookup, eclass_enode_lookup, canon_enodes, canon_enode_ids) = scope
         .iterative::<Time, _, _>(|scope| {
             let enodes = enodes.enter(scope);
@@ -291,6 +412,7 @@ where
             let union_find = derive_canonical_eclass_ids(
                 &eclass_mergers
                     .concat(&raw_eclass_mergers.enter(scope))
+                    .concat(&hashconsed.enter(scope))
                     // This distinct could be unnecessary, but it's here to make sure that the
                     // multiplicities from the variable don't overflow within the `propagate_core()`
                     // call inside of canon id derivation
@@ -389,6 +511,34 @@ where
     )
 }
 
+/// Merges the eclasses of enodes that are structurally identical, each one is merged
+/// into the eclass of the enode with the smallest id
+///
+/// [`ENode::Constant`]s are opaque values rather than a single structure, so they're
+/// never merged with each other
+fn hashcons<S, R>(enodes: &ENodeCollection<S, R>) -> EClassMerger<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    enodes
+        .filter(|(_, enode)| !matches!(enode, ENode::Constant))
+        .map(|(enode_id, enode)| (enode, enode_id))
+        .reduce(|_enode, enode_ids, mergers| {
+            let (&first_enode, _) = enode_ids[0];
+
+            mergers.reserve(enode_ids.len() - 1);
+            mergers.extend(
+                enode_ids
+                    .iter()
+                    .skip(1)
+                    .map(|&(&enode, _)| ((first_enode.as_eclass(), enode.as_eclass()), R::from(1))),
+            );
+        })
+        .map(|(_enode, merger)| merger)
+}
+
 fn derive_canonical_eclass_ids<S, R>(
     eclass_parents: &EClassMerger<S, R>,
     enodes: &ENodeCollection<S, R>,
@@ -396,7 +546,7 @@ fn derive_canonical_eclass_ids<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     let canonicalized_edges = eclass_parents.flat_map(|(src, dest)| {
         vec![
@@ -461,7 +611,7 @@ impl<S, R> Rewrite<S, R> for RedundantAddSubChain
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R>,
+    R: Difference,
 {
     fn render(
         self,
@@ -539,29 +689,20 @@ where
                         iter::once((c_raw, (a_raw, b)))
                     },
                 ) // 2
-                .join_core(
-                    &eclass_lookup_by_raw_neu,
-                    |_c_raw, &(a_raw, b), &c| {
-                        iter::once((c, (a_raw, b)))
-                    },
-                ) // 3
-                .join_core(
-                    &eclass_lookup_by_canon_neu,
-                    |_c, &(a_raw, b), &d_raw| {
-                        iter::once((d_raw.as_enode(), (a_raw, b)))
-                    },
-                ) // 5
-                .join_core(&sub_nodes_by_id_neu,
-                    |_d_raw, &(a_raw, b), sub| {
-                        let e_raw = sub.rhs();
-                        let f_raw = sub.lhs();
-                        iter::once(((e_raw, b), (a_raw, f_raw)))
+                .join_core(&eclass_lookup_by_raw_neu, |_c_raw, &(a_raw, b), &c| {
+                    iter::once((c, (a_raw, b)))
+                }) // 3
+                .join_core(&eclass_lookup_by_canon_neu, |_c, &(a_raw, b), &d_raw| {
+                    iter::once((d_raw.as_enode(), (a_raw, b)))
+                }) // 5
+                .join_core(&sub_nodes_by_id_neu, |_d_raw, &(a_raw, b), sub| {
+                    let e_raw = sub.rhs();
+                    let f_raw = sub.lhs();
+                    iter::once(((e_raw, b), (a_raw, f_raw)))
                 }) // 4
                 .join_core(
                     &eclass_lookup_raw_canon_by_self_neu,
-                    |&(_e_raw, _b), &(a_raw, f_raw), _: &()| {
-                        iter::once((a_raw.as_eclass(), f_raw))
-                    },
+                    |&(_e_raw, _b), &(a_raw, f_raw), _: &()| iter::once((a_raw.as_eclass(), f_raw)),
                 ); // 6
 
             concatenate(delta, vec![changes_1]).integrate()
@@ -702,9 +843,13 @@ where
 mod tests {
     use crate::{
         dataflow::Diff,
-        equisat::{Add, EClassId, EGraph, ENode, ENodeId, RedundantAddSubChain, Sub},
+        equisat::{
+            Add, AddZero, ConstantFolding, EClassId, EGraph, ENode, ENodeId, RedundantAddSubChain,
+            Sub,
+        },
     };
     use differential_dataflow::{input::Input, operators::Consolidate};
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
     use timely::{
         dataflow::{operators::probe::Handle, Scope},
         order::Product,
@@ -762,4 +907,153 @@ mod tests {
             worker.step_while(|| probe.less_than(enodes.time()));
         });
     }
+
+    #[test]
+    fn identical_enodes_share_an_eclass() {
+        let eclasses = Rc::new(RefCell::new(HashMap::new()));
+
+        let seen_eclasses = eclasses.clone();
+        timely::execute_directly(move |worker| {
+            let mut probe = Handle::new();
+
+            let mut enodes = worker.dataflow::<usize, _, _>(|scope| {
+                let (enode_input, enodes) = scope.new_collection();
+
+                let edges = scope.iterative::<usize, _, _>(|scope| {
+                    let mut graph =
+                        EGraph::<_, Diff>::new(scope, Product::new(Timestamp::minimum(), 1));
+                    graph.add_enodes(enodes.enter(scope));
+
+                    let (_nodes, edges) = graph.feedback();
+                    edges.leave()
+                });
+
+                edges
+                    .consolidate()
+                    .inspect(move |&((enode, eclass), _, diff)| {
+                        *seen_eclasses
+                            .borrow_mut()
+                            .entry((enode, eclass))
+                            .or_insert(0) += diff;
+                    })
+                    .probe_with(&mut probe);
+
+                enode_input
+            });
+
+            enodes.insert((ENodeId::new(0), ENode::Constant));
+            enodes.insert((ENodeId::new(1), ENode::Constant));
+            enodes.insert((ENodeId::new(2), ENode::Literal(4)));
+            enodes.insert((ENodeId::new(3), ENode::Literal(4)));
+            enodes.insert((
+                ENodeId::new(4),
+                ENode::Add(Add::new(EClassId::new(0), EClassId::new(1))),
+            ));
+            enodes.insert((
+                ENodeId::new(5),
+                ENode::Add(Add::new(EClassId::new(0), EClassId::new(1))),
+            ));
+
+            enodes.advance_to(1);
+            enodes.flush();
+
+            worker.step_while(|| probe.less_than(enodes.time()));
+        });
+
+        let eclasses = eclasses.borrow();
+        let eclass_of = |enode| {
+            eclasses
+                .iter()
+                .find(|&(&(seen, _), &diff)| seen == ENodeId::new(enode) && diff > 0)
+                .map(|(&(_, eclass), _)| eclass)
+        };
+
+        assert_eq!(eclass_of(3), Some(EClassId::new(2)));
+        assert_eq!(eclass_of(5), Some(EClassId::new(4)));
+
+        // Opaque constants are never assumed to be the same value
+        assert_eq!(eclass_of(1), Some(EClassId::new(1)));
+    }
+
+    #[test]
+    fn constant_folding_analysis() {
+        let constants = Rc::new(RefCell::new(HashMap::new()));
+        let eclasses = Rc::new(RefCell::new(HashMap::new()));
+
+        let (seen_constants, seen_eclasses) = (constants.clone(), eclasses.clone());
+        timely::execute_directly(move |worker| {
+            let mut probe = Handle::new();
+
+            let mut enodes = worker.dataflow::<usize, _, _>(|scope| {
+                let (enode_input, enodes) = scope.new_collection();
+
+                let (constants, edges) = scope.iterative::<usize, _, _>(|scope| {
+                    let mut graph =
+                        EGraph::<_, Diff>::new(scope, Product::new(Timestamp::minimum(), 1));
+                    graph.add_enodes(enodes.enter(scope));
+
+                    let constants = graph.add_analysis(ConstantFolding);
+                    graph.add_rewrite(AddZero::new(constants.clone()));
+
+                    let (_nodes, edges) = graph.feedback();
+
+                    (constants.leave(), edges.leave())
+                });
+
+                constants
+                    .consolidate()
+                    .inspect(move |&((eclass, value), _, diff)| {
+                        *seen_constants
+                            .borrow_mut()
+                            .entry((eclass, value))
+                            .or_insert(0) += diff;
+                    })
+                    .probe_with(&mut probe);
+
+                edges
+                    .consolidate()
+                    .inspect(move |&((enode, eclass), _, diff)| {
+                        *seen_eclasses
+                            .borrow_mut()
+                            .entry((enode, eclass))
+                            .or_insert(0) += diff;
+                    })
+                    .probe_with(&mut probe);
+
+                enode_input
+            });
+
+            // x + 0
+            enodes.insert((ENodeId::new(0), ENode::Literal(0)));
+            enodes.insert((ENodeId::new(1), ENode::Constant));
+            enodes.insert((
+                ENodeId::new(2),
+                ENode::Add(Add::new(EClassId::new(1), EClassId::new(0))),
+            ));
+
+            // 2 + 3
+            enodes.insert((ENodeId::new(3), ENode::Literal(2)));
+            enodes.insert((ENodeId::new(4), ENode::Literal(3)));
+            enodes.insert((
+                ENodeId::new(5),
+                ENode::Add(Add::new(EClassId::new(3), EClassId::new(4))),
+            ));
+
+            enodes.advance_to(1);
+            enodes.flush();
+
+            worker.step_while(|| probe.less_than(enodes.time()));
+        });
+
+        let constants = constants.borrow();
+        assert_eq!(constants.get(&(EClassId::new(0), 0)), Some(&1));
+        assert_eq!(constants.get(&(EClassId::new(5), 5)), Some(&1));
+        assert!(constants
+            .keys()
+            .all(|&(eclass, _)| eclass != EClassId::new(1)));
+
+        // `x + 0` is merged into the eclass of `x`
+        let eclasses = eclasses.borrow();
+        assert_eq!(eclasses.get(&(ENodeId::new(2), EClassId::new(1))), Some(&1),);
+    }
 }
@@ -0,0 +1,34 @@
+use crate::{
+    builder::BuilderError,
+    verify::{TypeError, ValidityError},
+    vsdg::node::EvaluationError,
+};
+use thiserror::Error;
+
+/// Any error produced by sruth
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    /// A function couldn't be built
+    #[error("failed to build a function: {0}")]
+    Builder(#[from] BuilderError),
+    /// The program isn't valid
+    #[error("invalid program: {0}")]
+    Validity(#[from] ValidityError),
+    /// The program isn't well typed
+    #[error("ill-typed program: {0}")]
+    Type(#[from] TypeError),
+    /// A program couldn't be lowered into sruth's ir
+    #[cfg(feature = "wasm")]
+    #[error("failed to lower a program: {0}")]
+    Lowering(#[from] crate::wasm::ParseError),
+    /// A program couldn't be emitted from sruth's ir
+    #[cfg(feature = "wasm")]
+    #[error("failed to emit a program: {0}")]
+    Emission(#[from] crate::wasm::EmitError),
+    /// Constant nodes couldn't be evaluated
+    #[error("failed to evaluate a constant: {0}")]
+    Evaluation(#[from] EvaluationError),
+}
+
+/// A result with a sruth [`Error`]
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -1,18 +1,24 @@
-#![feature(
-    step_trait,
-    iter_order_by,
-    option_expect_none,
-    generic_associated_types,
-    crate_visibility_modifier
-)]
-#![allow(incomplete_features)]
+#![cfg_attr(feature = "nightly", feature(generic_associated_types))]
+#![cfg_attr(feature = "nightly", allow(incomplete_features))]
 
+pub mod artifacts;
 pub mod builder;
 pub mod dataflow;
+pub mod driver;
+pub mod emit;
 mod equisat;
+mod error;
 pub mod optimize;
+pub mod parallel;
 pub mod repr;
+pub mod runtime;
+pub mod session;
+pub mod symbols;
+pub mod testing;
 mod tests;
 pub mod verify;
 pub mod vsdg;
 pub mod wasm;
+
+pub use error::{Error, Result};
+pub use symbols::SymbolResolver;
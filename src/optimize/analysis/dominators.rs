@@ -0,0 +1,114 @@
+//! Dominance within a function's control flow graph
+//!
+//! A block dominates another if every path from the function's entry to the other
+//! block passes through it, every block dominates itself. Dominators are only
+//! computed for the blocks that can be reached from the entry
+
+use crate::repr::{basic_block::BasicBlockDesc, BasicBlockId};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The dominators of every block reachable from a function's entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dominators {
+    entry: BasicBlockId,
+    dominators: BTreeMap<BasicBlockId, BTreeSet<BasicBlockId>>,
+}
+
+impl Dominators {
+    /// Computes dominators from the `(source, target)` edges of a control flow graph
+    pub fn new<I>(entry: BasicBlockId, edges: I) -> Self
+    where
+        I: IntoIterator<Item = (BasicBlockId, BasicBlockId)>,
+    {
+        let (mut successors, mut predecessors) =
+            (BTreeMap::<_, Vec<_>>::new(), BTreeMap::<_, Vec<_>>::new());
+        for (source, target) in edges {
+            successors.entry(source).or_default().push(target);
+            predecessors.entry(target).or_default().push(source);
+        }
+
+        let mut reachable = BTreeSet::new();
+        let mut stack = vec![entry];
+        while let Some(block) = stack.pop() {
+            if reachable.insert(block) {
+                stack.extend(successors.get(&block).into_iter().flatten().copied());
+            }
+        }
+
+        let mut dominators: BTreeMap<_, _> = reachable
+            .iter()
+            .map(|&block| {
+                if block == entry {
+                    (block, Some(entry).into_iter().collect())
+                } else {
+                    (block, reachable.clone())
+                }
+            })
+            .collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for &block in reachable.iter().filter(|&&block| block != entry) {
+                let mut block_dominators = predecessors
+                    .get(&block)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|pred| dominators.get(pred))
+                    .fold(None, |acc: Option<BTreeSet<_>>, pred_dominators| {
+                        Some(match acc {
+                            Some(acc) => acc.intersection(pred_dominators).copied().collect(),
+                            None => pred_dominators.clone(),
+                        })
+                    })
+                    .unwrap_or_default();
+                block_dominators.insert(block);
+
+                if dominators[&block] != block_dominators {
+                    dominators.insert(block, block_dominators);
+                    changed = true;
+                }
+            }
+        }
+
+        Self { entry, dominators }
+    }
+
+    /// Computes dominators from the terminators of a function's blocks
+    pub fn of_blocks(entry: BasicBlockId, blocks: &BTreeMap<BasicBlockId, BasicBlockDesc>) -> Self {
+        Self::new(
+            entry,
+            blocks.values().flat_map(|desc| {
+                desc.terminator
+                    .jump_targets()
+                    .into_iter()
+                    .map(move |target| (desc.id, target))
+            }),
+        )
+    }
+
+    pub const fn entry(&self) -> BasicBlockId {
+        self.entry
+    }
+
+    pub fn is_reachable(&self, block: BasicBlockId) -> bool {
+        self.dominators.contains_key(&block)
+    }
+
+    /// Every block dominating `block`, including itself, or `None` if it's unreachable
+    pub fn dominators(&self, block: BasicBlockId) -> Option<&BTreeSet<BasicBlockId>> {
+        self.dominators.get(&block)
+    }
+
+    /// Returns `true` if `dominator` dominates `block`, unreachable blocks aren't
+    /// dominated by anything
+    pub fn dominates(&self, dominator: BasicBlockId, block: BasicBlockId) -> bool {
+        self.dominators(block)
+            .map_or(false, |dominators| dominators.contains(&dominator))
+    }
+
+    pub fn strictly_dominates(&self, dominator: BasicBlockId, block: BasicBlockId) -> bool {
+        dominator != block && self.dominates(dominator, block)
+    }
+}
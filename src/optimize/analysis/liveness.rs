@@ -0,0 +1,192 @@
+//! Liveness of memory slots and the stores it makes redundant
+//!
+//! A slot is live at a point within a function if some path from that point loads
+//! from the slot before storing to it. The analysis only looks at the memory
+//! accesses of each block in program order and the edges between blocks, so it
+//! works over the basic blocks of the SSA form just as well as over the VSDG's
+//! effect chains. Blocks without successors leave every slot live since callers
+//! can still observe them
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A single access to a memory slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Access<S> {
+    Load(S),
+    Store(S),
+}
+
+impl<S> Access<S> {
+    pub const fn slot(&self) -> &S {
+        match self {
+            Self::Load(slot) | Self::Store(slot) => slot,
+        }
+    }
+
+    pub const fn is_store(&self) -> bool {
+        matches!(self, Self::Store(_))
+    }
+}
+
+/// The slots live on entry to and exit from every block, keyed by `(block, slot)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Liveness<B, S> {
+    live_in: BTreeSet<(B, S)>,
+    live_out: BTreeSet<(B, S)>,
+}
+
+impl<B, S> Liveness<B, S>
+where
+    B: Ord + Copy,
+    S: Ord + Clone,
+{
+    /// Computes liveness from the `(source, target)` edges of a control flow graph
+    /// and the items of each block, `access` returns the memory access an item makes
+    /// if it makes any
+    pub fn new<E, T, F>(edges: E, blocks: &BTreeMap<B, Vec<T>>, access: F) -> Self
+    where
+        E: IntoIterator<Item = (B, B)>,
+        F: Fn(&T) -> Option<Access<S>>,
+    {
+        let mut successors = BTreeMap::<_, Vec<_>>::new();
+        for (source, target) in edges {
+            successors.entry(source).or_default().push(target);
+            successors.entry(target).or_default();
+        }
+        for &block in blocks.keys() {
+            successors.entry(block).or_default();
+        }
+
+        let accesses: BTreeMap<B, Vec<Access<S>>> = blocks
+            .iter()
+            .map(|(&block, items)| (block, items.iter().filter_map(&access).collect()))
+            .collect();
+        let slots: BTreeSet<S> = accesses
+            .values()
+            .flatten()
+            .map(|access| access.slot().clone())
+            .collect();
+
+        let mut live_in = BTreeMap::<B, BTreeSet<S>>::new();
+        let mut live_out = BTreeMap::<B, BTreeSet<S>>::new();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            // Liveness flows backwards, so visit blocks in reverse to converge sooner
+            for (&block, block_successors) in successors.iter().rev() {
+                let out = if block_successors.is_empty() {
+                    slots.clone()
+                } else {
+                    block_successors
+                        .iter()
+                        .filter_map(|successor| live_in.get(successor))
+                        .flatten()
+                        .cloned()
+                        .collect()
+                };
+
+                let mut live = out.clone();
+                for access in accesses.get(&block).into_iter().flatten().rev() {
+                    match access {
+                        Access::Load(slot) => live.insert(slot.clone()),
+                        Access::Store(slot) => live.remove(slot),
+                    };
+                }
+
+                if live_in.get(&block) != Some(&live) {
+                    live_in.insert(block, live);
+                    changed = true;
+                }
+                live_out.insert(block, out);
+            }
+        }
+
+        let flatten = |sets: BTreeMap<B, BTreeSet<S>>| {
+            sets.into_iter()
+                .flat_map(|(block, slots)| slots.into_iter().map(move |slot| (block, slot)))
+                .collect()
+        };
+
+        Self {
+            live_in: flatten(live_in),
+            live_out: flatten(live_out),
+        }
+    }
+
+    pub fn is_live_in(&self, block: B, slot: &S) -> bool {
+        self.live_in.contains(&(block, slot.clone()))
+    }
+
+    pub fn is_live_out(&self, block: B, slot: &S) -> bool {
+        self.live_out.contains(&(block, slot.clone()))
+    }
+
+    /// Every slot live on exit from `block`
+    pub fn live_out(&self, block: B) -> impl Iterator<Item = &S> + '_ {
+        self.live_out
+            .iter()
+            .filter(move |(live_block, _)| *live_block == block)
+            .map(|(_, slot)| slot)
+    }
+
+    /// Returns the index of every store within `blocks` whose slot is overwritten
+    /// on every path before it's loaded from again
+    pub fn dead_stores<T, F>(&self, blocks: &BTreeMap<B, Vec<T>>, access: F) -> BTreeSet<(B, usize)>
+    where
+        F: Fn(&T) -> Option<Access<S>>,
+    {
+        let mut dead = BTreeSet::new();
+        for (&block, items) in blocks {
+            let mut live: BTreeSet<S> = self.live_out(block).cloned().collect();
+
+            for (idx, item) in items.iter().enumerate().rev() {
+                match access(item) {
+                    Some(Access::Load(slot)) => {
+                        live.insert(slot);
+                    }
+                    Some(Access::Store(slot)) => {
+                        if !live.remove(&slot) {
+                            dead.insert((block, idx));
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        dead
+    }
+}
+
+/// Removes every store within `blocks` whose slot is overwritten before any load
+/// from it, returning the number of removed stores
+///
+/// Removing a store never makes another slot live, so a single application removes
+/// every dead store
+pub fn eliminate_dead_stores<B, S, E, T, F>(
+    edges: E,
+    blocks: &mut BTreeMap<B, Vec<T>>,
+    access: F,
+) -> usize
+where
+    B: Ord + Copy,
+    S: Ord + Clone,
+    E: IntoIterator<Item = (B, B)>,
+    F: Fn(&T) -> Option<Access<S>>,
+{
+    let liveness = Liveness::new(edges, blocks, &access);
+    let dead = liveness.dead_stores(blocks, &access);
+
+    for (&block, items) in blocks.iter_mut() {
+        let mut idx = 0;
+        items.retain(|_| {
+            let keep = !dead.contains(&(block, idx));
+            idx += 1;
+            keep
+        });
+    }
+
+    dead.len()
+}
@@ -0,0 +1,9 @@
+//! Analyses that compute facts about programs for other passes to consume
+
+pub mod dominators;
+pub mod liveness;
+pub mod range;
+
+pub use dominators::Dominators;
+pub use liveness::{eliminate_dead_stores, Access, Liveness};
+pub use range::{impossible_branches, value_ranges, variable_ranges, Range};
@@ -0,0 +1,479 @@
+//! Conservative integer range analysis
+//!
+//! Every integer (and boolean) valued instruction is given a [`Range`] that its
+//! result is guaranteed to fall within, derived from constants, the arithmetic
+//! that produces it and the comparisons that feed branches. Values the analysis
+//! can't see through (parameters, calls, opaque instructions) are given the full
+//! range of their type
+
+use crate::{
+    dataflow::{algorithms::scc, Difference},
+    repr::{
+        terminator::Branch, BasicBlockId, Constant, InstId, Instruction, InstructionExt,
+        Terminator, Type, Value, VarId,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{arrange::ArrangeByKey, iterate::Iterate, reduce::ReduceCore, Join, Threshold},
+    trace::implementations::ord::OrdValSpine,
+    Collection,
+};
+use std::{
+    cmp,
+    convert::TryFrom,
+    fmt::{self, Display},
+};
+use timely::dataflow::Scope;
+
+/// An inclusive range of integers, booleans are represented as `0..=1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Range {
+    pub min: i128,
+    pub max: i128,
+}
+
+impl Range {
+    pub const fn new(min: i128, max: i128) -> Self {
+        Self { min, max }
+    }
+
+    /// A range containing exactly one value
+    pub const fn point(value: i128) -> Self {
+        Self::new(value, value)
+    }
+
+    /// The range of every value of the given type, or `None` if the type isn't
+    /// an integer or a boolean or its values don't fit within an `i128`
+    pub const fn of_type(ty: &Type) -> Option<Self> {
+        match ty {
+            Type::Int => Some(Self::new(i64::MIN as i128, i64::MAX as i128)),
+            Type::Uint => Some(Self::new(0, u64::MAX as i128)),
+            Type::Int128 => Some(Self::new(i128::MIN, i128::MAX)),
+            Type::Bool => Some(Self::new(0, 1)),
+            Type::Uint128
+            | Type::F32
+            | Type::F64
+            | Type::Unit
+            | Type::Infer
+            | Type::Array(..)
+            | Type::Struct(_)
+            | Type::Tuple(_) => None,
+        }
+    }
+
+    /// Returns `None` for floats, aggregate constants and unsigned 128-bit constants that
+    /// don't fit within an `i128`
+    pub fn of_constant(constant: &Constant) -> Option<Self> {
+        match *constant {
+            Constant::Int(int) => Some(Self::point(int as i128)),
+            Constant::Uint(uint) => Some(Self::point(uint as i128)),
+            Constant::Int128(int) => Some(Self::point(int)),
+            Constant::Uint128(uint) => i128::try_from(uint).ok().map(Self::point),
+            Constant::Bool(boolean) => Some(Self::point(boolean as i128)),
+            Constant::F32(_) | Constant::F64(_) | Constant::Array(..) | Constant::Struct(_) => None,
+        }
+    }
+
+    pub const fn is_point(&self) -> bool {
+        self.min == self.max
+    }
+
+    pub const fn contains(&self, value: i128) -> bool {
+        self.min <= value && value <= self.max
+    }
+
+    pub const fn overlaps(&self, other: &Self) -> bool {
+        self.min <= other.max && other.min <= self.max
+    }
+
+    /// The smallest range containing both ranges
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(cmp::min(self.min, other.min), cmp::max(self.max, other.max))
+    }
+
+    /// Returns `true` if the range fits within the given type
+    pub fn fits(&self, ty: &Type) -> bool {
+        Self::of_type(ty).map_or(false, |full| full.min <= self.min && self.max <= full.max)
+    }
+
+    /// The smallest range containing every combination of values from both ranges
+    /// under `op`, or `None` if `op` failed for any of them
+    fn corners<F>(&self, other: &Self, op: F) -> Option<Self>
+    where
+        F: Fn(i128, i128) -> Option<i128>,
+    {
+        let corners = [
+            op(self.min, other.min)?,
+            op(self.min, other.max)?,
+            op(self.max, other.min)?,
+            op(self.max, other.max)?,
+        ];
+
+        Some(Self::new(
+            corners.iter().copied().min().unwrap(),
+            corners.iter().copied().max().unwrap(),
+        ))
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_point() {
+            write!(f, "[{}]", self.min)
+        } else {
+            write!(f, "[{}, {}]", self.min, self.max)
+        }
+    }
+}
+
+/// Computes the range of every integer and boolean valued instruction
+pub fn value_ranges<S, R>(
+    instructions: &Collection<S, (InstId, Instruction), R>,
+) -> Collection<S, (InstId, Range), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
+{
+    ranges(instructions).map(|(_var, (inst, range))| (inst, range))
+}
+
+/// The same as [`value_ranges()`] but keyed by the variable each instruction defines
+pub fn variable_ranges<S, R>(
+    instructions: &Collection<S, (InstId, Instruction), R>,
+) -> Collection<S, (VarId, Range), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
+{
+    ranges(instructions).map(|(var, (_inst, range))| (var, range))
+}
+
+/// Produces the `(block, target)` edges of branches that can never be taken
+/// since their condition is known to always be true or always be false
+pub fn impossible_branches<S, R>(
+    terminators: &Collection<S, (BasicBlockId, Terminator), R>,
+    variable_ranges: &Collection<S, (VarId, Range), R>,
+) -> Collection<S, (BasicBlockId, BasicBlockId), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    terminators
+        .flat_map(|(block, terminator)| match terminator {
+            Terminator::Branch(Branch {
+                cond,
+                if_true,
+                if_false,
+            }) => cond
+                .as_var()
+                .map(|cond| (cond, (block, if_true.block, if_false.block))),
+            _ => None,
+        })
+        .join_map(
+            variable_ranges,
+            |_cond, &(block, if_true, if_false), range| {
+                if *range == Range::point(1) {
+                    Some((block, if_false))
+                } else if *range == Range::point(0) {
+                    Some((block, if_true))
+                } else {
+                    None
+                }
+            },
+        )
+        .flat_map(|edge| edge)
+}
+
+fn ranges<S, R>(
+    instructions: &Collection<S, (InstId, Instruction), R>,
+) -> Collection<S, (VarId, (InstId, Range)), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
+{
+    let exprs = instructions.flat_map(|(inst_id, inst)| {
+        RangeExpr::new(&inst).map(|expr| (inst.dest(), (inst_id, expr)))
+    });
+    let definitions = exprs.map(|(dest, (inst, _))| (dest, inst));
+
+    // Values that depend on themselves would need widening to reach a fixpoint,
+    // so they're conservatively given the full range of their type instead
+    let cyclic = scc::cyclic_nodes(&exprs.flat_map(|(dest, (_, expr))| {
+        expr.operand_vars()
+            .into_iter()
+            .map(move |(operand, _)| (operand, dest))
+    }));
+    let exprs = exprs
+        .antijoin(&cyclic)
+        .concat(
+            &exprs
+                .semijoin(&cyclic)
+                .map(|(dest, (inst, expr))| (dest, (inst, expr.widened()))),
+        )
+        .map(|(dest, (_, expr))| (dest, expr));
+
+    // Variables that aren't defined by any instruction (function parameters) could be anything
+    let parameters = exprs
+        .flat_map(|(_, expr)| expr.operand_vars())
+        .antijoin(&definitions.map(|(dest, _)| dest))
+        .distinct_core();
+
+    let seeds = exprs
+        .flat_map(|(dest, expr)| {
+            if expr.operand_vars().is_empty() {
+                expr.evaluate(&[]).map(|range| (dest, range))
+            } else {
+                None
+            }
+        })
+        .concat(&parameters);
+
+    let dependent = exprs.filter(|(_, expr)| !expr.operand_vars().is_empty());
+    let operands = dependent.flat_map(|(dest, expr)| {
+        expr.operand_vars()
+            .into_iter()
+            .enumerate()
+            .map(move |(idx, (var, _))| (var, (dest, idx)))
+    });
+
+    let ranges = seeds.iterate(|ranges| {
+        let (seeds, dependent, operands) = (
+            seeds.enter(&ranges.scope()),
+            dependent.enter(&ranges.scope()),
+            operands.enter(&ranges.scope()),
+        );
+
+        // Gather the ranges of each instruction's operands, in order
+        let operand_ranges = operands
+            .join_map(ranges, |_var, &(dest, idx), &range| (dest, (idx, range)))
+            .arrange_by_key()
+            .reduce_abelian::<_, OrdValSpine<_, _, _, _>>(
+                "GatherOperandRanges",
+                |_dest, input, output| {
+                    let ranges: Vec<Range> = input.iter().map(|((_, range), _)| *range).collect();
+                    output.push((ranges, R::from(1)));
+                },
+            )
+            .as_collection(|&dest, ranges| (dest, ranges.clone()));
+
+        let evaluated = dependent.join_map(&operand_ranges, |&dest, expr, operand_ranges| {
+            expr.evaluate(operand_ranges).map(|range| (dest, range))
+        });
+
+        seeds
+            .concat(&evaluated.flat_map(|range| range))
+            .distinct_core()
+    });
+
+    definitions.join_map(&ranges, |&dest, &inst, &range| (dest, (inst, range)))
+}
+
+/// The computation an instruction performs, reduced to what the analysis can reason about
+///
+/// Every variant other than [`RangeExpr::Cmp`] carries the full range of its result type
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum RangeExpr {
+    /// The instruction could produce any value of its type
+    Full(Range),
+    Copy(Operand, Range),
+    Neg(Operand, Range),
+    Add(Operand, Operand, Range),
+    Sub(Operand, Operand, Range),
+    Mul(Operand, Operand, Range),
+    Div(Operand, Operand, Range),
+    Cmp(Operand, Operand),
+    /// The condition followed by the values selected when it holds and when it doesn't
+    Select(Operand, Operand, Operand, Range),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum Operand {
+    Const(Range),
+    /// A variable along with the full range of its type
+    Var(VarId, Range),
+}
+
+impl Operand {
+    /// Returns `None` if the value isn't an integer or boolean
+    fn new(value: &Value) -> Option<Self> {
+        match value.as_const() {
+            Some(constant) => Some(Self::Const(Range::of_constant(constant)?)),
+            // Pooled constants are opaque to the analysis
+            None => Some(Self::Var(value.as_var()?, Range::of_type(&value.ty)?)),
+        }
+    }
+}
+
+impl RangeExpr {
+    fn new(inst: &Instruction) -> Option<Self> {
+        let full = Range::of_type(&inst.dest_type())?;
+
+        // Anything with operands the analysis can't track could produce any value
+        let expr = || -> Option<Self> {
+            Some(match inst {
+                Instruction::Assign(assign) => Self::Copy(Operand::new(&assign.value)?, full),
+                Instruction::Neg(neg) => Self::Neg(Operand::new(&neg.value)?, full),
+                Instruction::Add(add) => {
+                    Self::Add(Operand::new(&add.lhs)?, Operand::new(&add.rhs)?, full)
+                }
+                Instruction::Sub(sub) => {
+                    Self::Sub(Operand::new(&sub.lhs)?, Operand::new(&sub.rhs)?, full)
+                }
+                Instruction::Mul(mul) => {
+                    Self::Mul(Operand::new(&mul.lhs)?, Operand::new(&mul.rhs)?, full)
+                }
+                Instruction::Div(div) => {
+                    Self::Div(Operand::new(&div.lhs)?, Operand::new(&div.rhs)?, full)
+                }
+                Instruction::Cmp(cmp) => {
+                    Self::Cmp(Operand::new(&cmp.lhs)?, Operand::new(&cmp.rhs)?)
+                }
+                Instruction::Select(select) => Self::Select(
+                    Operand::new(&select.cond)?,
+                    Operand::new(&select.if_true)?,
+                    Operand::new(&select.if_false)?,
+                    full,
+                ),
+                Instruction::Rem(_)
+                | Instruction::Bitcast(_)
+                | Instruction::Call(_)
+                | Instruction::Opaque(_)
+                | Instruction::Intrinsic(_)
+                | Instruction::ExtractValue(_)
+                | Instruction::InsertValue(_) => Self::Full(full),
+            })
+        };
+
+        Some(expr().unwrap_or(Self::Full(full)))
+    }
+
+    fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Self::Full(_) => Vec::new(),
+            Self::Copy(value, _) | Self::Neg(value, _) => vec![value],
+            Self::Add(lhs, rhs, _)
+            | Self::Sub(lhs, rhs, _)
+            | Self::Mul(lhs, rhs, _)
+            | Self::Div(lhs, rhs, _)
+            | Self::Cmp(lhs, rhs) => vec![lhs, rhs],
+            Self::Select(cond, if_true, if_false, _) => vec![cond, if_true, if_false],
+        }
+    }
+
+    /// The variables the expression uses along with the full ranges of their types
+    fn operand_vars(&self) -> Vec<(VarId, Range)> {
+        self.operands()
+            .into_iter()
+            .filter_map(|operand| match *operand {
+                Operand::Var(var, full) => Some((var, full)),
+                Operand::Const(_) => None,
+            })
+            .collect()
+    }
+
+    /// Gives up on tracking the expression, producing the full range of its type
+    fn widened(self) -> Self {
+        match self {
+            Self::Copy(_, full)
+            | Self::Neg(_, full)
+            | Self::Add(_, _, full)
+            | Self::Sub(_, _, full)
+            | Self::Mul(_, _, full)
+            | Self::Div(_, _, full)
+            | Self::Select(_, _, _, full) => Self::Full(full),
+            Self::Cmp(..) => Self::Full(Range::new(0, 1)),
+            Self::Full(_) => self,
+        }
+    }
+
+    /// Evaluates the expression given the ranges of its variable operands in
+    /// the order they appear, returning `None` unless all of them are known
+    fn evaluate(&self, var_ranges: &[Range]) -> Option<Range> {
+        let operands = self.operands();
+        let vars = operands
+            .iter()
+            .filter(|operand| matches!(operand, Operand::Var(..)))
+            .count();
+        if vars != var_ranges.len() {
+            return None;
+        }
+
+        let mut var_ranges = var_ranges.iter().copied();
+        let operands: Vec<Range> = operands
+            .into_iter()
+            .map(|operand| match *operand {
+                Operand::Const(range) => range,
+                Operand::Var(..) => var_ranges.next().unwrap(),
+            })
+            .collect();
+
+        // Anything that might overflow wraps around, so it could be any value
+        let within = |range: Option<Range>, full: Range| {
+            Some(
+                range
+                    .filter(|range| full.min <= range.min && range.max <= full.max)
+                    .unwrap_or(full),
+            )
+        };
+
+        match *self {
+            Self::Full(full) => Some(full),
+            Self::Copy(_, _) => Some(operands[0]),
+            Self::Neg(_, full) => {
+                within(Some(Range::new(-operands[0].max, -operands[0].min)), full)
+            }
+            Self::Add(_, _, full) => {
+                within(operands[0].corners(&operands[1], i128::checked_add), full)
+            }
+            Self::Sub(_, _, full) => {
+                within(operands[0].corners(&operands[1], i128::checked_sub), full)
+            }
+            Self::Mul(_, _, full) => {
+                within(operands[0].corners(&operands[1], i128::checked_mul), full)
+            }
+
+            // Division by a range containing zero could trap, so only divisors
+            // that are strictly positive or strictly negative are handled
+            Self::Div(_, _, full) => {
+                let range = if operands[1].contains(0) {
+                    None
+                } else {
+                    operands[0].corners(&operands[1], i128::checked_div)
+                };
+
+                within(range, full)
+            }
+
+            Self::Cmp(..) => {
+                let (lhs, rhs) = (operands[0], operands[1]);
+
+                Some(if !lhs.overlaps(&rhs) {
+                    Range::point(0)
+                } else if lhs.is_point() && lhs == rhs {
+                    Range::point(1)
+                } else {
+                    Range::new(0, 1)
+                })
+            }
+
+            Self::Select(..) => {
+                let (cond, if_true, if_false) = (operands[0], operands[1], operands[2]);
+
+                Some(if cond == Range::point(1) {
+                    if_true
+                } else if cond == Range::point(0) {
+                    if_false
+                } else {
+                    if_true.union(&if_false)
+                })
+            }
+        }
+    }
+}
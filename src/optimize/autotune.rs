@@ -0,0 +1,171 @@
+//! Experimental statistics-driven pass ordering
+//!
+//! An [`Autotuner`] is given a set of candidate pass orderings which are each run
+//! over a small sample of the module's functions. The size of the functions each
+//! run produces is recorded and the ordering producing the smallest code is selected
+//! for optimizing the rest of the module, see [`Driver::run_autotuned()`]
+//!
+//! [`Driver::run_autotuned()`]: crate::driver::Driver::run_autotuned
+
+use crate::repr::{utils::EstimateAsm, FuncId, Function};
+use std::fmt::{self, Debug, Display};
+
+#[derive(Debug, Clone)]
+pub struct Autotuner<P> {
+    candidates: Vec<Vec<P>>,
+    stats: Vec<OrderingStats>,
+    sample_size: usize,
+}
+
+impl<P> Autotuner<P> {
+    /// The default number of functions each ordering is tried on
+    pub const DEFAULT_SAMPLE_SIZE: usize = 16;
+
+    pub fn new(candidates: Vec<Vec<P>>) -> Self {
+        let stats = vec![OrderingStats::default(); candidates.len()];
+
+        Self {
+            candidates,
+            stats,
+            sample_size: Self::DEFAULT_SAMPLE_SIZE,
+        }
+    }
+
+    pub fn with_sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size.max(1);
+        self
+    }
+
+    pub fn candidates(&self) -> &[Vec<P>] {
+        &self.candidates
+    }
+
+    /// Picks the functions that candidate orderings should be tried on, evenly
+    /// spread across the given functions so that the sample is deterministic
+    pub fn sample(&self, functions: &[FuncId]) -> Vec<FuncId> {
+        if functions.len() <= self.sample_size {
+            return functions.to_vec();
+        }
+
+        let stride = functions.len() / self.sample_size;
+        functions
+            .iter()
+            .step_by(stride)
+            .take(self.sample_size)
+            .copied()
+            .collect()
+    }
+
+    /// Records the size of the sampled functions after being optimized by the
+    /// candidate ordering at `ordering`, orderings that aren't candidates are ignored
+    pub fn record<'a, I>(&mut self, ordering: usize, functions: I)
+    where
+        I: IntoIterator<Item = &'a Function>,
+    {
+        let stats = match self.stats.get_mut(ordering) {
+            Some(stats) => stats,
+            None => {
+                tracing::warn!(
+                    "tried to record stats for pass ordering {} of {}",
+                    ordering,
+                    self.candidates.len(),
+                );
+                return;
+            }
+        };
+
+        for function in functions {
+            stats.functions += 1;
+            for block in function.basic_blocks.iter() {
+                stats.estimated_asm += block
+                    .instructions
+                    .iter()
+                    .map(EstimateAsm::estimated_instructions)
+                    .sum::<usize>()
+                    + block.terminator.estimated_instructions();
+                stats.instructions += block.instructions.len();
+            }
+        }
+
+        tracing::debug!("recorded stats for pass ordering {}: {:?}", ordering, stats);
+    }
+
+    pub fn stats(&self) -> &[OrderingStats] {
+        &self.stats
+    }
+
+    /// Returns the index of the ordering with the lowest estimated asm, using the
+    /// instruction count to break ties. Orderings without any recorded samples are
+    /// never selected
+    pub fn best(&self) -> Option<usize> {
+        self.stats
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| stats.functions != 0)
+            .min_by_key(|(_, stats)| (stats.estimated_asm, stats.instructions))
+            .map(|(idx, _)| idx)
+    }
+
+    /// Returns the best ordering, falling back to the first candidate if no
+    /// samples have been recorded
+    pub fn best_ordering(&self) -> Option<&[P]> {
+        self.best()
+            .or_else(|| {
+                if self.candidates.is_empty() {
+                    None
+                } else {
+                    Some(0)
+                }
+            })
+            .map(|idx| &*self.candidates[idx])
+    }
+
+    pub fn report(&self) -> TuningReport<'_, P> {
+        TuningReport { tuner: self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrderingStats {
+    pub functions: usize,
+    pub estimated_asm: usize,
+    pub instructions: usize,
+}
+
+/// A human-readable summary of an [`Autotuner`]'s measurements and its choice
+pub struct TuningReport<'a, P> {
+    tuner: &'a Autotuner<P>,
+}
+
+impl<P> Display for TuningReport<'_, P>
+where
+    P: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let best = self.tuner.best();
+
+        writeln!(f, "pass ordering autotuning:")?;
+        for (idx, (ordering, stats)) in self
+            .tuner
+            .candidates
+            .iter()
+            .zip(self.tuner.stats.iter())
+            .enumerate()
+        {
+            writeln!(
+                f,
+                "  {} {:?}: {} functions, {} estimated asm, {} instructions",
+                if best == Some(idx) { "*" } else { " " },
+                ordering,
+                stats.functions,
+                stats.estimated_asm,
+                stats.instructions,
+            )?;
+        }
+
+        match best {
+            Some(best) => write!(f, "selected {:?}", self.tuner.candidates[best]),
+            None => write!(f, "no orderings were sampled"),
+        }
+    }
+}
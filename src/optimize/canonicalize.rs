@@ -0,0 +1,29 @@
+//! Puts the operands of commutative instructions into a canonical order
+//!
+//! `add v1, v0` and `add v0, v1` compute the same value but can't be deduplicated
+//! while their operands differ, so every commutative instruction has its variables
+//! ordered by id and its constants moved after them. Later passes can then also
+//! assume that a constant operand of a commutative instruction is on its right
+
+use crate::repr::{InstId, Instruction};
+use differential_dataflow::{difference::Semigroup, Collection};
+use timely::dataflow::Scope;
+
+pub fn canonicalize_operands<S, R>(
+    instructions: &Collection<S, (InstId, Instruction), R>,
+) -> Collection<S, (InstId, Instruction), R>
+where
+    S: Scope,
+    R: Semigroup,
+{
+    let span = tracing::debug_span!("operand canonicalization");
+    span.in_scope(|| {
+        instructions.map(|(id, mut inst)| {
+            if inst.canonicalize_operands() {
+                tracing::trace!("reordered the operands of {:?}", id);
+            }
+
+            (id, inst)
+        })
+    })
+}
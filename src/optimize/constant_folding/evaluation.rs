@@ -1,7 +1,7 @@
 use crate::{
     dataflow::operators::{FilterMap, InspectExt},
     repr::{
-        instruction::{Add, BinopExt, Div, Mul, Sub},
+        instruction::{Add, BinopExt, Div, Mul, Rem, Sub},
         Cast, Constant, InstId, Instruction, RawCast, Type, Value, ValueKind, VarId,
     },
 };
@@ -22,12 +22,14 @@ pub(super) fn evaluate_binary_op<S, T, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    T: Evaluate<Output = Instruction> + BinopExt + Data,
+    T: Evaluate + BinopExt + Data,
     Instruction: RawCast<T>,
     R: Semigroup + ExchangeData + Multiply<Output = R>,
 {
     let [both_const, lhs_const, rhs_const, no_const]: [Stream<_, _>; 4] = instructions
         .filter_map(|(id, binop)| binop.cast().map(|binop| (id, binop)))
+        // Pooled constants are opaque to folding
+        .filter(|(_, binop)| binop.lhs().as_pooled().is_none() && binop.rhs().as_pooled().is_none())
         .inner
         .partition(4, |((id, binop), time, diff)| {
             let stream = match (binop.lhs().is_const(), binop.rhs().is_const()) {
@@ -44,7 +46,7 @@ where
 
     let both_const = both_const
         .as_collection()
-        .map(|(id, binop)| (id, binop.eval()))
+        .flat_map(|(id, binop)| binop.eval().map(|inst| (id, inst)))
         .debug_inspect(|((id, inst), _, _)| tracing::trace!(inst = ?inst, "folded {:?}", id));
 
     let lhs_const = lhs_const
@@ -52,45 +54,59 @@ where
         .map(|(id, binop)| {
             (
                 binop.rhs().as_var().unwrap(),
-                (id, binop.dest(), binop.lhs()),
+                (id, (binop.dest(), binop.name()), binop.lhs()),
             )
         })
-        .join_map(&constants, |_, &(id, dest, ref lhs), (rhs, rhs_ty)| {
-            let evaluated = T::from_parts(
-                lhs.clone(),
-                Value::new(ValueKind::Const(rhs.clone()), rhs_ty.clone()),
-                dest,
-            )
-            .eval();
+        .join_map(
+            &constants,
+            |_, &(id, (dest, name), ref lhs), (rhs, rhs_ty)| {
+                let evaluated = T::from_parts(
+                    lhs.clone(),
+                    Value::new(ValueKind::Const(rhs.clone()), rhs_ty.clone()),
+                    dest,
+                    name,
+                )
+                .eval();
 
-            (id, evaluated)
-        });
+                evaluated.map(|evaluated| (id, evaluated))
+            },
+        )
+        .flat_map(|evaluated| evaluated);
 
     let rhs_const = rhs_const
         .as_collection()
         .map(|(id, binop)| {
             (
                 binop.lhs().as_var().unwrap(),
-                (id, binop.dest(), binop.rhs()),
+                (id, (binop.dest(), binop.name()), binop.rhs()),
             )
         })
-        .join_map(&constants, |_, &(id, dest, ref rhs), (lhs, lhs_ty)| {
-            let evaluated = T::from_parts(
-                Value::new(ValueKind::Const(lhs.clone()), lhs_ty.clone()),
-                rhs.clone(),
-                dest,
-            )
-            .eval();
+        .join_map(
+            &constants,
+            |_, &(id, (dest, name), ref rhs), (lhs, lhs_ty)| {
+                let evaluated = T::from_parts(
+                    Value::new(ValueKind::Const(lhs.clone()), lhs_ty.clone()),
+                    rhs.clone(),
+                    dest,
+                    name,
+                )
+                .eval();
 
-            (id, evaluated)
-        });
+                evaluated.map(|evaluated| (id, evaluated))
+            },
+        )
+        .flat_map(|evaluated| evaluated);
 
     let no_const = no_const
         .as_collection()
         .map(|(id, binop)| {
             (
                 binop.lhs().as_var().unwrap(),
-                (id, binop.dest(), binop.rhs().as_var().unwrap()),
+                (
+                    id,
+                    (binop.dest(), binop.name()),
+                    binop.rhs().as_var().unwrap(),
+                ),
             )
         })
         .join_map(&constants, |_, &(id, dest, rhs), lhs| {
@@ -98,17 +114,19 @@ where
         })
         .join_map(
             &constants,
-            |_, &(id, dest, (ref lhs, ref lhs_ty)), (rhs, rhs_ty)| {
+            |_, &(id, (dest, name), (ref lhs, ref lhs_ty)), (rhs, rhs_ty)| {
                 let evaluated = T::from_parts(
                     Value::new(ValueKind::Const(lhs.clone()), lhs_ty.clone()),
                     Value::new(ValueKind::Const(rhs.clone()), rhs_ty.clone()),
                     dest,
+                    name,
                 )
                 .eval();
 
-                (id, evaluated)
+                evaluated.map(|evaluated| (id, evaluated))
             },
-        );
+        )
+        .flat_map(|evaluated| evaluated);
 
     both_const
         .concat(&lhs_const)
@@ -117,40 +135,28 @@ where
 }
 
 // TODO: These suck
+/// Evaluates a binary operation with constant operands, returning `None` for
+/// operations that would trap
 pub(super) trait Evaluate {
-    type Output;
-
-    fn eval(self) -> Self::Output;
+    fn eval(self) -> Option<Instruction>;
 }
 
-impl Evaluate for Add {
-    type Output = Instruction;
-
-    fn eval(self) -> Self::Output {
-        self.evaluate().unwrap()
-    }
+macro_rules! impl_evaluate {
+    ($($type:ident),* $(,)?) => {
+        $(
+            impl Evaluate for $type {
+                fn eval(self) -> Option<Instruction> {
+                    self.evaluate()
+                }
+            }
+        )*
+    };
 }
 
-impl Evaluate for Sub {
-    type Output = Instruction;
-
-    fn eval(self) -> Self::Output {
-        self.evaluate().unwrap()
-    }
-}
-
-impl Evaluate for Mul {
-    type Output = Instruction;
-
-    fn eval(self) -> Self::Output {
-        self.evaluate().unwrap()
-    }
-}
-
-impl Evaluate for Div {
-    type Output = Instruction;
-
-    fn eval(self) -> Self::Output {
-        self.evaluate().unwrap()
-    }
+impl_evaluate! {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
 }
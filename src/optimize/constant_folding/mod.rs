@@ -1,23 +1,28 @@
 mod evaluation;
 mod promotion;
+mod sccp;
 
 use crate::{
-    dataflow::operators::{CollectCastable, CollectUsages, FilterMap, FilterSplit, InspectExt},
+    dataflow::{
+        operators::{CollectCastable, CollectUsages, FilterMap, FilterSplit, InspectExt},
+        Difference,
+    },
     repr::{
-        instruction::{Add, Assign, Div, Mul, Sub},
+        instruction::{Add, Assign, Div, Mul, Rem, Sub},
         terminator::Return,
         BasicBlockId, Cast, Constant, InstId, Instruction, InstructionExt, Terminator, Type, Value,
         ValueKind, VarId,
     },
 };
 use differential_dataflow::{
-    difference::{Abelian, Multiply},
     lattice::Lattice,
     operators::{consolidate::ConsolidateStream, Consolidate, Join},
-    Collection, ExchangeData,
+    Collection,
 };
 use timely::dataflow::Scope;
 
+pub use sccp::{sccp, sccp_with};
+
 type ConstProp<S, R> = (
     Collection<S, (InstId, Instruction), R>,
     Collection<S, (BasicBlockId, Terminator), R>,
@@ -31,7 +36,7 @@ pub fn constant_folding<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice + Clone,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     let span = tracing::debug_span!("constant folding");
     span.in_scope(|| {
@@ -56,6 +61,10 @@ where
                 .concat(&evaluation::evaluate_binary_op::<_, Div, _>(
                     &instructions,
                     &constants,
+                ))
+                .concat(&evaluation::evaluate_binary_op::<_, Rem, _>(
+                    &instructions,
+                    &constants,
                 ));
 
         // Replace the instructions we've modified
@@ -76,7 +85,8 @@ where
             })
             .consolidate();
 
-        let promoted_instructions = promotion::promote_constants(&new_instructions, &new_constants);
+        let promoted_instructions = promotion::promote_constants(&new_instructions, &new_constants)
+            .map(|(id, inst)| (id, fold_structural(inst)));
 
         let folded_terminators =
             propagate_to_terminators(&terminators, &new_constants).consolidate();
@@ -90,6 +100,19 @@ where
     })
 }
 
+/// Folds extracts from and insertions into constant aggregates along with selects
+/// on constant conditions into assignments
+fn fold_structural(inst: Instruction) -> Instruction {
+    let folded = match &inst {
+        Instruction::Select(select) => select.evaluate(),
+        Instruction::ExtractValue(extract) => extract.evaluate(),
+        Instruction::InsertValue(insert) => insert.evaluate(),
+        _ => None,
+    };
+
+    folded.map_or(inst, Instruction::Assign)
+}
+
 fn propagate_to_terminators<S, R>(
     terminators: &Collection<S, (BasicBlockId, Terminator), R>,
     constants: &Collection<S, (VarId, (Constant, Type)), R>,
@@ -97,11 +120,13 @@ fn propagate_to_terminators<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R>,
+    R: Difference,
 {
+    // Only single value returns are folded, each value of a multi-value return would
+    // need its own constant to be joined in before the return could be rebuilt
     let returns = terminators.filter_map(|(id, term)| {
         term.into_return()
-            .and_then(|ret| ret.value)
+            .and_then(|ret| ret.value().cloned())
             .and_then(|val| val.as_var().map(|var| (var, val.ty)))
             .map(|(var, ty)| (var, (id, ty)))
     });
@@ -112,12 +137,10 @@ where
 
             (
                 id,
-                Terminator::Return(Return {
-                    value: Some(Value::new(
-                        ValueKind::Const(constant.clone()),
-                        const_ty.clone(),
-                    )),
-                }),
+                Terminator::Return(Return::new(Some(Value::new(
+                    ValueKind::Const(constant.clone()),
+                    const_ty.clone(),
+                )))),
             )
         });
 
@@ -128,6 +151,7 @@ where
         branches.filter_split(|(id, br)| match br.cond.value.clone() {
             ValueKind::Const(constant) => (Some((id, (constant, br))), None),
             ValueKind::Var(var) => (None, Some((var, (id, br)))),
+            ValueKind::Pooled(_) => (None, None),
         });
 
     let const_branches = branch_const
@@ -162,10 +186,32 @@ where
             tracing::trace!("folded identical branch at {:?} into {:?}", id, term);
         });
 
+    let switches = terminators
+        .filter_map(|(id, term)| term.into_switch().map(move |switch| (id, switch)))
+        .consolidate_stream();
+    let (switch_const, switch_vars) =
+        switches.filter_split(|(id, switch)| match switch.scrutinee.value.clone() {
+            ValueKind::Const(constant) => (Some((id, (constant, switch))), None),
+            ValueKind::Var(var) => (None, Some((var, (id, switch)))),
+            ValueKind::Pooled(_) => (None, None),
+        });
+
+    let const_switches = switch_const
+        .map(|(id, (constant, switch))| (id, Terminator::Jump(switch.target(&constant))))
+        .concat(
+            &switch_vars.join_map(&constants, |_var, &(id, ref switch), (constant, _ty)| {
+                (id, Terminator::Jump(switch.target(constant)))
+            }),
+        )
+        .debug_inspect(|((id, term), _, _)| {
+            tracing::trace!("folded constant switch at {:?} into {:?}", id, term);
+        });
+
     let folded_terminators = folded_returns
         .concat(&const_branches)
         .concat(&var_branches)
-        .concat(&identical_branches);
+        .concat(&identical_branches)
+        .concat(&const_switches);
 
     terminators
         .antijoin(&folded_terminators.map(|(id, _)| id))
@@ -180,7 +226,7 @@ fn eliminate_redundant_assigns<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R>,
+    R: Difference,
 {
     let redundant_assignments = instructions
         .filter_map(|(id, inst)| {
@@ -1,12 +1,11 @@
 use crate::{
-    dataflow::operators::CollectUsages,
+    dataflow::{operators::CollectUsages, Difference},
     repr::{Constant, InstId, Instruction, InstructionExt, Type, Value, VarId},
 };
 use differential_dataflow::{
-    difference::{Abelian, Multiply},
     lattice::Lattice,
     operators::{consolidate::ConsolidateStream, Consolidate, Join, Reduce},
-    Collection, ExchangeData,
+    Collection,
 };
 use timely::dataflow::Scope;
 
@@ -31,7 +30,7 @@ pub(super) fn promote_constants<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     // Collect all variables that are used and the instructions that use them,
     // this allows us to ignore instructions which don't actually have any
@@ -0,0 +1,350 @@
+use super::{promotion, propagate_to_terminators};
+use crate::{
+    dataflow::{
+        analysis::{Use, UseDef, UseDefTrace},
+        Difference, Program,
+    },
+    repr::{
+        instruction::Assign, BasicBlockId, Constant, Instruction, InstructionExt, Terminator,
+        Value, ValueKind, VarId,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{
+        arrange::{ArrangeByKey, Arranged},
+        iterate::Variable,
+        Consolidate, Join, JoinCore, Reduce, Threshold,
+    },
+    Collection,
+};
+use std::collections::BTreeSet;
+use timely::{dataflow::Scope, order::Product};
+
+/// Sparse conditional constant propagation
+///
+/// Unlike [`constant_folding()`](super::constant_folding), block reachability is
+/// discovered alongside constants: a block's instructions only contribute constants
+/// once the block is known to be executable and a branch's targets only become
+/// executable once its condition could actually take them. This means that
+/// constants behind branches that are never taken don't pollute the results
+///
+/// Instructions that evaluate to constants are replaced with assignments, uses of
+/// constants are promoted to immediates and branches on constants are turned into
+/// jumps, the blocks that are left unreachable are removed by
+/// [`cull_unreachable_blocks()`](crate::dataflow::operators::Cleanup::cull_unreachable_blocks)
+pub fn sccp<S, R>(scope: &mut S, program: &Program<S, R>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    sccp_with(scope, program, &program.use_def())
+}
+
+/// The same as [`sccp()`] but reads the variables used by each instruction from an
+/// existing [`UseDef`] of the program
+pub fn sccp_with<S, R>(
+    scope: &mut S,
+    program: &Program<S, R>,
+    use_def: &UseDef<S, R>,
+) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let span = tracing::debug_span!("sparse conditional constant propagation");
+    span.in_scope(|| {
+        scope.region_named("sparse conditional constant propagation", |region| {
+            let program = program.enter_region(region);
+
+            let use_defs = use_def.use_defs.enter_region(region);
+            let constants =
+                lattice_values(&program, &use_defs).flat_map(|(var, value)| match value {
+                    LatticeValue::Constant(constant) => {
+                        let ty = constant.ty();
+                        Some((var, (constant, ty)))
+                    }
+                    LatticeValue::Overdefined => None,
+                });
+
+            // Replace every instruction that produces a constant with an assignment of it
+            let folded = program
+                .instructions
+                .map(|(id, inst)| (inst.dest(), id))
+                .join_map(&constants, |&dest, &id, (constant, ty)| {
+                    let value = Value::new(ValueKind::Const(constant.clone()), ty.clone());
+                    (id, Instruction::Assign(Assign::new(dest, value, None)))
+                });
+
+            let instructions = program
+                .instructions
+                .antijoin(&folded.map(|(id, _)| id))
+                .concat(&folded);
+            let instructions =
+                promotion::promote_constants(&instructions, &constants).consolidate();
+            let block_terminators =
+                propagate_to_terminators(&program.block_terminators, &constants).consolidate();
+
+            Program {
+                instructions,
+                block_terminators,
+                ..program
+            }
+            .leave_region()
+        })
+    })
+}
+
+/// The value of a variable, variables without one haven't been reached (yet)
+///
+/// Every variable has at most one value at a time
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum LatticeValue {
+    Constant(Constant),
+    /// The variable could hold more than one value
+    Overdefined,
+}
+
+/// Propagates constants and block executability together until both settle
+fn lattice_values<S, R>(
+    program: &Program<S, R>,
+    use_defs: &Arranged<S, UseDefTrace<S::Timestamp, R>>,
+) -> Collection<S, (VarId, LatticeValue), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let entries = program.function_descriptors.map(|(_, desc)| desc.entry);
+    let parameters = program.function_descriptors.flat_map(|(_, desc)| {
+        desc.params
+            .into_iter()
+            .map(|param| (param.var, LatticeValue::Overdefined))
+    });
+    let located_instructions = program
+        .instructions
+        .join_map(&program.block_instructions, |&id, inst, &block| {
+            (block, (id, inst.clone()))
+        });
+
+    program
+        .instructions
+        .scope()
+        .scoped::<Product<S::Timestamp, usize>, _, _>("sccp", |scope| {
+            let executable = Variable::new(scope, Product::new(Default::default(), 1));
+            let values = Variable::new(scope, Product::new(Default::default(), 1));
+
+            let (entries, parameters, located_instructions, terminators, use_defs) = (
+                entries.enter(scope),
+                parameters.enter(scope),
+                located_instructions.enter(scope),
+                program.block_terminators.enter(scope),
+                use_defs.enter(scope),
+            );
+
+            let live_instructions = located_instructions
+                .semijoin(&executable)
+                .map(|(_, inst)| inst);
+
+            // Instructions that don't depend on the values of their operands can be
+            // evaluated as soon as their block is executable
+            let independent = live_instructions
+                .filter(|(_, inst)| !needs_operands(inst))
+                .flat_map(|(_, inst)| evaluate(&inst, &[]).map(|value| (inst.dest(), value)));
+
+            // Gather the current values of each instruction's operands
+            let operand_values = live_instructions
+                .filter(|(_, inst)| needs_operands(inst))
+                .map(|(id, _)| (Use::Inst(id), ()))
+                .distinct_core::<R>()
+                .arrange_by_key()
+                .join_core(&use_defs, |used, &(), &var| used.inst().map(|id| (var, id)))
+                .join_map(&values, |&var, &id, value| (id, (var, value.clone())))
+                .reduce(|_id, operands, output| {
+                    let operands: Vec<_> = operands
+                        .iter()
+                        .map(|&(operand, _)| operand.clone())
+                        .collect();
+                    output.push((operands, R::from(1)));
+                });
+
+            let dependent = live_instructions
+                .filter(|(_, inst)| needs_operands(inst))
+                .join_map(&operand_values, |_id, inst, operands| {
+                    evaluate(inst, operands).map(|value| (inst.dest(), value))
+                })
+                .flat_map(|value| value);
+
+            // Jumps and branches on constants only have one feasible target
+            let live_terminators = terminators.semijoin(&executable);
+            let static_targets = live_terminators.flat_map(|(_, term)| match term {
+                Terminator::Jump(target) => Some(target),
+                Terminator::Branch(branch) => branch
+                    .cond
+                    .as_const()
+                    .and_then(Constant::as_bool)
+                    .map(|cond| {
+                        if cond {
+                            branch.if_true.block
+                        } else {
+                            branch.if_false.block
+                        }
+                    }),
+                Terminator::Switch(switch) => switch
+                    .scrutinee
+                    .as_const()
+                    .map(|scrutinee| switch.target(scrutinee)),
+                Terminator::Return(_) | Terminator::Unreachable | Terminator::Trap(_) => None,
+            });
+
+            // Branches and switches on variables don't have any feasible targets
+            // until their condition has a value
+            let dynamic_targets = live_terminators
+                .flat_map(|(_, term)| match term {
+                    Terminator::Branch(branch) => branch
+                        .cond
+                        .as_var()
+                        .map(|cond| (cond, Terminator::Branch(branch))),
+                    Terminator::Switch(switch) => switch
+                        .scrutinee
+                        .as_var()
+                        .map(|scrutinee| (scrutinee, Terminator::Switch(switch))),
+                    Terminator::Jump(_)
+                    | Terminator::Return(_)
+                    | Terminator::Unreachable
+                    | Terminator::Trap(_) => None,
+                })
+                .join_map(&values, |_cond, term, value| match (term, value) {
+                    (Terminator::Branch(branch), LatticeValue::Constant(constant)) => {
+                        match constant.as_bool() {
+                            Some(true) => vec![branch.if_true.block],
+                            Some(false) => vec![branch.if_false.block],
+                            None => term.jump_targets(),
+                        }
+                    }
+                    (Terminator::Switch(switch), LatticeValue::Constant(constant)) => {
+                        vec![switch.target(constant)]
+                    }
+                    _ => term.jump_targets(),
+                })
+                .flat_map(|targets| targets);
+
+            let next_executable: Collection<_, BasicBlockId, R> = entries
+                .concat(&static_targets)
+                .concat(&dynamic_targets)
+                .distinct_core();
+            executable.set(&next_executable);
+
+            // A variable can be defined more than once, like a parameter that's rebound
+            // by a tail call, and only has a constant value if all of its definitions agree
+            let next_values = parameters.concat(&independent).concat(&dependent).reduce(
+                |_var, values, output| {
+                    let value = match values {
+                        [(LatticeValue::Constant(constant), _)] => {
+                            LatticeValue::Constant(constant.clone())
+                        }
+                        _ => LatticeValue::Overdefined,
+                    };
+                    output.push((value, R::from(1)));
+                },
+            );
+            values.set(&next_values).leave()
+        })
+}
+
+/// Returns `true` if the instruction can be folded but needs the values of
+/// variable operands to do so
+fn needs_operands(inst: &Instruction) -> bool {
+    is_foldable(inst) && !inst.used_vars().is_empty()
+}
+
+const fn is_foldable(inst: &Instruction) -> bool {
+    matches!(
+        inst,
+        Instruction::Assign(_)
+            | Instruction::Neg(_)
+            | Instruction::Add(_)
+            | Instruction::Sub(_)
+            | Instruction::Mul(_)
+            | Instruction::Div(_)
+            | Instruction::Rem(_)
+            | Instruction::Cmp(_)
+            | Instruction::Select(_)
+            | Instruction::Intrinsic(_)
+            | Instruction::ExtractValue(_)
+            | Instruction::InsertValue(_),
+    )
+}
+
+/// Evaluates an instruction given the values of its variable operands, returning
+/// `None` if any of the operands don't have a value yet
+fn evaluate(inst: &Instruction, operands: &[(VarId, LatticeValue)]) -> Option<LatticeValue> {
+    if !is_foldable(inst) {
+        return Some(LatticeValue::Overdefined);
+    }
+
+    let used: BTreeSet<VarId> = inst.used_vars().into_iter().map(|used| used.var).collect();
+    if operands.len() < used.len() {
+        return None;
+    }
+
+    let mut inst = inst.clone();
+    for (var, value) in operands {
+        match value {
+            LatticeValue::Constant(constant) => {
+                let value = Value::new(ValueKind::Const(constant.clone()), constant.ty());
+                inst.replace_uses(*var, &value);
+            }
+            LatticeValue::Overdefined => return Some(LatticeValue::Overdefined),
+        }
+    }
+
+    Some(fold(&inst).map_or(LatticeValue::Overdefined, LatticeValue::Constant))
+}
+
+/// Folds an instruction with constant operands, integer arithmetic wraps around on
+/// overflow and anything that would trap or produce a NaN isn't folded
+fn fold(inst: &Instruction) -> Option<Constant> {
+    match inst {
+        Instruction::Assign(assign) => assign.value.as_const().cloned(),
+        Instruction::Neg(neg) => match *neg.value.as_const()? {
+            Constant::Int(int) => Some(Constant::Int(int.wrapping_neg())),
+            Constant::Int128(int) => Some(Constant::Int128(int.wrapping_neg())),
+            // Negation only flips the sign bit, even for NaNs
+            Constant::F32(bits) => Some(Constant::F32(bits ^ (1 << 31))),
+            Constant::F64(bits) => Some(Constant::F64(bits ^ (1 << 63))),
+            Constant::Uint(_)
+            | Constant::Uint128(_)
+            | Constant::Bool(_)
+            | Constant::Array(..)
+            | Constant::Struct(_) => None,
+        },
+        // Binary operations share their folding with the other constant folders
+        Instruction::Add(add) => evaluated(add.clone().evaluate()),
+        Instruction::Sub(sub) => evaluated(sub.clone().evaluate()),
+        Instruction::Mul(mul) => evaluated(mul.clone().evaluate()),
+        Instruction::Div(div) => evaluated(div.clone().evaluate()),
+        Instruction::Rem(rem) => evaluated(rem.clone().evaluate()),
+        Instruction::Cmp(cmp) => cmp
+            .lhs
+            .as_const()?
+            .equals(cmp.rhs.as_const()?)
+            .map(Constant::Bool),
+        Instruction::Select(select) => select.evaluate()?.value.into_const(),
+        Instruction::Intrinsic(intrinsic) => intrinsic.fold(),
+        Instruction::ExtractValue(extract) => extract.evaluate()?.value.into_const(),
+        Instruction::InsertValue(insert) => insert.evaluate()?.value.into_const(),
+        Instruction::Bitcast(_) | Instruction::Call(_) | Instruction::Opaque(_) => None,
+    }
+}
+
+/// The constant assigned by an evaluated instruction
+fn evaluated(inst: Option<Instruction>) -> Option<Constant> {
+    match inst? {
+        Instruction::Assign(assign) => assign.value.into_const(),
+        _ => None,
+    }
+}
@@ -0,0 +1,152 @@
+//! Replaces uses of variables that are plain copies of another value with that
+//! value and removes the copies
+//!
+//! Assignments like `v1 := v0` or `v1 := 100` are left behind by the builder and by
+//! folding, every use of `v1` can read the assigned value directly which leaves the
+//! assignment itself dead. Chains of copies are followed back to the first value
+//! that isn't a copy so the whole chain collapses in a single application
+
+use crate::{
+    dataflow::{
+        analysis::{Use, UseDef},
+        operators::InspectExt,
+        Difference, Program,
+    },
+    repr::{instruction::Assign, utils::InstructionRewriter, Value, VarId},
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{arrange::ArrangeByKey, Consolidate, Iterate, Join, JoinCore, Reduce, Threshold},
+};
+use timely::dataflow::Scope;
+
+pub fn copy_propagation<S, R>(scope: &mut S, program: &Program<S, R>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    copy_propagation_with(scope, program, &program.use_def())
+}
+
+/// The same as [`copy_propagation()`] but finds the uses of copies within an
+/// existing [`UseDef`] of the program
+pub fn copy_propagation_with<S, R>(
+    scope: &mut S,
+    program: &Program<S, R>,
+    use_def: &UseDef<S, R>,
+) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let span = tracing::debug_span!("copy propagation");
+    span.in_scope(|| {
+        scope.region_named("copy propagation", |region| {
+            let program = program.enter_region(region);
+            let def_uses = use_def.def_uses.enter_region(region);
+
+            let copies = program.instructions.filter_map(|(id, inst)| {
+                inst.cast::<Assign>()
+                    .filter(|assign| assign.value.as_pooled().is_none())
+                    .map(|assign| (assign.dest, (id, assign.value)))
+            });
+
+            // Copies of another copy take on whatever their source resolves to
+            let chained = copies
+                .flat_map(|(dest, (_, value))| value.as_var().map(|source| (source, dest)))
+                .semijoin(&copies.map(|(dest, _)| dest));
+            let roots = copies
+                .map(|(dest, (_, value))| (dest, value))
+                .antijoin(&chained.map(|(_, dest)| dest));
+            let sources = roots
+                .iterate(|sources| {
+                    let (chained, roots) = (
+                        chained.enter(&sources.scope()),
+                        roots.enter(&sources.scope()),
+                    );
+
+                    chained
+                        .join_map(sources, |_source, &dest, value| (dest, value.clone()))
+                        .concat(&roots)
+                        .distinct_core()
+                })
+                .debug_inspect(|((dest, value), _, _)| {
+                    tracing::trace!("propagating copy {:?} := {:?}", dest, value);
+                });
+
+            // Gather every copy used at each use site so they can all be replaced at once
+            let replacements = sources
+                .arrange_by_key()
+                .join_core(&def_uses, |&dest, value, &used| {
+                    Some((used, (dest, value.clone())))
+                })
+                .reduce(|_used, copies, output| {
+                    let copies: Vec<(VarId, Value)> =
+                        copies.iter().map(|&(copy, _)| copy.clone()).collect();
+                    output.push((copies, R::from(1)));
+                });
+
+            let instructions = program
+                .instructions
+                .antijoin(&copies.map(|(_, (id, _))| id));
+            let rewritten_instructions = instructions.join_map(
+                &replacements.flat_map(|(used, copies)| used.inst().map(|id| (id, copies))),
+                |&id, inst, copies| {
+                    let mut inst = inst.clone();
+                    Copies(copies).rewrite_instruction(&mut inst);
+
+                    (id, inst)
+                },
+            );
+            let instructions = instructions
+                .antijoin(&rewritten_instructions.map(|(id, _)| id))
+                .concat(&rewritten_instructions)
+                .consolidate();
+
+            let rewritten_terminators = program.block_terminators.join_map(
+                &replacements.flat_map(|(used, copies)| match used {
+                    Use::Terminator(block) => Some((block, copies)),
+                    Use::Inst(_) => None,
+                }),
+                |&block, term, copies| {
+                    let mut term = term.clone();
+                    Copies(copies).rewrite_terminator(&mut term);
+
+                    (block, term)
+                },
+            );
+            let block_terminators = program
+                .block_terminators
+                .antijoin(&rewritten_terminators.map(|(block, _)| block))
+                .concat(&rewritten_terminators)
+                .consolidate();
+
+            Program {
+                instructions,
+                block_terminators,
+                ..program
+            }
+            .leave_region()
+        })
+    })
+}
+
+/// Replaces every operand that's one of the copied variables with its source
+struct Copies<'a>(&'a [(VarId, Value)]);
+
+impl InstructionRewriter for Copies<'_> {
+    fn rewrite_value(&mut self, value: &mut Value) -> bool {
+        let source = value
+            .as_var()
+            .and_then(|var| self.0.iter().find(|&&(dest, _)| dest == var));
+
+        if let Some((_, source)) = source {
+            *value = source.clone();
+            true
+        } else {
+            false
+        }
+    }
+}
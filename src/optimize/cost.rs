@@ -0,0 +1,115 @@
+//! A shared model of execution costs so that every heuristic weighs instructions,
+//! calls and branches the same way
+
+use crate::repr::{utils::EstimateAsm, Instruction, Terminator};
+
+/// Estimates the runtime cost of ir, measured in roughly the cost of a single
+/// simple machine instruction
+///
+/// Everything that needs to weigh one piece of code against another (inlining,
+/// e-graph extraction, if-conversion, speculative hoisting) should go through a
+/// cost model so that their decisions agree with each other and can be tuned by
+/// swapping out the model
+pub trait CostModel {
+    /// The cost of executing a single instruction, not including the overhead
+    /// of any calls it makes
+    fn instruction_cost(&self, inst: &Instruction) -> f32;
+
+    /// The cost of executing a terminator, not including any mispredictions
+    fn terminator_cost(&self, terminator: &Terminator) -> f32;
+
+    /// The overhead of calling a function, on top of the cost of its body
+    fn call_overhead(&self) -> f32;
+
+    /// The expected cost of a conditional branch being mispredicted
+    fn branch_misprediction(&self) -> f32;
+
+    /// The multiplier applied to the inlining cost of pure functions
+    fn pure_function_factor(&self) -> f32 {
+        1.0
+    }
+
+    /// The multiplier applied to the inlining cost of recursive functions
+    fn recursive_function_factor(&self) -> f32 {
+        1.0
+    }
+
+    /// The total cost of a straight-line sequence of instructions
+    fn block_cost<'a, I>(&self, instructions: I, terminator: &Terminator) -> f32
+    where
+        Self: Sized,
+        I: IntoIterator<Item = &'a Instruction>,
+    {
+        let mut cost = instructions
+            .into_iter()
+            .map(|inst| self.instruction_cost(inst))
+            .sum::<f32>()
+            + self.terminator_cost(terminator);
+
+        if terminator.is_branching() {
+            cost += self.branch_misprediction();
+        }
+
+        cost
+    }
+}
+
+/// The cost model used when no other is given, based on the
+/// [`EstimateAsm`] of each instruction
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DefaultCostModel;
+
+impl CostModel for DefaultCostModel {
+    fn instruction_cost(&self, inst: &Instruction) -> f32 {
+        inst.estimated_instructions() as f32
+    }
+
+    fn terminator_cost(&self, terminator: &Terminator) -> f32 {
+        terminator.estimated_instructions() as f32
+    }
+
+    fn call_overhead(&self) -> f32 {
+        1.4
+    }
+
+    fn branch_misprediction(&self) -> f32 {
+        1.2
+    }
+
+    fn pure_function_factor(&self) -> f32 {
+        0.6
+    }
+
+    fn recursive_function_factor(&self) -> f32 {
+        1.3
+    }
+}
+
+impl<M> CostModel for &M
+where
+    M: CostModel + ?Sized,
+{
+    fn instruction_cost(&self, inst: &Instruction) -> f32 {
+        (**self).instruction_cost(inst)
+    }
+
+    fn terminator_cost(&self, terminator: &Terminator) -> f32 {
+        (**self).terminator_cost(terminator)
+    }
+
+    fn call_overhead(&self) -> f32 {
+        (**self).call_overhead()
+    }
+
+    fn branch_misprediction(&self) -> f32 {
+        (**self).branch_misprediction()
+    }
+
+    fn pure_function_factor(&self) -> f32 {
+        (**self).pure_function_factor()
+    }
+
+    fn recursive_function_factor(&self) -> f32 {
+        (**self).recursive_function_factor()
+    }
+}
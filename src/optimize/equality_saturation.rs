@@ -0,0 +1,318 @@
+//! Runs the integer arithmetic of the program through an e-graph and rewrites
+//! every computation that it proves equal to something cheaper
+//!
+//! Each variable defined by an integer `add`, `sub` or constant assignment becomes an
+//! e-node, constant operands become literal e-nodes and every other variable used by
+//! them becomes an opaque e-node. E-nodes get [ids](ENodeId::from_inst) derived from
+//! the instructions they were lowered from, so the e-graph of a program is the same
+//! across runs, workers and epochs. The e-graph is saturated with the default rule set
+//! inside of a nested iterative scope, after which each computation is replaced by
+//! the best representative of its e-class: a constant if constant folding knows its
+//! value, otherwise one of the variables it was computed from. Only variables from a
+//! computation's own operands are used since their definitions are the only ones
+//! known to dominate it, the assignments left behind are removed by copy propagation
+
+use crate::{
+    dataflow::{operators::InspectExt, Difference, Program, Time},
+    equisat::{
+        Add, AddZero, ConstantFolding, EClassId, EGraph, ENode, ENodeId, ENodeSlot,
+        RedundantAddSubChain, Sub,
+    },
+    repr::{
+        instruction::Assign, Constant, InstId, Instruction, InstructionExt, Type, Value, ValueKind,
+        VarId,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Iterate, Join, Reduce, Threshold},
+};
+use timely::{dataflow::Scope, order::Product};
+
+pub fn equality_saturation<S, R>(scope: &mut S, program: &Program<S, R>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let span = tracing::debug_span!("equality saturation");
+    span.in_scope(|| {
+        scope.region_named("equality saturation", |region| {
+            let program = program.enter_region(region);
+
+            let arithmetic = program
+                .instructions
+                .filter_map(|(id, inst)| Arithmetic::new(&inst).map(|arith| (id, arith)));
+
+            let defined = arithmetic.map(|(_, arith)| arith.dest);
+            let operands = arithmetic.flat_map(|(_, arith)| {
+                let dest = arith.dest;
+                arith.operand_vars().map(move |operand| (dest, operand))
+            });
+
+            // Operands computed by anything the e-graph doesn't understand are opaque
+            let opaque = operands
+                .map(|(_, operand)| (operand, ()))
+                .antijoin(&defined)
+                .map(|(operand, ())| operand)
+                .distinct_core();
+
+            // The enode defining each variable known to the egraph
+            let var_enodes = arithmetic
+                .map(|(id, arith)| (arith.dest, ENodeId::from_inst(id, ENodeSlot::Result)))
+                .concat(&opaque.map(|var| (var, ENodeId::from_var(var))));
+
+            // Variable operands refer to the eclasses of their definitions
+            let operand_eclasses = arithmetic
+                .flat_map(|(id, arith)| {
+                    arith
+                        .operand_slots()
+                        .into_iter()
+                        .filter_map(move |(slot, operand)| match operand {
+                            Operand::Var(var) => Some((var, (id, slot))),
+                            Operand::Literal(_) => None,
+                        })
+                })
+                .join_map(&var_enodes, |_var, &(id, slot), &enode| {
+                    (id, (slot, enode.as_eclass()))
+                })
+                .reduce(|_id, operands, output| {
+                    let operands: Vec<(ENodeSlot, EClassId)> =
+                        operands.iter().map(|&(&operand, _)| operand).collect();
+                    output.push((operands, R::from(1)));
+                });
+
+            let resolved = arithmetic
+                .join_map(&operand_eclasses, |&id, arith, operands| {
+                    (id, (arith.clone(), operands.clone()))
+                })
+                .concat(
+                    &arithmetic
+                        .antijoin(&operand_eclasses.map(|(id, _)| id))
+                        .map(|(id, arith)| (id, (arith, Vec::new()))),
+                );
+
+            let enodes = resolved
+                .flat_map(|(id, (arith, operands))| arith.enodes(id, &operands))
+                .concat(&opaque.map(|var| (ENodeId::from_var(var), ENode::Constant)));
+
+            let (eclasses, constants) = region.iterative::<Time, _, _>(|nested| {
+                let mut graph = EGraph::<_, R>::new(nested, Product::new(Default::default(), 1));
+                graph.add_enodes(enodes.enter(nested));
+
+                let constants = graph.add_analysis(ConstantFolding);
+                graph
+                    .add_rewrite(RedundantAddSubChain)
+                    .add_rewrite(AddZero::new(constants.clone()));
+
+                let (_enodes, eclasses) = graph.feedback();
+                (eclasses.leave(), constants.leave())
+            });
+
+            // The canonical eclass of every variable known to the egraph
+            let var_eclasses = var_enodes
+                .map(|(var, enode)| (enode, var))
+                .join_map(&eclasses, |_enode, &var, &eclass| (eclass, var));
+
+            let folded = var_eclasses
+                .join_map(&constants, |_eclass, &var, &value| {
+                    (
+                        var,
+                        Value::new(ValueKind::Const(Constant::Int(value)), Type::Int),
+                    )
+                })
+                .semijoin(
+                    &arithmetic.filter_map(|(_, arith)| arith.is_computed().then(|| arith.dest)),
+                );
+
+            // Every variable each computation is transitively computed from
+            let subterms = operands.iterate(|subterms| {
+                let operands = operands.enter(&subterms.scope());
+
+                subterms
+                    .map(|(var, subterm)| (subterm, var))
+                    .join_map(&operands, |_subterm, &var, &operand| (var, operand))
+                    .concat(&operands)
+                    .distinct_core()
+            });
+            let var_eclasses = var_eclasses.map(|(eclass, var)| (var, eclass));
+            let equivalent = subterms
+                .join_map(&var_eclasses, |&var, &subterm, &eclass| {
+                    (subterm, (var, eclass))
+                })
+                .join_map(
+                    &var_eclasses,
+                    |&subterm, &(var, eclass), &subterm_eclass| {
+                        (var, (subterm, eclass == subterm_eclass))
+                    },
+                )
+                .filter(|&(_, (_, equal))| equal)
+                .map(|(var, (subterm, _))| (var, subterm))
+                .antijoin(&folded.map(|(var, _)| var))
+                .reduce(|_var, subterms, output| {
+                    output.push((
+                        Value::new(ValueKind::Var(*subterms[0].0), Type::Int),
+                        R::from(1),
+                    ));
+                });
+
+            let representatives =
+                folded
+                    .concat(&equivalent)
+                    .debug_inspect(|((var, value), _, _)| {
+                        tracing::trace!("extracted {:?} as the representative of {:?}", value, var);
+                    });
+
+            let rewritten = program
+                .instructions
+                .map(|(id, inst)| (inst.dest(), (id, inst)))
+                .join_map(&representatives, |&dest, (id, inst), value| {
+                    let assign = Assign::new(dest, value.clone(), inst.name());
+                    (*id, Instruction::Assign(assign))
+                });
+
+            let instructions = program
+                .instructions
+                .antijoin(&rewritten.map(|(id, _)| id))
+                .concat(&rewritten);
+
+            Program {
+                instructions,
+                ..program
+            }
+            .leave_region()
+        })
+    })
+}
+
+/// An integer computation the egraph can reason about
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+struct Arithmetic {
+    dest: VarId,
+    kind: ArithmeticKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum ArithmeticKind {
+    Add(Operand, Operand),
+    Sub(Operand, Operand),
+    Literal(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum Operand {
+    Var(VarId),
+    Literal(i64),
+}
+
+impl Operand {
+    fn new(value: &Value) -> Option<Self> {
+        if value.ty != Type::Int {
+            return None;
+        }
+
+        match value.value {
+            ValueKind::Var(var) => Some(Self::Var(var)),
+            ValueKind::Const(Constant::Int(value)) => Some(Self::Literal(value)),
+            ValueKind::Const(_) | ValueKind::Pooled(_) => None,
+        }
+    }
+}
+
+impl Arithmetic {
+    fn new(inst: &Instruction) -> Option<Self> {
+        // Canonical operands let the egraph deduplicate `x + y` and `y + x`
+        let mut inst = inst.clone();
+        inst.canonicalize_operands();
+
+        let kind = match &inst {
+            Instruction::Add(add) => {
+                ArithmeticKind::Add(Operand::new(&add.lhs)?, Operand::new(&add.rhs)?)
+            }
+            Instruction::Sub(sub) => {
+                ArithmeticKind::Sub(Operand::new(&sub.lhs)?, Operand::new(&sub.rhs)?)
+            }
+            Instruction::Assign(assign) => match Operand::new(&assign.value)? {
+                Operand::Literal(value) => ArithmeticKind::Literal(value),
+                Operand::Var(_) => return None,
+            },
+            _ => return None,
+        };
+
+        Some(Self {
+            dest: inst.dest(),
+            kind,
+        })
+    }
+
+    /// Whether the variable is computed rather than being a literal, only computations
+    /// have anything to be replaced with
+    fn is_computed(&self) -> bool {
+        !matches!(self.kind, ArithmeticKind::Literal(_))
+    }
+
+    fn operands(&self) -> Option<(Operand, Operand)> {
+        match self.kind {
+            ArithmeticKind::Add(lhs, rhs) | ArithmeticKind::Sub(lhs, rhs) => Some((lhs, rhs)),
+            ArithmeticKind::Literal(_) => None,
+        }
+    }
+
+    /// The operands along with the slots their enodes would take up
+    fn operand_slots(&self) -> Vec<(ENodeSlot, Operand)> {
+        self.operands()
+            .into_iter()
+            .flat_map(|(lhs, rhs)| vec![(ENodeSlot::Lhs, lhs), (ENodeSlot::Rhs, rhs)])
+            .collect()
+    }
+
+    fn operand_vars(&self) -> impl Iterator<Item = VarId> {
+        self.operands()
+            .into_iter()
+            .flat_map(|(lhs, rhs)| vec![lhs, rhs])
+            .filter_map(|operand| match operand {
+                Operand::Var(var) => Some(var),
+                Operand::Literal(_) => None,
+            })
+    }
+
+    /// The enode of the computation along with the enodes of its literal operands,
+    /// given the eclasses of its variable operands
+    fn enodes(&self, inst: InstId, operands: &[(ENodeSlot, EClassId)]) -> Vec<(ENodeId, ENode)> {
+        let mut enodes = Vec::with_capacity(3);
+
+        let mut operand = |slot: ENodeSlot, operand: Operand| -> Option<EClassId> {
+            match operand {
+                Operand::Var(_) => operands
+                    .iter()
+                    .find(|&&(operand, _)| operand == slot)
+                    .map(|&(_, eclass)| eclass),
+                Operand::Literal(value) => {
+                    let enode = ENodeId::from_inst(inst, slot);
+                    enodes.push((enode, ENode::Literal(value)));
+                    Some(enode.as_eclass())
+                }
+            }
+        };
+
+        let enode = match self.kind {
+            ArithmeticKind::Add(lhs, rhs) => operand(ENodeSlot::Lhs, lhs)
+                .zip(operand(ENodeSlot::Rhs, rhs))
+                .map(|(lhs, rhs)| ENode::Add(Add::new(lhs, rhs))),
+            ArithmeticKind::Sub(lhs, rhs) => operand(ENodeSlot::Lhs, lhs)
+                .zip(operand(ENodeSlot::Rhs, rhs))
+                .map(|(lhs, rhs)| ENode::Sub(Sub::new(lhs, rhs))),
+            ArithmeticKind::Literal(value) => Some(ENode::Literal(value)),
+        };
+
+        // Operands without a definition can't be lowered
+        match enode {
+            Some(enode) => {
+                enodes.push((ENodeId::from_inst(inst, ENodeSlot::Result), enode));
+                enodes
+            }
+            None => Vec::new(),
+        }
+    }
+}
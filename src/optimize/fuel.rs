@@ -0,0 +1,133 @@
+//! Optimization fuel for bisecting miscompiles
+//!
+//! Every rewrite a fueled pass makes consumes one unit of fuel, and once the fuel
+//! runs out passes stop transforming the program. Rewrites are taken in the order
+//! of their keys, so a given budget always applies the same prefix of rewrites and
+//! the rewrite that introduced a miscompile can be found by bisecting over the
+//! budget. All rewrites are funneled through a single worker to be counted, so fuel
+//! is only meant for debugging
+
+use crate::dataflow::Difference;
+use differential_dataflow::{
+    collection::AsCollection,
+    difference::Abelian,
+    lattice::Lattice,
+    operators::{Join, Reduce, Threshold},
+    Collection, ExchangeData,
+};
+use timely::{
+    dataflow::{operators::ToStream, scopes::Child, Scope},
+    progress::Timestamp,
+};
+
+/// The fuel that remains for the passes that have yet to run
+#[derive(Clone)]
+pub struct Fuel<S, R>
+where
+    S: Scope,
+    R: Abelian,
+{
+    remaining: Collection<S, usize, R>,
+}
+
+impl<S, R> Fuel<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    /// Creates a fuel tank that allows `budget` rewrites
+    pub fn new(scope: &mut S, budget: usize) -> Self {
+        let remaining = Some((budget, S::Timestamp::minimum(), R::from(1)))
+            .to_stream(scope)
+            .as_collection();
+
+        Self { remaining }
+    }
+
+    /// The amount of fuel that's left
+    pub fn remaining(&self) -> &Collection<S, usize, R> {
+        &self.remaining
+    }
+
+    /// Allows as many of the candidate rewrites as there's fuel for, taking them in
+    /// order and consuming one unit of fuel for each of them
+    pub fn consume<K>(&mut self, candidates: &Collection<S, K, R>) -> Collection<S, K, R>
+    where
+        K: ExchangeData,
+    {
+        // The remaining fuel is held in `Ok`, which sorts before every `Err` rewrite
+        let consumed = self
+            .remaining
+            .map(|fuel| ((), Ok(fuel)))
+            .concat(&candidates.distinct_core().map(|rewrite| ((), Err(rewrite))))
+            .reduce(|&(), entries, output| {
+                let mut remaining = match entries[0].0 {
+                    Ok(fuel) => *fuel,
+                    Err(_) => 0,
+                };
+
+                for (entry, _) in entries.iter() {
+                    if remaining == 0 {
+                        break;
+                    }
+
+                    if let Err(rewrite) = entry {
+                        output.push((Err(rewrite.clone()), R::from(1)));
+                        remaining -= 1;
+                    }
+                }
+
+                output.push((Ok(remaining), R::from(1)));
+            })
+            .map(|((), entry)| entry);
+
+        self.remaining = consumed.flat_map(Result::ok);
+        consumed.flat_map(Result::err)
+    }
+
+    /// Limits the changes that turned `input` into `output` to the ones there's fuel
+    /// for, where all changes to a single key make up one rewrite
+    pub fn limit<K, V>(
+        &mut self,
+        input: &Collection<S, (K, V), R>,
+        output: &Collection<S, (K, V), R>,
+    ) -> Collection<S, (K, V), R>
+    where
+        K: ExchangeData,
+        V: ExchangeData,
+    {
+        let changes = output.concat(&input.negate());
+
+        // A key's changes can have a total count of zero, like when one of its values
+        // is replaced by another, so keys are collected without counting them
+        let rewritten = changes
+            .reduce(|_key, _changes, output| output.push(((), R::from(1))))
+            .map(|(key, ())| key);
+        let allowed = self.consume(&rewritten);
+
+        input.concat(&changes.semijoin(&allowed))
+    }
+
+    pub fn enter_region<'a>(
+        &self,
+        region: &Child<'a, S, S::Timestamp>,
+    ) -> Fuel<Child<'a, S, S::Timestamp>, R> {
+        Fuel {
+            remaining: self.remaining.enter_region(region),
+        }
+    }
+}
+
+impl<'a, S, R> Fuel<Child<'a, S, S::Timestamp>, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    pub fn leave_region(&self) -> Fuel<S, R> {
+        Fuel {
+            remaining: self.remaining.leave_region(),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Conversion of branch diamonds into selects
+//!
+//! A block that branches to two arms which each do nothing but assign the same
+//! variable before jumping to a shared merge block is a diamond producing a single
+//! value. The branching block can compute that value with a [`Select`] of both
+//! assignments instead and jump straight to the merge block, which is far cheaper
+//! on targets like wasm where a `select` is a single instruction
+//!
+//! Arms are only converted when the branching block is their sole predecessor so
+//! that removing them can't strand any other block. Both arms assigning the same
+//! variable means it has more than one definition, so like
+//! [tail call elimination](crate::optimize::tail_call) this is meant to run as part
+//! of lowering a program for emission
+
+use crate::repr::{
+    instruction::Select, terminator::Branch, BasicBlock, BasicBlockId, Function, Instruction,
+    Terminator,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Returns `true` if the function has any diamonds [`if_conversion()`] would turn
+/// into selects
+pub fn has_diamonds(function: &Function) -> bool {
+    !diamonds(function).is_empty()
+}
+
+/// Turns every diamond within the function that produces a single value into a
+/// select within the branching block, removing the diamond's arms
+pub fn if_conversion(function: &Function) -> Function {
+    let diamonds = diamonds(function);
+    if diamonds.is_empty() {
+        return function.clone();
+    }
+
+    let arms: BTreeSet<BasicBlockId> = diamonds
+        .values()
+        .flat_map(|diamond| [diamond.if_true, diamond.if_false])
+        .collect();
+
+    let mut converted = function.clone();
+    converted
+        .basic_blocks
+        .retain(|block| !arms.contains(&block.id));
+    for block in converted.basic_blocks.iter_mut() {
+        if let Some(diamond) = diamonds.get(&block.id) {
+            if !block.instruction_spans.is_empty() {
+                block.instruction_spans.push(block.terminator_span);
+            }
+
+            block.instructions.push(diamond.select.clone().into());
+            block.terminator = Terminator::Jump(diamond.merge);
+        }
+    }
+
+    converted
+}
+
+/// A block branching to two arms that assign the same variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Diamond {
+    if_true: BasicBlockId,
+    if_false: BasicBlockId,
+    merge: BasicBlockId,
+    select: Select,
+}
+
+/// Finds every diamond of the function keyed by its branching block
+fn diamonds(function: &Function) -> BTreeMap<BasicBlockId, Diamond> {
+    let mut predecessors = BTreeMap::<_, usize>::new();
+    for block in function.basic_blocks.iter() {
+        for target in block.terminator.jump_targets() {
+            *predecessors.entry(target).or_default() += 1;
+        }
+    }
+
+    let blocks: BTreeMap<BasicBlockId, &BasicBlock> = function
+        .basic_blocks
+        .iter()
+        .map(|block| (block.id, block))
+        .collect();
+
+    // An arm must assign a single variable, jump to the merge block and be reachable
+    // from nothing but the branching block
+    let arm = |id: BasicBlockId| {
+        let block = blocks.get(&id)?;
+        if predecessors.get(&id) != Some(&1) || id == function.entry {
+            return None;
+        }
+
+        match (block.instructions.as_slice(), &block.terminator) {
+            ([Instruction::Assign(assign)], &Terminator::Jump(merge)) => Some((assign, merge)),
+            _ => None,
+        }
+    };
+
+    function
+        .basic_blocks
+        .iter()
+        .filter_map(|block| {
+            let Branch {
+                cond,
+                if_true,
+                if_false,
+            } = match &block.terminator {
+                Terminator::Branch(branch) if branch.if_true.block != branch.if_false.block => {
+                    branch
+                }
+                _ => return None,
+            };
+
+            let (true_assign, merge) = arm(if_true.block)?;
+            let (false_assign, false_merge) = arm(if_false.block)?;
+            if merge != false_merge || true_assign.dest != false_assign.dest {
+                return None;
+            }
+
+            let select = Select::new(
+                true_assign.dest,
+                cond.clone(),
+                true_assign.value.clone(),
+                false_assign.value.clone(),
+            );
+
+            Some((
+                block.id,
+                Diamond {
+                    if_true: if_true.block,
+                    if_false: if_false.block,
+                    merge,
+                    select,
+                },
+            ))
+        })
+        .collect()
+}
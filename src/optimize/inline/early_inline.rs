@@ -1,9 +1,9 @@
-use crate::{dataflow::Program, optimize::inline::InlineHeuristics, repr::FuncId};
-use differential_dataflow::{
-    difference::{Abelian, Multiply, Semigroup},
-    lattice::Lattice,
-    Collection, ExchangeData,
+use crate::{
+    dataflow::{Difference, Program},
+    optimize::{inline::InlineHeuristics, rewrites},
+    repr::FuncId,
 };
+use differential_dataflow::{lattice::Lattice, operators::Join, Collection};
 use timely::dataflow::Scope;
 
 pub fn early_inline<S, R>(
@@ -12,18 +12,35 @@ pub fn early_inline<S, R>(
 ) -> Program<S, R>
 where
     S: Scope,
-    S::Timestamp: Lattice,
-    R: Semigroup + Abelian + ExchangeData + Multiply<Output = R>,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
 {
+    let attributes = program
+        .function_descriptors
+        .map(|(func, desc)| (func, desc.attributes));
+
+    // `inline(always)` and `inline(never)` override whatever the heuristics decide,
+    // external functions have no body that could be inlined
     let trivially_inlinable = heuristics
-        .filter(|(_, heuristics)| heuristics.trivially_inlinable())
-        .inspect(|((func, heuristics), _, _)| {
+        .join_map(&attributes, |&func, heuristics, &attributes| {
+            (func, heuristics.clone(), attributes)
+        })
+        .filter(|(_, heuristics, attributes)| {
+            !attributes.is_external()
+                && !attributes.inline_never()
+                && (attributes.inline_always() || heuristics.trivially_inlinable())
+        })
+        .inspect(|((func, heuristics, attributes), _, _)| {
             tracing::trace!(
-                "decided to inline trivial function {:?} based on {:?}",
+                "decided to inline function {:?} based on {:?} with attributes {}",
                 func,
                 heuristics,
+                attributes,
             );
         });
 
-    program.inline_functions(&trivially_inlinable.map(|(func, _)| func))
+    let inlined = program.inline_functions(&trivially_inlinable.map(|(func, _, _)| func));
+    rewrites::log_rewrites("inline", program, &inlined);
+
+    inlined
 }
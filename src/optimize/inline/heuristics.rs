@@ -1,20 +1,16 @@
 use crate::{
-    dataflow::{
-        operators::{CountExt, FilterMap},
-        Program,
-    },
-    repr::{
-        instruction::Call,
-        utils::{CastRef, EstimateAsm, InstructionExt, InstructionPurity},
-        Cast, FuncId,
+    dataflow::{call_graph, with_functions, Difference, JoinOrder, Program, Shared},
+    optimize::{
+        cost::{CostModel, DefaultCostModel},
+        purity,
     },
+    repr::{instruction::Call, utils::CastRef, FuncId},
 };
 use abomonation_derive::Abomonation;
 use differential_dataflow::{
-    difference::{Abelian, Multiply, Semigroup},
     lattice::Lattice,
-    operators::{Consolidate, Join, Reduce, Threshold},
-    Collection, ExchangeData,
+    operators::{Join, Reduce},
+    Collection,
 };
 use num_traits::AsPrimitive;
 use std::iter;
@@ -25,171 +21,143 @@ pub fn harvest_heuristics<S, R>(
 ) -> Collection<S, (FuncId, InlineHeuristics), R>
 where
     S: Scope,
-    S::Timestamp: Lattice,
-    R: Semigroup
-        + Abelian
-        + ExchangeData
-        + Multiply<Output = R>
-        + AsPrimitive<usize>
-        + From<i8>
-        + Clone,
-    isize: Multiply<R, Output = isize>,
+    S::Timestamp: Lattice + Ord,
+    R: Difference + AsPrimitive<usize>,
 {
-    let instructions = program
-        .block_instructions
-        .join_map(&program.instructions, |&inst_id, &block, inst| {
-            (block, (inst_id, inst.clone()))
-        })
-        .join_map(
-            &program.function_blocks,
-            |_block, (_inst_id, inst), &func| (func, inst.clone()),
-        );
+    harvest_heuristics_with(program, DefaultCostModel)
+}
 
-    let mut block_lengths = program
+/// Collects the inlining heuristics of every function, estimating the size
+/// of their bodies with the given cost model
+///
+/// Passing a [`SizeTable`](crate::optimize::size::SizeTable) weighs functions
+/// purely by their estimated code size
+pub fn harvest_heuristics_with<S, R, M>(
+    program: &Program<S, R>,
+    model: M,
+) -> Collection<S, (FuncId, InlineHeuristics), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference + AsPrimitive<usize>,
+    M: CostModel + Clone + 'static,
+{
+    // Rewritten instructions only flow through the join with their locations, which
+    // are built from ids alone and so rarely change. They're shared so the join's
+    // output doesn't deep copy every instruction
+    let shared = program
+        .instructions
+        .map(|(inst_id, inst)| (inst_id, Shared::new(inst)));
+    let instructions = with_functions(program, &shared, JoinOrder::LocationsFirst);
+
+    let blocks = program
         .function_blocks
-        .map(|(_, func)| func)
-        .count_core::<R>();
-
-    block_lengths = block_lengths.concat(
-        &program
-            .function_descriptors
-            .antijoin(&block_lengths.map(|(func, _)| func))
-            .map(|(id, _)| (id, R::from(0))),
-    );
-
-    let mut ssa_inst_lengths = instructions.map(|(func, _)| func).count_core();
-
-    ssa_inst_lengths = ssa_inst_lengths.concat(
-        &program
-            .function_descriptors
-            .antijoin(&ssa_inst_lengths.map(|(func, _)| func))
-            .map(|(id, _)| (id, R::from(0))),
-    );
-
-    let mut invocations = instructions
-        .filter_map(|(_, inst)| inst.cast::<Call>().map(|call| call.func))
-        .count_core();
-
-    invocations = invocations.concat(
-        &program
-            .function_descriptors
-            .antijoin(&invocations.map(|(func, _)| func))
-            .map(|(id, _)| (id, R::from(0))),
-    );
-
-    let mut branches = program
-        .block_terminators
-        .filter(|(_, term)| term.is_branching())
-        .join_map(&program.function_blocks, |_block, _term, &func| func)
-        .count_core();
-
-    branches = branches.concat(
-        &program
-            .function_descriptors
-            .antijoin(&branches.map(|(func, _)| func))
-            .map(|(id, _)| (id, R::from(0))),
-    );
-
-    let mut function_calls = instructions
-        .filter_map(|(func, inst)| inst.cast::<Call>().map(move |_call| func))
-        .count_core();
-
-    function_calls = function_calls.concat(
-        &program
-            .function_descriptors
-            .antijoin(&function_calls.map(|(func, _)| func))
-            .map(|(id, _)| (id, R::from(0))),
-    );
-
-    let mut is_pure = instructions
-        .consolidate()
-        .reduce(|_func, instructions, output| {
-            let is_pure = instructions
-                .iter()
-                .all(|(inst, _)| inst.purity() == InstructionPurity::Pure);
-
-            output.push((is_pure, R::from(1)));
+        .map(|(_, func)| (func, Statistic::Block));
+
+    let instruction_model = model.clone();
+    let instruction_stats = instructions.flat_map(move |(func, inst)| {
+        let cost = instruction_model.instruction_cost(&inst).round() as isize;
+        let call = (*inst).cast_ref::<Call>().map(|call| {
+            iter::once((func, Statistic::Call))
+                .chain(iter::once((call.func, Statistic::Invocation)))
         });
 
-    is_pure = is_pure.concat(
-        &program
-            .function_descriptors
-            .antijoin(&is_pure.map(|(func, _)| func))
-            .map(|(id, _)| (id, true)),
-    );
+        iter::once((func, Statistic::Instruction { cost })).chain(call.into_iter().flatten())
+    });
 
-    let mut is_recursive = instructions
-        .filter_map(|(func, inst)| {
-            inst.cast_ref::<Call>()
-                .filter(|call| call.func == func)
-                .map(|_call| (func, true))
-        })
-        .distinct_core();
-
-    is_recursive = is_recursive.concat(
-        &program
-            .function_descriptors
-            .antijoin(&is_recursive.map(|(func, _)| func))
-            .map(|(id, _)| (id, false)),
-    );
-
-    let terminator_instructions = program
-        .block_terminators
-        .map(|(block, term)| (block, term.estimated_instructions() as isize))
-        .join_map(&program.function_blocks, |_block, &instructions, &func| {
-            (func, instructions)
-        });
-
-    // TODO: Add terminators to this
-    let mut estimated_asm = instructions
-        .map(|(func, inst)| (func, inst.estimated_instructions() as isize))
-        .concat(&terminator_instructions)
-        .explode(|(func, inst)| iter::once((func, (R::from(1), inst))))
-        .count_core::<R>()
-        .map(|(func, diff)| (func, diff.1 as usize));
-
-    estimated_asm = estimated_asm.concat(
-        &terminator_instructions
-            .antijoin(&estimated_asm.map(|(func, _)| func))
-            .map(|(id, instructions)| (id, instructions as usize)),
-    );
-
-    block_lengths
-        .join(&ssa_inst_lengths)
-        .join(&invocations)
-        .join(&branches)
-        .join(&function_calls)
-        .join(&is_pure)
-        .join(&estimated_asm)
-        .join_map(
-            &is_recursive,
-            |&func,
-             &(
-                (
-                    ((((block_length, ssa_inst_length), invocations), branches), function_calls),
-                    is_pure,
-                ),
-                estimated_asm,
-            ),
-             &is_recursive| {
+    let terminator_stats =
+        program
+            .block_terminators
+            .join_map(&program.function_blocks, move |_block, term, &func| {
                 (
                     func,
-                    InlineHeuristics::new(
-                        branches.clone().as_(),
-                        invocations.clone().as_(),
-                        block_length.clone().as_(),
-                        ssa_inst_length.clone().as_(),
-                        function_calls.clone().as_(),
-                        is_pure,
-                        is_recursive,
-                        estimated_asm,
-                    ),
+                    Statistic::Terminator {
+                        cost: model.terminator_cost(term).round() as isize,
+                        branching: term.is_branching(),
+                    },
                 )
-            },
-        )
+            });
+
+    let call_graph = call_graph::call_graph_with(program, JoinOrder::InstructionsFirst);
+    let is_pure = purity::function_purity_with(program, &call_graph)
+        .filter(|&(_, is_pure)| is_pure)
+        .map(|(func, _)| (func, Statistic::Pure));
+    let is_recursive =
+        call_graph::recursive_functions(&call_graph).map(|func| (func, Statistic::Recursive));
+
+    // Every statistic is gathered into a single reduction instead of joining each one
+    // onto the last, so a change to one statistic only touches a single arrangement
+    program
+        .function_descriptors
+        .map(|(func, _)| (func, Statistic::Function))
+        .concat(&blocks)
+        .concat(&instruction_stats)
+        .concat(&terminator_stats)
+        .concat(&is_pure)
+        .concat(&is_recursive)
+        .reduce(|_func, stats, output| {
+            // Statistics of functions that don't exist have nothing to be attached to
+            if stats
+                .first()
+                .map_or(true, |&(stat, _)| *stat != Statistic::Function)
+            {
+                return;
+            }
+
+            let mut heuristics = InlineHeuristics::new(0, 0, 0, 0, 0, false, false, 0);
+            let mut estimated_asm = 0isize;
+
+            for (stat, diff) in stats {
+                let count: usize = diff.clone().as_();
+
+                match **stat {
+                    Statistic::Function => {}
+                    Statistic::Block => heuristics.block_length += count,
+                    Statistic::Instruction { cost } => {
+                        heuristics.ssa_inst_length += count;
+                        estimated_asm += cost * count as isize;
+                    }
+                    Statistic::Call => heuristics.function_calls += count,
+                    Statistic::Invocation => heuristics.invocations += count,
+                    Statistic::Terminator { cost, branching } => {
+                        if branching {
+                            heuristics.branches += count;
+                        }
+                        estimated_asm += cost * count as isize;
+                    }
+                    Statistic::Pure => heuristics.is_pure = true,
+                    Statistic::Recursive => heuristics.is_recursive = true,
+                }
+            }
+            heuristics.estimated_asm = estimated_asm.max(0) as usize;
+
+            output.push((heuristics, R::from(1)));
+        })
+}
+
+/// A single contribution to the [`InlineHeuristics`] of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum Statistic {
+    /// Sorts first so that only existing functions produce heuristics
+    Function,
+    Block,
+    Instruction {
+        cost: isize,
+    },
+    /// The function calls another function
+    Call,
+    /// The function is called by another function
+    Invocation,
+    Terminator {
+        cost: isize,
+        branching: bool,
+    },
+    Pure,
+    Recursive,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InlineHeuristics {
     pub branches: usize,
     pub invocations: usize,
@@ -231,21 +199,27 @@ impl InlineHeuristics {
         }
     }
 
+    /// The cost of inlining the function according to the [`DefaultCostModel`]
+    pub fn inline_cost(&self) -> f32 {
+        self.inline_cost_with(&DefaultCostModel)
+    }
+
     // TODO: Estimate stack size
     // TODO: Hot/cold calling conventions
-    // TODO: Function purity
-    // TODO: inline(never) & inline(always)
-    pub fn inline_cost(&self) -> f32 {
+    pub fn inline_cost_with<M>(&self, model: &M) -> f32
+    where
+        M: CostModel + ?Sized,
+    {
         let mut cost = self.estimated_asm as f32;
-        cost += self.branches as f32 * 1.2;
-        cost += self.function_calls as f32 * 1.4;
+        cost += self.branches as f32 * model.branch_misprediction();
+        cost += self.function_calls as f32 * model.call_overhead();
 
         if self.is_pure {
-            cost *= 0.6;
+            cost *= model.pure_function_factor();
         }
 
         if self.is_recursive {
-            cost *= 1.3;
+            cost *= model.recursive_function_factor();
         }
 
         cost
@@ -255,7 +229,14 @@ impl InlineHeuristics {
     /// and has very few invocations. Inlining trivial functions like this helps with
     /// both performance (via removal of indirection and cache locality) and code size
     pub fn trivially_inlinable(&self) -> bool {
-        self.inline_cost() > Self::TRIVIALLY_INLINABLE
+        self.trivially_inlinable_with(&DefaultCostModel)
+    }
+
+    pub fn trivially_inlinable_with<M>(&self, model: &M) -> bool
+    where
+        M: CostModel + ?Sized,
+    {
+        self.inline_cost_with(model) > Self::TRIVIALLY_INLINABLE
     }
 
     const TRIVIALLY_INLINABLE: f32 = 100.0;
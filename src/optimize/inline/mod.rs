@@ -2,25 +2,20 @@ mod early_inline;
 mod heuristics;
 
 pub use early_inline::early_inline;
-pub use heuristics::{harvest_heuristics, InlineHeuristics};
+pub use heuristics::{harvest_heuristics, harvest_heuristics_with, InlineHeuristics};
 
 use crate::{
-    dataflow::{operators::CollectCastable, Program},
+    dataflow::{operators::CollectCastable, Difference, Program},
     repr::{instruction::Call, FuncId},
 };
-use differential_dataflow::{
-    difference::{Abelian, Multiply, Semigroup},
-    lattice::Lattice,
-    operators::Join,
-    Collection, ExchangeData,
-};
+use differential_dataflow::{lattice::Lattice, operators::Join, Collection};
 use timely::dataflow::Scope;
 
 impl<S, R> Program<S, R>
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Semigroup + Abelian + ExchangeData + Multiply<Output = R>,
+    R: Difference,
 {
     // TODO: Finish this
     pub fn inline_functions(&self, functions: &Collection<S, FuncId, R>) -> Self {
@@ -29,11 +24,32 @@ where
             .region_named("inline functions", |region| {
                 let (program, functions) = (self.enter(region), functions.enter(region));
 
+                // Functions are only inlined into callers with the same calling convention
+                // that are within the same module
+                let conventions = program
+                    .function_descriptors
+                    .map(|(func, desc)| (func, (desc.calling_convention, desc.module)));
+                let callers = program
+                    .block_instructions
+                    .map(|(inst, block)| (block, inst))
+                    .join_map(&program.function_blocks, |_block, &inst, &func| {
+                        (func, inst)
+                    })
+                    .join_map(&conventions, |_func, &inst, &convention| (inst, convention));
+
                 let call_sites = program
                     .instructions
                     .collect_castable::<Call>()
                     .map(|(inst, call)| (call.func, (inst, call)))
-                    .semijoin(&functions);
+                    .semijoin(&functions)
+                    .join_map(&conventions, |_func, (inst, call), &callee| {
+                        (*inst, (call.clone(), callee))
+                    })
+                    .join_map(&callers, |&inst, (call, callee), &caller| {
+                        (inst, call.clone(), *callee == caller)
+                    })
+                    .filter(|&(_, _, matching)| matching)
+                    .map(|(inst, call, _)| (call.func, (inst, call)));
 
                 let _instructions = program
                     .instructions
@@ -0,0 +1,196 @@
+//! Branch weights and the order of blocks within functions
+//!
+//! The edges leaving a branch or switch can carry a [weight](Label::weight), set by
+//! front-ends with [`BasicBlockBuilder::branch_weights()`] or derived from the edge
+//! counts of a profile with [`apply_profile()`]. Passes retarget labels in place, so
+//! weights follow their edges through transforms of the cfg and are only dropped
+//! along with the edges themselves, like when a branch is folded into a jump
+//!
+//! [`layout()`] orders the blocks of a function so that every block is followed by
+//! its hottest successor, which lets backends fall through into it instead of
+//! branching. Blocks are placed after all of their predecessors whenever possible,
+//! so functions without loops are always laid out in a topological order
+//!
+//! [`BasicBlockBuilder::branch_weights()`]: crate::builder::BasicBlockBuilder::branch_weights
+
+use crate::repr::{terminator::Label, BasicBlock, BasicBlockId, Function, Terminator};
+use std::collections::{HashMap, HashSet};
+
+/// The weight of edges without one, edges into blocks that always trap or are
+/// unreachable are assumed to be cold and have no weight at all instead
+pub const DEFAULT_WEIGHT: u32 = 1;
+
+/// Weighs the labels of every branch and switch by the number of times their edge
+/// was taken, keyed by the block the edge leaves and the block it enters
+///
+/// Counts are scaled down uniformly when they don't fit into a weight. Blocks that
+/// were never executed keep their weights, the edges of executed blocks that were
+/// never taken get a weight of zero
+pub fn apply_profile(
+    function: &mut Function,
+    edge_counts: &HashMap<(BasicBlockId, BasicBlockId), u64>,
+) {
+    let largest = edge_counts.values().copied().max().unwrap_or(0);
+    let scale = (largest / u64::from(u32::MAX)) + 1;
+
+    for block in function.basic_blocks.iter_mut() {
+        let id = block.id;
+        let mut labels = block.terminator.labels_mut();
+
+        let executed = labels
+            .iter()
+            .any(|label| edge_counts.contains_key(&(id, label.block)));
+        if executed {
+            for label in labels.iter_mut() {
+                let count = edge_counts.get(&(id, label.block)).copied().unwrap_or(0);
+                label.weight = Some((count / scale) as u32);
+            }
+        }
+    }
+}
+
+/// Orders the blocks of a function for emission, starting with its entry
+///
+/// Each placed block is followed by its heaviest successor out of those whose
+/// predecessors have all been placed, falling back to the earliest such block
+/// within the function and breaking loops by their earliest block. Blocks that
+/// can't be reached from the entry come last, in their original order
+pub fn layout(function: &Function) -> Vec<BasicBlockId> {
+    let blocks: HashMap<BasicBlockId, &BasicBlock> = function
+        .basic_blocks
+        .iter()
+        .map(|block| (block.id, block))
+        .collect();
+
+    let mut reachable = HashSet::new();
+    let mut stack: Vec<BasicBlockId> = Some(function.entry)
+        .filter(|entry| blocks.contains_key(entry))
+        .into_iter()
+        .collect();
+    while let Some(block) = stack.pop() {
+        if reachable.insert(block) {
+            stack.extend(
+                successors(&blocks, blocks[&block])
+                    .into_iter()
+                    .map(|(target, _)| target)
+                    .filter(|target| blocks.contains_key(target)),
+            );
+        }
+    }
+
+    // The number of reachable blocks each block is still waiting on
+    let mut waiting: HashMap<BasicBlockId, usize> = HashMap::new();
+    for &block in reachable.iter() {
+        for target in distinct_targets(blocks[&block]) {
+            *waiting.entry(target).or_insert(0) += 1;
+        }
+    }
+    let is_ready = |waiting: &HashMap<BasicBlockId, usize>, block: &BasicBlockId| {
+        waiting.get(block).copied().unwrap_or(0) == 0
+    };
+
+    let mut order = Vec::with_capacity(function.basic_blocks.len());
+    let mut placed = HashSet::with_capacity(function.basic_blocks.len());
+    let mut current = Some(function.entry).filter(|entry| reachable.contains(entry));
+
+    while let Some(block) = current {
+        placed.insert(block);
+        order.push(block);
+
+        for target in distinct_targets(blocks[&block]) {
+            if let Some(waiting) = waiting.get_mut(&target) {
+                *waiting = waiting.saturating_sub(1);
+            }
+        }
+
+        // The first of the heaviest successors wins ties
+        let mut hottest: Option<(BasicBlockId, u32)> = None;
+        for (target, weight) in successors(&blocks, blocks[&block]) {
+            let candidate = reachable.contains(&target)
+                && !placed.contains(&target)
+                && is_ready(&waiting, &target);
+
+            if candidate && hottest.map_or(true, |(_, hottest)| weight > hottest) {
+                hottest = Some((target, weight));
+            }
+        }
+
+        let unplaced = || {
+            function
+                .basic_blocks
+                .iter()
+                .map(|block| block.id)
+                .filter(|block| reachable.contains(block) && !placed.contains(block))
+        };
+        current = hottest
+            .map(|(target, _)| target)
+            .or_else(|| unplaced().find(|block| is_ready(&waiting, block)))
+            .or_else(|| unplaced().next());
+    }
+
+    order.extend(
+        function
+            .basic_blocks
+            .iter()
+            .map(|block| block.id)
+            .filter(|block| !reachable.contains(block)),
+    );
+
+    order
+}
+
+/// Reorders the blocks of a function into their [layout]
+pub fn lay_out_blocks(function: &Function) -> Function {
+    let position: HashMap<BasicBlockId, usize> = layout(function)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, block)| (block, idx))
+        .collect();
+
+    let mut function = function.clone();
+    function
+        .basic_blocks
+        .sort_by_key(|block| position.get(&block.id).copied().unwrap_or(usize::MAX));
+
+    function
+}
+
+/// Every edge leaving a block along with its weight
+fn successors(
+    blocks: &HashMap<BasicBlockId, &BasicBlock>,
+    block: &BasicBlock,
+) -> Vec<(BasicBlockId, u32)> {
+    let weight = |label: &Label| {
+        label.weight.unwrap_or_else(|| {
+            let cold = blocks.get(&label.block).map_or(false, |target| {
+                matches!(
+                    target.terminator,
+                    Terminator::Trap(_) | Terminator::Unreachable
+                )
+            });
+
+            if cold {
+                0
+            } else {
+                DEFAULT_WEIGHT
+            }
+        })
+    };
+
+    match block.terminator {
+        Terminator::Jump(target) => vec![(target, DEFAULT_WEIGHT)],
+        ref terminator => terminator
+            .labels()
+            .into_iter()
+            .map(|label| (label.block, weight(label)))
+            .collect(),
+    }
+}
+
+fn distinct_targets(block: &BasicBlock) -> Vec<BasicBlockId> {
+    let mut targets = block.terminator.jump_targets();
+    targets.sort_unstable();
+    targets.dedup();
+
+    targets
+}
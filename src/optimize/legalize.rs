@@ -0,0 +1,517 @@
+//! Splitting of 128-bit integers for targets that can't operate on them directly
+//!
+//! Every `int128` and `uint128` variable is replaced by a pair of `uint`s holding
+//! its low and high 64 bits, and every operation on them is expanded into 64-bit
+//! operations on the halves. Signed and unsigned wide integers share the same two's
+//! complement bits, so both split into unsigned halves
+//!
+//! The ir only has equality comparisons, so the carry out of the low halves of an
+//! addition is derived through division instead, `(a + b) / 2^64` is computed as
+//! `(a / 2 + b / 2 + (a % 2 + b % 2) / 2) / 2^63` which never overflows. The high
+//! half of a product is assembled from the 32-bit partial products of the low halves
+//!
+//! Wide division and remainders, calls and opaque instructions returning wide
+//! integers (both only have a single destination), switches over wide integers and
+//! aggregates holding them can't be split and are reported as
+//! [`LegalizeError::Unsupported`]
+
+use crate::{
+    builder::IdAllocator,
+    repr::{
+        instruction::{Add, Assign, Call, Cmp, Div, Mul, Rem, Select, Sub},
+        terminator::Return,
+        BasicBlock, Constant, Function, Instruction, InstructionExt, Terminator, Type, TypedVar,
+        Value, ValueKind, VarId,
+    },
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt::{self, Display},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegalizeError {
+    /// The function uses a wide integer in a way that can't be split into halves
+    Unsupported(&'static str),
+    /// A wide variable is used without being a parameter or the result of an
+    /// instruction
+    UndefinedVar(VarId),
+}
+
+impl Display for LegalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(feature) => {
+                write!(f, "unsupported use of 128-bit integers: {}", feature)
+            }
+            Self::UndefinedVar(var) => write!(f, "use of undefined variable {:?}", var),
+        }
+    }
+}
+
+impl Error for LegalizeError {}
+
+/// Returns `true` if the function has any 128-bit integer parameters, results or
+/// values that [`legalize_wide_integers()`] would split
+pub fn has_wide_integers(function: &Function) -> bool {
+    function.params.iter().any(|param| param.ty.is_wide())
+        || function.ret_ty.results().iter().any(Type::is_wide)
+        || !wide_vars(function).is_empty()
+}
+
+/// Rewrites the function so that every 128-bit integer is held in a pair of 64-bit
+/// halves, minting the ids of the halves and of any temporaries from `ids`
+///
+/// Wide parameters and results are split into two consecutive `uint`s, low half
+/// first, so callers and callees have to be legalized together
+pub fn legalize_wide_integers<A>(function: &Function, ids: &A) -> Result<Function, LegalizeError>
+where
+    A: IdAllocator + ?Sized,
+{
+    if !has_wide_integers(function) {
+        return Ok(function.clone());
+    }
+
+    let halves = wide_vars(function)
+        .into_iter()
+        .chain(
+            function
+                .params
+                .iter()
+                .filter(|param| param.ty.is_wide())
+                .map(|param| param.var),
+        )
+        .map(|var| (var, (ids.var_id(), ids.var_id())))
+        .collect();
+    let mut legalizer = Legalizer {
+        ids,
+        halves,
+        emitted: Vec::new(),
+    };
+
+    let mut legalized = function.clone();
+    legalized.params = Vec::with_capacity(function.params.len());
+    legalized.metadata.params = Vec::new();
+    for (idx, param) in function.params.iter().enumerate() {
+        let attributes = function.metadata.param(idx);
+
+        if param.ty.is_wide() {
+            let (low, high) = legalizer.halves[&param.var];
+            legalized.params.push(TypedVar::new(low, Type::Uint));
+            legalized.params.push(TypedVar::new(high, Type::Uint));
+            legalized.metadata.params.extend([attributes, attributes]);
+        } else {
+            legalized.params.push(param.clone());
+            legalized.metadata.params.push(attributes);
+        }
+    }
+
+    legalized.ret_ty = Type::from_results(
+        function
+            .ret_ty
+            .results()
+            .iter()
+            .flat_map(|result| {
+                if result.is_wide() {
+                    vec![Type::Uint, Type::Uint]
+                } else {
+                    vec![result.clone()]
+                }
+            })
+            .collect(),
+    );
+
+    legalized.basic_blocks = function
+        .basic_blocks
+        .iter()
+        .map(|block| legalizer.block(block))
+        .collect::<Result<_, _>>()?;
+
+    Ok(legalized)
+}
+
+/// Every variable defined by the function's instructions that holds a wide integer
+fn wide_vars(function: &Function) -> BTreeSet<VarId> {
+    let mut wide: BTreeSet<VarId> = function
+        .params
+        .iter()
+        .filter(|param| param.ty.is_wide())
+        .map(|param| param.var)
+        .collect();
+
+    // Blocks aren't ordered by dominance, so keep going until no more wide
+    // variables are found
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        let instructions = function
+            .basic_blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter());
+        for inst in instructions {
+            if !wide.contains(&inst.dest()) && defines_wide(inst, &wide) {
+                wide.insert(inst.dest());
+                changed = true;
+            }
+        }
+    }
+
+    for param in function.params.iter() {
+        wide.remove(&param.var);
+    }
+
+    wide
+}
+
+fn defines_wide(inst: &Instruction, wide: &BTreeSet<VarId>) -> bool {
+    let is_wide = |value: &Value| {
+        value.ty.is_wide()
+            || value
+                .as_const()
+                .map_or(false, |constant| constant.ty().is_wide())
+            || value.as_var().map_or(false, |var| wide.contains(&var))
+    };
+
+    match inst {
+        Instruction::Assign(assign) => is_wide(&assign.value),
+        Instruction::Add(Add { lhs, rhs, .. })
+        | Instruction::Sub(Sub { lhs, rhs, .. })
+        | Instruction::Mul(Mul { lhs, rhs, .. })
+        | Instruction::Div(Div { lhs, rhs, .. })
+        | Instruction::Rem(Rem { lhs, rhs, .. }) => is_wide(lhs) || is_wide(rhs),
+        Instruction::Neg(neg) => is_wide(&neg.value),
+        Instruction::Select(select) => is_wide(&select.if_true) || is_wide(&select.if_false),
+        Instruction::Bitcast(bitcast) => bitcast.dest.ty.is_wide(),
+        Instruction::Call(call) => call.ret_ty.is_wide(),
+        Instruction::Opaque(opaque) => opaque.ret_ty.is_wide(),
+        Instruction::Intrinsic(intrinsic) => intrinsic.ret_ty.is_wide(),
+        Instruction::ExtractValue(extract) => extract.ty.is_wide(),
+        Instruction::Cmp(_) | Instruction::InsertValue(_) => false,
+    }
+}
+
+struct Legalizer<'a, A: ?Sized> {
+    ids: &'a A,
+    /// The low and high halves of every wide variable
+    halves: HashMap<VarId, (VarId, VarId)>,
+    /// The instructions the current instruction has been expanded into
+    emitted: Vec<Instruction>,
+}
+
+impl<'a, A> Legalizer<'a, A>
+where
+    A: IdAllocator + ?Sized,
+{
+    fn block(&mut self, block: &BasicBlock) -> Result<BasicBlock, LegalizeError> {
+        let mut legalized = block.clone();
+        legalized.instructions = Vec::with_capacity(block.instructions.len());
+        legalized.instruction_spans = Vec::new();
+
+        for (idx, inst) in block.instructions.iter().enumerate() {
+            self.instruction(inst)?;
+
+            // Every instruction an instruction expands into keeps its span
+            if !block.instruction_spans.is_empty() {
+                let span = block.instruction_span(idx);
+                legalized
+                    .instruction_spans
+                    .extend(self.emitted.iter().map(|_| span));
+            }
+            legalized.instructions.append(&mut self.emitted);
+        }
+
+        legalized.terminator = match &block.terminator {
+            Terminator::Return(Return { values }) => {
+                let mut split = Vec::with_capacity(values.len());
+                for value in values {
+                    if self.is_wide(value) {
+                        let (low, high) = self.split(value)?;
+                        split.extend([low, high]);
+                    } else {
+                        split.push(value.clone());
+                    }
+                }
+
+                Terminator::Return(Return::multiple(split))
+            }
+            Terminator::Switch(switch) if self.is_wide(&switch.scrutinee) => {
+                return Err(LegalizeError::Unsupported("switches"));
+            }
+            terminator => terminator.clone(),
+        };
+
+        Ok(legalized)
+    }
+
+    fn instruction(&mut self, inst: &Instruction) -> Result<(), LegalizeError> {
+        if !self.halves.contains_key(&inst.dest()) {
+            return self.narrow_instruction(inst);
+        }
+
+        let (low, high) = match inst {
+            Instruction::Assign(assign) => self.split(&assign.value)?,
+            Instruction::Bitcast(bitcast) => self.split(&bitcast.source.clone().into())?,
+
+            Instruction::Add(add) => {
+                let (lhs, rhs) = (self.split(&add.lhs)?, self.split(&add.rhs)?);
+                self.add(lhs, rhs)
+            }
+            Instruction::Sub(sub) => {
+                let (lhs, rhs) = (self.split(&sub.lhs)?, self.split(&sub.rhs)?);
+                let negated = self.neg(rhs);
+                self.add(lhs, negated)
+            }
+            Instruction::Mul(mul) => {
+                let (lhs, rhs) = (self.split(&mul.lhs)?, self.split(&mul.rhs)?);
+                self.mul(lhs, rhs)
+            }
+            Instruction::Neg(neg) => {
+                let value = self.split(&neg.value)?;
+                self.neg(value)
+            }
+            Instruction::Select(select) => {
+                let (if_true, if_false) =
+                    (self.split(&select.if_true)?, self.split(&select.if_false)?);
+                (
+                    self.select_uint(&select.cond, if_true.0, if_false.0),
+                    self.select_uint(&select.cond, if_true.1, if_false.1),
+                )
+            }
+
+            Instruction::Div(_) | Instruction::Rem(_) => {
+                return Err(LegalizeError::Unsupported("division"));
+            }
+            Instruction::Call(_) | Instruction::Opaque(_) => {
+                return Err(LegalizeError::Unsupported("wide results"));
+            }
+            Instruction::Intrinsic(_) => return Err(LegalizeError::Unsupported("intrinsics")),
+            Instruction::ExtractValue(_) => return Err(LegalizeError::Unsupported("aggregates")),
+            Instruction::Cmp(_) | Instruction::InsertValue(_) => {
+                unreachable!("comparisons and insertions never define wide integers")
+            }
+        };
+
+        let (dest_low, dest_high) = self.halves[&inst.dest()];
+        self.emitted
+            .push(Assign::new(dest_low, low, inst.name()).into());
+        self.emitted.push(Assign::new(dest_high, high, None).into());
+
+        Ok(())
+    }
+
+    /// Splits the wide operands of an instruction that doesn't produce a wide integer
+    fn narrow_instruction(&mut self, inst: &Instruction) -> Result<(), LegalizeError> {
+        match inst {
+            Instruction::Cmp(cmp) if self.is_wide(&cmp.lhs) || self.is_wide(&cmp.rhs) => {
+                let (lhs, rhs) = (self.split(&cmp.lhs)?, self.split(&cmp.rhs)?);
+
+                // Both halves are equal when the sum of their differences is zero,
+                // the differences are shrunk first so that the sum can't wrap around
+                let low = self.difference(lhs.0, rhs.0);
+                let high = self.difference(lhs.1, rhs.1);
+                let both = self.add_uint(low, high);
+
+                self.emitted.push(Cmp::new(both, uint(0), cmp.dest).into());
+            }
+
+            Instruction::Call(call) if call.args.iter().any(|arg| self.is_wide(arg)) => {
+                let mut args = Vec::with_capacity(call.args.len());
+                for arg in call.args.iter() {
+                    if self.is_wide(arg) {
+                        let (low, high) = self.split(arg)?;
+                        args.extend([low, high]);
+                    } else {
+                        args.push(arg.clone());
+                    }
+                }
+
+                self.emitted
+                    .push(Call::new(call.func, args, call.dest, call.ret_ty.clone()).into());
+            }
+
+            inst if inst
+                .used_vars()
+                .iter()
+                .any(|used| self.halves.contains_key(&used.var)) =>
+            {
+                return Err(LegalizeError::Unsupported("narrowing"));
+            }
+
+            inst => self.emitted.push(inst.clone()),
+        }
+
+        Ok(())
+    }
+
+    fn is_wide(&self, value: &Value) -> bool {
+        match &value.value {
+            ValueKind::Var(var) => self.halves.contains_key(var),
+            ValueKind::Const(constant) => constant.ty().is_wide(),
+            ValueKind::Pooled(_) => value.ty.is_wide(),
+        }
+    }
+
+    /// The low and high halves of a wide value
+    fn split(&self, value: &Value) -> Result<(Value, Value), LegalizeError> {
+        let bits = match &value.value {
+            ValueKind::Var(var) => {
+                let &(low, high) = self
+                    .halves
+                    .get(var)
+                    .ok_or(LegalizeError::UndefinedVar(*var))?;
+
+                return Ok((var_value(low), var_value(high)));
+            }
+
+            ValueKind::Const(Constant::Int128(int)) => *int as u128,
+            ValueKind::Const(Constant::Uint128(uint)) => *uint,
+            ValueKind::Const(_) => return Err(LegalizeError::Unsupported("mismatched types")),
+            ValueKind::Pooled(_) => return Err(LegalizeError::Unsupported("pooled constants")),
+        };
+
+        Ok((uint(bits as u64), uint((bits >> 64) as u64)))
+    }
+
+    /// Adds an instruction defining a fresh variable of the given type
+    fn emit<F>(&mut self, ty: Type, inst: F) -> Value
+    where
+        F: FnOnce(VarId) -> Instruction,
+    {
+        let dest = self.ids.var_id();
+        self.emitted.push(inst(dest));
+
+        Value::new(ValueKind::Var(dest), ty)
+    }
+
+    fn add_uint(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.emit(Type::Uint, |dest| Add::new(lhs, rhs, dest).into())
+    }
+
+    fn div_uint(&mut self, lhs: Value, divisor: u64) -> Value {
+        self.emit(Type::Uint, |dest| Div::new(lhs, uint(divisor), dest).into())
+    }
+
+    fn rem_uint(&mut self, lhs: Value, divisor: u64) -> Value {
+        self.emit(Type::Uint, |dest| Rem::new(lhs, uint(divisor), dest).into())
+    }
+
+    fn mul_uint(&mut self, lhs: Value, rhs: Value) -> Value {
+        self.emit(Type::Uint, |dest| Mul::new(lhs, rhs, dest).into())
+    }
+
+    fn select_uint(&mut self, cond: &Value, if_true: Value, if_false: Value) -> Value {
+        let cond = cond.clone();
+        self.emit(Type::Uint, |dest| {
+            Select::new(dest, cond, if_true, if_false).into()
+        })
+    }
+
+    /// The difference between two halves divided by four and rounded up, which is
+    /// only zero if the halves are equal
+    fn difference(&mut self, lhs: Value, rhs: Value) -> Value {
+        let difference = self.emit(Type::Uint, |dest| Sub::new(lhs, rhs, dest).into());
+        let quarter = self.div_uint(difference.clone(), 4);
+        let remainder = self.rem_uint(difference, 4);
+        let remainder = self.add_uint(remainder, uint(3));
+        let rounding = self.div_uint(remainder, 4);
+
+        self.add_uint(quarter, rounding)
+    }
+
+    /// The carry out of adding two halves, either zero or one
+    fn carry(&mut self, lhs: Value, rhs: Value) -> Value {
+        let (lhs_half, rhs_half) = (self.div_uint(lhs.clone(), 2), self.div_uint(rhs.clone(), 2));
+        let (lhs_bit, rhs_bit) = (self.rem_uint(lhs, 2), self.rem_uint(rhs, 2));
+
+        let bits = self.add_uint(lhs_bit, rhs_bit);
+        let bits_carry = self.div_uint(bits, 2);
+        let halves = self.add_uint(lhs_half, rhs_half);
+        let halved_sum = self.add_uint(halves, bits_carry);
+
+        self.div_uint(halved_sum, 1 << 63)
+    }
+
+    fn add(
+        &mut self,
+        (lhs_low, lhs_high): (Value, Value),
+        (rhs_low, rhs_high): (Value, Value),
+    ) -> (Value, Value) {
+        let low = self.add_uint(lhs_low.clone(), rhs_low.clone());
+        let carry = self.carry(lhs_low, rhs_low);
+        let high = self.add_uint(lhs_high, rhs_high);
+        let high = self.add_uint(high, carry);
+
+        (low, high)
+    }
+
+    /// Negates a wide value as `!value + 1`
+    fn neg(&mut self, (low, high): (Value, Value)) -> (Value, Value) {
+        let inverted_low = self.emit(Type::Uint, |dest| {
+            Sub::new(uint(u64::MAX), low, dest).into()
+        });
+        let inverted_high = self.emit(Type::Uint, |dest| {
+            Sub::new(uint(u64::MAX), high, dest).into()
+        });
+
+        let negated_low = self.add_uint(inverted_low.clone(), uint(1));
+        let carry = self.carry(inverted_low, uint(1));
+        let negated_high = self.add_uint(inverted_high, carry);
+
+        (negated_low, negated_high)
+    }
+
+    fn mul(
+        &mut self,
+        (lhs_low, lhs_high): (Value, Value),
+        (rhs_low, rhs_high): (Value, Value),
+    ) -> (Value, Value) {
+        const HALF: u64 = 1 << 32;
+
+        let low = self.mul_uint(lhs_low.clone(), rhs_low.clone());
+
+        // The high 64 bits of multiplying the low halves
+        let (a0, a1) = (
+            self.rem_uint(lhs_low.clone(), HALF),
+            self.div_uint(lhs_low.clone(), HALF),
+        );
+        let (b0, b1) = (
+            self.rem_uint(rhs_low.clone(), HALF),
+            self.div_uint(rhs_low.clone(), HALF),
+        );
+        let p00 = self.mul_uint(a0.clone(), b0.clone());
+        let p01 = self.mul_uint(a0, b1.clone());
+        let p10 = self.mul_uint(a1.clone(), b0);
+        let p11 = self.mul_uint(a1, b1);
+
+        let p00_high = self.div_uint(p00, HALF);
+        let p01_low = self.rem_uint(p01.clone(), HALF);
+        let p10_low = self.rem_uint(p10.clone(), HALF);
+        let middle = self.add_uint(p00_high, p01_low);
+        let middle = self.add_uint(middle, p10_low);
+
+        let p01_high = self.div_uint(p01, HALF);
+        let p10_high = self.div_uint(p10, HALF);
+        let middle_high = self.div_uint(middle, HALF);
+        let high = self.add_uint(p11, p01_high);
+        let high = self.add_uint(high, p10_high);
+        let high = self.add_uint(high, middle_high);
+
+        // The cross products only contribute to the high half
+        let cross = self.mul_uint(lhs_low, rhs_high);
+        let high = self.add_uint(high, cross);
+        let cross = self.mul_uint(lhs_high, rhs_low);
+        let high = self.add_uint(high, cross);
+
+        (low, high)
+    }
+}
+
+fn uint(value: u64) -> Value {
+    Value::new(ValueKind::Const(Constant::Uint(value)), Type::Uint)
+}
+
+fn var_value(var: VarId) -> Value {
+    Value::new(ValueKind::Var(var), Type::Uint)
+}
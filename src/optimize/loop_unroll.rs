@@ -0,0 +1,467 @@
+//! Full unrolling of loops with small, statically known trip counts
+//!
+//! Natural loops are found from the back edges of each function's control flow
+//! graph and their trip count is derived from the [branches the range analysis
+//! proves can't be taken](crate::optimize::analysis::impossible_branches). Loops
+//! that fit within an [`UnrollBudget`] are replaced by copies of their body
+//! chained one after another, with fresh ids minted for every copy through the
+//! [remapping utilities](crate::repr::remap), and the back edge of the last copy
+//! is removed. Every other loop is left alone
+//!
+//! Only innermost loops are unrolled, outer loops are picked up by later runs of
+//! the pass once their inner loops are gone
+
+use crate::{
+    builder::IdAllocator,
+    dataflow::{Difference, Program},
+    optimize::analysis::{impossible_branches, variable_ranges, Dominators},
+    repr::{
+        basic_block::BasicBlockDesc, rebase::IdRemapping, remap, terminator::Branch,
+        utils::InstructionRewriter, BasicBlockId, FuncId, InstId, Instruction, Terminator,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+    Collection,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use timely::dataflow::Scope;
+
+/// Limits on how far loops are unrolled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UnrollBudget {
+    /// The largest trip count of a loop that's unrolled
+    pub max_trip_count: u64,
+    /// The most instructions the copies of a single unrolled loop can have
+    pub max_instructions: usize,
+}
+
+impl UnrollBudget {
+    pub const fn new(max_trip_count: u64, max_instructions: usize) -> Self {
+        Self {
+            max_trip_count,
+            max_instructions,
+        }
+    }
+
+    /// Returns `true` if a loop with the given number of instructions can be
+    /// unrolled `trip_count` times
+    pub fn allows(&self, trip_count: u64, instructions: usize) -> bool {
+        trip_count <= self.max_trip_count
+            && (trip_count as usize)
+                .checked_mul(instructions)
+                .map_or(false, |total| total <= self.max_instructions)
+    }
+}
+
+impl Default for UnrollBudget {
+    fn default() -> Self {
+        Self::new(8, 256)
+    }
+}
+
+/// A loop with a single entry through its header
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NaturalLoop {
+    pub header: BasicBlockId,
+    /// The blocks that jump back to the header
+    pub latches: BTreeSet<BasicBlockId>,
+    /// Every block of the loop, including its header
+    pub blocks: BTreeSet<BasicBlockId>,
+}
+
+impl NaturalLoop {
+    /// Returns `true` if `edge` goes from within the loop back to its header
+    pub fn is_back_edge(&self, (from, to): (BasicBlockId, BasicBlockId)) -> bool {
+        to == self.header && self.blocks.contains(&from)
+    }
+}
+
+/// Fully unrolls every loop of the program with a trip count within the budget,
+/// minting the ids of the copies from `ids`
+pub fn loop_unroll<S, R, A>(program: &Program<S, R>, ids: A, budget: UnrollBudget) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
+    A: IdAllocator + 'static,
+{
+    program
+        .instructions
+        .scope()
+        .region_named("loop unrolling", |region| {
+            let program = program.enter_region(region);
+
+            let ranges = variable_ranges(&program.instructions);
+            let impossible = impossible_branches(&program.block_terminators, &ranges);
+
+            // Gather everything about a function that's needed to unroll its loops
+            let entries = program
+                .function_descriptors
+                .map(|(func, desc)| (func, FunctionPart::Entry(desc.entry)));
+            let blocks = program
+                .block_descriptors
+                .join_map(&program.function_blocks, |_, desc, &func| {
+                    (func, FunctionPart::Block(desc.clone()))
+                });
+            let instructions = program
+                .block_instructions
+                .join_map(&program.instructions, |&inst_id, &block, inst| {
+                    (block, (inst_id, inst.clone()))
+                })
+                .join_map(&program.function_blocks, |_, (inst_id, inst), &func| {
+                    (func, FunctionPart::Inst(*inst_id, inst.clone()))
+                });
+            let impossible = impossible
+                .join_map(&program.function_blocks, |&block, &target, &func| {
+                    (func, FunctionPart::Impossible(block, target))
+                });
+
+            let unrolled = entries
+                .concat(&blocks)
+                .concat(&instructions)
+                .concat(&impossible)
+                .reduce(move |&func, parts, output| {
+                    if let Some(unrolled) = unroll_function(func, parts, &ids, budget) {
+                        output.push((unrolled, R::from(1)));
+                    }
+                })
+                .map(|(_, unrolled)| unrolled);
+
+            let removed = unrolled.flat_map(|unrolled| unrolled.removed);
+            let added = unrolled.flat_map(|unrolled| unrolled.added);
+            let added_instructions = unrolled.flat_map(|unrolled| unrolled.instructions);
+
+            let descriptors = added
+                .map(|(_, desc)| (desc.id, desc))
+                .concat(&removed.map(|(_, desc)| (desc.id, desc)).negate());
+
+            let program = Program {
+                instructions: program
+                    .instructions
+                    .concat(&added_instructions.map(|(inst_id, inst, _)| (inst_id, inst))),
+                block_instructions: program
+                    .block_instructions
+                    .concat(&added_instructions.map(|(inst_id, _, block)| (inst_id, block))),
+                block_terminators: program
+                    .block_terminators
+                    .concat(&descriptors.map(|(block, desc)| (block, desc.terminator))),
+                block_descriptors: program.block_descriptors.concat(&descriptors),
+                function_blocks: program.function_blocks.concat(
+                    &added
+                        .map(|(func, desc)| (desc.id, func))
+                        .concat(&removed.map(|(func, desc)| (desc.id, func)).negate()),
+                ),
+                function_descriptors: program.function_descriptors.clone(),
+            };
+
+            program.leave_region()
+        })
+}
+
+/// Finds the natural loops of a function, loops that share a header are merged
+/// into one
+pub fn natural_loops(
+    entry: BasicBlockId,
+    blocks: &BTreeMap<BasicBlockId, BasicBlockDesc>,
+) -> Vec<NaturalLoop> {
+    let successors = |block: BasicBlockId| {
+        blocks
+            .get(&block)
+            .map(|desc| desc.terminator.jump_targets())
+            .unwrap_or_default()
+    };
+
+    let mut predecessors: BTreeMap<BasicBlockId, Vec<BasicBlockId>> = BTreeMap::new();
+    for &block in blocks.keys() {
+        for target in successors(block) {
+            predecessors.entry(target).or_default().push(block);
+        }
+    }
+
+    let dominators = Dominators::of_blocks(entry, blocks);
+
+    let mut loops: BTreeMap<BasicBlockId, NaturalLoop> = BTreeMap::new();
+    for &block in blocks.keys() {
+        for header in successors(block) {
+            if !dominators.dominates(header, block) {
+                continue;
+            }
+
+            let natural_loop = loops.entry(header).or_insert_with(|| NaturalLoop {
+                header,
+                latches: BTreeSet::new(),
+                blocks: Some(header).into_iter().collect(),
+            });
+            natural_loop.latches.insert(block);
+
+            // The loop's body is everything that reaches the latch without passing
+            // through the header
+            let mut stack = vec![block];
+            while let Some(block) = stack.pop() {
+                if natural_loop.blocks.insert(block) {
+                    stack.extend(predecessors.get(&block).into_iter().flatten().copied());
+                }
+            }
+        }
+    }
+
+    loops
+        .into_iter()
+        .map(|(_, natural_loop)| natural_loop)
+        .collect()
+}
+
+/// The number of times the header of a loop runs each time the loop is entered,
+/// `None` if it's unknown
+///
+/// A loop runs exactly once if every path from its header back to itself goes
+/// through a branch that can't be taken
+// TODO: Derive trip counts from induction variables once phi nodes exist, right now
+//       there's no way for a loop to carry values between its iterations
+pub fn trip_count(
+    natural_loop: &NaturalLoop,
+    blocks: &BTreeMap<BasicBlockId, BasicBlockDesc>,
+    impossible: &BTreeSet<(BasicBlockId, BasicBlockId)>,
+) -> Option<u64> {
+    let mut visited = BTreeSet::new();
+    let mut stack = vec![natural_loop.header];
+
+    while let Some(block) = stack.pop() {
+        if !visited.insert(block) {
+            continue;
+        }
+
+        let terminator = &blocks.get(&block)?.terminator;
+        for target in terminator.jump_targets() {
+            if impossible.contains(&(block, target)) {
+                continue;
+            }
+
+            if natural_loop.is_back_edge((block, target)) {
+                return None;
+            } else if natural_loop.blocks.contains(&target) {
+                stack.push(target);
+            }
+        }
+    }
+
+    Some(1)
+}
+
+/// A fact about a function collected for unrolling its loops
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum FunctionPart {
+    Entry(BasicBlockId),
+    Block(BasicBlockDesc),
+    Inst(InstId, Instruction),
+    Impossible(BasicBlockId, BasicBlockId),
+}
+
+/// The changes made to a function by unrolling its loops, blocks whose terminators
+/// changed are both removed and added
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+struct Unrolled {
+    removed: Vec<(FuncId, BasicBlockDesc)>,
+    added: Vec<(FuncId, BasicBlockDesc)>,
+    instructions: Vec<(InstId, Instruction, BasicBlockId)>,
+}
+
+fn unroll_function<A, R>(
+    func: FuncId,
+    parts: &[(&FunctionPart, R)],
+    ids: &A,
+    budget: UnrollBudget,
+) -> Option<Unrolled>
+where
+    A: IdAllocator + ?Sized,
+{
+    let (mut entry, mut blocks, mut instructions, mut impossible) =
+        (None, BTreeMap::new(), BTreeMap::new(), BTreeSet::new());
+    for (part, _diff) in parts {
+        match part {
+            FunctionPart::Entry(block) => entry = Some(*block),
+            FunctionPart::Block(desc) => {
+                blocks.insert(desc.id, desc.clone());
+            }
+            FunctionPart::Inst(inst_id, inst) => {
+                instructions.insert(*inst_id, inst.clone());
+            }
+            FunctionPart::Impossible(block, target) => {
+                impossible.insert((*block, *target));
+            }
+        }
+    }
+
+    let loops = natural_loops(entry?, &blocks);
+    let headers: BTreeSet<_> = loops
+        .iter()
+        .map(|natural_loop| natural_loop.header)
+        .collect();
+
+    let mut unrolled = Unrolled {
+        removed: Vec::new(),
+        added: Vec::new(),
+        instructions: Vec::new(),
+    };
+    for natural_loop in loops.iter() {
+        let is_innermost = natural_loop
+            .blocks
+            .iter()
+            .all(|block| *block == natural_loop.header || !headers.contains(block));
+        if !is_innermost {
+            continue;
+        }
+
+        let trip_count = match trip_count(natural_loop, &blocks, &impossible) {
+            Some(trip_count) => trip_count,
+            None => continue,
+        };
+
+        let loop_blocks: Vec<BasicBlockDesc> = match natural_loop
+            .blocks
+            .iter()
+            .map(|block| blocks.get(block).cloned())
+            .collect()
+        {
+            Some(loop_blocks) => loop_blocks,
+            None => continue,
+        };
+        let loop_instructions: Vec<(InstId, Instruction)> = loop_blocks
+            .iter()
+            .flat_map(|desc| desc.instructions.iter())
+            .filter_map(|inst_id| {
+                instructions
+                    .get(inst_id)
+                    .map(|inst| (*inst_id, inst.clone()))
+            })
+            .collect();
+
+        if !budget.allows(trip_count, loop_instructions.len()) {
+            tracing::trace!(
+                func = ?func,
+                header = ?natural_loop.header,
+                trip_count,
+                instructions = loop_instructions.len(),
+                "loop exceeds the unrolling budget",
+            );
+
+            continue;
+        }
+
+        // Back edges of switches can't be removed without rebuilding the switch
+        let switches_back = loop_blocks.iter().any(|desc| match &desc.terminator {
+            Terminator::Switch(switch) => switch
+                .jump_targets()
+                .into_iter()
+                .any(|target| natural_loop.is_back_edge((desc.id, target))),
+            _ => false,
+        });
+        if switches_back {
+            continue;
+        }
+
+        tracing::trace!(
+            func = ?func,
+            header = ?natural_loop.header,
+            trip_count,
+            "fully unrolling loop",
+        );
+
+        // The original blocks are the first copy, every other copy is chained after it
+        let mut copies = vec![(loop_blocks.clone(), IdRemapping::default())];
+        for _ in 1..trip_count {
+            let (copy, copy_instructions, remapping) =
+                remap::clone_descs(&loop_blocks, &loop_instructions, ids);
+
+            let placement: BTreeMap<InstId, BasicBlockId> = copy
+                .iter()
+                .flat_map(|desc| desc.instructions.iter().map(move |&inst| (inst, desc.id)))
+                .collect();
+            unrolled
+                .instructions
+                .extend(copy_instructions.into_iter().map(|(inst_id, inst)| {
+                    let block = placement[&inst_id];
+                    (inst_id, inst, block)
+                }));
+
+            copies.push((copy, remapping));
+        }
+
+        let copy_headers: Vec<BasicBlockId> = copies
+            .iter()
+            .map(|(_, remapping)| remapping.block(natural_loop.header))
+            .collect();
+        for (idx, (copy, _)) in copies.into_iter().enumerate() {
+            let header = copy_headers[idx];
+
+            for (original, mut desc) in loop_blocks.iter().zip(copy) {
+                if let Some(&next) = copy_headers.get(idx + 1) {
+                    // Jumps back to the header continue into the next copy
+                    let mut retarget = IdRemapping::default();
+                    retarget.blocks.insert(header, next);
+
+                    let mut rewriter = &retarget;
+                    rewriter.rewrite_terminator(&mut desc.terminator);
+                } else {
+                    remove_back_edges(natural_loop, header, original.id, &mut desc, &impossible);
+                }
+
+                if natural_loop.blocks.contains(&desc.id) {
+                    if desc != *original {
+                        unrolled.removed.push((func, original.clone()));
+                        unrolled.added.push((func, desc));
+                    }
+                } else {
+                    unrolled.added.push((func, desc));
+                }
+            }
+        }
+    }
+
+    if unrolled.added.is_empty() && unrolled.removed.is_empty() {
+        None
+    } else {
+        Some(unrolled)
+    }
+}
+
+/// Replaces the back edges of a block from the last copy of an unrolled loop, which
+/// are never taken. `header` is the header of the copy while `original` is the
+/// block the copy was made from
+fn remove_back_edges(
+    natural_loop: &NaturalLoop,
+    header: BasicBlockId,
+    original: BasicBlockId,
+    desc: &mut BasicBlockDesc,
+    impossible: &BTreeSet<(BasicBlockId, BasicBlockId)>,
+) {
+    let back_edge_impossible = impossible.contains(&(original, natural_loop.header));
+
+    let replacement = match &desc.terminator {
+        &Terminator::Jump(target) if target == header => {
+            // Anything that unconditionally jumps back to the header can't be
+            // reached, otherwise the loop would run more than its trip count
+            Some(Terminator::Unreachable)
+        }
+
+        Terminator::Branch(Branch {
+            if_true, if_false, ..
+        }) if if_true.block == header && back_edge_impossible => {
+            Some(Terminator::Jump(if_false.block))
+        }
+        Terminator::Branch(Branch {
+            if_true, if_false, ..
+        }) if if_false.block == header && back_edge_impossible => {
+            Some(Terminator::Jump(if_true.block))
+        }
+
+        _ => None,
+    };
+
+    if let Some(replacement) = replacement {
+        desc.terminator = replacement;
+    }
+}
@@ -1,9 +1,11 @@
-use crate::{dataflow::Program, repr::BasicBlockId};
+use crate::{
+    dataflow::{Difference, Program},
+    repr::BasicBlockId,
+};
 use differential_dataflow::{
-    difference::{Abelian, Multiply, Semigroup},
     lattice::Lattice,
     operators::{Consolidate, Iterate, Join, Threshold},
-    Collection, ExchangeData,
+    Collection,
 };
 use timely::dataflow::Scope;
 
@@ -11,7 +13,7 @@ impl<S, R> Program<S, R>
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Semigroup + Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     pub fn loops(&self) -> Collection<S, (BasicBlockId, BasicBlockId), R> {
         let graph = self.block_terminators.flat_map(|(block, term)| {
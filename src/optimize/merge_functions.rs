@@ -0,0 +1,256 @@
+//! Deduplication of structurally identical functions
+//!
+//! Aggressive inlining and front ends that monomorphize generics both tend to produce
+//! functions that only differ in the ids they use. Every function is reduced to a
+//! canonical shape with its blocks and variables renumbered in the order they first
+//! appear in, and functions that share a shape are merged into the one with the
+//! lowest id. Calls to the duplicates are redirected to that canonical copy and the
+//! duplicates are removed, unless they're exported and could be called from outside
+//! of the program
+
+use crate::{
+    dataflow::{operators::FilterMap, Difference, Program},
+    repr::{
+        function::FunctionDesc, instruction::Call, rebase::IdRemapping, utils::CastRef, BasicBlock,
+        BasicBlockId, FuncId, Function, FunctionAttributes, Instruction, InstructionExt,
+        Terminator, VarId,
+    },
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+    Collection,
+};
+use std::num::NonZeroU64;
+use timely::dataflow::Scope;
+
+/// Merges every function into the lowest-id function with the same shape, see the
+/// [module docs](self)
+pub fn merge_functions<S, R>(program: &Program<S, R>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    program
+        .instructions
+        .scope()
+        .region_named("merge functions", |region| {
+            let program = program.enter_region(region);
+            let duplicates = duplicate_functions(&program);
+
+            let redirected_calls = program
+                .instructions
+                .filter_map(|(inst_id, inst)| {
+                    inst.cast_ref::<Call>()
+                        .map(|call| (call.func, (inst_id, inst.clone())))
+                })
+                .join_map(&duplicates, |_duplicate, (inst_id, inst), &canonical| {
+                    let mut redirected = inst.clone();
+                    if let Instruction::Call(call) = &mut redirected {
+                        call.func = canonical;
+                    }
+
+                    (*inst_id, inst.clone(), redirected)
+                });
+
+            let instructions = program
+                .instructions
+                .concat(
+                    &redirected_calls
+                        .map(|(inst_id, inst, _)| (inst_id, inst))
+                        .negate(),
+                )
+                .concat(&redirected_calls.map(|(inst_id, _, redirected)| (inst_id, redirected)));
+
+            // Exported duplicates are kept since their callers may not be within the program
+            let removed_functions = program
+                .function_descriptors
+                .semijoin(&duplicates.map(|(duplicate, _)| duplicate))
+                .filter(|(_, desc)| !desc.attributes.is_exported())
+                .map(|(func, _)| func);
+            let removed_blocks = program
+                .function_blocks
+                .map(|(block, func)| (func, block))
+                .semijoin(&removed_functions)
+                .map(|(_, block)| block);
+            let removed_instructions = program
+                .block_instructions
+                .map(|(inst, block)| (block, inst))
+                .semijoin(&removed_blocks)
+                .map(|(_, inst)| inst);
+
+            Program {
+                instructions: instructions.antijoin(&removed_instructions),
+                block_instructions: program.block_instructions.antijoin(&removed_instructions),
+                block_terminators: program.block_terminators.antijoin(&removed_blocks),
+                block_descriptors: program.block_descriptors.antijoin(&removed_blocks),
+                function_blocks: program.function_blocks.antijoin(&removed_blocks),
+                function_descriptors: program.function_descriptors.antijoin(&removed_functions),
+            }
+            .leave_region()
+        })
+}
+
+/// Produces every function that has the same shape as a function with a lower id,
+/// along with the lowest id of the functions that share its shape
+pub fn duplicate_functions<S, R>(program: &Program<S, R>) -> Collection<S, (FuncId, FuncId), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let block_contents = program
+        .block_descriptors
+        .flat_map(|(block, desc)| {
+            desc.instructions
+                .into_iter()
+                .enumerate()
+                .map(move |(index, inst)| (inst, (block, index)))
+        })
+        .join_map(&program.instructions, |_inst_id, &(block, index), inst| {
+            (block, (index, inst.clone()))
+        })
+        .reduce(|_block, instructions, output| {
+            // Values are sorted, so instructions are in the order they appear in the block
+            let instructions: Vec<Instruction> = instructions
+                .iter()
+                .map(|(&(_, ref inst), _)| inst.clone())
+                .collect();
+
+            output.push((instructions, R::from(1)));
+        });
+
+    // Blocks that have no instructions still have terminators
+    let empty_blocks = program
+        .block_terminators
+        .map(|(block, _)| block)
+        .antijoin(&block_contents.map(|(block, _)| block))
+        .map(|block| (block, Vec::new()));
+
+    let function_blocks = block_contents
+        .concat(&empty_blocks)
+        .join_map(
+            &program.block_terminators,
+            |&block, instructions, terminator| (block, (instructions.clone(), terminator.clone())),
+        )
+        .join_map(
+            &program.function_blocks,
+            |&block, (instructions, terminator), &func| {
+                (func, (block, instructions.clone(), terminator.clone()))
+            },
+        )
+        .reduce(|_func, blocks, output| {
+            let blocks: Vec<_> = blocks.iter().map(|(block, _)| (*block).clone()).collect();
+            output.push((blocks, R::from(1)));
+        });
+
+    program
+        .function_descriptors
+        .join_map(&function_blocks, |&func, desc, blocks| {
+            (function_shape(desc, blocks), func)
+        })
+        .reduce(|_shape, functions, output| {
+            // Function ids are sorted, so the first function is the canonical copy
+            let canonical = *functions[0].0;
+            for &(&func, _) in functions.iter().skip(1) {
+                output.push(((func, canonical), R::from(1)));
+            }
+        })
+        .map(|(_shape, duplicate)| duplicate)
+}
+
+/// Rebuilds a function with every id it defines renumbered in order of appearance
+/// and with everything that doesn't affect its behavior stripped out, so that two
+/// functions have the same shape exactly when they're interchangeable
+fn function_shape(
+    desc: &FunctionDesc,
+    blocks: &[(BasicBlockId, Vec<Instruction>, Terminator)],
+) -> Function {
+    let mut basic_blocks: Vec<BasicBlock> = blocks
+        .iter()
+        .map(|(id, instructions, terminator)| BasicBlock {
+            name: None,
+            id: *id,
+            instructions: instructions.iter().cloned().map(strip_name).collect(),
+            terminator: terminator.clone(),
+            instruction_spans: Vec::new(),
+            terminator_span: None,
+        })
+        .collect();
+    basic_blocks.sort_by_key(|block| desc.basic_blocks.iter().position(|&id| id == block.id));
+
+    let mut remapping = IdRemapping::default();
+
+    // Recursive calls are the same for every copy of a function
+    remapping
+        .functions
+        .insert(desc.id, FuncId::new(NonZeroU64::new(u64::MAX).unwrap()));
+
+    for (index, block) in basic_blocks.iter().enumerate() {
+        remapping
+            .blocks
+            .insert(block.id, BasicBlockId::new(nonzero(index)));
+    }
+
+    let mut number_var = |var: VarId| {
+        let next = VarId::new(nonzero(remapping.vars.len()));
+        remapping.vars.entry(var).or_insert(next);
+    };
+    desc.params.iter().for_each(|param| number_var(param.var));
+    for block in basic_blocks.iter() {
+        for inst in block.instructions.iter() {
+            inst.used_vars()
+                .into_iter()
+                .for_each(|var| number_var(var.var));
+            number_var(inst.dest());
+        }
+        block
+            .terminator
+            .used_vars()
+            .into_iter()
+            .for_each(&mut number_var);
+    }
+
+    // Whether a function is exported doesn't change what it does
+    let attributes = desc.attributes.without(FunctionAttributes::EXPORT);
+    let mut shape = Function {
+        name: None,
+        id: desc.id,
+        params: desc.params.clone(),
+        ret_ty: desc.ret_ty.clone(),
+        entry: desc.entry,
+        basic_blocks,
+        metadata: desc.metadata().with_attributes(attributes),
+    };
+    remapping.apply(&mut shape);
+
+    shape
+}
+
+/// Removes the debug name of an instruction
+fn strip_name(mut inst: Instruction) -> Instruction {
+    match &mut inst {
+        Instruction::Assign(assign) => assign.name = None,
+        Instruction::Add(add) => add.name = None,
+        Instruction::Sub(sub) => sub.name = None,
+        Instruction::Mul(mul) => mul.name = None,
+        Instruction::Div(div) => div.name = None,
+        Instruction::Rem(rem) => rem.name = None,
+        Instruction::Bitcast(_)
+        | Instruction::Neg(_)
+        | Instruction::Cmp(_)
+        | Instruction::Select(_)
+        | Instruction::Call(_)
+        | Instruction::Opaque(_)
+        | Instruction::Intrinsic(_)
+        | Instruction::ExtractValue(_)
+        | Instruction::InsertValue(_) => {}
+    }
+
+    inst
+}
+
+fn nonzero(index: usize) -> NonZeroU64 {
+    NonZeroU64::new(index as u64 + 1).unwrap()
+}
@@ -1,4 +1,21 @@
+pub mod analysis;
+pub mod autotune;
+pub mod canonicalize;
 pub mod constant_folding;
+pub mod copy_propagation;
+pub mod cost;
+pub mod equality_saturation;
+pub mod fuel;
+pub mod if_conversion;
 pub mod inline;
+pub mod layout;
+pub mod legalize;
+pub mod loop_unroll;
 pub mod loops;
+pub mod merge_functions;
 pub mod peephole;
+pub mod purity;
+pub mod rewrites;
+pub mod schedule;
+pub mod size;
+pub mod tail_call;
@@ -1,18 +1,24 @@
 use crate::{
-    dataflow::operators::{CollectCastable, FilterMap},
+    dataflow::{
+        panics::{self, PanicContext},
+        Difference,
+    },
+    optimize::rewrites,
     repr::{
-        instruction::{Assign, BinopExt, Mul, Neg, Sub},
+        instruction::{Assign, BinopExt, Neg},
+        utils::InstructionRewriter,
         Constant, InstId, Instruction, InstructionExt,
     },
 };
 use differential_dataflow::{
-    difference::{Abelian, Multiply, Semigroup},
     lattice::Lattice,
-    operators::{consolidate::ConsolidateStream, Consolidate, Join},
-    Collection, ExchangeData,
+    operators::{consolidate::ConsolidateStream, Consolidate},
+    Collection,
 };
+use std::fmt::{self, Debug};
 use timely::dataflow::Scope;
 
+/// Applies the [built-in](PeepholePass::builtin_rules) peephole rules to every instruction
 pub fn peephole<S, R>(
     scope: &mut S,
     instructions: &Collection<S, (InstId, Instruction), R>,
@@ -20,144 +26,226 @@ pub fn peephole<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Semigroup + Abelian + ExchangeData + Multiply<Output = R>,
+    R: Difference,
 {
-    let span = tracing::debug_span!("peephole optimization");
-    span.in_scope(|| {
-        scope.region_named("peephole optimization", |region| {
-            let instructions = instructions.enter(region).consolidate_stream();
-            let instructions = apply::<_, _, MulByZero>(&instructions).consolidate_stream();
-            let instructions = apply::<_, _, SubtractZero>(&instructions);
-
-            instructions.consolidate().leave_region()
-        })
-    })
+    PeepholePass::new().apply(scope, instructions)
 }
 
-fn apply<S, R, P>(
-    instructions: &Collection<S, (InstId, Instruction), R>,
-) -> Collection<S, (InstId, Instruction), R>
-where
-    S: Scope,
-    S::Timestamp: Lattice,
-    R: Semigroup + Abelian + ExchangeData + Multiply<Output = R>,
-    P: PeepholePass,
-{
-    let span = tracing::debug_span!("peephole pass", name = P::name());
-    let reduced = span.in_scope(|| P::stream_instructions(instructions));
-
-    instructions
-        .antijoin(&reduced.map(|(inst, _)| inst))
-        .concat(&reduced)
+/// A registry of peephole rules that are applied to every instruction in order
+///
+/// ```rust,ignore
+/// fn add_zero(inst: &Instruction) -> Option<Instruction> { ... }
+///
+/// let pass = PeepholePass::new().with_rule(PeepholeRule::new("add zero", add_zero));
+/// let instructions = pass.apply(scope, &instructions);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeepholePass {
+    rules: Vec<PeepholeRule>,
 }
 
-pub trait PeepholePass {
-    fn name() -> &'static str;
+impl PeepholePass {
+    /// Creates a pass with all of the built-in rules
+    pub fn new() -> Self {
+        Self {
+            rules: Self::builtin_rules().to_vec(),
+        }
+    }
 
-    fn stream_instructions<S, R>(
-        instructions: &Collection<S, (InstId, Instruction), R>,
-    ) -> Collection<S, (InstId, Instruction), R>
-    where
-        S: Scope,
-        R: Semigroup,
-    {
-        instructions.clone()
+    /// Creates a pass without any rules
+    pub const fn empty() -> Self {
+        Self { rules: Vec::new() }
     }
-}
 
-struct MulByZero;
+    /// The rules included within [`PeepholePass::new()`]
+    pub const fn builtin_rules() -> &'static [PeepholeRule] {
+        &[MUL_BY_ZERO, SUBTRACT_ZERO]
+    }
+
+    /// Adds a rule to the end of the pass
+    pub fn with_rule(mut self, rule: PeepholeRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn rules(&self) -> &[PeepholeRule] {
+        &self.rules
+    }
 
-impl PeepholePass for MulByZero {
-    fn name() -> &'static str {
-        "multiply by zero"
+    /// Rewrites a single instruction, feeding the output of each matching rule
+    /// into the rules after it and returning `None` if no rule matched
+    pub fn rewrite(&self, inst: &Instruction) -> Option<Instruction> {
+        let mut rewritten: Option<Instruction> = None;
+        for rule in self.rules.iter() {
+            if let Some(inst) = rule.rewrite(rewritten.as_ref().unwrap_or(inst)) {
+                rewritten = Some(inst);
+            }
+        }
+
+        rewritten
     }
 
-    fn stream_instructions<S, R>(
+    pub fn apply<S, R>(
+        &self,
+        scope: &mut S,
         instructions: &Collection<S, (InstId, Instruction), R>,
     ) -> Collection<S, (InstId, Instruction), R>
     where
         S: Scope,
-        R: Semigroup,
+        S::Timestamp: Lattice,
+        R: Difference,
     {
-        // TODO: This could be a `.map_in_place()`
-        instructions
-            .collect_castable::<Mul>()
-            .filter_map(|(id, mul)| {
-                let constant = {
-                    let lhs = mul.lhs();
-                    lhs.as_const().cloned().map(|c| ((lhs, c), true))
-                }
-                .or_else(|| {
-                    let rhs = mul.rhs();
-                    rhs.as_const().cloned().map(|c| ((rhs, c), false))
-                });
-
-                constant
-                    .and_then(|((val, constant), is_lhs)| match constant {
-                        Constant::Int(int) if int == 0 => Some(((val, Constant::Int(0)), is_lhs)),
-                        Constant::Uint(uint) if uint == 0 => {
-                            Some(((val, Constant::Uint(0)), is_lhs))
-                        }
-                        _ => None,
+        let span = tracing::debug_span!("peephole optimization", rules = self.rules.len());
+        span.in_scope(|| {
+            scope.region_named("peephole optimization", |region| {
+                let pass = self.clone();
+
+                instructions
+                    .enter(region)
+                    .consolidate_stream()
+                    .map(move |(id, mut inst)| {
+                        GuardedRules::new(&pass, id).rewrite_instruction(&mut inst);
+                        (id, inst)
                     })
-                    .map(|((value, zero), is_lhs)| {
-                        tracing::trace!(
-                            inst = ?id,
-                            "reduced `{}` to `0`",
-                            if is_lhs { "0 * x" } else { "x * 0" },
-                        );
-
-                        let assign = Assign::new(mul.dest(), value.map(|_| zero.into()), None);
-                        Instruction::Assign(assign)
-                    })
-                    .map(|inst| (id, inst))
+                    .consolidate()
+                    .leave_region()
             })
+        })
     }
 }
 
-struct SubtractZero;
+impl Default for PeepholePass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-impl PeepholePass for SubtractZero {
-    fn name() -> &'static str {
-        "subtract zero"
+/// Peephole rules replace whole instructions, so instructions are rewritten as a
+/// unit instead of by their parts
+impl InstructionRewriter for PeepholePass {
+    fn rewrite_instruction(&mut self, inst: &mut Instruction) -> bool {
+        match self.rewrite(inst) {
+            Some(rewritten) => {
+                *inst = rewritten;
+                true
+            }
+            None => false,
+        }
     }
+}
 
-    fn stream_instructions<S, R>(
-        instructions: &Collection<S, (InstId, Instruction), R>,
-    ) -> Collection<S, (InstId, Instruction), R>
-    where
-        S: Scope,
-        R: Semigroup,
-    {
-        // TODO: This could be a `.map_in_place()`
-        instructions
-            .collect_castable::<Sub>()
-            .filter_map(|(id, sub)| {
-                if let Some(constant) = sub.lhs().as_const() {
-                    // TODO: figure out unsigned interactions
-                    if constant.is_zero() && constant.is_signed_int() {
-                        tracing::trace!(
-                            inst = ?id,
-                            "reduced `0 - x` to `-x`",
-                        );
-
-                        return Some((id, Instruction::Neg(Neg::new(sub.dest(), sub.rhs()))));
-                    }
-                } else if let Some(constant) = sub.rhs().as_const() {
-                    if constant.is_zero() {
-                        tracing::trace!(
-                            inst = ?id,
-                            "reduced `x - 0` to `x`",
-                        );
-
-                        return Some((
-                            id,
-                            Instruction::Assign(Assign::new(sub.dest(), sub.lhs(), None)),
-                        ));
-                    }
+/// Rewrites instructions like [`PeepholePass`], but rules that panic are skipped
+/// and reported instead of taking down the worker
+struct GuardedRules<'a> {
+    pass: &'a PeepholePass,
+    id: InstId,
+}
+
+impl<'a> GuardedRules<'a> {
+    const fn new(pass: &'a PeepholePass, id: InstId) -> Self {
+        Self { pass, id }
+    }
+}
+
+impl InstructionRewriter for GuardedRules<'_> {
+    fn rewrite_instruction(&mut self, inst: &mut Instruction) -> bool {
+        let mut changed = false;
+        for rule in self.pass.rules.iter() {
+            let context = PanicContext::pass("peephole")
+                .with_rule(rule.name())
+                .with_instruction(self.id);
+
+            match panics::catch(context, || rule.rewrite(inst)) {
+                Ok(Some(rewritten)) => {
+                    rewrites::rewritten(rule.name(), None, self.id, Some(&*inst), Some(&rewritten));
+
+                    *inst = rewritten;
+                    changed = true;
+                }
+                Ok(None) => {}
+                Err(diagnostic) => {
+                    tracing::error!("skipped a peephole rule that {}", diagnostic);
                 }
+            }
+        }
 
-                None
-            })
+        changed
+    }
+}
+
+/// A single local rewrite over an instruction
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PeepholeRule {
+    name: &'static str,
+    rewrite: fn(&Instruction) -> Option<Instruction>,
+}
+
+impl PeepholeRule {
+    /// Creates a rule from a function that returns the rewritten instruction
+    /// or `None` if the rule doesn't apply to it
+    pub const fn new(name: &'static str, rewrite: fn(&Instruction) -> Option<Instruction>) -> Self {
+        Self { name, rewrite }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
     }
+
+    pub fn rewrite(&self, inst: &Instruction) -> Option<Instruction> {
+        (self.rewrite)(inst)
+    }
+}
+
+impl Debug for PeepholeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PeepholeRule").field(&self.name).finish()
+    }
+}
+
+/// Reduces `x * 0` and `0 * x` to `0`
+pub const MUL_BY_ZERO: PeepholeRule = PeepholeRule::new("multiply by zero", mul_by_zero);
+
+/// Reduces `x - 0` to `x` and `0 - x` to `-x` for signed integers
+pub const SUBTRACT_ZERO: PeepholeRule = PeepholeRule::new("subtract zero", subtract_zero);
+
+fn mul_by_zero(inst: &Instruction) -> Option<Instruction> {
+    let mul = match inst {
+        Instruction::Mul(mul) => mul,
+        _ => return None,
+    };
+
+    let (value, zero) =
+        [mul.lhs(), mul.rhs()]
+            .iter()
+            .find_map(|value| match value.as_const()? {
+                zero if zero.is_zero() => Some((value.clone(), zero.clone())),
+                _ => None,
+            })?;
+
+    let assign = Assign::new(mul.dest(), value.map(|_| zero.into()), None);
+    Some(Instruction::Assign(assign))
+}
+
+fn subtract_zero(inst: &Instruction) -> Option<Instruction> {
+    let sub = match inst {
+        Instruction::Sub(sub) => sub,
+        _ => return None,
+    };
+
+    if let Some(constant) = sub.lhs().as_const() {
+        // TODO: figure out unsigned interactions
+        if constant.is_zero() && constant.is_signed_int() {
+            return Some(Instruction::Neg(Neg::new(sub.dest(), sub.rhs())));
+        }
+    } else if let Some(constant) = sub.rhs().as_const() {
+        if constant.is_zero() {
+            return Some(Instruction::Assign(Assign::new(
+                sub.dest(),
+                sub.lhs(),
+                None,
+            )));
+        }
+    }
+
+    None
 }
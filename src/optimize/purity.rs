@@ -0,0 +1,127 @@
+use crate::{
+    dataflow::{
+        algorithms::reachable,
+        call_graph::{self, CallEdge},
+        operators::{FilterMap, Reverse},
+        Difference, Program,
+    },
+    repr::{
+        instruction::Call,
+        utils::{CastRef, InstructionExt, InstructionPurity},
+        FuncId, InstId,
+    },
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Threshold},
+    Collection,
+};
+use timely::dataflow::Scope;
+
+/// Produces the purity of every function within the program
+///
+/// A function is pure if none of its instructions are impure and every function
+/// it (transitively) calls is pure, calls are ignored when looking at instructions
+/// and are instead resolved through the call graph
+pub fn function_purity<S, R>(program: &Program<S, R>) -> Collection<S, (FuncId, bool), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    function_purity_with(program, &call_graph::call_graph(program))
+}
+
+/// The same as [`function_purity()`] but reuses an already computed call graph
+pub fn function_purity_with<S, R>(
+    program: &Program<S, R>,
+    call_graph: &Collection<S, CallEdge, R>,
+) -> Collection<S, (FuncId, bool), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let impure = impure_functions(program, call_graph);
+
+    program
+        .function_descriptors
+        .map(|(func, _)| (func, true))
+        .antijoin(&impure)
+        .concat(&impure.map(|func| (func, false)))
+}
+
+/// Produces all functions that are pure, see [`function_purity()`]
+pub fn pure_functions<S, R>(
+    program: &Program<S, R>,
+    call_graph: &Collection<S, CallEdge, R>,
+) -> Collection<S, FuncId, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    program
+        .function_descriptors
+        .map(|(func, _)| func)
+        .antijoin(&impure_functions(program, call_graph))
+}
+
+/// Produces all call instructions that invoke pure functions, making them eligible
+/// for the same treatment as any other pure instruction by dce and cse
+pub fn pure_calls<S, R>(
+    program: &Program<S, R>,
+    pure_functions: &Collection<S, FuncId, R>,
+) -> Collection<S, InstId, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    program
+        .instructions
+        .filter_map(|(inst_id, inst)| inst.cast_ref::<Call>().map(|call| (call.func, inst_id)))
+        .semijoin(pure_functions)
+        .map(|(_func, inst_id)| inst_id)
+}
+
+/// Finds all impure functions by propagating the impurity of functions backwards
+/// through the call graph until a fixpoint is reached
+fn impure_functions<S, R>(
+    program: &Program<S, R>,
+    call_graph: &Collection<S, CallEdge, R>,
+) -> Collection<S, FuncId, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let locally_impure = program
+        .instructions
+        .filter(|(_, inst)| inst.cast_ref::<Call>().is_none())
+        .filter(|(_, inst)| inst.purity() != InstructionPurity::Pure)
+        .join_map(&program.block_instructions, |_inst_id, _inst, &block| {
+            (block, ())
+        })
+        .join_map(&program.function_blocks, |_block, &(), &func| func);
+
+    // Calling a function we know nothing about is conservatively assumed to be impure
+    let unknown_callees = call_graph
+        .reverse()
+        .antijoin(&program.function_descriptors.map(|(func, _)| func))
+        .map(|(_callee, caller)| caller);
+
+    // External functions have no body to look into, so they're impure as well
+    let externals = program
+        .function_descriptors
+        .filter(|(_, desc)| desc.attributes.is_external())
+        .map(|(func, _)| func);
+
+    let roots = locally_impure
+        .concat(&unknown_callees)
+        .concat(&externals)
+        .distinct_core();
+
+    // Impurity flows from callees to their callers
+    reachable::reachable(&call_graph.reverse(), &roots)
+}
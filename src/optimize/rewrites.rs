@@ -0,0 +1,114 @@
+//! Structured events for the rewrites passes make
+//!
+//! Every rewrite is reported as a `TRACE` event with the target [`REWRITES`] that
+//! holds the function and instruction it touched, the rule that made it and the
+//! instruction pretty printed both before and after it. Events are emitted within a
+//! `rewrite` span carrying the rule, so single rules can be picked out with an env
+//! filter like `sruth::rewrites[rewrite{rule=peephole}]=trace`
+//!
+//! Instructions are only pretty printed for events that are actually recorded, but
+//! the dataflow that finds the rewrites of a whole pass is only built within debug
+//! builds, like the other per-update logging
+
+use crate::{
+    dataflow::{instruction_functions, operators::InspectExt, Difference, Program},
+    repr::{utils::IRDisplay, FuncId, InstId, Instruction},
+    symbols::NoSymbols,
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+};
+use std::fmt::{self, Display};
+use timely::dataflow::Scope;
+
+/// The target of every rewrite event
+pub const REWRITES: &str = "sruth::rewrites";
+
+/// Reports a single rewrite of an instruction, `before` is `None` for instructions
+/// the rule created and `after` is `None` for ones it deleted
+pub fn rewritten(
+    rule: &str,
+    func: Option<FuncId>,
+    inst: InstId,
+    before: Option<&Instruction>,
+    after: Option<&Instruction>,
+) {
+    let span = tracing::trace_span!(target: REWRITES, "rewrite", rule);
+    let _guard = span.enter();
+
+    tracing::trace!(
+        target: REWRITES,
+        rule,
+        func = ?func,
+        inst = ?inst,
+        before = %Pretty(before),
+        after = %Pretty(after),
+        "{}",
+        match (before, after) {
+            (Some(_), Some(_)) => "rewrote an instruction",
+            (None, _) => "created an instruction",
+            (_, None) => "deleted an instruction",
+        },
+    );
+}
+
+/// Reports every instruction that differs between a program and the output of a
+/// rule applied to it, attributing them to the functions they belong to
+pub fn log_rewrites<S, R>(rule: &'static str, before: &Program<S, R>, after: &Program<S, R>)
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
+{
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    let functions = instruction_functions(before).concat(&instruction_functions(after));
+    before
+        .instructions
+        .map(|(inst, instruction)| (inst, (false, instruction)))
+        .concat(
+            &after
+                .instructions
+                .map(|(inst, instruction)| (inst, (true, instruction))),
+        )
+        .reduce(|_inst, versions, output| {
+            let (mut before, mut after) = (None, None);
+            for ((is_after, instruction), _) in versions {
+                if *is_after {
+                    after = Some(instruction.clone());
+                } else {
+                    before = Some(instruction.clone());
+                }
+            }
+
+            if before != after {
+                output.push(((before, after), R::from(1)));
+            }
+        })
+        .join_map(&functions, |&inst, versions, &func| {
+            (inst, (func, versions.clone()))
+        })
+        .reduce(|_inst, located, output| {
+            // Moved instructions belong to a function both before and after
+            let ((func, versions), _) = &located[0];
+            output.push(((*func, versions.clone()), R::from(1)));
+        })
+        .debug_inspect(move |((inst, (func, (before, after))), _, _)| {
+            rewritten(rule, Some(*func), *inst, before.as_ref(), after.as_ref());
+        });
+}
+
+/// Pretty prints an instruction only once it's displayed
+struct Pretty<'a>(Option<&'a Instruction>);
+
+impl Display for Pretty<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(inst) => f.write_str(&inst.to_pretty_string(&NoSymbols)),
+            None => f.write_str("none"),
+        }
+    }
+}
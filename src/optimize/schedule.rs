@@ -0,0 +1,230 @@
+//! Orders the instructions within each basic block so that values are computed
+//! directly before they're used
+//!
+//! Blocks otherwise keep the order their instructions were created in, which leaves
+//! intermediate values live across unrelated instructions. A stack machine like wasm
+//! can only leave a value on the stack instead of in a local when it's consumed by the
+//! computation that follows it, so every pure value with a single use within its
+//! block is scheduled right before its user, after the operands its user consumes
+//! before it. Every other instruction, including every effectful one, keeps its
+//! relative order
+
+use crate::{
+    dataflow::{
+        analysis::{Use, UseDef},
+        Difference, Program,
+    },
+    repr::{
+        utils::InstructionPurity, BasicBlockId, InstId, Instruction, InstructionExt, Terminator,
+        VarId,
+    },
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+};
+use std::collections::HashMap;
+use timely::dataflow::Scope;
+
+pub fn schedule<S, R>(program: &Program<S, R>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    schedule_with(program, &program.use_def())
+}
+
+/// The same as [`schedule()`] but counts the uses of each variable within an
+/// existing [`UseDef`] of the program
+pub fn schedule_with<S, R>(program: &Program<S, R>, use_def: &UseDef<S, R>) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let span = tracing::debug_span!("instruction scheduling");
+    span.in_scope(|| {
+        // The place every variable with exactly one use is used at
+        let single_uses = use_def
+            .def_uses
+            .as_collection(|&var, &used| (var, used))
+            .reduce(|_var, uses, output| {
+                if let [(&used, _)] = uses {
+                    output.push((used, R::from(1)));
+                }
+            });
+        let users = program
+            .instructions
+            .map(|(id, inst)| (inst.dest(), id))
+            .join_map(&single_uses, |_dest, &id, &used| (id, used));
+
+        let located = program
+            .block_instructions
+            .join_map(&program.instructions, |&id, &block, inst| {
+                (id, (block, inst.clone()))
+            });
+        let block_contents = located
+            .join_map(&users, |&id, (block, inst), &used| {
+                (*block, (id, inst.clone(), Some(used)))
+            })
+            .concat(
+                &located
+                    .antijoin(&users.map(|(id, _)| id))
+                    .map(|(id, (block, inst))| (block, (id, inst, None))),
+            )
+            .reduce(|_block, contents, output| {
+                let contents: Vec<_> = contents
+                    .iter()
+                    .map(|(content, _)| (*content).clone())
+                    .collect();
+                output.push((contents, R::from(1)));
+            });
+
+        let scheduled = program
+            .block_descriptors
+            .join_map(&block_contents, |&block, desc, contents| {
+                (block, (desc.clone(), contents.clone()))
+            })
+            .join_map(
+                &program.block_terminators,
+                |&block, (desc, contents), terminator| {
+                    let mut desc = desc.clone();
+                    desc.instructions =
+                        BlockSchedule::new(block, &desc.instructions, contents, terminator)
+                            .schedule();
+
+                    (block, desc)
+                },
+            );
+
+        // Blocks without any instructions have nothing to reorder
+        let block_descriptors = program
+            .block_descriptors
+            .antijoin(&block_contents.map(|(block, _)| block))
+            .concat(&scheduled);
+
+        Program {
+            block_descriptors,
+            ..program.clone()
+        }
+    })
+}
+
+/// The instructions of a single block along with which of them can be left on the
+/// stack for their user
+struct BlockSchedule<'a> {
+    instructions: Vec<&'a (InstId, Instruction, Option<Use>)>,
+    terminator: &'a Terminator,
+    /// The index of the instruction defining each variable defined within the block
+    defs: HashMap<VarId, usize>,
+    /// Whether each instruction is scheduled directly before its only user
+    stacked: Vec<bool>,
+    placed: Vec<bool>,
+    order: Vec<InstId>,
+}
+
+impl<'a> BlockSchedule<'a> {
+    fn new(
+        block: BasicBlockId,
+        order: &[InstId],
+        contents: &'a [(InstId, Instruction, Option<Use>)],
+        terminator: &'a Terminator,
+    ) -> Self {
+        // Instructions missing from the block's current order go after the ones within it
+        let positions: HashMap<InstId, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(position, &id)| (id, position))
+            .collect();
+        let mut instructions: Vec<_> = contents.iter().collect();
+        instructions
+            .sort_by_key(|&&(id, _, _)| (positions.get(&id).copied().unwrap_or(usize::MAX), id));
+
+        let indices: HashMap<InstId, usize> = instructions
+            .iter()
+            .enumerate()
+            .map(|(idx, &&(id, _, _))| (id, idx))
+            .collect();
+        let defs = instructions
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, inst, _))| (inst.dest(), idx))
+            .collect();
+
+        // Values used more than once by their user still need a local to be read from
+        let uses_once =
+            |used: &[VarId], var: VarId| used.iter().filter(|&&v| v == var).count() == 1;
+        let stacked = instructions
+            .iter()
+            .map(|(_, inst, used)| {
+                let dest = inst.dest();
+
+                inst.purity() == InstructionPurity::Pure
+                    && match *used {
+                        Some(Use::Inst(user)) => indices.get(&user).map_or(false, |&user| {
+                            let used: Vec<_> = instructions[user]
+                                .1
+                                .used_vars()
+                                .into_iter()
+                                .map(|used| used.var)
+                                .collect();
+
+                            uses_once(&used, dest)
+                        }),
+                        Some(Use::Terminator(user)) => {
+                            user == block && uses_once(&terminator.used_vars(), dest)
+                        }
+                        None => false,
+                    }
+            })
+            .collect();
+
+        Self {
+            placed: vec![false; instructions.len()],
+            order: Vec::with_capacity(instructions.len()),
+            instructions,
+            terminator,
+            defs,
+            stacked,
+        }
+    }
+
+    fn schedule(mut self) -> Vec<InstId> {
+        for idx in 0..self.instructions.len() {
+            if !self.stacked[idx] {
+                self.place(idx);
+            }
+        }
+
+        for var in self.terminator.used_vars() {
+            self.place_operand(var);
+        }
+
+        debug_assert_eq!(self.order.len(), self.instructions.len());
+        self.order
+    }
+
+    /// Places an instruction after the stacked instructions computing its operands
+    fn place(&mut self, idx: usize) {
+        if self.placed[idx] {
+            return;
+        }
+        self.placed[idx] = true;
+
+        let &(id, ref inst, _) = self.instructions[idx];
+        for operand in inst.used_vars() {
+            self.place_operand(operand.var);
+        }
+
+        self.order.push(id);
+    }
+
+    fn place_operand(&mut self, var: VarId) {
+        if let Some(&def) = self.defs.get(&var) {
+            if self.stacked[def] {
+                self.place(def);
+            }
+        }
+    }
+}
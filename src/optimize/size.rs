@@ -0,0 +1,242 @@
+//! Code size estimation
+//!
+//! Sizes are looked up from per-target [`SizeTable`]s that give the approximate
+//! number of bytes each kind of instruction and terminator emits, they're used
+//! by the inlining heuristics (see [`SizeTable`]'s [`CostModel`] impl) and to
+//! report the size of every function through [`function_sizes()`]
+
+use crate::{
+    dataflow::{Difference, Program},
+    optimize::cost::CostModel,
+    repr::{instruction::IntrinsicOp, FuncId, Instruction, Terminator},
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+    Collection,
+};
+use num_traits::AsPrimitive;
+use std::fmt::{self, Display};
+use timely::dataflow::Scope;
+
+/// The number of bytes each instruction and terminator is expected to take
+/// up once emitted for a given target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SizeTable {
+    pub assign: usize,
+    pub neg: usize,
+    pub add: usize,
+    pub sub: usize,
+    pub mul: usize,
+    pub div: usize,
+    pub cmp: usize,
+    pub select: usize,
+    pub bitcast: usize,
+    pub extract: usize,
+    pub insert: usize,
+    pub call: usize,
+    /// The extra size of every argument passed to a call
+    pub call_arg: usize,
+    /// The extra size of every argument passed to an opaque instruction, the
+    /// payload itself is always counted byte for byte
+    pub opaque_arg: usize,
+    pub jump: usize,
+    pub branch: usize,
+    pub switch: usize,
+    /// The extra size of every case within a switch's jump table
+    pub switch_case: usize,
+    pub ret: usize,
+    pub unreachable: usize,
+    /// The fixed size of every function, such as its prologue and epilogue
+    pub function_overhead: usize,
+}
+
+impl SizeTable {
+    /// Sizes for the wasm binary format, where most operations need to load
+    /// their operands from locals and store their result back into one
+    pub const WASM: Self = Self {
+        assign: 4,
+        neg: 7,
+        add: 7,
+        sub: 7,
+        mul: 7,
+        div: 7,
+        cmp: 7,
+        select: 10,
+        bitcast: 0,
+        extract: 7,
+        insert: 10,
+        call: 4,
+        call_arg: 2,
+        opaque_arg: 2,
+        jump: 2,
+        branch: 6,
+        switch: 6,
+        switch_case: 2,
+        ret: 3,
+        unreachable: 1,
+        function_overhead: 4,
+    };
+
+    /// Sizes for a typical register machine with variable length encodings
+    pub const NATIVE: Self = Self {
+        assign: 4,
+        neg: 3,
+        add: 3,
+        sub: 3,
+        mul: 4,
+        div: 7,
+        cmp: 6,
+        select: 7,
+        bitcast: 0,
+        extract: 4,
+        insert: 4,
+        call: 5,
+        call_arg: 3,
+        opaque_arg: 3,
+        jump: 5,
+        branch: 8,
+        switch: 12,
+        switch_case: 4,
+        ret: 1,
+        unreachable: 2,
+        function_overhead: 8,
+    };
+
+    pub fn instruction_size(&self, inst: &Instruction) -> usize {
+        match inst {
+            Instruction::Assign(_) => self.assign,
+            Instruction::Neg(_) => self.neg,
+            Instruction::Add(_) => self.add,
+            Instruction::Sub(_) => self.sub,
+            Instruction::Mul(_) => self.mul,
+            Instruction::Div(_) | Instruction::Rem(_) => self.div,
+            Instruction::Cmp(_) => self.cmp,
+            Instruction::Select(_) => self.select,
+            Instruction::Bitcast(_) => self.bitcast,
+            Instruction::ExtractValue(_) => self.extract,
+            Instruction::InsertValue(_) => self.insert,
+            Instruction::Call(call) => self.call + call.args.len() * self.call_arg,
+            Instruction::Opaque(opaque) => {
+                opaque.payload.len() + opaque.args.len() * self.opaque_arg
+            }
+            // Memory copies are lowered into calls into the runtime
+            Instruction::Intrinsic(intrinsic) => match intrinsic.op {
+                IntrinsicOp::Memcpy => self.call + intrinsic.args.len() * self.call_arg,
+                _ => self.add,
+            },
+        }
+    }
+
+    pub fn terminator_size(&self, terminator: &Terminator) -> usize {
+        match terminator {
+            Terminator::Jump(_) => self.jump,
+            Terminator::Branch(_) => self.branch,
+            Terminator::Switch(switch) => self.switch + switch.cases.len() * self.switch_case,
+            Terminator::Return(_) => self.ret,
+            Terminator::Unreachable | Terminator::Trap(_) => self.unreachable,
+        }
+    }
+}
+
+impl Default for SizeTable {
+    fn default() -> Self {
+        Self::NATIVE
+    }
+}
+
+/// Weighs code purely by its size, for inlining that optimizes for size
+impl CostModel for SizeTable {
+    fn instruction_cost(&self, inst: &Instruction) -> f32 {
+        self.instruction_size(inst) as f32
+    }
+
+    fn terminator_cost(&self, terminator: &Terminator) -> f32 {
+        self.terminator_size(terminator) as f32
+    }
+
+    fn call_overhead(&self) -> f32 {
+        self.call as f32
+    }
+
+    // Mispredictions don't affect code size
+    fn branch_misprediction(&self) -> f32 {
+        0.0
+    }
+}
+
+/// The estimated size of a function's body in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionSize {
+    pub instructions: usize,
+    pub terminators: usize,
+    pub overhead: usize,
+}
+
+impl FunctionSize {
+    pub const fn total(&self) -> usize {
+        self.instructions + self.terminators + self.overhead
+    }
+}
+
+impl Display for FunctionSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes ({} in instructions, {} in terminators, {} of overhead)",
+            self.total(),
+            self.instructions,
+            self.terminators,
+            self.overhead,
+        )
+    }
+}
+
+/// Estimates the size of every function within the program using the given table
+pub fn function_sizes<S, R>(
+    program: &Program<S, R>,
+    table: SizeTable,
+) -> Collection<S, (FuncId, FunctionSize), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference + AsPrimitive<usize>,
+{
+    let instruction_sizes = program
+        .block_instructions
+        .join_map(&program.instructions, move |_inst, &block, inst| {
+            (block, (table.instruction_size(inst), 0))
+        });
+    let terminator_sizes = program
+        .block_terminators
+        .map(move |(block, term)| (block, (0, table.terminator_size(&term))));
+
+    let block_sizes = instruction_sizes
+        .concat(&terminator_sizes)
+        .join_map(&program.function_blocks, |_block, &sizes, &func| {
+            (func, sizes)
+        });
+
+    // Every function gets a size, even ones without any blocks
+    let overheads = program.function_descriptors.map(|(func, _)| (func, (0, 0)));
+
+    block_sizes
+        .concat(&overheads)
+        .reduce(move |_func, sizes, output| {
+            let mut size = FunctionSize {
+                instructions: 0,
+                terminators: 0,
+                overhead: table.function_overhead,
+            };
+
+            for &(&(instructions, terminators), ref diff) in sizes {
+                let count: usize = diff.as_();
+                size.instructions += instructions * count;
+                size.terminators += terminators * count;
+            }
+
+            output.push((size, R::from(1)));
+        })
+}
@@ -0,0 +1,276 @@
+//! Turns self-recursive tail calls into loops
+//!
+//! A block whose last instruction calls its own function and that then returns the
+//! call's result (or returns nothing from a function returning unit) doesn't need
+//! a new stack frame, the call's arguments are bound to the function's parameters
+//! and the block jumps back to the start of the function instead. Since nothing may
+//! jump to a function's entry, functions with tail calls are given a fresh entry
+//! that falls through to their original one, which becomes the loop's header
+//!
+//! Arguments are first copied into temporaries so that parameters read by later
+//! arguments aren't clobbered. Rebinding parameters assigns to them more than once,
+//! so the pass is meant to run after the passes that rely on every variable having
+//! a single definition, as part of lowering a program for emission
+
+use crate::{
+    builder::IdAllocator,
+    dataflow::{Difference, Program},
+    repr::{
+        basic_block::BasicBlockDesc,
+        function::FunctionDesc,
+        instruction::{Assign, Call},
+        terminator::Return,
+        utils::CastRef,
+        BasicBlockId, InstId, Instruction, Terminator, Type, TypedVar, Value, ValueKind,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+};
+use timely::dataflow::Scope;
+
+/// Rewrites every self-recursive tail call of the program into a jump back to the
+/// start of its function, minting the ids of the new blocks, variables and
+/// instructions from `ids`
+pub fn tail_call<S, R, A>(program: &Program<S, R>, ids: A) -> Program<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+    A: IdAllocator + 'static,
+{
+    program
+        .instructions
+        .scope()
+        .region_named("tail call elimination", |region| {
+            let program = program.enter_region(region);
+
+            // Find the blocks that end in a call followed by a return
+            let tail_calls = program
+                .block_descriptors
+                .flat_map(|(block, desc)| {
+                    desc.instructions
+                        .last()
+                        .copied()
+                        .map(|inst| (inst, (block, desc)))
+                })
+                .join_map(&program.instructions, |&inst_id, (block, desc), inst| {
+                    inst.cast_ref::<Call>()
+                        .map(|call| (*block, (desc.clone(), inst_id, call.clone())))
+                })
+                .flat_map(|candidate| candidate)
+                .join_map(
+                    &program.function_blocks,
+                    |_, (desc, inst_id, call), &func| {
+                        (func, (desc.clone(), *inst_id, call.clone()))
+                    },
+                )
+                .join_map(
+                    &program.function_descriptors,
+                    |&func, (desc, inst_id, call), function| {
+                        (
+                            func,
+                            (function.clone(), desc.clone(), *inst_id, call.clone()),
+                        )
+                    },
+                )
+                .filter(|(func, (function, desc, _, call))| {
+                    call.func == *func && is_tail_call(function, desc, call)
+                });
+
+            let rewrites = tail_calls
+                .map(|(func, (function, desc, inst_id, call))| {
+                    (
+                        func,
+                        (
+                            function,
+                            TailCall {
+                                desc,
+                                inst_id,
+                                call,
+                            },
+                        ),
+                    )
+                })
+                .reduce(move |_func, tail_calls, output| {
+                    let function = (tail_calls[0].0).0.clone();
+                    let tail_calls = tail_calls.iter().map(|((_, tail_call), _)| tail_call);
+
+                    output.push((rewrite_function(function, tail_calls, &ids), R::from(1)));
+                })
+                .map(|(_, rewrite)| rewrite);
+
+            let removed_blocks = rewrites
+                .flat_map(|rewrite| rewrite.blocks.into_iter().map(|(old, _)| (old.id, old)));
+            let added_blocks = rewrites.flat_map(|rewrite| {
+                rewrite
+                    .blocks
+                    .into_iter()
+                    .map(|(_, new)| new)
+                    .chain(Some(rewrite.entry))
+                    .map(|desc| (desc.id, desc))
+            });
+            let descriptors = added_blocks.concat(&removed_blocks.negate());
+
+            let removed_calls = rewrites.flat_map(|rewrite| rewrite.removed);
+            let added_instructions = rewrites.flat_map(|rewrite| rewrite.added);
+
+            let program = Program {
+                instructions: program
+                    .instructions
+                    .concat(
+                        &removed_calls
+                            .map(|(inst_id, inst, _)| (inst_id, inst))
+                            .negate(),
+                    )
+                    .concat(&added_instructions.map(|(inst_id, inst, _)| (inst_id, inst))),
+                block_instructions: program
+                    .block_instructions
+                    .concat(
+                        &removed_calls
+                            .map(|(inst_id, _, block)| (inst_id, block))
+                            .negate(),
+                    )
+                    .concat(&added_instructions.map(|(inst_id, _, block)| (inst_id, block))),
+                block_terminators: program
+                    .block_terminators
+                    .concat(&descriptors.map(|(block, desc)| (block, desc.terminator))),
+                block_descriptors: program.block_descriptors.concat(&descriptors),
+                function_blocks: program
+                    .function_blocks
+                    .concat(&rewrites.map(|rewrite| (rewrite.entry.id, rewrite.function.1.id))),
+                function_descriptors: program.function_descriptors.concat(
+                    &rewrites
+                        .map(|rewrite| (rewrite.function.0.id, rewrite.function.0))
+                        .negate()
+                        .concat(
+                            &rewrites.map(|rewrite| (rewrite.function.1.id, rewrite.function.1)),
+                        ),
+                ),
+            };
+
+            program.leave_region()
+        })
+}
+
+/// Returns `true` if `call` is immediately followed by a return of its result,
+/// `call` must be the last instruction of `block`
+pub fn is_tail_call(function: &FunctionDesc, block: &BasicBlockDesc, call: &Call) -> bool {
+    if call.args.len() != function.params.len() {
+        return false;
+    }
+
+    match &block.terminator {
+        Terminator::Return(Return { values }) => match values.as_slice() {
+            [] => function.ret_ty == Type::Unit,
+            [value] => value.as_var() == Some(call.dest),
+            _ => false,
+        },
+
+        _ => false,
+    }
+}
+
+/// A tail call found within a block
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+struct TailCall {
+    desc: BasicBlockDesc,
+    inst_id: InstId,
+    call: Call,
+}
+
+/// The changes made to a function by rewriting its tail calls
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+struct Rewrite {
+    /// The function before and after its entry was replaced
+    function: (FunctionDesc, FunctionDesc),
+    /// The new entry block
+    entry: BasicBlockDesc,
+    /// Every block with a tail call before and after it was rewritten
+    blocks: Vec<(BasicBlockDesc, BasicBlockDesc)>,
+    removed: Vec<(InstId, Instruction, BasicBlockId)>,
+    added: Vec<(InstId, Instruction, BasicBlockId)>,
+}
+
+fn rewrite_function<'a, I, A>(function: FunctionDesc, tail_calls: I, ids: &A) -> Rewrite
+where
+    I: Iterator<Item = &'a TailCall>,
+    A: IdAllocator + ?Sized,
+{
+    let header = function.entry;
+    let entry = BasicBlockDesc::new(None, ids.block_id(), Vec::new(), Terminator::Jump(header));
+
+    let mut rewritten = function.clone();
+    rewritten.entry = entry.id;
+    rewritten.basic_blocks.insert(0, entry.id);
+
+    let mut rewrite = Rewrite {
+        function: (function.clone(), rewritten),
+        entry,
+        blocks: Vec::new(),
+        removed: Vec::new(),
+        added: Vec::new(),
+    };
+
+    for TailCall {
+        desc,
+        inst_id,
+        call,
+    } in tail_calls
+    {
+        tracing::trace!(
+            func = ?function.id,
+            block = ?desc.id,
+            call = ?inst_id,
+            "rewriting self-recursive tail call into a loop",
+        );
+
+        let mut new_desc = desc.clone();
+        new_desc.instructions.pop();
+        new_desc.terminator = Terminator::Jump(header);
+
+        let mut bind = |inst: Instruction| {
+            let id = ids.inst_id();
+            new_desc.instructions.push(id);
+            rewrite.added.push((id, inst, desc.id));
+        };
+
+        // Arguments that read a parameter are copied out before any parameter is
+        // rebound, everything else can be assigned directly
+        let reads_param = |value: &Value| {
+            value.as_var().map_or(false, |var| {
+                function.params.iter().any(|param| param.var == var)
+            })
+        };
+        let mut bindings = Vec::with_capacity(call.args.len());
+        for (param, arg) in function.params.iter().zip(call.args.iter()) {
+            if arg.as_var() == Some(param.var) {
+                continue;
+            }
+
+            let value = if reads_param(arg) {
+                let temp = TypedVar::new(ids.var_id(), param.ty.clone());
+                bind(Assign::new(temp.var, arg.clone(), None).into());
+
+                Value::new(ValueKind::Var(temp.var), temp.ty)
+            } else {
+                arg.clone()
+            };
+
+            bindings.push((param.var, value));
+        }
+
+        for (param, value) in bindings {
+            bind(Assign::new(param, value, None).into());
+        }
+
+        rewrite
+            .removed
+            .push((*inst_id, Instruction::Call(call.clone()), desc.id));
+        rewrite.blocks.push((desc.clone(), new_desc));
+    }
+
+    rewrite
+}
@@ -0,0 +1,98 @@
+//! Helpers for parallelizing batch work that happens outside of dataflows, like
+//! rendering graphs or reports
+//!
+//! With the `parallel` feature enabled work is spread across rayon's global thread
+//! pool, otherwise everything runs sequentially on the calling thread. The
+//! [`MaybeSend`] and [`MaybeSync`] bounds only require their respective traits when
+//! the feature is enabled so that sequential builds don't impose them on callers
+
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Requires [`Send`] only when the `parallel` feature is enabled
+#[cfg(feature = "parallel")]
+pub trait MaybeSend: Send {}
+
+#[cfg(feature = "parallel")]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// Requires [`Send`] only when the `parallel` feature is enabled
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSend {}
+
+#[cfg(not(feature = "parallel"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// Requires [`Sync`] only when the `parallel` feature is enabled
+#[cfg(feature = "parallel")]
+pub trait MaybeSync: Sync {}
+
+#[cfg(feature = "parallel")]
+impl<T: Sync + ?Sized> MaybeSync for T {}
+
+/// Requires [`Sync`] only when the `parallel` feature is enabled
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSync {}
+
+#[cfg(not(feature = "parallel"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+/// Maps every item, preserving the order of the items in the output
+pub fn map<T, U, F>(items: Vec<T>, map: F) -> Vec<U>
+where
+    T: MaybeSend,
+    U: MaybeSend,
+    F: Fn(T) -> U + MaybeSend + MaybeSync,
+{
+    #[cfg(feature = "parallel")]
+    {
+        items.into_par_iter().map(map).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.into_iter().map(map).collect()
+    }
+}
+
+/// Calls `func` on every item in no particular order
+pub fn for_each<T, F>(items: Vec<T>, func: F)
+where
+    T: MaybeSend,
+    F: Fn(T) + MaybeSend + MaybeSync,
+{
+    #[cfg(feature = "parallel")]
+    {
+        items.into_par_iter().for_each(func);
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.into_iter().for_each(func);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn map_matches_serial_map() {
+        let items: Vec<usize> = (0..1000).collect();
+        let square = |item: usize| (item, item * item);
+
+        let serial: Vec<_> = items.clone().into_iter().map(square).collect();
+        assert_eq!(super::map(items, square), serial);
+        assert_eq!(super::map(Vec::new(), square), Vec::new());
+    }
+
+    #[test]
+    fn for_each_visits_every_item() {
+        let sum = AtomicUsize::new(0);
+        super::for_each((1..=100).collect(), |item: usize| {
+            sum.fetch_add(item, Ordering::Relaxed);
+        });
+
+        assert_eq!(sum.into_inner(), 5050);
+    }
+}
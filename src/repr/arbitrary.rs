@@ -0,0 +1,274 @@
+#![cfg(any(test, feature = "arbitrary"))]
+
+//! Random well-typed functions for fuzzing the pipeline
+//!
+//! Every generated module is built through the [`Builder`], so it's valid by
+//! construction and can be given straight to the optimizer, the verifier or
+//! anything else that consumes ir. Generated functions have random acyclic control
+//! flow, arithmetic over their parameters and small constants, and calls into the
+//! functions generated before them. Since there are no loops or recursive calls
+//! every function terminates on every input, which makes evaluating the functions
+//! before and after optimization a usable oracle for semantic preservation
+//!
+//! ```rust,ignore
+//! let functions = IrGenerator::new(seed).functions(&context);
+//! let output = Driver::new(context).run(functions, Pass::ALL);
+//! assert!(output.errors.is_empty());
+//! ```
+//!
+//! The generator is always available to the crate's own tests, which use it as the
+//! corpus that passes are tested against
+//!
+//! [`Builder`]: crate::builder::Builder
+
+use crate::{
+    builder::{BasicBlockBuilder, BuildResult, Builder, Context},
+    repr::{BasicBlockId, Constant, FuncId, Function, Type, Value},
+};
+use std::{collections::HashSet, sync::Arc};
+
+/// Limits on the size of the generated functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArbitraryConfig {
+    /// The maximum number of functions within a module, at least one is always made
+    pub max_functions: usize,
+    /// The maximum number of parameters each function takes
+    pub max_params: usize,
+    /// The maximum number of basic blocks within each function
+    pub max_blocks: usize,
+    /// The maximum number of instructions within each basic block, not counting the
+    /// comparisons of branches
+    pub max_instructions: usize,
+    /// Constants are taken from `0..max_constant`
+    pub max_constant: i64,
+}
+
+impl Default for ArbitraryConfig {
+    fn default() -> Self {
+        Self {
+            max_functions: 4,
+            max_params: 3,
+            max_blocks: 5,
+            max_instructions: 5,
+            max_constant: 16,
+        }
+    }
+}
+
+/// Generates random modules from a seed, the same seed always generates the same
+/// module
+#[derive(Debug, Clone)]
+pub struct IrGenerator {
+    rng: Rng,
+    config: ArbitraryConfig,
+}
+
+impl IrGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            config: ArbitraryConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: ArbitraryConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub const fn config(&self) -> &ArbitraryConfig {
+        &self.config
+    }
+
+    /// Generates a module within a fresh builder, returning its functions
+    pub fn functions(&mut self, context: &Arc<Context>) -> Vec<Function> {
+        let mut builder = context.builder();
+        self.module(&mut builder)
+            .expect("generated functions are valid by construction");
+
+        let functions = builder.materialize().collect();
+        builder.discard();
+
+        functions
+    }
+
+    /// Generates a module within the given builder, returning the ids of its
+    /// functions in the order they were generated in
+    pub fn module(&mut self, builder: &mut Builder) -> BuildResult<Vec<FuncId>> {
+        let num_functions = 1 + self.rng.below(self.config.max_functions);
+
+        // The ids and parameter counts of every generated function, functions
+        // can only call the ones generated before them
+        let mut callees: Vec<(FuncId, usize)> = Vec::with_capacity(num_functions);
+        for _ in 0..num_functions {
+            let num_params = self.rng.below(self.config.max_params + 1);
+            let id = self.function(builder, num_params, &callees)?;
+            callees.push((id, num_params));
+        }
+
+        Ok(callees.into_iter().map(|(id, _)| id).collect())
+    }
+
+    fn function(
+        &mut self,
+        builder: &mut Builder,
+        num_params: usize,
+        callees: &[(FuncId, usize)],
+    ) -> BuildResult<FuncId> {
+        builder.function(Type::Int, |func| {
+            let params = func.params(vec![Type::Int; num_params]);
+            let blocks: Vec<_> = (1..1 + self.rng.below(self.config.max_blocks))
+                .map(|_| func.allocate_basic_block())
+                .collect();
+            let block_ids: Vec<BasicBlockId> = blocks.iter().map(|block| **block).collect();
+
+            // Every block has to be reachable, so a block jumps to the one after it
+            // whenever nothing before it has
+            let mut targeted = HashSet::new();
+            let required = |next: Option<&BasicBlockId>, targeted: &HashSet<_>| {
+                next.copied().filter(|next| !targeted.contains(next))
+            };
+
+            // The entry block dominates every other block, so its values are
+            // available everywhere
+            let mut available: Vec<Value> = params.into_iter().map(Into::into).collect();
+            let next = required(block_ids.first(), &targeted);
+            func.basic_block(|block| {
+                self.instructions(block, &mut available, callees)?;
+                targeted.extend(self.terminator(block, &available, &block_ids, next)?);
+
+                Ok(())
+            })?;
+
+            for (index, target) in blocks.into_iter().enumerate() {
+                let mut values = available.clone();
+                let next = required(block_ids.get(index + 1), &targeted);
+                func.resume_building(target, |block| {
+                    self.instructions(block, &mut values, callees)?;
+                    targeted.extend(self.terminator(
+                        block,
+                        &values,
+                        &block_ids[index + 1..],
+                        next,
+                    )?);
+
+                    Ok(())
+                })?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Emits random arithmetic and calls, adding their results to `values`
+    ///
+    /// Multiplication and division are only ever by small constants so that
+    /// evaluation can't overflow or divide by zero
+    fn instructions(
+        &mut self,
+        block: &mut BasicBlockBuilder<'_, '_>,
+        values: &mut Vec<Value>,
+        callees: &[(FuncId, usize)],
+    ) -> BuildResult<()> {
+        for _ in 0..self.rng.below(self.config.max_instructions + 1) {
+            let lhs = self.value(values);
+
+            let result = match self.rng.below(5) {
+                0 => block.add(lhs, self.value(values))?,
+                1 => block.sub(lhs, self.value(values))?,
+                2 => block.mul(lhs, self.constant(8))?,
+                3 => block.div(lhs, Constant::Int(1 + self.rng.below(8) as i64))?,
+                _ if !callees.is_empty() => {
+                    let (callee, num_params) = callees[self.rng.below(callees.len())];
+                    let args = (0..num_params).map(|_| self.value(values)).collect();
+
+                    block.call(callee, args)?
+                }
+                _ => block.add(lhs, self.value(values))?,
+            };
+            values.push(result.into());
+        }
+
+        Ok(())
+    }
+
+    /// Terminates the block by returning, jumping or branching to one of `successors`,
+    /// returning the blocks it jumps to
+    ///
+    /// The `required` block is always one of the targets
+    fn terminator(
+        &mut self,
+        block: &mut BasicBlockBuilder<'_, '_>,
+        values: &[Value],
+        successors: &[BasicBlockId],
+        required: Option<BasicBlockId>,
+    ) -> BuildResult<Vec<BasicBlockId>> {
+        let targets = match self.rng.below(3) {
+            0 if !successors.is_empty() => {
+                let target =
+                    required.unwrap_or_else(|| successors[self.rng.below(successors.len())]);
+                block.jump(target);
+
+                vec![target]
+            }
+
+            1 if !successors.is_empty() => {
+                let cond = block.cmp(self.value(values), self.value(values))?;
+                let (if_true, if_false) = (
+                    required.unwrap_or_else(|| successors[self.rng.below(successors.len())]),
+                    successors[self.rng.below(successors.len())],
+                );
+                block.branch(cond, if_true, if_false)?;
+
+                vec![if_true, if_false]
+            }
+
+            _ => {
+                if let Some(target) = required {
+                    block.jump(target);
+                    vec![target]
+                } else {
+                    block.ret(self.value(values))?;
+                    Vec::new()
+                }
+            }
+        };
+
+        Ok(targets)
+    }
+
+    /// Picks either one of the given values or a small constant
+    fn value(&mut self, values: &[Value]) -> Value {
+        if values.is_empty() || self.rng.below(4) == 0 {
+            self.constant(self.config.max_constant).into()
+        } else {
+            values[self.rng.below(values.len())].clone()
+        }
+    }
+
+    fn constant(&mut self, max: i64) -> Constant {
+        Constant::Int(self.rng.below(max.max(1) as usize) as i64)
+    }
+}
+
+/// A tiny xorshift generator so that modules are reproducible without any extra
+/// dependencies
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound.max(1) as u64) as usize
+    }
+}
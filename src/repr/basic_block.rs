@@ -1,18 +1,32 @@
-use crate::repr::{
-    utils::{DisplayCtx, IRDisplay},
-    Ident, InstId, Instruction, Terminator,
+use crate::{
+    repr::{
+        utils::{DisplayCtx, IRDisplay},
+        Ident, InstId, Instruction, Span, Terminator,
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
 use std::num::NonZeroU64;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BasicBlock {
     pub name: Option<Ident>,
     pub id: BasicBlockId,
     pub instructions: Vec<Instruction>,
     pub terminator: Terminator,
+    /// The spans of each instruction, empty if the block has no source information
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub instruction_spans: Vec<Option<Span>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub terminator_span: Option<Span>,
+}
+
+impl BasicBlock {
+    pub fn instruction_span(&self, idx: usize) -> Option<Span> {
+        self.instruction_spans.get(idx).copied().flatten()
+    }
 }
 
 impl IRDisplay for BasicBlock {
@@ -21,7 +35,7 @@ impl IRDisplay for BasicBlock {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         let name = self
             .name
@@ -40,7 +54,10 @@ impl IRDisplay for BasicBlock {
             .append(ctx.hardline())
             .append(
                 ctx.intersperse(
-                    self.instructions.iter().map(|inst| inst.display(ctx)),
+                    self.instructions
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, inst)| display_spanned(ctx, inst, self.instruction_span(idx))),
                     ctx.hardline(),
                 )
                 .append(if self.instructions.is_empty() {
@@ -48,13 +65,41 @@ impl IRDisplay for BasicBlock {
                 } else {
                     ctx.hardline()
                 })
-                .append(self.terminator.display(ctx)),
+                .append(display_spanned(
+                    ctx,
+                    &self.terminator,
+                    self.terminator_span,
+                )),
             )
             .nest(4)
     }
 }
 
+/// Displays an item followed by its span if spans are enabled
+fn display_spanned<'a, D, A, R, T>(
+    ctx: DisplayCtx<'a, D, A, R>,
+    item: &T,
+    span: Option<Span>,
+) -> DocBuilder<'a, D, A>
+where
+    D: DocAllocator<'a, A>,
+    D::Doc: Clone,
+    A: Clone + 'a,
+    R: SymbolResolver,
+    T: IRDisplay,
+{
+    match span {
+        Some(span) if ctx.spans => item
+            .display(ctx)
+            .append(ctx.space())
+            .append(span.display(ctx)),
+
+        _ => item.display(ctx),
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(transparent)]
 pub struct BasicBlockId(NonZeroU64);
 
@@ -74,13 +119,14 @@ impl IRDisplay for BasicBlockId {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         ctx.text(format!("block.{}", self.0))
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BasicBlockDesc {
     pub name: Option<Ident>,
     pub id: BasicBlockId,
@@ -1,19 +1,61 @@
-use crate::repr::{
-    utils::{DisplayCtx, IRDisplay},
-    Type,
+use crate::{
+    repr::{
+        utils::{DisplayCtx, IRDisplay},
+        Type,
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
+use fxhash::{FxHashMap, FxHasher};
 use pretty::{DocAllocator, DocBuilder};
+use std::{
+    hash::{Hash, Hasher},
+    sync::RwLock,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Constant {
     Bool(bool),
     Int(i64),
     Uint(u64),
+    Int128(i128),
+    Uint128(u128),
+    /// A 32-bit float held as its bit pattern so that constants can be compared
+    /// and hashed, see [`Constant::f32()`]
+    F32(u32),
+    /// A 64-bit float held as its bit pattern, see [`Constant::f64()`]
+    F64(u64),
+    /// An array holding elements of the given type
+    Array(Type, Vec<Constant>),
+    Struct(Vec<Constant>),
 }
 
 impl Constant {
+    pub fn f32(value: f32) -> Self {
+        Self::F32(value.to_bits())
+    }
+
+    pub fn f64(value: f64) -> Self {
+        Self::F64(value.to_bits())
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        if let Self::F32(bits) = *self {
+            Some(f32::from_bits(bits))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        if let Self::F64(bits) = *self {
+            Some(f64::from_bits(bits))
+        } else {
+            None
+        }
+    }
+
     pub const fn as_bool(&self) -> Option<bool> {
         if let Self::Bool(bool) = *self {
             Some(bool)
@@ -27,19 +69,121 @@ impl Constant {
             Self::Bool(_) => Type::Bool,
             Self::Int(_) => Type::Int,
             Self::Uint(_) => Type::Uint,
+            Self::Int128(_) => Type::Int128,
+            Self::Uint128(_) => Type::Uint128,
+            Self::F32(_) => Type::F32,
+            Self::F64(_) => Type::F64,
+            Self::Array(element, elements) => {
+                Type::Array(Box::new(element.clone()), elements.len() as u64)
+            }
+            Self::Struct(fields) => Type::Struct(fields.iter().map(Self::ty).collect()),
         }
     }
 
+    /// Returns `true` for integer zeroes, floats are never considered zero since
+    /// the identities that hold for a zero integer don't hold for signed zeroes
+    /// and NaNs
     pub const fn is_zero(&self) -> bool {
         match *self {
             Self::Int(int) if int == 0 => true,
             Self::Uint(uint) if uint == 0 => true,
-            Self::Bool(_) | Self::Int(_) | Self::Uint(_) => false,
+            Self::Int128(int) if int == 0 => true,
+            Self::Uint128(uint) if uint == 0 => true,
+            Self::Bool(_)
+            | Self::Int(_)
+            | Self::Uint(_)
+            | Self::Int128(_)
+            | Self::Uint128(_)
+            | Self::F32(_)
+            | Self::F64(_)
+            | Self::Array(..)
+            | Self::Struct(_) => false,
         }
     }
 
+    /// Returns the element or field at `index` within an aggregate constant, or
+    /// `None` if the constant isn't an aggregate or the index is out of bounds
+    pub fn field(&self, index: u64) -> Option<&Constant> {
+        match self {
+            Self::Array(_, fields) | Self::Struct(fields) => fields.get(index as usize),
+            Self::Bool(_)
+            | Self::Int(_)
+            | Self::Uint(_)
+            | Self::Int128(_)
+            | Self::Uint128(_)
+            | Self::F32(_)
+            | Self::F64(_) => None,
+        }
+    }
+
+    /// Returns a copy of an aggregate constant with the element or field at `index`
+    /// replaced by `value`, or `None` if the constant isn't an aggregate or the index
+    /// is out of bounds
+    pub fn with_field(&self, index: u64, value: Constant) -> Option<Constant> {
+        let mut aggregate = self.clone();
+        match &mut aggregate {
+            Self::Array(_, fields) | Self::Struct(fields) => {
+                *fields.get_mut(index as usize)? = value;
+            }
+            Self::Bool(_)
+            | Self::Int(_)
+            | Self::Uint(_)
+            | Self::Int128(_)
+            | Self::Uint128(_)
+            | Self::F32(_)
+            | Self::F64(_) => return None,
+        }
+
+        Some(aggregate)
+    }
+
     pub const fn is_signed_int(&self) -> bool {
-        matches!(self, Self::Int(_))
+        matches!(self, Self::Int(_) | Self::Int128(_))
+    }
+
+    /// Compares two constants for equality the way a comparison would at runtime,
+    /// floats are compared by value so NaNs never equal anything and both zeroes
+    /// are equal. Returns `None` if the constants have different types
+    pub fn equals(&self, other: &Constant) -> Option<bool> {
+        match (self, other) {
+            (&Self::F32(lhs), &Self::F32(rhs)) => Some(f32::from_bits(lhs) == f32::from_bits(rhs)),
+            (&Self::F64(lhs), &Self::F64(rhs)) => Some(f64::from_bits(lhs) == f64::from_bits(rhs)),
+            (Self::Array(_, lhs), Self::Array(_, rhs)) | (Self::Struct(lhs), Self::Struct(rhs))
+                if lhs.len() == rhs.len() =>
+            {
+                lhs.iter()
+                    .zip(rhs)
+                    .try_fold(true, |equal, (lhs, rhs)| Some(equal && lhs.equals(rhs)?))
+            }
+            (lhs, rhs) if lhs.ty() == rhs.ty() => Some(lhs == rhs),
+            _ => None,
+        }
+    }
+
+    /// Applies an arithmetic operation to two floats of the same type
+    ///
+    /// Float arithmetic is rounded to nearest and never traps, so folding it gives
+    /// the same result as the target would, except for NaNs whose sign and payload
+    /// are target specific. Operations producing a NaN are never folded
+    pub fn fold_float(
+        &self,
+        rhs: &Constant,
+        op32: fn(f32, f32) -> f32,
+        op64: fn(f64, f64) -> f64,
+    ) -> Option<Constant> {
+        let result = match (self, rhs) {
+            (&Self::F32(lhs), &Self::F32(rhs)) => {
+                Self::f32(op32(f32::from_bits(lhs), f32::from_bits(rhs)))
+            }
+            (&Self::F64(lhs), &Self::F64(rhs)) => {
+                Self::f64(op64(f64::from_bits(lhs), f64::from_bits(rhs)))
+            }
+            _ => return None,
+        };
+
+        let is_nan = result.as_f32().map_or(false, f32::is_nan)
+            || result.as_f64().map_or(false, f64::is_nan);
+        (!is_nan).then(|| result)
     }
 }
 
@@ -49,12 +193,131 @@ impl IRDisplay for Constant {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         match self {
             Self::Bool(boolean) => alloc.text(format!("{}", boolean)),
             Self::Int(int) => alloc.text(format!("{}", int)),
             Self::Uint(uint) => alloc.text(format!("{}", uint)),
+            Self::Int128(int) => alloc.text(format!("{}", int)),
+            Self::Uint128(uint) => alloc.text(format!("{}", uint)),
+            Self::F32(bits) => alloc.text(format!("{:?}f32", f32::from_bits(*bits))),
+            Self::F64(bits) => alloc.text(format!("{:?}f64", f64::from_bits(*bits))),
+            Self::Array(_, elements) => alloc
+                .intersperse(
+                    elements.iter().map(|element| element.display(alloc)),
+                    alloc.text(",").append(alloc.space()),
+                )
+                .brackets(),
+            Self::Struct(fields) => alloc
+                .intersperse(
+                    fields.iter().map(|field| field.display(alloc)),
+                    alloc.text(",").append(alloc.space()),
+                )
+                .braces(),
+        }
+    }
+}
+
+/// The id of a constant within a [`ConstantPool`], which is derived from the
+/// constant's contents so that equal constants share the same id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(transparent)]
+pub struct ConstId(u64);
+
+impl ConstId {
+    pub const fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl IRDisplay for ConstId {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        ctx.text(format!("@const.{:x}", self.0))
+    }
+}
+
+/// Deduplicates constants so that instructions can refer to a single copy of
+/// them by their [`ConstId`]
+///
+/// Constants are only ever added to the pool, so an id stays valid for as long
+/// as the pool it was interned into
+#[derive(Debug, Default)]
+pub struct ConstantPool {
+    constants: RwLock<FxHashMap<ConstId, Constant>>,
+}
+
+impl ConstantPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a constant to the pool, returning the id of the existing copy if
+    /// there already is one
+    pub fn intern(&self, constant: Constant) -> ConstId {
+        let mut hasher = FxHasher::default();
+        constant.hash(&mut hasher);
+        let mut id = ConstId(hasher.finish());
+
+        let mut constants = self
+            .constants
+            .write()
+            .expect("the constant pool was poisoned");
+
+        // Colliding constants are given the next free id
+        loop {
+            match constants.get(&id) {
+                Some(existing) if *existing == constant => return id,
+                Some(_) => id = ConstId(id.0.wrapping_add(1)),
+                None => {
+                    constants.insert(id, constant);
+                    return id;
+                }
+            }
         }
     }
+
+    pub fn get(&self, id: ConstId) -> Option<Constant> {
+        self.constants
+            .read()
+            .expect("the constant pool was poisoned")
+            .get(&id)
+            .cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.constants
+            .read()
+            .expect("the constant pool was poisoned")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every constant within the pool, ordered by id
+    pub fn constants(&self) -> Vec<(ConstId, Constant)> {
+        let mut constants: Vec<_> = self
+            .constants
+            .read()
+            .expect("the constant pool was poisoned")
+            .iter()
+            .map(|(&id, constant)| (id, constant.clone()))
+            .collect();
+        constants.sort_unstable_by_key(|&(id, _)| id);
+
+        constants
+    }
 }
@@ -0,0 +1,271 @@
+//! Line diffs between two versions of pretty printed ir
+//!
+//! Every instruction and terminator is rendered on its own line, so a line diff of
+//! two rendered functions lines up unchanged instructions and points out the ones
+//! that were added, removed or rewritten. Rewritten lines are paired up with what
+//! they replaced and, when colored, the tokens that actually differ between the
+//! two are highlighted
+
+use crate::{
+    repr::{utils::IRDisplay, Function},
+    symbols::SymbolResolver,
+};
+use std::fmt::{self, Display};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const INVERT: &str = "\x1b[7m";
+const UNINVERT: &str = "\x1b[27m";
+const RESET: &str = "\x1b[0m";
+
+/// A single line of a [`FunctionDiff`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiffLine {
+    Unchanged(String),
+    Removed(String),
+    Added(String),
+}
+
+impl DiffLine {
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Unchanged(line) | Self::Removed(line) | Self::Added(line) => line,
+        }
+    }
+
+    pub const fn is_unchanged(&self) -> bool {
+        matches!(self, Self::Unchanged(_))
+    }
+
+    const fn sign(&self) -> char {
+        match self {
+            Self::Unchanged(_) => ' ',
+            Self::Removed(_) => '-',
+            Self::Added(_) => '+',
+        }
+    }
+}
+
+/// The difference between two rendered versions of a function, see
+/// [`Function::diff_display()`]
+///
+/// Displaying the diff prints every line prefixed with `-` if it was removed, `+`
+/// if it was added or a space if it's unchanged, so both versions stay aligned
+/// with each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDiff {
+    lines: Vec<DiffLine>,
+    colored: bool,
+}
+
+impl FunctionDiff {
+    /// Diffs two rendered pieces of ir line by line
+    pub fn new(before: &str, after: &str) -> Self {
+        let (before, after): (Vec<_>, Vec<_>) = (before.lines().collect(), after.lines().collect());
+
+        let lines = diff(&before, &after)
+            .into_iter()
+            .map(|edit| match edit {
+                Edit::Keep(line) => DiffLine::Unchanged(line.to_owned()),
+                Edit::Remove(line) => DiffLine::Removed(line.to_owned()),
+                Edit::Add(line) => DiffLine::Added(line.to_owned()),
+            })
+            .collect();
+
+        Self {
+            lines,
+            colored: false,
+        }
+    }
+
+    /// Sets whether the diff is rendered with ansi colors, highlighting the tokens
+    /// that changed within rewritten lines
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    pub fn lines(&self) -> &[DiffLine] {
+        &self.lines
+    }
+
+    /// Returns `true` if both versions rendered the same
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(DiffLine::is_unchanged)
+    }
+
+    /// The number of lines that were removed and added
+    pub fn changes(&self) -> (usize, usize) {
+        self.lines
+            .iter()
+            .fold((0, 0), |(removed, added), line| match line {
+                DiffLine::Unchanged(_) => (removed, added),
+                DiffLine::Removed(_) => (removed + 1, added),
+                DiffLine::Added(_) => (removed, added + 1),
+            })
+    }
+
+    fn fmt_colored(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut idx = 0;
+        while idx < self.lines.len() {
+            if self.lines[idx].is_unchanged() {
+                writeln!(f, "  {}", self.lines[idx].text())?;
+                idx += 1;
+                continue;
+            }
+
+            // Rewritten lines show up as a run of removals followed by a run of
+            // additions, each removal is paired with the addition at the same offset
+            let removed_end = idx
+                + self.lines[idx..]
+                    .iter()
+                    .take_while(|line| matches!(line, DiffLine::Removed(_)))
+                    .count();
+            let added_end = removed_end
+                + self.lines[removed_end..]
+                    .iter()
+                    .take_while(|line| matches!(line, DiffLine::Added(_)))
+                    .count();
+            let (removed, added) = (
+                &self.lines[idx..removed_end],
+                &self.lines[removed_end..added_end],
+            );
+
+            for (offset, line) in removed.iter().enumerate() {
+                let paired = added.get(offset).map(DiffLine::text);
+                write_highlighted(f, RED, line, paired)?;
+            }
+            for (offset, line) in added.iter().enumerate() {
+                let paired = removed.get(offset).map(DiffLine::text);
+                write_highlighted(f, GREEN, line, paired)?;
+            }
+
+            idx = added_end;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for FunctionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.colored {
+            return self.fmt_colored(f);
+        }
+
+        for line in self.lines.iter() {
+            writeln!(f, "{} {}", line.sign(), line.text())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Function {
+    /// Renders the changes between this function and `other`, see [`FunctionDiff`]
+    pub fn diff_display<R>(&self, other: &Self, interner: &R) -> FunctionDiff
+    where
+        R: SymbolResolver,
+    {
+        FunctionDiff::new(
+            &self.to_pretty_string(interner),
+            &other.to_pretty_string(interner),
+        )
+    }
+}
+
+/// Writes a changed line in the given color, inverting the tokens it doesn't share
+/// with the line it's paired with
+fn write_highlighted(
+    f: &mut fmt::Formatter<'_>,
+    color: &str,
+    line: &DiffLine,
+    paired: Option<&str>,
+) -> fmt::Result {
+    write!(f, "{}{} ", color, line.sign())?;
+
+    let tokens = tokenize(line.text());
+    match paired {
+        Some(paired) => {
+            let paired = tokenize(paired);
+            for edit in diff(&tokens, &paired) {
+                match edit {
+                    Edit::Keep(token) => f.write_str(token)?,
+                    Edit::Remove(token) => write!(f, "{}{}{}", INVERT, token, UNINVERT)?,
+                    // Tokens only in the paired line are shown on its own line
+                    Edit::Add(_) => {}
+                }
+            }
+        }
+
+        None => f.write_str(line.text())?,
+    }
+
+    writeln!(f, "{}", RESET)
+}
+
+/// Splits a line into runs of identifier characters and single other characters
+fn tokenize(line: &str) -> Vec<&str> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '.';
+
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    for (idx, c) in line.char_indices() {
+        let end = idx + c.len_utf8();
+
+        let continues_word = is_word(c) && line[start..idx].chars().last().map_or(false, is_word);
+        if !continues_word && start != idx {
+            tokens.push(&line[start..idx]);
+            start = idx;
+        }
+
+        if end == line.len() {
+            tokens.push(&line[start..end]);
+        }
+    }
+
+    tokens
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit<T> {
+    Keep(T),
+    Remove(T),
+    Add(T),
+}
+
+/// The shortest edit turning `before` into `after`, found through their longest
+/// common subsequence
+fn diff<'a>(before: &[&'a str], after: &[&'a str]) -> Vec<Edit<&'a str>> {
+    // lengths[i][j] is the length of the longest common subsequence of
+    // `before[i..]` and `after[j..]`
+    let mut lengths = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            lengths[i][j] = if before[i] == after[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut edits, mut i, mut j) = (Vec::with_capacity(before.len().max(after.len())), 0, 0);
+    while i < before.len() && j < after.len() {
+        if before[i] == after[j] {
+            edits.push(Edit::Keep(before[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            edits.push(Edit::Remove(before[i]));
+            i += 1;
+        } else {
+            edits.push(Edit::Add(after[j]));
+            j += 1;
+        }
+    }
+    edits.extend(before[i..].iter().map(|&line| Edit::Remove(line)));
+    edits.extend(after[j..].iter().map(|&line| Edit::Add(line)));
+
+    edits
+}
@@ -1,14 +1,19 @@
 use super::{utils::IRDisplay, BasicBlockId, TypedVar};
 use crate::{
     optimize::inline::InlineHeuristics,
-    repr::{utils::DisplayCtx, BasicBlock, Ident, Type},
+    repr::{utils::DisplayCtx, BasicBlock, Ident, ModuleId, Type},
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
-use std::num::NonZeroU64;
+use std::{
+    fmt::{self, Display},
+    num::NonZeroU64,
+    ops::{BitOr, BitOrAssign},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Function {
     pub name: Option<Ident>,
     pub id: FuncId,
@@ -26,7 +31,7 @@ impl IRDisplay for Function {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         let name = self
             .name
@@ -43,12 +48,19 @@ impl IRDisplay for Function {
             .append(name)
             .append(
                 ctx.intersperse(
-                    self.params.iter().map(|var| {
+                    self.params.iter().enumerate().map(|(idx, var)| {
+                        let attributes = self.metadata.param(idx);
+
                         var.var
                             .display(ctx)
                             .append(ctx.text(":"))
                             .append(ctx.space())
                             .append(var.ty.display(ctx))
+                            .append(if attributes == ParamAttributes::DEFAULT {
+                                ctx.nil()
+                            } else {
+                                ctx.space().append(ctx.text(attributes.to_string()))
+                            })
                             .group()
                     }),
                     ctx.text(",").append(ctx.space()),
@@ -109,13 +121,54 @@ impl IRDisplay for Function {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Metadata {
     pub inline_heuristics: Option<InlineHeuristics>,
+    pub attributes: FunctionAttributes,
+    /// The attributes of each parameter, parameters without an entry use the
+    /// [default attributes](ParamAttributes::DEFAULT)
+    pub params: Vec<ParamAttributes>,
+    pub calling_convention: CallingConvention,
+    pub module: ModuleId,
 }
 
 impl Metadata {
     pub const fn new(inline_heuristics: Option<InlineHeuristics>) -> Self {
-        Self { inline_heuristics }
+        Self {
+            inline_heuristics,
+            attributes: FunctionAttributes::NONE,
+            params: Vec::new(),
+            calling_convention: CallingConvention::Sruth,
+            module: ModuleId::DEFAULT,
+        }
+    }
+
+    pub const fn with_attributes(mut self, attributes: FunctionAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub fn with_params(mut self, params: Vec<ParamAttributes>) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub const fn with_calling_convention(mut self, calling_convention: CallingConvention) -> Self {
+        self.calling_convention = calling_convention;
+        self
+    }
+
+    pub const fn with_module(mut self, module: ModuleId) -> Self {
+        self.module = module;
+        self
+    }
+
+    /// The attributes of the parameter at `index`
+    pub fn param(&self, index: usize) -> ParamAttributes {
+        self.params
+            .get(index)
+            .copied()
+            .unwrap_or(ParamAttributes::DEFAULT)
     }
 }
 
@@ -125,9 +178,39 @@ impl IRDisplay for Metadata {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
-        if let Some(heuristics) = self.inline_heuristics.as_ref() {
+        let attributes = if self.attributes.is_empty() {
+            ctx.nil()
+        } else {
+            ctx.text(";")
+                .append(ctx.space())
+                .append(ctx.text(format!("attributes: {}", self.attributes)))
+                .group()
+                .append(ctx.hardline())
+        };
+
+        let calling_convention = if self.calling_convention == CallingConvention::Sruth {
+            ctx.nil()
+        } else {
+            ctx.text(";")
+                .append(ctx.space())
+                .append(ctx.text(format!("calling convention: {}", self.calling_convention)))
+                .group()
+                .append(ctx.hardline())
+        };
+
+        let module = if self.module.is_default() {
+            ctx.nil()
+        } else {
+            ctx.text(";")
+                .append(ctx.space())
+                .append(ctx.text(format!("module: {}", self.module)))
+                .group()
+                .append(ctx.hardline())
+        };
+
+        let heuristics = if let Some(heuristics) = self.inline_heuristics.as_ref() {
             ctx.text(";")
                 .append(ctx.space())
                 .append(ctx.text(format!(
@@ -149,11 +232,246 @@ impl IRDisplay for Metadata {
                 .append(ctx.hardline())
         } else {
             ctx.nil()
+        };
+
+        attributes
+            .append(calling_convention)
+            .append(module)
+            .append(heuristics)
+    }
+}
+
+/// A set of attributes that change how a function is treated by optimizations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(transparent)]
+pub struct FunctionAttributes(u8);
+
+impl FunctionAttributes {
+    pub const NONE: Self = Self(0);
+    /// The function is inlined into every call site regardless of its cost
+    pub const INLINE_ALWAYS: Self = Self(1 << 0);
+    /// The function is never inlined
+    pub const INLINE_NEVER: Self = Self(1 << 1);
+    /// The function is rarely called and is placed after all other functions
+    pub const COLD: Self = Self(1 << 2);
+    /// The function is visible outside of the module and is never removed
+    pub const EXPORT: Self = Self(1 << 3);
+    /// The function is only declared and is defined outside of the module, it has
+    /// a signature but no body
+    pub const EXTERNAL: Self = Self(1 << 4);
+
+    const NAMES: &'static [(Self, &'static str)] = &[
+        (Self::INLINE_ALWAYS, "inline(always)"),
+        (Self::INLINE_NEVER, "inline(never)"),
+        (Self::COLD, "cold"),
+        (Self::EXPORT, "export"),
+        (Self::EXTERNAL, "external"),
+    ];
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if every attribute within `other` is also within `self`
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub const fn inline_always(self) -> bool {
+        self.contains(Self::INLINE_ALWAYS)
+    }
+
+    pub const fn inline_never(self) -> bool {
+        self.contains(Self::INLINE_NEVER)
+    }
+
+    pub const fn is_cold(self) -> bool {
+        self.contains(Self::COLD)
+    }
+
+    pub const fn is_exported(self) -> bool {
+        self.contains(Self::EXPORT)
+    }
+
+    pub const fn is_external(self) -> bool {
+        self.contains(Self::EXTERNAL)
+    }
+}
+
+impl BitOr for FunctionAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl BitOrAssign for FunctionAttributes {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+impl Display for FunctionAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut attributes = Self::NAMES
+            .iter()
+            .filter(|&&(attribute, _)| self.contains(attribute))
+            .map(|&(_, name)| name);
+
+        if let Some(first) = attributes.next() {
+            f.write_str(first)?;
+            for attribute in attributes {
+                write!(f, ", {}", attribute)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The convention a function's arguments and return value are passed under
+///
+/// Functions are only ever inlined into callers that share their convention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallingConvention {
+    /// The internal convention, which optimizations are free to change
+    Sruth,
+    /// The C calling convention of the target
+    C,
+    /// The convention of functions imported from or exported to a wasm host
+    Wasm,
+}
+
+impl CallingConvention {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Sruth => "sruth",
+            Self::C => "c",
+            Self::Wasm => "wasm",
         }
     }
 }
 
+impl Default for CallingConvention {
+    fn default() -> Self {
+        Self::Sruth
+    }
+}
+
+impl Display for CallingConvention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Whether a parameter is passed as a value or as a reference to one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PassingMode {
+    ByValue,
+    ByRef,
+}
+
+/// How an integer parameter narrower than a register is widened by the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Extension {
+    None,
+    Zero,
+    Sign,
+}
+
+/// The attributes of a single function parameter
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParamAttributes {
+    pub passing: PassingMode,
+    pub extension: Extension,
+    /// The parameter doesn't alias any other parameter or memory the function can access
+    pub noalias: bool,
+}
+
+impl ParamAttributes {
+    /// A by-value parameter without extension that may alias
+    pub const DEFAULT: Self = Self {
+        passing: PassingMode::ByValue,
+        extension: Extension::None,
+        noalias: false,
+    };
+
+    pub const fn by_ref(mut self) -> Self {
+        self.passing = PassingMode::ByRef;
+        self
+    }
+
+    pub const fn zero_extended(mut self) -> Self {
+        self.extension = Extension::Zero;
+        self
+    }
+
+    pub const fn sign_extended(mut self) -> Self {
+        self.extension = Extension::Sign;
+        self
+    }
+
+    pub const fn noalias(mut self) -> Self {
+        self.noalias = true;
+        self
+    }
+
+    pub const fn is_by_ref(self) -> bool {
+        matches!(self.passing, PassingMode::ByRef)
+    }
+}
+
+impl Default for ParamAttributes {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Display for ParamAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let passing = match self.passing {
+            PassingMode::ByValue => None,
+            PassingMode::ByRef => Some("byref"),
+        };
+        let extension = match self.extension {
+            Extension::None => None,
+            Extension::Zero => Some("zext"),
+            Extension::Sign => Some("sext"),
+        };
+        let noalias = if self.noalias { Some("noalias") } else { None };
+
+        let mut attributes = passing.into_iter().chain(extension).chain(noalias);
+        if let Some(first) = attributes.next() {
+            f.write_str(first)?;
+            for attribute in attributes {
+                write!(f, " {}", attribute)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(transparent)]
 pub struct FuncId(pub(crate) NonZeroU64);
 
@@ -173,13 +491,18 @@ impl IRDisplay for FuncId {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
-        ctx.text(format!("function.{}", self.0))
+        // Resolvers that know the function's name render it in place of the id
+        match ctx.interner.function_name(*self) {
+            Some(name) => name.display(ctx),
+            None => ctx.text(format!("function.{}", self.0)),
+        }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionDesc {
     pub name: Option<Ident>,
     pub id: FuncId,
@@ -187,6 +510,11 @@ pub struct FunctionDesc {
     pub ret_ty: Type,
     pub entry: BasicBlockId,
     pub basic_blocks: Vec<BasicBlockId>,
+    pub attributes: FunctionAttributes,
+    pub param_attributes: Vec<ParamAttributes>,
+    pub calling_convention: CallingConvention,
+    /// The module the function belongs to
+    pub module: ModuleId,
 }
 
 impl FunctionDesc {
@@ -205,6 +533,40 @@ impl FunctionDesc {
             ret_ty,
             entry,
             basic_blocks,
+            attributes: FunctionAttributes::NONE,
+            param_attributes: Vec::new(),
+            calling_convention: CallingConvention::Sruth,
+            module: ModuleId::DEFAULT,
         }
     }
+
+    pub const fn with_attributes(mut self, attributes: FunctionAttributes) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub const fn with_module(mut self, module: ModuleId) -> Self {
+        self.module = module;
+        self
+    }
+
+    /// Copies the attributes, abi and module of the function out of its metadata
+    pub fn with_metadata(self, metadata: &Metadata) -> Self {
+        Self {
+            attributes: metadata.attributes,
+            param_attributes: metadata.params.clone(),
+            calling_convention: metadata.calling_convention,
+            module: metadata.module,
+            ..self
+        }
+    }
+
+    /// The metadata carried by the descriptor
+    pub fn metadata(&self) -> Metadata {
+        Metadata::default()
+            .with_attributes(self.attributes)
+            .with_params(self.param_attributes.clone())
+            .with_calling_convention(self.calling_convention)
+            .with_module(self.module)
+    }
 }
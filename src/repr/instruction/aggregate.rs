@@ -0,0 +1,227 @@
+use crate::{
+    repr::{
+        instruction::Assign,
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
+        Type, TypedVar, Value, ValueKind, VarId,
+    },
+    symbols::SymbolResolver,
+};
+use abomonation_derive::Abomonation;
+use pretty::{DocAllocator, DocBuilder};
+
+/// Reads the element or field at `index` out of an array or struct
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtractValue {
+    pub aggregate: Value,
+    pub index: u64,
+    pub dest: VarId,
+    /// The type of the extracted element or field
+    pub ty: Type,
+}
+
+impl ExtractValue {
+    pub const fn new(dest: VarId, aggregate: Value, index: u64, ty: Type) -> Self {
+        Self {
+            aggregate,
+            index,
+            dest,
+            ty,
+        }
+    }
+
+    pub const fn is_const(&self) -> bool {
+        self.aggregate.is_const()
+    }
+
+    /// Folds an extract from a constant aggregate into an assignment of the
+    /// extracted value
+    pub fn evaluate(&self) -> Option<Assign> {
+        let field = self.aggregate.as_const()?.field(self.index)?;
+        let value = Value::new(ValueKind::Const(field.clone()), field.ty());
+
+        Some(Assign::new(self.dest, value, None))
+    }
+}
+
+impl InstructionExt for ExtractValue {
+    fn dest(&self) -> VarId {
+        self.dest
+    }
+
+    fn dest_type(&self) -> Type {
+        self.ty.clone()
+    }
+
+    fn purity(&self) -> InstructionPurity {
+        InstructionPurity::Pure
+    }
+
+    fn replace_uses(&mut self, from: VarId, to: &Value) -> bool {
+        if let Some(var) = self.aggregate.as_var() {
+            if var == from {
+                self.aggregate = to.clone();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn used_vars(&self) -> Vec<TypedVar> {
+        self.aggregate.as_typed_var().into_iter().collect()
+    }
+
+    fn used_values_into<'a>(&'a self, buf: &mut Vec<&'a Value>) {
+        buf.push(&self.aggregate);
+    }
+
+    fn used_values_mut(&mut self) -> Vec<&mut Value> {
+        vec![&mut self.aggregate]
+    }
+}
+
+impl EstimateAsm for ExtractValue {
+    fn estimated_instructions(&self) -> usize {
+        1
+    }
+}
+
+impl IRDisplay for ExtractValue {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        self.dest
+            .display(ctx)
+            .append(ctx.space())
+            .append(ctx.text(":="))
+            .append(ctx.space())
+            .append(ctx.text("extract"))
+            .append(ctx.space())
+            .append(self.aggregate.display(ctx))
+            .append(ctx.text(","))
+            .append(ctx.space())
+            .append(ctx.text(format!("{}", self.index)))
+            .group()
+    }
+}
+
+/// Produces a copy of an array or struct with the element or field at `index`
+/// replaced by `value`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InsertValue {
+    pub aggregate: Value,
+    pub index: u64,
+    pub value: Value,
+    pub dest: VarId,
+}
+
+impl InsertValue {
+    pub const fn new(dest: VarId, aggregate: Value, index: u64, value: Value) -> Self {
+        Self {
+            aggregate,
+            index,
+            value,
+            dest,
+        }
+    }
+
+    pub const fn is_const(&self) -> bool {
+        self.aggregate.is_const() && self.value.is_const()
+    }
+
+    /// Folds an insertion of a constant into a constant aggregate into an
+    /// assignment of the new aggregate
+    pub fn evaluate(&self) -> Option<Assign> {
+        let aggregate = self
+            .aggregate
+            .as_const()?
+            .with_field(self.index, self.value.as_const()?.clone())?;
+        let value = Value::new(ValueKind::Const(aggregate), self.aggregate.ty.clone());
+
+        Some(Assign::new(self.dest, value, None))
+    }
+}
+
+impl InstructionExt for InsertValue {
+    fn dest(&self) -> VarId {
+        self.dest
+    }
+
+    fn dest_type(&self) -> Type {
+        self.aggregate.ty.clone()
+    }
+
+    fn purity(&self) -> InstructionPurity {
+        InstructionPurity::Pure
+    }
+
+    fn replace_uses(&mut self, from: VarId, to: &Value) -> bool {
+        let mut replaced = false;
+
+        for value in self.used_values_mut() {
+            if let Some(var) = value.as_var() {
+                if var == from {
+                    *value = to.clone();
+                    replaced = true;
+                }
+            }
+        }
+
+        replaced
+    }
+
+    fn used_vars(&self) -> Vec<TypedVar> {
+        self.aggregate
+            .as_typed_var()
+            .into_iter()
+            .chain(self.value.as_typed_var())
+            .collect()
+    }
+
+    fn used_values_into<'a>(&'a self, buf: &mut Vec<&'a Value>) {
+        buf.push(&self.aggregate);
+        buf.push(&self.value);
+    }
+
+    fn used_values_mut(&mut self) -> Vec<&mut Value> {
+        vec![&mut self.aggregate, &mut self.value]
+    }
+}
+
+impl EstimateAsm for InsertValue {
+    fn estimated_instructions(&self) -> usize {
+        1
+    }
+}
+
+impl IRDisplay for InsertValue {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        self.dest
+            .display(ctx)
+            .append(ctx.space())
+            .append(ctx.text(":="))
+            .append(ctx.space())
+            .append(ctx.text("insert"))
+            .append(ctx.space())
+            .append(self.aggregate.display(ctx))
+            .append(ctx.text(","))
+            .append(ctx.space())
+            .append(ctx.text(format!("{}", self.index)))
+            .append(ctx.text(","))
+            .append(ctx.space())
+            .append(self.value.display(ctx))
+            .group()
+    }
+}
@@ -1,13 +1,16 @@
-use crate::repr::{
-    utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
-    Ident, Type, TypedVar, Value,
+use crate::{
+    repr::{
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
+        Ident, Type, TypedVar, Value,
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
 use std::num::NonZeroU64;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Assign {
     pub value: Value,
     pub dest: VarId,
@@ -33,6 +36,10 @@ impl InstructionExt for Assign {
         self.value.ty.clone()
     }
 
+    fn name(&self) -> Option<Ident> {
+        self.name
+    }
+
     fn purity(&self) -> InstructionPurity {
         InstructionPurity::Pure
     }
@@ -73,7 +80,7 @@ impl IRDisplay for Assign {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         ctx.nil()
             .append(self.dest.display(ctx))
@@ -86,6 +93,7 @@ impl IRDisplay for Assign {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(transparent)]
 pub struct VarId(NonZeroU64);
 
@@ -93,6 +101,10 @@ impl VarId {
     pub const fn new(id: NonZeroU64) -> Self {
         Self(id)
     }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0.get() - 1
+    }
 }
 
 impl IRDisplay for VarId {
@@ -101,7 +113,7 @@ impl IRDisplay for VarId {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         ctx.text(format!("_{}", self.0))
     }
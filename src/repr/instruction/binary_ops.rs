@@ -1,43 +1,76 @@
-use crate::repr::{
-    instruction::{Assign, VarId},
-    utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity, RawCast},
-    Constant, Instruction, Type, TypedVar, Value, ValueKind,
+use crate::{
+    repr::{
+        instruction::{Assign, VarId},
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity, RawCast},
+        Constant, Ident, Instruction, Type, TypedVar, Value, ValueKind,
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Add {
     pub lhs: Value,
     pub rhs: Value,
     pub dest: VarId,
+    pub name: Option<Ident>,
 }
 
 impl Add {
     // TODO: These evaluate functions are terrible
+    /// Evaluates the instruction if both of its operands are constants, integer
+    /// arithmetic wraps around on overflow and float arithmetic that produces a NaN
+    /// is never evaluated
     pub fn evaluate(self) -> Option<Instruction> {
         let (rhs, lhs) = (self.rhs.into_const()?, self.lhs.into_const()?);
 
         match (lhs, rhs) {
             (Constant::Int(lhs), Constant::Int(rhs)) => Some(Instruction::Assign(Assign {
-                value: Value::new(ValueKind::Const(Constant::Int(lhs + rhs)), Type::Int),
+                value: Value::new(
+                    ValueKind::Const(Constant::Int(lhs.wrapping_add(rhs))),
+                    Type::Int,
+                ),
                 dest: self.dest,
-                name: None,
+                name: self.name,
             })),
             (Constant::Uint(lhs), Constant::Uint(rhs)) => Some(Instruction::Assign(Assign {
-                value: Value::new(ValueKind::Const(Constant::Uint(lhs + rhs)), Type::Uint),
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint(lhs.wrapping_add(rhs))),
+                    Type::Uint,
+                ),
                 dest: self.dest,
-                name: None,
+                name: self.name,
             })),
+            (Constant::Int128(lhs), Constant::Int128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Int128(lhs.wrapping_add(rhs))),
+                    Type::Int128,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Uint128(lhs), Constant::Uint128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint128(lhs.wrapping_add(rhs))),
+                    Type::Uint128,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+
+            (lhs @ Constant::F32(_), rhs) | (lhs @ Constant::F64(_), rhs) => {
+                Some(Instruction::Assign(Assign {
+                    value: lhs
+                        .fold_float(&rhs, |lhs, rhs| lhs + rhs, |lhs, rhs| lhs + rhs)?
+                        .into(),
+                    dest: self.dest,
+                    name: self.name,
+                }))
+            }
 
-            (Constant::Bool(_), Constant::Bool(_))
-            | (Constant::Bool(_), Constant::Int(_))
-            | (Constant::Bool(_), Constant::Uint(_))
-            | (Constant::Int(_), Constant::Bool(_))
-            | (Constant::Int(_), Constant::Uint(_))
-            | (Constant::Uint(_), Constant::Bool(_))
-            | (Constant::Uint(_), Constant::Int(_)) => None,
+            _ => None,
         }
     }
 }
@@ -48,7 +81,7 @@ impl IRDisplay for Add {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.dest
             .display(ctx)
@@ -66,10 +99,12 @@ impl IRDisplay for Add {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sub {
     pub lhs: Value,
     pub rhs: Value,
     pub dest: VarId,
+    pub name: Option<Ident>,
 }
 
 impl Sub {
@@ -78,23 +113,49 @@ impl Sub {
 
         match (lhs, rhs) {
             (Constant::Int(lhs), Constant::Int(rhs)) => Some(Instruction::Assign(Assign {
-                value: Value::new(ValueKind::Const(Constant::Int(lhs - rhs)), Type::Int),
+                value: Value::new(
+                    ValueKind::Const(Constant::Int(lhs.wrapping_sub(rhs))),
+                    Type::Int,
+                ),
                 dest: self.dest,
-                name: None,
+                name: self.name,
             })),
             (Constant::Uint(lhs), Constant::Uint(rhs)) => Some(Instruction::Assign(Assign {
-                value: Value::new(ValueKind::Const(Constant::Uint(lhs - rhs)), Type::Uint),
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint(lhs.wrapping_sub(rhs))),
+                    Type::Uint,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Int128(lhs), Constant::Int128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Int128(lhs.wrapping_sub(rhs))),
+                    Type::Int128,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Uint128(lhs), Constant::Uint128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint128(lhs.wrapping_sub(rhs))),
+                    Type::Uint128,
+                ),
                 dest: self.dest,
-                name: None,
+                name: self.name,
             })),
 
-            (Constant::Bool(_), Constant::Bool(_))
-            | (Constant::Bool(_), Constant::Int(_))
-            | (Constant::Bool(_), Constant::Uint(_))
-            | (Constant::Int(_), Constant::Bool(_))
-            | (Constant::Int(_), Constant::Uint(_))
-            | (Constant::Uint(_), Constant::Bool(_))
-            | (Constant::Uint(_), Constant::Int(_)) => None,
+            (lhs @ Constant::F32(_), rhs) | (lhs @ Constant::F64(_), rhs) => {
+                Some(Instruction::Assign(Assign {
+                    value: lhs
+                        .fold_float(&rhs, |lhs, rhs| lhs - rhs, |lhs, rhs| lhs - rhs)?
+                        .into(),
+                    dest: self.dest,
+                    name: self.name,
+                }))
+            }
+
+            _ => None,
         }
     }
 }
@@ -105,7 +166,7 @@ impl IRDisplay for Sub {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.dest
             .display(ctx)
@@ -123,10 +184,12 @@ impl IRDisplay for Sub {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mul {
     pub lhs: Value,
     pub rhs: Value,
     pub dest: VarId,
+    pub name: Option<Ident>,
 }
 
 impl Mul {
@@ -135,23 +198,49 @@ impl Mul {
 
         match (lhs, rhs) {
             (Constant::Int(lhs), Constant::Int(rhs)) => Some(Instruction::Assign(Assign {
-                value: Value::new(ValueKind::Const(Constant::Int(lhs * rhs)), Type::Int),
+                value: Value::new(
+                    ValueKind::Const(Constant::Int(lhs.wrapping_mul(rhs))),
+                    Type::Int,
+                ),
                 dest: self.dest,
-                name: None,
+                name: self.name,
             })),
             (Constant::Uint(lhs), Constant::Uint(rhs)) => Some(Instruction::Assign(Assign {
-                value: Value::new(ValueKind::Const(Constant::Uint(lhs * rhs)), Type::Uint),
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint(lhs.wrapping_mul(rhs))),
+                    Type::Uint,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Int128(lhs), Constant::Int128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Int128(lhs.wrapping_mul(rhs))),
+                    Type::Int128,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Uint128(lhs), Constant::Uint128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint128(lhs.wrapping_mul(rhs))),
+                    Type::Uint128,
+                ),
                 dest: self.dest,
-                name: None,
+                name: self.name,
             })),
 
-            (Constant::Bool(_), Constant::Bool(_))
-            | (Constant::Bool(_), Constant::Int(_))
-            | (Constant::Bool(_), Constant::Uint(_))
-            | (Constant::Int(_), Constant::Bool(_))
-            | (Constant::Int(_), Constant::Uint(_))
-            | (Constant::Uint(_), Constant::Bool(_))
-            | (Constant::Uint(_), Constant::Int(_)) => None,
+            (lhs @ Constant::F32(_), rhs) | (lhs @ Constant::F64(_), rhs) => {
+                Some(Instruction::Assign(Assign {
+                    value: lhs
+                        .fold_float(&rhs, |lhs, rhs| lhs * rhs, |lhs, rhs| lhs * rhs)?
+                        .into(),
+                    dest: self.dest,
+                    name: self.name,
+                }))
+            }
+
+            _ => None,
         }
     }
 }
@@ -162,7 +251,7 @@ impl IRDisplay for Mul {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.dest
             .display(ctx)
@@ -179,36 +268,69 @@ impl IRDisplay for Mul {
     }
 }
 
+/// Division, which truncates towards zero for integers and traps when dividing by
+/// zero or when dividing the smallest signed integer by `-1`. Float division follows
+/// IEEE 754 and never traps, dividing by zero produces an infinity or `NaN`
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Div {
     pub lhs: Value,
     pub rhs: Value,
     pub dest: VarId,
+    pub name: Option<Ident>,
 }
 
 impl Div {
+    /// Evaluates the instruction if both of its operands are constants, divisions
+    /// that would trap are never evaluated
     pub fn evaluate(self) -> Option<Instruction> {
         let (rhs, lhs) = (self.rhs.into_const()?, self.lhs.into_const()?);
 
         match (lhs, rhs) {
             (Constant::Int(lhs), Constant::Int(rhs)) => Some(Instruction::Assign(Assign {
-                value: Value::new(ValueKind::Const(Constant::Int(lhs / rhs)), Type::Int),
+                value: Value::new(
+                    ValueKind::Const(Constant::Int(lhs.checked_div(rhs)?)),
+                    Type::Int,
+                ),
                 dest: self.dest,
-                name: None,
+                name: self.name,
             })),
             (Constant::Uint(lhs), Constant::Uint(rhs)) => Some(Instruction::Assign(Assign {
-                value: Value::new(ValueKind::Const(Constant::Uint(lhs / rhs)), Type::Uint),
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint(lhs.checked_div(rhs)?)),
+                    Type::Uint,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Int128(lhs), Constant::Int128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Int128(lhs.checked_div(rhs)?)),
+                    Type::Int128,
+                ),
                 dest: self.dest,
-                name: None,
+                name: self.name,
+            })),
+            (Constant::Uint128(lhs), Constant::Uint128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint128(lhs.checked_div(rhs)?)),
+                    Type::Uint128,
+                ),
+                dest: self.dest,
+                name: self.name,
             })),
 
-            (Constant::Bool(_), Constant::Bool(_))
-            | (Constant::Bool(_), Constant::Int(_))
-            | (Constant::Bool(_), Constant::Uint(_))
-            | (Constant::Int(_), Constant::Bool(_))
-            | (Constant::Int(_), Constant::Uint(_))
-            | (Constant::Uint(_), Constant::Bool(_))
-            | (Constant::Uint(_), Constant::Int(_)) => None,
+            (lhs @ Constant::F32(_), rhs) | (lhs @ Constant::F64(_), rhs) => {
+                Some(Instruction::Assign(Assign {
+                    value: lhs
+                        .fold_float(&rhs, |lhs, rhs| lhs / rhs, |lhs, rhs| lhs / rhs)?
+                        .into(),
+                    dest: self.dest,
+                    name: self.name,
+                }))
+            }
+
+            _ => None,
         }
     }
 }
@@ -219,7 +341,7 @@ impl IRDisplay for Div {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.dest
             .display(ctx)
@@ -236,6 +358,96 @@ impl IRDisplay for Div {
     }
 }
 
+/// The remainder of division, which takes the sign of the dividend and traps under
+/// the same conditions as integer [`Div`]. Float remainders never trap, a remainder
+/// by zero produces `NaN`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rem {
+    pub lhs: Value,
+    pub rhs: Value,
+    pub dest: VarId,
+    pub name: Option<Ident>,
+}
+
+impl Rem {
+    /// Evaluates the instruction if both of its operands are constants, remainders
+    /// that would trap are never evaluated
+    pub fn evaluate(self) -> Option<Instruction> {
+        let (rhs, lhs) = (self.rhs.into_const()?, self.lhs.into_const()?);
+
+        match (lhs, rhs) {
+            (Constant::Int(lhs), Constant::Int(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Int(lhs.checked_rem(rhs)?)),
+                    Type::Int,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Uint(lhs), Constant::Uint(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint(lhs.checked_rem(rhs)?)),
+                    Type::Uint,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Int128(lhs), Constant::Int128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Int128(lhs.checked_rem(rhs)?)),
+                    Type::Int128,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+            (Constant::Uint128(lhs), Constant::Uint128(rhs)) => Some(Instruction::Assign(Assign {
+                value: Value::new(
+                    ValueKind::Const(Constant::Uint128(lhs.checked_rem(rhs)?)),
+                    Type::Uint128,
+                ),
+                dest: self.dest,
+                name: self.name,
+            })),
+
+            (lhs @ Constant::F32(_), rhs) | (lhs @ Constant::F64(_), rhs) => {
+                Some(Instruction::Assign(Assign {
+                    value: lhs
+                        .fold_float(&rhs, |lhs, rhs| lhs % rhs, |lhs, rhs| lhs % rhs)?
+                        .into(),
+                    dest: self.dest,
+                    name: self.name,
+                }))
+            }
+
+            _ => None,
+        }
+    }
+}
+
+impl IRDisplay for Rem {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        self.dest
+            .display(ctx)
+            .append(ctx.space())
+            .append(ctx.text(":="))
+            .append(ctx.space())
+            .append(ctx.text("rem"))
+            .append(ctx.space())
+            .append(self.lhs.display(ctx))
+            .append(ctx.text(","))
+            .append(ctx.space())
+            .append(self.rhs.display(ctx))
+            .group()
+    }
+}
+
 pub trait BinopExt: InstructionExt {
     fn lhs(&self) -> Value;
 
@@ -245,7 +457,7 @@ pub trait BinopExt: InstructionExt {
         (self.lhs(), self.rhs())
     }
 
-    fn from_parts(lhs: Value, rhs: Value, dest: VarId) -> Self;
+    fn from_parts(lhs: Value, rhs: Value, dest: VarId, name: Option<Ident>) -> Self;
 }
 
 macro_rules! impl_binop {
@@ -253,7 +465,11 @@ macro_rules! impl_binop {
         $(
             impl $type {
                 pub const fn new(lhs: Value, rhs: Value, dest: VarId) -> Self {
-                    Self { lhs, rhs, dest }
+                    Self { lhs, rhs, dest, name: None }
+                }
+
+                pub const fn named(lhs: Value, rhs: Value, dest: VarId, name: Ident) -> Self {
+                    Self { lhs, rhs, dest, name: Some(name) }
                 }
 
                 pub const fn is_const(&self) -> bool {
@@ -270,8 +486,8 @@ macro_rules! impl_binop {
                     self.rhs.clone()
                 }
 
-                fn from_parts(lhs: Value, rhs: Value, dest: VarId) -> Self {
-                    Self { lhs, rhs, dest }
+                fn from_parts(lhs: Value, rhs: Value, dest: VarId, name: Option<Ident>) -> Self {
+                    Self { lhs, rhs, dest, name }
                 }
             }
 
@@ -284,6 +500,10 @@ macro_rules! impl_binop {
                     self.lhs().ty.clone()
                 }
 
+                fn name(&self) -> Option<Ident> {
+                    self.name
+                }
+
                 fn purity(&self) -> InstructionPurity {
                     InstructionPurity::Pure
                 }
@@ -333,6 +553,7 @@ macro_rules! impl_binop {
         )*
 
         #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
         pub enum BinaryOp {
             $($type($type),)*
         }
@@ -392,6 +613,12 @@ macro_rules! impl_binop {
                 }
             }
 
+            fn name(&self) -> Option<Ident> {
+                match self {
+                    $(Self::$type(op) => op.name(),)*
+                }
+            }
+
             fn purity(&self) -> InstructionPurity {
                 match self {
                     $(Self::$type(op) => op.purity(),)*
@@ -437,7 +664,7 @@ macro_rules! impl_binop {
                 D: DocAllocator<'a, A>,
                 D::Doc: Clone,
                 A: Clone + 'a,
-                R: Resolver,
+                R: SymbolResolver,
             {
                 match self {
                     $(Self::$type(op) => op.display(ctx),)*
@@ -467,4 +694,5 @@ impl_binop! {
     Sub,
     Mul,
     Div,
+    Rem,
 }
@@ -1,12 +1,15 @@
-use crate::repr::{
-    utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionPurity},
-    InstructionExt, Type, TypedVar, Value, ValueKind, VarId,
+use crate::{
+    repr::{
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionPurity},
+        InstructionExt, Type, TypedVar, Value, ValueKind, VarId,
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bitcast {
     pub dest: TypedVar,
     pub source: Value,
@@ -47,6 +50,16 @@ impl Bitcast {
                 | (Type::Bool, Type::Int)
                 | (Type::Bool, Type::Uint)
                 | (Type::Bool, Type::Bool)
+                | (Type::Int128, Type::Int128)
+                | (Type::Int128, Type::Uint128)
+                | (Type::Uint128, Type::Int128)
+                | (Type::Uint128, Type::Uint128)
+                | (Type::F64, Type::Int)
+                | (Type::F64, Type::Uint)
+                | (Type::Int, Type::F64)
+                | (Type::Uint, Type::F64)
+                | (Type::F32, Type::F32)
+                | (Type::F64, Type::F64)
         )
     }
 
@@ -104,7 +117,7 @@ impl IRDisplay for Bitcast {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.dest
             .var
@@ -1,13 +1,16 @@
-use crate::repr::{
-    utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
-    Type, TypedVar, Value, VarId,
+use crate::{
+    repr::{
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
+        Type, TypedVar, Value, VarId,
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
 
 // TODO: Comparison kind
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cmp {
     pub lhs: Value,
     pub rhs: Value,
@@ -87,7 +90,7 @@ impl IRDisplay for Cmp {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.dest
             .display(ctx)
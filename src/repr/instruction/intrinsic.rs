@@ -0,0 +1,369 @@
+use crate::{
+    repr::{
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
+        Constant, Type, TypedVar, Value, VarId,
+    },
+    symbols::SymbolResolver,
+};
+use abomonation_derive::Abomonation;
+use pretty::{DocAllocator, DocBuilder};
+use std::fmt::{self, Display};
+
+/// An operation built into sruth, see [`Intrinsic`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IntrinsicOp {
+    /// Copies `len` bytes from `src` to `dest`, taking `(dest, src, len)` as
+    /// addresses and a length in bytes
+    Memcpy,
+    /// The number of set bits within an integer
+    Ctpop,
+    /// The number of leading zero bits within an integer, which is the integer's
+    /// width for zero
+    Ctlz,
+    /// The number of trailing zero bits within an integer, which is the integer's
+    /// width for zero
+    Cttz,
+    /// Addition that wraps around on overflow instead of being undefined
+    WrappingAdd,
+    /// Subtraction that wraps around on overflow instead of being undefined
+    WrappingSub,
+    /// Multiplication that wraps around on overflow instead of being undefined
+    WrappingMul,
+}
+
+impl IntrinsicOp {
+    /// Every known intrinsic
+    pub const ALL: &'static [Self] = &[
+        Self::Memcpy,
+        Self::Ctpop,
+        Self::Ctlz,
+        Self::Cttz,
+        Self::WrappingAdd,
+        Self::WrappingSub,
+        Self::WrappingMul,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Memcpy => "memcpy",
+            Self::Ctpop => "ctpop",
+            Self::Ctlz => "ctlz",
+            Self::Cttz => "cttz",
+            Self::WrappingAdd => "wrapping_add",
+            Self::WrappingSub => "wrapping_sub",
+            Self::WrappingMul => "wrapping_mul",
+        }
+    }
+
+    /// Looks up an intrinsic by its [name](IntrinsicOp::name)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|op| op.name() == name)
+    }
+
+    /// The number of arguments the intrinsic takes
+    pub const fn arity(&self) -> usize {
+        match self {
+            Self::Memcpy => 3,
+            Self::Ctpop | Self::Ctlz | Self::Cttz => 1,
+            Self::WrappingAdd | Self::WrappingSub | Self::WrappingMul => 2,
+        }
+    }
+
+    /// The type the intrinsic returns when given arguments of the given types, or
+    /// `None` if it can't be applied to them. Arguments of an inferred type are
+    /// assumed to be correct
+    pub fn result_type(&self, args: &[Type]) -> Option<Type> {
+        if args.len() != self.arity() {
+            return None;
+        }
+
+        let fits = |ty: &Type, expected: fn(&Type) -> bool| ty.is_infer() || expected(ty);
+        match self {
+            Self::Memcpy => args
+                .iter()
+                .all(|arg| fits(arg, |ty| *ty == Type::Uint))
+                .then(|| Type::Unit),
+
+            // Bit counts have the type of the integer they count the bits of
+            Self::Ctpop | Self::Ctlz | Self::Cttz => {
+                fits(&args[0], Type::is_integer).then(|| args[0].clone())
+            }
+
+            Self::WrappingAdd | Self::WrappingSub | Self::WrappingMul => {
+                let (lhs, rhs) = (&args[0], &args[1]);
+                if !fits(lhs, Type::is_integer) || !fits(rhs, Type::is_integer) {
+                    return None;
+                }
+
+                match (lhs.is_infer(), rhs.is_infer()) {
+                    (true, _) => Some(rhs.clone()),
+                    (_, true) => Some(lhs.clone()),
+                    _ => (lhs == rhs).then(|| lhs.clone()),
+                }
+            }
+        }
+    }
+
+    /// Memory copies have side effects, everything else only depends on its
+    /// arguments
+    pub const fn purity(&self) -> InstructionPurity {
+        match self {
+            Self::Memcpy => InstructionPurity::Impure,
+            Self::Ctpop
+            | Self::Ctlz
+            | Self::Cttz
+            | Self::WrappingAdd
+            | Self::WrappingSub
+            | Self::WrappingMul => InstructionPurity::Pure,
+        }
+    }
+
+    /// Evaluates the intrinsic over constant arguments, returning `None` if it has
+    /// side effects or if the arguments don't fit its signature
+    pub fn fold(&self, args: &[&Constant]) -> Option<Constant> {
+        let folded = match (self, args) {
+            (Self::Ctpop, [arg]) => match **arg {
+                Constant::Int(int) => Constant::Int(int.count_ones().into()),
+                Constant::Uint(uint) => Constant::Uint(uint.count_ones().into()),
+                Constant::Int128(int) => Constant::Int128(int.count_ones().into()),
+                Constant::Uint128(uint) => Constant::Uint128(uint.count_ones().into()),
+                _ => return None,
+            },
+            (Self::Ctlz, [arg]) => match **arg {
+                Constant::Int(int) => Constant::Int(int.leading_zeros().into()),
+                Constant::Uint(uint) => Constant::Uint(uint.leading_zeros().into()),
+                Constant::Int128(int) => Constant::Int128(int.leading_zeros().into()),
+                Constant::Uint128(uint) => Constant::Uint128(uint.leading_zeros().into()),
+                _ => return None,
+            },
+            (Self::Cttz, [arg]) => match **arg {
+                Constant::Int(int) => Constant::Int(int.trailing_zeros().into()),
+                Constant::Uint(uint) => Constant::Uint(uint.trailing_zeros().into()),
+                Constant::Int128(int) => Constant::Int128(int.trailing_zeros().into()),
+                Constant::Uint128(uint) => Constant::Uint128(uint.trailing_zeros().into()),
+                _ => return None,
+            },
+
+            (Self::WrappingAdd, [lhs, rhs]) => wrapping(
+                lhs,
+                rhs,
+                i64::wrapping_add,
+                u64::wrapping_add,
+                i128::wrapping_add,
+                u128::wrapping_add,
+            )?,
+            (Self::WrappingSub, [lhs, rhs]) => wrapping(
+                lhs,
+                rhs,
+                i64::wrapping_sub,
+                u64::wrapping_sub,
+                i128::wrapping_sub,
+                u128::wrapping_sub,
+            )?,
+            (Self::WrappingMul, [lhs, rhs]) => wrapping(
+                lhs,
+                rhs,
+                i64::wrapping_mul,
+                u64::wrapping_mul,
+                i128::wrapping_mul,
+                u128::wrapping_mul,
+            )?,
+
+            _ => return None,
+        };
+
+        Some(folded)
+    }
+
+    /// How the intrinsic is lowered into wasm when applied to values of type `ty`,
+    /// or `None` if there's no lowering for it. Integers are held in `i64` locals
+    /// so native lowerings use the `i64` form of their instruction
+    pub fn wasm_lowering(&self, ty: &Type) -> Option<Lowering> {
+        let native = matches!(ty, Type::Int | Type::Uint);
+        let lowering = match self {
+            Self::Memcpy => Lowering::Libcall("memcpy"),
+
+            Self::Ctlz if native => Lowering::Wasm(&[0x79]),
+            Self::Cttz if native => Lowering::Wasm(&[0x7A]),
+            Self::Ctpop if native => Lowering::Wasm(&[0x7B]),
+            Self::WrappingAdd if native => Lowering::Wasm(&[0x7C]),
+            Self::WrappingSub if native => Lowering::Wasm(&[0x7D]),
+            Self::WrappingMul if native => Lowering::Wasm(&[0x7E]),
+
+            // 128-bit integers go through the usual compiler runtime functions
+            Self::Ctlz if ty.is_wide() => Lowering::Libcall("__clzti2"),
+            Self::Cttz if ty.is_wide() => Lowering::Libcall("__ctzti2"),
+            Self::Ctpop if ty.is_wide() => Lowering::Libcall("__popcountti2"),
+            Self::WrappingMul if ty.is_wide() => Lowering::Libcall("__multi3"),
+
+            _ => return None,
+        };
+
+        Some(lowering)
+    }
+}
+
+impl Display for IntrinsicOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn wrapping(
+    lhs: &Constant,
+    rhs: &Constant,
+    int: fn(i64, i64) -> i64,
+    uint: fn(u64, u64) -> u64,
+    int128: fn(i128, i128) -> i128,
+    uint128: fn(u128, u128) -> u128,
+) -> Option<Constant> {
+    let wrapped = match (lhs, rhs) {
+        (&Constant::Int(lhs), &Constant::Int(rhs)) => Constant::Int(int(lhs, rhs)),
+        (&Constant::Uint(lhs), &Constant::Uint(rhs)) => Constant::Uint(uint(lhs, rhs)),
+        (&Constant::Int128(lhs), &Constant::Int128(rhs)) => Constant::Int128(int128(lhs, rhs)),
+        (&Constant::Uint128(lhs), &Constant::Uint128(rhs)) => Constant::Uint128(uint128(lhs, rhs)),
+        _ => return None,
+    };
+
+    Some(wrapped)
+}
+
+/// How a backend implements an intrinsic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lowering {
+    /// The encoded instructions to apply to the intrinsic's arguments, which leave
+    /// its result on the stack
+    Wasm(&'static [u8]),
+    /// A call to the runtime library function of the given name
+    Libcall(&'static str),
+}
+
+/// A call to an operation built into sruth
+///
+/// Intrinsics let front-ends express operations like bit counting or copying
+/// memory without declaring fake external functions for them. Unlike calls, the
+/// optimizer knows their signatures, whether they're pure and how to fold them,
+/// and backends lower them to native instructions or runtime library calls
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Intrinsic {
+    pub op: IntrinsicOp,
+    pub args: Vec<Value>,
+    pub dest: VarId,
+    pub ret_ty: Type,
+}
+
+impl Intrinsic {
+    pub const fn new(op: IntrinsicOp, args: Vec<Value>, dest: VarId, ret_ty: Type) -> Self {
+        Self {
+            op,
+            args,
+            dest,
+            ret_ty,
+        }
+    }
+
+    /// The types of the intrinsic's arguments
+    pub fn arg_types(&self) -> Vec<Type> {
+        self.args.iter().map(|arg| arg.ty.clone()).collect()
+    }
+
+    /// Folds the intrinsic if all of its arguments are constants, see
+    /// [`IntrinsicOp::fold()`]
+    pub fn fold(&self) -> Option<Constant> {
+        let args = self
+            .args
+            .iter()
+            .map(Value::as_const)
+            .collect::<Option<Vec<_>>>()?;
+
+        self.op.fold(&args)
+    }
+}
+
+impl InstructionExt for Intrinsic {
+    fn dest(&self) -> VarId {
+        self.dest
+    }
+
+    fn dest_type(&self) -> Type {
+        self.ret_ty.clone()
+    }
+
+    fn purity(&self) -> InstructionPurity {
+        self.op.purity()
+    }
+
+    fn replace_uses(&mut self, from: VarId, to: &Value) -> bool {
+        let mut replaced = false;
+
+        for value in self.args.iter_mut() {
+            if let Some(var) = value.as_var() {
+                if var == from {
+                    *value = to.clone();
+                    replaced = true;
+                }
+            }
+        }
+
+        replaced
+    }
+
+    fn used_vars(&self) -> Vec<TypedVar> {
+        self.args
+            .iter()
+            .filter_map(|arg| arg.as_typed_var())
+            .collect()
+    }
+
+    fn used_values_into<'a>(&'a self, buf: &mut Vec<&'a Value>) {
+        buf.extend(self.args.iter());
+    }
+
+    fn used_values_mut(&mut self) -> Vec<&mut Value> {
+        self.args.iter_mut().collect()
+    }
+}
+
+impl EstimateAsm for Intrinsic {
+    fn estimated_instructions(&self) -> usize {
+        match self.op {
+            // Copies are a call into the runtime plus the copy itself
+            IntrinsicOp::Memcpy => 10,
+            IntrinsicOp::Ctpop
+            | IntrinsicOp::Ctlz
+            | IntrinsicOp::Cttz
+            | IntrinsicOp::WrappingAdd
+            | IntrinsicOp::WrappingSub
+            | IntrinsicOp::WrappingMul => 1,
+        }
+    }
+}
+
+impl IRDisplay for Intrinsic {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        self.dest
+            .display(ctx)
+            .append(ctx.space())
+            .append(ctx.text(":="))
+            .append(ctx.space())
+            .append(ctx.text("intrinsic"))
+            .append(ctx.space())
+            .append(ctx.text(self.op.name()))
+            .append(
+                ctx.intersperse(
+                    self.args.iter().map(|arg| arg.display(ctx)),
+                    ctx.text(",").append(ctx.space()),
+                )
+                .parens(),
+            )
+            .group()
+    }
+}
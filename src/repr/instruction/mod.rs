@@ -1,40 +1,58 @@
+mod aggregate;
 mod assign;
 mod binary_ops;
 mod bitcast;
 mod call;
 mod cmp;
+mod intrinsic;
 mod neg;
+mod opaque;
+mod select;
 
+pub use aggregate::{ExtractValue, InsertValue};
 pub use assign::{Assign, VarId};
-pub use binary_ops::{Add, BinaryOp, BinopExt, Div, Mul, Sub};
+pub use binary_ops::{Add, BinaryOp, BinopExt, Div, Mul, Rem, Sub};
 pub use bitcast::Bitcast;
 pub use call::Call;
 pub use cmp::Cmp;
+pub use intrinsic::{Intrinsic, IntrinsicOp, Lowering};
 pub use neg::Neg;
-
-use crate::repr::{
-    utils::{
-        DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity, RawCast, RawRefCast,
+pub use opaque::Opaque;
+pub use select::Select;
+
+use crate::{
+    repr::{
+        utils::{
+            DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity, RawCast,
+            RawRefCast,
+        },
+        Ident, Type, TypedVar, Value,
     },
-    Type, TypedVar, Value,
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
-use std::num::NonZeroU64;
+use std::{cmp::Ordering, mem, num::NonZeroU64};
 
 // TODO: Make instructions self-describing (Let them carry their own id around somehow)
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     Assign(Assign),
     Add(Add),
     Sub(Sub),
     Mul(Mul),
     Div(Div),
+    Rem(Rem),
     Bitcast(Bitcast),
     Neg(Neg),
     Cmp(Cmp),
+    Select(Select),
     Call(Call),
+    Opaque(Opaque),
+    Intrinsic(Intrinsic),
+    ExtractValue(ExtractValue),
+    InsertValue(InsertValue),
 }
 
 impl Instruction {
@@ -42,12 +60,40 @@ impl Instruction {
     pub const fn is_binop(&self) -> bool {
         matches!(
             self,
-            Self::Add(_) | Self::Sub(_) | Self::Mul(_) | Self::Div(_)
+            Self::Add(_) | Self::Sub(_) | Self::Mul(_) | Self::Div(_) | Self::Rem(_)
         )
     }
+
+    /// Returns `true` if the instruction's operands can be swapped without changing
+    /// its result
+    pub const fn is_commutative(&self) -> bool {
+        matches!(self, Self::Add(_) | Self::Mul(_) | Self::Cmp(_))
+    }
+
+    /// Puts the operands of commutative instructions into their [canonical
+    /// order](Value::canonical_cmp), returning `true` if they were swapped
+    ///
+    /// Instructions that only differ by the order of their operands become identical,
+    /// which lets anything deduplicating instructions treat them as the same
+    pub fn canonicalize_operands(&mut self) -> bool {
+        let (lhs, rhs) = match self {
+            Self::Add(add) => (&mut add.lhs, &mut add.rhs),
+            Self::Mul(mul) => (&mut mul.lhs, &mut mul.rhs),
+            Self::Cmp(cmp) => (&mut cmp.lhs, &mut cmp.rhs),
+            _ => return false,
+        };
+
+        if lhs.canonical_cmp(rhs) == Ordering::Greater {
+            mem::swap(lhs, rhs);
+            true
+        } else {
+            false
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(transparent)]
 pub struct InstId(NonZeroU64);
 
@@ -108,6 +154,12 @@ macro_rules! impl_instruction {
                 }
             }
 
+            fn name(&self) -> Option<Ident> {
+                match self {
+                    $(Self::$type(value) => value.name(),)*
+                }
+            }
+
             fn purity(&self) -> InstructionPurity {
                 match self {
                     $(Self::$type(value) => value.purity(),)*
@@ -153,10 +205,20 @@ macro_rules! impl_instruction {
                 D: DocAllocator<'a, A>,
                 D::Doc: Clone,
                 A: Clone + 'a,
-                R: Resolver,
+                R: SymbolResolver,
             {
-                match self {
+                let inst = match self {
                     $(Self::$type(value) => value.display(ctx),)*
+                };
+
+                if let Some(name) = self.name() {
+                    inst.append(ctx.space())
+                        .append(ctx.text(";"))
+                        .append(ctx.space())
+                        .append(name.display(ctx))
+                        .group()
+                } else {
+                    inst
                 }
             }
         }
@@ -168,9 +230,15 @@ impl_instruction! {
     Sub,
     Mul,
     Div,
+    Rem,
     Assign,
     Bitcast,
     Neg,
     Cmp,
+    Select,
     Call,
+    Opaque,
+    Intrinsic,
+    ExtractValue,
+    InsertValue,
 }
@@ -1,12 +1,15 @@
-use crate::repr::{
-    utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
-    Type, TypedVar, Value, VarId,
+use crate::{
+    repr::{
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
+        Type, TypedVar, Value, VarId,
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Neg {
     pub value: Value,
     pub dest: VarId,
@@ -72,7 +75,7 @@ impl IRDisplay for Neg {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.dest
             .display(ctx)
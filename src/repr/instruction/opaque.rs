@@ -0,0 +1,142 @@
+use crate::{
+    repr::{
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
+        Type, TypedVar, Value, VarId,
+    },
+    symbols::SymbolResolver,
+};
+use abomonation_derive::Abomonation;
+use pretty::{DocAllocator, DocBuilder};
+use std::fmt::Write;
+
+/// A target-specific payload (such as inline assembly) that sruth can't reason about
+///
+/// Opaque instructions are always impure and are never rewritten, they're kept
+/// exactly where they are and handed to backends verbatim
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Opaque {
+    /// The backend the payload is meant for, e.g. `wasm32` or `x86_64`
+    pub target: String,
+    pub payload: Vec<u8>,
+    pub args: Vec<Value>,
+    pub dest: VarId,
+    pub ret_ty: Type,
+}
+
+impl Opaque {
+    pub const fn new(
+        target: String,
+        payload: Vec<u8>,
+        args: Vec<Value>,
+        dest: VarId,
+        ret_ty: Type,
+    ) -> Self {
+        Self {
+            target,
+            payload,
+            args,
+            dest,
+            ret_ty,
+        }
+    }
+
+    /// Returns `true` if the payload is meant for the given target
+    pub fn is_for(&self, target: &str) -> bool {
+        self.target == target
+    }
+}
+
+impl InstructionExt for Opaque {
+    fn dest(&self) -> VarId {
+        self.dest
+    }
+
+    fn dest_type(&self) -> Type {
+        self.ret_ty.clone()
+    }
+
+    fn purity(&self) -> InstructionPurity {
+        InstructionPurity::Impure
+    }
+
+    fn replace_uses(&mut self, from: VarId, to: &Value) -> bool {
+        let mut replaced = false;
+
+        for value in self.args.iter_mut() {
+            if let Some(var) = value.as_var() {
+                if var == from {
+                    *value = to.clone();
+                    replaced = true;
+                }
+            }
+        }
+
+        replaced
+    }
+
+    fn used_vars(&self) -> Vec<TypedVar> {
+        self.args
+            .iter()
+            .filter_map(|arg| arg.as_typed_var())
+            .collect()
+    }
+
+    fn used_values_into<'a>(&'a self, buf: &mut Vec<&'a Value>) {
+        buf.extend(self.args.iter());
+    }
+
+    fn used_values_mut(&mut self) -> Vec<&mut Value> {
+        self.args.iter_mut().collect()
+    }
+}
+
+impl EstimateAsm for Opaque {
+    // There's no way to know what the payload does, so assume it's fairly expensive
+    fn estimated_instructions(&self) -> usize {
+        10
+    }
+}
+
+impl IRDisplay for Opaque {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        // Textual payloads (like assembly) are shown as strings, anything else as hex
+        let payload = match std::str::from_utf8(&self.payload) {
+            Ok(payload) => format!("{:?}", payload),
+            Err(_) => {
+                let mut hex = String::with_capacity(2 + self.payload.len() * 2);
+                hex.push_str("0x");
+                for byte in self.payload.iter() {
+                    write!(hex, "{:02x}", byte).expect("writing to a string can't fail");
+                }
+
+                hex
+            }
+        };
+
+        self.dest
+            .display(ctx)
+            .append(ctx.space())
+            .append(ctx.text(":="))
+            .append(ctx.space())
+            .append(ctx.text("opaque"))
+            .append(ctx.space())
+            .append(ctx.text(format!("{:?}", self.target)))
+            .append(ctx.space())
+            .append(ctx.text(payload))
+            .append(
+                ctx.intersperse(
+                    self.args.iter().map(|arg| arg.display(ctx)),
+                    ctx.text(",").append(ctx.space()),
+                )
+                .parens(),
+            )
+            .group()
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{
+    repr::{
+        instruction::Assign,
+        utils::{DisplayCtx, EstimateAsm, IRDisplay, InstructionExt, InstructionPurity},
+        Type, TypedVar, Value, VarId,
+    },
+    symbols::SymbolResolver,
+};
+use abomonation_derive::Abomonation;
+use pretty::{DocAllocator, DocBuilder};
+
+/// Picks `if_true` when `cond` holds and `if_false` otherwise without branching,
+/// both values are always evaluated
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Select {
+    pub cond: Value,
+    pub if_true: Value,
+    pub if_false: Value,
+    pub dest: VarId,
+}
+
+impl Select {
+    pub const fn new(dest: VarId, cond: Value, if_true: Value, if_false: Value) -> Self {
+        Self {
+            cond,
+            if_true,
+            if_false,
+            dest,
+        }
+    }
+
+    pub const fn is_const(&self) -> bool {
+        self.cond.is_const() && self.if_true.is_const() && self.if_false.is_const()
+    }
+
+    /// Folds a select on a constant condition or between two identical values into
+    /// an assignment of the selected value
+    pub fn evaluate(&self) -> Option<Assign> {
+        let value = if self.if_true == self.if_false {
+            &self.if_true
+        } else if self.cond.as_const()?.as_bool()? {
+            &self.if_true
+        } else {
+            &self.if_false
+        };
+
+        Some(Assign::new(self.dest, value.clone(), None))
+    }
+}
+
+impl InstructionExt for Select {
+    fn dest(&self) -> VarId {
+        self.dest
+    }
+
+    fn dest_type(&self) -> Type {
+        self.if_true.ty.clone()
+    }
+
+    fn purity(&self) -> InstructionPurity {
+        InstructionPurity::Pure
+    }
+
+    fn replace_uses(&mut self, from: VarId, to: &Value) -> bool {
+        let mut replaced = false;
+
+        for value in self.used_values_mut() {
+            if let Some(var) = value.as_var() {
+                if var == from {
+                    *value = to.clone();
+                    replaced = true;
+                }
+            }
+        }
+
+        replaced
+    }
+
+    fn used_vars(&self) -> Vec<TypedVar> {
+        self.cond
+            .as_typed_var()
+            .into_iter()
+            .chain(self.if_true.as_typed_var())
+            .chain(self.if_false.as_typed_var())
+            .collect()
+    }
+
+    fn used_values_into<'a>(&'a self, buf: &mut Vec<&'a Value>) {
+        buf.push(&self.cond);
+        buf.push(&self.if_true);
+        buf.push(&self.if_false);
+    }
+
+    fn used_values_mut(&mut self) -> Vec<&mut Value> {
+        vec![&mut self.cond, &mut self.if_true, &mut self.if_false]
+    }
+}
+
+impl EstimateAsm for Select {
+    fn estimated_instructions(&self) -> usize {
+        1
+    }
+}
+
+impl IRDisplay for Select {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        self.dest
+            .display(ctx)
+            .append(ctx.space())
+            .append(ctx.text(":="))
+            .append(ctx.space())
+            .append(ctx.text("select"))
+            .append(ctx.space())
+            .append(self.cond.display(ctx))
+            .append(ctx.text(","))
+            .append(ctx.space())
+            .append(self.if_true.display(ctx))
+            .append(ctx.text(","))
+            .append(ctx.space())
+            .append(self.if_false.display(ctx))
+            .group()
+    }
+}
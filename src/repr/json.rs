@@ -0,0 +1,146 @@
+#![cfg(feature = "json")]
+
+//! Serialize sruth ir to and from json so that external tools can consume it
+//! without having to run any dataflows
+//!
+//! Note that [`Ident`]s are serialized as their raw interner keys, so the
+//! interner used to build the ir is needed in order to resolve them, a [`Module`]
+//! carries its strings along with it for when that interner isn't available
+//!
+//! [`Ident`]: crate::repr::Ident
+
+use crate::repr::Function;
+use lasso::{Key, Spur, ThreadedRodeo};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{Read, Write};
+
+pub use serde_json::{Error, Result};
+
+/// Serializes a [`Function`], [`BasicBlock`], [`Instruction`] or any other
+/// piece of ir into a json string
+///
+/// [`Function`]: crate::repr::Function
+/// [`BasicBlock`]: crate::repr::BasicBlock
+/// [`Instruction`]: crate::repr::Instruction
+pub fn to_string<T>(ir: &T) -> Result<String>
+where
+    T: Serialize + ?Sized,
+{
+    serde_json::to_string(ir)
+}
+
+/// Serializes ir into an indented json string
+pub fn to_string_pretty<T>(ir: &T) -> Result<String>
+where
+    T: Serialize + ?Sized,
+{
+    serde_json::to_string_pretty(ir)
+}
+
+pub fn to_writer<W, T>(writer: W, ir: &T) -> Result<()>
+where
+    W: Write,
+    T: Serialize + ?Sized,
+{
+    serde_json::to_writer(writer, ir)
+}
+
+pub fn from_str<T>(json: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str(json)
+}
+
+pub fn from_reader<R, T>(reader: R) -> Result<T>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    serde_json::from_reader(reader)
+}
+
+/// A set of functions bundled with the strings needed to resolve their [`Ident`]s,
+/// allowing ir to be loaded by a process other than the one that built it
+///
+/// [`Ident`]: crate::repr::Ident
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Module {
+    /// Every interned string, where each string's index is its interner key
+    pub strings: Vec<String>,
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    pub fn new<I>(functions: I, interner: &ThreadedRodeo) -> Self
+    where
+        I: IntoIterator<Item = Function>,
+    {
+        let mut strings: Vec<(Spur, &str)> = interner.iter().collect();
+        strings.sort_by_key(|&(key, _)| key.into_usize());
+
+        Self {
+            strings: strings
+                .into_iter()
+                .map(|(_, string)| string.to_owned())
+                .collect(),
+            functions: functions.into_iter().collect(),
+        }
+    }
+
+    /// Interns the module's strings and returns its functions, the interner must
+    /// be empty so that the module's keys resolve to the same strings
+    pub fn load(self, interner: &ThreadedRodeo) -> Result<Vec<Function>> {
+        for (idx, string) in self.strings.iter().enumerate() {
+            let key = interner.get_or_intern(string);
+
+            if key.into_usize() != idx {
+                return Err(<Error as serde::de::Error>::custom(format!(
+                    "the string {:?} was given the key {} instead of {}, \
+                     modules can only be loaded into an empty interner",
+                    string,
+                    key.into_usize(),
+                    idx,
+                )));
+            }
+        }
+
+        Ok(self.functions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::Context,
+        repr::{json, Constant, Function, Type},
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn function_roundtrip() {
+        let context = Arc::new(Context::new(0));
+        let mut builder = context.builder();
+
+        builder
+            .named_function("roundtrip", Type::Int, |func| {
+                func.basic_block(|block| {
+                    let sum = block.add_named("sum", Constant::Int(1), Constant::Int(2))?;
+                    block.ret(sum)?;
+
+                    Ok(())
+                })?;
+
+                Ok(())
+            })
+            .unwrap();
+
+        let function = builder.materialize().next().unwrap();
+        builder.discard();
+
+        let json = json::to_string(&function).unwrap();
+        let parsed: Function = json::from_str(&json).unwrap();
+
+        assert_eq!(function, parsed);
+    }
+}
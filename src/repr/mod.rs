@@ -1,17 +1,26 @@
+pub mod arbitrary;
 pub mod basic_block;
 pub mod constant;
+pub mod diff;
 pub mod function;
 pub mod instruction;
+pub mod json;
+pub mod module;
+pub mod rebase;
+pub mod remap;
+pub mod span;
 pub mod terminator;
 pub mod types;
 pub mod utils;
 pub mod value;
 
 pub use basic_block::{BasicBlock, BasicBlockId};
-pub use constant::Constant;
-pub use function::{FuncId, Function};
+pub use constant::{ConstId, Constant, ConstantPool};
+pub use function::{CallingConvention, FuncId, Function, FunctionAttributes, ParamAttributes};
 pub use instruction::{InstId, Instruction, VarId};
-pub use terminator::Terminator;
+pub use module::{ModuleId, ModuleMeta};
+pub use span::{SourceLoc, Span};
+pub use terminator::{Terminator, TrapCode};
 pub use types::Type;
 pub use utils::{Cast, Ident, InstructionExt, RawCast};
 pub use value::{TypedVar, Value, ValueKind};
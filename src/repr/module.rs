@@ -0,0 +1,204 @@
+use abomonation_derive::Abomonation;
+use std::fmt::{self, Display, Write};
+
+/// The name of the wasm custom section that [`ModuleMeta`] is embedded within
+pub const CUSTOM_SECTION_NAME: &str = "sruth.meta";
+
+/// The producer recorded when none is explicitly given
+pub const DEFAULT_PRODUCER: &str = concat!("sruth ", env!("CARGO_PKG_VERSION"));
+
+/// Identifies one of the independent modules compiled within a single dataflow
+///
+/// Every function belongs to exactly one module, functions can only be called
+/// from other modules when they're [exported](crate::repr::FunctionAttributes::EXPORT)
+/// so that passes working across functions never mix up unrelated modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(transparent)]
+pub struct ModuleId(u32);
+
+impl ModuleId {
+    /// The module functions belong to unless they're given another one
+    pub const DEFAULT: Self = Self(0);
+
+    pub const fn new(id: u32) -> Self {
+        Self(id)
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_default(self) -> bool {
+        self.0 == Self::DEFAULT.0
+    }
+}
+
+impl Display for ModuleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "module{}", self.0)
+    }
+}
+
+/// Module-level metadata that's carried alongside a program so that emitted
+/// artifacts can be traced back to their inputs and configuration
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModuleMeta {
+    /// The target triple the module is being compiled for, `None` for the host
+    pub target: Option<String>,
+    /// The target features enabled for the module
+    pub features: Vec<String>,
+    /// The tool that produced the module
+    pub producer: String,
+    /// A hash of the module's source, if it came from one
+    pub source_hash: Option<u64>,
+}
+
+impl ModuleMeta {
+    pub fn new() -> Self {
+        Self {
+            target: None,
+            features: Vec::new(),
+            producer: DEFAULT_PRODUCER.to_owned(),
+            source_hash: None,
+        }
+    }
+
+    pub fn with_target<T>(mut self, target: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn with_feature<F>(mut self, feature: F) -> Self
+    where
+        F: Into<String>,
+    {
+        self.features.push(feature.into());
+        self
+    }
+
+    pub fn with_producer<P>(mut self, producer: P) -> Self
+    where
+        P: Into<String>,
+    {
+        self.producer = producer.into();
+        self
+    }
+
+    /// Records the hash of the module's source code
+    pub fn with_source<S>(mut self, source: S) -> Self
+    where
+        S: AsRef<[u8]>,
+    {
+        self.source_hash = Some(fxhash::hash64(source.as_ref()));
+        self
+    }
+
+    /// The target triple, falling back to `"host"` when none was set
+    pub fn target(&self) -> &str {
+        self.target.as_deref().unwrap_or("host")
+    }
+
+    /// Renders the metadata as a series of comment lines, each starting with `prefix`
+    /// (e.g. `"; "` for assembly or `"// "` for graphviz)
+    pub fn comment_header(&self, prefix: &str) -> String {
+        let mut header = String::new();
+        for line in self.to_string().lines() {
+            // Writing to a string can't fail
+            let _ = writeln!(header, "{}{}", prefix, line);
+        }
+
+        header
+    }
+
+    /// Encodes the metadata as a complete wasm custom section named [`CUSTOM_SECTION_NAME`]
+    pub fn to_wasm_section(&self) -> Vec<u8> {
+        let payload = self.to_string();
+
+        let mut contents = Vec::with_capacity(payload.len() + CUSTOM_SECTION_NAME.len() + 10);
+        write_uleb128(&mut contents, CUSTOM_SECTION_NAME.len() as u64);
+        contents.extend_from_slice(CUSTOM_SECTION_NAME.as_bytes());
+        contents.extend_from_slice(payload.as_bytes());
+
+        // Custom sections have an id of zero
+        let mut section = Vec::with_capacity(contents.len() + 6);
+        section.push(0x00);
+        write_uleb128(&mut section, contents.len() as u64);
+        section.extend(contents);
+
+        section
+    }
+}
+
+impl Default for ModuleMeta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for ModuleMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "producer = {}", self.producer)?;
+        writeln!(f, "target = {}", self.target())?;
+
+        if !self.features.is_empty() {
+            writeln!(f, "features = {}", self.features.join(","))?;
+        }
+
+        if let Some(hash) = self.source_hash {
+            writeln!(f, "source_hash = {:016x}", hash)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_uleb128(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(byte);
+            break;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ModuleMeta, CUSTOM_SECTION_NAME};
+
+    #[test]
+    fn wasm_section() {
+        let meta = ModuleMeta::new()
+            .with_target("wasm32-unknown-unknown")
+            .with_feature("simd128")
+            .with_producer("test")
+            .with_source("fn main() {}");
+
+        let section = meta.to_wasm_section();
+        let payload = meta.to_string();
+
+        assert_eq!(section[0], 0x00);
+        assert_eq!(
+            section[1] as usize,
+            1 + CUSTOM_SECTION_NAME.len() + payload.len(),
+        );
+        assert_eq!(section[2] as usize, CUSTOM_SECTION_NAME.len());
+        assert_eq!(
+            &section[3..3 + CUSTOM_SECTION_NAME.len()],
+            CUSTOM_SECTION_NAME.as_bytes()
+        );
+        assert_eq!(
+            &section[3 + CUSTOM_SECTION_NAME.len()..],
+            payload.as_bytes()
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Merging of independently built modules
+//!
+//! Modules built by different [`Context`]s (or loaded from separate serialized
+//! modules) allocate their ids from separate counters, so their function, block
+//! and variable ids can collide. The [`ModuleMerger`] detects collisions as each
+//! module is added and consistently rebases the colliding ids of the new module
+//! onto fresh ones, updating every reference to them
+
+use crate::{
+    builder::Context,
+    repr::{
+        basic_block::BasicBlockDesc, utils::InstructionRewriter, BasicBlock, BasicBlockId, FuncId,
+        Function, InstId, Instruction, InstructionExt, Value, VarId,
+    },
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+/// Combines the functions of multiple modules into a single module without any
+/// conflicting ids
+///
+/// Ids of the first module to use them are always kept, ids from later modules
+/// are only rebased if they collide. Calls to functions that aren't defined
+/// within a module are treated as calls into previously merged modules and are
+/// left untouched
+#[derive(Debug)]
+pub struct ModuleMerger {
+    context: Arc<Context>,
+    functions: Vec<Function>,
+    function_ids: BTreeSet<FuncId>,
+    block_ids: BTreeSet<BasicBlockId>,
+    var_ids: BTreeSet<VarId>,
+}
+
+impl ModuleMerger {
+    /// Creates a merger that allocates fresh ids from the given context
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            context,
+            functions: Vec::new(),
+            function_ids: BTreeSet::new(),
+            block_ids: BTreeSet::new(),
+            var_ids: BTreeSet::new(),
+        }
+    }
+
+    /// Adds a module's functions to the merged module, returning the ids that
+    /// had to be rebased to avoid collisions
+    pub fn add_module(&mut self, mut functions: Vec<Function>) -> IdRemapping {
+        // Make sure the context never hands out any id that's already in use
+        for function in functions.iter() {
+            self.context.reserve_function_id(function.id);
+            for param in function.params.iter() {
+                self.context.reserve_var_id(param.var);
+            }
+
+            for block in function.basic_blocks.iter() {
+                self.context.reserve_block_id(block.id);
+                for inst in block.instructions.iter() {
+                    self.context.reserve_var_id(inst.dest());
+                }
+            }
+        }
+
+        let remapping = self.find_collisions(&functions);
+        if !remapping.is_empty() {
+            tracing::debug!(
+                functions = remapping.functions.len(),
+                blocks = remapping.blocks.len(),
+                vars = remapping.vars.len(),
+                "rebasing colliding ids of merged module",
+            );
+
+            for function in functions.iter_mut() {
+                remapping.apply(function);
+            }
+        }
+
+        for function in functions.iter() {
+            self.function_ids.insert(function.id);
+            self.var_ids
+                .extend(function.params.iter().map(|param| param.var));
+
+            for block in function.basic_blocks.iter() {
+                self.block_ids.insert(block.id);
+                self.var_ids
+                    .extend(block.instructions.iter().map(|inst| inst.dest()));
+            }
+        }
+        self.functions.extend(functions);
+
+        remapping
+    }
+
+    /// Returns the merged functions
+    pub fn finish(self) -> Vec<Function> {
+        self.functions
+    }
+
+    fn find_collisions(&self, functions: &[Function]) -> IdRemapping {
+        let mut remapping = IdRemapping::default();
+
+        for function in functions {
+            if self.function_ids.contains(&function.id) {
+                remapping
+                    .functions
+                    .insert(function.id, self.context.function_id());
+            }
+
+            for param in function.params.iter() {
+                if self.var_ids.contains(&param.var) {
+                    remapping.vars.insert(param.var, self.context.var_id());
+                }
+            }
+
+            for block in function.basic_blocks.iter() {
+                if self.block_ids.contains(&block.id) {
+                    remapping.blocks.insert(block.id, self.context.block_id());
+                }
+
+                for inst in block.instructions.iter() {
+                    let dest = inst.dest();
+                    if self.var_ids.contains(&dest) {
+                        remapping.vars.insert(dest, self.context.var_id());
+                    }
+                }
+            }
+        }
+
+        remapping
+    }
+}
+
+/// The ids of a module that were rebased onto fresh ones
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdRemapping {
+    pub functions: BTreeMap<FuncId, FuncId>,
+    pub blocks: BTreeMap<BasicBlockId, BasicBlockId>,
+    pub vars: BTreeMap<VarId, VarId>,
+    pub insts: BTreeMap<InstId, InstId>,
+}
+
+impl IdRemapping {
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+            && self.blocks.is_empty()
+            && self.vars.is_empty()
+            && self.insts.is_empty()
+    }
+
+    pub fn function(&self, id: FuncId) -> FuncId {
+        self.functions.get(&id).copied().unwrap_or(id)
+    }
+
+    pub fn block(&self, id: BasicBlockId) -> BasicBlockId {
+        self.blocks.get(&id).copied().unwrap_or(id)
+    }
+
+    pub fn var(&self, id: VarId) -> VarId {
+        self.vars.get(&id).copied().unwrap_or(id)
+    }
+
+    pub fn inst(&self, id: InstId) -> InstId {
+        self.insts.get(&id).copied().unwrap_or(id)
+    }
+
+    /// Rewrites every id defined or referenced within the function
+    pub fn apply(&self, function: &mut Function) {
+        function.id = self.function(function.id);
+        function.entry = self.block(function.entry);
+        for param in function.params.iter_mut() {
+            param.var = self.var(param.var);
+        }
+
+        for block in function.basic_blocks.iter_mut() {
+            self.apply_block(block);
+        }
+    }
+
+    /// Rewrites every id defined or referenced within the block
+    pub fn apply_block(&self, block: &mut BasicBlock) {
+        let mut rewriter = self;
+
+        block.id = self.block(block.id);
+        for inst in block.instructions.iter_mut() {
+            rewriter.rewrite_instruction(inst);
+        }
+        rewriter.rewrite_terminator(&mut block.terminator);
+    }
+
+    /// Rewrites every id defined or referenced within the block's description,
+    /// the instructions it refers to are rewritten separately with
+    /// [`IdRemapping::apply_instruction()`]
+    pub fn apply_desc(&self, block: &mut BasicBlockDesc) {
+        block.id = self.block(block.id);
+        for inst in block.instructions.iter_mut() {
+            *inst = self.inst(*inst);
+        }
+
+        let mut rewriter = self;
+        rewriter.rewrite_terminator(&mut block.terminator);
+    }
+
+    /// Rewrites every id defined or referenced by the instruction
+    pub fn apply_instruction(&self, (id, inst): &mut (InstId, Instruction)) {
+        let mut rewriter = self;
+
+        *id = self.inst(*id);
+        rewriter.rewrite_instruction(inst);
+    }
+}
+
+/// Remapping only ever reads the remapped ids, so a shared reference is enough to
+/// rewrite with
+impl InstructionRewriter for &IdRemapping {
+    fn rewrite_value(&mut self, value: &mut Value) -> bool {
+        match value.as_var_mut() {
+            Some(var) => self.rewrite_dest(var),
+            None => false,
+        }
+    }
+
+    fn rewrite_dest(&mut self, dest: &mut VarId) -> bool {
+        let var = self.var(*dest);
+        let changed = var != *dest;
+        *dest = var;
+
+        changed
+    }
+
+    fn rewrite_callee(&mut self, callee: &mut FuncId) -> bool {
+        let func = self.function(*callee);
+        let changed = func != *callee;
+        *callee = func;
+
+        changed
+    }
+
+    fn rewrite_target(&mut self, target: &mut BasicBlockId) -> bool {
+        let block = self.block(*target);
+        let changed = block != *target;
+        *target = block;
+
+        changed
+    }
+}
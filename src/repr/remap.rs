@@ -0,0 +1,129 @@
+//! Cloning of code with fresh ids
+//!
+//! Inlining, function duplication, loop unrolling and outlining all copy some
+//! part of a function and need every block, variable and instruction defined by
+//! the copy to get a fresh id while references to anything defined outside of it
+//! are kept as-is. The functions here mint those ids from any [`IdAllocator`],
+//! so they work the same with a [`Context`](crate::builder::Context) or within
+//! dataflow operators with a [`WorkerIds`](crate::builder::WorkerIds)
+
+use crate::{
+    builder::IdAllocator,
+    repr::{
+        basic_block::BasicBlockDesc, rebase::IdRemapping, BasicBlock, Function, InstId,
+        Instruction, InstructionExt,
+    },
+};
+
+/// Maps every block and variable defined within `blocks` onto fresh ids
+pub fn fresh_ids<A>(blocks: &[BasicBlock], ids: &A) -> IdRemapping
+where
+    A: IdAllocator + ?Sized,
+{
+    let mut remapping = IdRemapping::default();
+    for block in blocks {
+        remapping.blocks.insert(block.id, ids.block_id());
+
+        for inst in block.instructions.iter() {
+            remapping.vars.insert(inst.dest(), ids.var_id());
+        }
+    }
+
+    remapping
+}
+
+/// Clones `blocks` with fresh ids for everything they define, references to
+/// blocks and variables defined elsewhere are left untouched
+pub fn clone_blocks<A>(blocks: &[BasicBlock], ids: &A) -> (Vec<BasicBlock>, IdRemapping)
+where
+    A: IdAllocator + ?Sized,
+{
+    let remapping = fresh_ids(blocks, ids);
+    let blocks = blocks
+        .iter()
+        .map(|block| {
+            let mut block = block.clone();
+            remapping.apply_block(&mut block);
+
+            block
+        })
+        .collect();
+
+    (blocks, remapping)
+}
+
+/// Duplicates a function under a fresh id, giving its parameters, blocks and
+/// instructions fresh ids as well
+pub fn clone_function<A>(function: &Function, ids: &A) -> (Function, IdRemapping)
+where
+    A: IdAllocator + ?Sized,
+{
+    let mut remapping = fresh_ids(&function.basic_blocks, ids);
+    remapping.functions.insert(function.id, ids.function_id());
+    for param in function.params.iter() {
+        remapping.vars.insert(param.var, ids.var_id());
+    }
+
+    let mut function = function.clone();
+    remapping.apply(&mut function);
+
+    (function, remapping)
+}
+
+/// Maps every block, instruction and variable defined by the given block
+/// descriptions and their instructions onto fresh ids
+pub fn fresh_desc_ids<A>(
+    blocks: &[BasicBlockDesc],
+    instructions: &[(InstId, Instruction)],
+    ids: &A,
+) -> IdRemapping
+where
+    A: IdAllocator + ?Sized,
+{
+    let mut remapping = IdRemapping::default();
+    for block in blocks {
+        remapping.blocks.insert(block.id, ids.block_id());
+    }
+
+    for (id, inst) in instructions {
+        remapping.insts.insert(*id, ids.inst_id());
+        remapping.vars.insert(inst.dest(), ids.var_id());
+    }
+
+    remapping
+}
+
+/// Clones the dataflow form of some blocks with fresh ids for everything they
+/// define, see [`clone_blocks()`]
+pub fn clone_descs<A>(
+    blocks: &[BasicBlockDesc],
+    instructions: &[(InstId, Instruction)],
+    ids: &A,
+) -> (Vec<BasicBlockDesc>, Vec<(InstId, Instruction)>, IdRemapping)
+where
+    A: IdAllocator + ?Sized,
+{
+    let remapping = fresh_desc_ids(blocks, instructions, ids);
+
+    let blocks = blocks
+        .iter()
+        .map(|block| {
+            let mut block = block.clone();
+            remapping.apply_desc(&mut block);
+
+            block
+        })
+        .collect();
+
+    let instructions = instructions
+        .iter()
+        .map(|inst| {
+            let mut inst = inst.clone();
+            remapping.apply_instruction(&mut inst);
+
+            inst
+        })
+        .collect();
+
+    (blocks, instructions, remapping)
+}
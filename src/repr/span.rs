@@ -0,0 +1,75 @@
+use crate::{
+    repr::{
+        utils::{DisplayCtx, IRDisplay},
+        Ident,
+    },
+    symbols::SymbolResolver,
+};
+use abomonation_derive::Abomonation;
+use pretty::{DocAllocator, DocBuilder};
+
+/// A line and column within a source file, both starting from one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceLoc {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl SourceLoc {
+    pub const fn new(line: u32, column: u32) -> Self {
+        Self { line, column }
+    }
+}
+
+/// The region of user code that an instruction or terminator originated from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub file: Option<Ident>,
+    pub start: SourceLoc,
+    pub end: SourceLoc,
+}
+
+impl Span {
+    pub const fn new(file: Option<Ident>, start: SourceLoc, end: SourceLoc) -> Self {
+        Self { file, start, end }
+    }
+
+    /// Creates a span that covers a single location
+    pub const fn point(file: Option<Ident>, loc: SourceLoc) -> Self {
+        Self::new(file, loc, loc)
+    }
+
+    pub const fn is_point(&self) -> bool {
+        self.start.line == self.end.line && self.start.column == self.end.column
+    }
+}
+
+impl IRDisplay for Span {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        let file = self
+            .file
+            .map(|file| file.display(ctx))
+            .unwrap_or_else(|| ctx.text("<unknown>"));
+
+        let start = ctx.text(format!(":{}:{}", self.start.line, self.start.column));
+        let end = if self.is_point() {
+            ctx.nil()
+        } else {
+            ctx.text(format!("-{}:{}", self.end.line, self.end.column))
+        };
+
+        ctx.text("!loc")
+            .append(ctx.space())
+            .append(file)
+            .append(start)
+            .append(end)
+    }
+}
@@ -1,28 +1,35 @@
-use crate::repr::{
-    instruction::VarId,
-    utils::{DisplayCtx, IRDisplay, RawCast},
-    value::Value,
-    BasicBlockId,
+use crate::{
+    repr::{
+        instruction::VarId,
+        utils::{DisplayCtx, IRDisplay, RawCast},
+        value::Value,
+        BasicBlockId, Constant, Type,
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
 
 use super::TypedVar;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Terminator {
     // TODO: Make this hold a label & a dedicated `Jump` struct
     Jump(BasicBlockId),
     // TODO: Make a `Return` struct
     Return(Return),
     Branch(Branch),
+    Switch(Switch),
     Unreachable,
+    /// Aborts execution, reached when an operation like dividing by zero is known
+    /// to fail. Unlike [`Terminator::Unreachable`] reaching a trap is well defined
+    Trap(TrapCode),
 }
 
 impl Terminator {
     pub const fn is_branching(&self) -> bool {
-        matches!(self, Self::Branch(_))
+        matches!(self, Self::Branch(_) | Self::Switch(_))
     }
 
     // TODO: Make a `Return` struct
@@ -42,6 +49,14 @@ impl Terminator {
         }
     }
 
+    pub const fn into_switch(self) -> Option<Switch> {
+        if let Self::Switch(switch) = self {
+            Some(switch)
+        } else {
+            None
+        }
+    }
+
     pub const fn into_jump(self) -> Option<BasicBlockId> {
         if let Self::Jump(jump) = self {
             Some(jump)
@@ -54,8 +69,19 @@ impl Terminator {
         match self {
             Self::Jump(_) => Vec::new(),
             Self::Branch(branch) => branch.used_vars(),
+            Self::Switch(switch) => switch.used_vars(),
             Self::Return(ret) => ret.used_vars(),
-            Self::Unreachable => Vec::new(),
+            Self::Unreachable | Self::Trap(_) => Vec::new(),
+        }
+    }
+
+    /// The values the terminator uses, including constants
+    pub fn used_values(&self) -> Vec<&Value> {
+        match self {
+            Self::Branch(branch) => vec![&branch.cond],
+            Self::Switch(switch) => vec![&switch.scrutinee],
+            Self::Return(ret) => ret.values.iter().collect(),
+            Self::Jump(_) | Self::Unreachable | Self::Trap(_) => Vec::new(),
         }
     }
 
@@ -63,7 +89,36 @@ impl Terminator {
         match self {
             &Self::Jump(block) => vec![block],
             Self::Branch(branch) => branch.jump_targets(),
-            Self::Return(_) | Self::Unreachable => Vec::new(),
+            Self::Switch(switch) => switch.jump_targets(),
+            Self::Return(_) | Self::Unreachable | Self::Trap(_) => Vec::new(),
+        }
+    }
+
+    /// The labels of a branch or switch in the same order as their
+    /// [jump targets](Self::jump_targets), other terminators have none
+    pub fn labels(&self) -> Vec<&Label> {
+        match self {
+            Self::Branch(branch) => vec![&branch.if_true, &branch.if_false],
+            Self::Switch(switch) => switch
+                .cases
+                .iter()
+                .map(|(_, label)| label)
+                .chain(Some(&switch.default))
+                .collect(),
+            Self::Jump(_) | Self::Return(_) | Self::Unreachable | Self::Trap(_) => Vec::new(),
+        }
+    }
+
+    pub fn labels_mut(&mut self) -> Vec<&mut Label> {
+        match self {
+            Self::Branch(branch) => vec![&mut branch.if_true, &mut branch.if_false],
+            Self::Switch(switch) => switch
+                .cases
+                .iter_mut()
+                .map(|(_, label)| label)
+                .chain(Some(&mut switch.default))
+                .collect(),
+            Self::Jump(_) | Self::Return(_) | Self::Unreachable | Self::Trap(_) => Vec::new(),
         }
     }
 
@@ -71,13 +126,16 @@ impl Terminator {
         match self {
             Self::Return(ret) => ret.replace_uses(from, to),
             Self::Branch(branch) => branch.replace_uses(from, to),
-            Self::Jump(_) | Self::Unreachable => false,
+            Self::Switch(switch) => switch.replace_uses(from, to),
+            Self::Jump(_) | Self::Unreachable | Self::Trap(_) => false,
         }
     }
 
     pub const fn estimated_instructions(&self) -> usize {
         match self {
-            Self::Jump(_) | Self::Return(_) | Self::Branch(_) => 1,
+            Self::Jump(_) | Self::Return(_) | Self::Branch(_) | Self::Trap(_) => 1,
+            // Switches are assumed to be lowered into a jump table
+            Self::Switch(_) => 3,
             Self::Unreachable => 0,
         }
     }
@@ -89,7 +147,7 @@ impl IRDisplay for Terminator {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         match self {
             Self::Jump(addr) => ctx
@@ -102,7 +160,35 @@ impl IRDisplay for Terminator {
 
             Self::Unreachable => ctx.text("unreachable"),
 
+            Self::Trap(code) => ctx
+                .text("trap")
+                .append(ctx.space())
+                .append(ctx.text(code.as_str()))
+                .group(),
+
             Self::Branch(branch) => branch.display(ctx),
+
+            Self::Switch(switch) => switch.display(ctx),
+        }
+    }
+}
+
+/// Why a [`Terminator::Trap`] aborts execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrapCode {
+    /// An integer was divided by zero
+    DivisionByZero,
+    /// A signed division overflowed, which only happens when dividing the smallest
+    /// signed integer by `-1`
+    IntegerOverflow,
+}
+
+impl TrapCode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::DivisionByZero => "division_by_zero",
+            Self::IntegerOverflow => "integer_overflow",
         }
     }
 }
@@ -147,7 +233,7 @@ macro_rules! impl_terminator {
         //         D: DocAllocator<'a, A>,
         //         D::Doc: Clone,
         //         A: Clone + 'a,
-        //         R: Resolver,
+        //         R: SymbolResolver,
         //     {
         //         match self {
         //             $(Self::$type(value) => value.display(ctx),)*
@@ -159,10 +245,12 @@ macro_rules! impl_terminator {
 
 impl_terminator! {
     Branch,
+    Switch,
     Return,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Branch {
     pub cond: Value,
     pub if_true: Label,
@@ -209,7 +297,7 @@ impl IRDisplay for Branch {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         ctx.text("branch")
             .append(ctx.space())
@@ -224,14 +312,123 @@ impl IRDisplay for Branch {
     }
 }
 
+/// A multi-way branch that jumps to the case matching the scrutinee, or to
+/// the default target if no case matches
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Switch {
+    pub scrutinee: Value,
+    pub cases: Vec<(Constant, Label)>,
+    pub default: Label,
+}
+
+impl Switch {
+    pub const fn new(scrutinee: Value, cases: Vec<(Constant, Label)>, default: Label) -> Self {
+        Self {
+            scrutinee,
+            cases,
+            default,
+        }
+    }
+
+    /// Returns the block the switch jumps to for the given value
+    pub fn target(&self, value: &Constant) -> BasicBlockId {
+        self.cases
+            .iter()
+            .find(|(case, _)| case == value)
+            .map_or(self.default.block, |(_, label)| label.block)
+    }
+
+    pub fn replace_uses(&mut self, from: VarId, to: VarId) -> bool {
+        if let Some(var) = self.scrutinee.as_var_mut() {
+            if *var == from {
+                *var = to;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    pub fn used_vars(&self) -> Vec<VarId> {
+        if let Some(val) = self.scrutinee.as_var() {
+            vec![val]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns every target of the switch, cases first and then the default
+    pub fn jump_targets(&self) -> Vec<BasicBlockId> {
+        self.cases
+            .iter()
+            .map(|(_, label)| label.block)
+            .chain(Some(self.default.block))
+            .collect()
+    }
+}
+
+impl IRDisplay for Switch {
+    fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
+    where
+        D: DocAllocator<'a, A>,
+        D::Doc: Clone,
+        A: Clone + 'a,
+        R: SymbolResolver,
+    {
+        let cases = self.cases.iter().map(|(case, label)| {
+            case.display(ctx)
+                .append(ctx.space())
+                .append(ctx.text("=>"))
+                .append(ctx.space())
+                .append(label.display(ctx))
+        });
+        let default = ctx
+            .text("default")
+            .append(ctx.space())
+            .append(ctx.text("=>"))
+            .append(ctx.space())
+            .append(self.default.display(ctx));
+
+        ctx.text("switch")
+            .append(ctx.space())
+            .append(self.scrutinee.display(ctx))
+            .append(ctx.text(","))
+            .append(ctx.space())
+            .append(
+                ctx.intersperse(
+                    cases.chain(Some(default)),
+                    ctx.text(",").append(ctx.space()),
+                )
+                .brackets(),
+            )
+            .group()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Label {
     pub block: BasicBlockId,
+    /// How likely the edge is to be taken relative to the other edges leaving its
+    /// block, set by front-ends or [derived from profiles](crate::optimize::layout::apply_profile)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub weight: Option<u32>,
 }
 
 impl Label {
     pub const fn new(block: BasicBlockId) -> Self {
-        Self { block }
+        Self {
+            block,
+            weight: None,
+        }
+    }
+
+    pub const fn weighted(block: BasicBlockId, weight: u32) -> Self {
+        Self {
+            block,
+            weight: Some(weight),
+        }
     }
 }
 
@@ -241,43 +438,69 @@ impl IRDisplay for Label {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
-        self.block.display(ctx)
+        self.block
+            .display(ctx)
+            .append(self.weight.map_or_else(
+                || ctx.nil(),
+                |weight| ctx.space().append(ctx.text(format!("(weight {})", weight))),
+            ))
+            .group()
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Return {
-    pub value: Option<Value>,
+    /// The returned values, empty for functions returning unit and holding one
+    /// value per element for functions returning a [tuple](Type::Tuple)
+    pub values: Vec<Value>,
 }
 
 impl Return {
-    pub const fn new(value: Option<Value>) -> Self {
-        Self { value }
+    pub fn new(value: Option<Value>) -> Self {
+        Self {
+            values: value.into_iter().collect(),
+        }
     }
 
-    pub fn returned_var(&self) -> Option<TypedVar> {
-        self.value.as_ref().and_then(Value::as_typed_var)
+    /// Creates a return of multiple values from a function returning a tuple
+    pub const fn multiple(values: Vec<Value>) -> Self {
+        Self { values }
+    }
+
+    /// The returned value if exactly one value is returned
+    pub fn value(&self) -> Option<&Value> {
+        match self.values.as_slice() {
+            [value] => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The type of the returned values as a function's return type
+    pub fn ty(&self) -> Type {
+        Type::from_results(self.values.iter().map(|value| value.ty.clone()).collect())
+    }
+
+    pub fn returned_vars(&self) -> Vec<TypedVar> {
+        self.values.iter().filter_map(Value::as_typed_var).collect()
     }
 
     pub fn replace_uses(&mut self, from: VarId, to: VarId) -> bool {
-        if let Some(var) = self.value.as_mut().and_then(Value::as_var_mut) {
+        let mut replaced = false;
+        for var in self.values.iter_mut().filter_map(Value::as_var_mut) {
             if *var == from {
                 *var = to;
-                return true;
+                replaced = true;
             }
         }
 
-        false
+        replaced
     }
 
     pub fn used_vars(&self) -> Vec<VarId> {
-        if let Some(val) = self.value.as_ref().and_then(Value::as_var) {
-            vec![val]
-        } else {
-            Vec::new()
-        }
+        self.values.iter().filter_map(Value::as_var).collect()
     }
 }
 
@@ -287,15 +510,19 @@ impl IRDisplay for Return {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         ctx.text("return")
-            .append(
-                self.value
-                    .as_ref()
-                    .map(|ret| ctx.space().append(ret.display(ctx)).append(ctx.space()))
-                    .unwrap_or_else(|| ctx.nil()),
-            )
+            .append(if self.values.is_empty() {
+                ctx.nil()
+            } else {
+                ctx.space()
+                    .append(ctx.intersperse(
+                        self.values.iter().map(|value| value.display(ctx)),
+                        ctx.text(",").append(ctx.space()),
+                    ))
+                    .append(ctx.space())
+            })
             .group()
     }
 }
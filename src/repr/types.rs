@@ -1,21 +1,161 @@
-use crate::repr::utils::{DisplayCtx, IRDisplay};
+use crate::{
+    repr::{
+        utils::{DisplayCtx, IRDisplay},
+        Constant,
+    },
+    symbols::SymbolResolver,
+};
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
+use std::fmt::{self, Display};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     Int,
     Uint,
+    /// A signed 128-bit integer
+    Int128,
+    /// An unsigned 128-bit integer
+    Uint128,
+    /// A 32-bit IEEE 754 float
+    F32,
+    /// A 64-bit IEEE 754 float
+    F64,
     Bool,
     Unit,
     Infer,
+    /// A fixed number of elements of a single type
+    Array(Box<Type>, u64),
+    /// A sequence of fields of any type
+    Struct(Vec<Type>),
+    /// The results of a function that returns multiple values at once
+    Tuple(Vec<Type>),
 }
 
 impl Type {
     pub const fn is_infer(&self) -> bool {
         matches!(self, Self::Infer)
     }
+
+    /// The most elements, counting those of nested aggregates, that a
+    /// [default constant](Type::default_constant) is built with
+    pub const MAX_DEFAULT_ELEMENTS: u64 = 1 << 16;
+
+    /// Returns the zeroed default value of the type, or `None` for types
+    /// without a value such as [`Type::Unit`] and [`Type::Infer`]
+    ///
+    /// Every element of a default array is materialized, so aggregates with more
+    /// than [`Type::MAX_DEFAULT_ELEMENTS`] elements don't have a default either
+    pub fn default_constant(&self) -> Option<Constant> {
+        let mut budget = Self::MAX_DEFAULT_ELEMENTS;
+        self.bounded_default_constant(&mut budget)
+    }
+
+    /// Builds the default value of the type out of at most `budget` elements,
+    /// taking the elements it used from it
+    fn bounded_default_constant(&self, budget: &mut u64) -> Option<Constant> {
+        *budget = budget.checked_sub(1)?;
+
+        match self {
+            Self::Int => Some(Constant::Int(0)),
+            Self::Uint => Some(Constant::Uint(0)),
+            Self::Int128 => Some(Constant::Int128(0)),
+            Self::Uint128 => Some(Constant::Uint128(0)),
+            Self::F32 => Some(Constant::f32(0.0)),
+            Self::F64 => Some(Constant::f64(0.0)),
+            Self::Bool => Some(Constant::Bool(false)),
+            Self::Unit | Self::Infer | Self::Tuple(_) => None,
+
+            Self::Array(element, len) => {
+                let available = *budget;
+                let zero = element.bounded_default_constant(budget)?;
+
+                // The element was only paid for once, but it's repeated `len` times
+                let per_element = available - *budget;
+                *budget = available.checked_sub(per_element.checked_mul(*len)?)?;
+
+                Some(Constant::Array(
+                    (**element).clone(),
+                    vec![zero; *len as usize],
+                ))
+            }
+            Self::Struct(fields) => fields
+                .iter()
+                .map(|field| field.bounded_default_constant(budget))
+                .collect::<Option<_>>()
+                .map(Constant::Struct),
+        }
+    }
+
+    pub const fn is_integer(&self) -> bool {
+        matches!(self, Self::Int | Self::Uint | Self::Int128 | Self::Uint128)
+    }
+
+    pub const fn is_float(&self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    /// Returns `true` for the types arithmetic can be performed on
+    pub const fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// Returns `true` for integers wider than 64 bits, which targets without native
+    /// 128-bit arithmetic have to split into pairs of 64-bit halves
+    pub const fn is_wide(&self) -> bool {
+        matches!(self, Self::Int128 | Self::Uint128)
+    }
+
+    /// The individual values returned by a function with this return type, unit
+    /// returns nothing and tuples return each of their elements
+    pub fn results(&self) -> &[Type] {
+        match self {
+            Self::Unit => &[],
+            Self::Tuple(elements) => elements,
+            ty => std::slice::from_ref(ty),
+        }
+    }
+
+    /// Creates the return type of a function returning the given values
+    pub fn from_results(mut results: Vec<Type>) -> Self {
+        match results.len() {
+            0 => Self::Unit,
+            1 => results.remove(0),
+            _ => Self::Tuple(results),
+        }
+    }
+
+    pub const fn is_aggregate(&self) -> bool {
+        matches!(self, Self::Array(..) | Self::Struct(_))
+    }
+
+    /// Returns the type of the element or field at `index` within an aggregate, or
+    /// `None` if the type isn't an aggregate or the index is out of bounds
+    pub fn field(&self, index: u64) -> Option<&Type> {
+        match self {
+            Self::Array(element, len) if index < *len => Some(&**element),
+            Self::Struct(fields) => fields.get(index as usize),
+            _ => None,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Int => "int",
+            Self::Uint => "uint",
+            Self::Int128 => "int128",
+            Self::Uint128 => "uint128",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::Bool => "bool",
+            Self::Unit => "unit",
+            Self::Infer => "infer",
+            Self::Array(..) => "array",
+            Self::Struct(_) => "struct",
+            Self::Tuple(_) => "tuple",
+        }
+    }
 }
 
 impl IRDisplay for Type {
@@ -24,14 +164,37 @@ impl IRDisplay for Type {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
+        ctx.text(self.to_string())
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Int => ctx.text("int"),
-            Self::Uint => ctx.text("uint"),
-            Self::Bool => ctx.text("bool"),
-            Self::Unit => ctx.text("unit"),
-            Self::Infer => ctx.text("infer"),
+            Self::Array(element, len) => write!(f, "[{}; {}]", element, len),
+            Self::Struct(fields) => {
+                f.write_str("{")?;
+                for (idx, field) in fields.iter().enumerate() {
+                    if idx != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", field)?;
+                }
+                f.write_str("}")
+            }
+            Self::Tuple(elements) => {
+                f.write_str("(")?;
+                for (idx, element) in elements.iter().enumerate() {
+                    if idx != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_str(")")
+            }
+            ty => f.write_str(ty.name()),
         }
     }
 }
@@ -1,8 +1,14 @@
-use crate::repr::{instruction::VarId, Type, TypedVar, Value};
+use crate::{
+    repr::{
+        instruction::VarId, BasicBlockId, ConstantPool, FuncId, Instruction, Terminator, Type,
+        TypedVar, Value,
+    },
+    symbols::SymbolResolver,
+};
 use abomonation::Abomonation;
 use abomonation_derive::Abomonation;
-use lasso::{Resolver, Spur};
-use pretty::{DocAllocator, DocBuilder};
+use lasso::{Key, Spur};
+use pretty::{BoxAllocator, DocAllocator, DocBuilder, RefDoc};
 use std::{marker::PhantomData, num::NonZeroU32, ops::Deref};
 
 pub trait RawCast<T> {
@@ -65,6 +71,11 @@ pub trait InstructionExt: EstimateAsm + IRDisplay {
 
     fn dest_type(&self) -> Type;
 
+    /// The user-given name of the instruction's destination, if it has one
+    fn name(&self) -> Option<Ident> {
+        None
+    }
+
     fn purity(&self) -> InstructionPurity;
 
     fn replace_uses(&mut self, from: VarId, to: &Value) -> bool;
@@ -97,12 +108,170 @@ pub trait InstructionExt: EstimateAsm + IRDisplay {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InstructionPurity {
     Pure,
     Maybe,
     Impure,
 }
 
+/// Walks the operands, destinations, callees and jump targets of instructions and
+/// terminators, only the parts a visitor is interested in need to be implemented
+///
+/// The default [`InstructionVisitor::visit_instruction()`] and
+/// [`InstructionVisitor::visit_terminator()`] visit every part of their input, see
+/// [`walk_instruction()`] and [`walk_terminator()`]
+pub trait InstructionVisitor {
+    fn visit_instruction(&mut self, inst: &Instruction) {
+        walk_instruction(self, inst);
+    }
+
+    fn visit_terminator(&mut self, terminator: &Terminator) {
+        walk_terminator(self, terminator);
+    }
+
+    /// Visits an operand, including constant ones
+    fn visit_value(&mut self, _value: &Value) {}
+
+    /// Visits the variable an instruction defines
+    fn visit_dest(&mut self, _dest: VarId) {}
+
+    fn visit_callee(&mut self, _callee: FuncId) {}
+
+    fn visit_target(&mut self, _target: BasicBlockId) {}
+}
+
+/// Visits every operand of an instruction followed by its callee (if it's a call)
+/// and its destination
+pub fn walk_instruction<V>(visitor: &mut V, inst: &Instruction)
+where
+    V: InstructionVisitor + ?Sized,
+{
+    for value in inst.used_values() {
+        visitor.visit_value(value);
+    }
+
+    if let Instruction::Call(call) = inst {
+        visitor.visit_callee(call.func);
+    }
+    visitor.visit_dest(inst.dest());
+}
+
+/// Visits the operand of a terminator followed by its jump targets
+pub fn walk_terminator<V>(visitor: &mut V, terminator: &Terminator)
+where
+    V: InstructionVisitor + ?Sized,
+{
+    for value in terminator.used_values() {
+        visitor.visit_value(value);
+    }
+
+    for target in terminator.jump_targets() {
+        visitor.visit_target(target);
+    }
+}
+
+/// Rewrites the operands, destinations, callees and jump targets of instructions
+/// and terminators in place, every method returns `true` if it changed anything
+///
+/// The default [`InstructionRewriter::rewrite_instruction()`] and
+/// [`InstructionRewriter::rewrite_terminator()`] rewrite every part of their input,
+/// see [`rewrite_instruction()`] and [`rewrite_terminator()`]
+pub trait InstructionRewriter {
+    fn rewrite_instruction(&mut self, inst: &mut Instruction) -> bool {
+        rewrite_instruction(self, inst)
+    }
+
+    fn rewrite_terminator(&mut self, terminator: &mut Terminator) -> bool {
+        rewrite_terminator(self, terminator)
+    }
+
+    /// Rewrites an operand, including constant ones
+    fn rewrite_value(&mut self, _value: &mut Value) -> bool {
+        false
+    }
+
+    /// Rewrites the variable an instruction defines
+    fn rewrite_dest(&mut self, _dest: &mut VarId) -> bool {
+        false
+    }
+
+    fn rewrite_callee(&mut self, _callee: &mut FuncId) -> bool {
+        false
+    }
+
+    fn rewrite_target(&mut self, _target: &mut BasicBlockId) -> bool {
+        false
+    }
+}
+
+/// Rewrites every operand of an instruction followed by its callee (if it's a
+/// call) and its destination
+pub fn rewrite_instruction<R>(rewriter: &mut R, inst: &mut Instruction) -> bool
+where
+    R: InstructionRewriter + ?Sized,
+{
+    let mut changed = false;
+    for value in inst.used_values_mut() {
+        changed |= rewriter.rewrite_value(value);
+    }
+
+    let dest = match inst {
+        Instruction::Assign(assign) => &mut assign.dest,
+        Instruction::Neg(neg) => &mut neg.dest,
+        Instruction::Add(add) => &mut add.dest,
+        Instruction::Sub(sub) => &mut sub.dest,
+        Instruction::Mul(mul) => &mut mul.dest,
+        Instruction::Div(div) => &mut div.dest,
+        Instruction::Rem(rem) => &mut rem.dest,
+        Instruction::Cmp(cmp) => &mut cmp.dest,
+        Instruction::Select(select) => &mut select.dest,
+        Instruction::Bitcast(bitcast) => &mut bitcast.dest.var,
+        Instruction::Opaque(opaque) => &mut opaque.dest,
+        Instruction::Intrinsic(intrinsic) => &mut intrinsic.dest,
+        Instruction::ExtractValue(extract) => &mut extract.dest,
+        Instruction::InsertValue(insert) => &mut insert.dest,
+        Instruction::Call(call) => {
+            changed |= rewriter.rewrite_callee(&mut call.func);
+            &mut call.dest
+        }
+    };
+    changed |= rewriter.rewrite_dest(dest);
+
+    changed
+}
+
+/// Rewrites the operand of a terminator followed by its jump targets
+pub fn rewrite_terminator<R>(rewriter: &mut R, terminator: &mut Terminator) -> bool
+where
+    R: InstructionRewriter + ?Sized,
+{
+    let mut changed = false;
+    match terminator {
+        Terminator::Jump(target) => changed |= rewriter.rewrite_target(target),
+        Terminator::Branch(branch) => {
+            changed |= rewriter.rewrite_value(&mut branch.cond);
+            changed |= rewriter.rewrite_target(&mut branch.if_true.block);
+            changed |= rewriter.rewrite_target(&mut branch.if_false.block);
+        }
+        Terminator::Switch(switch) => {
+            changed |= rewriter.rewrite_value(&mut switch.scrutinee);
+            for (_, label) in switch.cases.iter_mut() {
+                changed |= rewriter.rewrite_target(&mut label.block);
+            }
+            changed |= rewriter.rewrite_target(&mut switch.default.block);
+        }
+        Terminator::Return(ret) => {
+            for value in ret.values.iter_mut() {
+                changed |= rewriter.rewrite_value(value);
+            }
+        }
+        Terminator::Unreachable | Terminator::Trap(_) => {}
+    }
+
+    changed
+}
+
 #[allow(clippy::upper_case_acronyms)]
 pub trait IRDisplay {
     fn display<'a, D, A, R>(&self, ctx: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
@@ -110,19 +279,64 @@ pub trait IRDisplay {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver;
+        R: SymbolResolver;
+
+    /// Renders the item into a string with a line width of [`PRETTY_WIDTH`]
+    fn to_pretty_string<R>(&self, interner: &R) -> String
+    where
+        R: SymbolResolver,
+    {
+        pretty_string(self, interner, None)
+    }
+
+    /// Renders the item like [`IRDisplay::to_pretty_string()`] while resolving
+    /// pooled constants into their values
+    fn to_pretty_string_with<R>(&self, interner: &R, constants: &ConstantPool) -> String
+    where
+        R: SymbolResolver,
+    {
+        pretty_string(self, interner, Some(constants))
+    }
+}
+
+fn pretty_string<T, R>(item: &T, interner: &R, constants: Option<&ConstantPool>) -> String
+where
+    T: IRDisplay + ?Sized,
+    R: SymbolResolver,
+{
+    let alloc = BoxAllocator;
+    let mut ctx = DisplayCtx::new(&alloc, interner);
+    if let Some(constants) = constants {
+        ctx = ctx.with_constants(constants);
+    }
+
+    let mut output = String::new();
+    item.display::<BoxAllocator, RefDoc, R>(ctx)
+        .1
+        .render_fmt(PRETTY_WIDTH, &mut output)
+        .expect("writing to a string can't fail");
+
+    output
 }
 
+/// The line width used when pretty printing ir
+pub const PRETTY_WIDTH: usize = 70;
+
 #[derive(Debug)]
 pub struct DisplayCtx<'a, D, A, R>
 where
     D: DocAllocator<'a, A>,
     D::Doc: Clone,
     A: Clone + 'a,
-    R: Resolver,
+    R: SymbolResolver,
 {
     pub alloc: &'a D,
     pub interner: &'a R,
+    /// Resolves pooled constants into their values, pooled constants are rendered
+    /// as their ids without it
+    pub constants: Option<&'a ConstantPool>,
+    /// Whether source spans should be rendered alongside instructions
+    pub spans: bool,
     __alloc: PhantomData<&'a A>,
 }
 
@@ -131,15 +345,27 @@ where
     D: DocAllocator<'a, A>,
     D::Doc: Clone,
     A: Clone + 'a,
-    R: Resolver,
+    R: SymbolResolver,
 {
     pub fn new(alloc: &'a D, interner: &'a R) -> Self {
         Self {
             alloc,
             interner,
+            constants: None,
+            spans: false,
             __alloc: PhantomData,
         }
     }
+
+    pub fn with_constants(mut self, constants: &'a ConstantPool) -> Self {
+        self.constants = Some(constants);
+        self
+    }
+
+    pub fn with_spans(mut self, spans: bool) -> Self {
+        self.spans = spans;
+        self
+    }
 }
 
 impl<'a, D, A, R> Deref for DisplayCtx<'a, D, A, R>
@@ -147,7 +373,7 @@ where
     D: DocAllocator<'a, A>,
     D::Doc: Clone,
     A: Clone + 'a,
-    R: Resolver,
+    R: SymbolResolver,
 {
     type Target = &'a D;
 
@@ -161,12 +387,14 @@ where
     D: DocAllocator<'a, A>,
     D::Doc: Clone,
     A: Clone + 'a,
-    R: Resolver,
+    R: SymbolResolver,
 {
     fn clone(&self) -> Self {
         Self {
             alloc: self.alloc,
             interner: self.interner,
+            constants: self.constants,
+            spans: self.spans,
             __alloc: PhantomData,
         }
     }
@@ -177,16 +405,16 @@ where
     D: DocAllocator<'a, A>,
     D::Doc: Clone,
     A: Clone + 'a,
-    R: Resolver,
+    R: SymbolResolver,
 {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(transparent)]
-pub struct Ident(crate Spur);
+pub struct Ident(pub(crate) Spur);
 
 impl Ident {
-    crate const fn new(spur: Spur) -> Self {
+    pub(crate) const fn new(spur: Spur) -> Self {
         Self(spur)
     }
 }
@@ -205,14 +433,43 @@ impl Abomonation for Ident {
     }
 }
 
+// Idents are serialized as their raw interner keys, so the interner they were
+// created with is needed to resolve them on the other side
+#[cfg(feature = "serde")]
+impl serde::Serialize for Ident {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u64(self.0.into_usize() as u64)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Ident {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let key = <u64 as serde::Deserialize>::deserialize(deserializer)?;
+
+        Spur::try_from_usize(key as usize)
+            .map(Self)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid ident key {}", key)))
+    }
+}
+
 impl IRDisplay for Ident {
     fn display<'a, D, A, R>(&self, alloc: DisplayCtx<'a, D, A, R>) -> DocBuilder<'a, D, A>
     where
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
-        alloc.text(alloc.interner.resolve(&self.0))
+        match alloc.interner.try_resolve_symbol(self.0) {
+            Some(name) => alloc.text(name),
+            None => alloc.text(alloc.interner.display_symbol(self.0).to_string()),
+        }
     }
 }
@@ -1,14 +1,18 @@
 use super::Type;
-use crate::repr::{
-    constant::Constant,
-    instruction::VarId,
-    utils::{DisplayCtx, IRDisplay},
+use crate::{
+    repr::{
+        constant::{ConstId, Constant},
+        instruction::VarId,
+        utils::{DisplayCtx, IRDisplay},
+    },
+    symbols::SymbolResolver,
 };
 use abomonation_derive::Abomonation;
-use lasso::Resolver;
 use pretty::{DocAllocator, DocBuilder};
+use std::cmp::Ordering;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Value {
     pub value: ValueKind,
     pub ty: Type,
@@ -50,6 +54,20 @@ impl Value {
         self.value.is_var()
     }
 
+    /// Creates a value that refers to a constant within the [`ConstantPool`]
+    ///
+    /// [`ConstantPool`]: crate::repr::ConstantPool
+    pub const fn pooled(id: ConstId, ty: Type) -> Self {
+        Self {
+            value: ValueKind::Pooled(id),
+            ty,
+        }
+    }
+
+    pub const fn as_pooled(&self) -> Option<ConstId> {
+        self.value.as_pooled()
+    }
+
     pub const fn as_const(&self) -> Option<&Constant> {
         self.value.as_const()
     }
@@ -73,6 +91,15 @@ impl Value {
     pub fn as_typed_var(&self) -> Option<TypedVar> {
         self.as_var().map(|var| TypedVar::new(var, self.ty.clone()))
     }
+
+    /// The order operands of commutative instructions are kept in, variables come
+    /// first in order of their ids and constants come last
+    pub fn canonical_cmp(&self, other: &Self) -> Ordering {
+        self.value
+            .canonical_rank()
+            .cmp(&other.value.canonical_rank())
+            .then_with(|| self.value.cmp(&other.value))
+    }
 }
 
 impl From<Constant> for Value {
@@ -99,7 +126,7 @@ impl IRDisplay for Value {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.ty
             .display(ctx)
@@ -110,9 +137,12 @@ impl IRDisplay for Value {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ValueKind {
     Const(Constant),
     Var(VarId),
+    /// A constant held within the [`ConstantPool`](crate::repr::ConstantPool)
+    Pooled(ConstId),
 }
 
 impl ValueKind {
@@ -155,6 +185,28 @@ impl ValueKind {
             None
         }
     }
+
+    pub const fn is_pooled(&self) -> bool {
+        matches!(self, Self::Pooled(_))
+    }
+
+    pub const fn as_pooled(&self) -> Option<ConstId> {
+        if let Self::Pooled(id) = *self {
+            Some(id)
+        } else {
+            None
+        }
+    }
+}
+
+impl ValueKind {
+    const fn canonical_rank(&self) -> u8 {
+        match self {
+            Self::Var(_) => 0,
+            Self::Pooled(_) => 1,
+            Self::Const(_) => 2,
+        }
+    }
 }
 
 impl From<Constant> for ValueKind {
@@ -175,16 +227,21 @@ impl IRDisplay for ValueKind {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         match self {
             Self::Const(constant) => constant.display(ctx),
             Self::Var(var) => var.display(ctx),
+            Self::Pooled(id) => match ctx.constants.and_then(|constants| constants.get(*id)) {
+                Some(constant) => constant.display(ctx),
+                None => id.display(ctx),
+            },
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypedVar {
     pub var: VarId,
     pub ty: Type,
@@ -202,7 +259,7 @@ impl IRDisplay for TypedVar {
         D: DocAllocator<'a, A>,
         D::Doc: Clone,
         A: Clone + 'a,
-        R: Resolver,
+        R: SymbolResolver,
     {
         self.ty
             .display(ctx)
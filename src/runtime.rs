@@ -0,0 +1,373 @@
+//! Running the optimization pipeline over multiple threads and processes
+//!
+//! A [`Runtime`] is configured with a [`RuntimeConfig`] describing how many workers
+//! each process runs and, for clusters, the addresses of every process. Every
+//! process of a cluster runs the same [`Runtime::run()`] call, inputs are fed in
+//! according to the config's [`InputDistribution`] and all results are gathered
+//! onto the sink worker (the first worker of the first process), so only the
+//! process hosting it gets any output back
+//!
+//! ```rust,ignore
+//! // Run on two processes with four workers each, started with `--process 0`
+//! // and `--process 1` respectively
+//! let hosts = vec!["10.0.0.1:2101".to_owned(), "10.0.0.2:2101".to_owned()];
+//! let config = RuntimeConfig::cluster(4, process, hosts)
+//!     .with_distribution(InputDistribution::Partitioned)
+//!     .with_partitioning(Partitioning::ByFunction);
+//!
+//! if let Some(output) = Runtime::new(context, config).run(functions, &passes)? {
+//!     // Only the process hosting the sink worker gets here
+//! }
+//! ```
+
+use crate::{
+    builder::Context,
+    dataflow::{
+        function_worker,
+        operators::{CrossbeamExtractor, CrossbeamPusher},
+        panics, Partitioning,
+    },
+    driver::{layout_functions, load_functions, load_functions_with, DriverOutput, Pass, Pipeline},
+    repr::{Function, InstId},
+};
+use differential_dataflow::operators::Consolidate;
+use std::{
+    fmt::{self, Display},
+    num::NonZeroU64,
+    sync::Arc,
+};
+use timely::{
+    dataflow::{
+        channels::pact::Exchange,
+        operators::{capture::Extract, Capture, Operator},
+    },
+    CommunicationConfig, WorkerConfig,
+};
+
+/// The index of the worker that all results are gathered onto
+pub const SINK_WORKER: usize = 0;
+
+/// How the functions given to [`Runtime::run()`] are fed into the dataflow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputDistribution {
+    /// The sink worker loads every function, only the process hosting the sink
+    /// worker needs to be given any functions
+    Sink,
+    /// Every worker loads the functions whose ids are assigned to it, every process
+    /// must be given the exact same functions
+    Partitioned,
+}
+
+impl Default for InputDistribution {
+    fn default() -> Self {
+        Self::Sink
+    }
+}
+
+/// Where and how many workers the pipeline is run on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// The number of workers within each process
+    workers: usize,
+    /// The index of the current process within `hosts`
+    process: usize,
+    /// The addresses of every process, empty when running within a single process
+    hosts: Vec<String>,
+    distribution: InputDistribution,
+    partitioning: Partitioning,
+}
+
+impl RuntimeConfig {
+    /// Runs the pipeline on a single worker within the current thread
+    pub fn thread() -> Self {
+        Self::process(1)
+    }
+
+    /// Runs the pipeline on `workers` threads within the current process
+    pub fn process(workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            process: 0,
+            hosts: Vec::new(),
+            distribution: InputDistribution::default(),
+            partitioning: Partitioning::default(),
+        }
+    }
+
+    /// Runs the pipeline over a cluster of processes that each have `workers` threads,
+    /// `process` is the index of the current process's address within `hosts`
+    pub fn cluster(workers: usize, process: usize, hosts: Vec<String>) -> Self {
+        Self {
+            workers: workers.max(1),
+            process,
+            hosts,
+            distribution: InputDistribution::default(),
+            partitioning: Partitioning::default(),
+        }
+    }
+
+    pub fn with_distribution(mut self, distribution: InputDistribution) -> Self {
+        self.distribution = distribution;
+        self
+    }
+
+    /// Sets how the program is placed onto workers while it's optimized, see
+    /// [`Pipeline::partitioning()`]
+    pub fn with_partitioning(mut self, partitioning: Partitioning) -> Self {
+        self.partitioning = partitioning;
+        self
+    }
+
+    pub const fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub const fn process(&self) -> usize {
+        self.process
+    }
+
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    pub const fn distribution(&self) -> InputDistribution {
+        self.distribution
+    }
+
+    pub const fn partitioning(&self) -> Partitioning {
+        self.partitioning
+    }
+
+    /// The total number of workers across every process
+    pub fn peers(&self) -> usize {
+        self.workers * self.hosts.len().max(1)
+    }
+
+    /// Returns `true` if the current process hosts the sink worker
+    pub const fn is_sink(&self) -> bool {
+        self.process == 0
+    }
+
+    fn validate(&self) -> Result<(), RuntimeError> {
+        if !self.hosts.is_empty() && self.process >= self.hosts.len() {
+            return Err(RuntimeError::InvalidProcess {
+                process: self.process,
+                hosts: self.hosts.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn timely_config(&self) -> timely::Config {
+        let communication = if self.hosts.len() > 1 {
+            CommunicationConfig::Cluster {
+                threads: self.workers,
+                process: self.process,
+                addresses: self.hosts.clone(),
+                report: false,
+                log_fn: Box::new(|_| None),
+            }
+        } else if self.workers > 1 {
+            CommunicationConfig::Process(self.workers)
+        } else {
+            CommunicationConfig::Thread
+        };
+
+        timely::Config {
+            communication,
+            worker: WorkerConfig::default(),
+        }
+    }
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self::thread()
+    }
+}
+
+/// Runs modules through the optimization pipeline across every worker of a
+/// [`RuntimeConfig`]
+#[derive(Debug, Clone)]
+pub struct Runtime {
+    context: Arc<Context>,
+    config: RuntimeConfig,
+}
+
+impl Runtime {
+    pub fn new(context: Arc<Context>, config: RuntimeConfig) -> Self {
+        Self { context, config }
+    }
+
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    pub fn config(&self) -> &RuntimeConfig {
+        &self.config
+    }
+
+    /// Verifies the given functions and then runs each pass over them once and in order
+    ///
+    /// Returns the transformed functions (ordered by [`layout_functions()`]) and validity
+    /// errors on the process hosting the sink worker and `None` on every other process
+    pub fn run(
+        &self,
+        functions: Vec<Function>,
+        passes: &[Pass],
+    ) -> Result<Option<DriverOutput>, RuntimeError> {
+        self.config.validate()?;
+        panics::install_hook();
+
+        let span = tracing::info_span!(
+            "runtime",
+            process = self.config.process,
+            workers = self.config.workers,
+            peers = self.config.peers(),
+        );
+        let _guard = span.enter();
+
+        let (context, passes, distribution, partitioning) = (
+            self.context.clone(),
+            passes.to_vec(),
+            self.config.distribution,
+            self.config.partitioning,
+        );
+        let functions = Arc::new(functions);
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        let guards = timely::execute(self.config.timely_config(), move |worker| {
+            let (index, peers) = (worker.index(), worker.peers());
+
+            let pipeline = passes.iter().fold(
+                Pipeline::new(context.clone())
+                    .fixpoint(false)
+                    .partitioning(partitioning),
+                |pipeline, &pass| pipeline.add_pass(pass),
+            );
+            let mut handles = pipeline.build(worker);
+
+            let (functions_trace, errors_trace, probe) = (
+                &mut handles.functions,
+                &mut handles.errors,
+                &mut handles.probe,
+            );
+            let sender = sender.clone();
+            worker.dataflow_named("runtime outputs", |scope| {
+                let functions = functions_trace
+                    .import(scope)
+                    .as_collection(|_id, function| Ok(function.clone()));
+                let errors = errors_trace
+                    .import(scope)
+                    .as_collection(|error, &()| Err(error.clone()));
+
+                // Every worker builds the same dataflow, but all results are routed
+                // to the sink so only its capture ever receives anything
+                functions
+                    .concat(&errors)
+                    .consolidate()
+                    .probe_with(probe)
+                    .inner
+                    .unary(
+                        Exchange::new(|_| SINK_WORKER as u64),
+                        "GatherToSink",
+                        |_, _| {
+                            let mut buffer = Vec::new();
+                            move |input, output| {
+                                input.for_each(|time, data| {
+                                    data.swap(&mut buffer);
+                                    output.session(&time).give_vec(&mut buffer);
+                                });
+                            }
+                        },
+                    )
+                    .capture_into(CrossbeamPusher::new(sender));
+            });
+
+            match distribution {
+                InputDistribution::Sink if index == SINK_WORKER => {
+                    load_functions(&context, &mut handles.input, functions.to_vec());
+                }
+                InputDistribution::Sink => {}
+
+                // Instruction ids are strided by worker so that they can't collide
+                // even though every process allocates them independently
+                InputDistribution::Partitioned => {
+                    let owned = functions
+                        .iter()
+                        .filter(|function| function_worker(function.id) as usize % peers == index)
+                        .cloned()
+                        .collect();
+
+                    let mut next = index as u64;
+                    load_functions_with(context.constants(), &mut handles.input, owned, || {
+                        let id = InstId::new(NonZeroU64::new(next + 1).unwrap());
+                        next += peers as u64;
+                        id
+                    });
+                }
+            }
+
+            handles.advance_to(1);
+            handles.step_until_complete(worker);
+        })
+        .map_err(RuntimeError::Communication)?;
+
+        let panicked = guards
+            .join()
+            .into_iter()
+            .filter(|result| result.is_err())
+            .count();
+        if panicked != 0 {
+            return Err(RuntimeError::WorkerPanicked { workers: panicked });
+        }
+
+        if !self.config.is_sink() {
+            return Ok(None);
+        }
+
+        let mut output = DriverOutput::default();
+        for (_time, data) in CrossbeamExtractor::new(receiver).extract() {
+            for (data, _time, diff) in data {
+                for _ in 0..diff {
+                    match data.clone() {
+                        Ok(function) => output.functions.push(function),
+                        Err(error) => output.errors.push(error),
+                    }
+                }
+            }
+        }
+        layout_functions(&mut output.functions);
+        output.errors.sort();
+
+        Ok(Some(output))
+    }
+}
+
+/// An error that prevented the runtime from running the pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// The process index isn't within the list of hosts
+    InvalidProcess { process: usize, hosts: usize },
+    /// The workers couldn't connect to each other
+    Communication(String),
+    /// Some of the current process's workers panicked
+    WorkerPanicked { workers: usize },
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidProcess { process, hosts } => write!(
+                f,
+                "process {} doesn't exist within a cluster of {} hosts",
+                process, hosts,
+            ),
+            Self::Communication(err) => write!(f, "failed to start the workers: {}", err),
+            Self::WorkerPanicked { workers } => write!(f, "{} workers panicked", workers),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
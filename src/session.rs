@@ -0,0 +1,295 @@
+//! Long running sessions that incrementally optimize a changing program
+//!
+//! A [`Session`] keeps a [`Pipeline`] alive on its own worker thread across any
+//! number of epochs. Clients update and remove functions, advance the session to
+//! the next epoch and receive only the optimized functions that changed within it,
+//! which makes the pipeline usable as an incremental compiler server
+//!
+//! ```rust,ignore
+//! let mut session = Session::new(Pipeline::new(context).add_pass(Pass::Cleanup));
+//!
+//! session.update(functions);
+//! session.advance();
+//! let update = session.next_update().unwrap();
+//!
+//! // Only the edited function is sent for the next epoch
+//! session.update(vec![edited]);
+//! session.advance();
+//! let update = session.next_update().unwrap();
+//! ```
+
+use crate::{
+    dataflow::{
+        panics::{self, PanicContext, PanicDiagnostic},
+        trace_changes, Time,
+    },
+    driver::{layout_functions, LoadedFunction, Pipeline, PipelineHandles},
+    repr::{diff::FunctionDiff, utils::IRDisplay, FuncId, Function},
+    symbols::SymbolResolver,
+    verify::ValidityError,
+};
+use crossbeam_channel::{Receiver, Sender};
+use std::{
+    collections::HashMap,
+    thread::{self, JoinHandle},
+};
+use timely::{communication::Allocate, worker::Worker};
+
+/// The changes made to the optimized program within a single epoch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochUpdate {
+    /// The epoch the session was advanced to
+    pub epoch: Time,
+    /// Every optimized function that was added or changed
+    pub functions: Vec<Function>,
+    /// The versions of the changed functions from before the epoch
+    pub previous: Vec<Function>,
+    /// The functions that were removed
+    pub removed: Vec<FuncId>,
+    /// The validity errors that were introduced into the input program
+    pub errors: Vec<ValidityError>,
+}
+
+impl EpochUpdate {
+    /// Returns `true` if nothing changed within the epoch
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty() && self.removed.is_empty() && self.errors.is_empty()
+    }
+
+    /// Diffs every changed function against its version from before the epoch,
+    /// functions that were newly added are diffed against nothing
+    pub fn diffs<R>(&self, interner: &R) -> Vec<(FuncId, FunctionDiff)>
+    where
+        R: SymbolResolver,
+    {
+        self.functions
+            .iter()
+            .map(|function| {
+                let previous = self
+                    .previous
+                    .iter()
+                    .find(|previous| previous.id == function.id)
+                    .map(|previous| previous.to_pretty_string(interner))
+                    .unwrap_or_default();
+
+                let diff = FunctionDiff::new(&previous, &function.to_pretty_string(interner));
+                (function.id, diff)
+            })
+            .collect()
+    }
+}
+
+enum Command {
+    Update(Vec<Function>),
+    Remove(Vec<FuncId>),
+    Advance,
+}
+
+/// A pipeline kept running on a dedicated worker thread, see the
+/// [module level docs](self)
+///
+/// Dropping the session shuts down its worker, [`Session::finish()`] does the
+/// same while also reporting whether the worker panicked
+pub struct Session {
+    commands: Option<Sender<Command>>,
+    updates: Receiver<EpochUpdate>,
+    worker: Option<JoinHandle<Result<(), PanicDiagnostic>>>,
+    epoch: Time,
+}
+
+impl Session {
+    /// Builds the pipeline on a new worker thread
+    pub fn new(pipeline: Pipeline) -> Self {
+        let (commands, command_receiver) = crossbeam_channel::unbounded();
+        let (update_sender, updates) = crossbeam_channel::unbounded();
+
+        let worker = thread::Builder::new()
+            .name("sruth session".to_owned())
+            .spawn(move || run_session(pipeline, command_receiver, update_sender))
+            .expect("failed to spawn the session's worker thread");
+
+        Self {
+            commands: Some(commands),
+            updates,
+            worker: Some(worker),
+            epoch: 0,
+        }
+    }
+
+    /// The epoch the session was last advanced to
+    pub const fn epoch(&self) -> Time {
+        self.epoch
+    }
+
+    /// Gives functions to the session, replacing any previous versions of them
+    ///
+    /// Updates only become visible once the session is [advanced](Session::advance)
+    pub fn update(&self, functions: Vec<Function>) {
+        self.send(Command::Update(functions));
+    }
+
+    /// Removes the functions with the given ids from the session, ids that were
+    /// never given to the session are ignored
+    pub fn remove(&self, functions: Vec<FuncId>) {
+        self.send(Command::Remove(functions));
+    }
+
+    /// Advances the session to the next epoch, returning it
+    ///
+    /// The changes made within the epoch are sent to [`Session::updates()`] once the
+    /// pipeline has processed them
+    pub fn advance(&mut self) -> Time {
+        self.send(Command::Advance);
+        self.epoch += 1;
+        self.epoch
+    }
+
+    /// The subscription channel receiving the changes made within every epoch, in
+    /// the order the session was advanced in
+    pub fn updates(&self) -> &Receiver<EpochUpdate> {
+        &self.updates
+    }
+
+    /// Waits for the changes made within the next epoch, returns `None` if the
+    /// worker stopped before sending them
+    pub fn next_update(&self) -> Option<EpochUpdate> {
+        self.updates.recv().ok()
+    }
+
+    /// Shuts down the session's worker once it finishes processing every
+    /// outstanding command, returning the panic that stopped it if it panicked
+    pub fn finish(mut self) -> Result<(), PanicDiagnostic> {
+        self.shutdown()
+    }
+
+    fn send(&self, command: Command) {
+        let commands = self
+            .commands
+            .as_ref()
+            .expect("the session has already been shut down");
+
+        // The worker only hangs up if it panicked, which is reported by `finish()`
+        if commands.send(command).is_err() {
+            tracing::error!("sent a command to a session whose worker has stopped");
+        }
+    }
+
+    fn shutdown(&mut self) -> Result<(), PanicDiagnostic> {
+        // Hanging up stops the worker once it runs out of commands
+        self.commands.take();
+
+        match self.worker.take() {
+            Some(worker) => worker
+                .join()
+                .expect("session workers catch their own panics"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Err(diagnostic) = self.shutdown() {
+            tracing::error!("the session's worker {}", diagnostic);
+        }
+    }
+}
+
+fn run_session(
+    pipeline: Pipeline,
+    commands: Receiver<Command>,
+    updates: Sender<EpochUpdate>,
+) -> Result<(), PanicDiagnostic> {
+    panics::install_hook();
+
+    panics::catch(PanicContext::stage("running a session"), move || {
+        timely::execute_directly(move |worker| {
+            let context = pipeline.context().clone();
+            let mut handles = pipeline.build(worker);
+            let mut loaded: HashMap<FuncId, LoadedFunction> = HashMap::new();
+
+            for command in commands.iter() {
+                match command {
+                    Command::Update(functions) => {
+                        for function in functions {
+                            let function = LoadedFunction::new(&context, function);
+                            if let Some(previous) = loaded.remove(&function.id()) {
+                                previous.retract(&mut handles.input);
+                            }
+
+                            function.insert(&mut handles.input);
+                            loaded.insert(function.id(), function);
+                        }
+                    }
+
+                    Command::Remove(functions) => {
+                        for id in functions {
+                            if let Some(previous) = loaded.remove(&id) {
+                                previous.retract(&mut handles.input);
+                            }
+                        }
+                    }
+
+                    Command::Advance => {
+                        let update = advance(worker, &mut handles);
+
+                        // The client may have stopped listening while still sending
+                        // commands, which isn't an error
+                        let _ = updates.send(update);
+                    }
+                }
+            }
+        })
+    })
+}
+
+/// Advances the pipeline by a single epoch and collects the changes made within it
+fn advance<A>(worker: &mut Worker<A>, handles: &mut PipelineHandles) -> EpochUpdate
+where
+    A: Allocate,
+{
+    let (from, to) = (handles.time(), handles.time() + 1);
+    handles.advance_to(to);
+    handles.step_until_complete(worker);
+
+    let functions =
+        trace_changes(&mut handles.functions, &from, &to).expect("the epoch was completed");
+    let errors = trace_changes(&mut handles.errors, &from, &to).expect("the epoch was completed");
+
+    let mut update = EpochUpdate {
+        epoch: to,
+        functions: functions
+            .added
+            .into_iter()
+            .map(|(_, function)| function)
+            .collect(),
+        previous: functions
+            .removed
+            .into_iter()
+            .map(|(_, function)| function)
+            .collect(),
+        removed: Vec::new(),
+        errors: errors.added.into_iter().map(|(error, ())| error).collect(),
+    };
+
+    // A changed function is removed and added, so it's only reported as changed
+    let changed: Vec<FuncId> = update
+        .functions
+        .iter()
+        .map(|function| function.id)
+        .collect();
+    let (previous, removed): (Vec<_>, Vec<_>) = update
+        .previous
+        .drain(..)
+        .partition(|function| changed.contains(&function.id));
+    update.previous = previous;
+    update.removed = removed.into_iter().map(|function| function.id).collect();
+    update.removed.dedup();
+    layout_functions(&mut update.functions);
+
+    // Only the epoch that was just completed is read again, so everything before
+    // it can be compacted away
+    handles.compact(worker, from);
+
+    update
+}
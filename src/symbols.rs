@@ -0,0 +1,463 @@
+//! Resolution of interned symbols back into human readable names
+//!
+//! Anything that renders [`Ident`]s or other interned keys (pretty printing,
+//! error reporting and logging) goes through a [`SymbolResolver`] instead of
+//! borrowing the [`Context`]'s interner directly, so read-only snapshots of an
+//! interner or resolvers that know additional names can be used in its place
+//!
+//! The [`Context`]'s interner only lives within a single process, so workers of
+//! other processes resolve names through an [`InternerSnapshot`] that's kept up to
+//! date by broadcasting [`SnapshotUpdate`]s with [`sync_snapshot()`]
+//!
+//! ```rust,ignore
+//! let mut snapshot = context.interner_snapshot();
+//! let (mut updates, synced) = worker.dataflow(|scope| {
+//!     let (input, updates) = scope.new_input();
+//!     (input, sync_snapshot(&updates))
+//! });
+//!
+//! // Only the worker holding the context publishes new strings
+//! if worker.index() == 0 {
+//!     updates.send(snapshot.update(context.interner()));
+//! }
+//! ```
+
+use crate::{
+    builder::Context,
+    repr::{BasicBlockId, FuncId, Function, Ident, InstructionExt, VarId},
+};
+use abomonation_derive::Abomonation;
+use fxhash::FxHashMap;
+use lasso::{Key, Resolver, Rodeo, RodeoReader, RodeoResolver, Spur, ThreadedRodeo};
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    rc::Rc,
+    sync::Arc,
+};
+use thiserror::Error;
+use timely::dataflow::{
+    operators::{Broadcast, Inspect},
+    Scope, Stream,
+};
+
+pub trait SymbolResolver {
+    /// Returns the string `symbol` was interned from, or `None` if it's unknown
+    /// to the resolver
+    fn try_resolve_symbol(&self, symbol: Spur) -> Option<&str>;
+
+    /// Returns the name of the function with the given id if the resolver knows it
+    fn function_name(&self, _func: FuncId) -> Option<Ident> {
+        None
+    }
+
+    /// Returns the name of the block with the given id if the resolver knows it
+    fn block_name(&self, _block: BasicBlockId) -> Option<Ident> {
+        None
+    }
+
+    /// Returns the name of the variable with the given id if the resolver knows it
+    fn variable_name(&self, _var: VarId) -> Option<Ident> {
+        None
+    }
+
+    /// Displays `symbol` as the string it was interned from, falling back to its
+    /// raw key if it can't be resolved
+    fn display_symbol(&self, symbol: Spur) -> DisplaySymbol<'_, Self>
+    where
+        Self: Sized,
+    {
+        DisplaySymbol {
+            resolver: self,
+            symbol,
+        }
+    }
+}
+
+impl<R> SymbolResolver for &R
+where
+    R: SymbolResolver + ?Sized,
+{
+    fn try_resolve_symbol(&self, symbol: Spur) -> Option<&str> {
+        (**self).try_resolve_symbol(symbol)
+    }
+
+    fn function_name(&self, func: FuncId) -> Option<Ident> {
+        (**self).function_name(func)
+    }
+
+    fn block_name(&self, block: BasicBlockId) -> Option<Ident> {
+        (**self).block_name(block)
+    }
+
+    fn variable_name(&self, var: VarId) -> Option<Ident> {
+        (**self).variable_name(var)
+    }
+}
+
+impl<R> SymbolResolver for Arc<R>
+where
+    R: SymbolResolver + ?Sized,
+{
+    fn try_resolve_symbol(&self, symbol: Spur) -> Option<&str> {
+        (**self).try_resolve_symbol(symbol)
+    }
+
+    fn function_name(&self, func: FuncId) -> Option<Ident> {
+        (**self).function_name(func)
+    }
+
+    fn block_name(&self, block: BasicBlockId) -> Option<Ident> {
+        (**self).block_name(block)
+    }
+
+    fn variable_name(&self, var: VarId) -> Option<Ident> {
+        (**self).variable_name(var)
+    }
+}
+
+/// A resolver that doesn't know any symbols, for rendering ir where no interner is
+/// at hand. Every symbol is displayed as its raw key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct NoSymbols;
+
+impl SymbolResolver for NoSymbols {
+    fn try_resolve_symbol(&self, _symbol: Spur) -> Option<&str> {
+        None
+    }
+}
+
+impl SymbolResolver for Context {
+    fn try_resolve_symbol(&self, symbol: Spur) -> Option<&str> {
+        self.interner().try_resolve(&symbol)
+    }
+}
+
+macro_rules! impl_for_interners {
+    ($($interner:ident $(<$hasher:ident>)?),* $(,)?) => {
+        $(
+            impl$(<$hasher>)? SymbolResolver for $interner<Spur $(, $hasher)?>
+            where
+                Self: Resolver<Spur>,
+            {
+                fn try_resolve_symbol(&self, symbol: Spur) -> Option<&str> {
+                    self.try_resolve(&symbol)
+                }
+            }
+        )*
+    };
+}
+
+impl_for_interners!(ThreadedRodeo<S>, Rodeo<S>, RodeoReader<S>, RodeoResolver);
+
+/// Resolves symbols through another resolver while also knowing the names of a
+/// set of functions along with the names of their blocks and variables, so that
+/// they can be referred to by name wherever only their id is at hand
+#[derive(Debug, Clone)]
+pub struct FunctionNames<R> {
+    resolver: R,
+    names: FxHashMap<FuncId, Ident>,
+    blocks: FxHashMap<BasicBlockId, Ident>,
+    variables: FxHashMap<VarId, Ident>,
+}
+
+impl<R> FunctionNames<R>
+where
+    R: SymbolResolver,
+{
+    pub fn new<'a, I>(resolver: R, functions: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Function>,
+    {
+        let (mut names, mut blocks, mut variables) = (
+            FxHashMap::default(),
+            FxHashMap::default(),
+            FxHashMap::default(),
+        );
+
+        for function in functions {
+            if let Some(name) = function.name {
+                names.insert(function.id, name);
+            }
+
+            for block in &function.basic_blocks {
+                if let Some(name) = block.name {
+                    blocks.insert(block.id, name);
+                }
+
+                variables.extend(
+                    block
+                        .instructions
+                        .iter()
+                        .filter_map(|inst| Some((inst.dest(), inst.name()?))),
+                );
+            }
+        }
+
+        Self {
+            resolver,
+            names,
+            blocks,
+            variables,
+        }
+    }
+
+    pub fn resolver(&self) -> &R {
+        &self.resolver
+    }
+}
+
+impl<R> SymbolResolver for FunctionNames<R>
+where
+    R: SymbolResolver,
+{
+    fn try_resolve_symbol(&self, symbol: Spur) -> Option<&str> {
+        self.resolver.try_resolve_symbol(symbol)
+    }
+
+    fn function_name(&self, func: FuncId) -> Option<Ident> {
+        self.names
+            .get(&func)
+            .copied()
+            .or_else(|| self.resolver.function_name(func))
+    }
+
+    fn block_name(&self, block: BasicBlockId) -> Option<Ident> {
+        self.blocks
+            .get(&block)
+            .copied()
+            .or_else(|| self.resolver.block_name(block))
+    }
+
+    fn variable_name(&self, var: VarId) -> Option<Ident> {
+        self.variables
+            .get(&var)
+            .copied()
+            .or_else(|| self.resolver.variable_name(var))
+    }
+}
+
+/// Displays a symbol through a [`SymbolResolver`], created by
+/// [`SymbolResolver::display_symbol()`]
+#[derive(Debug, Clone, Copy)]
+pub struct DisplaySymbol<'a, R> {
+    resolver: &'a R,
+    symbol: Spur,
+}
+
+impl<R> Display for DisplaySymbol<'_, R>
+where
+    R: SymbolResolver,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.resolver.try_resolve_symbol(self.symbol) {
+            Some(string) => f.write_str(string),
+            None => write!(f, "<symbol {}>", self.symbol.into_usize()),
+        }
+    }
+}
+
+macro_rules! symbol_names {
+    ($($(#[$meta:meta])* $name:ident($id:ident) => $lookup:ident),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            #[derive(Clone, Copy)]
+            pub struct $name<'a> {
+                id: $id,
+                resolver: Option<&'a dyn SymbolResolver>,
+            }
+
+            impl<'a> $name<'a> {
+                pub fn new(id: $id, resolver: Option<&'a dyn SymbolResolver>) -> Self {
+                    Self { id, resolver }
+                }
+            }
+
+            impl Display for $name<'_> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    let name = self.resolver.and_then(|resolver| {
+                        resolver
+                            .$lookup(self.id)
+                            .and_then(|name| resolver.try_resolve_symbol(name.0))
+                    });
+
+                    match name {
+                        Some(name) => write!(f, "`{}`", name),
+                        None => write!(f, "{:?}", self.id),
+                    }
+                }
+            }
+
+            impl fmt::Debug for $name<'_> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    Display::fmt(self, f)
+                }
+            }
+        )*
+    };
+}
+
+symbol_names! {
+    /// A function that's displayed by its name when the resolver knows it, falling
+    /// back to its id
+    FunctionName(FuncId) => function_name,
+    /// A block that's displayed by its name when the resolver knows it, falling
+    /// back to its id
+    BlockName(BasicBlockId) => block_name,
+    /// A variable that's displayed by its name when the resolver knows it, falling
+    /// back to its id
+    VariableName(VarId) => variable_name,
+}
+
+/// A frozen copy of an interner's strings that can be shared between threads and
+/// sent to other processes, each string's index is its interner key
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InternerSnapshot {
+    strings: Vec<String>,
+}
+
+impl InternerSnapshot {
+    pub fn new(interner: &ThreadedRodeo) -> Self {
+        let mut snapshot = Self::default();
+        snapshot.update(interner);
+
+        snapshot
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Returns every string within the snapshot in the order of their keys
+    pub fn strings(&self) -> &[String] {
+        &self.strings
+    }
+
+    /// Adds every string interned since the snapshot was taken or last updated,
+    /// returning the added strings as an update that brings other copies of the
+    /// snapshot up to date
+    ///
+    /// Strings are added in the order of their keys and stop at the first key that
+    /// isn't resolvable yet, strings that are concurrently being interned are
+    /// picked up by a later update
+    pub fn update(&mut self, interner: &ThreadedRodeo) -> SnapshotUpdate {
+        let mut added: Vec<_> = interner
+            .iter()
+            .filter(|(key, _)| key.into_usize() >= self.strings.len())
+            .collect();
+        added.sort_by_key(|&(key, _)| key.into_usize());
+
+        let start = self.strings.len();
+        for (key, string) in added {
+            if key.into_usize() != self.strings.len() {
+                break;
+            }
+
+            self.strings.push(string.to_owned());
+        }
+
+        SnapshotUpdate {
+            start,
+            strings: self.strings[start..].to_vec(),
+        }
+    }
+
+    /// Applies an update made to another copy of the snapshot, strings the
+    /// snapshot already holds are skipped so updates can be applied more than once
+    pub fn apply(&mut self, update: &SnapshotUpdate) -> Result<(), SnapshotError> {
+        if update.start > self.strings.len() {
+            return Err(SnapshotError::MissingStrings {
+                len: self.strings.len(),
+                start: update.start,
+            });
+        }
+
+        for (idx, string) in (update.start..).zip(&update.strings) {
+            match self.strings.get(idx) {
+                Some(existing) if existing != string => {
+                    return Err(SnapshotError::ConflictingString {
+                        key: idx,
+                        existing: existing.clone(),
+                        update: string.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => self.strings.push(string.clone()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SymbolResolver for InternerSnapshot {
+    fn try_resolve_symbol(&self, symbol: Spur) -> Option<&str> {
+        self.strings.get(symbol.into_usize()).map(String::as_str)
+    }
+}
+
+/// The strings added to an [`InternerSnapshot`] by a single
+/// [update](InternerSnapshot::update)
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotUpdate {
+    /// The key of the first added string
+    pub start: usize,
+    pub strings: Vec<String>,
+}
+
+impl SnapshotUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Error)]
+pub enum SnapshotError {
+    #[error("an update starting at key {start} can't be applied to a snapshot of {len} strings")]
+    MissingStrings { len: usize, start: usize },
+
+    #[error("the key {key} is {existing:?} within the snapshot but {update:?} within the update")]
+    ConflictingString {
+        key: usize,
+        existing: String,
+        update: String,
+    },
+}
+
+/// Broadcasts `updates` to every worker and applies them to a snapshot local to
+/// the worker, so that names resolve identically on all workers of all processes
+///
+/// Updates may arrive in any order, ones that skip past the end of the snapshot
+/// are held back until the updates before them have been applied
+pub fn sync_snapshot<S>(updates: &Stream<S, SnapshotUpdate>) -> Rc<RefCell<InternerSnapshot>>
+where
+    S: Scope,
+{
+    let snapshot = Rc::new(RefCell::new(InternerSnapshot::default()));
+    let mut pending: Vec<SnapshotUpdate> = Vec::new();
+
+    let synced = snapshot.clone();
+    updates.broadcast().inspect(move |update| {
+        pending.push(update.clone());
+        pending.sort_by_key(|update| update.start);
+
+        let mut snapshot = synced.borrow_mut();
+        while pending
+            .first()
+            .map_or(false, |update| update.start <= snapshot.len())
+        {
+            let update = pending.remove(0);
+            if let Err(error) = snapshot.apply(&update) {
+                tracing::error!("failed to apply an interner snapshot update: {}", error);
+            }
+        }
+    });
+
+    snapshot
+}
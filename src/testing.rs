@@ -0,0 +1,217 @@
+//! Support for writing snapshot tests of passes
+//!
+//! A [`PassTest`] builds its input, runs it through a [`Driver`] with the requested
+//! passes and renders the optimized functions into a canonical [`Snapshot`], so a
+//! test only has to describe the program and the output it expects
+//!
+//! ```rust,ignore
+//! let snapshot = PassTest::named(&["constant-folding", "cleanup"])
+//!     .build(|builder| {
+//!         builder.named_function("main", Type::Int, |func| {
+//!             func.basic_block(|block| {
+//!                 let one = block.assign(Constant::Int(1));
+//!                 let sum = block.add(one, Constant::Int(2))?;
+//!                 block.ret(sum)?;
+//!
+//!                 Ok(())
+//!             })?;
+//!
+//!             Ok(())
+//!         })?;
+//!
+//!         Ok(())
+//!     })
+//!     .run();
+//!
+//! snapshot.assert_matches(
+//!     r#"
+//!     ; entry: bb0
+//!     def main() -> int {
+//!       ...
+//!     }
+//!     "#,
+//! );
+//! ```
+
+use crate::{
+    builder::{BuildResult, Builder, Context},
+    driver::{Driver, Pass},
+    repr::{diff::FunctionDiff, utils::IRDisplay, Function},
+    verify::ValidityError,
+};
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
+};
+
+/// A program along with the passes it should be optimized by
+#[derive(Debug, Clone)]
+pub struct PassTest {
+    context: Arc<Context>,
+    passes: Vec<Pass>,
+    functions: Vec<Function>,
+    fuel: Option<usize>,
+}
+
+impl PassTest {
+    pub fn new(passes: &[Pass]) -> Self {
+        Self {
+            context: Arc::new(Context::new(0)),
+            passes: passes.to_vec(),
+            functions: Vec::new(),
+            fuel: None,
+        }
+    }
+
+    /// Creates a test that runs the passes with the given [names](Pass::name)
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the names don't belong to a registered pass
+    pub fn named(passes: &[&str]) -> Self {
+        let passes: Vec<Pass> = passes
+            .iter()
+            .map(|&name| Pass::from_name(name).unwrap_or_else(|| panic!("unknown pass {:?}", name)))
+            .collect();
+
+        Self::new(&passes)
+    }
+
+    /// The context the input is built within, functions given to
+    /// [`PassTest::with_functions()`] must have been built within it
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    /// Limits the rewrites the passes can make, see [`Driver::with_fuel()`]
+    pub fn with_fuel(mut self, fuel: Option<usize>) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    /// Adds already built functions to the input
+    pub fn with_functions(mut self, functions: Vec<Function>) -> Self {
+        self.functions.extend(functions);
+        self
+    }
+
+    /// Builds functions into the input
+    ///
+    /// # Panics
+    ///
+    /// Panics if building the functions fails
+    pub fn build<F>(mut self, build: F) -> Self
+    where
+        F: FnOnce(&mut Builder) -> BuildResult<()>,
+    {
+        let mut builder = self.context.builder();
+        if let Err(err) = build(&mut builder) {
+            builder.discard();
+            panic!("failed to build the test input: {:?}", err);
+        }
+
+        self.functions.extend(builder.materialize());
+        builder.discard();
+
+        self
+    }
+
+    /// Runs the passes over the input
+    ///
+    /// # Panics
+    ///
+    /// Panics if the optimized program is invalid
+    pub fn run(self) -> Snapshot {
+        let snapshot = self.try_run();
+        assert!(
+            snapshot.errors.is_empty(),
+            "the optimized program is invalid: {:?}\n{}",
+            snapshot.errors,
+            snapshot,
+        );
+
+        snapshot
+    }
+
+    /// Runs the passes over the input, keeping any validity errors of the optimized
+    /// program within the snapshot
+    pub fn try_run(self) -> Snapshot {
+        let output = Driver::new(self.context.clone())
+            .with_fuel(self.fuel)
+            .run(self.functions, &self.passes);
+
+        let text = output
+            .functions
+            .iter()
+            .map(|function| function.to_pretty_string(self.context.interner()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        Snapshot {
+            functions: output.functions,
+            errors: output.errors,
+            text: normalize(&text),
+        }
+    }
+}
+
+/// The optimized functions of a [`PassTest`] and their rendered form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub functions: Vec<Function>,
+    pub errors: Vec<ValidityError>,
+    text: String,
+}
+
+impl Snapshot {
+    /// The optimized functions pretty printed in their output order
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Asserts that the rendered functions match `expected`
+    ///
+    /// Both are compared after trailing whitespace, surrounding blank lines and the
+    /// indentation shared by every line are removed, so the expected output can be
+    /// written as an indented raw string
+    pub fn assert_matches(&self, expected: &str) {
+        let expected = normalize(expected);
+        assert!(
+            self.text == expected,
+            "snapshot mismatch\n--- expected\n{}\n--- actual\n{}\n--- diff\n{}",
+            expected,
+            self.text,
+            FunctionDiff::new(&expected, &self.text),
+        );
+    }
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Strips trailing whitespace, surrounding blank lines and shared indentation
+fn normalize(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(0);
+    let end = lines
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(start, |end| end + 1);
+    let lines = &lines[start..end];
+
+    let indent = lines
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
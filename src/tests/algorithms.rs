@@ -0,0 +1,148 @@
+use crate::dataflow::{
+    algorithms::{
+        propagate::least_label_propagation,
+        reachable::{reachable, reachable_from, reachable_present},
+        scc::{components, cyclic_nodes},
+    },
+    operators::{Present, PresentExt, Reverse},
+};
+use differential_dataflow::{
+    input::Input,
+    operators::{Consolidate, Join},
+};
+use std::{
+    mem,
+    sync::{Arc, Mutex},
+};
+
+const CYCLIC_EDGES: &[(u32, u32)] = &[(1, 2), (2, 3), (3, 1), (4, 3)];
+
+#[test]
+fn least_labels() {
+    timely::execute_directly(|worker| {
+        worker.dataflow::<usize, _, _>(|scope| {
+            let (_edges, edges) = scope.new_collection_from(CYCLIC_EDGES.iter().copied());
+            let (_labels, labels) = scope.new_collection_from(vec![(1, 5), (4, 10)]);
+            let (_expected, expected) =
+                scope.new_collection_from(vec![(1, 5), (2, 5), (3, 5), (4, 10)]);
+
+            least_label_propagation(&edges, &labels).assert_eq(&expected);
+        });
+    });
+}
+
+#[test]
+fn reachable_from_roots() {
+    timely::execute_directly(|worker| {
+        worker.dataflow::<usize, _, _>(|scope| {
+            let (_edges, edges) = scope.new_collection_from(CYCLIC_EDGES.iter().copied());
+            let (_roots, roots) = scope.new_collection_from(vec![(1, 1), (4, 4)]);
+            let (_expected, expected) = scope.new_collection_from(vec![
+                (1, 1),
+                (1, 4),
+                (2, 1),
+                (2, 4),
+                (3, 1),
+                (3, 4),
+                (4, 4),
+            ]);
+
+            reachable_from(&edges, &roots).assert_eq(&expected);
+        });
+    });
+}
+
+#[test]
+fn strongly_connected_components() {
+    timely::execute_directly(|worker| {
+        worker.dataflow::<usize, _, _>(|scope| {
+            // A self loop is a cycle of its own, a lone edge isn't part of any
+            let (_edges, edges) =
+                scope.new_collection_from(CYCLIC_EDGES.iter().copied().chain(vec![(5, 5), (6, 7)]));
+            let (_cyclic, cyclic) = scope.new_collection_from(vec![1, 2, 3, 5]);
+            let (_components, expected) = scope.new_collection_from(vec![
+                (1, 1),
+                (2, 1),
+                (3, 1),
+                (4, 4),
+                (5, 5),
+                (6, 6),
+                (7, 7),
+            ]);
+
+            cyclic_nodes(&edges).assert_eq(&cyclic);
+            components(&edges).assert_eq(&expected);
+        });
+    });
+}
+
+/// Every node of a complete graph is reachable along an exponential number of paths,
+/// so any differences that aren't normalized would quickly blow up
+#[test]
+fn propagation_differences_are_normalized() {
+    const NODES: u32 = 32;
+
+    timely::execute_directly(|worker| {
+        worker.dataflow::<usize, _, _>(|scope| {
+            let edges = (0..NODES).flat_map(|src| (0..NODES).map(move |dest| (src, dest)));
+            let (_edges, edges) = scope.new_collection_from(edges);
+            let (_roots, roots) = scope.new_collection_from(vec![(0, 0)]);
+            let (_expected, expected) = scope.new_collection_from((0..NODES).map(|node| (node, 0)));
+
+            least_label_propagation(&edges, &roots).assert_eq(&expected);
+            reachable_from(&edges, &roots).assert_eq(&expected);
+        });
+    });
+}
+
+#[test]
+fn present_collections_keep_set_semantics() {
+    assert_eq!(mem::size_of::<Present>(), 0);
+
+    let paths = Arc::new(Mutex::new(Vec::new()));
+    let captured = paths.clone();
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<usize, _, _>(|scope| {
+            let (_edges, edges) = scope.new_collection_from(CYCLIC_EDGES.iter().copied());
+
+            // Duplicated edges would double the paths they're part of if the
+            // multiplicities were kept around
+            let edges = edges.concat(&edges).present();
+            edges
+                .reverse()
+                .join(&edges)
+                .map(|(_middle, path)| path)
+                .consolidate()
+                .inspect(move |&(path, _, Present)| captured.lock().unwrap().push(path));
+        });
+    });
+
+    let mut paths = paths.lock().unwrap().clone();
+    paths.sort_unstable();
+    assert_eq!(paths, vec![(1, 3), (2, 1), (3, 2), (4, 1)]);
+}
+
+#[test]
+fn present_reachability_matches_reachability() {
+    let reached = Arc::new(Mutex::new(Vec::new()));
+    let captured = reached.clone();
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<usize, _, _>(|scope| {
+            let (_edges, edges) = scope.new_collection_from(CYCLIC_EDGES.iter().copied());
+            let (_roots, roots) = scope.new_collection_from(vec![1, 1]);
+
+            // The duplicated root doesn't change anything about what can be reached
+            reachable_present(&edges.present(), &roots.present())
+                .inspect(move |&(node, _, Present)| captured.lock().unwrap().push(node));
+
+            // Unlike the present version, reachability collapses the multiplicity of
+            // every node it reaches
+            let (_expected, expected) = scope.new_collection_from(vec![1, 2, 3]);
+            reachable(&edges, &roots).assert_eq(&expected);
+        });
+    });
+
+    let mut reached = reached.lock().unwrap().clone();
+    reached.sort_unstable();
+    assert_eq!(reached, vec![1, 2, 3]);
+}
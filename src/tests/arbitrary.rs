@@ -0,0 +1,53 @@
+use crate::{
+    builder::Context,
+    driver::{Driver, Pass},
+    repr::arbitrary::{ArbitraryConfig, IrGenerator},
+};
+use std::sync::Arc;
+
+#[test]
+fn generated_modules_are_reproducible() {
+    for seed in 1..=8 {
+        let first = IrGenerator::new(seed).functions(&Arc::new(Context::new(0)));
+        let second = IrGenerator::new(seed).functions(&Arc::new(Context::new(0)));
+
+        assert_eq!(
+            first, second,
+            "seed {} generated two different modules",
+            seed
+        );
+    }
+}
+
+#[test]
+fn generated_modules_survive_the_pipeline() {
+    let config = ArbitraryConfig {
+        max_functions: 3,
+        ..ArbitraryConfig::default()
+    };
+
+    for seed in 1..=16 {
+        let context = Arc::new(Context::new(0));
+        let functions = IrGenerator::new(seed)
+            .with_config(config)
+            .functions(&context);
+        let driver = Driver::new(context);
+
+        let output = driver.run(functions, Pass::ALL);
+        assert!(
+            output.errors.is_empty(),
+            "seed {} generated an invalid module: {:?}",
+            seed,
+            output.errors,
+        );
+
+        // The optimized module has to be just as valid as the one that went in
+        let reverified = driver.run(output.functions, &[]);
+        assert!(
+            reverified.errors.is_empty(),
+            "optimizing the module from seed {} made it invalid: {:?}",
+            seed,
+            reverified.errors,
+        );
+    }
+}
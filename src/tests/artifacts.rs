@@ -0,0 +1,54 @@
+use crate::artifacts::{sanitize_name, ArtifactSink, DirectorySink, NullSink};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+#[test]
+fn sanitized_names_are_valid_file_names() {
+    assert_eq!(sanitize_name("main"), "main");
+    assert_eq!(sanitize_name("ops::add<i32>"), "ops__add_i32_");
+    assert_eq!(sanitize_name("a/b\\c|d?e*f\"g"), "a_b_c_d_e_f_g");
+    assert_eq!(sanitize_name("tab\tnewline\n"), "tab_newline_");
+}
+
+#[test]
+fn null_sinks_discard_everything() {
+    assert_eq!(
+        NullSink
+            .write(Path::new("graph.dot"), b"digraph {}")
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        NullSink
+            .render_graphviz(Path::new("graph.dot"), "png")
+            .unwrap(),
+        None,
+    );
+    NullSink.write_graph("graph", "digraph {}").unwrap();
+}
+
+#[test]
+fn directory_sinks_write_graphs() {
+    let root = env::temp_dir().join(format!("sruth-artifacts-{}", std::process::id()));
+    let sink = DirectorySink::new(&root).with_graphviz(None::<PathBuf>);
+    assert_eq!(sink.root(), root);
+
+    let written = sink
+        .write(Path::new("nested/report.txt"), b"report")
+        .unwrap()
+        .unwrap();
+    assert_eq!(written, root.join("nested/report.txt"));
+    assert_eq!(fs::read(&written).unwrap(), b"report");
+
+    // Graph names are sanitized into directories, without graphviz the dot source
+    // is still written but nothing is rendered
+    sink.write_graph("ops::add", "digraph {}").unwrap();
+    let dot = root.join("ops__add").join("graphviz.dot");
+    assert_eq!(fs::read_to_string(&dot).unwrap(), "digraph {}");
+    assert_eq!(sink.render_graphviz(&dot, "png").unwrap(), None);
+    assert!(!dot.with_extension("png").exists());
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,843 @@
+use crate::{
+    builder::{BinaryOpKind, BuilderError, Context, TypeMismatch, WarningKind},
+    repr::{
+        instruction::{IntrinsicOp, Lowering},
+        terminator::{Branch, Label, Return, Switch},
+        utils::{
+            DisplayCtx, IRDisplay, InstructionPurity, InstructionRewriter, InstructionVisitor,
+            PRETTY_WIDTH,
+        },
+        BasicBlockId, Constant, FuncId, Ident, Instruction, InstructionExt, SourceLoc, Span,
+        Terminator, Type, Value, VarId,
+    },
+};
+use pretty::{BoxAllocator, RefDoc};
+use std::sync::Arc;
+
+#[test]
+fn entry_block() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut entry = None;
+    builder
+        .function(Type::Unit, |func| {
+            func.basic_block(|block| {
+                block.ret_unit();
+                Ok(())
+            })?;
+
+            entry = Some(func.entry_block(|block| {
+                block.ret_unit();
+                Ok(())
+            })?);
+
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().next().unwrap();
+    assert_eq!(Some(function.entry), entry);
+
+    builder.discard();
+}
+
+#[test]
+fn implicit_return() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let strict = builder.function(Type::Uint, |func| {
+        func.basic_block(|_block| Ok(()))?;
+        Ok(())
+    });
+    assert_eq!(strict, Err(BuilderError::MissingTerminator));
+
+    builder.set_permissive(true);
+    builder
+        .function(Type::Uint, |func| {
+            func.basic_block(|block| {
+                block.assign(Constant::Uint(10));
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().last().unwrap();
+    assert_eq!(
+        function.basic_blocks[0].terminator,
+        Terminator::Return(Return::new(Some(Constant::Uint(0).into()))),
+    );
+
+    builder.discard();
+}
+
+#[test]
+fn implicit_returns_need_defaults() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder.set_permissive(true);
+
+    // An array of units has no default, so no return with every result can be made
+    let results = Type::Tuple(vec![Type::Int, Type::Array(Box::new(Type::Unit), 2)]);
+    let implicit = builder.function(results, |func| {
+        func.basic_block(|block| {
+            block.assign(Constant::Int(10));
+            Ok(())
+        })?;
+
+        Ok(())
+    });
+    assert_eq!(implicit, Err(BuilderError::MissingTerminator));
+
+    builder.discard();
+}
+
+#[test]
+fn default_constants_are_bounded() {
+    let array = |element, len| Type::Array(Box::new(element), len);
+
+    assert_eq!(
+        array(Type::Bool, 2).default_constant(),
+        Some(Constant::Array(
+            Type::Bool,
+            vec![Constant::Bool(false), Constant::Bool(false)],
+        )),
+    );
+
+    // Arrays would be materialized element by element, so huge ones have no default
+    assert_eq!(array(Type::Int, u64::MAX).default_constant(), None);
+    assert_eq!(
+        array(array(Type::Int, 1 << 10), 1 << 10).default_constant(),
+        None,
+    );
+    assert!(array(array(Type::Int, 1 << 4), 1 << 4)
+        .default_constant()
+        .is_some());
+}
+
+#[test]
+fn multi_value_returns() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let pair = Type::Tuple(vec![Type::Int, Type::Bool]);
+
+    builder
+        .function(pair.clone(), |func| {
+            func.basic_block(|block| {
+                let x = block.assign(Constant::Int(10));
+                block.ret_multiple(vec![Value::from(x), Constant::Bool(true).into()])?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().last().unwrap();
+    let ret = function.basic_blocks[0]
+        .terminator
+        .clone()
+        .into_return()
+        .unwrap();
+    assert_eq!(ret.values.len(), 2);
+    assert_eq!(ret.ty(), pair);
+
+    let too_few = builder.function(pair.clone(), |func| {
+        func.basic_block(|block| {
+            block.ret_multiple(vec![Constant::Int(10)])?;
+            Ok(())
+        })?;
+
+        Ok(())
+    });
+    assert_eq!(
+        too_few,
+        Err(BuilderError::MismatchedReturnArity {
+            expected: 2,
+            got: 1,
+        }),
+    );
+
+    let mismatched = builder.function(pair, |func| {
+        func.basic_block(|block| {
+            block.ret_multiple(vec![Constant::Int(10), Constant::Int(20)])?;
+            Ok(())
+        })?;
+
+        Ok(())
+    });
+    assert_eq!(mismatched, Err(BuilderError::MismatchedReturnTypes));
+
+    builder.discard();
+}
+
+#[test]
+fn call_site_validation() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let clamp = builder
+        .named_function("clamp", Type::Int, |func| {
+            let x = func.named_param(Type::Int, "x");
+            let _low = func.param_with_default(Type::Int, "low", Constant::Int(0))?;
+            let _high = func.param_with_default(Type::Int, "high", Constant::Int(100))?;
+
+            func.basic_block(|block| {
+                block.ret(x)?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(context.signature(clamp).unwrap().params.len(), 3);
+
+    builder
+        .function(Type::Int, |func| {
+            func.basic_block(|block| {
+                let defaulted = block.call_with_defaults(clamp, vec![Constant::Int(5).into()])?;
+                assert_eq!(defaulted.ty, Type::Int);
+
+                let named = block.call_named(
+                    clamp,
+                    vec![
+                        ("high", Value::from(defaulted)),
+                        ("x", Constant::Int(7).into()),
+                    ],
+                )?;
+                block.ret(named)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let caller = builder.materialize().last().unwrap();
+    let args: Vec<_> = caller.basic_blocks[0]
+        .instructions
+        .iter()
+        .filter_map(|inst| match inst {
+            Instruction::Call(call) => Some(call.args.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(args.len(), 2);
+    assert_eq!(
+        args[0][1..],
+        [Constant::Int(0).into(), Constant::Int(100).into()]
+    );
+    assert_eq!(args[1][0], Constant::Int(7).into());
+    assert_eq!(args[1][1], Constant::Int(0).into());
+
+    let call = |args: Vec<Value>| {
+        let mut builder = context.builder();
+        let result = builder.function(Type::Int, |func| {
+            func.basic_block(|block| {
+                let result = block.call(clamp, args)?;
+                block.ret(result)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        });
+        builder.discard();
+
+        result
+    };
+
+    assert_eq!(
+        call(vec![Constant::Int(1).into()]),
+        Err(BuilderError::CallArityMismatch {
+            callee: clamp,
+            expected: 3,
+            got: 1,
+        }),
+    );
+    assert_eq!(
+        call(vec![
+            Constant::Int(1).into(),
+            Constant::Bool(false).into(),
+            Constant::Int(2).into(),
+        ]),
+        Err(BuilderError::CallArgumentTypeMismatch {
+            callee: clamp,
+            arg: 1,
+            expected: Type::Int,
+            got: Type::Bool,
+        }),
+    );
+
+    let missing = builder.function(Type::Int, |func| {
+        func.basic_block(|block| {
+            let result = block.call_named(clamp, vec![("low", Constant::Int(1))])?;
+            block.ret(result)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    });
+    assert_eq!(
+        missing,
+        Err(BuilderError::MissingCallArgument {
+            callee: clamp,
+            arg: 0,
+        }),
+    );
+
+    builder.discard();
+}
+
+#[test]
+fn unused_value_warnings() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder.set_warn_unused(true);
+
+    let (mut unused_param, mut unused_value) = (None, None);
+    builder
+        .named_function("unused", Type::Int, |func| {
+            let x = func.param(Type::Int);
+            unused_param = Some(func.param(Type::Int).var);
+
+            func.basic_block(|block| {
+                unused_value = Some(block.mul(x.clone(), Constant::Int(2))?.var);
+                let sum = block.add(x, Constant::Int(1))?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let kinds: Vec<_> = builder
+        .warnings()
+        .iter()
+        .map(|warning| warning.kind)
+        .collect();
+    assert_eq!(kinds.len(), 2);
+    assert_eq!(
+        kinds[0],
+        WarningKind::UnusedParam {
+            param: unused_param.unwrap(),
+        },
+    );
+    assert!(matches!(
+        kinds[1],
+        WarningKind::UnusedValue { value, .. } if Some(value) == unused_value,
+    ));
+    assert_eq!(
+        builder.warnings()[0].function_name.as_deref(),
+        Some("unused"),
+    );
+
+    builder.discard();
+}
+
+#[test]
+fn named_values() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .function(Type::Uint, |func| {
+            func.basic_block(|block| {
+                block.named("loop.header");
+
+                let lhs = block.assign(Constant::Uint(1));
+                let sum = block.add_named("sum", lhs, Constant::Uint(2))?;
+                let rem = block.rem_named("rem", sum, Constant::Uint(3))?;
+                let diff = block.sub(rem, lhs)?;
+                block.ret(diff)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().next().unwrap();
+    let block = &function.basic_blocks[0];
+    let resolve = |name| context.interner().resolve(&name.0);
+
+    assert_eq!(block.name.map(resolve), Some("loop.header"));
+    assert_eq!(block.instructions[1].name().map(resolve), Some("sum"));
+    assert_eq!(block.instructions[2].name().map(resolve), Some("rem"));
+    assert_eq!(block.instructions[3].name(), None);
+
+    builder.discard();
+}
+
+#[test]
+fn control_flow_helpers() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let (mut looping, mut branching) = (None, None);
+    builder
+        .function(Type::Unit, |func| {
+            let while_loop = func.while_loop(
+                |_header| Ok(Constant::Bool(true)),
+                |body| {
+                    let if_else = body.if_else(
+                        Constant::Bool(false),
+                        |_then| Ok(()),
+                        |else_| {
+                            else_.ret_unit();
+                            Ok(())
+                        },
+                    )?;
+
+                    branching = Some((if_else.then, if_else.else_, *if_else.merge));
+                    body.function().resume_building(if_else.merge, |merge| {
+                        merge.ret_unit();
+                        Ok(())
+                    })?;
+
+                    Ok(())
+                },
+            )?;
+
+            let header = while_loop.header;
+            looping = Some((header, while_loop.body, *while_loop.exit));
+
+            func.resume_building(while_loop.exit, |exit| {
+                exit.ret_unit();
+                Ok(())
+            })?;
+            func.entry_block(|entry| {
+                entry.jump(header);
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().next().unwrap();
+    let block = |id| {
+        function
+            .basic_blocks
+            .iter()
+            .find(|block| block.id == id)
+            .unwrap()
+    };
+
+    let (header, body, exit) = looping.unwrap();
+    let (then, else_, merge) = branching.unwrap();
+
+    assert_eq!(
+        block(header).terminator,
+        Terminator::Branch(Branch::new(
+            Constant::Bool(true).into(),
+            Label::new(body),
+            Label::new(exit),
+        )),
+    );
+    assert_eq!(
+        block(body).terminator,
+        Terminator::Branch(Branch::new(
+            Constant::Bool(false).into(),
+            Label::new(then),
+            Label::new(else_),
+        )),
+    );
+    assert_eq!(block(then).terminator, Terminator::Jump(merge));
+
+    builder.discard();
+}
+
+#[test]
+fn switch_terminator() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut targets = None;
+    builder
+        .function(Type::Unit, |func| {
+            let one = func.basic_block(|block| {
+                block.ret_unit();
+                Ok(())
+            })?;
+            let two = func.basic_block(|block| {
+                block.ret_unit();
+                Ok(())
+            })?;
+            let default = func.basic_block(|block| {
+                block.ret_unit();
+                Ok(())
+            })?;
+
+            let entry = func.entry_block(|block| {
+                assert_eq!(
+                    block.switch(
+                        Constant::Uint(1),
+                        vec![(Constant::Uint(1), one), (Constant::Bool(true), two)],
+                        default,
+                    ),
+                    Err(BuilderError::IncorrectSwitchCaseType),
+                );
+
+                block.switch(
+                    Constant::Uint(1),
+                    vec![(Constant::Uint(1), one), (Constant::Uint(2), two)],
+                    default,
+                )?;
+                Ok(())
+            })?;
+
+            targets = Some((entry, one, two, default));
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().next().unwrap();
+    let (entry, one, two, default) = targets.unwrap();
+    let entry = function
+        .basic_blocks
+        .iter()
+        .find(|block| block.id == entry)
+        .unwrap();
+
+    let switch = Switch::new(
+        Constant::Uint(1).into(),
+        vec![
+            (Constant::Uint(1), Label::new(one)),
+            (Constant::Uint(2), Label::new(two)),
+        ],
+        Label::new(default),
+    );
+    assert_eq!(switch.target(&Constant::Uint(2)), two);
+    assert_eq!(switch.target(&Constant::Uint(3)), default);
+    assert_eq!(entry.terminator, Terminator::Switch(switch));
+
+    builder.discard();
+}
+
+#[test]
+fn operand_type_mismatch() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut func_id = None;
+    let result = builder.named_function("mismatched", Type::Uint, |func| {
+        func_id = Some(func.func_id());
+
+        func.basic_block(|block| {
+            let sum = block.add(Constant::Bool(true), Constant::Uint(1))?;
+            block.ret(sum)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    });
+
+    let error = TypeMismatch {
+        operation: BinaryOpKind::Add,
+        lhs: Type::Bool,
+        rhs: Type::Uint,
+        function: func_id.unwrap(),
+        function_name: Some("mismatched".to_owned()),
+    };
+    assert_eq!(
+        error.to_string(),
+        "mismatched operand types for add: bool and uint in function `mismatched`",
+    );
+    assert_eq!(result, Err(BuilderError::TypeMismatch(error)));
+
+    builder.discard();
+}
+
+#[test]
+fn intrinsics() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mismatched = builder.function(Type::Uint, |func| {
+        func.basic_block(|block| {
+            let sum = block.intrinsic(
+                IntrinsicOp::WrappingAdd,
+                vec![Constant::Uint(1).into(), Constant::Int(1).into()],
+            )?;
+            block.ret(sum)?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    });
+    assert_eq!(
+        mismatched,
+        Err(BuilderError::InvalidIntrinsicArguments {
+            intrinsic: IntrinsicOp::WrappingAdd,
+            args: vec![Type::Uint, Type::Int],
+        }),
+    );
+
+    builder
+        .function(Type::Uint, |func| {
+            func.basic_block(|block| {
+                let count =
+                    block.intrinsic(IntrinsicOp::Ctpop, vec![Constant::Uint(0b1011).into()])?;
+                assert_eq!(count.ty, Type::Uint);
+
+                let copy = block.intrinsic(
+                    IntrinsicOp::Memcpy,
+                    vec![
+                        Constant::Uint(0).into(),
+                        Constant::Uint(64).into(),
+                        count.clone().into(),
+                    ],
+                )?;
+                assert_eq!(copy.ty, Type::Unit);
+                block.ret(count)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    let (count, copy) = match function.basic_blocks[0].instructions.as_slice() {
+        [Instruction::Intrinsic(count), Instruction::Intrinsic(copy)] => (count, copy),
+        instructions => panic!("expected two intrinsics, got {:?}", instructions),
+    };
+    assert_eq!(count.fold(), Some(Constant::Uint(3)));
+    assert_eq!(count.purity(), InstructionPurity::Pure);
+
+    // Copies touch memory, so they're never folded or removed
+    assert_eq!(copy.fold(), None);
+    assert_eq!(copy.purity(), InstructionPurity::Impure);
+
+    // Counting the bits of zero gives the integer's width and wrapping arithmetic
+    // wraps around instead of overflowing
+    let fold = |op: IntrinsicOp, args: &[Constant]| op.fold(&args.iter().collect::<Vec<_>>());
+    assert_eq!(
+        fold(IntrinsicOp::Ctlz, &[Constant::Int(0)]),
+        Some(Constant::Int(64))
+    );
+    assert_eq!(
+        fold(IntrinsicOp::Cttz, &[Constant::Uint128(0)]),
+        Some(Constant::Uint128(128)),
+    );
+    assert_eq!(
+        fold(
+            IntrinsicOp::WrappingAdd,
+            &[Constant::Int(i64::MAX), Constant::Int(1)]
+        ),
+        Some(Constant::Int(i64::MIN)),
+    );
+    assert_eq!(
+        fold(
+            IntrinsicOp::WrappingMul,
+            &[Constant::Uint(u64::MAX), Constant::Uint(2)]
+        ),
+        Some(Constant::Uint(u64::MAX - 1)),
+    );
+
+    assert_eq!(IntrinsicOp::from_name("ctpop"), Some(IntrinsicOp::Ctpop));
+    assert_eq!(
+        IntrinsicOp::Ctpop.wasm_lowering(&Type::Uint),
+        Some(Lowering::Wasm(&[0x7B])),
+    );
+    assert_eq!(
+        IntrinsicOp::Ctpop.wasm_lowering(&Type::Int128),
+        Some(Lowering::Libcall("__popcountti2")),
+    );
+}
+
+#[test]
+fn source_spans() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let file = context.interner().get_or_intern("main.sr");
+    let (assign_span, add_span) = (
+        Span::point(Some(Ident::new(file)), SourceLoc::new(1, 5)),
+        Span::new(
+            Some(Ident::new(file)),
+            SourceLoc::new(2, 5),
+            SourceLoc::new(2, 10),
+        ),
+    );
+
+    builder
+        .function(Type::Uint, |func| {
+            func.basic_block(|block| {
+                let lhs = block.at(assign_span).assign(Constant::Uint(1));
+                let sum = block.at(add_span).add(lhs, Constant::Uint(2))?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().next().unwrap();
+    let block = &function.basic_blocks[0];
+
+    assert_eq!(
+        block.instruction_spans,
+        vec![Some(assign_span), Some(add_span)]
+    );
+    assert_eq!(block.terminator_span, Some(add_span));
+
+    assert!(!block.to_pretty_string(context.interner()).contains("!loc"));
+
+    let (alloc, mut rendered) = (BoxAllocator, String::new());
+    block
+        .display::<BoxAllocator, RefDoc, _>(
+            DisplayCtx::new(&alloc, context.interner()).with_spans(true),
+        )
+        .1
+        .render_fmt(PRETTY_WIDTH, &mut rendered)
+        .unwrap();
+    assert!(rendered.contains("!loc main.sr:1:5"));
+    assert!(rendered.contains("!loc main.sr:2:5-2:10"));
+
+    builder.discard();
+}
+
+#[test]
+fn visitors_and_rewriters_walk_every_operand() {
+    #[derive(Default)]
+    struct Counter {
+        values: usize,
+        dests: Vec<VarId>,
+        callees: Vec<FuncId>,
+        targets: Vec<BasicBlockId>,
+    }
+
+    impl InstructionVisitor for Counter {
+        fn visit_value(&mut self, _value: &Value) {
+            self.values += 1;
+        }
+
+        fn visit_dest(&mut self, dest: VarId) {
+            self.dests.push(dest);
+        }
+
+        fn visit_callee(&mut self, callee: FuncId) {
+            self.callees.push(callee);
+        }
+
+        fn visit_target(&mut self, target: BasicBlockId) {
+            self.targets.push(target);
+        }
+    }
+
+    /// Replaces every constant operand with zero
+    struct ZeroConstants;
+
+    impl InstructionRewriter for ZeroConstants {
+        fn rewrite_value(&mut self, value: &mut Value) -> bool {
+            if value.is_const() {
+                *value = Constant::Int(0).into();
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut callee = None;
+    builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+            callee = Some(func.func_id());
+
+            func.basic_block(|block| {
+                let y = block.add(x, Constant::Int(1))?;
+                block.ret(y)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let callee = callee.unwrap();
+
+    builder
+        .function(Type::Int, |func| {
+            let exit = func.allocate_basic_block();
+
+            func.basic_block(|block| {
+                let y = block.call(callee, vec![Constant::Int(2).into()])?;
+                let cond = block.cmp(y, Constant::Int(3))?;
+                block.branch(cond, *exit, *exit)?;
+
+                Ok(())
+            })?;
+
+            func.resume_building(exit, |block| {
+                block.ret(Constant::Int(4))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let mut functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+    functions.sort_by_key(|function| function.id);
+
+    let caller = &mut functions[1];
+    let mut counter = Counter::default();
+    for block in caller.basic_blocks.iter() {
+        for inst in block.instructions.iter() {
+            counter.visit_instruction(inst);
+        }
+        counter.visit_terminator(&block.terminator);
+    }
+
+    // The call's argument, both sides of the comparison, the branch condition and
+    // the returned constant
+    assert_eq!(counter.values, 5);
+    assert_eq!(counter.dests.len(), 2);
+    assert_eq!(counter.callees, vec![callee]);
+    assert_eq!(counter.targets.len(), 2);
+
+    let mut changed = false;
+    for block in caller.basic_blocks.iter_mut() {
+        for inst in block.instructions.iter_mut() {
+            changed |= ZeroConstants.rewrite_instruction(inst);
+        }
+        changed |= ZeroConstants.rewrite_terminator(&mut block.terminator);
+    }
+    assert!(changed);
+
+    for block in caller.basic_blocks.iter() {
+        let constants = block
+            .instructions
+            .iter()
+            .flat_map(Instruction::used_values)
+            .chain(block.terminator.used_values())
+            .filter_map(Value::as_const);
+
+        for constant in constants {
+            assert_eq!(constant, &Constant::Int(0));
+        }
+    }
+}
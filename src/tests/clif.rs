@@ -0,0 +1,58 @@
+#![cfg(feature = "cranelift")]
+
+use crate::{
+    builder::Context,
+    emit::clif::{self, EmitError},
+    repr::{Constant, Function, Type},
+};
+use std::sync::Arc;
+
+#[test]
+fn functions_compile_into_objects() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let double = builder
+        .named_function("double", Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let doubled = block.mul(x, Constant::Int(2))?;
+                block.ret(doubled)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    builder
+        .named_function("quadruple", Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let mut doubled = block.call(double, vec![x.into()])?;
+                doubled.ty = Type::Int;
+                let mut quadrupled = block.call(double, vec![doubled.into()])?;
+                quadrupled.ty = Type::Int;
+                block.ret(quadrupled)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    let object = clif::emit_object(&functions, context.interner()).unwrap();
+    assert!(!object.is_empty());
+
+    // Callees have to be translated along with their callers
+    assert!(matches!(
+        clif::emit_object(&functions[1..], context.interner()),
+        Err(EmitError::UnknownFunction(func)) if func == double,
+    ));
+}
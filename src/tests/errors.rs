@@ -0,0 +1,485 @@
+use crate::{
+    builder::{BuilderError, Context},
+    dataflow::{Diff, KeyTraceHandle, Time, TraceHandle},
+    driver::{Driver, LoadedFunction, Pipeline, TYPE_ERRORS_TRACE},
+    repr::{
+        self, basic_block::BasicBlockDesc, function::FunctionDesc, instruction::Assign,
+        terminator::Return, BasicBlockId, FuncId, FunctionAttributes, InstId, Instruction,
+        InstructionExt, ModuleId, Terminator, Type, VarId,
+    },
+    symbols::FunctionNames,
+    verify::{verify, TypeError, ValidityError, Verifier},
+    vsdg::node::{Constant, EvaluationError},
+    Error,
+};
+use differential_dataflow::input::Input;
+use std::{
+    num::NonZeroU64,
+    sync::{Arc, Mutex},
+};
+use timely::progress::frontier::AntichainRef;
+
+#[test]
+fn checked_constant_arithmetic() {
+    let (one, two) = (Constant::Uint8(1), Constant::Uint8(2));
+    assert_eq!(one.checked_add(&two), Ok(Constant::Uint8(3)));
+    assert_eq!(one.checked_sub(&two), Ok(Constant::Uint8(u8::MAX)));
+    assert_eq!(two.checked_mul(&two), Ok(Constant::Uint8(4)));
+
+    let err = one.checked_add(&Constant::Bool(true)).unwrap_err();
+    assert_eq!(
+        err,
+        EvaluationError::IncompatibleOperands {
+            operation: "add",
+            lhs: Constant::Uint8(1),
+            rhs: Constant::Bool(true),
+        },
+    );
+    assert!(matches!(Error::from(err), Error::Evaluation(_)));
+}
+
+#[test]
+fn errors_convert_into_crate_error() {
+    fn build() -> crate::Result<()> {
+        Err(BuilderError::MissingEntryBlock)?;
+        Ok(())
+    }
+
+    let err = build().unwrap_err();
+    assert_eq!(err, Error::Builder(BuilderError::MissingEntryBlock));
+    assert_eq!(
+        err.to_string(),
+        "failed to build a function: a function has no entry block",
+    );
+}
+
+#[test]
+fn typecheck_reports_mismatched_types() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .named_function("is_one", Type::Bool, |func| {
+            let value = func.param(Type::Int);
+            func.basic_block(|block| {
+                let is_one = block.cmp(value, repr::Constant::Int(1))?;
+                block.ret(is_one)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let mut function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    if let Instruction::Cmp(cmp) = &mut function.basic_blocks[0].instructions[0] {
+        cmp.rhs = repr::Constant::Bool(true).into();
+    }
+    function.ret_ty = Type::Int;
+    let block = function.basic_blocks[0].id;
+
+    timely::execute_directly(move |worker| {
+        let mut handles = Pipeline::new(context.clone()).build(worker);
+        LoadedFunction::new(&context, function).insert(&mut handles.input);
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+
+        let mut errors = Vec::new();
+        let handle: KeyTraceHandle<TypeError, Time, Diff> =
+            TraceHandle::new(context.interner().get_or_intern_static(TYPE_ERRORS_TRACE));
+        assert!(handles
+            .trace_manager
+            .export(handle, AntichainRef::new(&[1]), |error, &(), _| {
+                errors.push(error.clone())
+            }));
+
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|error| matches!(
+            error,
+            TypeError::OperandTypeMismatch {
+                lhs: Type::Int,
+                rhs: Type::Bool,
+                ..
+            },
+        )));
+        assert!(errors.contains(&TypeError::ReturnTypeMismatch {
+            block,
+            expected: Type::Int,
+            got: Type::Bool,
+        }));
+    });
+}
+
+#[test]
+fn verify_checks_control_flow() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let (mut entry, mut back) = (None, None);
+    let func = builder
+        .named_function("jumps_to_entry", Type::Int, |func| {
+            let start = func.basic_block(|block| {
+                block.ret(repr::Constant::Int(0))?;
+                Ok(())
+            })?;
+
+            // Nothing jumps to this block, but it jumps back to the entry
+            back = Some(func.basic_block(|block| {
+                block.jump(start);
+                Ok(())
+            })?);
+            entry = Some(start);
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let (entry, back) = (entry.unwrap(), back.unwrap());
+    let mut errors = Driver::new(context).run(functions, &[]).errors;
+    errors.sort();
+
+    let mut expected = vec![
+        ValidityError::EntryHasPredecessors {
+            func,
+            entry,
+            predecessor: back,
+        },
+        ValidityError::UnreachableBlock { func, block: back },
+    ];
+    expected.sort();
+    assert_eq!(errors, expected);
+}
+
+#[test]
+fn verifier_matches_the_driver() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .named_function("valid", Type::Int, |func| {
+            func.basic_block(|block| {
+                block.ret(repr::Constant::Int(0))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let mut unreachable = None;
+    let func = builder
+        .named_function("unreachable", Type::Int, |func| {
+            func.basic_block(|block| {
+                block.ret(repr::Constant::Int(0))?;
+                Ok(())
+            })?;
+
+            unreachable = Some(func.basic_block(|block| {
+                block.ret(repr::Constant::Int(1))?;
+                Ok(())
+            })?);
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let verifier = Verifier::new(context.clone());
+    assert!(verifier.check_module(vec![functions[0].clone()]).is_empty());
+
+    let errors = verifier.check_module(functions.clone());
+    assert_eq!(
+        errors,
+        vec![ValidityError::UnreachableBlock {
+            func,
+            block: unreachable.unwrap(),
+        }],
+    );
+
+    let mut driver_errors = Driver::new(context).run(functions, &[]).errors;
+    driver_errors.sort();
+    assert_eq!(errors, driver_errors);
+}
+
+#[test]
+fn verify_rejects_calls_into_unexported_functions() {
+    let context = Arc::new(Context::new(0));
+
+    let mut library = context.builder();
+    library.set_module(ModuleId::new(1));
+    let private = library
+        .named_function("private", Type::Int, |func| {
+            func.basic_block(|block| {
+                block.ret(repr::Constant::Int(1))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let public = library
+        .named_function("public", Type::Int, |func| {
+            func.with_attrs(FunctionAttributes::EXPORT);
+            func.basic_block(|block| {
+                block.ret(repr::Constant::Int(2))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let mut application = context.builder();
+    application.set_module(ModuleId::new(2));
+    let main = application
+        .named_function("main", Type::Int, |func| {
+            func.basic_block(|block| {
+                let one = block.call(private, Vec::new())?;
+                let two = block.call(public, Vec::new())?;
+                let sum = block.add(one, two)?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = library
+        .materialize()
+        .chain(application.materialize())
+        .collect();
+    library.discard();
+    application.discard();
+
+    // Only the call to the function that isn't exported is an error
+    let errors = Driver::new(context.clone())
+        .run(functions.clone(), &[])
+        .errors;
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(matches!(
+        errors[0],
+        ValidityError::CrossModuleCall {
+            caller,
+            caller_module,
+            callee,
+            callee_module,
+            ..
+        } if caller == main
+            && caller_module == ModuleId::new(2)
+            && callee == private
+            && callee_module == ModuleId::new(1),
+    ));
+
+    // Resolvers that know the functions' names render them in place of their ids
+    let names = FunctionNames::new(context.interner(), &functions);
+    let rendered = errors[0].display_with(&names).to_string();
+    assert!(rendered.contains("`main`"), "{}", rendered);
+    assert!(rendered.contains("`private`"), "{}", rendered);
+    assert!(!errors[0].to_string().contains("`main`"));
+}
+
+#[test]
+fn validity_errors_render_block_and_variable_names() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let (mut header, mut sum) = (None, None);
+    let func = builder
+        .named_function("named", Type::Int, |func| {
+            func.basic_block(|block| {
+                block.named("loop.header");
+                header = Some(block.block_id());
+
+                let value =
+                    block.add_named("sum", repr::Constant::Int(1), repr::Constant::Int(2))?;
+                sum = Some(value.var);
+                block.ret(value)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let error = ValidityError::UseBeforeDef {
+        func,
+        block: header.unwrap(),
+        inst: None,
+        var: sum.unwrap(),
+    };
+
+    let names = FunctionNames::new(context.interner(), &functions);
+    assert_eq!(
+        error.display_with(&names).to_string(),
+        "the terminator of `loop.header` within `named` uses `sum` before it's defined",
+    );
+
+    // Without a resolver everything is referred to by its id
+    let rendered = error.to_string();
+    assert!(!rendered.contains('`'), "{}", rendered);
+}
+
+#[test]
+fn verify_rejects_uses_before_definitions() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let func = builder
+        .named_function("use_before_def", Type::Int, |func| {
+            func.basic_block(|block| {
+                let one = block.add(repr::Constant::Int(1), repr::Constant::Int(2))?;
+                let two = block.add(one, one)?;
+                block.ret(two)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let mut functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    // Swapping the instructions makes the second one read the first's result before
+    // it's been computed
+    let block = &mut functions[0].basic_blocks[0];
+    block.instructions.swap(0, 1);
+    block.instruction_spans.clear();
+    let (block_id, used) = (block.id, block.instructions[1].dest());
+
+    let errors = Driver::new(context).run(functions, &[]).errors;
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(matches!(
+        errors[0],
+        ValidityError::UseBeforeDef {
+            func: error_func,
+            block,
+            inst: Some(_),
+            var,
+        } if error_func == func && block == block_id && var == used,
+    ));
+}
+
+#[test]
+fn verify_checks_id_consistency() {
+    let inst = |id| InstId::new(NonZeroU64::new(id).unwrap());
+    let block = |id| BasicBlockId::new(NonZeroU64::new(id).unwrap());
+    let func = |id| FuncId::new(NonZeroU64::new(id).unwrap());
+    let assign = |var| -> Instruction {
+        let dest = VarId::new(NonZeroU64::new(var).unwrap());
+        Assign::new(dest, repr::Constant::Int(0).into(), None).into()
+    };
+    let ret = || Terminator::Return(Return::new(None));
+
+    // The first instruction is listed by both blocks, the second doesn't exist, the
+    // third isn't listed by either block and both functions claim the first block
+    let instructions = vec![(inst(1), assign(1)), (inst(3), assign(3))];
+    let basic_blocks = vec![
+        (
+            block(1),
+            BasicBlockDesc::new(None, block(1), vec![inst(1), inst(2)], ret()),
+        ),
+        (
+            block(2),
+            BasicBlockDesc::new(None, block(2), vec![inst(1)], ret()),
+        ),
+    ];
+    let functions = vec![
+        (
+            func(1),
+            FunctionDesc::new(
+                None,
+                func(1),
+                Vec::new(),
+                Type::Unit,
+                block(1),
+                vec![block(1)],
+            ),
+        ),
+        (
+            func(2),
+            FunctionDesc::new(
+                None,
+                func(2),
+                Vec::new(),
+                Type::Unit,
+                block(2),
+                vec![block(2), block(1)],
+            ),
+        ),
+    ];
+
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let captured = errors.clone();
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<usize, _, _>(|scope| {
+            let (_instructions, instructions) = scope.new_collection_from(instructions);
+            let (_basic_blocks, basic_blocks) = scope.new_collection_from(basic_blocks);
+            let (_functions, functions) = scope.new_collection_from(functions);
+
+            verify(scope, &instructions, &basic_blocks, &functions).inspect(
+                move |(error, _, diff)| {
+                    assert_eq!(*diff, 1);
+                    captured.lock().unwrap().push(error.clone());
+                },
+            );
+        });
+    });
+
+    // The shared blocks and instructions trip other checks as well
+    let mut errors: Vec<_> = errors
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|error| {
+            matches!(
+                error,
+                ValidityError::DuplicateInstruction { .. }
+                    | ValidityError::DuplicateBlock { .. }
+                    | ValidityError::MissingInstruction { .. }
+                    | ValidityError::OrphanedInstruction { .. },
+            )
+        })
+        .cloned()
+        .collect();
+    errors.sort();
+
+    let mut expected = vec![
+        ValidityError::DuplicateInstruction {
+            inst: inst(1),
+            block: block(1),
+        },
+        ValidityError::DuplicateInstruction {
+            inst: inst(1),
+            block: block(2),
+        },
+        ValidityError::DuplicateBlock {
+            block: block(1),
+            func: func(1),
+        },
+        ValidityError::DuplicateBlock {
+            block: block(1),
+            func: func(2),
+        },
+        ValidityError::MissingInstruction {
+            block: block(1),
+            inst: inst(2),
+        },
+        ValidityError::OrphanedInstruction { inst: inst(3) },
+    ];
+    expected.sort();
+    assert_eq!(errors, expected);
+}
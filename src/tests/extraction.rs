@@ -0,0 +1,53 @@
+use crate::{
+    builder::Context,
+    dataflow::ExtractionDisplay,
+    repr::{terminator::Return, Constant, Terminator, Type},
+};
+use std::sync::Arc;
+
+#[test]
+fn changes_only_print_changed_lines() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .function(Type::Uint, |func| {
+            func.basic_block(|block| {
+                let value = block.assign(Constant::Uint(1));
+                block.ret(value)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let original = builder.materialize().next().unwrap();
+
+    let mut folded = original.clone();
+    folded.basic_blocks[0].terminator =
+        Terminator::Return(Return::new(Some(Constant::Uint(1).into())));
+
+    let extracted = vec![(
+        1,
+        vec![
+            (Ok((original.id, original.clone())), 1, -1),
+            (Ok((folded.id, folded)), 1, 1),
+        ],
+    )];
+    let changes = ExtractionDisplay::Changes.render(&extracted, &*context.interner());
+
+    let changed: Vec<_> = changes
+        .lines()
+        .filter(|line| line.starts_with('+') || line.starts_with('-'))
+        .collect();
+    assert_eq!(changed.len(), 2, "{}", changes);
+    assert!(changed[0].starts_with('-') && changed[1].starts_with('+'));
+    assert_ne!(changed[0][1..], changed[1][1..]);
+
+    // Timestamps without any changes aren't printed at all
+    let unchanged = ExtractionDisplay::Changes.render(&[(2, Vec::new())], &*context.interner());
+    assert!(unchanged.is_empty());
+
+    builder.discard();
+}
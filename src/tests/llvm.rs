@@ -0,0 +1,174 @@
+use crate::{
+    builder::Context,
+    emit::llvm::{self, EmitError},
+    repr::{
+        function::Metadata,
+        instruction::{Assign, Call, Cmp},
+        terminator::{Branch, Label, Return},
+        BasicBlock, BasicBlockId, FuncId, Function, FunctionAttributes, Ident, Instruction,
+        ParamAttributes, Terminator, TrapCode, Type, TypedVar, Value, ValueKind, VarId,
+    },
+};
+use std::num::NonZeroU64;
+
+fn var(id: u64) -> VarId {
+    VarId::new(NonZeroU64::new(id).unwrap())
+}
+
+fn block_id(id: u64) -> BasicBlockId {
+    BasicBlockId::new(NonZeroU64::new(id).unwrap())
+}
+
+fn func_id(id: u64) -> FuncId {
+    FuncId::new(NonZeroU64::new(id).unwrap())
+}
+
+fn int(id: u64) -> Value {
+    Value::new(ValueKind::Var(var(id)), Type::Int)
+}
+
+fn block(id: u64, instructions: Vec<Instruction>, terminator: Terminator) -> BasicBlock {
+    BasicBlock {
+        name: None,
+        id: block_id(id),
+        instructions,
+        terminator,
+        instruction_spans: Vec::new(),
+        terminator_span: None,
+    }
+}
+
+fn function(
+    context: &Context,
+    name: &str,
+    id: u64,
+    params: Vec<TypedVar>,
+    ret_ty: Type,
+    basic_blocks: Vec<BasicBlock>,
+) -> Function {
+    Function {
+        name: Some(Ident::new(context.interner().get_or_intern(name))),
+        id: func_id(id),
+        params,
+        ret_ty,
+        entry: basic_blocks
+            .first()
+            .map_or_else(|| block_id(1), |block| block.id),
+        basic_blocks,
+        metadata: Metadata::default(),
+    }
+}
+
+#[test]
+fn functions_print_as_llvm_ir() {
+    let context = Context::new(0);
+
+    // Both arms assign the same variable, which gets a stack slot
+    let max = function(
+        &context,
+        "max",
+        1,
+        vec![
+            TypedVar::new(var(1), Type::Int),
+            TypedVar::new(var(2), Type::Int),
+        ],
+        Type::Int,
+        vec![
+            block(
+                1,
+                vec![Cmp::new(int(1), int(2), var(3)).into()],
+                Terminator::Branch(Branch::new(
+                    Value::new(ValueKind::Var(var(3)), Type::Bool),
+                    Label::new(block_id(2)),
+                    Label::new(block_id(3)),
+                )),
+            ),
+            block(
+                2,
+                vec![Assign::new(var(4), int(1), None).into()],
+                Terminator::Jump(block_id(4)),
+            ),
+            block(
+                3,
+                vec![Assign::new(var(4), int(2), None).into()],
+                Terminator::Jump(block_id(4)),
+            ),
+            block(4, Vec::new(), Terminator::Return(Return::new(Some(int(4))))),
+        ],
+    );
+
+    let mut print = function(
+        &context,
+        "print value",
+        2,
+        vec![TypedVar::new(var(6), Type::Uint)],
+        Type::Unit,
+        Vec::new(),
+    );
+    print.metadata = Metadata::default().with_attributes(FunctionAttributes::EXTERNAL);
+    print.metadata.params = vec![ParamAttributes::DEFAULT.zero_extended()];
+
+    let report = function(
+        &context,
+        "report",
+        3,
+        vec![TypedVar::new(var(7), Type::Uint)],
+        Type::Unit,
+        vec![block(
+            5,
+            vec![Call::new(
+                func_id(2),
+                vec![Value::new(ValueKind::Var(var(7)), Type::Uint)],
+                var(8),
+                Type::Infer,
+            )
+            .into()],
+            Terminator::Trap(TrapCode::DivisionByZero),
+        )],
+    );
+
+    let functions = vec![max, print, report];
+    let expected = "\
+define i64 @max(i64 %v0, i64 %v1) {
+bb0:
+    %v3.slot = alloca i64
+    %v2 = icmp eq i64 %v0, %v1
+    br i1 %v2, label %bb1, label %bb2
+
+bb1:
+    %t1 = bitcast i64 %v0 to i64
+    store i64 %t1, ptr %v3.slot
+    br label %bb3
+
+bb2:
+    %t2 = bitcast i64 %v1 to i64
+    store i64 %t2, ptr %v3.slot
+    br label %bb3
+
+bb3:
+    %t3 = load i64, ptr %v3.slot
+    ret i64 %t3
+}
+
+declare void @\"print value\"(i64 zeroext %v5)
+
+define void @report(i64 %v6) {
+bb4:
+    call void @\"print value\"(i64 %v6)
+    call void @llvm.trap()
+    unreachable
+}
+
+declare void @llvm.trap() cold noreturn nounwind
+";
+    assert_eq!(
+        llvm::emit(&functions, context.interner()).unwrap(),
+        expected
+    );
+
+    // Callees have to be emitted along with their callers
+    assert_eq!(
+        llvm::emit(&functions[2..], context.interner()),
+        Err(EmitError::UnknownFunction(func_id(2))),
+    );
+}
@@ -0,0 +1,177 @@
+use crate::{
+    builder::Context,
+    driver::{Driver, Pass},
+    repr::{
+        instruction::Call, rebase::ModuleMerger, remap, BasicBlockId, Constant, FuncId, Function,
+        Instruction, InstructionExt, Type, VarId,
+    },
+};
+use std::{collections::BTreeSet, sync::Arc};
+
+fn calls(function: &Function) -> Vec<FuncId> {
+    function
+        .basic_blocks
+        .iter()
+        .flat_map(|block| block.instructions.iter())
+        .filter_map(|inst| match inst {
+            Instruction::Call(Call { func, .. }) => Some(*func),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn merged_modules_have_unique_ids() {
+    // The first module defines three functions, the last of which is called from the second
+    let first = Arc::new(Context::new(0));
+    let mut builder = first.builder();
+    for value in 0..3 {
+        builder
+            .function(Type::Int, |func| {
+                func.basic_block(|block| {
+                    block.ret(Constant::Int(value))?;
+                    Ok(())
+                })?;
+
+                Ok(())
+            })
+            .unwrap();
+    }
+    let first_functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+    let external = first_functions[2].id;
+
+    // The second module is built by a separate context, so all of its ids start
+    // from the same place as the first module's
+    let second = Arc::new(Context::new(0));
+    let mut builder = second.builder();
+    let callee = builder
+        .function(Type::Int, |func| {
+            func.basic_block(|block| {
+                block.ret(Constant::Int(10))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    builder
+        .function(Type::Int, |func| {
+            func.basic_block(|block| {
+                let value = block.call(callee, Vec::new())?;
+                block.call(external, Vec::new())?;
+                block.ret(value)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let second_functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let context = Arc::new(Context::new(0));
+    let mut merger = ModuleMerger::new(context.clone());
+    assert!(merger.add_module(first_functions).is_empty());
+    let remapping = merger.add_module(second_functions);
+    assert!(!remapping.is_empty());
+    let functions = merger.finish();
+
+    let (mut function_ids, mut block_ids, mut var_ids) = (
+        BTreeSet::new(),
+        BTreeSet::<BasicBlockId>::new(),
+        BTreeSet::<VarId>::new(),
+    );
+    for function in functions.iter() {
+        assert!(
+            function_ids.insert(function.id),
+            "duplicate {:?}",
+            function.id
+        );
+
+        for block in function.basic_blocks.iter() {
+            assert!(block_ids.insert(block.id), "duplicate {:?}", block.id);
+
+            for inst in block.instructions.iter() {
+                assert!(var_ids.insert(inst.dest()), "duplicate {:?}", inst.dest());
+            }
+        }
+    }
+
+    // Calls within the second module follow the callee to its new id while
+    // calls into the first module are left alone
+    assert_eq!(
+        calls(&functions[4]),
+        vec![remapping.function(callee), external],
+    );
+    assert_eq!(functions[3].id, remapping.function(callee));
+
+    let output = Driver::new(context).run(functions, &[Pass::Cleanup]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+}
+
+#[test]
+fn cloned_functions_get_fresh_ids() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let callee = builder
+        .function(Type::Int, |func| {
+            func.basic_block(|block| {
+                block.ret(Constant::Int(10))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    builder
+        .function(Type::Int, |func| {
+            func.basic_block(|block| {
+                let value = block.call(callee, Vec::new())?;
+                block.ret(value)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    // Workers claim disjoint ranges from the shared context, so clones made by
+    // separate workers never share any ids
+    let (first, second) = (context.worker_ids(0, 2), context.worker_ids(1, 2));
+    let (first_clone, first_remapping) = remap::clone_function(&functions[1], &first);
+    let (second_clone, _) = remap::clone_function(&functions[1], &second);
+    let (third_clone, _) = remap::clone_function(&functions[1], &first);
+
+    let (mut function_ids, mut block_ids, mut var_ids) = (
+        BTreeSet::new(),
+        BTreeSet::<BasicBlockId>::new(),
+        BTreeSet::<VarId>::new(),
+    );
+    for function in functions
+        .iter()
+        .chain(vec![&first_clone, &second_clone, &third_clone])
+    {
+        assert!(
+            function_ids.insert(function.id),
+            "duplicate {:?}",
+            function.id
+        );
+
+        for block in function.basic_blocks.iter() {
+            assert!(block_ids.insert(block.id), "duplicate {:?}", block.id);
+
+            for inst in block.instructions.iter() {
+                assert!(var_ids.insert(inst.dest()), "duplicate {:?}", inst.dest());
+            }
+        }
+    }
+
+    // References to anything defined outside of the clone are kept
+    assert_eq!(calls(&first_clone), vec![callee]);
+    assert_eq!(first_clone.entry, first_remapping.block(functions[1].entry));
+}
@@ -1,14 +1,27 @@
 #![cfg(test)]
 
+mod algorithms;
+mod arbitrary;
+mod artifacts;
+mod builder;
+mod clif;
+mod errors;
+mod extraction;
+mod llvm;
+mod merge;
 mod num_folding;
+mod passes;
+mod session;
+mod traces;
+mod wasm;
 
 use crate::{
     builder::{Builder, Context},
     dataflow::{
         operators::{Cleanup, CrossbeamExtractor, CrossbeamPusher},
-        Diff, InputManager, ProgramVariable, Time, TraceManager,
+        Diff, ExtractionDisplay, InputManager, ProgramVariable, Time, TraceManager,
     },
-    optimize::{constant_folding, inline, peephole},
+    optimize::{constant_folding, copy_propagation, inline, peephole},
     repr::{
         function::Metadata,
         utils::{DisplayCtx, IRDisplay},
@@ -59,29 +72,19 @@ pub(crate) fn run_dataflow(workers: usize, builder: Builder, context: Arc<Contex
 
         let mut input_manager = worker.dataflow_named("inputs", |scope| {
             let mut input = InputManager::new(scope);
+            let imported = input.import(scope);
 
-            let (instructions, basic_blocks, functions) = (
-                input
-                    .instruction_trace
-                    .import(scope)
-                    .as_collection(|&inst_id, inst| (inst_id, inst.clone())),
-                input
-                    .basic_block_trace
-                    .import(scope)
-                    .as_collection(|&block, meta| (block, meta.clone())),
-                input
-                    .function_trace
-                    .import(scope)
-                    .as_collection(|&func, meta| (func, meta.clone())),
-            );
+            let errors = verify(
+                scope,
+                &imported.instructions(),
+                &imported.basic_blocks(),
+                &imported.functions(),
+            )
+            .probe_with(&mut probe);
 
-            let errors = verify(scope, &instructions, &basic_blocks, &functions)
-                .probe_with(&mut probe)
-                .arrange_by_self();
-
-            trace_manager.insert_trace::<TraceAgent<OrdKeySpine<ValidityError, Time, Diff>>>(
+            trace_manager.arrange_once::<TraceAgent<OrdKeySpine<ValidityError, Time, Diff>>, _>(
                 context.interner().get_or_intern_static("input/errors"),
-                errors.trace,
+                || errors.arrange_by_self().trace,
             );
 
             input
@@ -89,36 +92,7 @@ pub(crate) fn run_dataflow(workers: usize, builder: Builder, context: Arc<Contex
 
         let (mut program, mut inline_heuristics) =
             worker.dataflow_named::<Time, _, _>("constant propagation", |scope| {
-                let (instruction_trace, basic_block_trace, function_trace) = (
-                    input_manager.instruction_trace.import(scope),
-                    input_manager.basic_block_trace.import(scope),
-                    input_manager.function_trace.import(scope),
-                );
-
-                let instructions = instruction_trace.as_collection(|&id, inst| (id, inst.clone()));
-
-                let block_instructions = basic_block_trace.flat_map_ref(|&block, meta| {
-                    meta.instructions
-                        .clone()
-                        .into_iter()
-                        .map(move |inst| (inst, block))
-                });
-
-                let block_terminators = basic_block_trace
-                    .as_collection(|&block, meta| (block, meta.terminator.clone()));
-
-                let block_descriptors =
-                    basic_block_trace.as_collection(|&block, desc| (block, desc.clone()));
-
-                let function_blocks = function_trace.flat_map_ref(|&func, meta| {
-                    meta.basic_blocks
-                        .clone()
-                        .into_iter()
-                        .map(move |block| (block, func))
-                });
-
-                let function_descriptors =
-                    function_trace.as_collection(|&id, func| (id, func.clone()));
+                let input = input_manager.import_program(scope);
 
                 let program = scope
                     .scoped::<Product<_, Time>, _, _>("optimization", |scope| {
@@ -126,17 +100,19 @@ pub(crate) fn run_dataflow(workers: usize, builder: Builder, context: Arc<Contex
                             let summary = Product::new(Default::default(), 1);
 
                             let instructions =
-                                Variable::new_from(instructions.enter(scope), summary);
+                                Variable::new_from(input.instructions.enter(scope), summary);
                             let block_instructions =
-                                Variable::new_from(block_instructions.enter(scope), summary);
+                                Variable::new_from(input.block_instructions.enter(scope), summary);
                             let block_terminators =
-                                Variable::new_from(block_terminators.enter(scope), summary);
+                                Variable::new_from(input.block_terminators.enter(scope), summary);
                             let block_descriptors =
-                                Variable::new_from(block_descriptors.enter(scope), summary);
+                                Variable::new_from(input.block_descriptors.enter(scope), summary);
                             let function_blocks =
-                                Variable::new_from(function_blocks.enter(scope), summary);
-                            let function_descriptors =
-                                Variable::new_from(function_descriptors.enter(scope), summary);
+                                Variable::new_from(input.function_blocks.enter(scope), summary);
+                            let function_descriptors = Variable::new_from(
+                                input.function_descriptors.enter(scope),
+                                summary,
+                            );
 
                             ProgramVariable::new(
                                 instructions,
@@ -159,6 +135,7 @@ pub(crate) fn run_dataflow(workers: usize, builder: Builder, context: Arc<Contex
                         program.instructions = folded_instructions;
                         program.block_terminators = folded_terminators;
 
+                        program = copy_propagation::copy_propagation(scope, &program);
                         program.instructions = peephole::peephole(scope, &program.instructions);
 
                         program = program
@@ -215,6 +192,8 @@ pub(crate) fn run_dataflow(workers: usize, builder: Builder, context: Arc<Contex
                                 id: block_id,
                                 instructions: instructions.to_owned(),
                                 terminator: term.to_owned(),
+                                instruction_spans: Vec::new(),
+                                terminator_span: None,
                             },
                         ))
                     },
@@ -235,6 +214,8 @@ pub(crate) fn run_dataflow(workers: usize, builder: Builder, context: Arc<Contex
                                 id: block,
                                 instructions: Vec::new(),
                                 terminator,
+                                instruction_spans: Vec::new(),
+                                terminator_span: None,
                             },
                         )
                     }),
@@ -270,7 +251,7 @@ pub(crate) fn run_dataflow(workers: usize, builder: Builder, context: Arc<Contex
                         ret_ty: desc.ret_ty.clone(),
                         entry: desc.entry,
                         basic_blocks: blocks.clone(),
-                        metadata: metadata.clone(),
+                        metadata: metadata.clone().with_attributes(desc.attributes),
                     };
 
                     (func_id, func)
@@ -335,26 +316,11 @@ pub(crate) fn run_dataflow(workers: usize, builder: Builder, context: Arc<Contex
     })
     .expect("failed to start dataflow");
 
-    let alloc = BoxAllocator;
-    for (time, data) in CrossbeamExtractor::new(output_receiver).extract() {
-        println!("Data from timestamp {}:", time);
-
-        for (data, _time, _diff) in data {
-            match data {
-                Ok((_id, func)) => {
-                    func.display::<BoxAllocator, RefDoc, _>(DisplayCtx::new(
-                        &alloc,
-                        &*retained_context.interner(),
-                    ))
-                    .1
-                    .render(70, &mut io::stdout())
-                    .unwrap();
-                }
-
-                Err(err) => println!("Error: {:?}", err),
-            }
-        }
-    }
+    let extracted = CrossbeamExtractor::new(output_receiver).extract();
+    print!(
+        "{}",
+        ExtractionDisplay::from_env().render(&extracted, &*retained_context.interner()),
+    );
 }
 
 #[test]
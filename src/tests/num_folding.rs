@@ -1,9 +1,17 @@
 use crate::{
-    builder::Context,
-    repr::{Constant, Type},
+    builder::{BuilderError, Context},
+    driver::{Driver, Pass},
+    optimize::legalize::legalize_wide_integers,
+    repr::{
+        instruction::{Add, Assign},
+        terminator::{Branch, Label, Return},
+        BasicBlock, BasicBlockId, Constant, FuncId, Function, Instruction, InstructionExt,
+        Terminator, Type, TypedVar, Value, ValueKind, VarId,
+    },
     tests::run_dataflow,
+    verify::ValidityError,
 };
-use std::sync::Arc;
+use std::{num::NonZeroU64, sync::Arc};
 
 #[test]
 fn iadd_fold() {
@@ -92,3 +100,446 @@ fn looping() {
 
     run_dataflow(1, builder, context);
 }
+
+#[test]
+fn aggregate_extract_fold() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .named_function("aggregate_extract_fold", Type::Int, |func| {
+            func.named_basic_block("entry", |block| {
+                let pair = Constant::Struct(vec![Constant::Int(1), Constant::Int(2)]);
+                let v0 = block.assign(pair);
+                let v1 = block.insert_value(v0, 0, Constant::Int(40))?;
+                let v2 = block.extract_value(v1.clone(), 0)?;
+                let v3 = block.extract_value(v1, 1)?;
+                let v4 = block.add(v2, v3)?;
+
+                assert_eq!(
+                    block.extract_value(v4.clone(), 0),
+                    Err(BuilderError::InvalidAggregateIndex {
+                        ty: Type::Int,
+                        index: 0,
+                    }),
+                );
+
+                block.ret(v4)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions, &[Pass::ConditionalConstantPropagation]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+    assert_eq!(
+        output.functions[0].basic_blocks[0].terminator,
+        Terminator::Return(Return::new(Some(Constant::Int(42).into()))),
+    );
+}
+
+#[test]
+fn trapping_division_isnt_folded() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .named_function("trapping_division", Type::Int, |func| {
+            func.named_basic_block("entry", |block| {
+                let by_zero = block.div(Constant::Int(7), Constant::Int(0))?;
+                let overflow = block.div(Constant::Int(i64::MIN), Constant::Int(-1))?;
+                let remainder = block.rem(Constant::Int(7), Constant::Int(2))?;
+                let wrapped = block.add(Constant::Int(i64::MAX), Constant::Int(2))?;
+
+                let sum = block.add(by_zero, overflow)?;
+                let sum = block.add(sum, remainder)?;
+                let sum = block.add(sum, wrapped)?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    for &pass in &[Pass::ConstantFolding, Pass::ConditionalConstantPropagation] {
+        let output = Driver::new(context.clone()).run(functions.clone(), &[pass]);
+
+        // Dividing by a constant zero is only a warning
+        assert_eq!(output.errors.len(), 1, "{:?}", output.errors);
+        assert!(output.errors[0].is_warning());
+
+        let instructions = &output.functions[0].basic_blocks[0].instructions;
+        let divisions = instructions
+            .iter()
+            .filter(|inst| matches!(inst, Instruction::Div(_)))
+            .count();
+        assert_eq!(divisions, 2, "{} folded a trapping division", pass);
+        assert!(!instructions
+            .iter()
+            .any(|inst| matches!(inst, Instruction::Rem(_))));
+
+        // Overflowing arithmetic wraps around
+        let wrapped = instructions.iter().any(|inst| {
+            inst.used_values()
+                .iter()
+                .any(|value| value.as_const() == Some(&Constant::Int(i64::MIN + 1)))
+        });
+        assert!(wrapped, "{} didn't wrap around on overflow", pass);
+    }
+}
+
+#[test]
+fn wide_arithmetic_folds() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .named_function("wide_arithmetic", Type::Int128, |func| {
+            func.named_basic_block("entry", |block| {
+                let product = block.mul(Constant::Int128(i128::MAX), Constant::Int128(3))?;
+                let difference = block.sub(product, Constant::Int128(-(1 << 70)))?;
+                let negated = block.sub(Constant::Int128(0), difference)?;
+                let quotient = block.div(negated, Constant::Int128(7))?;
+
+                block.ret(quotient)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let expected = i128::MAX
+        .wrapping_mul(3)
+        .wrapping_sub(-(1 << 70))
+        .wrapping_neg()
+        / 7;
+    for &pass in &[Pass::ConstantFolding, Pass::ConditionalConstantPropagation] {
+        let output = Driver::new(context.clone()).run(functions.clone(), &[pass]);
+        assert!(output.errors.is_empty(), "{:?}", output.errors);
+        assert_eq!(
+            output.functions[0].basic_blocks[0].terminator,
+            Terminator::Return(Return::new(Some(Constant::Int128(expected).into()))),
+            "{} didn't fold 128-bit arithmetic",
+            pass,
+        );
+    }
+}
+
+#[test]
+fn float_arithmetic_folds_without_nans() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .named_function("float_arithmetic", Type::F64, |func| {
+            func.named_basic_block("entry", |block| {
+                let sum = block.add(Constant::f64(0.1), Constant::f64(0.2))?;
+                let product = block.mul(sum, Constant::f64(10.0))?;
+                let infinity = block.div(product, Constant::f64(0.0))?;
+                let nan = block.div(Constant::f64(0.0), Constant::f64(0.0))?;
+                let negated = block.neg(infinity)?;
+
+                let nan = block.add(nan, negated)?;
+                block.ret(nan)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions, &[Pass::ConditionalConstantPropagation]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+
+    let instructions = &output.functions[0].basic_blocks[0].instructions;
+    let folded = instructions.iter().any(|inst| {
+        inst.used_values()
+            .iter()
+            .any(|value| value.as_const() == Some(&Constant::f64(f64::NEG_INFINITY)))
+    });
+    assert!(folded, "float arithmetic wasn't folded: {:?}", instructions);
+
+    // The bits of a NaN are target specific, so producing one is left to the target
+    let divisions = instructions
+        .iter()
+        .filter(|inst| matches!(inst, Instruction::Div(_)))
+        .count();
+    assert_eq!(divisions, 1);
+}
+
+#[test]
+fn legalized_wide_arithmetic_folds_to_halves() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let (lhs, rhs) = (u128::MAX - 12345, (1 << 100) + 0xDEAD_BEEF);
+    builder
+        .named_function("wide_halves", Type::Uint128, |func| {
+            let (x, y) = (func.param(Type::Uint128), func.param(Type::Uint128));
+
+            func.named_basic_block("entry", |block| {
+                let product = block.mul(x.clone(), y.clone())?;
+                let sum = block.add(product, x)?;
+                let difference = block.sub(sum, y)?;
+
+                block.ret(difference)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    builder
+        .named_function("wide_equal", Type::Bool, |func| {
+            func.named_basic_block("entry", |block| {
+                let sum = block.add(Constant::Uint128(lhs), Constant::Uint128(rhs))?;
+                let equal = block.cmp(sum, Constant::Uint128(lhs.wrapping_add(rhs)))?;
+
+                block.ret(equal)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let legalized: Vec<Function> = functions
+        .iter()
+        .map(|function| legalize_wide_integers(function, &*context).unwrap())
+        .collect();
+    assert_eq!(legalized[0].params.len(), 4);
+    assert_eq!(
+        legalized[0].ret_ty,
+        Type::Tuple(vec![Type::Uint, Type::Uint]),
+    );
+
+    // Bind the parameters to constants so that the halves can be folded
+    let mut bound = legalized[0].clone();
+    let halves = [
+        lhs as u64,
+        (lhs >> 64) as u64,
+        rhs as u64,
+        (rhs >> 64) as u64,
+    ];
+    let bindings = bound
+        .params
+        .drain(..)
+        .zip(halves.iter())
+        .map(|(param, &half)| {
+            Instruction::Assign(Assign::new(param.var, Constant::Uint(half).into(), None))
+        });
+    let entry = &mut bound.basic_blocks[0];
+    entry.instructions.splice(0..0, bindings);
+    entry.instruction_spans.clear();
+    bound.metadata.params.clear();
+    bound.ret_ty = Type::Tuple(vec![Type::Uint, Type::Uint]);
+
+    let output = Driver::new(context.clone()).run(
+        vec![bound, legalized[1].clone()],
+        &[Pass::ConditionalConstantPropagation],
+    );
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+
+    let expected = lhs.wrapping_mul(rhs).wrapping_add(lhs).wrapping_sub(rhs);
+    assert_eq!(
+        output.functions[0].basic_blocks[0].terminator,
+        Terminator::Return(Return::multiple(vec![
+            Constant::Uint(expected as u64).into(),
+            Constant::Uint((expected >> 64) as u64).into(),
+        ])),
+    );
+    assert_eq!(
+        output.functions[1].basic_blocks[0].terminator,
+        Terminator::Return(Return::new(Some(Constant::Bool(true).into()))),
+    );
+}
+
+/// A function branching on `cond` into two arms which either return on their own
+/// or meet in a merge block
+fn branching(
+    cond: Value,
+    params: Vec<TypedVar>,
+    arms: [(Vec<Instruction>, Terminator); 2],
+    merge: Option<Terminator>,
+) -> Function {
+    let id = |id: u64| BasicBlockId::new(NonZeroU64::new(id).unwrap());
+    let (entry, then, else_) = (id(1), id(2), id(3));
+
+    let block = |id, instructions, terminator| BasicBlock {
+        name: None,
+        id,
+        instructions,
+        terminator,
+        instruction_spans: Vec::new(),
+        terminator_span: None,
+    };
+
+    let [(then_instructions, then_terminator), (else_instructions, else_terminator)] = arms;
+    let mut basic_blocks = vec![
+        block(
+            entry,
+            Vec::new(),
+            Branch::new(cond, Label::new(then), Label::new(else_)).into(),
+        ),
+        block(then, then_instructions, then_terminator),
+        block(else_, else_instructions, else_terminator),
+    ];
+    if let Some(merge) = merge {
+        basic_blocks.push(block(id(4), Vec::new(), merge));
+    }
+
+    Function {
+        name: None,
+        id: FuncId::new(NonZeroU64::new(1).unwrap()),
+        params,
+        ret_ty: Type::Int,
+        entry,
+        basic_blocks,
+        metadata: Default::default(),
+    }
+}
+
+fn block_with(function: &Function, id: u64) -> Option<&BasicBlock> {
+    let id = BasicBlockId::new(NonZeroU64::new(id).unwrap());
+    function.basic_blocks.iter().find(|block| block.id == id)
+}
+
+fn returning(value: Value) -> Terminator {
+    Terminator::Return(Return::new(Some(value)))
+}
+
+fn merging() -> Terminator {
+    Terminator::Jump(BasicBlockId::new(NonZeroU64::new(4).unwrap()))
+}
+
+#[test]
+fn constants_behind_untaken_branches_fold() {
+    let context = Arc::new(Context::new(0));
+    let dest = VarId::new(NonZeroU64::new(1).unwrap());
+
+    // Both arms define `dest`, but only the one that's taken counts
+    let function = branching(
+        Constant::Bool(true).into(),
+        Vec::new(),
+        [
+            (
+                vec![Assign::new(dest, Constant::Int(1).into(), None).into()],
+                merging(),
+            ),
+            (
+                vec![Assign::new(dest, Constant::Int(2).into(), None).into()],
+                merging(),
+            ),
+        ],
+        Some(returning(Value::new(ValueKind::Var(dest), Type::Int))),
+    );
+
+    let output = Driver::new(context).run(vec![function], &[Pass::ConditionalConstantPropagation]);
+    assert!(
+        output
+            .errors
+            .iter()
+            .all(|error| matches!(error, ValidityError::Redeclaration { .. })),
+        "{:?}",
+        output.errors,
+    );
+    assert_eq!(
+        block_with(&output.functions[0], 4).unwrap().terminator,
+        returning(Constant::Int(1).into()),
+    );
+}
+
+#[test]
+fn branches_on_parameters_execute_both_targets() {
+    let context = Arc::new(Context::new(0));
+    let id = |id: u64| VarId::new(NonZeroU64::new(id).unwrap());
+    let (cond, sum, double) = (TypedVar::new(id(1), Type::Bool), id(2), id(3));
+
+    let function = branching(
+        cond.clone().into(),
+        vec![cond],
+        [
+            (
+                vec![Add::new(Constant::Int(3).into(), Constant::Int(4).into(), sum).into()],
+                returning(Value::new(ValueKind::Var(sum), Type::Int)),
+            ),
+            (
+                vec![Add::new(Constant::Int(1).into(), Constant::Int(1).into(), double).into()],
+                returning(Value::new(ValueKind::Var(double), Type::Int)),
+            ),
+        ],
+        None,
+    );
+
+    let output = Driver::new(context).run(vec![function], &[Pass::ConditionalConstantPropagation]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+
+    // Either target could be taken, so both of them fold
+    for &(block, value) in &[(2, 7), (3, 2)] {
+        assert_eq!(
+            block_with(&output.functions[0], block).unwrap().terminator,
+            returning(Constant::Int(value).into()),
+        );
+    }
+}
+
+#[test]
+fn disagreeing_definitions_arent_constant() {
+    let context = Arc::new(Context::new(0));
+    let id = |id: u64| VarId::new(NonZeroU64::new(id).unwrap());
+    let (cond, dest) = (TypedVar::new(id(1), Type::Bool), id(2));
+
+    let definition =
+        |value| -> Instruction { Assign::new(dest, Constant::Int(value).into(), None).into() };
+    let function = branching(
+        cond.clone().into(),
+        vec![cond],
+        [
+            (vec![definition(1)], merging()),
+            (vec![definition(2)], merging()),
+        ],
+        Some(returning(Value::new(ValueKind::Var(dest), Type::Int))),
+    );
+
+    let output = Driver::new(context).run(vec![function], &[Pass::ConditionalConstantPropagation]);
+    let function = &output.functions[0];
+
+    // Each definition is left as-is instead of being duplicated or replaced
+    assert_eq!(
+        block_with(function, 2).unwrap().instructions,
+        vec![definition(1)],
+    );
+    assert_eq!(
+        block_with(function, 3).unwrap().instructions,
+        vec![definition(2)],
+    );
+    assert_eq!(
+        block_with(function, 4).unwrap().terminator,
+        returning(Value::new(ValueKind::Var(dest), Type::Int)),
+    );
+}
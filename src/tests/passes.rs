@@ -0,0 +1,2073 @@
+use crate::{
+    builder::{BuildResult, BuilderError, Context, FunctionBuilder},
+    dataflow::{
+        analysis::{dominance_frontiers, dominators, phi_placements},
+        call_graph::{call_graph, call_graph_with, recursive_functions},
+        panics::{self, PanicContext},
+        Budget, BudgetExceeded, BudgetKind, Cardinalities, Diff, InputManager, JoinOrder,
+        Partitioning, Program, Time,
+    },
+    driver::{load_functions, Analysis, Driver, Pass, PassManager, Step},
+    equisat::{self, EGraph, ENode, ENodeId, ENodeSlot, RedundantAddSubChain},
+    optimize::{
+        analysis::{
+            eliminate_dead_stores, impossible_branches, value_ranges, variable_ranges, Access,
+            Liveness, Range,
+        },
+        autotune::Autotuner,
+        cost::{CostModel, DefaultCostModel},
+        if_conversion,
+        inline::InlineHeuristics,
+        layout,
+        loop_unroll::{self, UnrollBudget},
+        peephole::{PeepholePass, PeepholeRule},
+        purity::{function_purity, pure_calls, pure_functions},
+        rewrites,
+        size::{function_sizes, FunctionSize, SizeTable},
+        tail_call,
+    },
+    repr::{
+        arbitrary::{ArbitraryConfig, IrGenerator},
+        basic_block::BasicBlockDesc,
+        function::FunctionDesc,
+        instruction::{Assign, BinopExt, Call, Select},
+        terminator::{Branch, Label, Return},
+        utils::IRDisplay,
+        BasicBlock, BasicBlockId, CallingConvention, Constant, FuncId, Function,
+        FunctionAttributes, Ident, InstId, Instruction, InstructionExt, ParamAttributes, SourceLoc,
+        Span, Terminator, Type, TypedVar, Value, ValueKind, VarId,
+    },
+    runtime::{InputDistribution, Runtime, RuntimeConfig},
+    testing::PassTest,
+};
+use differential_dataflow::{input::Input, operators::Consolidate, Collection, ExchangeData};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    hash::Hash,
+    num::NonZeroU64,
+    sync::{Arc, Mutex},
+};
+use timely::{
+    communication::allocator::Thread,
+    dataflow::{scopes::Child, ProbeHandle, Scope},
+    order::Product,
+    worker::Worker,
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    layer::{self, Layer, SubscriberExt},
+    util::SubscriberInitExt,
+};
+
+/// The number of randomly generated modules each pass is tested against
+const CORPUS_SIZE: u64 = 16;
+
+/// The most rounds of the full pass list a module may need before it stops changing
+const CONVERGENCE_BOUND: usize = 8;
+
+/// Runs `pass` over the functions twice, asserting that the second run doesn't
+/// change anything
+fn assert_idempotent(driver: &Driver, pass: Pass, functions: Vec<Function>) {
+    let first = driver.run(functions, &[pass]);
+    assert!(first.errors.is_empty(), "{:?}", first.errors);
+
+    let second = driver.run(first.functions.clone(), &[pass]);
+    assert_eq!(
+        first.functions, second.functions,
+        "running {} a second time changed the program",
+        pass,
+    );
+}
+
+/// Repeatedly runs every pass over the functions until they stop changing, returning
+/// the number of rounds that took or `None` if they didn't settle within `bound` rounds
+fn rounds_to_converge(
+    driver: &Driver,
+    mut functions: Vec<Function>,
+    bound: usize,
+) -> Option<usize> {
+    for round in 1..=bound {
+        let output = driver.run(functions.clone(), Pass::ALL);
+        if output.functions == functions {
+            return Some(round);
+        }
+
+        functions = output.functions;
+    }
+
+    None
+}
+
+#[test]
+fn passes_are_idempotent() {
+    for seed in 1..=CORPUS_SIZE {
+        let context = Arc::new(Context::new(0));
+        let functions = random_functions(&context, seed);
+        let driver = Driver::new(context);
+
+        for &pass in Pass::ALL {
+            assert_idempotent(&driver, pass, functions.clone());
+        }
+    }
+}
+
+#[test]
+fn optimization_converges() {
+    for seed in 1..=CORPUS_SIZE {
+        let context = Arc::new(Context::new(0));
+        let functions = random_functions(&context, seed);
+        let driver = Driver::new(context);
+
+        assert!(
+            rounds_to_converge(&driver, functions, CONVERGENCE_BOUND).is_some(),
+            "the module generated from seed {} didn't converge within {} rounds",
+            seed,
+            CONVERGENCE_BOUND,
+        );
+    }
+}
+
+#[test]
+fn custom_peephole_rules() {
+    fn add_zero(inst: &Instruction) -> Option<Instruction> {
+        match inst {
+            Instruction::Add(add) if add.rhs().as_const().map_or(false, Constant::is_zero) => Some(
+                Instruction::Assign(Assign::new(add.dest(), add.lhs(), None)),
+            ),
+            _ => None,
+        }
+    }
+
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let sum = block.add(x, Constant::Int(0))?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    let add = &function.basic_blocks[0].instructions[0];
+    assert_eq!(PeepholePass::new().rewrite(add), None);
+
+    let pass = PeepholePass::new().with_rule(PeepholeRule::new("add zero", add_zero));
+    assert!(matches!(pass.rewrite(add), Some(Instruction::Assign(_))));
+}
+
+#[test]
+fn panics_are_tagged_with_context() {
+    panics::install_hook();
+
+    let result = panics::catch(PanicContext::stage("testing"), || {
+        panics::with_context(
+            PanicContext::pass("broken").with_rule("always panics"),
+            || panic!("rule failed"),
+        )
+    });
+
+    let diagnostic = result.unwrap_err();
+    assert_eq!(diagnostic.message.as_deref(), Some("rule failed"));
+    assert_eq!(
+        diagnostic.context,
+        PanicContext::pass("broken").with_rule("always panics"),
+    );
+    assert_eq!(panics::current_context(), None);
+
+    assert_eq!(panics::catch(PanicContext::stage("testing"), || 1), Ok(1));
+}
+
+#[test]
+fn opaque_instructions_are_kept() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .function(Type::Int, |func| {
+            func.basic_block(|block| {
+                // The result of the opaque instruction is never used, but it's
+                // impure so it can't be removed
+                block.opaque("x86_64", "mfence", Vec::new(), Type::Unit);
+                block.ret(Constant::Int(0))?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions.clone(), Pass::ALL);
+    assert_eq!(
+        output.functions[0].basic_blocks[0].instructions,
+        functions[0].basic_blocks[0].instructions,
+    );
+}
+
+#[test]
+fn effectful_instructions_survive_cleanup() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                // Neither store's result is used but both have effects, the
+                // addition between them is dead and can be removed
+                block.opaque("wasm32", "i32.store", vec![x.clone().into()], Type::Unit);
+                block.add(x.clone(), Constant::Int(1))?;
+                block.opaque("wasm32", "i32.store", vec![x.into()], Type::Unit);
+                block.ret(Constant::Int(0))?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions.clone(), &[Pass::Cleanup]);
+    let original = &functions[0].basic_blocks[0].instructions;
+    assert_eq!(
+        output.functions[0].basic_blocks[0].instructions,
+        vec![original[0].clone(), original[2].clone()],
+    );
+}
+
+#[test]
+fn external_calls_survive_cleanup() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let log = builder.declare_external("log", vec![Type::Int], Type::Int);
+    builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                // The call's result is unused, but the external function may have effects
+                block.call(log, vec![x.into()])?;
+                block.ret(Constant::Int(0))?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+    assert!(functions[0].basic_blocks.is_empty());
+
+    let output = Driver::new(context).run(functions.clone(), Pass::ALL);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+
+    let external = output
+        .functions
+        .iter()
+        .find(|function| function.id == log)
+        .expect("the called external function was removed");
+    assert!(external.metadata.attributes.is_external());
+    assert!(external.basic_blocks.is_empty());
+
+    let caller = output
+        .functions
+        .iter()
+        .find(|function| function.id != log)
+        .unwrap();
+    assert!(matches!(
+        caller.basic_blocks[0].instructions.as_slice(),
+        [Instruction::Call(call)] if call.func == log,
+    ));
+}
+
+#[test]
+fn function_attributes_are_kept() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let attributes = FunctionAttributes::COLD | FunctionAttributes::EXPORT;
+    let cold = builder
+        .function(Type::Uint, |func| {
+            func.with_attrs(attributes).basic_block(|block| {
+                block.ret(Constant::Uint(1))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let hot = builder
+        .function(Type::Uint, |func| {
+            func.basic_block(|block| {
+                block.ret(Constant::Uint(2))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+    assert_eq!(functions[0].metadata.attributes, attributes);
+
+    // Cold functions are laid out after everything else
+    let output = Driver::new(context).run(functions, Pass::ALL);
+    let ids: Vec<_> = output
+        .functions
+        .iter()
+        .map(|function| function.id)
+        .collect();
+    assert_eq!(ids, vec![hot, cold]);
+    assert_eq!(output.functions[1].metadata.attributes, attributes);
+}
+
+#[test]
+fn multi_worker_runtime_matches_driver() {
+    let context = Arc::new(Context::new(0));
+    let functions = random_functions(&context, 1);
+    let expected = Driver::new(context.clone()).run(functions.clone(), Pass::ALL);
+
+    for &distribution in &[InputDistribution::Sink, InputDistribution::Partitioned] {
+        let config = RuntimeConfig::process(3).with_distribution(distribution);
+        let output = Runtime::new(context.clone(), config)
+            .run(functions.clone(), Pass::ALL)
+            .unwrap()
+            .expect("the current process hosts the sink worker");
+
+        assert_eq!(output, expected, "{:?} distribution", distribution);
+    }
+}
+
+#[test]
+fn partitioning_by_function_matches_driver() {
+    let context = Arc::new(Context::new(0));
+    let functions = random_functions(&context, 2);
+    let expected = Driver::new(context.clone()).run(functions.clone(), Pass::ALL);
+
+    for &distribution in &[InputDistribution::Sink, InputDistribution::Partitioned] {
+        let config = RuntimeConfig::process(3)
+            .with_distribution(distribution)
+            .with_partitioning(Partitioning::ByFunction);
+        let output = Runtime::new(context.clone(), config)
+            .run(functions.clone(), Pass::ALL)
+            .unwrap()
+            .expect("the current process hosts the sink worker");
+
+        assert_eq!(output, expected, "{:?} distribution", distribution);
+    }
+}
+
+#[test]
+fn functions_over_budget_are_left_untouched() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut ids = Vec::new();
+    for &adds in &[1, 4] {
+        let id = builder
+            .function(Type::Int, |func| {
+                func.basic_block(|block| {
+                    let mut sum = block.assign(Constant::Int(0));
+                    for _ in 0..adds {
+                        sum = block.add(sum, Constant::Int(1))?;
+                    }
+                    block.ret(sum)?;
+
+                    Ok(())
+                })?;
+
+                Ok(())
+            })
+            .unwrap();
+        ids.push(id);
+    }
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    let budget = Budget::unlimited().with_max_instructions(Some(3));
+    let output = Driver::new(context)
+        .with_budget(budget)
+        .run(functions.clone(), &[Pass::ConstantFolding]);
+
+    assert_eq!(
+        output.budget_exceeded,
+        vec![BudgetExceeded {
+            func: ids[1],
+            pass: None,
+            kind: BudgetKind::Instructions,
+            limit: 3,
+            actual: 5,
+        }],
+    );
+
+    let find = |functions: &[Function], id| {
+        functions
+            .iter()
+            .find(|function| function.id == id)
+            .cloned()
+            .unwrap()
+    };
+    assert_eq!(find(&output.functions, ids[1]), find(&functions, ids[1]));
+    assert_ne!(find(&output.functions, ids[0]), find(&functions, ids[0]));
+}
+
+#[test]
+fn sanitized_passes_keep_functions_valid_and_cheap() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let sum = block.add(Constant::Int(1), Constant::Int(2))?;
+                let product = block.mul(x, sum)?;
+                block.ret(product)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context)
+        .with_sanitizer(true)
+        .run(functions, &[Pass::ConstantFolding, Pass::Cleanup]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+    assert!(output.sanitizer.is_empty(), "{:?}", output.sanitizer);
+}
+
+/// Collects the fields of every rewrite event
+#[derive(Clone, Default)]
+struct RewriteEvents(Arc<Mutex<Vec<BTreeMap<String, String>>>>);
+
+impl<S: Subscriber> Layer<S> for RewriteEvents {
+    fn on_event(&self, event: &Event<'_>, _ctx: layer::Context<'_, S>) {
+        if event.metadata().target() == rewrites::REWRITES {
+            let mut fields = EventFields(BTreeMap::new());
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+}
+
+struct EventFields(BTreeMap<String, String>);
+
+impl Visit for EventFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), format!("{:?}", value));
+    }
+}
+
+#[test]
+fn rewrites_are_logged_with_their_functions() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let func = builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let difference = block.sub(x, Constant::Int(0))?;
+                block.ret(difference)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let events = RewriteEvents::default();
+    let subscriber = tracing_subscriber::registry().with(events.clone());
+    {
+        let _guard = subscriber.set_default();
+        Driver::new(context).run(functions, &[Pass::Peephole]);
+    }
+
+    // Whole passes are only logged within debug builds
+    let events = events.0.lock().unwrap();
+    if cfg!(debug_assertions) {
+        let event = events
+            .iter()
+            .find(|event| event["rule"] == "peephole")
+            .expect("the rewrite wasn't logged");
+
+        assert_eq!(event["func"], format!("{:?}", Some(func)));
+        assert_ne!(event["before"], event["after"]);
+    }
+}
+
+/// Generates the module of the corpus with the given seed
+fn random_functions(context: &Arc<Context>, seed: u64) -> Vec<Function> {
+    let config = ArbitraryConfig {
+        max_functions: 3,
+        ..ArbitraryConfig::default()
+    };
+
+    IrGenerator::new(seed)
+        .with_config(config)
+        .functions(context)
+}
+
+#[test]
+fn identical_functions_are_merged() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut add_one = || {
+        builder.function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let sum = block.add(x, Constant::Int(1))?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+    };
+    let (first, second) = (add_one().unwrap(), add_one().unwrap());
+
+    builder
+        .function(Type::Int, |func| {
+            func.with_attrs(FunctionAttributes::EXPORT);
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let mut lhs = block.call(first, vec![x.clone().into()])?;
+                lhs.ty = Type::Int;
+                let mut rhs = block.call(second, vec![x.into()])?;
+                rhs.ty = Type::Int;
+
+                let sum = block.add(lhs, rhs)?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions, &[Pass::MergeFunctions]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+    assert_eq!(output.functions.len(), 2);
+    assert!(output
+        .functions
+        .iter()
+        .all(|function| function.id != second));
+
+    let callees: Vec<_> = output
+        .functions
+        .iter()
+        .flat_map(|function| function.basic_blocks.iter())
+        .flat_map(|block| block.instructions.iter())
+        .filter_map(|inst| match inst {
+            Instruction::Call(call) => Some(call.func),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(callees, vec![first, first]);
+}
+
+#[test]
+fn fuel_limits_rewrites() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let a = block.sub(x.clone(), Constant::Int(0))?;
+                let b = block.mul(x.clone(), Constant::Int(0))?;
+                let c = block.sub(x, Constant::Int(0))?;
+                let sum = block.add(a, b)?;
+                let sum = block.add(sum, c)?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let driver = Driver::new(context);
+    let original = driver.run(functions.clone(), &[]).functions;
+    let rewrites = |fuel: Option<usize>| {
+        let output = driver
+            .clone()
+            .with_fuel(fuel)
+            .run(functions.clone(), &[Pass::Peephole])
+            .functions;
+
+        output[0].basic_blocks[0]
+            .instructions
+            .iter()
+            .zip(original[0].basic_blocks[0].instructions.iter())
+            .filter(|(output, original)| output != original)
+            .count()
+    };
+
+    assert_eq!(rewrites(Some(0)), 0);
+    assert_eq!(rewrites(Some(1)), 1);
+    assert_eq!(rewrites(Some(2)), 2);
+    assert_eq!(rewrites(Some(10)), 3);
+    assert_eq!(rewrites(None), 3);
+}
+
+#[test]
+fn copy_propagation_removes_assigns() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .function(Type::Uint, |func| {
+            let x = func.param(Type::Uint);
+
+            func.basic_block(|block| {
+                let limit = block.assign(Constant::Uint(100));
+                let copy = block.assign(limit);
+                let sum = block.add(x, copy)?;
+                let result = block.assign(sum);
+                block.ret(result)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions, &[Pass::CopyPropagation]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+
+    let block = &output.functions[0].basic_blocks[0];
+    assert_eq!(block.instructions.len(), 1, "{:?}", block.instructions);
+    assert!(!matches!(block.instructions[0], Instruction::Assign(_)));
+    assert_eq!(
+        block.instructions[0].used_values()[1].as_const(),
+        Some(&Constant::Uint(100)),
+    );
+}
+
+#[test]
+fn schedule_places_values_before_their_use() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let mut dests = Vec::new();
+    builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let a = block.add(x.clone(), Constant::Int(1))?;
+                let b = block.mul(x, Constant::Int(2))?;
+                let c = block.sub(a.clone(), Constant::Int(3))?;
+                let d = block.add(b.clone(), c.clone())?;
+                block.ret(d.clone())?;
+
+                // `b` is consumed before `c`, which is computed from `a`
+                dests = vec![b.var, a.var, c.var, d.var];
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions, &[Pass::Schedule]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+
+    let scheduled: Vec<_> = output.functions[0].basic_blocks[0]
+        .instructions
+        .iter()
+        .map(|inst| inst.dest())
+        .collect();
+    assert_eq!(scheduled, dests);
+}
+
+#[test]
+fn pass_tests_render_optimized_functions() {
+    let snapshot = PassTest::named(&["constant-folding", "cleanup"])
+        .build(|builder| {
+            builder.named_function("snapshot", Type::Int, |func| {
+                func.basic_block(|block| {
+                    let one = block.assign(Constant::Int(1));
+                    let sum = block.add(one, Constant::Int(2))?;
+                    block.ret(sum)?;
+
+                    Ok(())
+                })?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .run();
+
+    assert_eq!(
+        snapshot.functions[0].basic_blocks[0].terminator,
+        Terminator::Return(Return::new(Some(Constant::Int(3).into()))),
+    );
+    assert!(snapshot.text().contains("snapshot"));
+    assert!(!snapshot.text().contains("add"));
+
+    // Expected output can be indented along with the test
+    let indented = snapshot.text().replace('\n', "\n        ");
+    snapshot.assert_matches(&format!("\n        {}\n    ", indented));
+}
+
+#[test]
+fn parameter_attributes_and_calling_conventions_are_kept() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let attributes = ParamAttributes::DEFAULT.zero_extended().noalias();
+    builder
+        .named_function("extern_c", Type::Uint, |func| {
+            func.with_calling_convention(CallingConvention::C);
+            let x = func.param_with_attrs(Type::Uint, attributes);
+            func.param(Type::Uint);
+
+            func.basic_block(|block| {
+                block.ret(x)?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+    assert_eq!(
+        functions[0].metadata.params,
+        vec![attributes, ParamAttributes::DEFAULT]
+    );
+
+    let output = Driver::new(context.clone()).run(functions, &[Pass::Cleanup]);
+    let metadata = &output.functions[0].metadata;
+    assert_eq!(metadata.calling_convention, CallingConvention::C);
+    assert_eq!(metadata.param(0), attributes);
+    assert_eq!(metadata.param(1), ParamAttributes::DEFAULT);
+
+    let rendered = output.functions[0].to_pretty_string(context.interner());
+    assert!(rendered.contains("calling convention: c"), "{}", rendered);
+    assert!(rendered.contains("zext noalias"), "{}", rendered);
+}
+
+#[test]
+fn equality_saturation_extracts_equivalent_values() {
+    let mut params = Vec::new();
+    let snapshot = PassTest::new(&[
+        Pass::EqualitySaturation,
+        Pass::CopyPropagation,
+        Pass::Cleanup,
+    ])
+    .build(|builder| {
+        builder.named_function("redundant", Type::Int, |func| {
+            let x = func.param(Type::Int);
+            let y = func.param(Type::Int);
+            params.push(y.clone());
+
+            func.basic_block(|block| {
+                // `(x + (y - x)) + 0` is `y`
+                let diff = block.sub(y, x.clone())?;
+                let sum = block.add(x, diff)?;
+                let result = block.add(sum, Constant::Int(0))?;
+                block.ret(result)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })?;
+
+        builder.named_function("literal", Type::Int, |func| {
+            func.basic_block(|block| {
+                let sum = block.add(Constant::Int(2), Constant::Int(3))?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    })
+    .run();
+
+    let redundant = &snapshot.functions[0].basic_blocks[0];
+    assert!(redundant.instructions.is_empty(), "{}", snapshot);
+    assert_eq!(
+        redundant.terminator,
+        Terminator::Return(Return::new(Some(params[0].clone().into()))),
+    );
+
+    let literal = &snapshot.functions[1].basic_blocks[0];
+    assert!(literal.instructions.is_empty(), "{}", snapshot);
+    assert_eq!(
+        literal.terminator,
+        Terminator::Return(Return::new(Some(Constant::Int(5).into()))),
+    );
+}
+
+#[test]
+fn egraphs_saturate_within_nested_scopes() {
+    let (x, y, sub, add) = (
+        ENodeId::new(0),
+        ENodeId::new(1),
+        ENodeId::new(2),
+        ENodeId::new(3),
+    );
+    let eclasses = Arc::new(Mutex::new(BTreeMap::new()));
+
+    let captured = eclasses.clone();
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<usize, _, _>(|scope| {
+            // `x + (y - x)` is `y`
+            let (_enodes, enodes) = scope.new_collection_from(vec![
+                (x, ENode::Constant),
+                (y, ENode::Constant),
+                (
+                    sub,
+                    ENode::Sub(equisat::Sub::new(y.as_eclass(), x.as_eclass())),
+                ),
+                (
+                    add,
+                    ENode::Add(equisat::Add::new(x.as_eclass(), sub.as_eclass())),
+                ),
+            ]);
+
+            // The outer egraph has no rewrites of its own, everything it knows about
+            // comes from the one saturated within the nested scope
+            scope
+                .iterative::<Time, _, _>(|outer| {
+                    let mut graph =
+                        EGraph::<_, Diff>::new(outer, Product::new(Default::default(), 1));
+                    graph.add_enodes(enodes.enter(outer));
+
+                    outer.iterative::<Time, _, _>(|inner| {
+                        let mut nested = graph.enter(inner, Product::new(Default::default(), 1));
+                        nested.add_rewrite(RedundantAddSubChain);
+                        nested.leave(&mut graph);
+                    });
+
+                    let (_enodes, eclasses) = graph.feedback();
+                    eclasses.leave()
+                })
+                .consolidate()
+                .inspect(move |&((enode, eclass), _, diff)| {
+                    let mut eclasses = captured.lock().unwrap();
+                    *eclasses.entry((enode, eclass)).or_insert(0) += diff;
+                });
+        });
+    });
+
+    let eclasses: BTreeMap<ENodeId, _> = eclasses
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|&(_, &diff)| diff > 0)
+        .map(|(&(enode, eclass), _)| (enode, eclass))
+        .collect();
+    assert_eq!(eclasses.len(), 4, "{:?}", eclasses);
+    assert_eq!(eclasses[&add], eclasses[&y]);
+    assert_ne!(eclasses[&x], eclasses[&y]);
+    assert_ne!(eclasses[&sub], eclasses[&y]);
+}
+
+#[test]
+fn enode_ids_are_derived_from_instructions() {
+    let inst = InstId::new(NonZeroU64::new(5).unwrap());
+    let var = VarId::new(NonZeroU64::new(5).unwrap());
+
+    let ids = [
+        ENodeId::from_inst(inst, ENodeSlot::Result),
+        ENodeId::from_inst(inst, ENodeSlot::Lhs),
+        ENodeId::from_inst(inst, ENodeSlot::Rhs),
+        ENodeId::from_var(var),
+    ];
+    let distinct: BTreeSet<_> = ids.iter().collect();
+    assert_eq!(distinct.len(), ids.len());
+
+    // Lowering the same program again gives the same ids
+    assert_eq!(ENodeId::from_inst(inst, ENodeSlot::Lhs), ids[1]);
+    assert_eq!(ENodeId::from_var(var), ids[3]);
+}
+
+#[test]
+fn canonicalize_orders_commutative_operands() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let mut params = Vec::new();
+    builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+            let y = func.param(Type::Int);
+            params = vec![Value::from(x.clone()), Value::from(y.clone())];
+
+            func.basic_block(|block| {
+                let sum = block.add(Constant::Int(1), y.clone())?;
+                let product = block.mul(y, x.clone())?;
+                let difference = block.sub(Constant::Int(1), x)?;
+                let total = block.add(sum, product)?;
+                let total = block.add(total, difference)?;
+                block.ret(total)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions, &[Pass::Canonicalize]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+
+    let (x, y) = (params[0].clone(), params[1].clone());
+    let operands: Vec<_> = output.functions[0].basic_blocks[0].instructions[..3]
+        .iter()
+        .map(|inst| inst.used_values().into_iter().cloned().collect::<Vec<_>>())
+        .collect();
+    assert_eq!(
+        operands,
+        vec![
+            vec![y.clone(), Constant::Int(1).into()],
+            vec![x.clone(), y],
+            // Subtraction isn't commutative
+            vec![Constant::Int(1).into(), x],
+        ],
+    );
+}
+
+#[test]
+fn pass_manager_shares_analyses_until_invalidated() {
+    let schedule = PassManager::new()
+        .add_pass(Pass::ConditionalConstantPropagation)
+        .add_pass(Pass::Canonicalize)
+        .add_pass(Pass::CopyPropagation)
+        .add_pass(Pass::Schedule)
+        .add_pass(Pass::Schedule)
+        .add_pass(Pass::Cleanup)
+        .schedule();
+
+    assert_eq!(
+        schedule,
+        vec![
+            Step::Build(Analysis::UseDef),
+            Step::Run(Pass::ConditionalConstantPropagation),
+            Step::Invalidate(Analysis::UseDef),
+            Step::Run(Pass::Canonicalize),
+            Step::Build(Analysis::UseDef),
+            Step::Run(Pass::CopyPropagation),
+            Step::Invalidate(Analysis::UseDef),
+            Step::Build(Analysis::UseDef),
+            Step::Run(Pass::Schedule),
+            Step::Run(Pass::Schedule),
+            Step::Run(Pass::Cleanup),
+            Step::Invalidate(Analysis::UseDef),
+        ],
+    );
+
+    // Every analysis a pass reads is built before it runs
+    let mut available = BTreeSet::new();
+    for step in PassManager::from_passes(Pass::ALL).schedule() {
+        match step {
+            Step::Build(analysis) => assert!(available.insert(analysis)),
+            Step::Invalidate(analysis) => assert!(available.remove(&analysis)),
+            Step::Run(pass) => {
+                for analysis in pass.required_analyses() {
+                    assert!(
+                        available.contains(analysis),
+                        "{} requires {}",
+                        pass,
+                        analysis
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn loops_that_cant_repeat_run_once() {
+    let id = |id: u64| BasicBlockId::new(NonZeroU64::new(id).unwrap());
+    let (entry, header, body, exit) = (id(1), id(2), id(3), id(4));
+    let cond = Value::new(
+        ValueKind::Var(VarId::new(NonZeroU64::new(1).unwrap())),
+        Type::Bool,
+    );
+
+    let blocks: BTreeMap<_, _> = vec![
+        BasicBlockDesc::new(None, entry, Vec::new(), Terminator::Jump(header)),
+        BasicBlockDesc::new(
+            None,
+            header,
+            Vec::new(),
+            Terminator::Branch(Branch::new(cond, Label::new(body), Label::new(exit))),
+        ),
+        BasicBlockDesc::new(None, body, Vec::new(), Terminator::Jump(header)),
+        BasicBlockDesc::new(
+            None,
+            exit,
+            Vec::new(),
+            Terminator::Return(Return::new(None)),
+        ),
+    ]
+    .into_iter()
+    .map(|desc| (desc.id, desc))
+    .collect();
+
+    let loops = loop_unroll::natural_loops(entry, &blocks);
+    assert_eq!(loops.len(), 1);
+    assert_eq!(loops[0].header, header);
+    assert_eq!(loops[0].blocks, vec![header, body].into_iter().collect());
+
+    // The loop repeats unless its body can never be entered
+    assert_eq!(
+        loop_unroll::trip_count(&loops[0], &blocks, &BTreeSet::new()),
+        None,
+    );
+    let impossible = Some((header, body)).into_iter().collect();
+    assert_eq!(
+        loop_unroll::trip_count(&loops[0], &blocks, &impossible),
+        Some(1),
+    );
+
+    let budget = UnrollBudget::default();
+    assert!(budget.allows(1, 10));
+    assert!(!budget.allows(budget.max_trip_count + 1, 1));
+}
+
+#[test]
+fn self_recursive_returns_are_tail_calls() {
+    let (func, block) = (
+        FuncId::new(NonZeroU64::new(1).unwrap()),
+        BasicBlockId::new(NonZeroU64::new(1).unwrap()),
+    );
+    let (param, dest) = (
+        TypedVar::new(VarId::new(NonZeroU64::new(1).unwrap()), Type::Int),
+        VarId::new(NonZeroU64::new(2).unwrap()),
+    );
+    let function = FunctionDesc::new(
+        None,
+        func,
+        vec![param.clone()],
+        Type::Int,
+        block,
+        vec![block],
+    );
+
+    let call = Call::new(
+        func,
+        vec![Value::new(ValueKind::Var(param.var), Type::Int)],
+        dest,
+        Type::Int,
+    );
+    let returning = |value: VarId| {
+        BasicBlockDesc::new(
+            None,
+            block,
+            Vec::new(),
+            Terminator::Return(Return::new(Some(Value::new(
+                ValueKind::Var(value),
+                Type::Int,
+            )))),
+        )
+    };
+
+    assert!(tail_call::is_tail_call(&function, &returning(dest), &call));
+    // Returning anything but the call's result needs the call's frame to stick around
+    assert!(!tail_call::is_tail_call(
+        &function,
+        &returning(param.var),
+        &call
+    ));
+}
+
+#[test]
+fn tail_calls_become_loops() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut params = None;
+    let func = builder
+        .function(Type::Int, |func| {
+            let (n, acc) = (func.param(Type::Int), func.param(Type::Int));
+            let id = func.func_id();
+
+            func.basic_block(|block| {
+                let next = block.sub(n.clone(), Constant::Int(1))?;
+                // `acc` is rebound to `n`, which the same call rebinds as well
+                let mut result = block.call(id, vec![next.clone().into(), n.clone().into()])?;
+                result.ty = Type::Int;
+                block.ret(result)?;
+
+                params = Some((n.var, acc.var, next.var));
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+    let (n, acc, next) = params.unwrap();
+    let header = functions[0].entry;
+
+    type Captured<K, V> = Arc<Mutex<BTreeMap<(K, V), Diff>>>;
+    let (descriptors, blocks, instructions): (
+        Captured<FuncId, FunctionDesc>,
+        Captured<BasicBlockId, BasicBlockDesc>,
+        Captured<InstId, Instruction>,
+    ) = Default::default();
+
+    let captured = (descriptors.clone(), blocks.clone(), instructions.clone());
+    timely::execute_directly(move |worker| {
+        let mut input = worker.dataflow(|scope| InputManager::<Time, Diff>::new(scope));
+
+        let mut probe = ProbeHandle::new();
+        worker.dataflow(|scope| {
+            let program = tail_call::tail_call(&input.import_program(scope), context.clone());
+
+            let (descriptors, blocks, instructions) = captured;
+            program
+                .function_descriptors
+                .consolidate()
+                .inspect(move |(data, _, diff)| {
+                    *descriptors.lock().unwrap().entry(data.clone()).or_insert(0) += diff;
+                })
+                .probe_with(&mut probe);
+            program
+                .block_descriptors
+                .consolidate()
+                .inspect(move |(data, _, diff)| {
+                    *blocks.lock().unwrap().entry(data.clone()).or_insert(0) += diff;
+                })
+                .probe_with(&mut probe);
+            program
+                .instructions
+                .consolidate()
+                .inspect(move |(data, _, diff)| {
+                    *instructions
+                        .lock()
+                        .unwrap()
+                        .entry(data.clone())
+                        .or_insert(0) += diff;
+                })
+                .probe_with(&mut probe);
+        });
+
+        load_functions(&context, &mut input, functions);
+        input.advance_to(1);
+        worker.step_while(|| probe.less_than(&1));
+    });
+
+    fn present<K, V>(captured: &Mutex<BTreeMap<(K, V), Diff>>) -> BTreeMap<K, V>
+    where
+        K: Copy + Ord,
+        V: Clone,
+    {
+        captured
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|&(_, &diff)| diff > 0)
+            .map(|((key, value), _)| (*key, value.clone()))
+            .collect()
+    }
+    let (descriptors, blocks, instructions) = (
+        present(&descriptors),
+        present(&blocks),
+        present(&instructions),
+    );
+
+    // The function gets a fresh entry that falls through to its old one
+    let function = &descriptors[&func];
+    assert_ne!(function.entry, header);
+    assert_eq!(function.basic_blocks, [function.entry, header]);
+    assert!(blocks[&function.entry].instructions.is_empty());
+    assert_eq!(blocks[&function.entry].terminator, Terminator::Jump(header));
+
+    // The call is replaced by rebinding the parameters and jumping back to the header
+    let body = &blocks[&header];
+    assert_eq!(body.terminator, Terminator::Jump(header));
+
+    let body: Vec<&Instruction> = body
+        .instructions
+        .iter()
+        .map(|id| &instructions[id])
+        .collect();
+    assert!(matches!(body[0], Instruction::Sub(_)));
+    let assigns: Vec<(VarId, Option<VarId>)> = body[1..]
+        .iter()
+        .map(|inst| match inst {
+            Instruction::Assign(assign) => (assign.dest, assign.value.as_var()),
+            inst => panic!("expected an assign, got {:?}", inst),
+        })
+        .collect();
+
+    // `n` is copied out before it's rebound so that `acc` gets its old value
+    let temp = assigns[0].0;
+    assert_eq!(
+        assigns,
+        [(temp, Some(n)), (n, Some(next)), (acc, Some(temp))],
+    );
+    assert!(!instructions
+        .values()
+        .any(|inst| matches!(inst, Instruction::Call(_))));
+}
+
+#[test]
+fn stores_overwritten_before_a_load_are_dead() {
+    // 1 -> 2 -> 4, 1 -> 3 -> 4 where only block 3 reads slot 0 before overwriting it
+    let edges = [(1, 2), (1, 3), (2, 4), (3, 4)];
+    let mut blocks = BTreeMap::new();
+    blocks.insert(
+        1,
+        vec![Access::Store(0), Access::Store(1), Access::Store(1)],
+    );
+    blocks.insert(2, vec![Access::Store(0), Access::Store(1)]);
+    blocks.insert(3, vec![Access::Load(0), Access::Store(0)]);
+    blocks.insert(4, vec![Access::Load(1)]);
+
+    let liveness = Liveness::new(edges.iter().copied(), &blocks, |&access| Some(access));
+    assert!(liveness.is_live_in(3, &0));
+    assert!(!liveness.is_live_in(2, &0));
+    assert!(liveness.is_live_out(1, &0));
+
+    // Slots stay live past the function's exit
+    assert!(liveness.is_live_out(4, &0));
+    assert!(liveness.is_live_out(2, &1));
+
+    let removed = eliminate_dead_stores(edges.iter().copied(), &mut blocks, |&access| Some(access));
+    // Only the first store to slot 1 is overwritten before anything could load it
+    assert_eq!(removed, 1);
+    assert_eq!(blocks[&1], vec![Access::Store(0), Access::Store(1)]);
+    assert_eq!(blocks[&2], vec![Access::Store(0), Access::Store(1)]);
+}
+
+#[test]
+fn diamonds_picking_a_value_become_selects() {
+    let id = |id: u64| NonZeroU64::new(id).unwrap();
+    let (cond, lhs, rhs, dest) = (
+        TypedVar::new(VarId::new(id(1)), Type::Bool),
+        TypedVar::new(VarId::new(id(2)), Type::Int),
+        TypedVar::new(VarId::new(id(3)), Type::Int),
+        VarId::new(id(4)),
+    );
+    let (entry, then, else_, merge) = (
+        BasicBlockId::new(id(1)),
+        BasicBlockId::new(id(2)),
+        BasicBlockId::new(id(3)),
+        BasicBlockId::new(id(4)),
+    );
+
+    let block = |id, instructions, terminator| BasicBlock {
+        name: None,
+        id,
+        instructions,
+        terminator,
+        instruction_spans: Vec::new(),
+        terminator_span: None,
+    };
+    let arm = |id, value: &TypedVar| {
+        block(
+            id,
+            vec![Assign::new(dest, value.clone().into(), None).into()],
+            Terminator::Jump(merge),
+        )
+    };
+
+    let function = Function {
+        name: None,
+        id: FuncId::new(id(1)),
+        params: vec![cond.clone(), lhs.clone(), rhs.clone()],
+        ret_ty: Type::Int,
+        entry,
+        basic_blocks: vec![
+            block(
+                entry,
+                Vec::new(),
+                Branch::new(cond.clone().into(), Label::new(then), Label::new(else_)).into(),
+            ),
+            arm(then, &lhs),
+            arm(else_, &rhs),
+            block(
+                merge,
+                Vec::new(),
+                Terminator::Return(Return::new(Some(Value::new(
+                    ValueKind::Var(dest),
+                    Type::Int,
+                )))),
+            ),
+        ],
+        metadata: Default::default(),
+    };
+    assert!(if_conversion::has_diamonds(&function));
+
+    let converted = if_conversion::if_conversion(&function);
+    let select = Select::new(dest, cond.into(), lhs.into(), rhs.into());
+    assert_eq!(
+        converted.basic_blocks[0].instructions,
+        vec![Instruction::Select(select.clone())],
+    );
+    assert_eq!(
+        converted.basic_blocks[0].terminator,
+        Terminator::Jump(merge)
+    );
+    assert_eq!(converted.basic_blocks.len(), 2);
+    assert!(!if_conversion::has_diamonds(&converted));
+
+    // Selects on constant conditions fold to the chosen value
+    let folded = Select {
+        cond: Value::new(ValueKind::Const(Constant::Bool(false)), Type::Bool),
+        ..select
+    }
+    .evaluate()
+    .unwrap();
+    assert_eq!(folded.value.as_var(), Some(VarId::new(id(3))));
+}
+
+#[test]
+fn branch_weights_lay_out_hot_blocks_first() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut blocks = None;
+    builder
+        .function(Type::Unit, |func| {
+            let cond = func.param(Type::Bool);
+
+            let merge = func.basic_block(|block| {
+                block.ret_unit();
+                Ok(())
+            })?;
+            let then = func.basic_block(|block| {
+                block.jump(merge);
+                Ok(())
+            })?;
+            let else_ = func.basic_block(|block| {
+                block.jump(merge);
+                Ok(())
+            })?;
+
+            let entry = func.entry_block(|block| {
+                block.branch(cond, then, else_)?;
+
+                assert_eq!(
+                    block.branch_weights(vec![1]),
+                    Err(BuilderError::MismatchedBranchWeights {
+                        labels: 2,
+                        weights: 1,
+                    }),
+                );
+                block.branch_weights(vec![1, 9])?;
+
+                Ok(())
+            })?;
+
+            blocks = Some((entry, then, else_, merge));
+            Ok(())
+        })
+        .unwrap();
+
+    let mut function = builder.materialize().next().unwrap();
+    builder.discard();
+    let (entry, then, else_, merge) = blocks.unwrap();
+
+    // The heavier side of the branch follows the entry and the merge block waits
+    // for both of its predecessors
+    assert_eq!(layout::layout(&function), [entry, else_, then, merge]);
+    let laid_out: Vec<BasicBlockId> = layout::lay_out_blocks(&function)
+        .basic_blocks
+        .iter()
+        .map(|block| block.id)
+        .collect();
+    assert_eq!(laid_out, [entry, else_, then, merge]);
+
+    // Profiles replace the weights given by the front-end
+    let counts = [((entry, then), 40), ((entry, else_), 2)]
+        .iter()
+        .copied()
+        .collect();
+    layout::apply_profile(&mut function, &counts);
+    assert_eq!(layout::layout(&function), [entry, then, else_, merge]);
+}
+
+#[test]
+fn spans_survive_the_driver() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let file = Some(Ident::new(context.interner().get_or_intern("main.sr")));
+    let (add_span, ret_span) = (
+        Span::new(file, SourceLoc::new(1, 5), SourceLoc::new(1, 10)),
+        Span::point(file, SourceLoc::new(2, 5)),
+    );
+
+    builder
+        .function(Type::Uint, |func| {
+            let param = func.param(Type::Uint);
+
+            func.basic_block(|block| {
+                let doubled = block.at(add_span).add(param, param)?;
+                block.at(ret_span).ret(doubled)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let output = Driver::new(context).run(functions, &[Pass::Cleanup]);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+
+    let block = &output.functions[0].basic_blocks[0];
+    assert_eq!(block.instruction_spans, vec![Some(add_span)]);
+    assert_eq!(block.terminator_span, Some(ret_span));
+}
+
+#[test]
+fn autotuning_picks_the_smallest_ordering() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    for _ in 0..3 {
+        builder
+            .function(Type::Int, |func| {
+                let x = func.param(Type::Int);
+
+                func.basic_block(|block| {
+                    // The sum is never used, so only cleaning up removes it
+                    block.add(x.clone(), x.clone())?;
+                    block.ret(x)?;
+
+                    Ok(())
+                })?;
+
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    let ids: Vec<FuncId> = functions.iter().map(|function| function.id).collect();
+    let mut tuner = Autotuner::new(vec![Vec::new(), vec![Pass::Cleanup]]).with_sample_size(2);
+    assert_eq!(tuner.sample(&ids), [ids[0], ids[1]]);
+    assert_eq!(tuner.best_ordering(), Some(&[][..]));
+
+    let output = Driver::new(context).run_autotuned(functions, &mut tuner);
+    assert!(output.errors.is_empty(), "{:?}", output.errors);
+    assert!(output
+        .functions
+        .iter()
+        .all(|function| function.basic_blocks[0].instructions.is_empty()));
+
+    assert_eq!(tuner.best(), Some(1));
+    assert_eq!(tuner.stats()[0].functions, 2);
+    assert_eq!(tuner.stats()[0].instructions, 2);
+    assert_eq!(tuner.stats()[1].instructions, 0);
+    assert!(tuner.report().to_string().ends_with("selected [Cleanup]"));
+
+    // Orderings that aren't candidates can't be recorded
+    let before = tuner.stats().to_vec();
+    tuner.record(2, &output.functions);
+    assert_eq!(tuner.stats(), &before[..]);
+}
+
+#[test]
+fn ranges_follow_arithmetic_and_prune_branches() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut vars = None;
+    builder
+        .function(Type::Uint, |func| {
+            let x = func.param(Type::Uint);
+            let (taken, never_taken) = (func.allocate_basic_block(), func.allocate_basic_block());
+
+            func.basic_block(|block| {
+                let five = block.assign(Constant::Uint(5));
+                let eight = block.add(five, Constant::Uint(3))?;
+                let halved = block.div(x.clone(), Constant::Uint(2))?;
+                let is_eight = block.cmp(eight.clone(), Constant::Uint(8))?;
+                block.branch(is_eight.clone(), *taken, *never_taken)?;
+
+                vars = Some((eight.var, halved.var, is_eight.var));
+                Ok(())
+            })?;
+
+            func.resume_building(taken, |block| {
+                block.ret(Constant::Uint(0))?;
+                Ok(())
+            })?;
+            func.resume_building(never_taken, |block| {
+                block.ret(x)?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+    let (eight, halved, is_eight) = vars.unwrap();
+
+    let function = &functions[0];
+    let entry = function
+        .basic_blocks
+        .iter()
+        .find(|block| block.id == function.entry)
+        .unwrap();
+    let never_taken = match &entry.terminator {
+        Terminator::Branch(branch) => branch.if_false.block,
+        terminator => panic!("expected a branch, got {:?}", terminator),
+    };
+
+    let instructions: Vec<(InstId, Instruction)> = entry
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(idx, inst)| {
+            let id = InstId::new(NonZeroU64::new(idx as u64 + 1).unwrap());
+            (id, inst.clone())
+        })
+        .collect();
+    let terminators: Vec<(BasicBlockId, Terminator)> = function
+        .basic_blocks
+        .iter()
+        .map(|block| (block.id, block.terminator.clone()))
+        .collect();
+    let entry = entry.id;
+
+    let captured = Arc::new(Mutex::new((BTreeMap::new(), BTreeSet::new(), 0)));
+    let output = captured.clone();
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<Time, _, _>(|scope| {
+            let (_instructions, instructions) = scope.new_collection_from(instructions);
+            let (_terminators, terminators) = scope.new_collection_from(terminators);
+
+            let ranges = variable_ranges::<_, Diff>(&instructions);
+            let (variables, impossible, values) = (output.clone(), output.clone(), output);
+            ranges.inspect(move |&((var, range), _, _)| {
+                variables.lock().unwrap().0.insert(var, range);
+            });
+            impossible_branches(&terminators, &ranges).inspect(move |&(edge, _, _)| {
+                impossible.lock().unwrap().1.insert(edge);
+            });
+            value_ranges(&instructions).inspect(move |_| values.lock().unwrap().2 += 1);
+        });
+    });
+
+    let (ranges, impossible, values) = captured.lock().unwrap().clone();
+    assert_eq!(ranges[&eight], Range::point(8));
+    assert_eq!(ranges[&halved], Range::new(0, u64::MAX as i128 / 2));
+    assert_eq!(ranges[&is_eight], Range::point(1));
+    assert_eq!(values, ranges.len());
+
+    // The comparison always holds, so its false edge can never be taken
+    assert_eq!(impossible, Some((entry, never_taken)).into_iter().collect());
+}
+
+#[test]
+fn function_sizes_follow_the_size_table() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let double = builder
+        .function(Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let doubled = block.add(x.clone(), x)?;
+                block.ret(doubled)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let caller = builder
+        .function(Type::Int, |func| {
+            func.basic_block(|block| {
+                let mut doubled = block.call(double, vec![Constant::Int(2).into()])?;
+                doubled.ty = Type::Int;
+                block.ret(doubled)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    let table = SizeTable::WASM;
+    let call = &functions[1].basic_blocks[0].instructions[0];
+    assert_eq!(table.instruction_size(call), table.call + table.call_arg);
+
+    let sizes = Arc::new(Mutex::new(BTreeMap::new()));
+    let captured = sizes.clone();
+    timely::execute_directly(move |worker| {
+        let mut input = worker.dataflow(|scope| InputManager::<Time, Diff>::new(scope));
+
+        let mut probe = ProbeHandle::new();
+        worker.dataflow(|scope| {
+            function_sizes(&input.import_program(scope), table)
+                .consolidate()
+                .inspect(move |&((func, size), _, _)| {
+                    captured.lock().unwrap().insert(func, size);
+                })
+                .probe_with(&mut probe);
+        });
+
+        load_functions(&context, &mut input, functions);
+        input.advance_to(1);
+        worker.step_while(|| probe.less_than(&1));
+    });
+
+    let sizes = sizes.lock().unwrap().clone();
+    let expected = |instructions, terminators| FunctionSize {
+        instructions,
+        terminators,
+        overhead: table.function_overhead,
+    };
+    assert_eq!(sizes[&double], expected(table.add, table.ret));
+    assert_eq!(
+        sizes[&caller],
+        expected(table.call + table.call_arg, table.ret),
+    );
+    assert_eq!(sizes[&double].total(), 14);
+}
+
+#[test]
+fn cost_models_weigh_blocks_and_inlining() {
+    /// Every instruction and terminator costs the same, calls and branches are
+    /// expensive and recursion is discouraged
+    struct Flat;
+
+    impl CostModel for Flat {
+        fn instruction_cost(&self, _inst: &Instruction) -> f32 {
+            1.0
+        }
+
+        fn terminator_cost(&self, _terminator: &Terminator) -> f32 {
+            1.0
+        }
+
+        fn call_overhead(&self) -> f32 {
+            10.0
+        }
+
+        fn branch_misprediction(&self) -> f32 {
+            5.0
+        }
+
+        fn recursive_function_factor(&self) -> f32 {
+            2.0
+        }
+    }
+
+    let var = |id: u64| VarId::new(NonZeroU64::new(id).unwrap());
+    let block = |id: u64| BasicBlockId::new(NonZeroU64::new(id).unwrap());
+
+    let instructions: Vec<Instruction> = (1..=3)
+        .map(|id| Instruction::Assign(Assign::new(var(id), Constant::Int(0).into(), None)))
+        .collect();
+    let ret = Terminator::Return(Return::new(None));
+    let branch = Terminator::Branch(Branch::new(
+        Value::new(ValueKind::Var(var(1)), Type::Bool),
+        Label::new(block(1)),
+        Label::new(block(2)),
+    ));
+
+    assert_eq!(Flat.block_cost(&instructions, &ret), 4.0);
+    // Branching blocks pay for the chance of a misprediction
+    assert_eq!(Flat.block_cost(&instructions, &branch), 9.0);
+    assert_eq!(
+        (&DefaultCostModel).block_cost(&instructions, &branch),
+        DefaultCostModel.block_cost(&instructions, &branch),
+    );
+
+    let heuristics = InlineHeuristics::new(2, 1, 4, 3, 1, false, true, 50);
+    assert_eq!(
+        heuristics.inline_cost_with(&Flat),
+        (50.0 + 10.0 + 10.0) * 2.0
+    );
+    assert_eq!(
+        heuristics.inline_cost(),
+        heuristics.inline_cost_with(&DefaultCostModel),
+    );
+
+    // Models can make the same function more or less attractive to inline
+    assert!(heuristics.trivially_inlinable_with(&Flat));
+    assert!(!heuristics.trivially_inlinable());
+}
+
+#[test]
+fn phis_are_placed_where_definitions_meet() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let mut blocks = None;
+    builder
+        .function(Type::Int, |func| {
+            let cond = func.param(Type::Bool);
+            let (left, right, join) = (
+                func.allocate_basic_block(),
+                func.allocate_basic_block(),
+                func.allocate_basic_block(),
+            );
+            let ids = (*left, *right, *join);
+
+            func.basic_block(|block| {
+                block.branch(cond, *left, *right)?;
+                Ok(())
+            })?;
+            func.resume_building(left, |block| {
+                block.assign(Constant::Int(1));
+                block.jump(*join);
+                Ok(())
+            })?;
+            func.resume_building(right, |block| {
+                block.assign(Constant::Int(2));
+                block.jump(*join);
+                Ok(())
+            })?;
+            func.resume_building(join, |block| {
+                block.ret(Constant::Int(0))?;
+                Ok(())
+            })?;
+
+            blocks = Some(ids);
+            Ok(())
+        })
+        .unwrap();
+
+    let mut functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+    let (left, right, join) = blocks.unwrap();
+
+    // Assigning to the same variable on both sides of the diamond means its
+    // definitions meet within the join
+    let function = &mut functions[0];
+    let var = function
+        .basic_blocks
+        .iter()
+        .find(|block| block.id == left)
+        .unwrap()
+        .instructions[0]
+        .dest();
+    let right_block = function
+        .basic_blocks
+        .iter_mut()
+        .find(|block| block.id == right)
+        .unwrap();
+    right_block.instructions[0] =
+        Instruction::Assign(Assign::new(var, Constant::Int(2).into(), None));
+
+    let captured: Arc<Mutex<(BTreeSet<_>, BTreeSet<_>)>> = Default::default();
+    let output = captured.clone();
+    timely::execute_directly(move |worker| {
+        let mut input = worker.dataflow(|scope| InputManager::<Time, Diff>::new(scope));
+
+        let mut probe = ProbeHandle::new();
+        worker.dataflow(|scope| {
+            let program = input.import_program(scope);
+            let frontiers = dominance_frontiers(&program, &dominators(&program));
+
+            let (frontier_output, phi_output) = (output.clone(), output);
+            frontiers
+                .consolidate()
+                .inspect(move |&(frontier, _, _)| {
+                    frontier_output.lock().unwrap().0.insert(frontier);
+                })
+                .probe_with(&mut probe);
+            phi_placements(&program, &frontiers)
+                .consolidate()
+                .inspect(move |&(placement, _, _)| {
+                    phi_output.lock().unwrap().1.insert(placement);
+                })
+                .probe_with(&mut probe);
+        });
+
+        load_functions(&context, &mut input, functions);
+        input.advance_to(1);
+        worker.step_while(|| probe.less_than(&1));
+    });
+
+    let (frontiers, phis) = captured.lock().unwrap().clone();
+    // The entry strictly dominates the join, so only the two sides of the diamond
+    // have it within their frontiers
+    assert_eq!(
+        frontiers,
+        vec![(left, join), (right, join)].into_iter().collect(),
+    );
+    assert_eq!(phis, Some((var, join)).into_iter().collect());
+}
+
+/// The scope [`analyze()`] runs analyses within
+type AnalysisScope<'a> = Child<'a, Worker<Thread>, Time>;
+
+/// Loads the functions into a new dataflow and collects everything the analysis
+/// produces from them
+fn analyze<D, F>(context: &Arc<Context>, functions: Vec<Function>, analysis: F) -> BTreeSet<D>
+where
+    D: ExchangeData + Hash,
+    F: for<'a> FnOnce(&Program<AnalysisScope<'a>, Diff>) -> Collection<AnalysisScope<'a>, D, Diff>
+        + Send
+        + Sync
+        + 'static,
+{
+    let context = context.clone();
+    let output = Arc::new(Mutex::new(BTreeSet::new()));
+
+    let captured = output.clone();
+    timely::execute_directly(move |worker| {
+        let mut input = worker.dataflow(|scope| InputManager::<Time, Diff>::new(scope));
+
+        let mut probe = ProbeHandle::new();
+        worker.dataflow(|scope| {
+            analysis(&input.import_program(scope))
+                .consolidate()
+                .inspect(move |(data, _, _)| {
+                    captured.lock().unwrap().insert(data.clone());
+                })
+                .probe_with(&mut probe);
+        });
+
+        load_functions(&context, &mut input, functions);
+        input.advance_to(1);
+        worker.step_while(|| probe.less_than(&1));
+    });
+
+    let output = output.lock().unwrap().clone();
+    output
+}
+
+/// Builds the body of a function that makes each of the given calls, optionally
+/// runs an opaque instruction and returns zero
+fn calling(
+    calls: Vec<(FuncId, Vec<Value>)>,
+    opaque: bool,
+) -> impl FnOnce(&mut FunctionBuilder<'_>) -> BuildResult<()> {
+    move |func| {
+        func.basic_block(|block| {
+            for (callee, args) in calls {
+                block.call(callee, args)?;
+            }
+            if opaque {
+                block.opaque("x86_64", "mfence", Vec::new(), Type::Unit);
+            }
+            block.ret(Constant::Int(0))?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Builds a module where `a` calls the self-recursive `b`, `c` and `d` call each
+/// other while `d` runs an opaque instruction, `e` calls nothing and `f` calls the
+/// external `log`, returning it along with the ids of `a` through `f` and `log`
+fn call_graph_module(context: &Arc<Context>) -> (Vec<Function>, [FuncId; 7]) {
+    let mut builder = context.builder();
+
+    let log = builder.declare_external("log", vec![Type::Int], Type::Int);
+    let (b, c, d) = (
+        builder.allocate_function(Type::Int),
+        builder.allocate_function(Type::Int),
+        builder.allocate_function(Type::Int),
+    );
+    let (b_id, c_id, d_id) = (*b, *c, *d);
+
+    let a = builder
+        .function(Type::Int, calling(vec![(b_id, Vec::new())], false))
+        .unwrap();
+    builder
+        .resume_building(b, calling(vec![(b_id, Vec::new())], false))
+        .unwrap();
+    builder
+        .resume_building(c, calling(vec![(d_id, Vec::new())], false))
+        .unwrap();
+    builder
+        .resume_building(d, calling(vec![(c_id, Vec::new())], true))
+        .unwrap();
+    let e = builder
+        .function(Type::Int, calling(Vec::new(), false))
+        .unwrap();
+    let f = builder
+        .function(
+            Type::Int,
+            calling(vec![(log, vec![Constant::Int(1).into()])], false),
+        )
+        .unwrap();
+
+    let functions = builder.materialize().collect();
+    builder.discard();
+
+    (functions, [a, b_id, c_id, d_id, e, f, log])
+}
+
+#[test]
+fn call_graphs_find_recursion() {
+    let context = Arc::new(Context::new(0));
+    let (functions, [a, b, c, d, _e, f, log]) = call_graph_module(&context);
+
+    let edges = analyze(&context, functions.clone(), |program| call_graph(program));
+    assert_eq!(
+        edges,
+        vec![(a, b), (b, b), (c, d), (d, c), (f, log)]
+            .into_iter()
+            .collect(),
+    );
+
+    // Both self-recursion and mutual recursion count, calling a recursive
+    // function doesn't
+    let recursive = analyze(&context, functions, |program| {
+        recursive_functions(&call_graph(program))
+    });
+    assert_eq!(recursive, vec![b, c, d].into_iter().collect());
+}
+
+#[test]
+fn impurity_propagates_to_callers() {
+    let context = Arc::new(Context::new(0));
+    let (functions, [a, b, c, d, e, f, log]) = call_graph_module(&context);
+
+    // `c` only calls `d`, but `d` is impure and so is everything calling it. External
+    // functions like `log` have no body to look into and are assumed to be impure
+    let purity = analyze(&context, functions.clone(), |program| {
+        function_purity(program)
+    });
+    assert_eq!(
+        purity,
+        vec![
+            (a, true),
+            (b, true),
+            (c, false),
+            (d, false),
+            (e, true),
+            (f, false),
+            (log, false),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    // Only the calls within `a` and `b` invoke pure functions
+    let calls = analyze(&context, functions, |program| {
+        pure_calls(program, &pure_functions(program, &call_graph(program)))
+    });
+    assert_eq!(calls.len(), 2);
+}
+
+#[test]
+fn cardinalities_pick_join_orders() {
+    let context = Arc::new(Context::new(0));
+    let (functions, _) = call_graph_module(&context);
+
+    // `log` is external and has no blocks, every other function has a single block
+    // where `d` runs an opaque instruction next to its call and `e` makes no calls
+    let cardinalities = Cardinalities::of(&functions);
+    assert_eq!(
+        cardinalities,
+        Cardinalities {
+            functions: 7,
+            basic_blocks: 6,
+            instructions: 6,
+            calls: 5,
+            branches: 0,
+        },
+    );
+    assert_eq!(cardinalities.instructions_per_block(), 1.0);
+    assert!((cardinalities.call_density() - 5.0 / 6.0).abs() < f64::EPSILON);
+
+    // Nearly every instruction is a call, so resolving every instruction's function
+    // up front is cheaper than joining the calls twice
+    assert_eq!(cardinalities.call_graph_order(), JoinOrder::LocationsFirst);
+    assert_eq!(cardinalities.join_order(1), JoinOrder::InstructionsFirst);
+    assert_eq!(
+        Cardinalities::default().call_graph_order(),
+        JoinOrder::InstructionsFirst
+    );
+
+    // Either order builds the same call graph
+    let expected = analyze(&context, functions.clone(), |program| call_graph(program));
+    for &order in &[JoinOrder::InstructionsFirst, JoinOrder::LocationsFirst] {
+        let edges = analyze(&context, functions.clone(), move |program| {
+            call_graph_with(program, order)
+        });
+        assert_eq!(edges, expected, "{:?} built a different call graph", order);
+    }
+}
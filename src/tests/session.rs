@@ -0,0 +1,134 @@
+use crate::{
+    builder::Context,
+    driver::{Pass, Pipeline},
+    repr::{
+        diff::{DiffLine, FunctionDiff},
+        instruction::Assign,
+        Constant, Instruction, InstructionExt, Type,
+    },
+    session::Session,
+};
+use std::sync::Arc;
+
+#[test]
+fn session_sends_changed_functions() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .named_function("one", Type::Int, |func| {
+            func.basic_block(|block| {
+                let one = block.assign(Constant::Int(1));
+                block.ret(one)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    let mut edited = function.clone();
+    let dest = edited.basic_blocks[0].instructions[0].dest();
+    edited.basic_blocks[0].instructions[0] =
+        Instruction::Assign(Assign::new(dest, Constant::Int(2).into(), None));
+
+    let mut session = Session::new(Pipeline::new(context.clone()).add_pass(Pass::Cleanup));
+
+    session.update(vec![function.clone()]);
+    assert_eq!(session.advance(), 1);
+    let update = session.next_update().unwrap();
+    assert_eq!(update.epoch, 1);
+    assert_eq!(update.functions.len(), 1);
+    assert!(update.removed.is_empty() && update.errors.is_empty());
+
+    // Nothing changed within the second epoch
+    session.advance();
+    assert!(session.next_update().unwrap().is_empty());
+
+    session.update(vec![edited]);
+    session.advance();
+    let update = session.next_update().unwrap();
+    assert_eq!(update.functions.len(), 1);
+    assert_eq!(update.functions[0].id, function.id);
+    assert!(update.removed.is_empty());
+
+    // Only the edited assignment shows up as changed
+    let diffs = update.diffs(context.interner());
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].1.changes(), (1, 1));
+    let rendered = diffs[0].1.to_string();
+    assert!(rendered
+        .lines()
+        .any(|line| line.starts_with("- ") && line.contains('1')));
+    assert!(rendered
+        .lines()
+        .any(|line| line.starts_with("+ ") && line.contains('2')));
+
+    session.remove(vec![function.id]);
+    session.advance();
+    let update = session.next_update().unwrap();
+    assert!(update.functions.is_empty());
+    assert_eq!(update.removed, vec![function.id]);
+
+    session.finish().unwrap();
+}
+
+#[test]
+fn function_diffs_align_rewritten_lines() {
+    let diff = FunctionDiff::new("a\nb = 1\nc", "a\nb = 2\nc\nd");
+    assert_eq!(
+        diff.lines(),
+        &[
+            DiffLine::Unchanged("a".to_owned()),
+            DiffLine::Removed("b = 1".to_owned()),
+            DiffLine::Added("b = 2".to_owned()),
+            DiffLine::Unchanged("c".to_owned()),
+            DiffLine::Added("d".to_owned()),
+        ],
+    );
+    assert_eq!(diff.changes(), (1, 2));
+    assert!(!diff.is_empty());
+    assert_eq!(diff.to_string(), "  a\n- b = 1\n+ b = 2\n  c\n+ d\n");
+
+    // Only the tokens that differ between paired lines are highlighted
+    let colored = diff.colored(true).to_string();
+    assert!(colored.contains("\x1b[31m- b = \x1b[7m1\x1b[27m\x1b[0m"));
+    assert!(colored.contains("\x1b[32m+ b = \x1b[7m2\x1b[27m\x1b[0m"));
+    assert!(colored.contains("\x1b[32m+ d\x1b[0m"));
+
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .named_function("one", Type::Int, |func| {
+            func.basic_block(|block| {
+                let one = block.assign(Constant::Int(1));
+                block.ret(one)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    let mut edited = function.clone();
+    let dest = edited.basic_blocks[0].instructions[0].dest();
+    edited.basic_blocks[0].instructions[0] =
+        Instruction::Assign(Assign::new(dest, Constant::Int(2).into(), None));
+
+    let interner = context.interner();
+    assert!(function.diff_display(&function, &*interner).is_empty());
+
+    let diff = function.diff_display(&edited, &*interner);
+    assert_eq!(diff.changes(), (1, 1));
+    let removed = diff
+        .lines()
+        .iter()
+        .find(|line| matches!(line, DiffLine::Removed(_)))
+        .unwrap();
+    assert!(removed.text().contains('1'), "{}", diff);
+}
@@ -0,0 +1,821 @@
+use crate::{
+    builder::Context,
+    dataflow::{
+        analysis::{Def, Use, UseDef},
+        trace_changes, Diff, InputManager, KeyTraceHandle, OptSummary, PassStats, Shared, Time,
+        TraceHandle, TraceManager, ValTraceHandle,
+    },
+    driver::{
+        Driver, LoadedFunction, Pass, Pipeline, PipelineHandles, CONSTANTS_TRACE, STATS_TRACE,
+        SUMMARIES_TRACE,
+    },
+    repr::{
+        instruction::Assign, utils::IRDisplay, ConstId, Constant, FuncId, Function, Instruction,
+        InstructionExt, Type, Value, ValueKind, VarId,
+    },
+    symbols::{sync_snapshot, InternerSnapshot, SnapshotError, SnapshotUpdate, SymbolResolver},
+};
+use differential_dataflow::{
+    input::Input,
+    operators::arrange::{ArrangeByKey, ArrangeBySelf, TraceAgent},
+    trace::implementations::ord::{OrdKeySpine, OrdValSpine},
+};
+use std::{num::NonZeroU64, sync::Arc};
+use timely::{
+    dataflow::{
+        operators::{Input as TimelyInput, Probe},
+        ProbeHandle,
+    },
+    progress::frontier::AntichainRef,
+};
+
+#[test]
+fn export_consolidated_traces() {
+    let context = Context::new(0);
+    let name = context.interner().get_or_intern_static("test/values");
+
+    timely::execute_directly(move |worker| {
+        let mut manager = TraceManager::new();
+        let (mut input, trace, probe) = worker.dataflow::<Time, _, _>(|scope| {
+            let (input, values) = scope.new_collection::<(u32, u32), Diff>();
+            let arranged = values.arrange_by_key();
+            let probe = arranged.stream.probe();
+
+            (input, arranged.trace, probe)
+        });
+        let handle: ValTraceHandle<u32, u32, Time, Diff> = manager.register(name, trace);
+
+        input.insert((1, 10));
+        input.insert((2, 20));
+        input.advance_to(1);
+        input.insert((3, 30));
+        input.remove((1, 10));
+        input.advance_to(2);
+        input.flush();
+        worker.step_while(|| probe.less_than(input.time()));
+
+        let export = |time: Time| {
+            let mut exported = Vec::new();
+            let complete =
+                manager.export(handle, AntichainRef::new(&[time]), |&key, &val, diff| {
+                    exported.push((key, val, diff))
+                });
+
+            complete.then(|| exported)
+        };
+
+        assert_eq!(export(1), Some(vec![(1, 10, 1), (2, 20, 1)]));
+        assert_eq!(export(2), Some(vec![(2, 20, 1), (3, 30, 1)]));
+        // The trace hasn't been completed up to the third timestamp yet
+        assert_eq!(export(3), None);
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        assert!(manager.export_to(handle, AntichainRef::new(&[2]), &sender));
+        drop(sender);
+        assert_eq!(receiver.iter().count(), 2);
+    });
+}
+
+#[test]
+fn program_delta_between_epochs() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .named_function("one", Type::Int, |func| {
+            func.basic_block(|block| {
+                let one = block.assign(Constant::Int(1));
+                block.ret(one)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    let mut edited = function.clone();
+    let dest = edited.basic_blocks[0].instructions[0].dest();
+    edited.basic_blocks[0].instructions[0] =
+        Instruction::Assign(Assign::new(dest, Constant::Int(2).into(), None));
+
+    timely::execute_directly(move |worker| {
+        let mut handles = Pipeline::new(context.clone()).build(worker);
+
+        let original = LoadedFunction::new(&context, function);
+        original.insert(&mut handles.input);
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+
+        let edited = LoadedFunction::new(&context, edited);
+        original.retract(&mut handles.input);
+        edited.insert(&mut handles.input);
+        handles.advance_to(2);
+        handles.step_until_complete(worker);
+
+        let delta = handles.delta(1, 2).unwrap();
+        assert_eq!(delta.instructions.removed.len(), 1);
+        assert_eq!(delta.instructions.added.len(), 1);
+        assert_eq!(
+            delta.instructions.added[0].1,
+            Instruction::Assign(Assign::new(dest, Constant::Int(2).into(), None)),
+        );
+        // The edited instruction got a new id, so its block changed along with it
+        assert_eq!(delta.basic_blocks.removed.len(), 1);
+        assert_eq!(delta.basic_blocks.added.len(), 1);
+        assert!(delta.terminators.is_empty());
+        assert!(delta.functions.is_empty());
+
+        let rendered = delta.to_pretty_string(context.interner());
+        assert!(rendered.lines().any(|line| line.starts_with("- ")));
+        assert!(rendered.lines().any(|line| line.starts_with("+ ")));
+
+        assert!(handles.delta(2, 2).unwrap().is_empty());
+        // The program hasn't been completed up to the third epoch yet
+        assert_eq!(handles.delta(2, 3), None);
+    });
+}
+
+#[test]
+fn deltas_skip_transient_changes() {
+    timely::execute_directly(|worker| {
+        let (mut input, mut trace, probe) = worker.dataflow::<Time, _, _>(|scope| {
+            let (input, values) = scope.new_collection::<(u32, u32), Diff>();
+            let arranged = values.arrange_by_key();
+            let probe = arranged.stream.probe();
+
+            (input, arranged.trace, probe)
+        });
+
+        input.insert((1, 10));
+        input.insert((2, 20));
+        input.advance_to(1);
+        input.remove((1, 10));
+        input.insert((3, 30));
+        input.advance_to(2);
+        input.remove((3, 30));
+        input.insert((4, 40));
+        input.advance_to(3);
+        input.flush();
+        worker.step_while(|| probe.less_than(input.time()));
+
+        let changes = trace_changes(&mut trace, &1, &2).unwrap();
+        assert_eq!(changes.added, vec![(3, 30)]);
+        assert_eq!(changes.removed, vec![(1, 10)]);
+
+        // Values that were added and removed again between the two epochs were never
+        // part of either version
+        let changes = trace_changes(&mut trace, &1, &3).unwrap();
+        assert_eq!(changes.added, vec![(4, 40)]);
+        assert_eq!(changes.removed, vec![(1, 10)]);
+
+        assert!(trace_changes(&mut trace, &3, &3).unwrap().is_empty());
+        assert_eq!(trace_changes(&mut trace, &3, &4), None);
+    });
+}
+
+#[test]
+fn deltas_include_removed_functions() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let func = builder
+        .named_function("one", Type::Int, |func| {
+            func.basic_block(|block| {
+                let one = block.assign(Constant::Int(1));
+                block.ret(one)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    timely::execute_directly(move |worker| {
+        let mut handles = Pipeline::new(context.clone()).build(worker);
+
+        let loaded = LoadedFunction::new(&context, function);
+        loaded.insert(&mut handles.input);
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+
+        loaded.retract(&mut handles.input);
+        handles.advance_to(2);
+        handles.step_until_complete(worker);
+
+        // Adding the function shows up as every part of it being added
+        let added = handles.delta(0, 1).unwrap();
+        assert_eq!(added.functions.added.len(), 1);
+        assert_eq!(added.functions.added[0].0, func);
+        assert_eq!(added.basic_blocks.added.len(), 1);
+        assert_eq!(added.terminators.added.len(), 1);
+        assert_eq!(added.instructions.added.len(), 1);
+
+        let removed = handles.delta(1, 2).unwrap();
+        assert_eq!(removed.functions.removed, added.functions.added);
+        assert_eq!(removed.basic_blocks.removed, added.basic_blocks.added);
+        assert_eq!(removed.terminators.removed, added.terminators.added);
+        assert_eq!(removed.instructions.removed, added.instructions.added);
+        assert!(removed.functions.added.is_empty() && removed.instructions.added.is_empty());
+        assert_eq!((removed.from, removed.to), (1, 2));
+
+        // The function never existed before being added or after being removed
+        assert!(handles.delta(0, 2).unwrap().is_empty());
+    });
+}
+
+#[test]
+fn compacted_pipelines_keep_producing_deltas() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .named_function("one", Type::Int, |func| {
+            func.basic_block(|block| {
+                let one = block.assign(Constant::Int(1));
+                block.ret(one)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    let dest = function.basic_blocks[0].instructions[0].dest();
+    let assign =
+        move |value: i64| Instruction::Assign(Assign::new(dest, Constant::Int(value).into(), None));
+    let with_value = |value: i64| {
+        let mut edited = function.clone();
+        edited.basic_blocks[0].instructions[0] = assign(value);
+        edited
+    };
+    let versions: Vec<_> = (1..=3).map(with_value).collect();
+
+    timely::execute_directly(move |worker| {
+        let driver = Driver::new(context.clone());
+        let mut handles = driver.pipeline(&[Pass::Cleanup]).build(worker);
+
+        let mut loaded = LoadedFunction::new(&context, versions[0].clone());
+        loaded.insert(&mut handles.input);
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+
+        // Every epoch is compacted away once it's been read, deltas from the last
+        // compacted epoch onwards are still exact
+        for (epoch, version) in (1..).zip(versions.into_iter().skip(1)) {
+            driver.compact(&mut handles, worker, epoch);
+
+            let edited = LoadedFunction::new(&context, version);
+            loaded.retract(&mut handles.input);
+            edited.insert(&mut handles.input);
+            loaded = edited;
+            handles.advance_to(epoch + 1);
+            handles.step_until_complete(worker);
+
+            let delta = handles.delta(epoch, epoch + 1).unwrap();
+            assert_eq!(delta.instructions.removed.len(), 1);
+            assert_eq!(delta.instructions.removed[0].1, assign(epoch as i64));
+            assert_eq!(delta.instructions.added.len(), 1);
+            assert_eq!(delta.instructions.added[0].1, assign(epoch as i64 + 1));
+
+            let functions = trace_changes(&mut handles.functions, &epoch, &(epoch + 1)).unwrap();
+            assert_eq!(functions.added.len(), 1);
+            assert_eq!(
+                functions.added[0].1.basic_blocks[0].instructions,
+                [assign(epoch as i64 + 1)],
+            );
+        }
+    });
+}
+
+#[test]
+fn pooled_constants_flow_through_pipeline() {
+    let context = Arc::new(Context::new(0));
+    let answer = context.intern_constant(Constant::Int(42));
+    assert_eq!(context.intern_constant(Constant::Int(42)), answer);
+    assert_ne!(context.intern_constant(Constant::Uint(42)), answer);
+
+    let mut builder = context.builder();
+    builder
+        .named_function("answer", Type::Int, |func| {
+            func.basic_block(|block| {
+                block.ret(Value::pooled(answer, Type::Int))?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    let rendered = function.to_pretty_string_with(context.interner(), context.constants());
+    assert!(rendered.contains("42"));
+    assert!(function
+        .to_pretty_string(context.interner())
+        .contains("@const."));
+
+    timely::execute_directly(move |worker| {
+        let mut handles = Pipeline::new(context.clone()).build(worker);
+        LoadedFunction::new(&context, function).insert(&mut handles.input);
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+
+        let handle: ValTraceHandle<ConstId, Constant, Time, Diff> =
+            TraceHandle::new(context.interner().get_or_intern_static(CONSTANTS_TRACE));
+
+        let mut constants = Vec::new();
+        assert!(handles.trace_manager.export(
+            handle,
+            AntichainRef::new(&[1]),
+            |&id, constant, diff| { constants.push((id, constant.clone(), diff)) }
+        ));
+        assert_eq!(constants, vec![(answer, Constant::Int(42), 1)]);
+    });
+}
+
+#[test]
+fn pipeline_summarizes_changes_per_function() {
+    let context = Arc::new(Context::new(0));
+
+    let mut builder = context.builder();
+    let folded = builder
+        .named_function("folded", Type::Int, |func| {
+            func.basic_block(|block| {
+                let sum = block.add(Constant::Int(1), Constant::Int(2))?;
+                block.ret(sum)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    builder
+        .named_function("untouched", Type::Int, |func| {
+            let arg = func.param(Type::Int);
+            func.basic_block(|block| {
+                block.ret(arg)?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let functions: Vec<_> = builder.materialize().collect();
+    builder.discard();
+
+    timely::execute_directly(move |worker| {
+        let mut handles = Pipeline::new(context.clone())
+            .add_pass(Pass::ConstantFolding)
+            .fixpoint(false)
+            .stats(true)
+            .build(worker);
+        for function in functions {
+            LoadedFunction::new(&context, function).insert(&mut handles.input);
+        }
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+
+        let handle: ValTraceHandle<FuncId, OptSummary, Time, Diff> =
+            TraceHandle::new(context.interner().get_or_intern_static(SUMMARIES_TRACE));
+
+        let mut summaries = Vec::new();
+        assert!(handles.trace_manager.export(
+            handle,
+            AntichainRef::new(&[1]),
+            |&func, summary, diff| summaries.push((func, summary.clone(), diff))
+        ));
+
+        // Only the function that had something to fold is summarized
+        assert_eq!(summaries.len(), 1);
+        let (func, summary, diff) = &summaries[0];
+        assert_eq!((*func, *diff), (folded, 1));
+        assert_eq!(summary.pass, "constant-folding");
+        assert_eq!(summary.epoch, 0);
+        assert!(summary.changes() > 0);
+        assert!(summary
+            .to_string()
+            .starts_with("constant-folding (epoch 0)"));
+    });
+}
+
+/// Builds a function for every name that has a constant addition to fold
+fn foldable_functions(context: &Arc<Context>, names: &[&str]) -> Vec<Function> {
+    let mut builder = context.builder();
+    for name in names {
+        builder
+            .named_function(name, Type::Int, |func| {
+                func.basic_block(|block| {
+                    let sum = block.add(Constant::Int(1), Constant::Int(2))?;
+                    block.ret(sum)?;
+
+                    Ok(())
+                })?;
+
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    let functions = builder.materialize().collect();
+    builder.discard();
+
+    functions
+}
+
+/// Exports the pass statistics a pipeline has collected up to `time`
+fn exported_stats(
+    context: &Context,
+    handles: &PipelineHandles,
+    time: Time,
+) -> Vec<(PassStats, Diff)> {
+    let handle: KeyTraceHandle<PassStats, Time, Diff> =
+        TraceHandle::new(context.interner().get_or_intern_static(STATS_TRACE));
+
+    let mut stats = Vec::new();
+    assert!(handles
+        .trace_manager
+        .export(handle, AntichainRef::new(&[time]), |stat, &(), diff| stats
+            .push((stat.clone(), diff))));
+    stats.sort();
+
+    stats
+}
+
+#[test]
+fn pipeline_collects_stats_per_epoch() {
+    let context = Arc::new(Context::new(0));
+    let functions = foldable_functions(&context, &["first", "second"]);
+
+    timely::execute_directly(move |worker| {
+        let mut handles = Pipeline::new(context.clone())
+            .add_pass(Pass::ConstantFolding)
+            .fixpoint(false)
+            .stats(true)
+            .build(worker);
+
+        // Each epoch gets a function of its own to fold
+        let mut functions = functions.into_iter();
+        LoadedFunction::new(&context, functions.next().unwrap()).insert(&mut handles.input);
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+
+        let first = exported_stats(&context, &handles, 1);
+        assert_eq!(first.len(), 1, "{:?}", first);
+        let (first, diff) = first[0].clone();
+        assert_eq!(diff, 1);
+        assert_eq!((first.pass.as_str(), first.epoch), ("constant-folding", 0));
+        assert!(first.changes > 0);
+        // Passes outside of an iterative scope settle within a single iteration
+        assert_eq!(first.iterations, 1);
+
+        LoadedFunction::new(&context, functions.next().unwrap()).insert(&mut handles.input);
+        handles.advance_to(2);
+        handles.step_until_complete(worker);
+
+        // Completed epochs keep their stats and the second epoch gets its own once
+        // it's complete
+        let both = exported_stats(&context, &handles, 2);
+        assert_eq!(both.len(), 2, "{:?}", both);
+        assert_eq!(both[0], (first.clone(), 1));
+
+        let (second, diff) = &both[1];
+        assert_eq!(*diff, 1);
+        assert_eq!(second.epoch, 1);
+        assert_eq!(second.iterations, 1);
+        // Both functions fold the same way, so both epochs change as much
+        assert_eq!(second.changes, first.changes);
+    });
+}
+
+#[test]
+fn pipeline_stats_count_fixpoint_iterations() {
+    let context = Arc::new(Context::new(0));
+    let functions = foldable_functions(&context, &["folded"]);
+
+    timely::execute_directly(move |worker| {
+        let mut handles = Pipeline::new(context.clone())
+            .add_pass(Pass::ConstantFolding)
+            .fixpoint(true)
+            .stats(true)
+            .build(worker);
+        for function in functions {
+            LoadedFunction::new(&context, function).insert(&mut handles.input);
+        }
+        handles.advance_to(1);
+        handles.step_until_complete(worker);
+
+        // The pass sees its own output on the next iteration, so settling takes
+        // more than the single iteration the fold itself needs
+        let stats = exported_stats(&context, &handles, 1);
+        assert_eq!(stats.len(), 1, "{:?}", stats);
+        assert_eq!(stats[0].0.epoch, 0);
+        assert!(stats[0].0.iterations > 1, "{}", stats[0].0);
+    });
+}
+
+#[test]
+fn use_def_traces_index_every_variable() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let (mut param, mut sum) = (None, None);
+    builder
+        .named_function("increment", Type::Int, |func| {
+            let x = func.param(Type::Int);
+            param = Some(x.var);
+
+            func.basic_block(|block| {
+                let y = block.add(x.clone(), x)?;
+                sum = Some(y.var);
+                block.ret(y)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+    let (param, sum) = (param.unwrap(), sum.unwrap());
+    let (func, block) = (function.id, function.basic_blocks[0].id);
+
+    timely::execute_directly(move |worker| {
+        let (mut manager, mut probe) = (TraceManager::new(), ProbeHandle::new());
+        let (mut input, handles) = worker.dataflow::<Time, _, _>(|scope| {
+            let mut input = InputManager::<Time, Diff>::new(scope);
+            let use_def = UseDef::new(&input.import_program(scope));
+            use_def.defs.stream.probe_with(&mut probe);
+            use_def.def_uses.stream.probe_with(&mut probe);
+            use_def.use_defs.stream.probe_with(&mut probe);
+
+            let handles = use_def.register(&mut manager, context.interner());
+            (input, handles)
+        });
+
+        LoadedFunction::new(&context, function).insert(&mut input);
+        input.advance_to(1);
+        worker.step_while(|| probe.less_than(&1));
+
+        let frontier = AntichainRef::new(&[1]);
+        let mut defs = Vec::new();
+        assert!(manager.export(handles.defs, frontier, |&var, &def, _| defs
+            .push((var, def))));
+        assert_eq!(defs.len(), 2);
+        assert!(defs.contains(&(param, Def::Param(func))));
+
+        let mut uses = Vec::new();
+        assert!(
+            manager.export(handles.def_uses, frontier, |&var, &used, diff| {
+                uses.push((var, used, diff))
+            })
+        );
+        // Using the parameter twice within the same instruction is a single use
+        let add = defs
+            .iter()
+            .find_map(|&(var, def)| (var == sum).then(|| def));
+        assert_eq!(
+            uses,
+            vec![
+                (param, Use::Inst(add.unwrap().inst().unwrap()), 1),
+                (sum, Use::Terminator(block), 1),
+            ],
+        );
+    });
+}
+
+#[test]
+fn traces_are_arranged_once() {
+    let context = Context::new(0);
+    let name = context.interner().get_or_intern_static("test/arranged");
+
+    timely::execute_directly(move |worker| {
+        let mut manager = TraceManager::<Time>::new();
+
+        let mut input = worker.dataflow::<Time, _, _>(|scope| {
+            let (input, values) = scope.new_collection::<(u32, u32), Diff>();
+            manager.arrange_once::<TraceAgent<OrdValSpine<u32, u32, Time, Diff>>, _>(name, || {
+                values.arrange_by_key().trace
+            });
+
+            input
+        });
+
+        // Later dataflows share the first arrangement
+        let mut probe = ProbeHandle::new();
+        worker.dataflow::<Time, _, _>(|scope| {
+            let values = manager
+                .arrange_once::<TraceAgent<OrdValSpine<u32, u32, Time, Diff>>, _>(name, || {
+                    unreachable!("the trace was already arranged")
+                })
+                .import(scope);
+            let values = values.as_collection(|&key, &val| (key, val));
+
+            // Arrangements of another type are kept under the same name alongside it
+            manager.arrange_once::<TraceAgent<OrdKeySpine<u32, Time, Diff>>, _>(name, || {
+                values.map(|(key, _)| key).arrange_by_self().trace
+            });
+            values.probe_with(&mut probe);
+        });
+        assert_eq!(
+            manager.trace_names().filter(|&trace| trace == name).count(),
+            2
+        );
+
+        input.insert((1, 10));
+        input.insert((2, 20));
+        input.advance_to(1);
+        input.flush();
+        worker.step_while(|| probe.less_than(input.time()));
+
+        let (handle, mut exported): (KeyTraceHandle<u32, Time, Diff>, _) =
+            (TraceHandle::new(name), Vec::new());
+        while !manager.export(handle, AntichainRef::new(&[1]), |&key, &(), _| {
+            exported.push(key)
+        }) {
+            worker.step();
+        }
+        assert_eq!(exported, vec![1, 2]);
+    });
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn replayed_sessions_reproduce_deltas() {
+    use crate::dataflow::recording::{InputRecorder, InputReplay};
+
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    builder
+        .named_function("one", Type::Int, |func| {
+            func.basic_block(|block| {
+                let one = block.assign(Constant::Int(1));
+                block.ret(one)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    let function = builder.materialize().next().unwrap();
+    builder.discard();
+
+    let mut edited = function.clone();
+    let dest = edited.basic_blocks[0].instructions[0].dest();
+    edited.basic_blocks[0].instructions[0] =
+        Instruction::Assign(Assign::new(dest, Constant::Int(2).into(), None));
+
+    let recording = {
+        let context = context.clone();
+        timely::execute_directly(move |worker| {
+            let mut handles = Pipeline::new(context.clone()).build(worker);
+            let recorder = InputRecorder::new(Vec::new());
+            worker.dataflow(|scope| recorder.record(scope, &mut handles.input));
+
+            let original = LoadedFunction::new(&context, function);
+            original.insert(&mut handles.input);
+            handles.advance_to(1);
+            handles.step_until_complete(worker);
+
+            let edited = LoadedFunction::new(&context, edited);
+            original.retract(&mut handles.input);
+            edited.insert(&mut handles.input);
+            handles.advance_to(2);
+            handles.step_until_complete(worker);
+
+            let recorded = handles.delta(1, 2).unwrap();
+            let recording = recorder.finish(context.interner()).unwrap();
+
+            (recorded.to_pretty_string(context.interner()), recording)
+        })
+    };
+    let (recorded, recording) = recording;
+
+    let replay = InputReplay::<Time, Diff>::from_reader(recording.as_slice()).unwrap();
+    assert_eq!(replay.times(), vec![0, 1]);
+
+    timely::execute_directly(move |worker| {
+        let context = Arc::new(Context::new(0));
+        let mut handles = Pipeline::new(context.clone()).build(worker);
+        let probe = handles.probe.clone();
+
+        replay
+            .replay_with(&mut handles.input, context.interner(), |input, &time| {
+                input.advance_to(time + 1);
+                while probe.less_than(input.time()) {
+                    worker.step_or_park(None);
+                }
+            })
+            .unwrap();
+
+        let replayed = handles.delta(1, 2).unwrap();
+        assert_eq!(replayed.to_pretty_string(context.interner()), recorded);
+    });
+}
+
+#[test]
+fn interner_snapshots_update_incrementally() {
+    let context = Context::new(0);
+    let main = context.interner().get_or_intern("main");
+
+    let mut snapshot = context.interner_snapshot();
+    assert_eq!(snapshot.try_resolve_symbol(main), Some("main"));
+
+    let helper = context.interner().get_or_intern("helper");
+    assert_eq!(snapshot.try_resolve_symbol(helper), None);
+
+    // Updates only carry the strings the snapshot didn't have yet
+    let update = snapshot.update(context.interner());
+    assert_eq!(update.strings, vec!["helper".to_owned()]);
+    assert_eq!(snapshot.try_resolve_symbol(helper), Some("helper"));
+    assert!(snapshot.update(context.interner()).is_empty());
+
+    // Updates can be applied more than once but can't skip over strings
+    let mut copy = InternerSnapshot::default();
+    assert_eq!(
+        copy.apply(&update),
+        Err(SnapshotError::MissingStrings { len: 0, start: 1 }),
+    );
+
+    let mut copy = InternerSnapshot::new(&lasso::ThreadedRodeo::new());
+    copy.apply(&InternerSnapshot::default().update(context.interner()))
+        .unwrap();
+    copy.apply(&update).unwrap();
+    assert_eq!(copy, snapshot);
+}
+
+#[test]
+fn snapshots_sync_across_workers() {
+    let context = Arc::new(Context::new(0));
+    let strings = ["main", "helper", "pipeline/functions"];
+    for string in &strings {
+        context.interner().get_or_intern(string);
+    }
+
+    let config = timely::Config::process(2);
+    let snapshots = timely::execute(config, move |worker| {
+        let (mut updates, snapshot) = worker.dataflow::<Time, _, _>(|scope| {
+            let (updates, stream) = TimelyInput::new_input(scope);
+            (updates, sync_snapshot(&stream))
+        });
+
+        // Only a single worker publishes updates, out of order
+        if worker.index() == 0 {
+            let mut partial = InternerSnapshot::default();
+            let mut first = partial.update(context.interner());
+            let rest = first.strings.split_off(1);
+
+            updates.send(SnapshotUpdate {
+                start: 1,
+                strings: rest,
+            });
+            updates.send(first);
+        }
+
+        // Run the dataflow to completion so every broadcast update has been applied
+        drop(updates);
+        while worker.step() {}
+
+        let snapshot = snapshot.borrow().clone();
+        snapshot
+    })
+    .unwrap()
+    .join();
+
+    for snapshot in snapshots {
+        let snapshot = snapshot.unwrap();
+        assert_eq!(snapshot.strings(), &strings[..]);
+    }
+}
+
+#[test]
+fn shared_instructions_survive_serialization() {
+    let inst: Instruction = Assign::new(
+        VarId::new(NonZeroU64::new(1).unwrap()),
+        Value::new(ValueKind::Const(Constant::Int(10)), Type::Int),
+        None,
+    )
+    .into();
+    let shared = Shared::new(inst.clone());
+    assert_eq!(*shared.clone(), inst);
+
+    let mut bytes = Vec::new();
+    unsafe { abomonation::encode(&shared, &mut bytes).unwrap() };
+
+    let (exhumed, rest) =
+        unsafe { abomonation::decode::<Shared<Instruction>>(&mut bytes) }.unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(**exhumed, inst);
+
+    // Clones of exhumed instructions don't borrow from the buffer they came from
+    let owned = exhumed.clone();
+    drop(bytes);
+    assert_eq!(owned.into_inner(), inst);
+}
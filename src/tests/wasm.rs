@@ -0,0 +1,264 @@
+#![cfg(feature = "wasm")]
+
+use crate::{
+    builder::Context,
+    repr::{Constant, Function, Ident, ParamAttributes, SourceLoc, Span, Type},
+    wasm::{self, EmitError},
+};
+use std::sync::Arc;
+
+fn function_names(context: &Context, functions: &[Function]) -> Vec<String> {
+    functions
+        .iter()
+        .filter_map(|function| function.name)
+        .map(|name| context.interner().resolve(&name.0).to_owned())
+        .collect()
+}
+
+#[test]
+fn emitted_functions_parse_back() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let double = builder
+        .named_function("double", Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let doubled = block.mul(x, Constant::Int(2))?;
+                block.ret(doubled)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+    builder
+        .named_function("quadruple", Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let mut doubled = block.call(double, vec![x.into()])?;
+                doubled.ty = Type::Int;
+                let mut quadrupled = block.call(double, vec![doubled.into()])?;
+                quadrupled.ty = Type::Int;
+                block.ret(quadrupled)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    let bytes = wasm::emit(&functions, context.interner()).unwrap();
+    let mut builder = context.builder();
+    let parsed = wasm::parse(&bytes, &mut builder).unwrap();
+    let reparsed: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(function_names(&context, &reparsed), ["double", "quadruple"]);
+
+    // Patches import the functions they call but don't contain
+    let patch = wasm::emit_patch(&functions[1..], &functions, context.interner()).unwrap();
+    let mut builder = context.builder();
+    assert_eq!(
+        wasm::parse(&patch, &mut builder),
+        Err(wasm::ParseError::Unsupported("imports")),
+    );
+    builder.discard();
+
+    assert_eq!(
+        wasm::emit_patch(&functions[1..], &[], context.interner()),
+        Err(EmitError::UnknownFunction(double)),
+    );
+}
+
+#[test]
+fn by_reference_parameters_are_unsupported() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .named_function("by_ref", Type::Int, |func| {
+            let x = func.param_with_attrs(Type::Int, ParamAttributes::DEFAULT.by_ref());
+
+            func.basic_block(|block| {
+                block.ret(x)?;
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    assert_eq!(
+        wasm::emit(&functions, context.interner()),
+        Err(EmitError::Unsupported("by-reference parameters")),
+    );
+}
+
+#[test]
+fn multi_value_returns_round_trip() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+    let pair = Type::Tuple(vec![Type::Int, Type::Int]);
+
+    builder
+        .named_function("divmod", pair.clone(), |func| {
+            let (x, y) = (func.param(Type::Int), func.param(Type::Int));
+
+            func.basic_block(|block| {
+                let quotient = block.div(x.clone(), y.clone())?;
+                let remainder = block.rem(x, y)?;
+                block.ret_multiple(vec![quotient, remainder])?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    let bytes = wasm::emit(&functions, context.interner()).unwrap();
+    let mut builder = context.builder();
+    wasm::parse(&bytes, &mut builder).unwrap();
+    let reparsed: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    assert_eq!(reparsed.len(), 1);
+    assert_eq!(reparsed[0].ret_ty, pair);
+
+    let ret = reparsed[0].basic_blocks[0]
+        .terminator
+        .clone()
+        .into_return()
+        .unwrap();
+    assert_eq!(ret.values.len(), 2);
+}
+
+#[test]
+fn external_functions_are_imported() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let log = builder.declare_external("log", vec![Type::Int], Type::Int);
+    builder
+        .named_function("main", Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let mut logged = block.call(log, vec![x.into()])?;
+                logged.ty = Type::Int;
+                block.ret(logged)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    // The parser doesn't support imports, so the external function being imported
+    // instead of defined is what makes parsing fail
+    let bytes = wasm::emit(&functions, context.interner()).unwrap();
+    let mut builder = context.builder();
+    assert_eq!(
+        wasm::parse(&bytes, &mut builder),
+        Err(wasm::ParseError::Unsupported("imports")),
+    );
+    builder.discard();
+}
+
+#[test]
+fn forward_branches_are_emitted() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    builder
+        .named_function("is_zero", Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            let zero = func.basic_block(|block| {
+                block.ret(Constant::Int(1))?;
+                Ok(())
+            })?;
+            let nonzero = func.basic_block(|block| {
+                block.ret(Constant::Int(0))?;
+                Ok(())
+            })?;
+
+            func.entry_block(|block| {
+                let cond = block.cmp(x, Constant::Int(0))?;
+                block.branch(cond, zero, nonzero)?;
+                block.branch_weights(vec![1, 9])?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    assert!(wasm::emit(&functions, context.interner()).is_ok());
+}
+
+#[test]
+fn emitted_code_maps_back_to_spans() {
+    let context = Arc::new(Context::new(0));
+    let mut builder = context.builder();
+
+    let file = Some(Ident::new(context.interner().get_or_intern("main.sr")));
+    let (mul_span, ret_span) = (
+        Span::new(file, SourceLoc::new(1, 5), SourceLoc::new(1, 10)),
+        Span::point(file, SourceLoc::new(2, 5)),
+    );
+
+    builder
+        .named_function("double", Type::Int, |func| {
+            let x = func.param(Type::Int);
+
+            func.basic_block(|block| {
+                let doubled = block.at(mul_span).mul(x, Constant::Int(2))?;
+                block.at(ret_span).ret(doubled)?;
+
+                Ok(())
+            })?;
+
+            Ok(())
+        })
+        .unwrap();
+
+    let functions: Vec<Function> = builder.materialize().collect();
+    builder.discard();
+
+    let (bytes, lines) = wasm::emit_with_lines(&functions, context.interner()).unwrap();
+    assert_eq!(bytes, wasm::emit(&functions, context.interner()).unwrap());
+
+    let spans: Vec<Span> = lines.rows().iter().map(|row| row.span).collect();
+    assert_eq!(spans, [mul_span, ret_span]);
+
+    let (mul, ret) = (lines.rows()[0].address, lines.rows()[1].address);
+    assert!(mul < ret);
+    assert_eq!(lines.span_of(mul), Some(mul_span));
+    assert_eq!(lines.span_of(ret - 1), Some(mul_span));
+    assert_eq!(lines.span_of(ret + 1), Some(ret_span));
+    assert_eq!(lines.span_of(mul - 1), None);
+}
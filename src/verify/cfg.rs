@@ -0,0 +1,93 @@
+//! Control flow checks over the blocks of each function
+//!
+//! Every function's entry has to be one of its own blocks and can't be jumped to,
+//! every jump has to stay within its function and every block has to be reachable
+//! from its function's entry. External functions are only declared and have no
+//! blocks at all, so they're exempt from every check
+
+use crate::{
+    dataflow::Difference,
+    repr::{basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, FuncId},
+    verify::ValidityError,
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Iterate, Join, Threshold},
+    Collection,
+};
+use timely::dataflow::Scope;
+
+pub(crate) fn verify_cfg<S, R>(
+    basic_blocks: &Collection<S, (BasicBlockId, BasicBlockDesc), R>,
+    functions: &Collection<S, (FuncId, FunctionDesc), R>,
+) -> Collection<S, ValidityError, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let function_blocks = functions.flat_map(|(func, desc)| {
+        desc.basic_blocks
+            .into_iter()
+            .map(move |block| (block, func))
+    });
+    let entries = functions
+        .filter(|(_, desc)| !desc.attributes.is_external())
+        .map(|(func, desc)| (desc.entry, func));
+
+    let invalid_entries = entries
+        .map(|(entry, func)| ((entry, func), ()))
+        .antijoin(&function_blocks)
+        .map(|((entry, func), ())| ValidityError::InvalidEntryBlock { func, entry });
+
+    let edges = basic_blocks.flat_map(|(source, desc)| {
+        desc.terminator
+            .jump_targets()
+            .into_iter()
+            .map(move |target| (source, target))
+    });
+
+    let entry_predecessors = edges.map(|(source, target)| (target, source)).join_map(
+        &entries,
+        |&entry, &predecessor, &func| ValidityError::EntryHasPredecessors {
+            func,
+            entry,
+            predecessor,
+        },
+    );
+
+    // Jumps into another function are reported as cross function jumps, this
+    // catches the blocks that exist but don't belong to any function
+    let jumps_outside_functions = edges
+        .join_map(&function_blocks, |&source, &target, &func| {
+            (target, (source, func))
+        })
+        .semijoin(&basic_blocks.map(|(block, _)| block))
+        .antijoin(&function_blocks.map(|(block, _)| block))
+        .map(
+            |(target, (source, func))| ValidityError::JumpOutsideFunction {
+                func,
+                source,
+                target,
+            },
+        );
+
+    let reachable = entries.map(|(entry, _)| entry).iterate(|reachable| {
+        let edges = edges.enter(&reachable.scope());
+
+        edges
+            .semijoin(reachable)
+            .map(|(_, target)| target)
+            .concat(reachable)
+            .distinct_core()
+    });
+    let unreachable_blocks = function_blocks
+        .semijoin(&basic_blocks.map(|(block, _)| block))
+        .antijoin(&reachable)
+        .map(|(block, func)| ValidityError::UnreachableBlock { func, block });
+
+    invalid_entries
+        .concat(&entry_predecessors)
+        .concat(&jumps_outside_functions)
+        .concat(&unreachable_blocks)
+}
@@ -0,0 +1,184 @@
+//! Checks that every variable is defined before it's used
+//!
+//! A variable's definition has to dominate each of its uses, either by coming
+//! earlier within the same block or by being within a block that dominates the
+//! using block. Function parameters are defined on entry and dominate everything.
+//! Undeclared variables and unreachable blocks are reported by other checks, so
+//! they're skipped here
+
+use crate::{
+    dataflow::Difference,
+    optimize::analysis::Dominators,
+    repr::{
+        basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, FuncId, InstId,
+        Instruction, InstructionExt, VarId,
+    },
+    verify::ValidityError,
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Reduce},
+    Collection,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use timely::dataflow::Scope;
+
+pub(crate) fn verify_dominance<S, R>(
+    instructions: &Collection<S, (InstId, Instruction), R>,
+    basic_blocks: &Collection<S, (BasicBlockId, BasicBlockDesc), R>,
+    functions: &Collection<S, (FuncId, FunctionDesc), R>,
+) -> Collection<S, ValidityError, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let function_blocks = functions.flat_map(|(func, desc)| {
+        desc.basic_blocks
+            .into_iter()
+            .map(move |block| (block, func))
+    });
+
+    let signatures = functions
+        .filter(|(_, desc)| !desc.attributes.is_external())
+        .map(|(func, desc)| {
+            let params = desc.params.iter().map(|param| param.var).collect();
+            (func, FunctionPart::Signature(desc.entry, params))
+        });
+    let blocks = basic_blocks.join_map(&function_blocks, |_, desc, &func| {
+        (func, FunctionPart::Block(desc.clone()))
+    });
+    let definitions = instructions
+        .map(|(inst, instruction)| {
+            let used = instruction
+                .used_vars()
+                .into_iter()
+                .map(|var| var.var)
+                .collect();
+
+            (inst, (instruction.dest(), used))
+        })
+        .join_map(
+            &basic_blocks.flat_map(|(block, desc)| {
+                desc.instructions.into_iter().map(move |inst| (inst, block))
+            }),
+            |&inst, (dest, used), &block| (block, (inst, *dest, used.clone())),
+        )
+        .join_map(&function_blocks, |_, (inst, dest, used), &func| {
+            (func, FunctionPart::Inst(*inst, *dest, used.clone()))
+        });
+
+    signatures
+        .concat(&blocks)
+        .concat(&definitions)
+        .reduce(|&func, parts, output| {
+            for error in uses_before_defs(func, parts) {
+                output.push((error, R::from(1)));
+            }
+        })
+        .map(|(_, error)| error)
+}
+
+/// A fact about a function collected for checking dominance
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum FunctionPart {
+    /// The function's entry and parameters
+    Signature(BasicBlockId, Vec<VarId>),
+    Block(BasicBlockDesc),
+    /// An instruction, the variable it defines and the variables it uses
+    Inst(InstId, VarId, Vec<VarId>),
+}
+
+fn uses_before_defs<R>(func: FuncId, parts: &[(&FunctionPart, R)]) -> Vec<ValidityError> {
+    let (mut signature, mut blocks, mut uses) = (None, BTreeMap::new(), BTreeMap::new());
+    for (part, _diff) in parts {
+        match part {
+            FunctionPart::Signature(entry, params) => signature = Some((*entry, params)),
+            FunctionPart::Block(desc) => {
+                blocks.insert(desc.id, desc);
+            }
+            FunctionPart::Inst(inst, dest, used) => {
+                uses.insert(*inst, (*dest, used));
+            }
+        }
+    }
+
+    let (entry, params) = match signature {
+        Some(signature) => signature,
+        None => return Vec::new(),
+    };
+    let params: BTreeSet<VarId> = params.iter().copied().collect();
+
+    // Where each variable is defined, as the block and the index within it
+    let mut definitions: BTreeMap<VarId, Vec<(BasicBlockId, usize)>> = BTreeMap::new();
+    for desc in blocks.values() {
+        for (idx, inst) in desc.instructions.iter().enumerate() {
+            if let Some(&(dest, _)) = uses.get(inst) {
+                definitions.entry(dest).or_default().push((desc.id, idx));
+            }
+        }
+    }
+
+    let dominators = Dominators::new(
+        entry,
+        blocks.values().flat_map(|desc| {
+            desc.terminator
+                .jump_targets()
+                .into_iter()
+                .map(move |target| (desc.id, target))
+        }),
+    );
+    let is_defined = |var: VarId, block: BasicBlockId, idx: usize| {
+        params.contains(&var)
+            || definitions.get(&var).map_or(true, |defs| {
+                defs.iter().any(|&(def_block, def_idx)| {
+                    (def_block == block && def_idx < idx)
+                        || dominators.strictly_dominates(def_block, block)
+                })
+            })
+    };
+
+    let mut errors = Vec::new();
+    for desc in blocks.values() {
+        if !dominators.is_reachable(desc.id) {
+            continue;
+        }
+
+        for (idx, inst) in desc.instructions.iter().enumerate() {
+            let used = match uses.get(inst) {
+                Some((_, used)) => used,
+                None => continue,
+            };
+
+            for &var in used.iter() {
+                if !is_defined(var, desc.id, idx) {
+                    errors.push(ValidityError::UseBeforeDef {
+                        func,
+                        block: desc.id,
+                        inst: Some(*inst),
+                        var,
+                    });
+                }
+            }
+        }
+
+        let terminator_idx = desc.instructions.len();
+        for value in desc.terminator.used_values() {
+            if let Some(var) = value.as_var() {
+                if !is_defined(var, desc.id, terminator_idx) {
+                    errors.push(ValidityError::UseBeforeDef {
+                        func,
+                        block: desc.id,
+                        inst: None,
+                        var,
+                    });
+                }
+            }
+        }
+    }
+
+    errors.sort();
+    errors.dedup();
+    errors
+}
@@ -0,0 +1,75 @@
+//! Consistency checks between the collections that encode a program
+//!
+//! Instructions, blocks and functions refer to each other by id, so a pass that
+//! forgets to retract one half of a relation or that reuses an id leaves the
+//! encoding in a state that no function could be reconstructed from. Every
+//! instruction has to belong to exactly one block, every block to at most one
+//! function and every instruction a block lists has to exist
+
+use crate::{
+    dataflow::{operators::CountExt, Difference},
+    repr::{
+        basic_block::BasicBlockDesc, function::FunctionDesc, BasicBlockId, FuncId, InstId,
+        Instruction,
+    },
+    verify::ValidityError,
+};
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Threshold},
+    Collection,
+};
+use timely::dataflow::Scope;
+
+pub(crate) fn verify_ids<S, R>(
+    instructions: &Collection<S, (InstId, Instruction), R>,
+    basic_blocks: &Collection<S, (BasicBlockId, BasicBlockDesc), R>,
+    functions: &Collection<S, (FuncId, FunctionDesc), R>,
+) -> Collection<S, ValidityError, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    R: Difference,
+{
+    let block_instructions = basic_blocks
+        .flat_map(|(block, desc)| desc.instructions.into_iter().map(move |inst| (inst, block)));
+    let function_blocks = functions.flat_map(|(func, desc)| {
+        desc.basic_blocks
+            .into_iter()
+            .map(move |block| (block, func))
+    });
+
+    // Listing an instruction twice within the same block counts as a duplicate too
+    #[allow(clippy::suspicious_map)]
+    let duplicate_instructions = block_instructions
+        .map(|(inst, _)| inst)
+        .count_core::<R>()
+        .filter(|(_, count)| count > &R::from(1))
+        .join_map(&block_instructions, |&inst, _count, &block| {
+            ValidityError::DuplicateInstruction { inst, block }
+        });
+
+    #[allow(clippy::suspicious_map)]
+    let duplicate_blocks = function_blocks
+        .map(|(block, _)| block)
+        .count_core::<R>()
+        .filter(|(_, count)| count > &R::from(1))
+        .join_map(&function_blocks, |&block, _count, &func| {
+            ValidityError::DuplicateBlock { block, func }
+        });
+
+    let instruction_ids = instructions.map(|(inst, _)| inst);
+    let missing_instructions = block_instructions
+        .antijoin(&instruction_ids)
+        .map(|(inst, block)| ValidityError::MissingInstruction { block, inst });
+    let orphaned_instructions = instruction_ids
+        .map(|inst| (inst, ()))
+        .antijoin(&block_instructions.map(|(inst, _)| inst))
+        .map(|(inst, ())| ValidityError::OrphanedInstruction { inst });
+
+    duplicate_instructions
+        .concat(&duplicate_blocks)
+        .concat(&missing_instructions)
+        .concat(&orphaned_instructions)
+        .distinct_core()
+}
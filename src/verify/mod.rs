@@ -1,35 +1,49 @@
 //! Tools for verifying the well-formedness of IR
 
+mod cfg;
+mod dominance;
+mod ids;
+mod typecheck;
+mod verifier;
+
+pub use typecheck::{typecheck, TypeError};
+pub use verifier::Verifier;
+
 use crate::{
-    dataflow::operators::{
-        CollectDeclarations, CollectUsages, CollectValues, CollectVariableTypes, CountExt,
-        FilterMap, FilterSplit,
+    dataflow::{
+        operators::{
+            CollectDeclarations, CollectUsages, CollectValues, CollectVariableTypes, CountExt,
+            FilterMap, FilterSplit,
+        },
+        Difference,
     },
     repr::{
         basic_block::BasicBlockDesc,
         function::FunctionDesc,
         instruction::{BinaryOp, Bitcast},
-        BasicBlockId, Cast, Constant, FuncId, InstId, Instruction, InstructionExt, Type, TypedVar,
-        ValueKind, VarId,
+        BasicBlockId, Cast, Constant, FuncId, InstId, Instruction, InstructionExt, ModuleId, Type,
+        TypedVar, ValueKind, VarId,
     },
+    symbols::{BlockName, FunctionName, SymbolResolver, VariableName},
 };
 use abomonation_derive::Abomonation;
 use differential_dataflow::{
-    difference::{Abelian, Multiply},
+    difference::Abelian,
     lattice::Lattice,
     operators::{arrange::ArrangeByKey, Join, JoinCore, Threshold},
     Collection, ExchangeData,
 };
-use std::iter;
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+    iter,
+};
 use timely::dataflow::Scope;
 
 // TODO: Every path is terminated
 // TODO: Check function param types
-// TODO: Check entry blocks exist
 // TODO: Check that all blocks mentioned in `FunctionMeta`s
 //       actually exist
-// TODO: Check that all instructions mentioned in `BasicBlockMeta`s
-//       actually exist
 // TODO: Check all called functions exist
 
 pub fn verify<S, R>(
@@ -41,7 +55,7 @@ pub fn verify<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice + Ord,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     let function_params = functions.flat_map(|(_, meta)| meta.params);
 
@@ -73,6 +87,23 @@ where
             .map(move |target| (target, block))
     });
 
+    let duplicate_switch_cases = basic_blocks.flat_map(|(block, meta)| {
+        let mut seen = BTreeSet::new();
+        meta.terminator
+            .into_switch()
+            .map(|switch| switch.cases)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(move |(case, _)| {
+                if seen.insert(case.clone()) {
+                    None
+                } else {
+                    Some((block, case))
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+
     let undeclared_blocks = targeted_blocks
         .antijoin(&block_ids)
         .map(|(target, source)| (source, target));
@@ -185,6 +216,7 @@ where
             .filter_split(|(id, value)| match value.value {
                 ValueKind::Const(constant) => (Some((id, constant, value.ty)), None),
                 ValueKind::Var(var) => (None, Some((var, value.ty))),
+                ValueKind::Pooled(_) => (None, None),
             });
 
     let invalid_constant_types = constants.filter(|(_, constant, ty)| &constant.ty() != ty);
@@ -200,16 +232,77 @@ where
             },
         ));
 
+    let cfg_errors = cfg::verify_cfg(basic_blocks, functions);
+    let dominance_errors = dominance::verify_dominance(instructions, basic_blocks, functions);
+    let id_errors = ids::verify_ids(instructions, basic_blocks, functions);
+
+    // Calls can only cross into another module through the functions it exports
+    let function_modules =
+        functions.map(|(func, desc)| (func, (desc.module, desc.attributes.is_exported())));
+    let cross_module_calls = instructions
+        .filter_map(|(inst, instruction)| match instruction {
+            Instruction::Call(call) => Some((inst, call.func)),
+            _ => None,
+        })
+        .join_map(
+            &basic_blocks.flat_map(|(block, desc)| {
+                desc.instructions.into_iter().map(move |inst| (inst, block))
+            }),
+            |&inst, &callee, &block| (block, (inst, callee)),
+        )
+        .join_core(&blocks_for_functions, |_block, &(inst, callee), &caller| {
+            iter::once((caller, (inst, callee)))
+        })
+        .join_map(
+            &function_modules,
+            |&caller, &(inst, callee), &(caller_module, _)| (callee, (inst, caller, caller_module)),
+        )
+        .join_map(
+            &function_modules,
+            |&callee, &(inst, caller, caller_module), &(callee_module, exported)| {
+                (caller_module != callee_module && !exported).then(|| {
+                    ValidityError::CrossModuleCall {
+                        inst,
+                        caller,
+                        caller_module,
+                        callee,
+                        callee_module,
+                    }
+                })
+            },
+        )
+        .flat_map(|error| error);
+
+    // Dividing by a constant zero is well defined but always traps
+    let divisions_by_zero = instructions.filter_map(|(inst, instruction)| {
+        let divisor = match instruction {
+            Instruction::Div(div) => div.rhs,
+            Instruction::Rem(rem) => rem.rhs,
+            _ => return None,
+        };
+
+        divisor
+            .as_const()
+            .map_or(false, Constant::is_zero)
+            .then(|| ValidityError::DivisionByZero { inst })
+    });
+
     concat_validity_errors(
         scope,
         &undeclared_variables,
         &redeclarations,
         &undeclared_blocks,
+        &duplicate_switch_cases,
         &cross_function_jumps,
         &incorrect_variable_types,
         &invalid_bitcast,
         &invalid_constant_types,
     )
+    .concat(&cfg_errors)
+    .concat(&dominance_errors)
+    .concat(&id_errors)
+    .concat(&divisions_by_zero)
+    .concat(&cross_module_calls)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
@@ -226,6 +319,10 @@ pub enum ValidityError {
         source: BasicBlockId,
         target: BasicBlockId,
     },
+    DuplicateSwitchCase {
+        block: BasicBlockId,
+        value: Constant,
+    },
     CrossFunctionJump {
         source_block: BasicBlockId,
         source_func: FuncId,
@@ -247,14 +344,297 @@ pub enum ValidityError {
         constant_ty: Type,
         declared_as: Type,
     },
+    /// The function's entry isn't one of its blocks
+    InvalidEntryBlock {
+        func: FuncId,
+        entry: BasicBlockId,
+    },
+    /// A block jumps to the entry of its function
+    EntryHasPredecessors {
+        func: FuncId,
+        entry: BasicBlockId,
+        predecessor: BasicBlockId,
+    },
+    /// A block jumps to a block that doesn't belong to any function
+    JumpOutsideFunction {
+        func: FuncId,
+        source: BasicBlockId,
+        target: BasicBlockId,
+    },
+    /// A block can't be reached from the entry of its function
+    UnreachableBlock {
+        func: FuncId,
+        block: BasicBlockId,
+    },
+    /// A division or remainder by a constant zero, which traps whenever it's executed.
+    /// This is only a [warning](ValidityError::is_warning)
+    DivisionByZero {
+        inst: InstId,
+    },
+    /// A function calls a function of another module that the other module doesn't
+    /// export
+    CrossModuleCall {
+        inst: InstId,
+        caller: FuncId,
+        caller_module: ModuleId,
+        callee: FuncId,
+        callee_module: ModuleId,
+    },
+    /// A variable is used somewhere its definition doesn't dominate, `inst` is
+    /// `None` for uses by the block's terminator
+    UseBeforeDef {
+        func: FuncId,
+        block: BasicBlockId,
+        inst: Option<InstId>,
+        var: VarId,
+    },
+    /// An instruction is listed by more than one block or more than once by the
+    /// same block, reported once for every block that lists it
+    DuplicateInstruction {
+        inst: InstId,
+        block: BasicBlockId,
+    },
+    /// A block is claimed by more than one function, reported once for every
+    /// function that claims it
+    DuplicateBlock {
+        block: BasicBlockId,
+        func: FuncId,
+    },
+    /// A block lists an instruction that doesn't exist
+    MissingInstruction {
+        block: BasicBlockId,
+        inst: InstId,
+    },
+    /// An instruction isn't listed by any block
+    OrphanedInstruction {
+        inst: InstId,
+    },
+}
+
+impl ValidityError {
+    /// Returns `true` if the error describes a program that's valid but most likely
+    /// doesn't do what was intended
+    pub const fn is_warning(&self) -> bool {
+        matches!(self, Self::DivisionByZero { .. })
+    }
 }
 
+impl ValidityError {
+    /// Displays the error with the functions, blocks and variables it refers to named
+    /// by `resolver` wherever it [knows their names](SymbolResolver::function_name)
+    pub fn display_with<'a, R>(&'a self, resolver: &'a R) -> DisplayValidityError<'a>
+    where
+        R: SymbolResolver,
+    {
+        DisplayValidityError {
+            error: self,
+            resolver: Some(resolver),
+        }
+    }
+
+    fn fmt_with(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        resolver: Option<&dyn SymbolResolver>,
+    ) -> fmt::Result {
+        let name = |func: &FuncId| FunctionName::new(*func, resolver);
+        let block_name = |block: &BasicBlockId| BlockName::new(*block, resolver);
+        let var_name = |var: &VarId| VariableName::new(*var, resolver);
+
+        match self {
+            Self::UndeclaredVariable { inst, var } => write!(
+                f,
+                "{:?} uses the undeclared variable {}",
+                inst,
+                var_name(&var.var),
+            ),
+            Self::Redeclaration { inst, var } => {
+                write!(f, "{:?} redeclares {}", inst, var_name(var))
+            }
+            Self::UndeclaredBlock { source, target } => write!(
+                f,
+                "{} jumps to the undeclared block {:?}",
+                block_name(source),
+                target,
+            ),
+            Self::DuplicateSwitchCase { block, value } => write!(
+                f,
+                "the switch terminating {} has multiple cases for {:?}",
+                block_name(block),
+                value,
+            ),
+            Self::CrossFunctionJump {
+                source_block,
+                source_func,
+                target_block,
+                target_func,
+            } => write!(
+                f,
+                "{} within {} jumps to {} within {}",
+                block_name(source_block),
+                name(source_func),
+                block_name(target_block),
+                name(target_func),
+            ),
+            Self::VariableTypeMismatch { var, expected, got } => write!(
+                f,
+                "{} was expected to have the type {} but has the type {}",
+                var_name(var),
+                expected,
+                got,
+            ),
+            Self::InvalidBitcast { inst, source, dest } => write!(
+                f,
+                "{:?} bitcasts from {} to {}, which have different sizes",
+                inst, source, dest,
+            ),
+            Self::ConstantTypeMismatch {
+                inst,
+                constant_ty,
+                declared_as,
+            } => write!(
+                f,
+                "{:?} declares a constant of type {} as {}",
+                inst, constant_ty, declared_as,
+            ),
+            Self::InvalidEntryBlock { func, entry } => write!(
+                f,
+                "the entry {:?} of {} isn't one of its blocks",
+                entry,
+                name(func),
+            ),
+            Self::EntryHasPredecessors {
+                func,
+                entry,
+                predecessor,
+            } => write!(
+                f,
+                "{} jumps to {}, the entry of {}",
+                block_name(predecessor),
+                block_name(entry),
+                name(func),
+            ),
+            Self::JumpOutsideFunction {
+                func,
+                source,
+                target,
+            } => write!(
+                f,
+                "{} within {} jumps to {:?}, which isn't within any function",
+                block_name(source),
+                name(func),
+                target,
+            ),
+            Self::UnreachableBlock { func, block } => write!(
+                f,
+                "{} can't be reached from the entry of {}",
+                block_name(block),
+                name(func),
+            ),
+            Self::DivisionByZero { inst } => {
+                write!(f, "{:?} divides by zero, which always traps", inst)
+            }
+            Self::CrossModuleCall {
+                inst,
+                caller,
+                caller_module,
+                callee,
+                callee_module,
+            } => write!(
+                f,
+                "{:?} within {} of {} calls {} of {}, which {} doesn't export",
+                inst,
+                name(caller),
+                caller_module,
+                name(callee),
+                callee_module,
+                callee_module,
+            ),
+            Self::UseBeforeDef {
+                func,
+                block,
+                inst: Some(inst),
+                var,
+            } => write!(
+                f,
+                "{:?} within {} of {} uses {} before it's defined",
+                inst,
+                block_name(block),
+                name(func),
+                var_name(var),
+            ),
+            Self::UseBeforeDef {
+                func,
+                block,
+                inst: None,
+                var,
+            } => write!(
+                f,
+                "the terminator of {} within {} uses {} before it's defined",
+                block_name(block),
+                name(func),
+                var_name(var),
+            ),
+            Self::DuplicateInstruction { inst, block } => write!(
+                f,
+                "{} lists {:?}, which is listed more than once",
+                block_name(block),
+                inst,
+            ),
+            Self::DuplicateBlock { block, func } => write!(
+                f,
+                "{} claims {}, which is claimed by more than one function",
+                name(func),
+                block_name(block),
+            ),
+            Self::MissingInstruction { block, inst } => write!(
+                f,
+                "{} lists the nonexistent instruction {:?}",
+                block_name(block),
+                inst,
+            ),
+            Self::OrphanedInstruction { inst } => {
+                write!(f, "{:?} doesn't belong to any block", inst)
+            }
+        }
+    }
+}
+
+impl Display for ValidityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, None)
+    }
+}
+
+/// Displays a [`ValidityError`] through a [`SymbolResolver`], created by
+/// [`ValidityError::display_with()`]
+#[derive(Clone, Copy)]
+pub struct DisplayValidityError<'a> {
+    error: &'a ValidityError,
+    resolver: Option<&'a dyn SymbolResolver>,
+}
+
+impl Display for DisplayValidityError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt_with(f, self.resolver)
+    }
+}
+
+impl fmt::Debug for DisplayValidityError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.error, f)
+    }
+}
+
+impl std::error::Error for ValidityError {}
+
 #[allow(clippy::too_many_arguments)]
 fn concat_validity_errors<S, R>(
     scope: &mut S,
     undeclared_variables: &Collection<S, (TypedVar, InstId), R>,
     redeclarations: &Collection<S, (InstId, VarId), R>,
     undeclared_blocks: &Collection<S, (BasicBlockId, BasicBlockId), R>,
+    duplicate_switch_cases: &Collection<S, (BasicBlockId, Constant), R>,
     cross_function_jumps: &Collection<S, (BasicBlockId, FuncId, BasicBlockId, FuncId), R>,
     incorrect_variable_types: &Collection<S, (VarId, Type, Type), R>,
     invalid_bitcast: &Collection<S, (InstId, (Type, Type)), R>,
@@ -279,6 +659,11 @@ where
                     .enter_region(region)
                     .map(|(source, target)| ValidityError::UndeclaredBlock { source, target }),
             )
+            .concat(
+                &duplicate_switch_cases
+                    .enter_region(region)
+                    .map(|(block, value)| ValidityError::DuplicateSwitchCase { block, value }),
+            )
             .concat(&cross_function_jumps.enter_region(region).map(
                 |(source_block, source_func, target_block, target_func)| {
                     ValidityError::CrossFunctionJump {
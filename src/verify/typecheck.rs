@@ -0,0 +1,527 @@
+//! Type checking as its own analysis, separate from the structural checks of
+//! [`verify()`](crate::verify::verify)
+//!
+//! Values are checked against the types they're used as, so operand types must
+//! agree with each other and with their operations, calls must match the
+//! signatures of their callees or intrinsics, accesses to aggregates must be in bounds and
+//! agree with the types of their fields and terminators must be given conditions,
+//! cases and return values of the right types. Anything involving [`Type::Infer`]
+//! is assumed to be correct
+
+use crate::{
+    dataflow::Difference,
+    repr::{
+        basic_block::BasicBlockDesc,
+        function::FunctionDesc,
+        instruction::{BinaryOp, Intrinsic, IntrinsicOp, Select},
+        BasicBlockId, Cast, FuncId, InstId, Instruction, Terminator, Type,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Join, Threshold},
+    Collection,
+};
+use std::fmt::{self, Display};
+use timely::dataflow::Scope;
+
+/// Collects the type errors within a program
+pub fn typecheck<S, R>(
+    scope: &mut S,
+    instructions: &Collection<S, (InstId, Instruction), R>,
+    basic_blocks: &Collection<S, (BasicBlockId, BasicBlockDesc), R>,
+    functions: &Collection<S, (FuncId, FunctionDesc), R>,
+) -> Collection<S, TypeError, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    scope.region_named("typecheck", |region| {
+        let (instructions, basic_blocks, functions) = (
+            instructions.enter_region(region),
+            basic_blocks.enter_region(region),
+            functions.enter_region(region),
+        );
+
+        let operand_errors =
+            instructions.flat_map(|(inst, instruction)| check_operands(inst, instruction));
+        let aggregate_errors =
+            instructions.flat_map(|(inst, instruction)| check_aggregate(inst, &instruction));
+        let intrinsic_errors = instructions.flat_map(|(inst, instruction)| match instruction {
+            Instruction::Intrinsic(intrinsic) => check_intrinsic(inst, intrinsic),
+            _ => None,
+        });
+
+        // The argument types and return type of every call keyed by its callee
+        let calls = instructions.flat_map(|(inst, instruction)| match instruction {
+            Instruction::Call(call) => {
+                let args: Vec<Type> = call.args.iter().map(|arg| arg.ty.clone()).collect();
+                Some((call.func, (inst, args, call.ret_ty)))
+            }
+            _ => None,
+        });
+
+        let undeclared_callees = calls
+            .antijoin(&functions.map(|(func, _)| func))
+            .map(|(callee, (inst, _, _))| TypeError::UndeclaredCallee { inst, callee });
+
+        let signature_errors = calls
+            .join_map(&functions, |&callee, (inst, args, ret_ty), desc| {
+                check_signature(*inst, callee, args, ret_ty, desc)
+            })
+            .flat_map(|errors| errors);
+
+        let terminator_errors =
+            basic_blocks.flat_map(|(block, desc)| check_terminator(block, &desc.terminator));
+
+        let return_errors = functions
+            .flat_map(|(_, desc)| {
+                let ret_ty = desc.ret_ty;
+                desc.basic_blocks
+                    .into_iter()
+                    .map(move |block| (block, ret_ty.clone()))
+            })
+            .join_map(&basic_blocks, |&block, expected, desc| {
+                let ret = match &desc.terminator {
+                    Terminator::Return(ret) => ret,
+                    _ => return None,
+                };
+
+                // Functions returning a value of an inferred type can't have their
+                // arity checked, every other function returns a fixed number of values
+                let (expected_arity, got_arity) = (expected.results().len(), ret.values.len());
+                if !expected.is_infer() && expected_arity != got_arity {
+                    return Some(TypeError::ReturnArityMismatch {
+                        block,
+                        expected: expected_arity,
+                        got: got_arity,
+                    });
+                }
+
+                let got = ret.ty();
+                mismatched(expected, &got).then(|| TypeError::ReturnTypeMismatch {
+                    block,
+                    expected: expected.clone(),
+                    got,
+                })
+            })
+            .flat_map(|error| error);
+
+        operand_errors
+            .concat(&aggregate_errors)
+            .concat(&intrinsic_errors)
+            .concat(&undeclared_callees)
+            .concat(&signature_errors)
+            .concat(&terminator_errors)
+            .concat(&return_errors)
+            .distinct_core()
+            .leave_region()
+    })
+}
+
+/// Checks the operands of arithmetic, comparisons, negations and selects
+fn check_operands(inst: InstId, instruction: Instruction) -> Vec<TypeError> {
+    let mut errors = Vec::new();
+
+    let (operands, numeric) = match instruction {
+        Instruction::Neg(neg) => (vec![neg.value.ty], true),
+        Instruction::Select(select) => return check_select(inst, select),
+        Instruction::Cmp(cmp) => (vec![cmp.lhs.ty, cmp.rhs.ty], false),
+        instruction => match instruction.cast::<BinaryOp>() {
+            Some(op) => {
+                let (lhs, rhs) = op.operands();
+                (vec![lhs.ty, rhs.ty], true)
+            }
+            None => return errors,
+        },
+    };
+
+    if let [lhs, rhs] = operands.as_slice() {
+        if mismatched(lhs, rhs) {
+            errors.push(TypeError::OperandTypeMismatch {
+                inst,
+                lhs: lhs.clone(),
+                rhs: rhs.clone(),
+            });
+        }
+    }
+
+    for ty in operands {
+        let invalid = if numeric {
+            !ty.is_integer()
+        } else {
+            ty == Type::Unit
+        };
+
+        if invalid && !ty.is_infer() {
+            errors.push(TypeError::InvalidOperandType { inst, ty });
+        }
+    }
+
+    errors
+}
+
+/// Checks that a select's condition is a boolean and that both of its values
+/// share a type
+fn check_select(inst: InstId, select: Select) -> Vec<TypeError> {
+    let mut errors = Vec::new();
+
+    let cond = select.cond.ty;
+    if cond != Type::Bool && !cond.is_infer() {
+        errors.push(TypeError::InvalidOperandType { inst, ty: cond });
+    }
+
+    let (lhs, rhs) = (select.if_true.ty, select.if_false.ty);
+    if mismatched(&lhs, &rhs) {
+        errors.push(TypeError::OperandTypeMismatch { inst, lhs, rhs });
+    }
+
+    errors
+}
+
+/// Checks that extracts and insertions are within the bounds of their aggregate
+/// and agree with the type of the field they access
+fn check_aggregate(inst: InstId, instruction: &Instruction) -> Option<TypeError> {
+    let (aggregate, index, got) = match instruction {
+        Instruction::ExtractValue(extract) => (&extract.aggregate.ty, extract.index, &extract.ty),
+        Instruction::InsertValue(insert) => (&insert.aggregate.ty, insert.index, &insert.value.ty),
+        _ => return None,
+    };
+
+    if aggregate.is_infer() {
+        return None;
+    }
+
+    match aggregate.field(index) {
+        Some(expected) => mismatched(expected, got).then(|| TypeError::FieldTypeMismatch {
+            inst,
+            expected: expected.clone(),
+            got: got.clone(),
+        }),
+        None => Some(TypeError::InvalidAggregateIndex {
+            inst,
+            ty: aggregate.clone(),
+            index,
+        }),
+    }
+}
+
+/// Checks an intrinsic against its signature
+fn check_intrinsic(inst: InstId, intrinsic: Intrinsic) -> Option<TypeError> {
+    let args = intrinsic.arg_types();
+
+    match intrinsic.op.result_type(&args) {
+        Some(expected) => mismatched(&expected, &intrinsic.ret_ty).then(|| {
+            TypeError::IntrinsicReturnTypeMismatch {
+                inst,
+                intrinsic: intrinsic.op,
+                expected,
+                got: intrinsic.ret_ty,
+            }
+        }),
+        None => Some(TypeError::IntrinsicSignatureMismatch {
+            inst,
+            intrinsic: intrinsic.op,
+            args,
+        }),
+    }
+}
+
+/// Checks a call against the signature of its callee
+fn check_signature(
+    inst: InstId,
+    callee: FuncId,
+    args: &[Type],
+    ret_ty: &Type,
+    desc: &FunctionDesc,
+) -> Vec<TypeError> {
+    let mut errors = Vec::new();
+
+    if args.len() != desc.params.len() {
+        errors.push(TypeError::ArityMismatch {
+            inst,
+            callee,
+            expected: desc.params.len(),
+            got: args.len(),
+        });
+    }
+
+    for (arg, (got, param)) in args.iter().zip(desc.params.iter()).enumerate() {
+        if mismatched(&param.ty, got) {
+            errors.push(TypeError::ArgumentTypeMismatch {
+                inst,
+                callee,
+                arg,
+                expected: param.ty.clone(),
+                got: got.clone(),
+            });
+        }
+    }
+
+    if mismatched(&desc.ret_ty, ret_ty) {
+        errors.push(TypeError::CallReturnTypeMismatch {
+            inst,
+            callee,
+            expected: desc.ret_ty.clone(),
+            got: ret_ty.clone(),
+        });
+    }
+
+    errors
+}
+
+/// Checks the conditions of branches and the cases of switches
+fn check_terminator(block: BasicBlockId, terminator: &Terminator) -> Vec<TypeError> {
+    match terminator {
+        Terminator::Branch(branch) if mismatched(&Type::Bool, &branch.cond.ty) => {
+            vec![TypeError::ConditionTypeMismatch {
+                block,
+                got: branch.cond.ty.clone(),
+            }]
+        }
+
+        Terminator::Switch(switch) => switch
+            .cases
+            .iter()
+            .map(|(case, _)| case.ty())
+            .filter(|case| mismatched(&switch.scrutinee.ty, case))
+            .map(|case| TypeError::SwitchCaseTypeMismatch {
+                block,
+                scrutinee: switch.scrutinee.ty.clone(),
+                case,
+            })
+            .collect(),
+
+        _ => Vec::new(),
+    }
+}
+
+/// Returns `true` if two types differ, types that still need to be inferred
+/// match every other type
+fn mismatched(expected: &Type, got: &Type) -> bool {
+    !expected.is_infer() && !got.is_infer() && expected != got
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum TypeError {
+    OperandTypeMismatch {
+        inst: InstId,
+        lhs: Type,
+        rhs: Type,
+    },
+    InvalidOperandType {
+        inst: InstId,
+        ty: Type,
+    },
+    InvalidAggregateIndex {
+        inst: InstId,
+        ty: Type,
+        index: u64,
+    },
+    FieldTypeMismatch {
+        inst: InstId,
+        expected: Type,
+        got: Type,
+    },
+    UndeclaredCallee {
+        inst: InstId,
+        callee: FuncId,
+    },
+    ArityMismatch {
+        inst: InstId,
+        callee: FuncId,
+        expected: usize,
+        got: usize,
+    },
+    ArgumentTypeMismatch {
+        inst: InstId,
+        callee: FuncId,
+        arg: usize,
+        expected: Type,
+        got: Type,
+    },
+    CallReturnTypeMismatch {
+        inst: InstId,
+        callee: FuncId,
+        expected: Type,
+        got: Type,
+    },
+    /// An intrinsic was given arguments it can't be applied to
+    IntrinsicSignatureMismatch {
+        inst: InstId,
+        intrinsic: IntrinsicOp,
+        args: Vec<Type>,
+    },
+    IntrinsicReturnTypeMismatch {
+        inst: InstId,
+        intrinsic: IntrinsicOp,
+        expected: Type,
+        got: Type,
+    },
+    ConditionTypeMismatch {
+        block: BasicBlockId,
+        got: Type,
+    },
+    SwitchCaseTypeMismatch {
+        block: BasicBlockId,
+        scrutinee: Type,
+        case: Type,
+    },
+    ReturnTypeMismatch {
+        block: BasicBlockId,
+        expected: Type,
+        got: Type,
+    },
+    ReturnArityMismatch {
+        block: BasicBlockId,
+        expected: usize,
+        got: usize,
+    },
+}
+
+impl TypeError {
+    /// The instruction the error occurred within, or `None` for errors within
+    /// terminators
+    pub const fn inst(&self) -> Option<InstId> {
+        match *self {
+            Self::OperandTypeMismatch { inst, .. }
+            | Self::InvalidOperandType { inst, .. }
+            | Self::InvalidAggregateIndex { inst, .. }
+            | Self::FieldTypeMismatch { inst, .. }
+            | Self::UndeclaredCallee { inst, .. }
+            | Self::ArityMismatch { inst, .. }
+            | Self::ArgumentTypeMismatch { inst, .. }
+            | Self::CallReturnTypeMismatch { inst, .. }
+            | Self::IntrinsicSignatureMismatch { inst, .. }
+            | Self::IntrinsicReturnTypeMismatch { inst, .. } => Some(inst),
+
+            Self::ConditionTypeMismatch { .. }
+            | Self::SwitchCaseTypeMismatch { .. }
+            | Self::ReturnTypeMismatch { .. }
+            | Self::ReturnArityMismatch { .. } => None,
+        }
+    }
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OperandTypeMismatch { inst, lhs, rhs } => write!(
+                f,
+                "{:?} has operands of the different types {} and {}",
+                inst, lhs, rhs,
+            ),
+            Self::InvalidOperandType { inst, ty } => {
+                write!(f, "{:?} can't operate on values of type {}", inst, ty)
+            }
+            Self::InvalidAggregateIndex { inst, ty, index } => write!(
+                f,
+                "{:?} accesses index {} of a value of type {}, which is out of bounds",
+                inst, index, ty,
+            ),
+            Self::FieldTypeMismatch {
+                inst,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{:?} uses a {} as a field of type {}",
+                inst, got, expected,
+            ),
+            Self::UndeclaredCallee { inst, callee } => {
+                write!(f, "{:?} calls the undeclared function {:?}", inst, callee)
+            }
+            Self::ArityMismatch {
+                inst,
+                callee,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{:?} calls {:?} with {} arguments but it takes {}",
+                inst, callee, got, expected,
+            ),
+            Self::ArgumentTypeMismatch {
+                inst,
+                callee,
+                arg,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{:?} passes a {} as argument {} of {:?}, which expects a {}",
+                inst, got, arg, callee, expected,
+            ),
+            Self::CallReturnTypeMismatch {
+                inst,
+                callee,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{:?} expects {:?} to return a {} but it returns a {}",
+                inst, callee, got, expected,
+            ),
+            Self::IntrinsicSignatureMismatch {
+                inst,
+                intrinsic,
+                args,
+            } => {
+                let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "{:?} applies {} to arguments of the types ({}), which it doesn't take",
+                    inst,
+                    intrinsic,
+                    args.join(", "),
+                )
+            }
+            Self::IntrinsicReturnTypeMismatch {
+                inst,
+                intrinsic,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{:?} expects {} to return a {} but it returns a {}",
+                inst, intrinsic, got, expected,
+            ),
+            Self::ConditionTypeMismatch { block, got } => write!(
+                f,
+                "the branch terminating {:?} has a condition of type {} instead of bool",
+                block, got,
+            ),
+            Self::SwitchCaseTypeMismatch {
+                block,
+                scrutinee,
+                case,
+            } => write!(
+                f,
+                "the switch terminating {:?} has a case of type {} for a scrutinee of type {}",
+                block, case, scrutinee,
+            ),
+            Self::ReturnTypeMismatch {
+                block,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{:?} returns a {} from a function returning {}",
+                block, got, expected,
+            ),
+            Self::ReturnArityMismatch {
+                block,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{:?} returns {} values from a function returning {}",
+                block, got, expected,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
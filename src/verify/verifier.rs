@@ -0,0 +1,90 @@
+use crate::{
+    builder::Context,
+    dataflow::{
+        operators::{CrossbeamExtractor, CrossbeamPusher},
+        Diff, InputManager, Time,
+    },
+    driver::load_functions,
+    repr::Function,
+    verify::{verify, ValidityError},
+};
+use differential_dataflow::operators::Consolidate;
+use std::sync::Arc;
+use timely::dataflow::{
+    operators::{capture::Extract, Capture},
+    ProbeHandle,
+};
+
+/// Verifies functions without the rest of the optimization pipeline
+///
+/// This is meant for quickly validating the output of frontends, running a
+/// function through the [`Driver`](crate::driver::Driver) also verifies it
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    context: Arc<Context>,
+}
+
+impl Verifier {
+    pub fn new(context: Arc<Context>) -> Self {
+        Self { context }
+    }
+
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    /// Verifies the given functions within a single-worker dataflow containing
+    /// nothing but [`verify()`], returning every validity error found
+    pub fn check_module(&self, functions: Vec<Function>) -> Vec<ValidityError> {
+        let context = self.context.clone();
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        timely::execute_directly(move |worker| {
+            let mut probe = ProbeHandle::new();
+
+            let mut input = worker.dataflow_named("verifier", |scope| {
+                let input = InputManager::<Time, Diff>::new(scope);
+
+                let instructions = input
+                    .instruction_trace
+                    .import(scope)
+                    .as_collection(|&id, inst| (id, inst.clone()));
+                let basic_blocks = input
+                    .basic_block_trace
+                    .import(scope)
+                    .as_collection(|&id, desc| (id, desc.clone()));
+                let functions = input
+                    .function_trace
+                    .import(scope)
+                    .as_collection(|&id, desc| (id, desc.clone()));
+
+                verify(scope, &instructions, &basic_blocks, &functions)
+                    .consolidate()
+                    .probe_with(&mut probe)
+                    .inner
+                    .capture_into(CrossbeamPusher::new(sender));
+
+                input
+            });
+
+            load_functions(&context, &mut input, functions);
+            input.advance_to(1);
+
+            while probe.less_than(input.time()) {
+                worker.step_or_park(None);
+            }
+        });
+
+        let mut errors = Vec::new();
+        for (_time, data) in CrossbeamExtractor::new(receiver).extract() {
+            for (error, _time, diff) in data {
+                for _ in 0..diff {
+                    errors.push(error.clone());
+                }
+            }
+        }
+        errors.sort();
+
+        errors
+    }
+}
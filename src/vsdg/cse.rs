@@ -1,5 +1,9 @@
 use crate::{
-    dataflow::operators::{FilterSplit, Reverse, SemijoinExt, Split},
+    dataflow::{
+        algorithms::propagate::least_label_propagation,
+        operators::{FilterSplit, Reverse, SemijoinExt, Split},
+        Difference,
+    },
     vsdg::{
         node::{Constant, End, NodeExt},
         ProgramGraph,
@@ -7,14 +11,11 @@ use crate::{
 };
 use abomonation_derive::Abomonation;
 use differential_dataflow::{
-    algorithms::graphs::propagate,
-    difference::{Abelian, Multiply},
     lattice::Lattice,
     operators::{
         arrange::{ArrangeByKey, ArrangeBySelf},
         Join, JoinCore, Threshold,
     },
-    ExchangeData,
 };
 use std::convert::identity;
 use timely::dataflow::Scope;
@@ -23,7 +24,7 @@ pub fn cse<S, R>(scope: &mut S, graph: &ProgramGraph<S, R>) -> ProgramGraph<S, R
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     scope.region_named("common subexpression elimination", |region| {
         let graph = graph.enter_region(region);
@@ -38,7 +39,7 @@ where
         // eliminate expressions on a per-function basis
         //
         // Is a collection of `NodeId`s to their root `End`'s `NodeId`
-        let nodes_grouped = propagate::propagate(&edges, &roots);
+        let nodes_grouped = least_label_propagation(&edges, &roots);
 
         // Get all the nodes eligible for elimination
         // TODO: Add more node kinds other than just constants,
@@ -2,6 +2,7 @@ use crate::{
     dataflow::{
         algorithms::reachable,
         operators::{Flatten, Split},
+        Difference,
     },
     vsdg::{
         node::{NodeExt, NodeId, Place},
@@ -9,13 +10,12 @@ use crate::{
     },
 };
 use differential_dataflow::{
-    difference::{Abelian, Multiply},
     lattice::Lattice,
     operators::{
         arrange::{ArrangeByKey, ArrangeBySelf},
         Join, JoinCore,
     },
-    Collection, ExchangeData,
+    Collection,
 };
 use dogsdogsdogs::{
     altneu::AltNeu,
@@ -28,7 +28,7 @@ pub fn dce<S, R>(scope: &mut S, graph: &ProgramGraph<S, R>) -> ProgramGraph<S, R
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     scope.region_named("dead code elimination", |region| {
         let graph = remove_places(region, &graph.enter_region(region));
@@ -69,7 +69,7 @@ fn delta_cull_edges<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R>,
+    R: Difference,
 {
     scope.scoped::<AltNeu<_>, _, _>("delta-join culling edges", |inner| {
         let neu = |time: &AltNeu<_>| {
@@ -125,7 +125,7 @@ fn remove_places<S, R>(scope: &mut S, graph: &ProgramGraph<S, R>) -> ProgramGrap
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R>,
+    R: Difference,
 {
     scope.region_named("remove place values", |region| {
         let graph = graph.enter_region(region);
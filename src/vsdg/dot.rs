@@ -1,29 +1,23 @@
 use crate::{
-    dataflow::operators::CrossbeamExtractor,
+    artifacts::ArtifactSink,
+    parallel::MaybeSync,
+    repr::ModuleMeta,
     vsdg::{
+        export::{export_graphs, export_graphs_named, node_label, ExportOptions, GraphLayout},
         logging::GraphReceiver,
-        node::{Constant, Error, FuncId, Function, Node, NodeExt, NodeId, Value},
+        node::{FuncId, Function, Node, NodeId, Value},
         Edge, ProgramGraph,
     },
 };
 use abomonation_derive::Abomonation;
 use differential_dataflow::difference::{Monoid, Semigroup};
-use petgraph::{dot::Dot, Graph};
 use std::{
-    cmp::Ordering,
     collections::HashMap,
-    fmt::Debug,
-    fs::{self, OpenOptions},
-    hash::Hash,
-    io::Write,
-    iter::Step,
-    process::Command,
+    fmt::{self, Write},
+    io,
 };
 use timely::dataflow::{
-    operators::{
-        capture::{Event, EventPusher},
-        Capture, Map,
-    },
+    operators::{capture::EventPusher, Capture, Map},
     Scope,
 };
 
@@ -67,184 +61,193 @@ where
     }
 }
 
-pub fn render_graphs<T, R>(receiver: GraphReceiver<T, R>)
+/// Renders all graphs sent through `receiver` into `sink`, prefixing each with
+/// the module's metadata if it's given
+///
+/// Every graph is rendered even if writing one of them fails, the first error
+/// that occurred is returned. See [`export_graphs()`] for exporting graphs in
+/// other formats or without invoking graphviz
+pub fn render_graphs<T, R, A>(
+    receiver: GraphReceiver<T, R>,
+    sink: A,
+    meta: Option<&ModuleMeta>,
+) -> io::Result<()>
 where
-    R: Monoid + Step,
+    R: Monoid + Ord + From<i8>,
+    A: ArtifactSink + MaybeSync,
 {
-    let mut graphs = HashMap::new();
-    for event in CrossbeamExtractor::new(receiver) {
-        if let Event::Messages(_, data) = event {
-            for ((name, node), _time, diff) in data {
-                let entry = graphs
-                    .entry(name)
-                    .or_insert_with(|| Vec::with_capacity(1024));
-
-                for _ in R::zero()..diff {
-                    entry.push(node.clone());
-                }
-            }
-        }
-    }
-
-    for (graph_name, mut graph_data) in graphs {
-        graph_data.sort_by(|a, b| {
-            match (
-                matches!(a, GraphNode::Node(_)),
-                matches!(b, GraphNode::Node(_)),
-            ) {
-                (true, true) | (false, false) => Ordering::Equal,
-                (true, false) => Ordering::Less,
-                (false, true) => Ordering::Greater,
-            }
-        });
+    export_graphs(receiver, sink, meta, &ExportOptions::default())
+}
 
-        let (mut graph, mut node_ids) = (Graph::new(), HashMap::new());
-        for node in graph_data {
-            match node {
-                GraphNode::ValueEdge((src, dest)) => {
-                    let src = node_ids.get(&src).copied().unwrap_or_else(|| {
-                        tracing::error!(src = ?src, dest = ?dest, "missing value edge source");
-                        graph.add_node(Node::Error(Error {}))
-                    });
+/// Renders all graphs like [`render_graphs()`], labelling their functions and nodes
+/// with the names `names` knows for them
+pub fn render_graphs_named<T, R, A>(
+    receiver: GraphReceiver<T, R>,
+    sink: A,
+    meta: Option<&ModuleMeta>,
+    names: &GraphNames,
+) -> io::Result<()>
+where
+    R: Monoid + Ord + From<i8>,
+    A: ArtifactSink + MaybeSync,
+{
+    export_graphs_named(receiver, sink, meta, &ExportOptions::default(), names)
+}
 
-                    let dest = node_ids.get(&dest).copied().unwrap_or_else(|| {
-                        tracing::error!(src = ?src, dest = ?dest, "missing value edge dest");
-                        graph.add_node(Node::Error(Error {}))
-                    });
+/// Renders a graph into dot source with the nodes of each function grouped into
+/// their own cluster
+///
+/// Edges can refer to nodes that weren't captured along with them, like when a
+/// pass produces an edge without its endpoints, those endpoints are rendered as
+/// placeholder nodes and the edges to them are highlighted
+pub fn to_dot(graph_data: &[GraphNode]) -> String {
+    to_dot_named(graph_data, &GraphNames::default())
+}
 
-                    graph.add_edge(src, dest, EdgeKind::Value);
-                }
+/// Renders a graph into dot source like [`to_dot()`], labelling the functions and
+/// nodes that `graph_names` knows with their names
+pub fn to_dot_named(graph_data: &[GraphNode], graph_names: &GraphNames) -> String {
+    let mut dot = String::new();
+    write_dot(&mut dot, &GraphLayout::new(graph_data), graph_names)
+        .expect("writing to a string can't fail");
 
-                GraphNode::EffectEdge((src, dest)) => {
-                    let src = node_ids.get(&src).copied().unwrap_or_else(|| {
-                        tracing::error!(src = ?src, dest = ?dest, "missing value edge source");
-                        graph.add_node(Node::Error(Error {}))
-                    });
+    dot
+}
 
-                    let dest = node_ids.get(&dest).copied().unwrap_or_else(|| {
-                        tracing::error!(src = ?src, dest = ?dest, "missing value edge dest");
-                        graph.add_node(Node::Error(Error {}))
-                    });
+/// The user-given names of the functions and nodes within a graph, usually taken
+/// from the [`Builder`](crate::builder::Builder::graph_names) that built it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphNames {
+    pub functions: HashMap<FuncId, String>,
+    pub nodes: HashMap<NodeId, String>,
+}
 
-                    graph.add_edge(src, dest, EdgeKind::Effect);
-                }
+impl GraphNames {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-                GraphNode::ControlEdge((src, dest)) => {
-                    let src = node_ids.get(&src).copied().unwrap_or_else(|| {
-                        tracing::error!(src = ?src, dest = ?dest, "missing value edge source");
-                        graph.add_node(Node::Error(Error {}))
-                    });
+    pub fn with_function<N>(mut self, func: FuncId, name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.functions.insert(func, name.into());
+        self
+    }
 
-                    let dest = node_ids.get(&dest).copied().unwrap_or_else(|| {
-                        tracing::error!(src = ?src, dest = ?dest, "missing value edge dest");
-                        graph.add_node(Node::Error(Error {}))
-                    });
+    pub fn with_node<N>(mut self, node: NodeId, name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.nodes.insert(node, name.into());
+        self
+    }
+}
 
-                    graph.add_edge(src, dest, EdgeKind::Control);
-                }
+fn write_dot<W>(dot: &mut W, layout: &GraphLayout, graph_names: &GraphNames) -> fmt::Result
+where
+    W: Write,
+{
+    let names = &layout.names;
+    let node_name = |node_id: &NodeId| graph_names.nodes.get(node_id).map(String::as_str);
 
-                GraphNode::Node((node_id, ref node)) => {
-                    let graph_id = graph.add_node(node.clone());
+    writeln!(dot, "digraph {{")?;
+    for (idx, (func_id, function_nodes)) in layout.functions.iter().enumerate() {
+        let function_nodes: Vec<_> = function_nodes
+            .iter()
+            .filter_map(|node_id| layout.nodes.get(node_id).map(|node| (node_id, node)))
+            .collect();
+        if function_nodes.is_empty() {
+            continue;
+        }
 
-                    if let Some(old_idx) = node_ids.insert(node_id, graph_id) {
-                        tracing::error!(
-                            node_id = ?node_id,
-                            node = ?node,
-                            old_idx = ?old_idx,
-                            new_idx = ?graph_id,
-                            "double inserted a graph node",
-                        );
-                    }
-                }
+        writeln!(dot, "    subgraph cluster_{} {{", idx)?;
+        let label = graph_names
+            .functions
+            .get(func_id)
+            .cloned()
+            .unwrap_or_else(|| format!("{:?}", func_id));
+        writeln!(dot, "        label = \"{}\";", escape(&label))?;
 
-                // TODO
-                GraphNode::FunctionNode(_) | GraphNode::Function(_) => {}
-            }
+        for (node_id, node) in function_nodes {
+            writeln!(
+                dot,
+                "        {} [{}];",
+                names[node_id],
+                node_attributes(node, node_name(node_id)),
+            )?;
         }
+        writeln!(dot, "    }}")?;
+    }
 
-        let dot = Dot::with_attr_getters(
-            &graph,
-            &[
-                petgraph::dot::Config::EdgeNoLabel,
-                petgraph::dot::Config::NodeNoLabel,
-            ],
-            &|_graph, edge| {
-                match edge.weight() {
-                    EdgeKind::Control => "color = black",
-                    EdgeKind::Effect => "color = cornflowerblue",
-                    EdgeKind::Value => "color = forestgreen",
-                    EdgeKind::Error => "color = red",
-                }
-                .to_owned()
-            },
-            &|_graph, (_idx, node)| match node {
-                Node::Value(value) => match value {
-                    Value::Constant(constant) => match constant {
-                        Constant::Uint8(uint8) => {
-                            format!("label = \"{}: u8\", shape = circle", uint8)
-                        }
-                        Constant::Bool(b) => {
-                            format!("label = \"{}: bool\", shape = circle", b)
-                        }
-                        Constant::Array(arr) => {
-                            format!("label = \"{:?}: array\", shape = circle", arr)
-                        }
-                    },
-                    Value::Parameter(param) => {
-                        format!("label = \"param: {}\", shape = doublecircle", param.ty)
-                    }
-                    Value::Pointer(_ptr) => "label = \"pointer\", shape = doublecircle".to_owned(),
-                },
-                Node::Control(control) => {
-                    format!("label = \"{}\", shape = diamond", control.node_name())
-                }
-                Node::Operation(operation) => {
-                    format!("label = \"{}\", shape = box", operation.node_name())
-                }
-                Node::End(_) | Node::Start(_) | Node::Merge(_) => {
-                    format!(
-                        "label = \"{}\", shape = box, peripheries = 2",
-                        node.node_name(),
-                    )
-                }
-                Node::Place(_) => "shape = point".to_owned(),
-                Node::Error(error) => format!("label = \"{}\", shape = diamond", error.node_name()),
-            },
-        );
+    for (node_id, node) in layout.nodes.iter() {
+        if !layout.node_functions.contains_key(node_id) {
+            writeln!(
+                dot,
+                "    {} [{}];",
+                names[node_id],
+                node_attributes(node, node_name(node_id)),
+            )?;
+        }
+    }
 
-        let _ = fs::remove_dir_all(format!("target/debug/{}", graph_name));
-        let _ = fs::remove_file(format!("target/debug/{}.png", graph_name));
-        fs::create_dir_all(format!("target/debug/{}", graph_name)).unwrap();
+    for node_id in layout.missing.iter() {
+        writeln!(
+            dot,
+            "    {} [label = \"missing {}\", shape = diamond, style = dashed, color = red];",
+            names[node_id],
+            escape(&format!("{:?}", node_id)),
+        )?;
+    }
 
-        let name = format!("target/debug/{}/graphviz.dot", graph_name);
+    for (src, dest, kind) in layout.edges.iter() {
+        writeln!(
+            dot,
+            "    {} -> {} [{}];",
+            names[src],
+            names[dest],
+            edge_attributes(*kind),
+        )?;
+    }
+    writeln!(dot, "}}")
+}
 
-        {
-            let mut file = OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .create(true)
-                .open(&name)
-                .unwrap();
+fn node_attributes(node: &Node, name: Option<&str>) -> String {
+    let shape = match node {
+        Node::Value(Value::Constant(_)) => "circle",
+        Node::Value(Value::Parameter(_)) | Node::Value(Value::Pointer(_)) => "doublecircle",
+        Node::Control(_) | Node::Error(_) => "diamond",
+        Node::Operation(_) => "box",
+        Node::End(_) | Node::Start(_) | Node::Merge(_) => "box, peripheries = 2",
+        Node::Place(_) => "point",
+    };
 
-            file.write_all(format!("{:?}", dot).as_bytes()).unwrap();
-        }
+    let label = match (name, node_label(node)) {
+        (Some(name), Some(label)) => Some(format!("{} = {}", name, label)),
+        (Some(name), None) => Some(name.to_owned()),
+        (None, label) => label,
+    };
 
-        Command::new("dot")
-            .arg(&name)
-            .args(&["-Tpng", "-o"])
-            .arg(name.replace("/graphviz.dot", ".png"))
-            .status()
-            .unwrap();
+    match label {
+        Some(label) => format!("label = \"{}\", shape = {}", escape(&label), shape),
+        None => format!("shape = {}", shape),
+    }
+}
 
-        Command::new("dot")
-            .arg(&name)
-            .args(&["-Tsvg", "-o"])
-            .arg(name.replace(".dot", ".svg"))
-            .status()
-            .unwrap();
+const fn edge_attributes(kind: EdgeKind) -> &'static str {
+    match kind {
+        EdgeKind::Control => "color = black",
+        EdgeKind::Effect => "color = cornflowerblue",
+        EdgeKind::Value => "color = forestgreen",
+        EdgeKind::Error => "color = red, style = dashed",
     }
 }
 
+/// Escapes a string for use within a quoted dot attribute
+fn escape(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
 pub enum GraphNode {
     ValueEdge(Edge),
@@ -262,11 +265,3 @@ pub enum EdgeKind {
     Control,
     Error,
 }
-
-// A hack because `petgraph::Dot` requires it
-#[doc(hidden)]
-impl std::fmt::Display for EdgeKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "")
-    }
-}
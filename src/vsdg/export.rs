@@ -0,0 +1,513 @@
+//! Exporting program graphs without depending on graphviz
+//!
+//! Graphs can be written as dot source (optionally rendered through graphviz),
+//! as GraphML or as JSON in the node-link format understood by tools like d3
+//! and networkx. Which format is used is picked by [`ExportOptions`]
+
+use crate::{
+    artifacts::{sanitize_name, ArtifactSink},
+    dataflow::operators::CrossbeamExtractor,
+    parallel::{self, MaybeSync},
+    repr::ModuleMeta,
+    vsdg::{
+        dot::{self, EdgeKind, GraphNames, GraphNode},
+        logging::GraphReceiver,
+        node::{Constant, FuncId, Function, Node, NodeExt, NodeId, Value},
+    },
+};
+use differential_dataflow::difference::Monoid;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Write},
+    io,
+    path::Path,
+};
+use timely::dataflow::operators::capture::Event;
+
+/// The format graphs are exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// Graphviz dot source
+    Dot,
+    /// GraphML, an xml based format
+    GraphMl,
+    /// JSON in the node-link format
+    Json,
+}
+
+impl ExportFormat {
+    /// The name of the file a graph is written to within its directory
+    pub const fn file_name(self) -> &'static str {
+        match self {
+            Self::Dot => "graphviz.dot",
+            Self::GraphMl => "graph.graphml",
+            Self::Json => "graph.json",
+        }
+    }
+}
+
+/// Configures how graphs are exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExportOptions {
+    /// The format graphs are written in
+    pub format: ExportFormat,
+    /// Whether graphs in the dot format are also rendered into images through
+    /// graphviz, ignored by every other format
+    pub render_images: bool,
+}
+
+impl ExportOptions {
+    pub const fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            render_images: false,
+        }
+    }
+
+    pub const fn with_render_images(mut self, render_images: bool) -> Self {
+        self.render_images = render_images;
+        self
+    }
+}
+
+impl Default for ExportOptions {
+    /// Dot source that's rendered by graphviz, the same as
+    /// [`render_graphs()`](crate::vsdg::dot::render_graphs)
+    fn default() -> Self {
+        Self::new(ExportFormat::Dot).with_render_images(true)
+    }
+}
+
+/// Exports all graphs sent through `receiver` into `sink`, prefixing each with
+/// the module's metadata if it's given
+///
+/// Every graph is exported even if writing one of them fails, the first error
+/// that occurred is returned
+pub fn export_graphs<T, R, A>(
+    receiver: GraphReceiver<T, R>,
+    sink: A,
+    meta: Option<&ModuleMeta>,
+    options: &ExportOptions,
+) -> io::Result<()>
+where
+    R: Monoid + Ord + From<i8>,
+    A: ArtifactSink + MaybeSync,
+{
+    export_graphs_named(receiver, sink, meta, options, &GraphNames::default())
+}
+
+/// Exports all graphs like [`export_graphs()`], labelling the functions and nodes
+/// within dot output with the names `names` knows for them, like the ones given to
+/// a [`Builder`](crate::builder::Builder::graph_names)
+pub fn export_graphs_named<T, R, A>(
+    receiver: GraphReceiver<T, R>,
+    sink: A,
+    meta: Option<&ModuleMeta>,
+    options: &ExportOptions,
+    names: &GraphNames,
+) -> io::Result<()>
+where
+    R: Monoid + Ord + From<i8>,
+    A: ArtifactSink + MaybeSync,
+{
+    let mut graphs = HashMap::new();
+    for event in CrossbeamExtractor::new(receiver) {
+        if let Event::Messages(_, data) = event {
+            for ((name, node), _time, diff) in data {
+                let entry = graphs
+                    .entry(name)
+                    .or_insert_with(|| Vec::with_capacity(1024));
+
+                // Counts up to the diff instead of ranging over it so that `R`
+                // doesn't need the unstable `Step` trait
+                let (one, mut count) = (R::from(1), R::zero());
+                while count < diff {
+                    entry.push(node.clone());
+                    count += &one;
+                }
+            }
+        }
+    }
+
+    // Each graph is independent so they can all be exported in parallel
+    parallel::map(graphs.into_iter().collect(), |(graph_name, graph_data)| {
+        export_graph(&sink, meta, &graph_name, &graph_data, options, names)
+    })
+    .into_iter()
+    .collect()
+}
+
+fn export_graph<A>(
+    sink: &A,
+    meta: Option<&ModuleMeta>,
+    graph_name: &str,
+    graph_data: &[GraphNode],
+    options: &ExportOptions,
+    names: &GraphNames,
+) -> io::Result<()>
+where
+    A: ArtifactSink,
+{
+    let contents = match options.format {
+        ExportFormat::Dot => {
+            let header = meta
+                .map(|meta| meta.comment_header("// "))
+                .unwrap_or_default();
+            let dot = format!("{}{}", header, dot::to_dot_named(graph_data, names));
+
+            if options.render_images {
+                return sink.write_graph(graph_name, &dot);
+            }
+            dot
+        }
+
+        ExportFormat::GraphMl => to_graphml(graph_data, meta),
+        ExportFormat::Json => to_json(graph_data, meta),
+    };
+
+    let path = Path::new(&sanitize_name(graph_name)).join(options.format.file_name());
+    sink.write(&path, contents.as_bytes())?;
+
+    Ok(())
+}
+
+/// Renders a graph into GraphML, nodes are annotated with their label, kind and
+/// the function they belong to and edges with their kind
+pub fn to_graphml(graph_data: &[GraphNode], meta: Option<&ModuleMeta>) -> String {
+    let mut graphml = String::new();
+    write_graphml(&mut graphml, &GraphLayout::new(graph_data), meta)
+        .expect("writing to a string can't fail");
+
+    graphml
+}
+
+fn write_graphml<W>(graphml: &mut W, layout: &GraphLayout, meta: Option<&ModuleMeta>) -> fmt::Result
+where
+    W: Write,
+{
+    writeln!(graphml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    if let Some(meta) = meta {
+        writeln!(graphml, "<!--")?;
+        // Comments can't contain `--`
+        write!(
+            graphml,
+            "{}",
+            meta.comment_header("  ").replace("--", "- -")
+        )?;
+        writeln!(graphml, "-->")?;
+    }
+
+    writeln!(
+        graphml,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#,
+    )?;
+    for (id, target, name) in [
+        ("label", "node", "label"),
+        ("kind", "node", "kind"),
+        ("function", "node", "function"),
+        ("edge_kind", "edge", "kind"),
+    ]
+    .iter()
+    {
+        writeln!(
+            graphml,
+            r#"  <key id="{}" for="{}" attr.name="{}" attr.type="string"/>"#,
+            id, target, name,
+        )?;
+    }
+
+    writeln!(graphml, r#"  <graph id="G" edgedefault="directed">"#)?;
+    for node in layout.nodes() {
+        writeln!(graphml, r#"    <node id="{}">"#, node.name)?;
+        if let Some(label) = node.label {
+            writeln!(
+                graphml,
+                r#"      <data key="label">{}</data>"#,
+                escape_xml(&label),
+            )?;
+        }
+        writeln!(graphml, r#"      <data key="kind">{}</data>"#, node.kind)?;
+        if let Some(func_id) = node.function {
+            writeln!(
+                graphml,
+                r#"      <data key="function">{}</data>"#,
+                escape_xml(&format!("{:?}", func_id)),
+            )?;
+        }
+        writeln!(graphml, "    </node>")?;
+    }
+
+    for (idx, (src, dest, kind)) in layout.edges.iter().enumerate() {
+        writeln!(
+            graphml,
+            r#"    <edge id="e{}" source="{}" target="{}">"#,
+            idx, layout.names[src], layout.names[dest],
+        )?;
+        writeln!(
+            graphml,
+            r#"      <data key="edge_kind">{}</data>"#,
+            edge_kind(*kind),
+        )?;
+        writeln!(graphml, "    </edge>")?;
+    }
+
+    writeln!(graphml, "  </graph>")?;
+    writeln!(graphml, "</graphml>")
+}
+
+/// Renders a graph into JSON in the node-link format, the module's metadata is
+/// kept within the `graph` object since JSON has no comments
+pub fn to_json(graph_data: &[GraphNode], meta: Option<&ModuleMeta>) -> String {
+    let mut json = String::new();
+    write_json(&mut json, &GraphLayout::new(graph_data), meta)
+        .expect("writing to a string can't fail");
+
+    json
+}
+
+fn write_json<W>(json: &mut W, layout: &GraphLayout, meta: Option<&ModuleMeta>) -> fmt::Result
+where
+    W: Write,
+{
+    writeln!(json, "{{")?;
+    writeln!(json, r#"  "directed": true,"#)?;
+    writeln!(json, r#"  "multigraph": true,"#)?;
+    match meta {
+        Some(meta) => writeln!(
+            json,
+            r#"  "graph": {{ "meta": "{}" }},"#,
+            escape_json(&meta.to_string()),
+        )?,
+        None => writeln!(json, r#"  "graph": {{}},"#)?,
+    }
+
+    writeln!(json, r#"  "nodes": ["#)?;
+    let nodes: Vec<_> = layout.nodes().collect();
+    for (idx, node) in nodes.iter().enumerate() {
+        write!(
+            json,
+            r#"    {{ "id": "{}", "kind": "{}""#,
+            node.name, node.kind
+        )?;
+        if let Some(label) = node.label.as_ref() {
+            write!(json, r#", "label": "{}""#, escape_json(label))?;
+        }
+        if let Some(func_id) = node.function {
+            write!(
+                json,
+                r#", "function": "{}""#,
+                escape_json(&format!("{:?}", func_id)),
+            )?;
+        }
+        writeln!(json, " }}{}", if idx + 1 < nodes.len() { "," } else { "" })?;
+    }
+    writeln!(json, "  ],")?;
+
+    writeln!(json, r#"  "links": ["#)?;
+    for (idx, (src, dest, kind)) in layout.edges.iter().enumerate() {
+        writeln!(
+            json,
+            r#"    {{ "source": "{}", "target": "{}", "kind": "{}" }}{}"#,
+            layout.names[src],
+            layout.names[dest],
+            edge_kind(*kind),
+            if idx + 1 < layout.edges.len() {
+                ","
+            } else {
+                ""
+            },
+        )?;
+    }
+    writeln!(json, "  ]")?;
+    writeln!(json, "}}")
+}
+
+/// A graph's nodes, functions and edges, collected from its [`GraphNode`]s
+///
+/// Edges can refer to nodes that weren't captured along with them, like when a
+/// pass produces an edge without its endpoints, those endpoints are collected as
+/// missing nodes and the edges to them are given [`EdgeKind::Error`]
+#[derive(Debug, Clone)]
+pub(crate) struct GraphLayout {
+    pub(crate) nodes: BTreeMap<NodeId, Node>,
+    pub(crate) functions: BTreeMap<FuncId, Vec<NodeId>>,
+    pub(crate) node_functions: HashMap<NodeId, FuncId>,
+    pub(crate) missing: BTreeSet<NodeId>,
+    pub(crate) edges: Vec<(NodeId, NodeId, EdgeKind)>,
+    /// The unique name of every node and missing node
+    pub(crate) names: HashMap<NodeId, String>,
+}
+
+impl GraphLayout {
+    pub(crate) fn new(graph_data: &[GraphNode]) -> Self {
+        let mut nodes = BTreeMap::new();
+        let mut functions: BTreeMap<FuncId, Vec<NodeId>> = BTreeMap::new();
+        let mut node_functions = HashMap::new();
+        let mut edges = Vec::new();
+
+        for node in graph_data {
+            match node {
+                GraphNode::Node((node_id, node)) => {
+                    if let Some(old_node) = nodes.insert(*node_id, node.clone()) {
+                        tracing::error!(
+                            node_id = ?node_id,
+                            node = ?node,
+                            old_node = ?old_node,
+                            "double inserted a graph node",
+                        );
+                    }
+                }
+
+                GraphNode::ValueEdge((src, dest)) => edges.push((*src, *dest, EdgeKind::Value)),
+                GraphNode::EffectEdge((src, dest)) => {
+                    edges.push((*src, *dest, EdgeKind::Effect));
+                }
+                GraphNode::ControlEdge((src, dest)) => {
+                    edges.push((*src, *dest, EdgeKind::Control));
+                }
+
+                GraphNode::FunctionNode((node_id, func_id)) => {
+                    node_functions.insert(*node_id, *func_id);
+                    functions.entry(*func_id).or_default().push(*node_id);
+                }
+                GraphNode::Function((func_id, Function {})) => {
+                    functions.entry(*func_id).or_default();
+                }
+            }
+        }
+
+        // Every endpoint that doesn't have a node gets a placeholder
+        let mut missing = BTreeSet::new();
+        for (src, dest, kind) in edges.iter_mut() {
+            for endpoint in [*src, *dest].iter() {
+                if !nodes.contains_key(endpoint) {
+                    if missing.insert(*endpoint) {
+                        tracing::error!(
+                            src = ?src,
+                            dest = ?dest,
+                            "missing graph node {:?}",
+                            endpoint,
+                        );
+                    }
+
+                    *kind = EdgeKind::Error;
+                }
+            }
+        }
+
+        let names = nodes
+            .keys()
+            .chain(missing.iter())
+            .enumerate()
+            .map(|(idx, &node_id)| (node_id, format!("n{}", idx)))
+            .collect();
+
+        Self {
+            nodes,
+            functions,
+            node_functions,
+            missing,
+            edges,
+            names,
+        }
+    }
+
+    /// Every node followed by every missing node, in the order of their names
+    fn nodes(&self) -> impl Iterator<Item = NodeEntry<'_>> + '_ {
+        let nodes = self.nodes.iter().map(move |(node_id, node)| NodeEntry {
+            name: &self.names[node_id],
+            label: node_label(node),
+            kind: node_kind(node),
+            function: self.node_functions.get(node_id).copied(),
+        });
+        let missing = self.missing.iter().map(move |node_id| NodeEntry {
+            name: &self.names[node_id],
+            label: Some(format!("missing {:?}", node_id)),
+            kind: "missing",
+            function: None,
+        });
+
+        nodes.chain(missing)
+    }
+}
+
+struct NodeEntry<'a> {
+    name: &'a str,
+    label: Option<String>,
+    kind: &'static str,
+    function: Option<FuncId>,
+}
+
+/// The unescaped label of a node, places don't have one
+pub(crate) fn node_label(node: &Node) -> Option<String> {
+    let label = match node {
+        Node::Value(value) => match value {
+            Value::Constant(constant) => match constant {
+                Constant::Uint8(uint8) => format!("{}: u8", uint8),
+                Constant::Bool(b) => format!("{}: bool", b),
+                Constant::F32(bits) => format!("{:?}: f32", f32::from_bits(*bits)),
+                Constant::F64(bits) => format!("{:?}: f64", f64::from_bits(*bits)),
+                Constant::Array(arr) => format!("{:?}: array", arr),
+            },
+            Value::Parameter(param) => format!("param: {}", param.ty),
+            Value::Pointer(_ptr) => "pointer".to_owned(),
+        },
+        Node::Control(control) => control.node_name().to_owned(),
+        Node::Operation(operation) => operation.node_name().to_owned(),
+        Node::End(_) | Node::Start(_) | Node::Merge(_) => node.node_name().to_owned(),
+        Node::Place(_) => return None,
+        Node::Error(error) => error.node_name().to_owned(),
+    };
+
+    Some(label)
+}
+
+const fn node_kind(node: &Node) -> &'static str {
+    match node {
+        Node::Value(_) => "value",
+        Node::Control(_) => "control",
+        Node::Operation(_) => "operation",
+        Node::Start(_) => "start",
+        Node::End(_) => "end",
+        Node::Merge(_) => "merge",
+        Node::Place(_) => "place",
+        Node::Error(_) => "error",
+    }
+}
+
+const fn edge_kind(kind: EdgeKind) -> &'static str {
+    match kind {
+        EdgeKind::Value => "value",
+        EdgeKind::Effect => "effect",
+        EdgeKind::Control => "control",
+        EdgeKind::Error => "error",
+    }
+}
+
+fn escape_xml(string: &str) -> String {
+    string
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_json(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for char in string.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            char if char.is_control() => {
+                // Writing to a string can't fail
+                let _ = write!(escaped, "\\u{:04x}", char as u32);
+            }
+            char => escaped.push(char),
+        }
+    }
+
+    escaped
+}
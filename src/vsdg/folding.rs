@@ -1,15 +1,18 @@
 use crate::{
-    dataflow::operators::{
-        DiscriminatedIdents, FilterMap, FlatSplit, Flatten, InspectExt, Keys, Reverse, SemijoinExt,
-        SplitBy,
+    dataflow::{
+        operators::{
+            DiscriminatedIdents, FilterMap, FlatSplit, Flatten, InspectExt, Keys, Reverse,
+            SemijoinExt,
+        },
+        Difference,
     },
     vsdg::{
-        node::{Add, Constant, Node, NodeExt, NodeId, Place, Sub},
+        node::{Add, Constant, Node, NodeExt, NodeId, Operation, Place, Sub},
+        pattern::{self, EdgeKind, Match, Pattern, PatternNode, Replacement, Rule},
         ProgramGraph,
     },
 };
 use differential_dataflow::{
-    difference::{Abelian, Multiply},
     lattice::Lattice,
     operators::{
         arrange::{ArrangeByKey, ArrangeBySelf},
@@ -17,12 +20,10 @@ use differential_dataflow::{
         iterate::SemigroupVariable,
         Join, JoinCore, Reduce, Threshold,
     },
-    AsCollection, ExchangeData,
+    AsCollection,
 };
 use std::{
-    convert::identity,
-    iter::{self, Step},
-    mem,
+    iter, mem,
     sync::{
         atomic::{AtomicU8, Ordering},
         Arc,
@@ -45,14 +46,15 @@ pub fn constant_folding<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8> + Step,
+    R: Difference,
 {
     // TODO: constant_folding is gonna be a lot more efficient if you use a delta-join for the first two joins,
     //       apply the .flat_split on the delta-stream, conclude with the antijoin (the .concat is the same
     //       in delta-stream-land) and finish up with .integrate()
     scope.region_named("constant folding", |region| {
         let graph = graph.enter_region(region);
-        let graph = algebraic_simplification(region, &graph, ident_discriminant);
+        let graph = algebraic_simplification(region, &graph, ident_discriminant.clone());
+        let graph = operation_folding(region, &graph, &ident_discriminant);
 
         let values_to_consumers = graph
             .value_edges
@@ -72,8 +74,11 @@ where
                 output.push((constants, R::from(1)));
             });
 
+        // Operations have already been folded, this only picks up the nodes that
+        // consume constants without evaluating to one, like branches
         let (evaluated_nodes, value_edges_to_remove) = graph
             .nodes
+            .filter(|(_, node)| node.isnt::<Operation>())
             .join(&used_constants)
             .flat_split(|(node_id, (node, constants))| {
                 let (evaluated_node, removed_edges) = node.evaluate_with_constants(&constants);
@@ -100,6 +105,77 @@ where
     })
 }
 
+/// Replaces every operation whose operands are all constant with the constant it
+/// evaluates to, dropping the edges to its operands
+fn operation_folding<S, R>(
+    scope: &mut S,
+    graph: &ProgramGraph<S, R>,
+    ident_discriminant: &AtomicU8,
+) -> ProgramGraph<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let rules = [binary_operation_folding(), unary_operation_folding()];
+    pattern::apply_rules(scope, graph, &rules, ident_discriminant)
+}
+
+fn binary_operation_folding() -> Rule {
+    let mut pattern = Pattern::new(|node| {
+        node.cast::<Operation>()
+            .map_or(false, |operation| operation.operands().len() == 2)
+    });
+    let lhs = pattern.node(|node| node.is::<Constant>());
+    let rhs = pattern.node(|node| node.is::<Constant>());
+    pattern.edge(PatternNode::ROOT, lhs, EdgeKind::Value).edge(
+        PatternNode::ROOT,
+        rhs,
+        EdgeKind::Value,
+    );
+
+    Rule::new("Op(const, const) => const", pattern, fold_operation)
+}
+
+fn unary_operation_folding() -> Rule {
+    let mut pattern = Pattern::new(|node| {
+        node.cast::<Operation>()
+            .map_or(false, |operation| operation.operands().len() == 1)
+    });
+    let value = pattern.node(|node| node.is::<Constant>());
+    pattern.edge(PatternNode::ROOT, value, EdgeKind::Value);
+
+    Rule::new("Op(const) => const", pattern, fold_operation)
+}
+
+/// Evaluates the operation at the root of a match, the rest of the match must be
+/// its operands in order so that each operation is only folded once
+fn fold_operation(matched: &Match) -> Option<Replacement> {
+    let operation = matched.root_node().cast::<Operation>()?;
+    let operands = &matched.nodes()[1..];
+    if !operands.iter().map(|&(id, _)| id).eq(operation.operands()) {
+        return None;
+    }
+
+    let constants: Vec<(NodeId, Constant)> = operands
+        .iter()
+        .filter_map(|(id, node)| {
+            node.cast::<Constant>()
+                .map(|constant| (*id, constant.clone()))
+        })
+        .collect();
+    let (evaluated, consumed) = operation.clone().evaluate_with_constants(&constants);
+    let evaluated = evaluated.cast::<Constant>()?.clone();
+
+    let mut replacement = Replacement::new();
+    replacement.replace(matched.root(), matched.root_node().clone(), evaluated);
+    for operand in consumed {
+        replacement.remove_edge(EdgeKind::Value, matched.root(), operand);
+    }
+
+    Some(replacement)
+}
+
 fn algebraic_simplification<S, R>(
     scope: &mut S,
     graph: &ProgramGraph<S, R>,
@@ -108,7 +184,7 @@ fn algebraic_simplification<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8> + Step,
+    R: Difference,
 {
     scope.region_named("Algebraic simplification", |region| {
         let graph = graph.enter_region(region);
@@ -129,7 +205,7 @@ fn zero_addition<S, R>(
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     scope.region_named("Add(x, 0) | Add(0, x) => x", |region| {
         let graph = graph.enter_region(region);
@@ -147,7 +223,7 @@ where
         });
         let non_const = graph.nodes.filter(|(_id, node)| node.isnt::<Constant>());
 
-        let (new_nodes, discarded_nodes, discarded_edges) = addition
+        let rewrites = addition
             .join_core(&value_edges_forward, |&add_id, add_node, &producer_id| {
                 iter::once((producer_id, (add_id, add_node.to_owned())))
             })
@@ -182,18 +258,17 @@ where
                         vec![value_edge, zero_edge, (consumer_id, add_id)],
                     ))
                 },
-            )
-            .split_by(identity);
+            );
+
+        let new_nodes = rewrites.map(|(new_node, _, _)| new_node);
+        let discarded_nodes = rewrites.map(|(_, discarded_node, _)| discarded_node);
+        let discarded_edges = rewrites.map(|(_, _, discarded_edges)| discarded_edges);
 
         let edge_discriminant = ident_discriminant.fetch_add(1, Ordering::Relaxed);
-        let (new_nodes, new_edges) = new_nodes.discriminated_idents(edge_discriminant).split_by(
-            |((node, consumer_id), new_id)| {
-                (
-                    (NodeId::new(new_id), node),
-                    (consumer_id, NodeId::new(new_id)),
-                )
-            },
-        );
+        let minted = new_nodes.discriminated_idents(edge_discriminant);
+        let new_nodes = minted.map(|((node, _consumer_id), new_id)| (NodeId::new(new_id), node));
+        let new_edges =
+            minted.map(|((_node, consumer_id), new_id)| (consumer_id, NodeId::new(new_id)));
 
         let discarded_edges =
             discarded_edges
@@ -263,7 +338,7 @@ fn self_subtract<S, R>(scope: &mut S, graph: &ProgramGraph<S, R>) -> ProgramGrap
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8> + Step,
+    R: Difference,
 {
     scope.region_named("Sub(x, x) => 0", |region| {
         let graph = graph.enter_region(region);
@@ -283,59 +358,49 @@ where
                 iter::once((sub_id, (value_id, sub_node.to_owned())))
             })
             .reduce(|_, values, output| {
-                let all_operands_eq = values.iter().eq_by(
-                    values.iter(),
-                    |((node1_id, node1), _), ((node2_id, node2), _)| {
+                let all_operands_eq = values.iter().zip(values.iter()).all(
+                    |(((node1_id, node1), _), ((node2_id, node2), _))| {
                         node1_id == node2_id || node1 == node2
                     },
                 );
 
                 if all_operands_eq {
-                    let value_ids: Vec<NodeId> = values
-                        .iter()
-                        .flat_map(|(&(id, _), diff)| {
-                            (R::zero()..diff.clone()).into_iter().map(move |_| id)
-                        })
-                        .collect();
-
-                    output.push((value_ids, R::from(1)));
+                    output.push(((), R::from(1)));
                 }
             });
 
-        let (new_nodes, discarded_nodes, discarded_edges) =
-            SemijoinExt::semijoin(&value_edges_reverse, &subtraction_nodes)
-                .join_core(&arranged_nodes, |&sub_id, &value_id, sub_node| {
-                    iter::once((sub_id, (value_id, sub_node.to_owned())))
-                })
-                .join_map(
-                    &identical_operands,
-                    |&sub_id, &(_consumer_id, ref sub_node), value_ids| {
-                        let new_node: (NodeId, Node) = (sub_id, Constant::Uint8(0).into());
-                        let discarded_node = (sub_id, sub_node.to_owned());
-
-                        let discarded_edges: Vec<(NodeId, NodeId)> = value_ids
-                            .iter()
-                            .map(|&value_id| (sub_id, value_id))
-                            .collect();
-
-                        (new_node, discarded_node, discarded_edges)
-                    },
-                )
-                .split_by(identity);
+        let rewrites = SemijoinExt::semijoin(&value_edges_reverse, &subtraction_nodes)
+            .join_core(&arranged_nodes, |&sub_id, &value_id, sub_node| {
+                iter::once((sub_id, (value_id, sub_node.to_owned())))
+            })
+            .join_map(
+                &identical_operands,
+                |&sub_id, &(_consumer_id, ref sub_node), &()| {
+                    let new_node: (NodeId, Node) = (sub_id, Constant::Uint8(0).into());
+                    let discarded_node = (sub_id, sub_node.to_owned());
 
-        let discarded_edges =
-            discarded_edges
-                .flatten()
-                .negate()
-                .debug_inspect(|((src, dest), time, diff)| {
-                    tracing::trace!(
-                        "discarding value edge from Sub(x, x): ({}->{}, {:?}, {:?})",
-                        src,
-                        dest,
-                        time,
-                        diff,
-                    );
-                });
+                    (new_node, discarded_node)
+                },
+            );
+
+        let new_nodes = rewrites.map(|(new_node, _)| new_node);
+        let discarded_nodes = rewrites.map(|(_, discarded_node)| discarded_node);
+
+        // The operand edges of every rewritten subtraction are dropped along with it
+        let discarded_edges = SemijoinExt::semijoin(
+            &value_edges_forward,
+            &identical_operands.map(|(sub_id, ())| sub_id),
+        )
+        .negate()
+        .debug_inspect(|((src, dest), time, diff)| {
+            tracing::trace!(
+                "discarding value edge from Sub(x, x): ({}->{}, {:?}, {:?})",
+                src,
+                dest,
+                time,
+                diff,
+            );
+        });
 
         // TODO: Don't remove nodes, mint them
         let nodes = graph
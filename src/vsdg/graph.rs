@@ -1,9 +1,8 @@
 use crate::{
-    dataflow::operators::FilterMap,
+    dataflow::{algorithms::propagate::least_label_propagation, operators::FilterMap, Difference},
     vsdg::node::{End, FuncId, Function, Node, NodeExt, NodeId},
 };
 use differential_dataflow::{
-    algorithms::graphs::propagate,
     difference::{Abelian, Multiply, Semigroup},
     input::{Input, InputSession},
     lattice::Lattice,
@@ -120,6 +119,17 @@ where
         }
     }
 
+    pub fn concat(&self, other: &Self) -> Self {
+        Self {
+            value_edges: self.value_edges.concat(&other.value_edges),
+            effect_edges: self.effect_edges.concat(&other.effect_edges),
+            control_edges: self.control_edges.concat(&other.control_edges),
+            nodes: self.nodes.concat(&other.nodes),
+            function_nodes: self.function_nodes.concat(&other.function_nodes),
+            functions: self.functions.concat(&other.functions),
+        }
+    }
+
     pub fn probe(&self) -> Handle<S::Timestamp> {
         let mut handle = Handle::new();
         self.probe_with(&mut handle);
@@ -190,12 +200,12 @@ where
     pub fn node_memberships(&self) -> Collection<S, (NodeId, NodeId), R>
     where
         S::Timestamp: Lattice,
-        R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+        R: Difference,
     {
         let edges = self.all_edges();
         let roots = self.end_node_ids().map(|id| (id, id));
 
-        propagate::propagate(&edges, &roots)
+        least_label_propagation(&edges, &roots)
     }
 
     pub fn function_ends(&self) -> Collection<S, (FuncId, NodeId), R>
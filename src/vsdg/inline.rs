@@ -1,13 +1,11 @@
 use crate::{
-    dataflow::operators::{CountExt, InspectExt},
+    dataflow::{
+        operators::{CountExt, InspectExt},
+        Difference,
+    },
     vsdg::{node::NodeExt, ProgramGraph},
 };
-use differential_dataflow::{
-    difference::{Abelian, Multiply},
-    lattice::Lattice,
-    operators::Join,
-    ExchangeData,
-};
+use differential_dataflow::{difference::Multiply, lattice::Lattice, operators::Join};
 use std::iter;
 use timely::dataflow::Scope;
 
@@ -19,7 +17,7 @@ pub fn trivial_inline<S, R>(scope: &mut S, graph: &ProgramGraph<S, R>) -> Progra
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
     isize: Multiply<R, Output = isize>,
 {
     scope.region_named("trivial inline", |region| {
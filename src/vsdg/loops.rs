@@ -1,13 +1,11 @@
 use crate::{
-    dataflow::operators::SemijoinExt,
+    dataflow::{algorithms::scc, operators::SemijoinExt, Difference},
     vsdg::{Edge, ProgramGraph},
 };
 use differential_dataflow::{
-    algorithms::graphs::scc,
-    difference::{Abelian, Multiply},
     lattice::Lattice,
     operators::{arrange::ArrangeByKey, Iterate, Threshold},
-    Collection, ExchangeData,
+    Collection,
 };
 use timely::dataflow::Scope;
 
@@ -15,7 +13,7 @@ pub fn detect_loops<S, R>(scope: &mut S, graph: &ProgramGraph<S, R>) -> Collecti
 where
     S: Scope,
     S::Timestamp: Lattice,
-    R: Abelian + ExchangeData + Multiply<Output = R> + From<i8>,
+    R: Difference,
 {
     scope.region_named("detect loops", |region| {
         // Reduce the control graph to strongly connected components
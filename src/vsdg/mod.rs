@@ -1,28 +1,27 @@
 mod cse;
 mod dce;
 pub mod dot;
+pub mod export;
 mod folding;
 mod graph;
 mod inline;
 mod logging;
 mod loops;
 pub mod node;
+pub mod pattern;
+pub mod regions;
 pub mod tests;
+pub mod types;
 
 pub use graph::{
     Edge, ProgramArranged, ProgramGraph, ProgramInputs, ProgramTrace, ProgramVariable,
 };
 
-use crate::{equisat, vsdg::logging::GraphSender};
+use crate::{dataflow::Difference, equisat, vsdg::logging::GraphSender};
 use abomonation_derive::Abomonation;
-use differential_dataflow::{
-    difference::{Abelian, Multiply},
-    lattice::Lattice,
-    ExchangeData,
-};
+use differential_dataflow::{difference::Multiply, lattice::Lattice};
 use std::{
     collections::HashMap,
-    iter::Step,
     rc::Rc,
     sync::{atomic::AtomicU8, Arc},
     time::Duration,
@@ -51,7 +50,7 @@ pub fn optimization_dataflow<A, T, R>(
 where
     A: Allocate,
     T: Timestamp + Lattice + TotalOrder + Refines<()>,
-    R: Abelian + Multiply<Output = R> + From<i8> + ExchangeData + Step,
+    R: Difference,
     isize: Multiply<R, Output = isize>,
 {
     worker.dataflow::<T, _, _>(|scope| {
@@ -82,7 +81,10 @@ where
         // let equisat = equisat::saturate(scope, &*ident_generation, &graph);
         // equisat.render_graph("equisat", sender.clone());
 
-        let graph = folding::constant_folding(scope, &graph, ident_generation);
+        // Folding assumes its operands are well typed, so ill-typed functions are skipped
+        let graph = types::gate_rewrite(scope, &graph, |scope, graph| {
+            folding::constant_folding(scope, graph, ident_generation)
+        });
         graph.render_graph("constant folding", sender.clone());
 
         // let graph = cse::cse(scope, &graph);
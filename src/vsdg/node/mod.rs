@@ -6,9 +6,9 @@ mod value;
 
 pub use control::{Branch, Control, Error, LoopHead, LoopTail, Return};
 pub use node_ext::{Castable, NodeExt};
-pub use operation::{Add, Cmp, CmpKind, Load, Mul, Operation, Store, Sub};
+pub use operation::{Add, And, Cmp, CmpKind, Div, Load, Mul, Not, Operation, Or, Store, Sub, Xor};
 pub use structure::{End, Merge, Place, Start};
-pub use value::{Constant, Parameter, Pointer, Type, Value};
+pub use value::{Constant, EvaluationError, Parameter, Pointer, Type, Value};
 
 use crate::dataflow::operators::Uuid;
 use abomonation_derive::Abomonation;
@@ -42,7 +42,7 @@ macro_rules! create_id {
 
             #[allow(dead_code)]
             impl $id {
-                crate const fn new(id: Uuid) -> Self {
+                pub(crate) const fn new(id: Uuid) -> Self {
                     Self(id)
                 }
             }
@@ -1,6 +1,6 @@
 use super::{
     node_ext::{Castable, NodeExt},
-    Constant, Node, NodeId,
+    Constant, EvaluationError, Node, NodeId,
 };
 use abomonation_derive::Abomonation;
 use derive_more::From;
@@ -17,11 +17,100 @@ pub enum Operation {
     Mul(Mul),
     Add(Add),
     Sub(Sub),
+    Div(Div),
     Cmp(Cmp),
+    And(And),
+    Or(Or),
+    Xor(Xor),
+    Not(Not),
     Load(Load),
     Store(Store),
 }
 
+impl Operation {
+    /// The value operands of the operation in order, memory operations don't
+    /// record theirs so they have none
+    pub fn operands(&self) -> Vec<NodeId> {
+        match self {
+            Self::Mul(Mul { lhs, rhs })
+            | Self::Add(Add { lhs, rhs })
+            | Self::Sub(Sub { lhs, rhs })
+            | Self::Div(Div { lhs, rhs })
+            | Self::Cmp(Cmp { lhs, rhs, .. })
+            | Self::And(And { lhs, rhs })
+            | Self::Or(Or { lhs, rhs })
+            | Self::Xor(Xor { lhs, rhs }) => vec![*lhs, *rhs],
+            Self::Not(Not { value }) => vec![*value],
+            Self::Load(_) | Self::Store(_) => Vec::new(),
+        }
+    }
+}
+
+/// Evaluates a binary operation once both of its operands are constant, returning
+/// the evaluated constant along with the operands it no longer depends on
+///
+/// Operations that fail to evaluate, like a division by zero or operands of
+/// mismatched types, are left as-is so that they fail at runtime instead
+fn evaluate_binary<N, F>(
+    node: N,
+    (lhs, rhs): (NodeId, NodeId),
+    constants: &[(NodeId, Constant)],
+    evaluate: F,
+) -> (Node, Vec<NodeId>)
+where
+    N: NodeExt + Into<Node>,
+    F: FnOnce(&Constant, &Constant) -> Result<Constant, EvaluationError>,
+{
+    if let Some(unrelated) = constants.iter().find(|&&(id, _)| id != lhs && id != rhs) {
+        tracing::error!(
+            "tried to evaluate a `{}` node with {:?}, which isn't one of its operands [{:?}, {:?}]",
+            node.node_name(),
+            unrelated,
+            lhs,
+            rhs,
+        );
+
+        return (node.into(), Vec::new());
+    }
+
+    let operand = |operand| {
+        constants
+            .iter()
+            .find(|&&(id, _)| id == operand)
+            .map(|(_, constant)| constant)
+    };
+    let (left, right) = match (operand(lhs), operand(rhs)) {
+        (Some(left), Some(right)) => (left, right),
+        _ => return (node.into(), Vec::new()),
+    };
+
+    match evaluate(left, right) {
+        Ok(result) => {
+            tracing::trace!(
+                "evaluating a `{}` node: {:?}, {:?} = {:?}",
+                node.node_name(),
+                left,
+                right,
+                result,
+            );
+
+            (result.into(), vec![lhs, rhs])
+        }
+
+        Err(err @ EvaluationError::DivisionByZero { .. })
+        | Err(err @ EvaluationError::NanResult { .. }) => {
+            tracing::trace!("left a `{}` node unevaluated: {}", node.node_name(), err);
+            (node.into(), Vec::new())
+        }
+
+        Err(err) => {
+            tracing::error!("failed to evaluate a `{}` node: {}", node.node_name(), err);
+            (node.into(), Vec::new())
+        }
+    }
+}
+
+/// Wrapping multiplication
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
 pub struct Mul {
     pub lhs: NodeId,
@@ -33,47 +122,9 @@ impl NodeExt for Mul {
         "Mul"
     }
 
-    // TODO: Maybe start using error nodes for failures
     fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
-        match constants {
-            [] | [_] => (self.into(), Vec::new()),
-
-            &[(left_id, ref left), (right_id, ref right)] =>
-                // FIXME: Update rhs and lhs when folding
-                // if (left_id == self.lhs && right_id == self.rhs)
-                //     || (left_id == self.rhs && right_id == self.lhs) =>
-            {
-                let sum = left * right;
-                tracing::trace!(
-                    "evaluating a `Mul` node: {:?} + {:?} = {:?}",
-                    left,
-                    right,
-                    sum,
-                );
-
-                // Note: If something with mul is buggy, it's because it doesn't actually respect
-                //       the declared left hand and right hand side nodes. This *shouldn't* matter,
-                //       but if weird shit starts happening it's probably this
-                (sum.into(), vec![left_id, right_id])
-            }
-
-            [rest @ ..] => {
-                tracing::error!(
-                    "tried to evaluate a `Mul` node with {} operands: {:?}, expected [{:?}, {:?}]",
-                    rest.len(),
-                    rest,
-                    self.lhs,
-                    self.rhs,
-                );
-                debug_assert_eq!(
-                    rest.len(),
-                    2,
-                    "incorrect number of operands passed to a `Mul` node",
-                );
-
-                (self.into(), Vec::new())
-            }
-        }
+        let operands = (self.lhs, self.rhs);
+        evaluate_binary(self, operands, constants, Constant::checked_mul)
     }
 
     // TODO: Take into account the const-ness of inputs and the possibility of
@@ -83,6 +134,7 @@ impl NodeExt for Mul {
     }
 }
 
+/// Wrapping addition
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
 pub struct Add {
     pub lhs: NodeId,
@@ -94,47 +146,9 @@ impl NodeExt for Add {
         "Add"
     }
 
-    // TODO: Maybe start using error nodes for failures
     fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
-        match constants {
-            [] | [_] => (self.into(), Vec::new()),
-
-            &[(left_id, ref left), (right_id, ref right)] =>
-                // FIXME: Update rhs and lhs when folding
-                // if (left_id == self.lhs && right_id == self.rhs)
-                //     || (left_id == self.rhs && right_id == self.lhs) =>
-            {
-                let sum = left + right;
-                tracing::trace!(
-                    "evaluating an `Add` node: {:?} + {:?} = {:?}",
-                    left,
-                    right,
-                    sum,
-                );
-
-                // Note: If something with add is buggy, it's because it doesn't actually respect
-                //       the declared left hand and right hand side nodes. This *shouldn't* matter,
-                //       but if weird shit starts happening it's probably this
-                (sum.into(), vec![left_id, right_id])
-            }
-
-            [rest @ ..] => {
-                tracing::error!(
-                    "tried to evaluate an `Add` node with {} operands: {:?}, expected [{:?}, {:?}]",
-                    rest.len(),
-                    rest,
-                    self.lhs,
-                    self.rhs,
-                );
-                debug_assert_eq!(
-                    rest.len(),
-                    2,
-                    "incorrect number of operands passed to an `Add` node",
-                );
-
-                (self.into(), Vec::new())
-            }
-        }
+        let operands = (self.lhs, self.rhs);
+        evaluate_binary(self, operands, constants, Constant::checked_add)
     }
 
     // TODO: Take into account the const-ness of inputs and the possibility of
@@ -144,6 +158,7 @@ impl NodeExt for Add {
     }
 }
 
+/// Wrapping subtraction
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
 pub struct Sub {
     pub lhs: NodeId,
@@ -155,55 +170,39 @@ impl NodeExt for Sub {
         "Sub"
     }
 
-    // TODO: Maybe start using error nodes for failures
     fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
-        match constants {
-            [] | [_] => (self.into(), Vec::new()),
-
-            &[(left_id, ref left), (right_id, ref right)]
-                if (left_id == self.lhs && right_id == self.rhs)
-                    || (left_id == self.rhs && right_id == self.lhs) =>
-            {
-                let (left, right) = if left_id == self.lhs && right_id == self.rhs {
-                    (left, right)
-                } else {
-                    (right, left)
-                };
-                let sum = left - right;
-
-                tracing::trace!(
-                    "evaluating a `Sub` node: {:?} + {:?} = {:?}",
-                    left,
-                    right,
-                    sum,
-                );
+        let operands = (self.lhs, self.rhs);
+        evaluate_binary(self, operands, constants, Constant::checked_sub)
+    }
 
-                (sum.into(), vec![left_id, right_id])
-            }
+    // TODO: Take into account the const-ness of inputs and the possibility of
+    //       const promotion
+    fn inline_cost(&self) -> isize {
+        1
+    }
+}
 
-            [rest @ ..] => {
-                tracing::error!(
-                    "tried to evaluate a `Sub` node with {} operands: {:?}, expected [{:?}, {:?}]",
-                    rest.len(),
-                    rest,
-                    self.lhs,
-                    self.rhs,
-                );
-                debug_assert_eq!(
-                    rest.len(),
-                    2,
-                    "incorrect number of operands passed to a `Sub` node",
-                );
+/// Division, which traps when dividing by zero
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct Div {
+    pub lhs: NodeId,
+    pub rhs: NodeId,
+}
 
-                (self.into(), Vec::new())
-            }
-        }
+impl NodeExt for Div {
+    fn node_name(&self) -> &'static str {
+        "Div"
+    }
+
+    fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
+        let operands = (self.lhs, self.rhs);
+        evaluate_binary(self, operands, constants, Constant::checked_div)
     }
 
     // TODO: Take into account the const-ness of inputs and the possibility of
     //       const promotion
     fn inline_cost(&self) -> isize {
-        1
+        2
     }
 }
 
@@ -219,56 +218,124 @@ impl NodeExt for Cmp {
         "Cmp"
     }
 
-    // TODO: Maybe start using error nodes for failures
+    fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
+        let (operands, kind) = ((self.lhs, self.rhs), self.kind);
+        evaluate_binary(self, operands, constants, |left, right| {
+            left.checked_cmp(kind, right)
+        })
+    }
+
+    // TODO: Take into account the const-ness of inputs and the possibility of
+    //       const promotion
+    fn inline_cost(&self) -> isize {
+        2
+    }
+}
+
+/// Bitwise and of integers or logical and of booleans
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct And {
+    pub lhs: NodeId,
+    pub rhs: NodeId,
+}
+
+impl NodeExt for And {
+    fn node_name(&self) -> &'static str {
+        "And"
+    }
+
+    fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
+        let operands = (self.lhs, self.rhs);
+        evaluate_binary(self, operands, constants, Constant::checked_and)
+    }
+
+    // TODO: Take into account the const-ness of inputs and the possibility of
+    //       const promotion
+    fn inline_cost(&self) -> isize {
+        1
+    }
+}
+
+/// Bitwise or of integers or logical or of booleans
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct Or {
+    pub lhs: NodeId,
+    pub rhs: NodeId,
+}
+
+impl NodeExt for Or {
+    fn node_name(&self) -> &'static str {
+        "Or"
+    }
+
+    fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
+        let operands = (self.lhs, self.rhs);
+        evaluate_binary(self, operands, constants, Constant::checked_or)
+    }
+
+    // TODO: Take into account the const-ness of inputs and the possibility of
+    //       const promotion
+    fn inline_cost(&self) -> isize {
+        1
+    }
+}
+
+/// Bitwise xor of integers or logical xor of booleans
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct Xor {
+    pub lhs: NodeId,
+    pub rhs: NodeId,
+}
+
+impl NodeExt for Xor {
+    fn node_name(&self) -> &'static str {
+        "Xor"
+    }
+
+    fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
+        let operands = (self.lhs, self.rhs);
+        evaluate_binary(self, operands, constants, Constant::checked_xor)
+    }
+
+    // TODO: Take into account the const-ness of inputs and the possibility of
+    //       const promotion
+    fn inline_cost(&self) -> isize {
+        1
+    }
+}
+
+/// Bitwise not of an integer or logical not of a boolean
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct Not {
+    pub value: NodeId,
+}
+
+impl NodeExt for Not {
+    fn node_name(&self) -> &'static str {
+        "Not"
+    }
+
     fn evaluate_with_constants(self, constants: &[(NodeId, Constant)]) -> (Node, Vec<NodeId>) {
         match constants {
-            [] | [_] => (self.into(), Vec::new()),
-
-            &[(left_id, ref left), (right_id, ref right)]
-                if (left_id == self.lhs && right_id == self.rhs)
-                    || (left_id == self.rhs && right_id == self.lhs) =>
-            {
-                let (left, right) = if left_id == self.lhs && right_id == self.rhs {
-                    (left, right)
-                } else if left_id == self.rhs && right_id == self.lhs {
-                    (right, left)
-                } else {
-                    panic!("invalid node ids")
-                };
-
-                // TODO: Make sure ord/eq are implemented properly
-                let result = Constant::Bool(match self.kind {
-                    CmpKind::Eq => left == right,
-                    CmpKind::NotEq => left != right,
-                    CmpKind::Less => left < right,
-                    CmpKind::Greater => left > right,
-                    CmpKind::LessEq => left <= right,
-                    CmpKind::GreaterEq => left >= right,
-                });
-
-                tracing::trace!(
-                    "evaluating a `Cmp` node: {:?} {} {:?} = {:?}",
-                    left,
-                    self.kind,
-                    right,
-                    result,
-                );
+            [] => (self.into(), Vec::new()),
 
-                (result.into(), vec![left_id, right_id])
-            }
+            [(id, value)] if *id == self.value => match value.checked_not() {
+                Ok(result) => {
+                    tracing::trace!("evaluating a `Not` node: !{:?} = {:?}", value, result);
+                    (result.into(), vec![self.value])
+                }
 
-            [rest @ ..] => {
+                Err(err) => {
+                    tracing::error!("failed to evaluate a `Not` node: {}", err);
+                    (self.into(), Vec::new())
+                }
+            },
+
+            rest => {
                 tracing::error!(
-                    "tried to evaluate an `Add` node with {} operands: {:?}, expected [{:?}, {:?}]",
-                    rest.len(),
+                    "tried to evaluate a `Not` node with {:?}, expected [{:?}]",
                     rest,
-                    self.lhs,
-                    self.rhs,
-                );
-                debug_assert_eq!(
-                    rest.len(),
-                    2,
-                    "incorrect number of operands passed to an `Add` node",
+                    self.value,
                 );
 
                 (self.into(), Vec::new())
@@ -279,7 +346,7 @@ impl NodeExt for Cmp {
     // TODO: Take into account the const-ness of inputs and the possibility of
     //       const promotion
     fn inline_cost(&self) -> isize {
-        2
+        1
     }
 }
 
@@ -374,7 +441,12 @@ util_traits! {
     Mul,
     Add,
     Sub,
+    Div,
     Cmp,
+    And,
+    Or,
+    Xor,
+    Not,
     Load,
     Store,
 }
@@ -1,21 +1,39 @@
 use super::{
-    super::node_ext::{Castable, NodeExt},
+    super::{
+        node_ext::{Castable, NodeExt},
+        CmpKind,
+    },
     Node, NodeId, Value,
 };
 use abomonation_derive::Abomonation;
-use std::{
-    hint,
-    ops::{Add, Mul, Sub},
-};
+use std::{cmp::Ordering, hint};
+use thiserror::Error;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
 pub enum Constant {
     Uint8(u8),
     Bool(bool),
+    /// A 32-bit float held as its bit pattern, see [`Constant::f32()`]
+    F32(u32),
+    /// A 64-bit float held as its bit pattern, see [`Constant::f64()`]
+    F64(u64),
     Array(Vec<Constant>),
 }
 
 impl Constant {
+    pub fn f32(value: f32) -> Self {
+        Self::F32(value.to_bits())
+    }
+
+    pub fn f64(value: f64) -> Self {
+        Self::F64(value.to_bits())
+    }
+
+    /// Returns `true` if the constant is [`F32`] or [`F64`]
+    pub const fn is_float(&self) -> bool {
+        matches!(self, Self::F32(..) | Self::F64(..))
+    }
+
     pub const fn as_bool(&self) -> Option<bool> {
         if let Self::Bool(b) = *self {
             Some(b)
@@ -74,71 +92,205 @@ impl NodeExt for Constant {
     }
 }
 
-impl Mul<Constant> for Constant {
-    // TODO: Should be result
-    type Output = Constant;
-
-    fn mul(self, rhs: Constant) -> Self::Output {
-        &self * &rhs
+impl Constant {
+    /// Multiplies two constants, wrapping on overflow
+    pub fn checked_mul(&self, rhs: &Constant) -> Result<Constant, EvaluationError> {
+        match (self, rhs) {
+            (&Constant::Uint8(left), &Constant::Uint8(right)) => {
+                Ok(Constant::Uint8(left.wrapping_mul(right)))
+            }
+            _ => self.float_op(
+                "mul",
+                rhs,
+                |left, right| left * right,
+                |left, right| left * right,
+            ),
+        }
     }
-}
 
-impl Mul<&Constant> for &Constant {
-    // TODO: Should be result
-    type Output = Constant;
+    /// Adds two constants, wrapping on overflow
+    pub fn checked_add(&self, rhs: &Constant) -> Result<Constant, EvaluationError> {
+        match (self, rhs) {
+            (&Constant::Uint8(left), &Constant::Uint8(right)) => {
+                Ok(Constant::Uint8(left.wrapping_add(right)))
+            }
+            _ => self.float_op(
+                "add",
+                rhs,
+                |left, right| left + right,
+                |left, right| left + right,
+            ),
+        }
+    }
 
-    fn mul(self, rhs: &Constant) -> Self::Output {
+    /// Subtracts two constants, wrapping on overflow
+    pub fn checked_sub(&self, rhs: &Constant) -> Result<Constant, EvaluationError> {
         match (self, rhs) {
             (&Constant::Uint8(left), &Constant::Uint8(right)) => {
-                Constant::Uint8(left.wrapping_mul(right))
+                Ok(Constant::Uint8(left.wrapping_sub(right)))
             }
-            _ => panic!(),
+            _ => self.float_op(
+                "sub",
+                rhs,
+                |left, right| left - right,
+                |left, right| left - right,
+            ),
         }
     }
-}
 
-impl Add<Constant> for Constant {
-    // TODO: Should be result
-    type Output = Constant;
+    /// Divides two constants, dividing an integer by zero traps so it's never
+    /// evaluated while dividing a float by zero produces an infinity
+    pub fn checked_div(&self, rhs: &Constant) -> Result<Constant, EvaluationError> {
+        match (self, rhs) {
+            (&Constant::Uint8(_), &Constant::Uint8(0)) => {
+                Err(EvaluationError::DivisionByZero { lhs: self.clone() })
+            }
+            (&Constant::Uint8(left), &Constant::Uint8(right)) => Ok(Constant::Uint8(left / right)),
+            _ => self.float_op(
+                "div",
+                rhs,
+                |left, right| left / right,
+                |left, right| left / right,
+            ),
+        }
+    }
+
+    /// Applies an operation to two floats of the same type, results that are NaN
+    /// aren't evaluated since their sign and payload are target specific
+    fn float_op(
+        &self,
+        operation: &'static str,
+        rhs: &Constant,
+        op32: fn(f32, f32) -> f32,
+        op64: fn(f64, f64) -> f64,
+    ) -> Result<Constant, EvaluationError> {
+        let result = match (self, rhs) {
+            (&Constant::F32(left), &Constant::F32(right)) => {
+                let result = op32(f32::from_bits(left), f32::from_bits(right));
+                if result.is_nan() {
+                    return Err(EvaluationError::NanResult { operation });
+                }
+
+                Constant::f32(result)
+            }
+            (&Constant::F64(left), &Constant::F64(right)) => {
+                let result = op64(f64::from_bits(left), f64::from_bits(right));
+                if result.is_nan() {
+                    return Err(EvaluationError::NanResult { operation });
+                }
 
-    fn add(self, rhs: Constant) -> Self::Output {
-        &self + &rhs
+                Constant::f64(result)
+            }
+            _ => return Err(EvaluationError::incompatible(operation, self, rhs)),
+        };
+
+        Ok(result)
     }
-}
 
-impl Add<&Constant> for &Constant {
-    // TODO: Should be result
-    type Output = Constant;
+    /// The bitwise and of two integers or the logical and of two booleans
+    pub fn checked_and(&self, rhs: &Constant) -> Result<Constant, EvaluationError> {
+        match (self, rhs) {
+            (&Constant::Uint8(left), &Constant::Uint8(right)) => Ok(Constant::Uint8(left & right)),
+            (&Constant::Bool(left), &Constant::Bool(right)) => Ok(Constant::Bool(left && right)),
+            _ => Err(EvaluationError::incompatible("and", self, rhs)),
+        }
+    }
 
-    fn add(self, rhs: &Constant) -> Self::Output {
+    /// The bitwise or of two integers or the logical or of two booleans
+    pub fn checked_or(&self, rhs: &Constant) -> Result<Constant, EvaluationError> {
         match (self, rhs) {
-            (&Constant::Uint8(left), &Constant::Uint8(right)) => {
-                Constant::Uint8(left.wrapping_add(right))
+            (&Constant::Uint8(left), &Constant::Uint8(right)) => Ok(Constant::Uint8(left | right)),
+            (&Constant::Bool(left), &Constant::Bool(right)) => Ok(Constant::Bool(left || right)),
+            _ => Err(EvaluationError::incompatible("or", self, rhs)),
+        }
+    }
+
+    /// The bitwise xor of two integers or the logical xor of two booleans
+    pub fn checked_xor(&self, rhs: &Constant) -> Result<Constant, EvaluationError> {
+        match (self, rhs) {
+            (&Constant::Uint8(left), &Constant::Uint8(right)) => Ok(Constant::Uint8(left ^ right)),
+            (&Constant::Bool(left), &Constant::Bool(right)) => Ok(Constant::Bool(left ^ right)),
+            _ => Err(EvaluationError::incompatible("xor", self, rhs)),
+        }
+    }
+
+    /// The bitwise not of an integer or the logical not of a boolean
+    pub fn checked_not(&self) -> Result<Constant, EvaluationError> {
+        match *self {
+            Constant::Uint8(int) => Ok(Constant::Uint8(!int)),
+            Constant::Bool(b) => Ok(Constant::Bool(!b)),
+            Constant::F32(_) | Constant::F64(_) | Constant::Array(_) => {
+                Err(EvaluationError::IncompatibleOperand {
+                    operation: "not",
+                    operand: self.clone(),
+                })
             }
-            _ => panic!(),
         }
     }
-}
 
-impl Sub<Constant> for Constant {
-    // TODO: Should be result
-    type Output = Constant;
+    /// Compares two constants of the same type, arrays can only be compared for
+    /// (in)equality and floats are unordered if either of them is NaN, which makes
+    /// every comparison but inequality false
+    pub fn checked_cmp(&self, kind: CmpKind, rhs: &Constant) -> Result<Constant, EvaluationError> {
+        let ordering = match (self, rhs) {
+            (Constant::Uint8(left), Constant::Uint8(right)) => Some(left.cmp(right)),
+            (Constant::Bool(left), Constant::Bool(right)) => Some(left.cmp(right)),
+            (&Constant::F32(left), &Constant::F32(right)) => {
+                f32::from_bits(left).partial_cmp(&f32::from_bits(right))
+            }
+            (&Constant::F64(left), &Constant::F64(right)) => {
+                f64::from_bits(left).partial_cmp(&f64::from_bits(right))
+            }
+            (Constant::Array(left), Constant::Array(right))
+                if matches!(kind, CmpKind::Eq | CmpKind::NotEq) =>
+            {
+                return Ok(Constant::Bool((left == right) == (kind == CmpKind::Eq)));
+            }
+            _ => return Err(EvaluationError::incompatible("compare", self, rhs)),
+        };
 
-    fn sub(self, rhs: Constant) -> Self::Output {
-        &self - &rhs
+        Ok(Constant::Bool(match kind {
+            CmpKind::Eq => ordering == Some(Ordering::Equal),
+            CmpKind::NotEq => ordering != Some(Ordering::Equal),
+            CmpKind::Less => ordering == Some(Ordering::Less),
+            CmpKind::Greater => ordering == Some(Ordering::Greater),
+            CmpKind::LessEq => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+            CmpKind::GreaterEq => {
+                matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal))
+            }
+        }))
     }
 }
 
-impl Sub<&Constant> for &Constant {
-    // TODO: Should be result
-    type Output = Constant;
+/// An error that occurred while evaluating constant nodes
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Error)]
+pub enum EvaluationError {
+    #[error("cannot {operation} {lhs:?} and {rhs:?}")]
+    IncompatibleOperands {
+        operation: &'static str,
+        lhs: Constant,
+        rhs: Constant,
+    },
 
-    fn sub(self, rhs: &Constant) -> Self::Output {
-        match (self, rhs) {
-            (&Constant::Uint8(left), &Constant::Uint8(right)) => {
-                Constant::Uint8(left.wrapping_sub(right))
-            }
-            _ => panic!(),
+    #[error("cannot {operation} {operand:?}")]
+    IncompatibleOperand {
+        operation: &'static str,
+        operand: Constant,
+    },
+
+    #[error("attempted to divide {lhs:?} by zero")]
+    DivisionByZero { lhs: Constant },
+
+    #[error("the result of {operation} is a NaN, whose bits are target specific")]
+    NanResult { operation: &'static str },
+}
+
+impl EvaluationError {
+    fn incompatible(operation: &'static str, lhs: &Constant, rhs: &Constant) -> Self {
+        Self::IncompatibleOperands {
+            operation,
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
         }
     }
 }
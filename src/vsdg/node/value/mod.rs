@@ -1,7 +1,7 @@
 mod constant;
 mod parameter;
 
-pub use constant::Constant;
+pub use constant::{Constant, EvaluationError};
 pub use parameter::Parameter;
 
 use super::{
@@ -30,6 +30,8 @@ pub enum Value {
 pub enum Type {
     Uint8,
     Bool,
+    F32,
+    F64,
 }
 
 impl Display for Type {
@@ -37,6 +39,8 @@ impl Display for Type {
         match self {
             Self::Uint8 => f.write_str("u8"),
             Self::Bool => f.write_str("bool"),
+            Self::F32 => f.write_str("f32"),
+            Self::F64 => f.write_str("f64"),
         }
     }
 }
@@ -0,0 +1,541 @@
+//! Peephole rewrites of a [`ProgramGraph`] expressed as subgraph patterns
+//!
+//! A [`Pattern`] is a small graph of node predicates connected by typed edges. It's
+//! compiled into a chain of joins over the graph's node and edge collections that
+//! binds one more pattern node with each join, and every [`Match`] it finds is handed
+//! to the rewrite function of a [`Rule`] which describes the changes it wants to make
+//! as a [`Replacement`]. The replacements of a rule are all applied at once, so the
+//! matches of a single rule shouldn't change the same nodes or edges
+
+use crate::{
+    dataflow::{operators::DiscriminatedIdents, Difference},
+    vsdg::{
+        node::{Node, NodeId},
+        Edge, ProgramGraph,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    difference::Abelian,
+    lattice::Lattice,
+    operators::{Join, Threshold},
+    Collection,
+};
+use std::{
+    fmt::{self, Debug},
+    sync::atomic::{AtomicU8, Ordering},
+};
+use timely::dataflow::Scope;
+
+/// The kinds of edges that connect the nodes of a [`ProgramGraph`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum EdgeKind {
+    Value,
+    Effect,
+    Control,
+}
+
+impl EdgeKind {
+    fn edges<S, R>(self, graph: &ProgramGraph<S, R>) -> &Collection<S, Edge, R>
+    where
+        S: Scope,
+        R: Abelian,
+    {
+        match self {
+            Self::Value => &graph.value_edges,
+            Self::Effect => &graph.effect_edges,
+            Self::Control => &graph.control_edges,
+        }
+    }
+}
+
+/// A node within a [`Pattern`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PatternNode(usize);
+
+impl PatternNode {
+    /// The node every pattern is rooted at
+    pub const ROOT: Self = Self(0);
+}
+
+/// A subgraph to search a [`ProgramGraph`] for
+///
+/// ```rust,ignore
+/// // Matches every `Add` along with each of its operands
+/// let mut pattern = Pattern::new(|node| node.is::<Add>());
+/// let operand = pattern.any();
+/// pattern.edge(PatternNode::ROOT, operand, EdgeKind::Value);
+/// ```
+#[derive(Clone)]
+pub struct Pattern {
+    nodes: Vec<fn(&Node) -> bool>,
+    edges: Vec<(PatternNode, PatternNode, EdgeKind)>,
+}
+
+impl Pattern {
+    /// Creates a pattern rooted at nodes matching `root`
+    pub fn new(root: fn(&Node) -> bool) -> Self {
+        Self {
+            nodes: vec![root],
+            edges: Vec::new(),
+        }
+    }
+
+    /// Adds a node matching `predicate` to the pattern
+    pub fn node(&mut self, predicate: fn(&Node) -> bool) -> PatternNode {
+        self.nodes.push(predicate);
+        PatternNode(self.nodes.len() - 1)
+    }
+
+    /// Adds a node matching any node to the pattern
+    pub fn any(&mut self) -> PatternNode {
+        self.node(|_| true)
+    }
+
+    /// Requires an edge of the given kind between two nodes of the pattern, edges
+    /// point from a node to the nodes it depends on
+    pub fn edge(&mut self, src: PatternNode, dest: PatternNode, kind: EdgeKind) -> &mut Self {
+        self.edges.push((src, dest, kind));
+        self
+    }
+
+    /// Finds every distinct match of the pattern within the graph
+    ///
+    /// # Panics
+    ///
+    /// Panics if any node of the pattern isn't connected to its root
+    pub fn matches<S, R>(&self, graph: &ProgramGraph<S, R>) -> Collection<S, Match, R>
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+        R: Difference,
+    {
+        let (len, root) = (self.nodes.len(), self.nodes[0]);
+        let mut bound = vec![false; len];
+        bound[0] = true;
+
+        let mut bindings = graph.nodes.flat_map(move |(id, node)| {
+            root(&node).then(|| {
+                let mut bindings = vec![None; len];
+                bindings[0] = Some((id, node));
+                bindings
+            })
+        });
+
+        for (src, dest, kind) in self.plan() {
+            let edges = kind.edges(graph);
+
+            bindings = match (bound[src.0], bound[dest.0]) {
+                (true, true) => bindings
+                    .map(move |bindings| {
+                        (
+                            (bound_id(&bindings, src), bound_id(&bindings, dest)),
+                            bindings,
+                        )
+                    })
+                    .semijoin(edges)
+                    .map(|(_, bindings)| bindings),
+
+                (true, false) => bind(
+                    &bindings.map(move |bindings| (bound_id(&bindings, src), bindings)),
+                    edges,
+                    &graph.nodes,
+                    dest,
+                    self.nodes[dest.0],
+                ),
+
+                (false, true) => bind(
+                    &bindings.map(move |bindings| (bound_id(&bindings, dest), bindings)),
+                    &edges.map(|(src, dest)| (dest, src)),
+                    &graph.nodes,
+                    src,
+                    self.nodes[src.0],
+                ),
+
+                (false, false) => unreachable!("planned an edge between two unbound nodes"),
+            };
+
+            bound[src.0] = true;
+            bound[dest.0] = true;
+        }
+
+        bindings
+            .map(|bindings| Match {
+                nodes: bindings
+                    .into_iter()
+                    .map(|binding| binding.expect("a pattern node was never bound"))
+                    .collect(),
+            })
+            .distinct_core()
+    }
+
+    /// Orders the edges of the pattern so that every edge touches the root or a
+    /// node bound by an earlier edge
+    fn plan(&self) -> Vec<(PatternNode, PatternNode, EdgeKind)> {
+        let mut bound = vec![false; self.nodes.len()];
+        bound[0] = true;
+
+        let (mut remaining, mut plan) = (self.edges.clone(), Vec::with_capacity(self.edges.len()));
+        while !remaining.is_empty() {
+            let next = remaining
+                .iter()
+                .position(|&(src, dest, _)| bound[src.0] || bound[dest.0])
+                .expect("every edge of a pattern must be connected to its root");
+
+            let (src, dest, kind) = remaining.remove(next);
+            bound[src.0] = true;
+            bound[dest.0] = true;
+            plan.push((src, dest, kind));
+        }
+
+        assert!(
+            bound.iter().all(|&bound| bound),
+            "every node of a pattern must be connected to its root",
+        );
+
+        plan
+    }
+}
+
+impl Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pattern")
+            .field("nodes", &self.nodes.len())
+            .field("edges", &self.edges)
+            .finish()
+    }
+}
+
+type Bindings = Vec<Option<(NodeId, Node)>>;
+
+fn bound_id(bindings: &[Option<(NodeId, Node)>], node: PatternNode) -> NodeId {
+    bindings[node.0]
+        .as_ref()
+        .expect("planned an edge from an unbound node")
+        .0
+}
+
+/// Binds `node` to every neighbor along `edges` that matches `predicate`
+fn bind<S, R>(
+    bindings: &Collection<S, (NodeId, Bindings), R>,
+    edges: &Collection<S, Edge, R>,
+    nodes: &Collection<S, (NodeId, Node), R>,
+    node: PatternNode,
+    predicate: fn(&Node) -> bool,
+) -> Collection<S, Bindings, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    bindings
+        .join_map(edges, |_bound, bindings, &neighbor| {
+            (neighbor, bindings.clone())
+        })
+        .join_map(nodes, move |&neighbor, bindings, contents| {
+            predicate(contents).then(|| {
+                let mut bindings = bindings.clone();
+                bindings[node.0] = Some((neighbor, contents.clone()));
+                bindings
+            })
+        })
+        .flat_map(|bindings| bindings)
+}
+
+/// The nodes a [`Pattern`] matched, in the order they were added to it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct Match {
+    nodes: Vec<(NodeId, Node)>,
+}
+
+impl Match {
+    pub fn id(&self, node: PatternNode) -> NodeId {
+        self.nodes[node.0].0
+    }
+
+    pub fn node(&self, node: PatternNode) -> &Node {
+        &self.nodes[node.0].1
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.id(PatternNode::ROOT)
+    }
+
+    pub fn root_node(&self) -> &Node {
+        self.node(PatternNode::ROOT)
+    }
+
+    /// Every matched node, in the order they were added to the pattern
+    pub fn nodes(&self) -> &[(NodeId, Node)] {
+        &self.nodes
+    }
+}
+
+/// A node referenced by a [`Replacement`], either one that's already within the
+/// graph or one the replacement [minted](Replacement::mint)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum NodeRef {
+    Existing(NodeId),
+    Minted(usize),
+}
+
+impl From<NodeId> for NodeRef {
+    fn from(id: NodeId) -> Self {
+        Self::Existing(id)
+    }
+}
+
+/// The changes a [`Rule`] makes to the graph for a single match
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation, Default)]
+pub struct Replacement {
+    minted: Vec<Node>,
+    replaced: Vec<(NodeId, Node, Node)>,
+    removed_nodes: Vec<(NodeId, Node)>,
+    added_edges: Vec<(EdgeKind, NodeRef, NodeRef)>,
+    removed_edges: Vec<(EdgeKind, Edge)>,
+}
+
+impl Replacement {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a new node to the function of the match's root
+    pub fn mint<N>(&mut self, node: N) -> NodeRef
+    where
+        N: Into<Node>,
+    {
+        self.minted.push(node.into());
+        NodeRef::Minted(self.minted.len() - 1)
+    }
+
+    /// Swaps the contents of a node, keeping every edge to and from it
+    pub fn replace<N>(&mut self, id: NodeId, old: Node, new: N) -> &mut Self
+    where
+        N: Into<Node>,
+    {
+        self.replaced.push((id, old, new.into()));
+        self
+    }
+
+    /// Removes a node from the graph, its edges have to be removed separately
+    pub fn remove_node(&mut self, id: NodeId, node: Node) -> &mut Self {
+        self.removed_nodes.push((id, node));
+        self
+    }
+
+    pub fn add_edge<A, B>(&mut self, kind: EdgeKind, src: A, dest: B) -> &mut Self
+    where
+        A: Into<NodeRef>,
+        B: Into<NodeRef>,
+    {
+        self.added_edges.push((kind, src.into(), dest.into()));
+        self
+    }
+
+    /// Removes a single copy of an edge
+    pub fn remove_edge(&mut self, kind: EdgeKind, src: NodeId, dest: NodeId) -> &mut Self {
+        self.removed_edges.push((kind, (src, dest)));
+        self
+    }
+}
+
+/// A named rewrite of every match of a [`Pattern`]
+#[derive(Clone)]
+pub struct Rule {
+    name: &'static str,
+    pattern: Pattern,
+    /// Describes the changes made for a match, returning `None` leaves it untouched
+    rewrite: fn(&Match) -> Option<Replacement>,
+}
+
+impl Rule {
+    pub const fn new(
+        name: &'static str,
+        pattern: Pattern,
+        rewrite: fn(&Match) -> Option<Replacement>,
+    ) -> Self {
+        Self {
+            name,
+            pattern,
+            rewrite,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub const fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    /// Rewrites every match of the rule's pattern within the graph
+    ///
+    /// Minted nodes get their ids from `ident_discriminant`, which is advanced once
+    /// per application
+    pub fn apply<S, R>(
+        &self,
+        scope: &mut S,
+        graph: &ProgramGraph<S, R>,
+        ident_discriminant: &AtomicU8,
+    ) -> ProgramGraph<S, R>
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+        R: Difference,
+    {
+        scope.region_named(self.name, |region| {
+            let graph = graph.enter_region(region);
+
+            let rewrite = self.rewrite;
+            let replacements = self.pattern.matches(&graph).flat_map(move |matched| {
+                rewrite(&matched).map(|replacement| (matched, replacement))
+            });
+
+            // Minted nodes are identified by the match that minted them and their
+            // index within its replacement
+            let discriminant = ident_discriminant.fetch_add(1, Ordering::Relaxed);
+            let minted = replacements
+                .flat_map(|(matched, replacement)| {
+                    let root = matched.root();
+                    replacement
+                        .minted
+                        .into_iter()
+                        .enumerate()
+                        .map(move |(idx, node)| ((matched.clone(), idx), (root, node)))
+                })
+                .discriminated_idents(discriminant)
+                .map(|((key, (root, node)), id)| (key, (NodeId::new(id), root, node)));
+
+            let minted_ids = minted.map(|(key, (id, _, _))| (key, id));
+            let minted_functions = minted
+                .map(|(_, (id, root, _))| (root, id))
+                .join_map(&graph.function_nodes, |_root, &id, &func| (id, func));
+
+            let removed_nodes = replacements.flat_map(|(_, replacement)| replacement.removed_nodes);
+            let removed_functions = graph
+                .function_nodes
+                .semijoin(&removed_nodes.map(|(id, _)| id));
+
+            let added_nodes =
+                minted
+                    .map(|(_, (id, _, node))| (id, node))
+                    .concat(&replacements.flat_map(|(_, replacement)| {
+                        replacement
+                            .replaced
+                            .into_iter()
+                            .map(|(id, _, new)| (id, new))
+                    }));
+            let discarded_nodes =
+                removed_nodes.concat(&replacements.flat_map(|(_, replacement)| {
+                    replacement
+                        .replaced
+                        .into_iter()
+                        .map(|(id, old, _)| (id, old))
+                }));
+
+            let edge_changes = resolve_edges(
+                &replacements.flat_map(|(matched, replacement)| {
+                    replacement
+                        .added_edges
+                        .into_iter()
+                        .map(move |edge| (matched.clone(), edge))
+                }),
+                &minted_ids,
+            )
+            .concat(
+                &replacements
+                    .flat_map(|(_, replacement)| replacement.removed_edges)
+                    .negate(),
+            );
+            let edges = |kind: EdgeKind| {
+                kind.edges(&graph).concat(
+                    &edge_changes
+                        .filter(move |&(edge_kind, _)| edge_kind == kind)
+                        .map(|(_, edge)| edge),
+                )
+            };
+
+            ProgramGraph {
+                value_edges: edges(EdgeKind::Value),
+                effect_edges: edges(EdgeKind::Effect),
+                control_edges: edges(EdgeKind::Control),
+                nodes: graph
+                    .nodes
+                    .concat(&added_nodes)
+                    .concat(&discarded_nodes.negate()),
+                function_nodes: graph
+                    .function_nodes
+                    .concat(&minted_functions)
+                    .concat(&removed_functions.negate()),
+                functions: graph.functions.clone(),
+            }
+            .leave_region()
+        })
+    }
+}
+
+impl Debug for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rule")
+            .field("name", &self.name)
+            .field("pattern", &self.pattern)
+            .finish()
+    }
+}
+
+/// Applies each rule to the graph in order
+pub fn apply_rules<S, R>(
+    scope: &mut S,
+    graph: &ProgramGraph<S, R>,
+    rules: &[Rule],
+    ident_discriminant: &AtomicU8,
+) -> ProgramGraph<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    rules.iter().fold(graph.clone(), |graph, rule| {
+        rule.apply(scope, &graph, ident_discriminant)
+    })
+}
+
+/// Replaces the minted nodes referenced by added edges with their ids
+fn resolve_edges<S, R>(
+    edges: &Collection<S, (Match, (EdgeKind, NodeRef, NodeRef)), R>,
+    minted_ids: &Collection<S, ((Match, usize), NodeId), R>,
+) -> Collection<S, (EdgeKind, Edge), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let existing_src = edges.flat_map(|(matched, (kind, src, dest))| match src {
+        NodeRef::Existing(src) => Some((matched, (kind, src, dest))),
+        NodeRef::Minted(_) => None,
+    });
+    let minted_src = edges
+        .flat_map(|(matched, (kind, src, dest))| match src {
+            NodeRef::Minted(idx) => Some(((matched.clone(), idx), (matched, kind, dest))),
+            NodeRef::Existing(_) => None,
+        })
+        .join_map(minted_ids, |_key, (matched, kind, dest), &src| {
+            (matched.clone(), (*kind, src, *dest))
+        });
+    let with_src = existing_src.concat(&minted_src);
+
+    let existing_dest = with_src.flat_map(|(_, (kind, src, dest))| match dest {
+        NodeRef::Existing(dest) => Some((kind, (src, dest))),
+        NodeRef::Minted(_) => None,
+    });
+    let minted_dest = with_src
+        .flat_map(|(matched, (kind, src, dest))| match dest {
+            NodeRef::Minted(idx) => Some(((matched, idx), (kind, src))),
+            NodeRef::Existing(_) => None,
+        })
+        .join_map(minted_ids, |_key, &(kind, src), &dest| (kind, (src, dest)));
+
+    existing_dest.concat(&minted_dest)
+}
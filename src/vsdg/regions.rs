@@ -0,0 +1,326 @@
+//! Single-entry single-exit regions within the control flow of each function
+//!
+//! Control edges point from a node to its control predecessor, so control flows
+//! from a function's [`Start`] node to its [`End`](crate::vsdg::node::End) node
+//! against the direction of the edges. A region is a pair of an entry and an exit
+//! node where the entry dominates the exit, the exit post-dominates the entry and
+//! every other node within the region is only entered from and only left towards
+//! nodes within the region. Each node starts at most one region, the smallest one
+//! it can, and every function gets a root region spanning all of it
+//!
+//! Regions are either nested or only share their boundaries, which makes them a
+//! tree that the scheduler, structured control lowerings and loop transforms can
+//! all walk instead of rediscovering the same structure. Each function's regions
+//! are computed in a single reduction over its control edges, much like
+//! [block dominators](crate::dataflow::analysis::dominance)
+
+use crate::{
+    dataflow::{operators::FilterMap, Difference},
+    vsdg::{
+        node::{NodeId, Start},
+        Edge, ProgramGraph,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    difference::Semigroup,
+    lattice::Lattice,
+    operators::{Join, Reduce},
+    Collection,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use timely::dataflow::Scope;
+
+/// A single-entry single-exit region, identified by its entry and exit nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub struct Region {
+    pub entry: NodeId,
+    pub exit: NodeId,
+}
+
+impl Region {
+    pub const fn new(entry: NodeId, exit: NodeId) -> Self {
+        Self { entry, exit }
+    }
+}
+
+/// The region tree of every function within a graph
+#[derive(Clone)]
+pub struct RegionTree<S, R>
+where
+    S: Scope,
+    R: Semigroup,
+{
+    /// Every region, including the root region of each function
+    pub regions: Collection<S, Region, R>,
+    /// Every region paired with the innermost region enclosing it, the root region of
+    /// each function has no parent
+    pub parents: Collection<S, (Region, Region), R>,
+    /// Every node reachable from its function's start paired with the innermost
+    /// region it belongs to
+    ///
+    /// A region's exit belongs to whatever encloses the region, so the exit of one
+    /// region is free to be the entry of the next
+    pub node_regions: Collection<S, (NodeId, Region), R>,
+}
+
+/// Builds the region tree of every function within the graph, see the
+/// [module docs](self)
+pub fn region_tree<S, R>(graph: &ProgramGraph<S, R>) -> RegionTree<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let memberships = graph.node_memberships();
+
+    let starts = graph
+        .nodes
+        .filter_map(|(id, node)| node.cast::<Start>().map(|_| (id, ())))
+        .join_map(&memberships, |&start, &(), &end| {
+            (end, FunctionPart::Start(start))
+        });
+    let edges = graph
+        .control_edges
+        .join_map(&memberships, |&src, &dest, &end| {
+            (end, FunctionPart::Edge((src, dest)))
+        });
+
+    let facts = starts
+        .concat(&edges)
+        .reduce(|&end, parts, output| {
+            let (mut start, mut edges) = (None, Vec::new());
+            for (part, _) in parts {
+                match *part {
+                    FunctionPart::Start(node) => start = Some(node),
+                    FunctionPart::Edge(edge) => edges.push(edge),
+                }
+            }
+
+            if let Some(start) = start {
+                for fact in FunctionRegions::new(start, end, &edges).facts() {
+                    output.push((fact, R::from(1)));
+                }
+            }
+        })
+        .map(|(_end, fact)| fact);
+
+    RegionTree {
+        regions: facts.filter_map(|fact| match fact {
+            RegionFact::Region(region) => Some(region),
+            _ => None,
+        }),
+        parents: facts.filter_map(|fact| match fact {
+            RegionFact::Parent(region, parent) => Some((region, parent)),
+            _ => None,
+        }),
+        node_regions: facts.filter_map(|fact| match fact {
+            RegionFact::Node(node, region) => Some((node, region)),
+            _ => None,
+        }),
+    }
+}
+
+/// The pieces of a function its regions are computed from
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum FunctionPart {
+    Start(NodeId),
+    Edge(Edge),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+enum RegionFact {
+    Region(Region),
+    Parent(Region, Region),
+    Node(NodeId, Region),
+}
+
+/// The control flow of a single function and every region within it
+struct FunctionRegions {
+    root: Region,
+    successors: BTreeMap<NodeId, Vec<NodeId>>,
+    predecessors: BTreeMap<NodeId, Vec<NodeId>>,
+    regions: BTreeMap<Region, BTreeSet<NodeId>>,
+}
+
+impl FunctionRegions {
+    fn new(start: NodeId, end: NodeId, edges: &[Edge]) -> Self {
+        let (mut successors, mut predecessors) =
+            (BTreeMap::<_, Vec<_>>::new(), BTreeMap::<_, Vec<_>>::new());
+        for &(node, pred) in edges {
+            successors.entry(pred).or_default().push(node);
+            predecessors.entry(node).or_default().push(pred);
+        }
+
+        let mut this = Self {
+            root: Region::new(start, end),
+            successors,
+            predecessors,
+            regions: BTreeMap::new(),
+        };
+
+        let root_nodes = this.region_nodes(this.root);
+        this.regions.insert(this.root, root_nodes);
+
+        let (dominators, post_dominators) = (
+            dominators(start, &this.successors, &this.predecessors),
+            dominators(end, &this.predecessors, &this.successors),
+        );
+
+        for (&entry, exits) in &post_dominators {
+            // Post-dominators closer to the entry are post-dominated by the further
+            // ones, so they have more post-dominators of their own
+            let mut candidates: Vec<NodeId> = exits
+                .iter()
+                .copied()
+                .filter(|&exit| {
+                    exit != entry
+                        && dominators
+                            .get(&exit)
+                            .map_or(false, |doms| doms.contains(&entry))
+                })
+                .collect();
+            candidates.sort_by_key(|exit| std::cmp::Reverse(post_dominators[exit].len()));
+
+            for exit in candidates {
+                let region = Region::new(entry, exit);
+                let nodes = this.region_nodes(region);
+
+                if this.is_single_entry_exit(region, &nodes) {
+                    this.regions.entry(region).or_insert(nodes);
+                    break;
+                }
+            }
+        }
+
+        this
+    }
+
+    /// The nodes reachable from a region's entry without passing through its exit
+    fn region_nodes(&self, region: Region) -> BTreeSet<NodeId> {
+        let mut nodes = BTreeSet::new();
+        let mut stack = vec![region.entry];
+        while let Some(node) = stack.pop() {
+            if nodes.insert(node) && node != region.exit {
+                stack.extend(self.successors.get(&node).into_iter().flatten().copied());
+            }
+        }
+
+        nodes
+    }
+
+    fn is_single_entry_exit(&self, region: Region, nodes: &BTreeSet<NodeId>) -> bool {
+        nodes.iter().all(|&node| {
+            let entered_within = node == region.entry
+                || self
+                    .predecessors
+                    .get(&node)
+                    .into_iter()
+                    .flatten()
+                    .all(|pred| nodes.contains(pred));
+            let left_within = node == region.exit
+                || self
+                    .successors
+                    .get(&node)
+                    .into_iter()
+                    .flatten()
+                    .all(|succ| nodes.contains(succ));
+
+            entered_within && left_within
+        })
+    }
+
+    fn facts(&self) -> Vec<RegionFact> {
+        let mut facts = Vec::new();
+
+        for (&region, nodes) in &self.regions {
+            facts.push(RegionFact::Region(region));
+
+            let parent = self
+                .regions
+                .iter()
+                .filter(|&(&other, other_nodes)| {
+                    other != region
+                        && other_nodes.len() > nodes.len()
+                        && nodes.is_subset(other_nodes)
+                })
+                .min_by_key(|&(&other, other_nodes)| (other_nodes.len(), other))
+                .map(|(&other, _)| other);
+
+            if let Some(parent) = parent {
+                facts.push(RegionFact::Parent(region, parent));
+            }
+        }
+
+        for &node in &self.regions[&self.root] {
+            let innermost = self
+                .regions
+                .iter()
+                .filter(|&(&region, nodes)| {
+                    nodes.contains(&node) && (node != region.exit || region == self.root)
+                })
+                .min_by_key(|&(&region, nodes)| (nodes.len(), region))
+                .map(|(&region, _)| region);
+
+            if let Some(region) = innermost {
+                facts.push(RegionFact::Node(node, region));
+            }
+        }
+
+        facts
+    }
+}
+
+/// The dominators of every node reachable from `entry`, including itself
+fn dominators(
+    entry: NodeId,
+    successors: &BTreeMap<NodeId, Vec<NodeId>>,
+    predecessors: &BTreeMap<NodeId, Vec<NodeId>>,
+) -> BTreeMap<NodeId, BTreeSet<NodeId>> {
+    let mut reachable = BTreeSet::new();
+    let mut stack = vec![entry];
+    while let Some(node) = stack.pop() {
+        if reachable.insert(node) {
+            stack.extend(successors.get(&node).into_iter().flatten().copied());
+        }
+    }
+
+    let mut dominators: BTreeMap<_, _> = reachable
+        .iter()
+        .map(|&node| {
+            if node == entry {
+                (node, Some(entry).into_iter().collect())
+            } else {
+                (node, reachable.clone())
+            }
+        })
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for &node in reachable.iter().filter(|&&node| node != entry) {
+            let mut node_dominators = predecessors
+                .get(&node)
+                .into_iter()
+                .flatten()
+                .filter_map(|pred| dominators.get(pred))
+                .fold(None, |acc: Option<BTreeSet<_>>, pred_dominators| {
+                    Some(match acc {
+                        Some(acc) => acc.intersection(pred_dominators).copied().collect(),
+                        None => pred_dominators.clone(),
+                    })
+                })
+                .unwrap_or_default();
+            node_dominators.insert(node);
+
+            if dominators[&node] != node_dominators {
+                dominators.insert(node, node_dominators);
+                changed = true;
+            }
+        }
+    }
+
+    dominators
+}
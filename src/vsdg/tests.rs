@@ -1,13 +1,27 @@
 #![cfg(test)]
 
 use crate::{
+    artifacts::DirectorySink,
     builder::{BuildResult, Builder, Context},
-    dataflow::{Diff, Time},
-    vsdg::{optimization_dataflow, ProgramGraph},
+    dataflow::{operators::Uuid, Diff, Time},
+    vsdg::{
+        dot::{self, GraphNames, GraphNode},
+        export,
+        node::{
+            Add, Branch, Cmp, CmpKind, Constant, Div, End, FuncId, Function, LoopHead, Merge, Node,
+            NodeExt, NodeId, Not, Parameter, Start, Sub, Type, Value,
+        },
+        optimization_dataflow,
+        pattern::{EdgeKind, Pattern, PatternNode, Replacement, Rule},
+        regions::{self, Region},
+        types::{self, GraphTypeError},
+        ProgramGraph,
+    },
 };
+use differential_dataflow::input::Input;
 use std::sync::{
     atomic::{AtomicU8, Ordering},
-    Arc,
+    Arc, Mutex,
 };
 use timely::Config;
 
@@ -25,6 +39,8 @@ where
     let context = Arc::new(Context::new(
         ident_generation.fetch_add(1, Ordering::Relaxed),
     ));
+    let graph_names = Arc::new(Mutex::new(GraphNames::new()));
+    let names = graph_names.clone();
 
     timely::execute(config, move |worker| {
         if let Ok(addr) = std::env::var("DIFFERENTIAL_LOG_ADDR") {
@@ -61,6 +77,7 @@ where
             let mut builder = context.builder();
 
             input.clone()(&mut builder).unwrap();
+            *names.lock().unwrap() = builder.graph_names();
             builder.vsdg_finish(&mut inputs, 0).unwrap();
 
             if let (Some(expected), Some(expected_input)) =
@@ -82,5 +99,448 @@ where
     })
     .unwrap();
 
-    super::dot::render_graphs(receiver);
+    let graph_names = graph_names.lock().unwrap();
+    if let Err(err) =
+        dot::render_graphs_named(receiver, DirectorySink::from_env(), None, &graph_names)
+    {
+        tracing::error!("failed to render graphs: {:?}", err);
+    }
+}
+
+#[test]
+fn dot_tolerates_dangling_edges() {
+    let (func, constant, missing) = (
+        FuncId::new(Uuid::new(0, 1)),
+        NodeId::new(Uuid::new(0, 2)),
+        NodeId::new(Uuid::new(0, 3)),
+    );
+
+    let dot = dot::to_dot(&[
+        GraphNode::Function((func, Function {})),
+        GraphNode::FunctionNode((constant, func)),
+        GraphNode::Node((constant, Node::Value(Value::Constant(Constant::Bool(true))))),
+        GraphNode::ValueEdge((constant, missing)),
+        GraphNode::ValueEdge((missing, constant)),
+    ]);
+
+    assert!(dot.starts_with("digraph {"));
+    assert_eq!(dot.matches("subgraph cluster_").count(), 1);
+    // The missing node gets a single placeholder that both edges point to
+    assert_eq!(dot.matches("missing").count(), 1);
+    assert_eq!(dot.matches("color = red, style = dashed").count(), 2);
+}
+
+#[test]
+fn exported_dot_uses_builder_names() {
+    let root = std::env::temp_dir().join(format!("sruth-graph-names-{}", std::process::id()));
+    let context = Arc::new(Context::new(0));
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    let names = timely::execute_directly(move |worker| {
+        let (mut inputs, probe) = worker.dataflow::<Time, _, _>(|scope| {
+            let (graph, inputs) = ProgramGraph::<_, Diff>::new(scope);
+            let graph = graph.render_graph("named", sender);
+
+            (inputs, graph.probe())
+        });
+
+        let mut builder = context.builder();
+        builder
+            .named_function("main", crate::repr::Type::Uint, |func| {
+                func.basic_block(|block| {
+                    block.ret(crate::repr::Constant::Uint(0))?;
+                    Ok(())
+                })?;
+
+                let param = func.vsdg_param(Type::Uint8);
+                let one = func.vsdg_const(Constant::Uint8(1));
+                let sum = func.vsdg_add(param, one)?;
+                func.vsdg_name(sum, "sum");
+
+                func.vsdg_return(sum)
+            })
+            .unwrap();
+
+        let names = builder.graph_names();
+        builder.vsdg_finish(&mut inputs, 0).unwrap();
+
+        inputs.advance_to(1);
+        inputs.flush();
+        worker.step_while(|| probe.less_than(inputs.time()));
+
+        names
+    });
+
+    assert_eq!(names.functions.values().collect::<Vec<_>>(), vec!["main"]);
+    assert_eq!(names.nodes.values().collect::<Vec<_>>(), vec!["sum"]);
+
+    let sink = DirectorySink::new(&root).with_graphviz(None::<std::path::PathBuf>);
+    let options = export::ExportOptions::new(export::ExportFormat::Dot);
+    export::export_graphs_named(receiver, sink, None, &options, &names).unwrap();
+
+    // Graphs are only captured by debug builds
+    if cfg!(debug_assertions) {
+        let dot = std::fs::read_to_string(root.join("named").join("graphviz.dot")).unwrap();
+        assert!(dot.contains("label = \"main\";"), "{}", dot);
+        assert!(dot.contains("label = \"sum = Add\""), "{}", dot);
+        assert!(dot.contains("label = \"param: u8\""), "{}", dot);
+    }
+
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn export_formats_include_every_node_and_edge() {
+    let (func, constant, missing) = (
+        FuncId::new(Uuid::new(0, 1)),
+        NodeId::new(Uuid::new(0, 2)),
+        NodeId::new(Uuid::new(0, 3)),
+    );
+
+    let graph = [
+        GraphNode::Function((func, Function {})),
+        GraphNode::FunctionNode((constant, func)),
+        GraphNode::Node((constant, Node::Value(Value::Constant(Constant::Bool(true))))),
+        GraphNode::ValueEdge((constant, missing)),
+    ];
+
+    let graphml = export::to_graphml(&graph, None);
+    assert_eq!(graphml.matches("<node id=").count(), 2);
+    assert_eq!(graphml.matches("<edge id=").count(), 1);
+    assert!(graphml.contains(r#"<data key="label">true: bool</data>"#));
+    assert!(graphml.contains(r#"<data key="edge_kind">error</data>"#));
+
+    let json = export::to_json(&graph, None);
+    assert!(json.contains(r#"{ "id": "n0", "kind": "value", "label": "true: bool""#));
+    assert!(json.contains(r#"{ "id": "n1", "kind": "missing""#));
+    assert!(json.contains(r#"{ "source": "n0", "target": "n1", "kind": "error" }"#));
+}
+
+#[test]
+fn patterns_rewrite_matched_subgraphs() {
+    let node = |hash| NodeId::new(Uuid::new(0, hash));
+    let (func, x, y, same, different) = (
+        FuncId::new(Uuid::new(0, 1)),
+        node(2),
+        node(3),
+        node(4),
+        node(5),
+    );
+
+    let mut pattern = Pattern::new(|node| node.is::<Sub>());
+    let operand = pattern.any();
+    pattern.edge(PatternNode::ROOT, operand, EdgeKind::Value);
+
+    let rule = Rule::new("Sub(x, x) => 0", pattern, |matched| {
+        let sub = matched.root_node().cast::<Sub>()?;
+        if sub.lhs != sub.rhs {
+            return None;
+        }
+
+        let mut replacement = Replacement::new();
+        replacement
+            .replace(
+                matched.root(),
+                matched.root_node().clone(),
+                Constant::Uint8(0),
+            )
+            .remove_edge(EdgeKind::Value, matched.root(), sub.lhs)
+            .remove_edge(EdgeKind::Value, matched.root(), sub.rhs);
+
+        Some(replacement)
+    });
+
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<Time, _, _>(|scope| {
+            let param = Node::from(Parameter { ty: Type::Uint8 });
+            let (_nodes, nodes) = scope.new_collection_from(vec![
+                (x, param.clone()),
+                (y, param.clone()),
+                (same, Node::from(Sub { lhs: x, rhs: x })),
+                (different, Node::from(Sub { lhs: x, rhs: y })),
+            ]);
+            let (_function_nodes, function_nodes) = scope.new_collection_from(vec![
+                (x, func),
+                (y, func),
+                (same, func),
+                (different, func),
+            ]);
+            let (_value_edges, value_edges) = scope.new_collection_from(vec![
+                (same, x),
+                (same, x),
+                (different, x),
+                (different, y),
+            ]);
+            let (_effect_edges, effect_edges) = scope.new_collection::<_, Diff>();
+            let (_control_edges, control_edges) = scope.new_collection::<_, Diff>();
+            let (_functions, functions) = scope.new_collection_from(vec![(func, Function {})]);
+
+            let graph = ProgramGraph {
+                value_edges,
+                effect_edges,
+                control_edges,
+                nodes,
+                function_nodes,
+                functions,
+            };
+            let rewritten = rule.apply(scope, &graph, &AtomicU8::new(0));
+
+            // Only the subtraction of identical operands is rewritten
+            let (_expected_nodes, expected_nodes) = scope.new_collection_from(vec![
+                (x, param.clone()),
+                (y, param),
+                (same, Node::from(Constant::Uint8(0))),
+                (different, Node::from(Sub { lhs: x, rhs: y })),
+            ]);
+            rewritten.nodes.assert_eq(&expected_nodes);
+
+            let (_expected_edges, expected_edges) =
+                scope.new_collection_from(vec![(different, x), (different, y)]);
+            rewritten.value_edges.assert_eq(&expected_edges);
+        });
+    });
+}
+
+#[test]
+fn operations_evaluate_constant_operands() {
+    let (lhs, rhs) = (NodeId::new(Uuid::new(0, 1)), NodeId::new(Uuid::new(0, 2)));
+
+    // Operands are matched up by id, not by the order they're given in
+    let (node, consumed) = Sub { lhs, rhs }
+        .evaluate_with_constants(&[(rhs, Constant::Uint8(5)), (lhs, Constant::Uint8(2))]);
+    assert_eq!(node, Node::from(Constant::Uint8(253)));
+    assert_eq!(consumed, vec![lhs, rhs]);
+
+    // Dividing by zero traps at runtime, so it's left alone
+    let div = Div { lhs, rhs };
+    let (node, consumed) = div
+        .clone()
+        .evaluate_with_constants(&[(lhs, Constant::Uint8(1)), (rhs, Constant::Uint8(0))]);
+    assert_eq!(node, Node::from(div));
+    assert!(consumed.is_empty());
+
+    // As are operands of mismatched types
+    let cmp = Cmp {
+        lhs,
+        rhs,
+        kind: CmpKind::Less,
+    };
+    let (node, consumed) = cmp
+        .clone()
+        .evaluate_with_constants(&[(lhs, Constant::Uint8(1)), (rhs, Constant::Bool(true))]);
+    assert_eq!(node, Node::from(cmp));
+    assert!(consumed.is_empty());
+
+    let (node, consumed) =
+        Not { value: lhs }.evaluate_with_constants(&[(lhs, Constant::Bool(false))]);
+    assert_eq!(node, Node::from(Constant::Bool(true)));
+    assert_eq!(consumed, vec![lhs]);
+}
+
+#[test]
+fn float_operations_respect_nans() {
+    let (lhs, rhs) = (NodeId::new(Uuid::new(0, 1)), NodeId::new(Uuid::new(0, 2)));
+
+    // Dividing a float by zero is well defined
+    let (node, _) = Div { lhs, rhs }
+        .evaluate_with_constants(&[(lhs, Constant::f64(1.0)), (rhs, Constant::f64(0.0))]);
+    assert_eq!(node, Node::from(Constant::f64(f64::INFINITY)));
+
+    // But the bits of a NaN depend on the target
+    let div = Div { lhs, rhs };
+    let (node, consumed) = div
+        .clone()
+        .evaluate_with_constants(&[(lhs, Constant::f32(0.0)), (rhs, Constant::f32(0.0))]);
+    assert_eq!(node, Node::from(div));
+    assert!(consumed.is_empty());
+
+    // NaNs are unordered, so every comparison but inequality is false
+    for &(kind, expected) in &[
+        (CmpKind::Eq, false),
+        (CmpKind::NotEq, true),
+        (CmpKind::Less, false),
+        (CmpKind::GreaterEq, false),
+    ] {
+        let (node, _) = Cmp { lhs, rhs, kind }.evaluate_with_constants(&[
+            (lhs, Constant::f32(f32::NAN)),
+            (rhs, Constant::f32(f32::NAN)),
+        ]);
+        assert_eq!(node, Node::from(Constant::Bool(expected)), "{:?}", kind);
+    }
+}
+
+#[test]
+fn rewrites_skip_ill_typed_functions() {
+    let node = |hash| NodeId::new(Uuid::new(0, hash));
+    let (func, ill_end, well_end, x, flag, ill, y, well) = (
+        FuncId::new(Uuid::new(0, 1)),
+        node(2),
+        node(3),
+        node(4),
+        node(5),
+        node(6),
+        node(7),
+        node(8),
+    );
+
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<Time, _, _>(|scope| {
+            let param = Node::from(Parameter { ty: Type::Uint8 });
+            let (_nodes, nodes) = scope.new_collection_from(vec![
+                (ill_end, Node::from(End)),
+                (well_end, Node::from(End)),
+                (x, param.clone()),
+                (flag, Node::from(Constant::Bool(true))),
+                (ill, Node::from(Add { lhs: x, rhs: flag })),
+                (y, param),
+                (well, Node::from(Add { lhs: y, rhs: y })),
+            ]);
+            let (_function_nodes, function_nodes) = scope.new_collection_from(vec![
+                (ill_end, func),
+                (well_end, func),
+                (x, func),
+                (flag, func),
+                (ill, func),
+                (y, func),
+                (well, func),
+            ]);
+            let (_value_edges, value_edges) =
+                scope.new_collection_from(vec![(ill, x), (ill, flag), (well, y), (well, y)]);
+            let (_control_edges, control_edges) =
+                scope.new_collection_from(vec![(ill_end, ill), (well_end, well)]);
+            let (_effect_edges, effect_edges) = scope.new_collection::<_, Diff>();
+            let (_functions, functions) = scope.new_collection_from(vec![(func, Function {})]);
+
+            let graph = ProgramGraph {
+                value_edges,
+                effect_edges,
+                control_edges,
+                nodes,
+                function_nodes,
+                functions,
+            };
+
+            // Operations take the type of their operands
+            let (_expected_types, expected_types) = scope.new_collection_from(vec![
+                (x, Type::Uint8),
+                (flag, Type::Bool),
+                (ill, Type::Uint8),
+                (y, Type::Uint8),
+                (well, Type::Uint8),
+            ]);
+            types::node_types(&graph).assert_eq(&expected_types);
+
+            let (_expected_errors, expected_errors) = scope.new_collection_from(vec![
+                GraphTypeError::OperandTypeMismatch {
+                    node: ill,
+                    lhs: Type::Uint8,
+                    rhs: Type::Bool,
+                },
+                GraphTypeError::InvalidOperandType {
+                    node: ill,
+                    ty: Type::Bool,
+                },
+            ]);
+            types::typecheck(&graph).assert_eq(&expected_errors);
+
+            // The rewrite removes every addition it's given, which leaves the one
+            // within the ill-typed function alone
+            let rewritten = types::gate_rewrite(scope, &graph, |_scope, graph| ProgramGraph {
+                nodes: graph.nodes.filter(|(_, node)| !node.is::<Add>()),
+                ..graph.clone()
+            });
+
+            let (_expected_nodes, expected_nodes) = scope.new_collection_from(vec![
+                (ill_end, Node::from(End)),
+                (well_end, Node::from(End)),
+                (x, Node::from(Parameter { ty: Type::Uint8 })),
+                (flag, Node::from(Constant::Bool(true))),
+                (ill, Node::from(Add { lhs: x, rhs: flag })),
+                (y, Node::from(Parameter { ty: Type::Uint8 })),
+            ]);
+            rewritten.nodes.assert_eq(&expected_nodes);
+            rewritten.functions.assert_eq(&graph.functions);
+        });
+    });
+}
+
+#[test]
+fn regions_nest_within_loops() {
+    let node = |hash| NodeId::new(Uuid::new(0, hash));
+    let (func, start, head, branch, left, right, join, end) = (
+        FuncId::new(Uuid::new(0, 1)),
+        node(2),
+        node(3),
+        node(4),
+        node(5),
+        node(6),
+        node(7),
+        node(8),
+    );
+
+    timely::execute_directly(move |worker| {
+        worker.dataflow::<Time, _, _>(|scope| {
+            let (_nodes, nodes) = scope.new_collection_from(vec![
+                (start, Node::from(Start)),
+                (head, Node::from(LoopHead {})),
+                (branch, Node::from(Branch {})),
+                (left, Node::from(Merge)),
+                (right, Node::from(Merge)),
+                (join, Node::from(Merge)),
+                (end, Node::from(End)),
+            ]);
+            let (_function_nodes, function_nodes) = scope.new_collection_from(vec![(end, func)]);
+            // A loop around a diamond, edges point from each node to its predecessor
+            let (_control_edges, control_edges) = scope.new_collection_from(vec![
+                (head, start),
+                (head, join),
+                (branch, head),
+                (left, branch),
+                (right, branch),
+                (join, left),
+                (join, right),
+                (end, head),
+            ]);
+            let (_value_edges, value_edges) = scope.new_collection::<_, Diff>();
+            let (_effect_edges, effect_edges) = scope.new_collection::<_, Diff>();
+            let (_functions, functions) = scope.new_collection_from(vec![(func, Function {})]);
+
+            let graph = ProgramGraph {
+                value_edges,
+                effect_edges,
+                control_edges,
+                nodes,
+                function_nodes,
+                functions,
+            };
+            let tree = regions::region_tree(&graph);
+
+            // The start can't be split from the loop since the loop's head is entered
+            // from within it
+            let (function, looping, diamond) = (
+                Region::new(start, end),
+                Region::new(head, end),
+                Region::new(branch, join),
+            );
+
+            let (_expected_regions, expected_regions) =
+                scope.new_collection_from(vec![function, looping, diamond]);
+            tree.regions.assert_eq(&expected_regions);
+
+            let (_expected_parents, expected_parents) =
+                scope.new_collection_from(vec![(looping, function), (diamond, looping)]);
+            tree.parents.assert_eq(&expected_parents);
+
+            let (_expected_nodes, expected_nodes) = scope.new_collection_from(vec![
+                (start, function),
+                (head, looping),
+                (branch, diamond),
+                (left, diamond),
+                (right, diamond),
+                (join, looping),
+                (end, function),
+            ]);
+            tree.node_regions.assert_eq(&expected_nodes);
+        });
+    });
 }
@@ -0,0 +1,262 @@
+//! The types of the values within a graph and the checks that keep them consistent
+//!
+//! Only [parameters](crate::vsdg::node::Parameter) and [constants](Constant) are
+//! annotated with their types, every operation gets its type from its operands.
+//! [`node_types()`] derives the type of every node that produces a value as a side
+//! collection and [`typecheck()`] verifies that the value edges of every operation
+//! connect it to operands it can operate on. Rewrites assume their input to be well
+//! typed, so they're run through [`gate_rewrite()`] to keep them away from ill-typed
+//! functions
+
+use crate::{
+    dataflow::Difference,
+    vsdg::{
+        node::{Constant, Node, NodeId, Operation, Type, Value},
+        ProgramGraph,
+    },
+};
+use abomonation_derive::Abomonation;
+use differential_dataflow::{
+    lattice::Lattice,
+    operators::{Iterate, Join, Reduce, Threshold},
+    Collection,
+};
+use std::fmt::{self, Display};
+use timely::dataflow::Scope;
+
+/// The type of every node that produces a value
+///
+/// Nodes without a type are either not values (like control and memory nodes) or
+/// have operands that don't have one either, like constant arrays
+pub fn node_types<S, R>(graph: &ProgramGraph<S, R>) -> Collection<S, (NodeId, Type), R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let annotated = graph.nodes.flat_map(|(id, node)| {
+        let ty = match node {
+            Node::Value(Value::Parameter(param)) => Some(param.ty),
+            Node::Value(Value::Constant(constant)) => constant_type(&constant),
+            Node::Operation(Operation::Cmp(_)) => Some(Type::Bool),
+            _ => None,
+        };
+
+        ty.map(|ty| (id, ty))
+    });
+
+    // Every other operation has the type of its first operand, mismatched operands
+    // are caught by the type checker
+    let inherited = graph.nodes.flat_map(|(id, node)| match node {
+        Node::Operation(Operation::Cmp(_)) => None,
+        Node::Operation(operation) => operation.operands().first().map(|&operand| (operand, id)),
+        _ => None,
+    });
+
+    annotated.iterate(|types| {
+        let (annotated, inherited) = (
+            annotated.enter(&types.scope()),
+            inherited.enter(&types.scope()),
+        );
+
+        inherited
+            .join_map(types, |_operand, &id, ty| (id, ty.clone()))
+            .concat(&annotated)
+            .distinct_core()
+    })
+}
+
+/// Collects the type errors within a graph
+pub fn typecheck<S, R>(graph: &ProgramGraph<S, R>) -> Collection<S, GraphTypeError, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+{
+    let types = node_types(graph);
+
+    // Every operand of every operation along with its position
+    let operands = graph.nodes.flat_map(|(id, node)| match node {
+        Node::Operation(operation) => operation
+            .operands()
+            .into_iter()
+            .enumerate()
+            .map(|(position, operand)| (operand, (id, position)))
+            .collect(),
+        _ => Vec::new(),
+    });
+
+    let untyped_operands = operands
+        .antijoin(&types.map(|(id, _)| id))
+        .map(|(operand, (node, _))| GraphTypeError::UntypedOperand { node, operand });
+
+    let operand_types = operands
+        .join_map(&types, |_operand, &(id, position), ty| {
+            (id, (position, ty.clone()))
+        })
+        .reduce(|_id, operands, output| {
+            let types: Vec<Type> = operands.iter().map(|((_, ty), _)| ty.clone()).collect();
+            output.push((types, R::from(1)));
+        });
+
+    let operand_errors = graph
+        .nodes
+        .flat_map(|(id, node)| match node {
+            Node::Operation(operation) => Some((id, operation)),
+            _ => None,
+        })
+        .join_map(&operand_types, |&node, operation, types| {
+            check_operands(node, operation, types)
+        })
+        .flat_map(|errors| errors);
+
+    untyped_operands.concat(&operand_errors).distinct_core()
+}
+
+/// Applies a rewrite to the well-typed functions within a graph, ill-typed ones are
+/// passed through untouched
+///
+/// A node belongs to the function of the end node it's reachable from, nodes that
+/// aren't reachable from any end node are always handed to the rewrite
+pub fn gate_rewrite<S, R, F>(
+    scope: &mut S,
+    graph: &ProgramGraph<S, R>,
+    rewrite: F,
+) -> ProgramGraph<S, R>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    R: Difference,
+    F: FnOnce(&mut S, &ProgramGraph<S, R>) -> ProgramGraph<S, R>,
+{
+    let memberships = graph.node_memberships();
+    let ill_typed_ends = typecheck(graph)
+        .map(|error| (error.node(), ()))
+        .join_map(&memberships, |_node, &(), &end| end)
+        .distinct_core::<R>();
+    let ill_typed = memberships
+        .map(|(node, end)| (end, node))
+        .semijoin(&ill_typed_ends)
+        .map(|(_end, node)| node)
+        .distinct_core::<R>();
+
+    let well_typed = ProgramGraph {
+        value_edges: graph.value_edges.antijoin(&ill_typed),
+        effect_edges: graph.effect_edges.antijoin(&ill_typed),
+        control_edges: graph.control_edges.antijoin(&ill_typed),
+        nodes: graph.nodes.antijoin(&ill_typed),
+        function_nodes: graph.function_nodes.antijoin(&ill_typed),
+        functions: graph.functions.clone(),
+    };
+    let untouched = ProgramGraph {
+        value_edges: graph.value_edges.semijoin(&ill_typed),
+        effect_edges: graph.effect_edges.semijoin(&ill_typed),
+        control_edges: graph.control_edges.semijoin(&ill_typed),
+        nodes: graph.nodes.semijoin(&ill_typed),
+        function_nodes: graph.function_nodes.semijoin(&ill_typed),
+        functions: graph.functions.filter(|_| false),
+    };
+
+    rewrite(scope, &well_typed).concat(&untouched)
+}
+
+fn constant_type(constant: &Constant) -> Option<Type> {
+    match constant {
+        Constant::Uint8(_) => Some(Type::Uint8),
+        Constant::Bool(_) => Some(Type::Bool),
+        Constant::F32(_) => Some(Type::F32),
+        Constant::F64(_) => Some(Type::F64),
+        Constant::Array(_) => None,
+    }
+}
+
+/// Checks that the operands of an operation share a type and that the operation
+/// can be applied to it, arithmetic isn't defined for booleans and bitwise logic
+/// isn't defined for floats
+fn check_operands(node: NodeId, operation: &Operation, types: &[Type]) -> Vec<GraphTypeError> {
+    let mut errors = Vec::new();
+
+    if let [lhs, rhs] = types {
+        if lhs != rhs {
+            errors.push(GraphTypeError::OperandTypeMismatch {
+                node,
+                lhs: lhs.clone(),
+                rhs: rhs.clone(),
+            });
+        }
+    }
+
+    let valid = |ty: &Type| match operation {
+        Operation::Mul(_) | Operation::Add(_) | Operation::Sub(_) | Operation::Div(_) => {
+            *ty != Type::Bool
+        }
+        Operation::And(_) | Operation::Or(_) | Operation::Xor(_) | Operation::Not(_) => {
+            !matches!(ty, Type::F32 | Type::F64)
+        }
+        Operation::Cmp(_) | Operation::Load(_) | Operation::Store(_) => true,
+    };
+
+    let mut invalid: Vec<&Type> = types.iter().filter(|ty| !valid(ty)).collect();
+    invalid.dedup();
+    errors.extend(
+        invalid
+            .into_iter()
+            .map(|ty| GraphTypeError::InvalidOperandType {
+                node,
+                ty: ty.clone(),
+            }),
+    );
+
+    errors
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum GraphTypeError {
+    OperandTypeMismatch {
+        node: NodeId,
+        lhs: Type,
+        rhs: Type,
+    },
+    InvalidOperandType {
+        node: NodeId,
+        ty: Type,
+    },
+    /// An operation uses a node that doesn't produce a value
+    UntypedOperand {
+        node: NodeId,
+        operand: NodeId,
+    },
+}
+
+impl GraphTypeError {
+    /// The operation the error occurred within
+    pub const fn node(&self) -> NodeId {
+        match *self {
+            Self::OperandTypeMismatch { node, .. }
+            | Self::InvalidOperandType { node, .. }
+            | Self::UntypedOperand { node, .. } => node,
+        }
+    }
+}
+
+impl Display for GraphTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OperandTypeMismatch { node, lhs, rhs } => write!(
+                f,
+                "node {} has operands of the different types {} and {}",
+                node, lhs, rhs,
+            ),
+            Self::InvalidOperandType { node, ty } => {
+                write!(f, "node {} can't operate on values of type {}", node, ty)
+            }
+            Self::UntypedOperand { node, operand } => write!(
+                f,
+                "node {} uses node {} as an operand, which doesn't produce a value",
+                node, operand,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GraphTypeError {}
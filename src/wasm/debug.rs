@@ -0,0 +1,193 @@
+//! Debug info for emitted wasm
+//!
+//! Emitted functions are named through the standard `name` custom section and
+//! the code offsets of instructions are mapped back to their source spans by a
+//! [`LineTable`], which the emitter fills in as it writes code, see
+//! [`emit_with_lines()`](crate::wasm::emit_with_lines)
+
+use crate::{
+    repr::{Function, Span},
+    symbols::SymbolResolver,
+};
+
+/// The `name` custom section, which names functions and locals within tools
+/// like debuggers and profilers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameSection {
+    module: Option<String>,
+    functions: Vec<(u32, String)>,
+    locals: Vec<(u32, Vec<(u32, String)>)>,
+}
+
+impl NameSection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names every named function, giving each function the index of its
+    /// position within `functions`
+    pub fn from_functions<R>(functions: &[Function], interner: &R) -> Self
+    where
+        R: SymbolResolver,
+    {
+        let mut section = Self::new();
+        for (idx, function) in functions.iter().enumerate() {
+            if let Some(name) = function.name {
+                section.function(idx as u32, &interner.display_symbol(name.0).to_string());
+            }
+        }
+
+        section
+    }
+
+    pub fn module(&mut self, name: &str) -> &mut Self {
+        self.module = Some(name.to_owned());
+        self
+    }
+
+    pub fn function(&mut self, func: u32, name: &str) -> &mut Self {
+        self.functions.push((func, name.to_owned()));
+        self
+    }
+
+    pub fn local(&mut self, func: u32, local: u32, name: &str) -> &mut Self {
+        match self.locals.iter_mut().find(|(idx, _)| *idx == func) {
+            Some((_, locals)) => locals.push((local, name.to_owned())),
+            None => self.locals.push((func, vec![(local, name.to_owned())])),
+        }
+
+        self
+    }
+
+    /// Encodes the entire custom section, including its section id and size
+    pub fn encode(&self) -> Vec<u8> {
+        let mut contents = Vec::new();
+        write_name(&mut contents, "name");
+
+        if let Some(module) = &self.module {
+            let mut subsection = Vec::new();
+            write_name(&mut subsection, module);
+            write_subsection(&mut contents, 0, &subsection);
+        }
+
+        if !self.functions.is_empty() {
+            let mut subsection = Vec::new();
+            write_name_map(&mut subsection, &self.functions);
+            write_subsection(&mut contents, 1, &subsection);
+        }
+
+        if !self.locals.is_empty() {
+            let mut locals = self.locals.clone();
+            locals.sort_by_key(|&(func, _)| func);
+
+            let mut subsection = Vec::new();
+            write_u32(&mut subsection, locals.len() as u32);
+            for (func, names) in locals.iter() {
+                write_u32(&mut subsection, *func);
+                write_name_map(&mut subsection, names);
+            }
+            write_subsection(&mut contents, 2, &subsection);
+        }
+
+        let mut section = vec![0x00];
+        write_u32(&mut section, contents.len() as u32);
+        section.extend(contents);
+
+        section
+    }
+}
+
+/// A single row of a [`LineTable`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineRow {
+    /// The offset of the first byte of the emitted code, relative to the start
+    /// of the code section
+    pub address: u64,
+    pub span: Span,
+}
+
+/// Maps ranges of emitted code back to the spans they were emitted from,
+/// in the same spirit as DWARF's `.debug_line`
+///
+/// Each row covers the code from its address up to the address of the next row
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineTable {
+    rows: Vec<LineRow>,
+}
+
+impl LineTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the code starting at `address` was emitted from `span`,
+    /// consecutive rows with the same span are merged together
+    pub fn add(&mut self, address: u64, span: Span) {
+        let row = LineRow { address, span };
+        let idx = match self.rows.binary_search(&row) {
+            Ok(_) => return,
+            Err(idx) => idx,
+        };
+
+        if idx > 0 && self.rows[idx - 1].span == span {
+            return;
+        }
+        self.rows.insert(idx, row);
+    }
+
+    pub fn rows(&self) -> &[LineRow] {
+        &self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Returns the span that the code at `address` was emitted from
+    pub fn span_of(&self, address: u64) -> Option<Span> {
+        let idx = match self.rows.binary_search_by_key(&address, |row| row.address) {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+
+        Some(self.rows[idx].span)
+    }
+}
+
+pub(super) fn write_subsection(buf: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    buf.push(id);
+    write_u32(buf, contents.len() as u32);
+    buf.extend_from_slice(contents);
+}
+
+fn write_name_map(buf: &mut Vec<u8>, names: &[(u32, String)]) {
+    // Name maps must be sorted by their indices
+    let mut names: Vec<_> = names.iter().collect();
+    names.sort_by_key(|&&(idx, _)| idx);
+
+    write_u32(buf, names.len() as u32);
+    for (idx, name) in names {
+        write_u32(buf, *idx);
+        write_name(buf, name);
+    }
+}
+
+pub(super) fn write_name(buf: &mut Vec<u8>, name: &str) {
+    write_u32(buf, name.len() as u32);
+    buf.extend_from_slice(name.as_bytes());
+}
+
+/// Writes an unsigned LEB128 integer
+pub(super) fn write_u32(buf: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
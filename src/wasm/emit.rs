@@ -0,0 +1,839 @@
+//! Encoding of sruth ir into wasm binaries
+//!
+//! The inverse of [`parse()`](super::parse()), covering the same subset of wasm
+//! along with floats and forward branches: functions over numbers whose blocks
+//! never jump backwards. Every emitted function is exported under its name and calls to
+//! functions that aren't being emitted are imported from [`IMPORT_MODULE`] under
+//! the callee's name, so a handful of changed functions can be emitted as a patch
+//! against the module they came from
+//!
+//! Wasm has no 128-bit integers, so functions are
+//! [legalized](crate::optimize::legalize) before they're encoded and every `int128`
+//! or `uint128` is passed around as a pair of `i64`s, low half first. Branch
+//! diamonds that only pick between two values are
+//! [converted into selects](crate::optimize::if_conversion) beforehand and blocks are
+//! [laid out](crate::optimize::layout) by their branch weights, so hot successors
+//! fall through while every other target is reached by breaking out of a wasm
+//! `block` ending right before it
+
+use crate::{
+    builder::IdAllocator,
+    optimize::{
+        if_conversion, layout,
+        legalize::{self, LegalizeError},
+    },
+    repr::{
+        instruction::{Add, Assign, Call, Cmp, Div, Lowering, Mul, Neg, Rem, Select, Sub},
+        terminator::{Branch, Return},
+        BasicBlock, BasicBlockId, Constant, FuncId, Function, InstId, Instruction, InstructionExt,
+        Span, Terminator, Type, Value, ValueKind, VarId,
+    },
+    symbols::SymbolResolver,
+    wasm::debug::{write_name, write_subsection, write_u32, LineTable, NameSection},
+};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::{self, Display},
+    num::NonZeroU64,
+};
+
+/// The module that functions called from a patch but not contained within it are
+/// imported from
+pub const IMPORT_MODULE: &str = "module";
+
+/// Encodes the given functions into a wasm binary
+pub fn emit<R>(functions: &[Function], interner: &R) -> Result<Vec<u8>, EmitError>
+where
+    R: SymbolResolver,
+{
+    emit_patch(functions, functions, interner)
+}
+
+/// Encodes the given functions into a wasm binary along with the [`LineTable`]
+/// mapping its code back to the spans it was emitted from
+pub fn emit_with_lines<R>(
+    functions: &[Function],
+    interner: &R,
+) -> Result<(Vec<u8>, LineTable), EmitError>
+where
+    R: SymbolResolver,
+{
+    emit_module(functions, functions, interner)
+}
+
+/// Encodes `patched` into a wasm binary that imports every function of `module`
+/// that the patched functions call but that isn't patched itself
+///
+/// [External](crate::repr::FunctionAttributes::EXTERNAL) functions have no body to
+/// emit, so every one of them within `patched` is imported instead
+pub fn emit_patch<R>(
+    patched: &[Function],
+    module: &[Function],
+    interner: &R,
+) -> Result<Vec<u8>, EmitError>
+where
+    R: SymbolResolver,
+{
+    emit_module(patched, module, interner).map(|(bytes, _lines)| bytes)
+}
+
+fn emit_module<R>(
+    patched: &[Function],
+    module: &[Function],
+    interner: &R,
+) -> Result<(Vec<u8>, LineTable), EmitError>
+where
+    R: SymbolResolver,
+{
+    let (patched, module) = (lower_all(patched)?, lower_all(module)?);
+    let (mut imports, patched): (Vec<&Function>, Vec<&Function>) = patched
+        .iter()
+        .partition(|function| function.metadata.attributes.is_external());
+    let patched_ids: HashSet<FuncId> = patched.iter().map(|function| function.id).collect();
+
+    for call in patched.iter().copied().flat_map(calls) {
+        if !patched_ids.contains(&call.func) && imports.iter().all(|func| func.id != call.func) {
+            let callee = module
+                .iter()
+                .find(|function| function.id == call.func)
+                .ok_or(EmitError::UnknownFunction(call.func))?;
+
+            imports.push(callee);
+        }
+    }
+
+    // Imported functions come before defined ones within the function index space
+    let callees: HashMap<FuncId, Callee> = imports
+        .iter()
+        .copied()
+        .chain(patched.iter().copied())
+        .enumerate()
+        .map(|(idx, function)| {
+            let callee = Callee {
+                index: idx as u32,
+                results: function
+                    .ret_ty
+                    .results()
+                    .iter()
+                    .map(value_type)
+                    .collect::<Result<_, _>>()?,
+            };
+
+            Ok((function.id, callee))
+        })
+        .collect::<Result<_, EmitError>>()?;
+
+    let mut signatures = Vec::new();
+    let mut signature_index = |function: &Function| -> Result<u32, EmitError> {
+        let signature = Signature::of(function)?;
+        let idx = signatures
+            .iter()
+            .position(|sig| *sig == signature)
+            .unwrap_or_else(|| {
+                signatures.push(signature);
+                signatures.len() - 1
+            });
+
+        Ok(idx as u32)
+    };
+
+    let mut import_section = Vec::new();
+    write_u32(&mut import_section, imports.len() as u32);
+    for function in imports.iter() {
+        write_name(&mut import_section, IMPORT_MODULE);
+        write_name(&mut import_section, &export_name(function, interner));
+        import_section.push(0x00);
+        write_u32(&mut import_section, signature_index(function)?);
+    }
+
+    let (mut function_section, mut export_section, mut code_section) =
+        (Vec::new(), Vec::new(), Vec::new());
+    write_u32(&mut function_section, patched.len() as u32);
+    write_u32(&mut export_section, patched.len() as u32);
+    write_u32(&mut code_section, patched.len() as u32);
+
+    let (mut names, mut lines) = (NameSection::new(), LineTable::new());
+    for function in imports.iter().copied().chain(patched.iter().copied()) {
+        names.function(
+            callees[&function.id].index,
+            &export_name(function, interner),
+        );
+    }
+
+    for function in patched {
+        write_u32(&mut function_section, signature_index(function)?);
+
+        write_name(&mut export_section, &export_name(function, interner));
+        export_section.push(0x00);
+        write_u32(&mut export_section, callees[&function.id].index);
+
+        let (body, spans) = Body::encode(function, &callees)?;
+        write_u32(&mut code_section, body.len() as u32);
+        for (offset, span) in spans {
+            lines.add((code_section.len() + offset) as u64, span);
+        }
+        code_section.extend(body);
+    }
+
+    let mut type_section = Vec::new();
+    write_u32(&mut type_section, signatures.len() as u32);
+    for signature in signatures {
+        type_section.push(0x60);
+        write_u32(&mut type_section, signature.params.len() as u32);
+        type_section.extend(signature.params);
+        write_u32(&mut type_section, signature.results.len() as u32);
+        type_section.extend(signature.results);
+    }
+
+    let mut bytes = b"\0asm".to_vec();
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    write_subsection(&mut bytes, 1, &type_section);
+    if !imports.is_empty() {
+        write_subsection(&mut bytes, 2, &import_section);
+    }
+    write_subsection(&mut bytes, 3, &function_section);
+    write_subsection(&mut bytes, 7, &export_section);
+    write_subsection(&mut bytes, 10, &code_section);
+    bytes.extend(names.encode());
+
+    Ok((bytes, lines))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmitError {
+    /// A function calls a function that isn't within the module
+    UnknownFunction(FuncId),
+    /// A block jumps to a block that isn't within its function
+    UnknownBlock(BasicBlockId),
+    /// A variable is used without being a parameter or the result of an instruction
+    UndefinedVar(VarId),
+    /// A value of the given type can't be represented as an `i64`, `f32` or `f64`
+    UnsupportedType(Type),
+    /// The function uses a feature of the ir that can't be emitted yet
+    Unsupported(&'static str),
+    /// The function's 128-bit integers couldn't be split into pairs of `i64`s
+    Legalize(LegalizeError),
+}
+
+impl Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFunction(func) => write!(f, "call to unknown function {:?}", func),
+            Self::UnknownBlock(block) => write!(f, "jump to unknown block {:?}", block),
+            Self::UndefinedVar(var) => write!(f, "use of undefined variable {:?}", var),
+            Self::UnsupportedType(ty) => write!(f, "unsupported type {:?}", ty),
+            Self::Unsupported(feature) => write!(f, "unsupported ir feature: {}", feature),
+            Self::Legalize(error) => Display::fmt(error, f),
+        }
+    }
+}
+
+impl Error for EmitError {}
+
+impl From<LegalizeError> for EmitError {
+    fn from(error: LegalizeError) -> Self {
+        Self::Legalize(error)
+    }
+}
+
+/// The `i64` value type
+const I64: u8 = 0x7E;
+/// The `f32` value type
+const F32: u8 = 0x7D;
+/// The `f64` value type
+const F64: u8 = 0x7C;
+
+/// Functions are exported under their names, unnamed ones get a name derived from their id
+fn export_name<R>(function: &Function, interner: &R) -> String
+where
+    R: SymbolResolver,
+{
+    function.name.map_or_else(
+        || format!("func{}", function.id.as_u64()),
+        |name| interner.display_symbol(name.0).to_string(),
+    )
+}
+
+/// Turns the branch diamonds of every function into selects, splits their 128-bit
+/// integers into pairs of 64-bit halves and lays out their blocks
+fn lower_all(functions: &[Function]) -> Result<Vec<Function>, EmitError> {
+    functions
+        .iter()
+        .map(|function| {
+            let mut function = if_conversion::if_conversion(function);
+
+            if legalize::has_wide_integers(&function) {
+                let ids = LocalIds::after(&function);
+                function = legalize::legalize_wide_integers(&function, &ids)?;
+            }
+
+            Ok(layout::lay_out_blocks(&function))
+        })
+        .collect()
+}
+
+/// Mints ids past the largest variable of a function, the variables the
+/// legalization introduces only have to be unique within the function they're
+/// emitted as locals of
+struct LocalIds {
+    next: Cell<u64>,
+}
+
+impl LocalIds {
+    fn after(function: &Function) -> Self {
+        let largest = function
+            .params
+            .iter()
+            .map(|param| param.var)
+            .chain(
+                function
+                    .basic_blocks
+                    .iter()
+                    .flat_map(|block| block.instructions.iter().map(InstructionExt::dest)),
+            )
+            .map(VarId::as_u64)
+            .max()
+            .unwrap_or(0);
+
+        Self {
+            next: Cell::new(largest + 1),
+        }
+    }
+
+    fn fetch(&self) -> NonZeroU64 {
+        let id = self.next.get();
+        self.next.set(id + 1);
+
+        // `VarId::as_u64()` is offset by one from the id it was created from
+        NonZeroU64::new(id + 1).expect("minted an invalid id")
+    }
+}
+
+impl IdAllocator for LocalIds {
+    fn function_id(&self) -> FuncId {
+        FuncId::new(self.fetch())
+    }
+
+    fn block_id(&self) -> BasicBlockId {
+        BasicBlockId::new(self.fetch())
+    }
+
+    fn inst_id(&self) -> InstId {
+        InstId::new(self.fetch())
+    }
+
+    fn var_id(&self) -> VarId {
+        VarId::new(self.fetch())
+    }
+}
+
+/// Every block reachable from the entry of a function
+fn reachable_blocks(function: &Function) -> Result<HashSet<BasicBlockId>, EmitError> {
+    let (mut reachable, mut stack) = (HashSet::new(), vec![function.entry]);
+    while let Some(current) = stack.pop() {
+        if reachable.insert(current) {
+            let block = function
+                .basic_blocks
+                .iter()
+                .find(|block| block.id == current)
+                .ok_or(EmitError::UnknownBlock(current))?;
+
+            stack.extend(block.terminator.jump_targets());
+        }
+    }
+
+    Ok(reachable)
+}
+
+fn calls(function: &Function) -> impl Iterator<Item = &Call> + '_ {
+    function
+        .basic_blocks
+        .iter()
+        .flat_map(|block| block.instructions.iter())
+        .filter_map(|inst| match inst {
+            Instruction::Call(call) => Some(call),
+            _ => None,
+        })
+}
+
+/// The wasm value type values of the given type are held in
+fn value_type(ty: &Type) -> Result<u8, EmitError> {
+    match ty {
+        Type::Int | Type::Uint => Ok(I64),
+        Type::F32 => Ok(F32),
+        Type::F64 => Ok(F64),
+        ty => Err(EmitError::UnsupportedType(ty.clone())),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Signature {
+    params: Vec<u8>,
+    /// Functions returning tuples have one result per element, which requires the
+    /// multi-value proposal
+    results: Vec<u8>,
+}
+
+impl Signature {
+    fn of(function: &Function) -> Result<Self, EmitError> {
+        let mut params = Vec::with_capacity(function.params.len());
+        for (idx, param) in function.params.iter().enumerate() {
+            params.push(value_type(&param.ty)?);
+
+            // Every integer is passed as an `i64` so extension never changes the
+            // signature, but there's no linear memory for references to point into
+            if function.metadata.param(idx).is_by_ref() {
+                return Err(EmitError::Unsupported("by-reference parameters"));
+            }
+        }
+
+        let results = function
+            .ret_ty
+            .results()
+            .iter()
+            .map(value_type)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { params, results })
+    }
+}
+
+/// The position of a function within the function index space
+#[derive(Debug, Clone)]
+struct Callee {
+    index: u32,
+    results: Vec<u8>,
+}
+
+/// Encodes a single function body, giving every parameter and instruction result a local
+struct Body<'a> {
+    callees: &'a HashMap<FuncId, Callee>,
+    locals: HashMap<VarId, u32>,
+    /// The value type of every local, starting with the parameters
+    types: Vec<u8>,
+    params: u32,
+    code: Vec<u8>,
+    /// The offset within `code` of every instruction and terminator with a span
+    spans: Vec<(usize, Span)>,
+}
+
+impl<'a> Body<'a> {
+    /// Encodes a function's body along with the offsets of the spanned code within it
+    fn encode(
+        function: &Function,
+        callees: &'a HashMap<FuncId, Callee>,
+    ) -> Result<(Vec<u8>, Vec<(usize, Span)>), EmitError> {
+        let mut body = Self {
+            callees,
+            locals: function
+                .params
+                .iter()
+                .enumerate()
+                .map(|(idx, param)| (param.var, idx as u32))
+                .collect(),
+            types: function
+                .params
+                .iter()
+                .map(|param| value_type(&param.ty))
+                .collect::<Result<_, _>>()?,
+            params: function.params.len() as u32,
+            code: Vec::new(),
+            spans: Vec::new(),
+        };
+
+        // Blocks are emitted in their layout order and jumps to the next block fall
+        // through into it. Every other jump target gets a wasm `block` that ends right
+        // before it so that jumping to it breaks out of that block, which only works
+        // as long as every jump goes forward
+        let reachable = reachable_blocks(function)?;
+        let blocks: Vec<&BasicBlock> = function
+            .basic_blocks
+            .iter()
+            .filter(|block| reachable.contains(&block.id))
+            .collect();
+        let positions: HashMap<BasicBlockId, usize> = blocks
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| (block.id, idx))
+            .collect();
+
+        let mut labeled = vec![false; blocks.len()];
+        for (idx, block) in blocks.iter().enumerate() {
+            if matches!(block.terminator, Terminator::Switch(_)) {
+                return Err(EmitError::Unsupported("switches"));
+            }
+
+            for target in block.terminator.jump_targets() {
+                let target = positions[&target];
+                if target <= idx {
+                    return Err(EmitError::Unsupported("loops"));
+                } else if target != idx + 1 {
+                    labeled[target] = true;
+                }
+            }
+        }
+
+        for _ in labeled.iter().filter(|&&labeled| labeled) {
+            body.code.extend_from_slice(&[0x02, 0x40]);
+        }
+
+        // The number of wasm blocks between a jump and its target
+        let depth = |from: usize, to: usize| {
+            labeled[from + 1..to]
+                .iter()
+                .filter(|&&labeled| labeled)
+                .count() as u32
+        };
+
+        for (idx, block) in blocks.iter().enumerate() {
+            if labeled[idx] {
+                body.code.push(0x0B);
+            }
+
+            for (inst_idx, inst) in block.instructions.iter().enumerate() {
+                if let Some(span) = block.instruction_span(inst_idx) {
+                    body.spans.push((body.code.len(), span));
+                }
+                body.instruction(inst)?;
+            }
+
+            if let Some(span) = block.terminator_span {
+                body.spans.push((body.code.len(), span));
+            }
+
+            match &block.terminator {
+                Terminator::Jump(next) => {
+                    let next = positions[next];
+                    if next != idx + 1 {
+                        body.code.push(0x0C);
+                        write_u32(&mut body.code, depth(idx, next));
+                    }
+                }
+
+                // Conditions are narrowed from their `i64` locals to the `i32` that
+                // `br_if` expects, or inverted when the taken side falls through
+                Terminator::Branch(Branch {
+                    cond,
+                    if_true,
+                    if_false,
+                }) => {
+                    let (if_true, if_false) =
+                        (positions[&if_true.block], positions[&if_false.block]);
+
+                    body.value(cond)?;
+                    if if_true == idx + 1 {
+                        body.code.push(0x50);
+                        body.code.push(0x0D);
+                        write_u32(&mut body.code, depth(idx, if_false));
+                    } else {
+                        body.code.push(0xA7);
+                        body.code.push(0x0D);
+                        write_u32(&mut body.code, depth(idx, if_true));
+
+                        if if_false != idx + 1 {
+                            body.code.push(0x0C);
+                            write_u32(&mut body.code, depth(idx, if_false));
+                        }
+                    }
+                }
+
+                // The last block returns by falling off the end of the function
+                Terminator::Return(Return { values }) => {
+                    for value in values {
+                        body.value(value)?;
+                    }
+
+                    if idx + 1 != blocks.len() {
+                        body.code.push(0x0F);
+                    }
+                }
+
+                // Traps are `unreachable` since wasm doesn't distinguish between them
+                Terminator::Unreachable | Terminator::Trap(_) => body.code.push(0x00),
+                Terminator::Switch(_) => unreachable!("switches are rejected beforehand"),
+            }
+        }
+        body.code.push(0x0B);
+
+        // Locals are declared in runs of the same type
+        let mut declared: Vec<(u32, u8)> = Vec::new();
+        for &ty in &body.types[body.params as usize..] {
+            match declared.last_mut() {
+                Some((count, last)) if *last == ty => *count += 1,
+                _ => declared.push((1, ty)),
+            }
+        }
+
+        let mut encoded = Vec::with_capacity(body.code.len() + 4);
+        write_u32(&mut encoded, declared.len() as u32);
+        for (count, ty) in declared {
+            write_u32(&mut encoded, count);
+            encoded.push(ty);
+        }
+        let code_start = encoded.len();
+        encoded.extend(body.code);
+
+        let spans = body
+            .spans
+            .into_iter()
+            .map(|(offset, span)| (code_start + offset, span))
+            .collect();
+
+        Ok((encoded, spans))
+    }
+
+    fn instruction(&mut self, inst: &Instruction) -> Result<(), EmitError> {
+        match inst {
+            Instruction::Assign(Assign { value, dest, .. }) => {
+                let ty = self.type_of(value)?;
+                self.value(value)?;
+                self.set(*dest, ty);
+            }
+
+            Instruction::Add(Add { lhs, rhs, dest, .. }) => {
+                self.binop(lhs, rhs, *dest, [0x7C, 0x92, 0xA0])?
+            }
+            Instruction::Sub(Sub { lhs, rhs, dest, .. }) => {
+                self.binop(lhs, rhs, *dest, [0x7D, 0x93, 0xA1])?
+            }
+            Instruction::Mul(Mul { lhs, rhs, dest, .. }) => {
+                self.binop(lhs, rhs, *dest, [0x7E, 0x94, 0xA2])?
+            }
+
+            // Wasm's integer division traps on division by zero and signed overflow
+            // just like `div` and `rem` do
+            Instruction::Div(Div { lhs, rhs, dest, .. }) => {
+                let opcode = if lhs.ty == Type::Uint { 0x80 } else { 0x7F };
+                self.binop(lhs, rhs, *dest, [opcode, 0x95, 0xA3])?
+            }
+            Instruction::Rem(Rem { lhs, rhs, dest, .. }) => {
+                if lhs.ty.is_float() {
+                    return Err(EmitError::Unsupported("float remainders"));
+                }
+
+                let opcode = if lhs.ty == Type::Uint { 0x82 } else { 0x81 };
+                self.binop(lhs, rhs, *dest, [opcode, opcode, opcode])?
+            }
+
+            Instruction::Neg(Neg { value, dest }) => {
+                let ty = self.type_of(value)?;
+                match ty {
+                    F32 => {
+                        self.value(value)?;
+                        self.code.push(0x8C);
+                    }
+                    F64 => {
+                        self.value(value)?;
+                        self.code.push(0x9A);
+                    }
+                    _ => {
+                        self.code.push(0x42);
+                        write_i64(&mut self.code, 0);
+                        self.value(value)?;
+                        self.code.push(0x7D);
+                    }
+                }
+                self.set(*dest, ty);
+            }
+
+            Instruction::Call(call) => {
+                for arg in call.args.iter() {
+                    self.value(arg)?;
+                }
+
+                let callee = self
+                    .callees
+                    .get(&call.func)
+                    .ok_or(EmitError::UnknownFunction(call.func))?;
+
+                // Calls only have a single destination to store their results in
+                if callee.results.len() > 1 {
+                    return Err(EmitError::Unsupported("calls returning multiple values"));
+                }
+
+                let (index, result) = (callee.index, callee.results.first().copied());
+                self.code.push(0x10);
+                write_u32(&mut self.code, index);
+
+                // The call's own return type may not have been inferred yet, so the
+                // callee's signature is used instead
+                if let Some(result) = result {
+                    self.set(call.dest, result);
+                }
+            }
+
+            // Booleans are held as zero or one within `i64` locals, floats are
+            // compared by value so NaNs never equal anything
+            Instruction::Cmp(Cmp { lhs, rhs, dest }) => {
+                let opcode = match self.type_of(lhs)? {
+                    F32 => 0x5B,
+                    F64 => 0x61,
+                    _ => 0x51,
+                };
+
+                self.value(lhs)?;
+                self.value(rhs)?;
+                self.code.push(opcode);
+                self.code.push(0xAD);
+                self.set(*dest, I64);
+            }
+
+            // Conditions are narrowed from their `i64` locals to the `i32` that
+            // `select` expects
+            Instruction::Select(Select {
+                cond,
+                if_true,
+                if_false,
+                dest,
+            }) => {
+                let ty = self.type_of(if_true)?;
+                self.value(if_true)?;
+                self.value(if_false)?;
+                self.value(cond)?;
+                self.code.push(0xA7);
+                self.code.push(0x1B);
+                self.set(*dest, ty);
+            }
+            Instruction::Bitcast(_) => return Err(EmitError::Unsupported("bitcasts")),
+            Instruction::Opaque(_) => return Err(EmitError::Unsupported("opaque instructions")),
+
+            // Intrinsics with a native lowering are applied to their arguments, runtime
+            // library calls would need the runtime to be imported
+            Instruction::Intrinsic(intrinsic) => {
+                let ty = intrinsic
+                    .args
+                    .first()
+                    .map_or(Type::Unit, |arg| arg.ty.clone());
+
+                match intrinsic.op.wasm_lowering(&ty) {
+                    Some(Lowering::Wasm(opcodes)) => {
+                        for arg in intrinsic.args.iter() {
+                            self.value(arg)?;
+                        }
+                        self.code.extend_from_slice(opcodes);
+                        self.set(intrinsic.dest, value_type(&ty)?);
+                    }
+                    Some(Lowering::Libcall(_)) => {
+                        return Err(EmitError::Unsupported("intrinsic libcalls"));
+                    }
+                    None => return Err(EmitError::UnsupportedType(ty)),
+                }
+            }
+            Instruction::ExtractValue(_) | Instruction::InsertValue(_) => {
+                return Err(EmitError::Unsupported("aggregates"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Emits a binary operation using the opcode for `i64`, `f32` or `f64` operands
+    fn binop(
+        &mut self,
+        lhs: &Value,
+        rhs: &Value,
+        dest: VarId,
+        [int, float32, float64]: [u8; 3],
+    ) -> Result<(), EmitError> {
+        let ty = self.type_of(lhs)?;
+        let opcode = match ty {
+            F32 => float32,
+            F64 => float64,
+            _ => int,
+        };
+
+        self.value(lhs)?;
+        self.value(rhs)?;
+        self.code.push(opcode);
+        self.set(dest, ty);
+
+        Ok(())
+    }
+
+    /// The value type of a value, variables have the type of their local
+    fn type_of(&self, value: &Value) -> Result<u8, EmitError> {
+        match value.value {
+            ValueKind::Var(var) => {
+                let local = *self.locals.get(&var).ok_or(EmitError::UndefinedVar(var))?;
+                Ok(self.types[local as usize])
+            }
+            ValueKind::Const(ref constant) => value_type(&constant.ty()),
+            ValueKind::Pooled(_) => value_type(&value.ty),
+        }
+    }
+
+    /// Pushes a value onto the stack, locals already have a supported type so only
+    /// constants need their types checked
+    fn value(&mut self, value: &Value) -> Result<(), EmitError> {
+        match value.value {
+            ValueKind::Const(Constant::Int(int)) => {
+                self.code.push(0x42);
+                write_i64(&mut self.code, int);
+            }
+            ValueKind::Const(Constant::Uint(uint)) => {
+                self.code.push(0x42);
+                write_i64(&mut self.code, uint as i64);
+            }
+            ValueKind::Const(Constant::F32(bits)) => {
+                self.code.push(0x43);
+                self.code.extend_from_slice(&bits.to_le_bytes());
+            }
+            ValueKind::Const(Constant::F64(bits)) => {
+                self.code.push(0x44);
+                self.code.extend_from_slice(&bits.to_le_bytes());
+            }
+            ValueKind::Const(Constant::Bool(_)) => {
+                return Err(EmitError::UnsupportedType(Type::Bool));
+            }
+            // Legalization already split every wide constant into halves
+            ValueKind::Const(ref wide @ Constant::Int128(_))
+            | ValueKind::Const(ref wide @ Constant::Uint128(_)) => {
+                return Err(EmitError::UnsupportedType(wide.ty()));
+            }
+            ValueKind::Const(ref aggregate @ Constant::Array(..))
+            | ValueKind::Const(ref aggregate @ Constant::Struct(_)) => {
+                return Err(EmitError::UnsupportedType(aggregate.ty()));
+            }
+
+            ValueKind::Var(var) => {
+                let local = *self.locals.get(&var).ok_or(EmitError::UndefinedVar(var))?;
+                self.code.push(0x20);
+                write_u32(&mut self.code, local);
+            }
+
+            ValueKind::Pooled(_) => return Err(EmitError::Unsupported("pooled constants")),
+        }
+
+        Ok(())
+    }
+
+    /// Pops the top of the stack into the local of `dest`, allocating a local of
+    /// the given type if needed
+    fn set(&mut self, dest: VarId, ty: u8) {
+        let next = self.types.len() as u32;
+        let local = *self.locals.entry(dest).or_insert(next);
+        if local == next {
+            self.types.push(ty);
+        }
+
+        self.code.push(0x21);
+        write_u32(&mut self.code, local);
+    }
+}
+
+/// Writes a signed LEB128 integer
+fn write_i64(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
@@ -1,3 +1,11 @@
 #![cfg(feature = "wasm")]
 
 //! Translate sruth ir to and from the `.wat` format
+
+pub mod debug;
+mod emit;
+mod parse;
+
+pub use debug::{LineRow, LineTable, NameSection};
+pub use emit::{emit, emit_patch, emit_with_lines, EmitError, IMPORT_MODULE};
+pub use parse::{parse, ParseError};
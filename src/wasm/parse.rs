@@ -0,0 +1,527 @@
+//! Decoding of wasm binaries into sruth ir
+//!
+//! Only the subset of wasm that the ir can currently express is supported:
+//! straight-line functions over `i64` values that use locals, integer arithmetic
+//! and calls. Structured control flow needs phi nodes to merge locals, so it's
+//! rejected as [unsupported](ParseError::Unsupported) for now
+
+use crate::{
+    builder::{Builder, BuilderError},
+    repr::{Constant, FuncId, Type, Value},
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    str,
+};
+
+/// Decodes every function within a wasm binary into the builder, returning the
+/// ids of the built functions in the order of the module's function index space
+///
+/// Exported functions are named after their exports
+pub fn parse(bytes: &[u8], builder: &mut Builder) -> Result<Vec<FuncId>, ParseError> {
+    let module = Module::decode(bytes)?;
+
+    let deferred: Vec<_> = module
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(idx, &ty)| {
+            let ret_ty = module.types[ty as usize].ret_ty();
+            match module.export_name(idx as u32) {
+                Some(name) => builder.allocate_named_function(name, ret_ty),
+                None => builder.allocate_function(ret_ty),
+            }
+        })
+        .collect();
+    let ids: Vec<FuncId> = deferred.iter().map(|function| **function).collect();
+
+    for (idx, function) in deferred.into_iter().enumerate() {
+        let signature = &module.types[module.functions[idx] as usize];
+        let body = &module.bodies[idx];
+
+        builder
+            .resume_building(function, |func| {
+                let mut locals: Vec<Value> = func
+                    .params(signature.params.iter().map(|_| Type::Int))
+                    .into_iter()
+                    .map(Value::from)
+                    .collect();
+                locals.extend((0..body.locals).map(|_| Value::from(Constant::Int(0))));
+
+                func.entry_block(|block| {
+                    let mut stack: Vec<Value> = Vec::new();
+
+                    // The stack was validated while decoding, so it can't underflow here
+                    let pop = |stack: &mut Vec<Value>| {
+                        stack.pop().expect("validated wasm stacks can't underflow")
+                    };
+
+                    for op in body.ops.iter() {
+                        match *op {
+                            Op::LocalGet(local) => stack.push(locals[local as usize].clone()),
+                            Op::LocalSet(local) => locals[local as usize] = pop(&mut stack),
+                            Op::LocalTee(local) => {
+                                locals[local as usize] = stack.last().unwrap().clone();
+                            }
+                            Op::Const(value) => stack.push(Constant::Int(value).into()),
+                            Op::Drop => {
+                                pop(&mut stack);
+                            }
+
+                            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Rem => {
+                                let (rhs, lhs) = (pop(&mut stack), pop(&mut stack));
+                                let result = match op {
+                                    Op::Add => block.add(lhs, rhs)?,
+                                    Op::Sub => block.sub(lhs, rhs)?,
+                                    Op::Mul => block.mul(lhs, rhs)?,
+                                    Op::Div => block.div(lhs, rhs)?,
+                                    Op::Rem => block.rem(lhs, rhs)?,
+                                    _ => unreachable!(),
+                                };
+
+                                stack.push(result.into());
+                            }
+
+                            Op::Call(callee) => {
+                                let callee_ty =
+                                    &module.types[module.functions[callee as usize] as usize];
+                                let args = stack.split_off(stack.len() - callee_ty.params.len());
+
+                                let mut result = block.call(ids[callee as usize], args)?;
+                                if !callee_ty.results.is_empty() {
+                                    result.ty = Type::Int;
+                                    stack.push(result.into());
+                                }
+                            }
+
+                            Op::Return => break,
+                        }
+                    }
+
+                    match signature.results.len() {
+                        0 => {
+                            block.ret_unit();
+                        }
+                        1 => {
+                            block.ret(pop(&mut stack))?;
+                        }
+                        results => {
+                            block.ret_multiple(stack.split_off(stack.len() - results))?;
+                        }
+                    }
+
+                    Ok(())
+                })?;
+
+                Ok(())
+            })
+            .map_err(ParseError::Build)?;
+    }
+
+    Ok(ids)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    UnexpectedEof,
+    InvalidLeb128,
+    InvalidUtf8,
+    /// The function and code sections declared different numbers of functions
+    FunctionCountMismatch {
+        functions: usize,
+        bodies: usize,
+    },
+    InvalidTypeIndex(u32),
+    InvalidFunctionIndex(u32),
+    InvalidLocalIndex(u32),
+    /// An instruction popped from an empty stack or a function ended
+    /// without its result on the stack
+    StackUnderflow,
+    UnsupportedValueType(u8),
+    UnsupportedOpcode(u8),
+    /// The module uses a wasm feature that can't be represented in the ir yet
+    Unsupported(&'static str),
+    Build(BuilderError),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMagic => f.write_str("not a wasm binary"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported wasm version {}", version),
+            Self::UnexpectedEof => f.write_str("unexpected end of input"),
+            Self::InvalidLeb128 => f.write_str("invalid leb128 integer"),
+            Self::InvalidUtf8 => f.write_str("invalid utf8 in name"),
+            Self::FunctionCountMismatch { functions, bodies } => write!(
+                f,
+                "{} functions were declared but {} bodies were given",
+                functions, bodies,
+            ),
+            Self::InvalidTypeIndex(idx) => write!(f, "invalid type index {}", idx),
+            Self::InvalidFunctionIndex(idx) => write!(f, "invalid function index {}", idx),
+            Self::InvalidLocalIndex(idx) => write!(f, "invalid local index {}", idx),
+            Self::StackUnderflow => f.write_str("value stack underflow"),
+            Self::UnsupportedValueType(ty) => write!(f, "unsupported value type 0x{:02x}", ty),
+            Self::UnsupportedOpcode(op) => write!(f, "unsupported opcode 0x{:02x}", op),
+            Self::Unsupported(feature) => write!(f, "unsupported wasm feature: {}", feature),
+            Self::Build(error) => write!(f, "failed to build function: {}", error),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Default)]
+struct Module {
+    types: Vec<Signature>,
+    /// The type index of every function
+    functions: Vec<u32>,
+    exports: Vec<(String, u32)>,
+    bodies: Vec<Body>,
+}
+
+impl Module {
+    fn decode(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut reader = Reader::new(bytes);
+        if reader.bytes(4)? != b"\0asm" {
+            return Err(ParseError::InvalidMagic);
+        }
+
+        let version = u32::from_le_bytes([
+            reader.byte()?,
+            reader.byte()?,
+            reader.byte()?,
+            reader.byte()?,
+        ]);
+        if version != 1 {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+
+        let mut module = Self::default();
+        while !reader.is_empty() {
+            let id = reader.byte()?;
+            let size = reader.u32()? as usize;
+            let mut section = Reader::new(reader.bytes(size)?);
+
+            match id {
+                // Custom sections don't affect semantics
+                0 => {}
+                1 => {
+                    for _ in 0..section.u32()? {
+                        module.types.push(Signature::decode(&mut section)?);
+                    }
+                }
+                2 => return Err(ParseError::Unsupported("imports")),
+                3 => {
+                    for _ in 0..section.u32()? {
+                        module.functions.push(section.u32()?);
+                    }
+                }
+                7 => {
+                    for _ in 0..section.u32()? {
+                        let name = section.name()?;
+                        let kind = section.byte()?;
+                        let idx = section.u32()?;
+
+                        if kind == 0x00 {
+                            module.exports.push((name, idx));
+                        }
+                    }
+                }
+                10 => {
+                    let count = section.u32()?;
+                    if count as usize != module.functions.len() {
+                        return Err(ParseError::FunctionCountMismatch {
+                            functions: module.functions.len(),
+                            bodies: count as usize,
+                        });
+                    }
+
+                    for idx in 0..count as usize {
+                        let size = section.u32()? as usize;
+                        let body =
+                            Body::decode(&mut Reader::new(section.bytes(size)?), &module, idx)?;
+                        module.bodies.push(body);
+                    }
+                }
+                4 => return Err(ParseError::Unsupported("tables")),
+                5 => return Err(ParseError::Unsupported("memories")),
+                6 => return Err(ParseError::Unsupported("globals")),
+                8 => return Err(ParseError::Unsupported("start functions")),
+                _ => {}
+            }
+        }
+
+        if module.bodies.len() != module.functions.len() {
+            return Err(ParseError::FunctionCountMismatch {
+                functions: module.functions.len(),
+                bodies: module.bodies.len(),
+            });
+        }
+
+        Ok(module)
+    }
+
+    fn export_name(&self, func: u32) -> Option<&str> {
+        self.exports
+            .iter()
+            .find(|&&(_, idx)| idx == func)
+            .map(|(name, _)| &**name)
+    }
+
+    fn signature(&self, func: u32) -> Result<&Signature, ParseError> {
+        let ty = *self
+            .functions
+            .get(func as usize)
+            .ok_or(ParseError::InvalidFunctionIndex(func))?;
+
+        self.types
+            .get(ty as usize)
+            .ok_or(ParseError::InvalidTypeIndex(ty))
+    }
+}
+
+#[derive(Debug)]
+struct Signature {
+    params: Vec<u8>,
+    results: Vec<u8>,
+}
+
+impl Signature {
+    fn decode(reader: &mut Reader<'_>) -> Result<Self, ParseError> {
+        if reader.byte()? != 0x60 {
+            return Err(ParseError::Unsupported("non-function types"));
+        }
+
+        let params = reader.value_types()?;
+        let results = reader.value_types()?;
+
+        Ok(Self { params, results })
+    }
+
+    fn ret_ty(&self) -> Type {
+        Type::from_results(self.results.iter().map(|_| Type::Int).collect())
+    }
+}
+
+#[derive(Debug)]
+struct Body {
+    /// The number of locals declared on top of the function's parameters
+    locals: u32,
+    ops: Vec<Op>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    Const(i64),
+    Drop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Call(u32),
+    Return,
+}
+
+impl Body {
+    /// Decodes a function body, checking that every instruction has the operands it needs
+    fn decode(reader: &mut Reader<'_>, module: &Module, func: usize) -> Result<Self, ParseError> {
+        let signature = module.signature(func as u32)?;
+
+        let mut locals = 0u32;
+        for _ in 0..reader.u32()? {
+            let count = reader.u32()?;
+            reader.value_type()?;
+            locals = locals
+                .checked_add(count)
+                .ok_or(ParseError::Unsupported("more than 2^32 locals"))?;
+        }
+        let total_locals = signature.params.len() as u32 + locals;
+
+        let (mut ops, mut depth) = (Vec::new(), 0usize);
+        let pop = |depth: &mut usize, count: usize| -> Result<(), ParseError> {
+            *depth = depth.checked_sub(count).ok_or(ParseError::StackUnderflow)?;
+            Ok(())
+        };
+        let local = |reader: &mut Reader<'_>| {
+            let local = reader.u32()?;
+            if local < total_locals {
+                Ok(local)
+            } else {
+                Err(ParseError::InvalidLocalIndex(local))
+            }
+        };
+
+        loop {
+            let opcode = reader.byte()?;
+            let op = match opcode {
+                // nop
+                0x01 => continue,
+                // end
+                0x0B => break,
+                0x0F => Op::Return,
+                0x10 => {
+                    let callee = reader.u32()?;
+                    let callee_ty = module.signature(callee)?;
+
+                    // Calls only have a single destination to hold their results in
+                    if callee_ty.results.len() > 1 {
+                        return Err(ParseError::Unsupported("calls returning multiple values"));
+                    }
+                    pop(&mut depth, callee_ty.params.len())?;
+                    depth += callee_ty.results.len();
+
+                    Op::Call(callee)
+                }
+                0x1A => {
+                    pop(&mut depth, 1)?;
+                    Op::Drop
+                }
+                0x20 => {
+                    depth += 1;
+                    Op::LocalGet(local(reader)?)
+                }
+                0x21 => {
+                    pop(&mut depth, 1)?;
+                    Op::LocalSet(local(reader)?)
+                }
+                0x22 => {
+                    pop(&mut depth, 1)?;
+                    depth += 1;
+                    Op::LocalTee(local(reader)?)
+                }
+                0x42 => {
+                    depth += 1;
+                    Op::Const(reader.i64()?)
+                }
+                0x7C | 0x7D | 0x7E | 0x7F | 0x81 => {
+                    pop(&mut depth, 2)?;
+                    depth += 1;
+
+                    match opcode {
+                        0x7C => Op::Add,
+                        0x7D => Op::Sub,
+                        0x7E => Op::Mul,
+                        0x7F => Op::Div,
+                        _ => Op::Rem,
+                    }
+                }
+
+                0x02 | 0x03 | 0x04 | 0x0C | 0x0D | 0x0E => {
+                    return Err(ParseError::Unsupported("structured control flow"));
+                }
+                opcode => return Err(ParseError::UnsupportedOpcode(opcode)),
+            };
+
+            ops.push(op);
+
+            // Anything after a return is dead, so the rest of the body is skipped
+            if let Op::Return = op {
+                break;
+            }
+        }
+
+        pop(&mut depth, signature.results.len())?;
+
+        Ok(Self { locals, ops })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    const fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn byte(&mut self) -> Result<u8, ParseError> {
+        let (&byte, rest) = self.bytes.split_first().ok_or(ParseError::UnexpectedEof)?;
+        self.bytes = rest;
+
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        if self.bytes.len() < len {
+            return Err(ParseError::UnexpectedEof);
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(bytes)
+    }
+
+    /// Reads an unsigned LEB128 integer
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let mut result = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            result |= ((byte & 0x7F) as u32)
+                .checked_shl(shift)
+                .ok_or(ParseError::InvalidLeb128)?;
+
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+
+        Err(ParseError::InvalidLeb128)
+    }
+
+    /// Reads a signed LEB128 integer
+    fn i64(&mut self) -> Result<i64, ParseError> {
+        let (mut result, mut shift) = (0i64, 0u32);
+        loop {
+            if shift >= 70 {
+                return Err(ParseError::InvalidLeb128);
+            }
+
+            let byte = self.byte()?;
+            result |= ((byte & 0x7F) as i64).wrapping_shl(shift);
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1i64 << shift;
+                }
+
+                return Ok(result);
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+
+        str::from_utf8(bytes)
+            .map(ToOwned::to_owned)
+            .map_err(|_| ParseError::InvalidUtf8)
+    }
+
+    /// Reads a value type, only `i64` is currently supported
+    fn value_type(&mut self) -> Result<u8, ParseError> {
+        match self.byte()? {
+            0x7E => Ok(0x7E),
+            ty => Err(ParseError::UnsupportedValueType(ty)),
+        }
+    }
+
+    fn value_types(&mut self) -> Result<Vec<u8>, ParseError> {
+        (0..self.u32()?).map(|_| self.value_type()).collect()
+    }
+}